@@ -29,6 +29,8 @@ pub const MAX_DNS_REDUNDANCY: usize = 5;
 
 pub const DNS_PORT: u16 = 53;
 pub const DNS_HTTP_PORT: u16 = 5353;
+/// The port on which DNS servers serve their metrics to oximeter
+pub const DNS_METRICS_PORT: u16 = 12228;
 pub const SLED_AGENT_PORT: u16 = 12345;
 
 /// The port propolis-server listens on inside the propolis zone.
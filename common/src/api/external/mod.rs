@@ -689,6 +689,7 @@ impl TryFrom<i64> for Generation {
 pub enum ResourceType {
     AddressLot,
     AddressLotBlock,
+    AffinityGroup,
     BackgroundTask,
     Fleet,
     Silo,
@@ -696,14 +697,17 @@ pub enum ResourceType {
     SiloGroup,
     IdentityProvider,
     SamlIdentityProvider,
+    OidcIdentityProvider,
     SshKey,
     Certificate,
     ConsoleSession,
     DeviceAuthRequest,
     DeviceAccessToken,
+    ScimClientBearerToken,
     Project,
     Dataset,
     Disk,
+    FloatingIp,
     Image,
     SiloImage,
     ProjectImage,
@@ -1021,7 +1025,9 @@ impl TryFrom<(&str, Option<Uuid>)> for DiskState {
                 Ok(DiskState::ImportingFromBulkWrites)
             }
             ("finalizing", None) => Ok(DiskState::Finalizing),
-            ("maintenance", None) => Ok(DiskState::Maintenance),
+            // A disk which is attached to a stopped instance remains attached
+            // to it while undergoing maintenance (e.g., while being resized).
+            ("maintenance", _) => Ok(DiskState::Maintenance),
             ("destroyed", None) => Ok(DiskState::Destroyed),
             ("faulted", None) => Ok(DiskState::Faulted),
             ("attaching", Some(id)) => Ok(DiskState::Attaching(id)),
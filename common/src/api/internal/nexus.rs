@@ -82,6 +82,35 @@ impl ProducerEndpoint {
     }
 }
 
+/// The largest number of samples a producer may push to a collector in one
+/// request.
+pub const MAX_PUSHED_SAMPLES: usize = 10_000;
+
+/// How long a push producer's registration lasts.
+///
+/// Producers that keep running register again before this runs out. Nexus and
+/// the collector forget about those that don't, which are usually processes
+/// that have exited.
+pub const PUSH_PRODUCER_LEASE_DURATION: Duration = Duration::from_secs(60 * 60);
+
+/// Information announced by a metric producer that pushes its samples to a
+/// collector, rather than running a server the collector polls.
+#[derive(Debug, Clone, JsonSchema, Serialize, Deserialize)]
+pub struct PushProducerInfo {
+    /// The ID of the producer
+    pub id: Uuid,
+}
+
+/// The collector a push producer has been assigned, to which it sends its
+/// samples.
+#[derive(Debug, Clone, JsonSchema, Serialize, Deserialize)]
+pub struct PushProducerAssignment {
+    /// The ID of the collector
+    pub collector_id: Uuid,
+    /// The address at which the collector accepts samples
+    pub collector_address: SocketAddr,
+}
+
 /// An identifier for a single update artifact.
 #[derive(
     Clone,
@@ -900,7 +900,11 @@ fn print_name(
     if records.len() == 1 {
         match &records[0] {
             DnsRecord::Srv(_) => (),
-            DnsRecord::Aaaa(_) | DnsRecord::A(_) => {
+            DnsRecord::Aaaa(_)
+            | DnsRecord::A(_)
+            | DnsRecord::Cname(_)
+            | DnsRecord::Txt(_)
+            | DnsRecord::Ptr(_) => {
                 println!(
                     "{}  {:50} {}",
                     prefix,
@@ -925,5 +929,8 @@ fn format_record(record: &DnsRecord) -> impl Display {
         DnsRecord::Srv(Srv { port, target, .. }) => {
             format!("SRV  port {:5} {}", port, target)
         }
+        DnsRecord::Cname(target) => format!("CNAME {}", target),
+        DnsRecord::Txt(strings) => format!("TXT  {:?}", strings),
+        DnsRecord::Ptr(target) => format!("PTR  {}", target),
     }
 }
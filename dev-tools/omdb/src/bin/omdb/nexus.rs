@@ -478,6 +478,26 @@ fn print_task_details(bgtask: &BackgroundTask, details: &serde_json::Value) {
                 }
             }
         }
+    } else if name == "push_producer_gc" {
+        // The "push_producer_gc" task reports how many expired push producers
+        // it deleted.
+        #[derive(Deserialize)]
+        struct PushProducerGcSuccess {
+            deleted: usize,
+        }
+
+        match serde_json::from_value::<PushProducerGcSuccess>(details.clone()) {
+            Err(error) => eprintln!(
+                "warning: failed to interpret task details: {:?}: {:?}",
+                error, details
+            ),
+            Ok(success) => {
+                println!(
+                    "    expired push producers deleted: {}",
+                    success.deleted
+                );
+            }
+        };
     } else {
         println!(
             "warning: unknown background task: {:?} \
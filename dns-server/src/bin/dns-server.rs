@@ -8,11 +8,21 @@
 use anyhow::anyhow;
 use anyhow::Context;
 use clap::Parser;
+use internal_dns::resolver::Resolver;
+use internal_dns::ServiceName;
+use omicron_common::address::DNS_METRICS_PORT;
+use omicron_common::address::NEXUS_INTERNAL_PORT;
+use omicron_common::api::internal::nexus::ProducerEndpoint;
+use omicron_common::backoff;
 use serde::Deserialize;
 use slog::info;
 use slog::o;
-use std::net::{SocketAddr, SocketAddrV6};
+use slog::warn;
+use slog::Logger;
+use std::net::{IpAddr, SocketAddr, SocketAddrV6};
 use std::path::PathBuf;
+use std::time::Duration;
+use uuid::Uuid;
 
 #[derive(Parser, Debug)]
 struct Args {
@@ -24,6 +34,10 @@ struct Args {
 
     #[clap(long, action)]
     dns_address: SocketAddr,
+
+    /// Report metrics to oximeter, identifying this server with the given id
+    #[clap(long, action)]
+    metrics_id: Option<Uuid>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -31,6 +45,36 @@ pub struct Config {
     pub log: dropshot::ConfigLogging,
     pub dropshot: dropshot::ConfigDropshot,
     pub storage: dns_server::storage::Config,
+    #[serde(default)]
+    pub zone_transfer: ZoneTransferConfig,
+    #[serde(default)]
+    pub rate_limit: dns_server::rate_limit::Config,
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+}
+
+/// Configuration for reporting metrics to oximeter
+///
+/// Metrics are only reported when the server is given a `--metrics-id`.
+#[derive(Default, Deserialize, Debug)]
+pub struct MetricsConfig {
+    /// address on which to serve metrics to oximeter (default: the HTTP
+    /// address's IP, on `DNS_METRICS_PORT`)
+    pub address: Option<SocketAddr>,
+    /// address of the Nexus internal API, with which we register as a metric
+    /// producer (default: found with internal DNS)
+    pub nexus_address: Option<SocketAddr>,
+}
+
+/// Configuration for secondary nameservers that copy our zones
+#[derive(Default, Deserialize, Debug)]
+pub struct ZoneTransferConfig {
+    /// addresses of peers allowed to transfer zones (AXFR and IXFR)
+    #[serde(default)]
+    pub peers: Vec<IpAddr>,
+    /// addresses of secondaries to notify when the DNS data changes
+    #[serde(default)]
+    pub notify: Vec<SocketAddr>,
 }
 
 #[tokio::main]
@@ -50,8 +94,12 @@ async fn main() -> Result<(), anyhow::Error> {
         .to_logger("dns-server")
         .context("failed to create logger")?;
 
-    let dns_server_config =
-        dns_server::dns_server::Config { bind_address: args.dns_address };
+    let dns_server_config = dns_server::dns_server::Config {
+        bind_address: args.dns_address,
+        transfer_peers: config.zone_transfer.peers.clone(),
+        notify_secondaries: config.zone_transfer.notify.clone(),
+        rate_limit: config.rate_limit.clone(),
+    };
 
     info!(&log, "config";
         "config" => ?config,
@@ -64,15 +112,130 @@ async fn main() -> Result<(), anyhow::Error> {
     )
     .context("initializing persistent storage")?;
 
-    let (_dns_server, dropshot_server) = dns_server::start_servers(
-        log,
+    let (dns_server, dropshot_server) = dns_server::start_servers(
+        log.clone(),
         store,
         &dns_server_config,
         &config.dropshot,
     )
     .await?;
 
+    if let Some(metrics_id) = args.metrics_id {
+        let address = config.metrics.address.unwrap_or_else(|| {
+            SocketAddr::new(
+                IpAddr::V6(*args.http_address.ip()),
+                DNS_METRICS_PORT,
+            )
+        });
+        let nexus = match config.metrics.nexus_address {
+            Some(address) => NexusLocator::Address(address),
+            None => NexusLocator::Dns(
+                Resolver::new_from_ip(
+                    log.new(o!("component" => "DnsResolver")),
+                    *args.http_address.ip(),
+                )
+                .context("creating internal DNS resolver")?,
+            ),
+        };
+        let producer = dns_server::metrics::Producer::new(
+            metrics_id,
+            dns_server.counters(),
+        );
+        tokio::spawn(serve_metrics(
+            log.new(o!("component" => "metrics")),
+            metrics_id,
+            address,
+            nexus,
+            producer,
+        ));
+    }
+
     dropshot_server
         .await
         .map_err(|error_message| anyhow!("server exiting: {}", error_message))
 }
+
+/// Describes how to find the Nexus internal API
+enum NexusLocator {
+    /// Nexus is at a fixed address
+    Address(SocketAddr),
+    /// Nexus is found by looking it up in internal DNS
+    Dns(Resolver),
+}
+
+impl NexusLocator {
+    async fn address(&self) -> Result<SocketAddr, anyhow::Error> {
+        match self {
+            NexusLocator::Address(address) => Ok(*address),
+            NexusLocator::Dns(resolver) => {
+                let ip = resolver
+                    .lookup_ipv6(ServiceName::Nexus)
+                    .await
+                    .context("looking up Nexus in internal DNS")?;
+                Ok(SocketAddr::V6(SocketAddrV6::new(
+                    ip,
+                    NEXUS_INTERNAL_PORT,
+                    0,
+                    0,
+                )))
+            }
+        }
+    }
+}
+
+/// Registers with Nexus as a metric producer and serves the DNS server's
+/// metrics to oximeter
+///
+/// Nexus may not be up yet (it depends on DNS, after all), so we keep trying
+/// to find it and register until it is.
+async fn serve_metrics(
+    log: Logger,
+    id: Uuid,
+    address: SocketAddr,
+    nexus: NexusLocator,
+    producer: dns_server::metrics::Producer,
+) {
+    let start = || async {
+        let nexus_address =
+            nexus.address().await.map_err(backoff::BackoffError::transient)?;
+        let config = oximeter_producer::Config {
+            server_info: ProducerEndpoint {
+                id,
+                address,
+                base_route: String::from("/collect"),
+                interval: Duration::from_secs(10),
+            },
+            registration_address: nexus_address,
+            dropshot: dropshot::ConfigDropshot {
+                bind_address: address,
+                ..Default::default()
+            },
+            log: oximeter_producer::LogConfig::Logger(log.clone()),
+        };
+        oximeter_producer::Server::start(&config).await.map_err(|error| {
+            backoff::BackoffError::transient(anyhow::Error::from(error))
+        })
+    };
+    let log_failure = |error, delay| {
+        warn!(
+            log,
+            "failed to start metric server, will retry in {:?}", delay;
+            "error_message" => ?error,
+        );
+    };
+    let server = backoff::retry_notify(
+        backoff::retry_policy_internal_service(),
+        start,
+        log_failure,
+    )
+    .await
+    .expect("expected an infinite retry loop starting the metric server");
+
+    if let Err(error) = server.registry().register_producer(producer) {
+        warn!(log, "failed to register metric producer"; "error_message" => ?error);
+        return;
+    }
+    if let Err(error) = server.serve_forever().await {
+        warn!(log, "metric server exited"; "error_message" => ?error);
+    }
+}
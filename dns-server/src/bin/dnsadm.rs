@@ -152,6 +152,15 @@ async fn main() -> Result<()> {
                                     srv.weight
                                 );
                             }
+                            DnsRecord::Cname(target) => {
+                                println!("        CNAME: {}", target);
+                            }
+                            DnsRecord::Txt(strings) => {
+                                println!("        TXT:  {:?}", strings);
+                            }
+                            DnsRecord::Ptr(target) => {
+                                println!("        PTR:  {}", target);
+                            }
                         }
                     }
                 }
@@ -213,6 +222,7 @@ async fn main() -> Result<()> {
                                 .into_iter()
                                 .filter(|(name, _)| *name != cmd.name)
                                 .collect(),
+                            nameservers: dns_zone.nameservers,
                         }
                     }
                 })
@@ -260,11 +270,11 @@ fn add_record(
     let generation = config.generation;
     let (our_zone, other_zones): (Vec<_>, Vec<_>) =
         config.zones.into_iter().partition(|z| z.zone_name == zone_name);
-    let our_records = our_zone
+    let (our_records, our_nameservers) = our_zone
         .into_iter()
         .next()
-        .map(|z| z.records)
-        .unwrap_or_else(HashMap::new);
+        .map(|z| (z.records, z.nameservers))
+        .unwrap_or_else(|| (HashMap::new(), Vec::new()));
     let (our_kv, other_kvs): (Vec<_>, Vec<_>) =
         our_records.into_iter().partition(|(n, _)| n == name);
     let mut our_kv = our_kv
@@ -281,6 +291,7 @@ fn add_record(
             .chain(once(DnsConfigZone {
                 zone_name: zone_name.to_owned(),
                 records: other_kvs.into_iter().chain(once(our_kv)).collect(),
+                nameservers: our_nameservers,
             }))
             .collect(),
     })
@@ -4,26 +4,59 @@
 
 //! Guts of the DNS (protocol) server within our DNS server program
 //!
-//! The facilities here handle binding a UDP socket, receiving DNS messages on
-//! that socket, and replying to them.
+//! The facilities here handle binding a UDP socket and a TCP listener on the
+//! same address, receiving DNS messages on them, and replying to them.
+//!
+//! Responses sent over UDP are limited to 512 bytes, or to the payload size
+//! that the client advertised with EDNS0 (up to [`MAX_UDP_PAYLOAD`]).  When a
+//! response doesn't fit, we set the truncation (TC) bit so that the client
+//! retries the query over TCP, where responses may be up to 64 KiB.
+//!
+//! Configured peers may also transfer whole zones (AXFR) or the changes since
+//! a previous generation (IXFR) over TCP, and we send DNS NOTIFY messages to
+//! configured secondaries whenever a new generation of DNS data is committed.
+//!
+//! To protect itself from floods of queries, the server caps the number of
+//! requests that it handles at once and limits the rate of UDP responses to
+//! each client network.  See [`crate::rate_limit`].
 
 use crate::dns_types::DnsRecord;
+use crate::metrics::Counters;
+use crate::rate_limit;
+use crate::rate_limit::RateLimiter;
+use crate::rate_limit::Verdict;
 use crate::storage;
 use crate::storage::QueryError;
 use crate::storage::Store;
+use crate::storage::ZoneInfo;
 use anyhow::anyhow;
 use anyhow::Context;
 use pretty_hex::*;
 use serde::Deserialize;
 use slog::{debug, error, info, o, trace, Logger};
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
 use std::net::SocketAddr;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 use thiserror::Error;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
 use tokio::net::UdpSocket;
+use tokio::sync::Mutex;
+use tokio::sync::Semaphore;
 use trust_dns_proto::op::header::Header;
 use trust_dns_proto::op::response_code::ResponseCode;
+use trust_dns_proto::op::Edns;
+use trust_dns_proto::op::{Message, MessageType, OpCode, Query};
+use trust_dns_proto::rr::rdata::SOA;
 use trust_dns_proto::rr::rdata::SRV;
+use trust_dns_proto::rr::rdata::TXT;
 use trust_dns_proto::rr::record_data::RData;
 use trust_dns_proto::rr::record_type::RecordType;
 use trust_dns_proto::rr::{Name, Record};
@@ -34,11 +67,56 @@ use trust_dns_server::authority::MessageResponse;
 use trust_dns_server::authority::{MessageRequest, MessageResponseBuilder};
 use uuid::Uuid;
 
+/// Largest response we'll send over UDP to a client without EDNS0 support
+/// (RFC 1035)
+const MIN_UDP_PAYLOAD: u16 = 512;
+
+/// Largest response we'll send over UDP, regardless of the payload size a
+/// client advertises with EDNS0
+///
+/// This is also the payload size we advertise in our own OPT records.
+pub const MAX_UDP_PAYLOAD: u16 = 4096;
+
+/// Limits how many CNAME records we'll follow when answering a query for a
+/// name that's an alias
+const MAX_CNAME_CHAIN: usize = 8;
+
+/// SOA timers (in seconds) that we advertise for each zone
+const SOA_REFRESH: i32 = 3600;
+const SOA_RETRY: i32 = 600;
+const SOA_EXPIRE: i32 = 18000;
+/// TTL for negative answers (RFC 2308).  The DNS data can change at any time,
+/// so this is short, but caching negative answers at all keeps a client that's
+/// repeatedly looking up a name that doesn't exist (yet) from hammering us.
+const SOA_MINIMUM: u32 = 30;
+
+/// How long we'll wait for the next message on an otherwise idle TCP
+/// connection before closing it, and for a client to accept a response that
+/// we're sending it
+const TCP_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How many times we'll send a NOTIFY message to a secondary that hasn't
+/// acknowledged it, and how long we'll wait for each acknowledgment
+const NOTIFY_ATTEMPTS: usize = 5;
+const NOTIFY_TIMEOUT: Duration = Duration::from_secs(2);
+
 /// Configuration related to the DNS server
 #[derive(Deserialize, Debug, Clone)]
 pub struct Config {
-    /// The address to listen for DNS requests on
+    /// The address to listen for DNS requests on (over both UDP and TCP)
     pub bind_address: SocketAddr,
+    /// Addresses of peers that may transfer zones (AXFR and IXFR) from this
+    /// server.  Transfer requests from anyone else are refused.
+    #[serde(default)]
+    pub transfer_peers: Vec<IpAddr>,
+    /// Secondary nameservers to send DNS NOTIFY messages (RFC 1996) to
+    /// whenever a new generation of DNS data is committed
+    #[serde(default)]
+    pub notify_secondaries: Vec<SocketAddr>,
+    /// Limits on the number of requests handled at once and the rate of
+    /// responses to each client
+    #[serde(default)]
+    pub rate_limit: rate_limit::Config,
 }
 
 /// Handle to the DNS server
@@ -46,6 +124,7 @@ pub struct Config {
 /// Dropping this handle shuts down the DNS server.
 pub struct ServerHandle {
     local_address: SocketAddr,
+    counters: Arc<Counters>,
     handle: tokio::task::JoinHandle<anyhow::Result<()>>,
 }
 
@@ -59,6 +138,13 @@ impl ServerHandle {
     pub fn local_address(&self) -> SocketAddr {
         self.local_address
     }
+
+    /// Returns the counters that the server maintains as it handles requests
+    ///
+    /// These can be reported to oximeter with a [`crate::metrics::Producer`].
+    pub fn counters(&self) -> Arc<Counters> {
+        self.counters.clone()
+    }
 }
 
 /// DNS (protocol) server
@@ -69,6 +155,15 @@ pub struct Server {
     log: Logger,
     store: storage::Store,
     server_socket: Arc<UdpSocket>,
+    tcp_listener: TcpListener,
+    transfer_peers: Arc<[IpAddr]>,
+    notify_secondaries: Vec<SocketAddr>,
+    rate_limiter: RateLimiter,
+    /// limits the number of requests that we handle at once
+    request_permits: Arc<Semaphore>,
+    /// limits the number of TCP connections that we have open at once
+    tcp_connection_permits: Arc<Semaphore>,
+    counters: Arc<Counters>,
 }
 
 impl Server {
@@ -91,16 +186,44 @@ impl Server {
             "DNS server start: failed to get local address of bound socket",
         )?;
 
+        // Bind the TCP listener to the address we actually got for UDP so that
+        // both use the same port even when the caller asked for port 0.
+        let tcp_listener =
+            TcpListener::bind(local_address).await.with_context(|| {
+                format!("DNS server start: TCP bind to {:?}", local_address)
+            })?;
+
         info!(&log, "DNS server bound to address";
             "local_address" => ?local_address
         );
 
-        let server = Server { log, store, server_socket };
+        let counters = Arc::new(Counters::default());
+        let server = Server {
+            log,
+            store,
+            server_socket,
+            tcp_listener,
+            transfer_peers: config.transfer_peers.clone().into(),
+            notify_secondaries: config.notify_secondaries.clone(),
+            rate_limiter: RateLimiter::new(&config.rate_limit),
+            request_permits: Arc::new(Semaphore::new(
+                config.rate_limit.max_concurrent_requests,
+            )),
+            tcp_connection_permits: Arc::new(Semaphore::new(
+                config.rate_limit.max_tcp_connections,
+            )),
+            counters: counters.clone(),
+        };
         let handle = tokio::task::spawn(server.run());
-        Ok(ServerHandle { local_address, handle })
+        Ok(ServerHandle { local_address, counters, handle })
     }
 
     async fn run(self) -> anyhow::Result<()> {
+        tokio::try_join!(self.run_udp(), self.run_tcp(), self.run_notify())?;
+        Ok(())
+    }
+
+    async fn run_udp(&self) -> anyhow::Result<()> {
         // The guts of the DNS server: read packets from the bound socket and
         // handle them.
         loop {
@@ -111,6 +234,27 @@ impl Server {
                 .await
                 .context("receiving packet from UDP listen socket")?;
             buf.resize(n, 0);
+            self.counters.request_udp();
+
+            // Responses over UDP can be sent to spoofed addresses, so limit
+            // how many we send to each client network.
+            let slip = match self.rate_limiter.check(client_addr.ip()) {
+                Verdict::Allow => false,
+                Verdict::Slip => {
+                    self.counters.rate_limited_slipped();
+                    true
+                }
+                Verdict::Drop => {
+                    self.counters.rate_limited_dropped();
+                    continue;
+                }
+            };
+
+            let Ok(permit) = self.request_permits.clone().try_acquire_owned()
+            else {
+                self.counters.overloaded_dropped();
+                continue;
+            };
 
             let req_id = Uuid::new_v4();
             let log = self.log.new(o!(
@@ -121,31 +265,307 @@ impl Server {
             let request = Request {
                 log,
                 store: self.store.clone(),
-                socket: self.server_socket.clone(),
+                transport: Transport::Udp(self.server_socket.clone()),
                 client_addr,
                 packet: buf,
+                max_response_size: MIN_UDP_PAYLOAD,
+                transfer_peers: self.transfer_peers.clone(),
+                slip,
                 req_id,
             };
 
-            // TODO-robustness We should cap the number of tokio tasks that
-            // we're willing to spawn if we receive a flood of requests.
-            tokio::spawn(handle_dns_packet(request));
+            tokio::spawn(async move {
+                handle_dns_packet(request).await;
+                drop(permit);
+            });
         }
     }
+
+    async fn run_tcp(&self) -> anyhow::Result<()> {
+        loop {
+            let (stream, client_addr) = match self.tcp_listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(error) => {
+                    // Failing to accept one connection (e.g., because we're
+                    // out of file descriptors) shouldn't take down the UDP
+                    // side of the server, too.
+                    error!(
+                        &self.log,
+                        "failed to accept TCP connection: {:#}", error
+                    );
+                    continue;
+                }
+            };
+
+            let log = self.log.new(o!(
+                "peer_addr" => client_addr.to_string(),
+                "transport" => "tcp",
+            ));
+
+            // Each open connection costs us a task and its buffers, so cap how
+            // many we'll keep open.  Dropping the stream closes it.
+            let Ok(permit) =
+                self.tcp_connection_permits.clone().try_acquire_owned()
+            else {
+                self.counters.tcp_connection_refused();
+                debug!(&log, "refusing TCP connection: too many open");
+                continue;
+            };
+
+            // Clients can't spoof their addresses over TCP, so these requests
+            // aren't rate-limited.  They do count against the cap on requests
+            // handled at once.
+            let connection = handle_tcp_connection(
+                log,
+                self.store.clone(),
+                self.transfer_peers.clone(),
+                self.request_permits.clone(),
+                self.counters.clone(),
+                stream,
+                client_addr,
+            );
+            tokio::spawn(async move {
+                connection.await;
+                drop(permit);
+            });
+        }
+    }
+
+    /// Sends NOTIFY messages to the configured secondaries for each of our
+    /// zones whenever a new generation of DNS data is committed
+    async fn run_notify(&self) -> anyhow::Result<()> {
+        if self.notify_secondaries.is_empty() {
+            return Ok(());
+        }
+
+        let mut generation_rx = self.store.watch_generation();
+        while generation_rx.changed().await.is_ok() {
+            let generation = *generation_rx.borrow_and_update();
+            let log = self.log.new(o!("generation" => generation));
+            let zones = match self.store.zones() {
+                Ok(zones) => zones,
+                Err(error) => {
+                    error!(
+                        &log,
+                        "failed to list zones to send NOTIFY: {:#}", error
+                    );
+                    continue;
+                }
+            };
+
+            for zone in zones.iter() {
+                let soa = match soa_record(zone) {
+                    Ok(soa) => soa,
+                    Err(error) => {
+                        error!(&log, "failed to send NOTIFY: {:#}", error);
+                        continue;
+                    }
+                };
+                for &secondary in &self.notify_secondaries {
+                    let log = log.new(o!(
+                        "zone" => zone.zone_name.clone(),
+                        "secondary" => secondary.to_string(),
+                    ));
+                    tokio::spawn(send_notify(log, secondary, soa.clone()));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Sends a NOTIFY message (RFC 1996) telling a secondary nameserver that the
+/// zone described by the given SOA record has changed
+///
+/// Like any other message over UDP, NOTIFY messages can be lost, so we retry a
+/// few times until the secondary acknowledges the message.
+async fn send_notify(log: Logger, secondary: SocketAddr, soa: Record) {
+    // The message ID only needs to be unpredictable enough that spoofed
+    // acknowledgments are unlikely.
+    let id_bytes = Uuid::new_v4().into_bytes();
+    let id = u16::from_be_bytes([id_bytes[0], id_bytes[1]]);
+    let mut message = Message::new();
+    message
+        .set_id(id)
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Notify)
+        .set_authoritative(true)
+        .add_query(Query::query(soa.name().clone(), RecordType::SOA))
+        .add_answer(soa);
+    let message_bytes = match message.to_vec() {
+        Ok(message_bytes) => message_bytes,
+        Err(error) => {
+            error!(&log, "failed to encode NOTIFY: {:#}", error);
+            return;
+        }
+    };
+
+    let bind_address: SocketAddr = match secondary {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = match UdpSocket::bind(bind_address).await {
+        Ok(socket) => socket,
+        Err(error) => {
+            error!(&log, "failed to bind socket to send NOTIFY: {:#}", error);
+            return;
+        }
+    };
+
+    for attempt in 1..=NOTIFY_ATTEMPTS {
+        if let Err(error) = socket.send_to(&message_bytes, secondary).await {
+            error!(&log, "failed to send NOTIFY: {:#}", error; "attempt" => attempt);
+            continue;
+        }
+
+        let mut buf = vec![0u8; usize::from(MAX_UDP_PAYLOAD)];
+        let acknowledged = match tokio::time::timeout(
+            NOTIFY_TIMEOUT,
+            socket.recv_from(&mut buf),
+        )
+        .await
+        {
+            Ok(Ok((n, from))) if from == secondary => {
+                Message::from_vec(&buf[..n])
+                    .map(|response| {
+                        response.id() == id
+                            && response.message_type() == MessageType::Response
+                            && response.op_code() == OpCode::Notify
+                    })
+                    .unwrap_or(false)
+            }
+            Ok(Ok(_)) | Err(_) => false,
+            Ok(Err(error)) => {
+                error!(&log, "failed to receive NOTIFY response: {:#}", error);
+                false
+            }
+        };
+
+        if acknowledged {
+            debug!(&log, "secondary acknowledged NOTIFY"; "attempt" => attempt);
+            return;
+        }
+    }
+
+    error!(&log, "giving up on NOTIFY after {} attempts", NOTIFY_ATTEMPTS);
+}
+
+/// Describes how responses get back to the client that sent a request
+enum Transport {
+    /// The request arrived on our UDP socket
+    Udp(Arc<UdpSocket>),
+    /// The request arrived on a TCP connection, whose write half is shared by
+    /// all requests on that connection
+    Tcp(Arc<Mutex<OwnedWriteHalf>>),
 }
 
 /// Describes an incoming DNS request
 struct Request {
     log: Logger,
     store: Store,
-    socket: Arc<UdpSocket>,
+    transport: Transport,
     client_addr: SocketAddr,
     packet: Vec<u8>,
+    /// largest response (in bytes) that we may send to this client
+    max_response_size: u16,
+    /// peers that may transfer zones from this server
+    transfer_peers: Arc<[IpAddr]>,
+    /// whether this client is over its response rate limit, in which case
+    /// we send an empty, truncated response so that it retries over TCP
+    slip: bool,
     #[allow(dead_code)]
     req_id: Uuid,
 }
 
-async fn handle_dns_packet(request: Request) {
+/// Reads DNS messages from a TCP connection and handles them in turn
+///
+/// Each message on the connection is preceded by its length as a two-byte,
+/// big-endian integer (RFC 1035, section 4.2.2).  Clients may send several
+/// queries on one connection, so we keep reading until the client closes it or
+/// leaves it idle for too long.
+async fn handle_tcp_connection(
+    log: Logger,
+    store: Store,
+    transfer_peers: Arc<[IpAddr]>,
+    request_permits: Arc<Semaphore>,
+    counters: Arc<Counters>,
+    stream: TcpStream,
+    client_addr: SocketAddr,
+) {
+    let (mut reader, writer) = stream.into_split();
+    let writer = Arc::new(Mutex::new(writer));
+
+    loop {
+        let mut length = [0u8; 2];
+        match tokio::time::timeout(
+            TCP_IDLE_TIMEOUT,
+            reader.read_exact(&mut length),
+        )
+        .await
+        {
+            Ok(Ok(_)) => (),
+            Ok(Err(error))
+                if error.kind() == std::io::ErrorKind::UnexpectedEof =>
+            {
+                trace!(&log, "TCP connection closed by client");
+                return;
+            }
+            Ok(Err(error)) => {
+                error!(&log, "failed to read from TCP connection: {:#}", error);
+                return;
+            }
+            Err(_) => {
+                debug!(&log, "closing idle TCP connection");
+                return;
+            }
+        }
+
+        let mut packet = vec![0u8; usize::from(u16::from_be_bytes(length))];
+        match tokio::time::timeout(
+            TCP_IDLE_TIMEOUT,
+            reader.read_exact(&mut packet),
+        )
+        .await
+        {
+            Ok(Ok(_)) => (),
+            Ok(Err(error)) => {
+                error!(
+                    &log,
+                    "failed to read DNS message over TCP: {:#}", error
+                );
+                return;
+            }
+            Err(_) => {
+                error!(&log, "timed out reading DNS message over TCP");
+                return;
+            }
+        }
+
+        counters.request_tcp();
+        let Ok(_permit) = request_permits.try_acquire() else {
+            counters.overloaded_dropped();
+            debug!(&log, "closing TCP connection while overloaded");
+            return;
+        };
+
+        let req_id = Uuid::new_v4();
+        let request = Request {
+            log: log.new(o!("req_id" => req_id.to_string())),
+            store: store.clone(),
+            transport: Transport::Tcp(writer.clone()),
+            client_addr,
+            packet,
+            max_response_size: u16::MAX,
+            transfer_peers: transfer_peers.clone(),
+            slip: false,
+            req_id,
+        };
+        handle_dns_packet(request).await;
+    }
+}
+
+async fn handle_dns_packet(mut request: Request) {
     let log = &request.log;
     let buf = &request.packet;
 
@@ -161,31 +581,27 @@ async fn handle_dns_packet(request: Request) {
         }
     };
 
+    // Clients that support EDNS0 tell us how large a UDP response they're
+    // willing to accept.
+    if let (Transport::Udp(_), Some(edns)) = (&request.transport, mr.edns()) {
+        request.max_response_size =
+            edns.max_payload().clamp(MIN_UDP_PAYLOAD, MAX_UDP_PAYLOAD);
+    }
+
+    if request.slip {
+        respond_slip(&request, &mr).await;
+        return;
+    }
+
     // Handle the message.
     match handle_dns_message(&request, &mr).await {
         Ok(_) => (),
         Err(error) => {
+            let log = &request.log;
             let header = Header::response_from_request(mr.header());
-            let rb_servfail = MessageResponseBuilder::from_message_request(&mr);
+            let rb_servfail = response_builder(&mr);
             error!(log, "failed to handle incoming DNS message: {:#}", error);
-            match error {
-                RequestError::NxDomain(_) => {
-                    let rb_nxdomain =
-                        MessageResponseBuilder::from_message_request(&mr);
-                    respond_nxdomain(
-                        &request,
-                        rb_nxdomain,
-                        rb_servfail,
-                        &header,
-                    )
-                    .await
-                }
-                RequestError::ServFail(_) => {
-                    let rb_servfail =
-                        MessageResponseBuilder::from_message_request(&mr);
-                    respond_servfail(&request, rb_servfail, &header).await
-                }
-            };
+            respond_servfail(&request, rb_servfail, &header).await;
         }
     }
 }
@@ -193,8 +609,6 @@ async fn handle_dns_packet(request: Request) {
 /// Describes how to respond to a particular request failure
 #[derive(Debug, Error)]
 enum RequestError {
-    #[error("NXDOMAIN: {0:#}")]
-    NxDomain(#[source] QueryError),
     #[error("SERVFAIL: {0:#}")]
     ServFail(#[source] anyhow::Error),
 }
@@ -202,11 +616,16 @@ enum RequestError {
 impl From<QueryError> for RequestError {
     fn from(source: QueryError) -> Self {
         match &source {
-            QueryError::NoName(_) => RequestError::NxDomain(source),
             // Bail with servfail when this query is for a zone that we don't
             // own (and other server-side failures) so that resolvers will look
             // to other DNS servers for this query.
-            QueryError::NoZone(_)
+            //
+            // Names that don't exist within our zones are reported by
+            // `Store::query()` as a lookup without records (which we turn into
+            // NXDOMAIN), so `NoName` only comes from lookups of names that the
+            // client didn't ask for directly.  It's not expected here.
+            QueryError::NoName(_)
+            | QueryError::NoZone(_)
             | QueryError::QueryFail(_)
             | QueryError::ParseFail(_) => RequestError::ServFail(source.into()),
         }
@@ -240,20 +659,331 @@ fn dns_record_to_record(
             port,
             target,
         }) => {
-            let tgt = Name::from_str(&target).map_err(|error| {
-                RequestError::ServFail(anyhow!(
-                    "serialization failed due to bad SRV target {:?}: {:#}",
-                    &target,
-                    error
-                ))
-            })?;
+            let tgt = parse_target_name("SRV", &target)?;
             let mut srv = Record::new();
             srv.set_name(name.clone())
                 .set_rr_type(RecordType::SRV)
                 .set_data(Some(RData::SRV(SRV::new(prio, weight, port, tgt))));
             Ok(srv)
         }
+
+        DnsRecord::CNAME(target) => {
+            let tgt = parse_target_name("CNAME", &target)?;
+            let mut cname = Record::new();
+            cname
+                .set_name(name.clone())
+                .set_rr_type(RecordType::CNAME)
+                .set_data(Some(RData::CNAME(tgt)));
+            Ok(cname)
+        }
+
+        DnsRecord::TXT(strings) => {
+            let mut txt = Record::new();
+            txt.set_name(name.clone())
+                .set_rr_type(RecordType::TXT)
+                .set_data(Some(RData::TXT(TXT::new(strings))));
+            Ok(txt)
+        }
+
+        DnsRecord::PTR(target) => {
+            let tgt = parse_target_name("PTR", &target)?;
+            let mut ptr = Record::new();
+            ptr.set_name(name.clone())
+                .set_rr_type(RecordType::PTR)
+                .set_data(Some(RData::PTR(tgt)));
+            Ok(ptr)
+        }
+    }
+}
+
+fn parse_target_name(
+    label: &'static str,
+    target: &str,
+) -> Result<Name, RequestError> {
+    Name::from_str(target).map_err(|error| {
+        RequestError::ServFail(anyhow!(
+            "serialization failed due to bad {} target {:?}: {:#}",
+            label,
+            target,
+            error
+        ))
+    })
+}
+
+/// Returns the SOA record for the given zone
+///
+/// We serve each zone's data directly from our own copy of it, so there's no
+/// real primary server.  We name the zone's first nameserver (if it has any).
+/// The serial number is the generation of the DNS data, so it changes whenever
+/// the data does.  (Serial numbers are only 32 bits, but they're compared
+/// using sequence space arithmetic (RFC 1982), so they can wrap around.)
+fn soa_record(zone: &ZoneInfo) -> Result<Record, RequestError> {
+    let mname = zone.nameservers.first().unwrap_or(&zone.name).clone();
+    let rname = Name::from_str("admin")
+        .and_then(|admin| admin.append_domain(&zone.name))
+        .map_err(|error| {
+            RequestError::ServFail(anyhow!(
+                "failed to construct SOA RNAME for zone {:?}: {:#}",
+                zone.name.to_string(),
+                error
+            ))
+        })?;
+    let soa = SOA::new(
+        mname,
+        rname,
+        soa_serial(zone.generation),
+        SOA_REFRESH,
+        SOA_RETRY,
+        SOA_EXPIRE,
+        SOA_MINIMUM,
+    );
+    Ok(Record::from_rdata(zone.name.clone(), 0, RData::SOA(soa)))
+}
+
+/// Returns the NS records for the given zone
+fn ns_records(zone: &ZoneInfo) -> impl Iterator<Item = Record> + '_ {
+    zone.nameservers.iter().map(|nameserver| {
+        Record::from_rdata(zone.name.clone(), 0, RData::NS(nameserver.clone()))
+    })
+}
+
+/// Returns the SOA serial number for a generation of DNS data
+fn soa_serial(generation: u64) -> u32 {
+    // Serial numbers wrap around, so truncating is what we want here.
+    generation as u32
+}
+
+/// Returns the most recent generation before `current_generation` whose SOA
+/// serial number is `serial`, if there is one
+fn generation_for_serial(current_generation: u64, serial: u32) -> Option<u64> {
+    let candidate =
+        (current_generation & !u64::from(u32::MAX)) | u64::from(serial);
+    if candidate < current_generation {
+        Some(candidate)
+    } else {
+        candidate.checked_sub(1 << 32)
+    }
+}
+
+/// Handle a request to transfer a zone (AXFR or IXFR)
+///
+/// Only configured peers may transfer zones, and only over TCP.  (Over UDP,
+/// IXFR requests get just the current SOA record, which tells a client that's
+/// behind to try again over TCP (RFC 1995, section 2).)
+async fn handle_zone_transfer(
+    request: &Request,
+    mr: &MessageRequest,
+) -> Result<(), RequestError> {
+    let log = &request.log;
+    let mut header = Header::response_from_request(mr.header());
+    header.set_authoritative(true);
+    let query = mr.query();
+    let query_type = query.query_type();
+
+    if !request.transfer_peers.contains(&request.client_addr.ip()) {
+        info!(log, "refusing zone transfer to unknown peer"; "query" => ?query);
+        header.set_response_code(ResponseCode::Refused);
+        return respond_records(request, mr, header, &[], &[], &[]).await;
+    }
+
+    let lookup = request.store.query(mr)?;
+    let zone = &lookup.zone;
+    if query.original().name().num_labels() != zone.name.num_labels() {
+        // Only whole zones can be transferred, and they're named by their apex.
+        header.set_response_code(ResponseCode::NotAuth);
+        return respond_records(request, mr, header, &[], &[], &[]).await;
+    }
+    let soa = soa_record(zone)?;
+
+    let is_tcp = matches!(request.transport, Transport::Tcp(_));
+    if query_type == RecordType::IXFR {
+        // The client's SOA record in the authority section tells us what
+        // version of the zone it already has.
+        let client_serial =
+            mr.name_servers().iter().find_map(|record| match record.data() {
+                Some(RData::SOA(client_soa)) => Some(client_soa.serial()),
+                _ => None,
+            });
+        if !is_tcp || client_serial == Some(soa_serial(zone.generation)) {
+            return respond_records(request, mr, header, &[soa], &[], &[])
+                .await;
+        }
+
+        if let Some(old_generation) = client_serial
+            .and_then(|serial| generation_for_serial(zone.generation, serial))
+        {
+            if let Some(records) =
+                ixfr_records(request, zone, &soa, old_generation)?
+            {
+                info!(log, "incremental zone transfer";
+                    "zone" => &zone.zone_name,
+                    "from_generation" => old_generation,
+                    "to_generation" => zone.generation,
+                );
+                return respond_transfer(request, mr, header, &records).await;
+            }
+        }
+
+        // If we no longer have the client's version of the zone, we send the
+        // whole zone instead (RFC 1995, section 4).
+    } else if !is_tcp {
+        // AXFR is only supported over TCP (RFC 5936, section 4.2).
+        header.set_response_code(ResponseCode::Refused);
+        return respond_records(request, mr, header, &[], &[], &[]).await;
     }
+
+    let Some(records) = zone_transfer_records(request, zone, zone.generation)?
+    else {
+        return Err(RequestError::ServFail(anyhow!(
+            "zone {:?} disappeared during transfer",
+            zone.zone_name
+        )));
+    };
+    info!(log, "full zone transfer";
+        "zone" => &zone.zone_name,
+        "generation" => zone.generation,
+    );
+    let records = std::iter::once(soa.clone())
+        .chain(records)
+        .chain(std::iter::once(soa))
+        .collect::<Vec<_>>();
+    respond_transfer(request, mr, header, &records).await
+}
+
+/// Returns all of the records in the given zone as of the given generation,
+/// other than its SOA record
+///
+/// Returns `None` if we don't have the zone's data for that generation.
+fn zone_transfer_records(
+    request: &Request,
+    zone: &ZoneInfo,
+    generation: u64,
+) -> Result<Option<Vec<Record>>, RequestError> {
+    let Some((zone_then, zone_records)) =
+        request.store.zone_records(zone, generation)?
+    else {
+        return Ok(None);
+    };
+
+    // Sort the names so that transfers of the same data are identical.
+    let mut zone_records = zone_records.into_iter().collect::<Vec<_>>();
+    zone_records.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut records = ns_records(&zone_then).collect::<Vec<_>>();
+    for (key, name_records) in zone_records {
+        let name = if key == storage::ZONE_APEX_KEY {
+            zone.name.clone()
+        } else {
+            Name::from_str(&key)
+                .and_then(|name| name.append_domain(&zone.name))
+                .map_err(|error| {
+                    RequestError::ServFail(anyhow!(
+                        "bad name {:?} in zone {:?}: {:#}",
+                        key,
+                        zone.zone_name,
+                        error
+                    ))
+                })?
+        };
+        for record in name_records {
+            records.push(dns_record_to_record(&name, record)?);
+        }
+    }
+
+    Ok(Some(records))
+}
+
+/// Returns the records for an incremental transfer of the given zone from
+/// `old_generation` to the current one (RFC 1995, section 4)
+///
+/// Returns `None` if we no longer have the zone's data for `old_generation`.
+fn ixfr_records(
+    request: &Request,
+    zone: &ZoneInfo,
+    soa: &Record,
+    old_generation: u64,
+) -> Result<Option<Vec<Record>>, RequestError> {
+    let Some(old_records) =
+        zone_transfer_records(request, zone, old_generation)?
+    else {
+        return Ok(None);
+    };
+    let Some(new_records) =
+        zone_transfer_records(request, zone, zone.generation)?
+    else {
+        return Ok(None);
+    };
+    let old_soa =
+        soa_record(&ZoneInfo { generation: old_generation, ..zone.clone() })?;
+
+    // TODO-scalability This is quadratic in the size of the zone.
+    let deleted = old_records.iter().filter(|r| !new_records.contains(r));
+    let added = new_records.iter().filter(|r| !old_records.contains(r));
+    let records = std::iter::once(soa)
+        .chain(std::iter::once(&old_soa))
+        .chain(deleted)
+        .chain(std::iter::once(soa))
+        .chain(added)
+        .chain(std::iter::once(soa))
+        .cloned()
+        .collect();
+    Ok(Some(records))
+}
+
+/// Respond to a zone transfer request with the given records
+///
+/// A zone can be larger than what fits in one message, so the response may
+/// span several messages (RFC 5936, section 2.2).
+async fn respond_transfer(
+    request: &Request,
+    mr: &MessageRequest,
+    header: Header,
+    records: &[Record],
+) -> Result<(), RequestError> {
+    let encode_error = |error: anyhow::Error| {
+        RequestError::ServFail(anyhow!("failed to emit transfer: {:#}", error))
+    };
+
+    let mut remaining = records;
+    while !remaining.is_empty() {
+        let mresp = response_builder(mr).build(
+            header,
+            remaining.iter().collect::<Vec<&Record>>(),
+            vec![],
+            vec![],
+            vec![],
+        );
+        let (mut resp_data, resp_header) =
+            encode_response(request, mresp, "transfer")
+                .map_err(encode_error)?;
+        let nsent = usize::from(resp_header.answer_count());
+        if nsent == 0 {
+            return Err(RequestError::ServFail(anyhow!(
+                "record too large to transfer: {:?}",
+                remaining[0]
+            )));
+        }
+
+        // The encoder sets the TC bit when not all of the records fit, but
+        // this isn't a truncated response: the rest of the records follow in
+        // the next message.  Encode just the records that fit instead.
+        if resp_header.truncated() {
+            let mresp = response_builder(mr).build(
+                header,
+                remaining[..nsent].iter().collect::<Vec<&Record>>(),
+                vec![],
+                vec![],
+                vec![],
+            );
+            (resp_data, _) = encode_response(request, mresp, "transfer")
+                .map_err(encode_error)?;
+        }
+
+        send_response(request, &resp_data, "transfer").await;
+        remaining = &remaining[nsent..];
+    }
+
+    Ok(())
 }
 
 /// Handle a well-formed, decoded DNS query
@@ -265,66 +995,134 @@ async fn handle_dns_message(
     let store = &request.store;
     debug!(&log, "message_request"; "mr" => #?mr);
 
-    let header = Header::response_from_request(mr.header());
+    if matches!(mr.query().query_type(), RecordType::AXFR | RecordType::IXFR) {
+        return handle_zone_transfer(request, mr).await;
+    }
+
+    let mut header = Header::response_from_request(mr.header());
+    // Everything we serve comes from zones that we're authoritative for.
+    header.set_authoritative(true);
     let query = mr.query();
     let name = query.original().name().clone();
-    let records = store.query(mr)?;
-    let rb = MessageResponseBuilder::from_message_request(mr);
-    let mut additional_records = vec![];
-    let response_records = records
+    let query_type = query.query_type();
+    let lookup = store.query(mr)?;
+    let zone = &lookup.zone;
+    // The zone's SOA record goes in the authority section of negative answers
+    // so that resolvers know how long they may cache them (RFC 2308).  That's
+    // the lesser of the record's TTL and its MINIMUM field, so the record
+    // needs a TTL, too.
+    let mut soa = soa_record(zone)?;
+    soa.set_ttl(SOA_MINIMUM);
+    let soa = [soa];
+
+    // The zone's apex exists even if it has no records configured because we
+    // synthesize its SOA and NS records.
+    let is_apex = name.num_labels() == zone.name.num_labels();
+    let records = match lookup.records {
+        Some(records) => records,
+        None if is_apex => Vec::new(),
+        None => {
+            debug!(&log, "dns response: NXDOMAIN"; "query" => ?query);
+            header.set_response_code(ResponseCode::NXDomain);
+            return respond_records(request, mr, header, &[], &soa, &[]).await;
+        }
+    };
+    let mut records = records
         .into_iter()
-        .map(|record| {
-            let record = dns_record_to_record(&name, record)?;
-
-            // DNS allows for the server to return additional records
-            // that weren't explicitly asked for by the client but that
-            // the server expects the client will want. The records
-            // corresponding to a lookup on a SRV target is one such case.
-            // We opportunistically attempt to resolve the target here
-            // and if successful return those additional records in the
-            // response.
-            // NOTE: we only do this one-layer deep.
-            if let Some(RData::SRV(srv)) = record.data() {
-                let target_records =
-                    store.query_name(srv.target()).map(|records| {
-                        records
-                            .into_iter()
-                            .map(|record| {
-                                dns_record_to_record(srv.target(), record)
-                            })
-                            .collect::<Result<Vec<_>, _>>()
-                    });
-                match target_records {
-                    Ok(Ok(target_records)) => {
-                        additional_records.extend(target_records);
-                    }
-                    // Don't bail out if we failed to lookup or
-                    // handle the response as the original request
-                    // did succeed and we only care to do this on
-                    // a best-effort basis.
-                    Err(error) => {
-                        slog::warn!(
-                            &log,
-                            "SRV target lookup failed";
-                            "original_mr" => #?mr,
-                            "target" => ?srv.target(),
-                            "error" => ?error,
-                        );
-                    }
-                    Ok(Err(error)) => {
-                        slog::warn!(
-                            &log,
-                            "SRV target unexpected response";
-                            "original_mr" => #?mr,
-                            "target" => ?srv.target(),
-                            "error" => ?error,
-                        );
-                    }
+        .map(|record| dns_record_to_record(&name, record))
+        .collect::<Result<Vec<_>, _>>()?;
+    if is_apex {
+        records.extend(soa.iter().cloned());
+        records.extend(ns_records(zone));
+    }
+
+    // Select the records that the client asked for.  If the name is an alias,
+    // then the answer is the CNAME record, followed by whatever records of
+    // the requested type we find by following it (to the extent that it
+    // stays within our zones).
+    let follow_cnames =
+        query_type != RecordType::CNAME && query_type != RecordType::ANY;
+    let mut response_records = Vec::new();
+    for _ in 0..MAX_CNAME_CHAIN {
+        let cname_target =
+            records.iter().find_map(|record| match record.data() {
+                Some(RData::CNAME(target)) if follow_cnames => {
+                    Some(target.clone())
                 }
+                _ => None,
+            });
+        let Some(target) = cname_target else {
+            response_records.extend(records.into_iter().filter(|record| {
+                query_type == RecordType::ANY
+                    || record.record_type() == query_type
+            }));
+            break;
+        };
+
+        response_records.extend(
+            records
+                .into_iter()
+                .filter(|record| record.record_type() == RecordType::CNAME),
+        );
+        // If we can't resolve the target ourselves, the client will have to.
+        let Ok(target_records) = store.query_name(&target) else {
+            break;
+        };
+        records = target_records
+            .into_iter()
+            .map(|record| dns_record_to_record(&target, record))
+            .collect::<Result<Vec<_>, _>>()?;
+    }
+
+    // DNS allows for the server to return additional records that weren't
+    // explicitly asked for by the client but that the server expects the
+    // client will want.  The address records corresponding to a lookup on a
+    // SRV target is one such case.  We opportunistically attempt to resolve
+    // the target here and if successful return those additional records in the
+    // response.
+    // NOTE: we only do this one-layer deep.
+    let mut additional_records = vec![];
+    for record in &response_records {
+        let Some(RData::SRV(srv)) = record.data() else {
+            continue;
+        };
+        let target_records = store.query_name(srv.target()).map(|records| {
+            records
+                .into_iter()
+                .filter(|record| {
+                    matches!(record, DnsRecord::A(_) | DnsRecord::AAAA(_))
+                })
+                .map(|record| dns_record_to_record(srv.target(), record))
+                .collect::<Result<Vec<_>, _>>()
+        });
+        match target_records {
+            Ok(Ok(target_records)) => {
+                additional_records.extend(target_records);
+            }
+            // Don't bail out if we failed to lookup or handle the response as
+            // the original request did succeed and we only care to do this on
+            // a best-effort basis.
+            Err(error) => {
+                slog::warn!(
+                    &log,
+                    "SRV target lookup failed";
+                    "original_mr" => #?mr,
+                    "target" => ?srv.target(),
+                    "error" => ?error,
+                );
             }
-            Ok(record)
-        })
-        .collect::<Result<Vec<_>, RequestError>>()?;
+            Ok(Err(error)) => {
+                slog::warn!(
+                    &log,
+                    "SRV target unexpected response";
+                    "original_mr" => #?mr,
+                    "target" => ?srv.target(),
+                    "error" => ?error,
+                );
+            }
+        }
+    }
+
     debug!(
         &log,
         "dns response";
@@ -332,51 +1130,92 @@ async fn handle_dns_message(
         "records" => ?&response_records,
         "additional_records" => ?&additional_records,
     );
-    respond_records(request, rb, header, &response_records, &additional_records)
-        .await
+
+    // If the name exists but has no records of the requested type, the answer
+    // is empty ("NODATA"), with the SOA record in the authority section.
+    let authority_records: &[Record] =
+        if response_records.is_empty() { &soa } else { &[] };
+    respond_records(
+        request,
+        mr,
+        header,
+        &response_records,
+        authority_records,
+        &additional_records,
+    )
+    .await
+}
+
+/// Returns a builder for the response to the given request
+///
+/// If the client sent an EDNS0 OPT record, the response includes one, too,
+/// advertising the largest UDP payload we're willing to send (RFC 6891).
+fn response_builder(mr: &MessageRequest) -> MessageResponseBuilder<'_> {
+    let mut rb = MessageResponseBuilder::from_message_request(mr);
+    if mr.edns().is_some() {
+        let mut edns = Edns::new();
+        edns.set_max_payload(MAX_UDP_PAYLOAD);
+        rb.edns(edns);
+    }
+    rb
 }
 
 /// Respond to a DNS query with the given set of DNS records
+///
+/// `soa_records` go in the authority section.  This is used for negative
+/// answers.
 async fn respond_records(
     request: &Request,
-    rb: MessageResponseBuilder<'_>,
+    mr: &MessageRequest,
     header: Header,
     response_records: &[Record],
+    soa_records: &[Record],
     additional_records: &[Record],
 ) -> Result<(), RequestError> {
-    let mresp = rb.build(
+    let encode_error = |error: anyhow::Error| {
+        RequestError::ServFail(anyhow!("failed to emit response: {:#}", error))
+    };
+
+    let mresp = response_builder(mr).build(
         header,
         response_records.iter().collect::<Vec<&Record>>(),
         vec![],
-        vec![],
+        soa_records,
         additional_records,
     );
+    let (mut resp_data, mut resp_header) =
+        encode_response(request, mresp, "records").map_err(encode_error)?;
 
-    encode_and_send(&request, mresp, "records").await.map_err(|error| {
-        RequestError::ServFail(anyhow!("failed to emit response: {:#}", error))
-    })
-}
+    // The encoder sets the TC bit whenever it runs out of room, but the
+    // additional records are only a hint: if all of the answers fit, it's
+    // better to leave the additional records out than to make the client
+    // retry over TCP (RFC 2181, section 9).
+    if resp_header.truncated()
+        && usize::from(resp_header.answer_count()) == response_records.len()
+    {
+        let mresp = response_builder(mr).build(
+            header,
+            response_records.iter().collect::<Vec<&Record>>(),
+            vec![],
+            soa_records,
+            vec![],
+        );
+        (resp_data, resp_header) =
+            encode_response(request, mresp, "records").map_err(encode_error)?;
+    }
 
-/// Respond to a DNS query with an NXDOMAIN error
-///
-/// This means that we are authoritative for the parent domain and the requested
-/// name definitely does not exist.
-async fn respond_nxdomain(
-    request: &Request,
-    rb_nxdomain: MessageResponseBuilder<'_>,
-    rb_servfail: MessageResponseBuilder<'_>,
-    header: &Header,
-) {
-    let log = &request.log;
-    let mresp = rb_nxdomain.error_msg(&header, ResponseCode::NXDomain);
-    if let Err(error) = encode_and_send(request, mresp, "NXDOMAIN").await {
-        error!(
-            log,
-            "switching to SERVFAIL after failure to encode NXDOMAIN ({:#})",
-            error
+    if resp_header.truncated() {
+        debug!(
+            &request.log,
+            "truncated response";
+            "max_response_size" => request.max_response_size,
+            "answers_sent" => resp_header.answer_count(),
+            "answers_total" => response_records.len(),
         );
-        respond_servfail(request, rb_servfail, header).await;
     }
+
+    send_response(request, &resp_data, "records").await;
+    Ok(())
 }
 
 /// Respond to a DNS query with a SERVFAIL error
@@ -390,46 +1229,106 @@ async fn respond_servfail(
     header: &Header,
 ) {
     let mresp = rb.error_msg(header, ResponseCode::ServFail);
-    if let Err(error) = encode_and_send(request, mresp, "SERVFAIL").await {
-        error!(&request.log, "failed to send SERVFAIL: {:#}", error);
+    match encode_response(request, mresp, "SERVFAIL") {
+        Ok((resp_data, _)) => {
+            send_response(request, &resp_data, "SERVFAIL").await
+        }
+        Err(error) => {
+            error!(&request.log, "failed to send SERVFAIL: {:#}", error);
+        }
+    }
+}
+
+/// Sends an empty response with the TC bit set, which tells the client to
+/// retry its query over TCP
+async fn respond_slip(request: &Request, mr: &MessageRequest) {
+    let mut header = Header::response_from_request(mr.header());
+    header.set_truncated(true);
+    let mresp = response_builder(mr).build_no_records(header);
+    match encode_response(request, mresp, "rate-limited") {
+        Ok((resp_data, _)) => {
+            send_response(request, &resp_data, "rate-limited").await
+        }
+        Err(error) => {
+            error!(
+                &request.log,
+                "failed to send rate-limited response: {:#}", error
+            );
+        }
     }
 }
 
 /// Encode the given message (which might describe an error or a collection of
 /// records) as a reply to a request
-fn encode_and_send<'a, Answers, NameServers, Soa, Additionals>(
-    request: &'a Request,
+///
+/// The encoded message is no larger than the request's `max_response_size`.
+/// Records that don't fit are left out and the TC bit is set in the returned
+/// header, which reflects what was actually encoded.
+fn encode_response<'a, Answers, NameServers, Soa, Additionals>(
+    request: &Request,
     mresp: MessageResponse<'a, 'a, Answers, NameServers, Soa, Additionals>,
     label: &'static str,
-) -> impl std::future::Future<Output = anyhow::Result<()>> + 'a
+) -> anyhow::Result<(Vec<u8>, Header)>
 where
     Answers: Iterator<Item = &'a Record> + Send + 'a,
     NameServers: Iterator<Item = &'a Record> + Send + 'a,
     Soa: Iterator<Item = &'a Record> + Send + 'a,
     Additionals: Iterator<Item = &'a Record> + Send + 'a,
 {
-    async move {
-        let mut resp_data = Vec::new();
-        let mut enc = BinEncoder::new(&mut resp_data);
-        let _ = mresp
-            .destructive_emit(&mut enc)
-            .with_context(|| format!("encoding {}", label))?;
-
-        // If we get this far and fail to send the data, there's nothing else to
-        // do.  Log the problem and treat this as a success as far as the caller
-        // is concerned.
-        if let Err(error) =
-            request.socket.send_to(&resp_data, &request.client_addr).await
-        {
-            error!(
-                &request.log,
-                "failed to send {} to {:?}: {:#}",
-                label,
-                request.client_addr.to_string(),
-                error
-            );
+    let mut resp_data = Vec::new();
+    let mut enc = BinEncoder::new(&mut resp_data);
+    enc.set_max_size(request.max_response_size);
+    let header = mresp
+        .destructive_emit(&mut enc)
+        .with_context(|| format!("encoding {}", label))?;
+    Ok((resp_data, header))
+}
+
+/// Send an encoded response back to the client that sent a request
+async fn send_response(request: &Request, resp_data: &[u8], label: &str) {
+    let result = match &request.transport {
+        Transport::Udp(socket) => {
+            socket.send_to(resp_data, &request.client_addr).await.map(|_| ())
         }
+        Transport::Tcp(writer) => {
+            // Over TCP, the message is preceded by its length.  (Encoding
+            // limits the message to `max_response_size`, which always fits.)
+            let length = u16::try_from(resp_data.len()).unwrap();
+            let mut framed = Vec::with_capacity(resp_data.len() + 2);
+            framed.extend_from_slice(&length.to_be_bytes());
+            framed.extend_from_slice(resp_data);
+            // A client that stops reading would otherwise leave us blocked
+            // here (and its connection open) indefinitely.
+            let mut writer = writer.lock().await;
+            match tokio::time::timeout(
+                TCP_IDLE_TIMEOUT,
+                writer.write_all(&framed),
+            )
+            .await
+            {
+                Ok(result) => result,
+                Err(_) => {
+                    // Whatever we wrote of this message has left the
+                    // connection unusable, so close it.
+                    let _ = writer.shutdown().await;
+                    Err(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        "timed out writing to TCP connection",
+                    ))
+                }
+            }
+        }
+    };
 
-        Ok(())
+    // If we get this far and fail to send the data, there's nothing else to
+    // do.  Log the problem and move on.
+    if let Err(error) = result {
+        error!(
+            &request.log,
+            "failed to send {} to {:?}: {:#}",
+            label,
+            request.client_addr.to_string(),
+            error
+        );
     }
 }
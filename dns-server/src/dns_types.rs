@@ -25,10 +25,21 @@ pub struct DnsConfig {
     pub zones: Vec<DnsConfigZone>,
 }
 
+/// Records for one DNS zone
+///
+/// Names in `records` are relative to the zone.  Records for the zone's own
+/// name (its apex) go under the name `@`.  The server synthesizes SOA and NS
+/// records for each zone from its `nameservers`.
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 pub struct DnsConfigZone {
     pub zone_name: String,
     pub records: HashMap<String, Vec<DnsRecord>>,
+    /// names of the zone's nameservers, relative to the zone (like the names
+    /// in `records`)
+    ///
+    /// Each of these should have address records for one of the DNS servers.
+    #[serde(default)]
+    pub nameservers: Vec<String>,
 }
 
 #[allow(clippy::upper_case_acronyms)]
@@ -38,6 +49,12 @@ pub enum DnsRecord {
     A(Ipv4Addr),
     AAAA(Ipv6Addr),
     SRV(SRV),
+    /// canonical name that this name is an alias for
+    CNAME(String),
+    /// text strings associated with this name
+    TXT(Vec<String>),
+    /// name that this (reverse-lookup) name points to
+    PTR(String),
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
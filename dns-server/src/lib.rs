@@ -4,8 +4,9 @@
 
 //! Dropshot-configurable DNS server
 //!
-//! This crate provides a standalone program that runs a UDP-based DNS server
-//! along with a Dropshot server for configuring the records served over DNS.
+//! This crate provides a standalone program that runs a DNS server (over both
+//! UDP and TCP) along with a Dropshot server for configuring the records served
+//! over DNS.
 //! The following RFDs describe the overall design of this server and how it's
 //! used:
 //!
@@ -45,6 +46,8 @@
 pub mod dns_server;
 pub mod dns_types;
 pub mod http_server;
+pub mod metrics;
+pub mod rate_limit;
 pub mod storage;
 
 use anyhow::{anyhow, Context};
@@ -134,7 +137,12 @@ impl TransientServer {
         let (dns_server, dropshot_server) = start_servers(
             dns_log,
             store,
-            &dns_server::Config { bind_address: dns_bind_address },
+            &dns_server::Config {
+                bind_address: dns_bind_address,
+                transfer_peers: vec![],
+                notify_secondaries: vec![],
+                rate_limit: Default::default(),
+            },
             &dropshot::ConfigDropshot {
                 bind_address: "[::1]:0".parse().unwrap(),
                 request_body_max_bytes: 4 * 1024 * 1024,
@@ -163,13 +171,17 @@ impl TransientServer {
 
     pub async fn resolver(&self) -> Result<TokioAsyncResolver, anyhow::Error> {
         let mut resolver_config = ResolverConfig::new();
-        resolver_config.add_name_server(NameServerConfig {
-            socket_addr: self.dns_server.local_address(),
-            protocol: Protocol::Udp,
-            tls_dns_name: None,
-            trust_nx_responses: false,
-            bind_addr: None,
-        });
+        // Configuring TCP as well as UDP allows the resolver to retry over TCP
+        // when a response is too large for UDP.
+        for protocol in [Protocol::Udp, Protocol::Tcp] {
+            resolver_config.add_name_server(NameServerConfig {
+                socket_addr: self.dns_server.local_address(),
+                protocol,
+                tls_dns_name: None,
+                trust_nx_responses: false,
+                bind_addr: None,
+            });
+        }
         let resolver =
             TokioAsyncResolver::tokio(resolver_config, ResolverOpts::default())
                 .context("creating DNS resolver")?;
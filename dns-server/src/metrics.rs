@@ -0,0 +1,173 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Metrics describing the DNS server's request handling
+//!
+//! The DNS server keeps simple counters as it handles requests.  A
+//! [`Producer`] reports them to oximeter.
+
+use chrono::DateTime;
+use chrono::Utc;
+use oximeter::types::Cumulative;
+use oximeter::types::Sample;
+use oximeter::Metric;
+use oximeter::MetricsError;
+use oximeter::Target;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Counters maintained by the DNS server
+#[derive(Debug, Default)]
+pub struct Counters {
+    requests_udp: AtomicU64,
+    requests_tcp: AtomicU64,
+    rate_limited_dropped: AtomicU64,
+    rate_limited_slipped: AtomicU64,
+    overloaded_dropped: AtomicU64,
+    tcp_connections_refused: AtomicU64,
+}
+
+impl Counters {
+    pub(crate) fn request_udp(&self) {
+        self.requests_udp.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn request_tcp(&self) {
+        self.requests_tcp.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn rate_limited_dropped(&self) {
+        self.rate_limited_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn rate_limited_slipped(&self) {
+        self.rate_limited_slipped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn overloaded_dropped(&self) {
+        self.overloaded_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn tcp_connection_refused(&self) {
+        self.tcp_connections_refused.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Identifies a DNS server in its timeseries
+#[derive(Debug, Clone, Target)]
+pub struct DnsServer {
+    pub server_id: Uuid,
+}
+
+/// Number of DNS requests received
+#[derive(Debug, Clone, Metric)]
+pub struct RequestsReceived {
+    /// "udp" or "tcp"
+    pub transport: String,
+    #[datum]
+    pub count: Cumulative<i64>,
+}
+
+/// Number of DNS requests dropped without a response
+#[derive(Debug, Clone, Metric)]
+pub struct RequestsDropped {
+    /// "rate_limited" (the client's prefix was over its response rate) or
+    /// "overloaded" (the server was handling too many requests already)
+    pub reason: String,
+    #[datum]
+    pub count: Cumulative<i64>,
+}
+
+/// Number of rate-limited DNS requests that got an empty, truncated response
+/// rather than being dropped
+#[derive(Debug, Clone, Metric)]
+pub struct ResponsesSlipped {
+    #[datum]
+    pub count: Cumulative<i64>,
+}
+
+/// Number of TCP connections closed as soon as they were accepted because the
+/// server already had as many open as it allows
+#[derive(Debug, Clone, Metric)]
+pub struct TcpConnectionsRefused {
+    #[datum]
+    pub count: Cumulative<i64>,
+}
+
+/// Reports a DNS server's [`Counters`] to oximeter
+#[derive(Debug, Clone)]
+pub struct Producer {
+    target: DnsServer,
+    start_time: DateTime<Utc>,
+    counters: Arc<Counters>,
+}
+
+impl Producer {
+    pub fn new(server_id: Uuid, counters: Arc<Counters>) -> Producer {
+        Producer {
+            target: DnsServer { server_id },
+            start_time: Utc::now(),
+            counters,
+        }
+    }
+
+    fn cumulative(&self, counter: &AtomicU64) -> Cumulative<i64> {
+        let value =
+            i64::try_from(counter.load(Ordering::Relaxed)).unwrap_or(i64::MAX);
+        Cumulative::with_start_time(self.start_time, value)
+    }
+}
+
+impl oximeter::Producer for Producer {
+    fn produce(
+        &mut self,
+    ) -> Result<Box<dyn Iterator<Item = Sample> + 'static>, MetricsError> {
+        let c = &self.counters;
+        let samples = vec![
+            Sample::new(
+                &self.target,
+                &RequestsReceived {
+                    transport: String::from("udp"),
+                    count: self.cumulative(&c.requests_udp),
+                },
+            )?,
+            Sample::new(
+                &self.target,
+                &RequestsReceived {
+                    transport: String::from("tcp"),
+                    count: self.cumulative(&c.requests_tcp),
+                },
+            )?,
+            Sample::new(
+                &self.target,
+                &RequestsDropped {
+                    reason: String::from("rate_limited"),
+                    count: self.cumulative(&c.rate_limited_dropped),
+                },
+            )?,
+            Sample::new(
+                &self.target,
+                &RequestsDropped {
+                    reason: String::from("overloaded"),
+                    count: self.cumulative(&c.overloaded_dropped),
+                },
+            )?,
+            Sample::new(
+                &self.target,
+                &ResponsesSlipped {
+                    count: self.cumulative(&c.rate_limited_slipped),
+                },
+            )?,
+            Sample::new(
+                &self.target,
+                &TcpConnectionsRefused {
+                    count: self.cumulative(&c.tcp_connections_refused),
+                },
+            )?,
+        ];
+        Ok(Box::new(samples.into_iter()))
+    }
+}
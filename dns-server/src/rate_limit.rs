@@ -0,0 +1,315 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Limits on the work the DNS server does on behalf of clients
+//!
+//! The external DNS server is reachable from the internet, so it needs to
+//! protect itself (and potential victims of reflection attacks) from floods of
+//! queries.  There are three mechanisms here:
+//!
+//! * A global cap on the number of requests we'll handle concurrently.
+//!   Requests that arrive when we're at the cap are dropped.
+//!
+//! * A cap on the number of TCP connections open at once.  Connections
+//!   accepted beyond the cap are closed immediately.  (The DNS server also
+//!   closes connections that sit idle, or whose client stops reading
+//!   responses, for too long.)
+//!
+//! * Response rate limiting (RRL) for UDP, applied per source address prefix
+//!   (so that a client can't evade the limit by using many addresses in the
+//!   same network).  Each prefix is hashed into a fixed-size table of token
+//!   buckets that refill at a configured rate.  When a prefix's bucket runs
+//!   out of tokens, we drop most of its queries, but "slip" every Nth one
+//!   through as an empty, truncated response.  A legitimate client that gets
+//!   one of those retries over TCP, which can't be spoofed and isn't
+//!   rate-limited.
+//!
+//! The table's size bounds the memory and time we spend on this no matter how
+//! many prefixes are sending queries.  Prefixes that hash to the same bucket
+//! share its limit, so a flood from many prefixes limits everyone rather than
+//! escaping the limit.  The hash is keyed randomly so that an attacker can't
+//! choose prefixes that collide with a particular victim's.
+//!
+//! This is modeled after the RRL implementation in BIND, though much simpler.
+
+use serde::Deserialize;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Configuration for limiting the work done on behalf of clients
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Config {
+    /// Maximum number of requests (or, for TCP, messages) handled at once
+    pub max_concurrent_requests: usize,
+    /// Maximum number of TCP connections open at once
+    pub max_tcp_connections: usize,
+    /// Number of responses per second that we'll send over UDP to each source
+    /// prefix.  Zero disables rate limiting.
+    pub responses_per_second: u32,
+    /// Of the queries from a prefix that's over its limit, every `slip`th one
+    /// gets a truncated response instead of being dropped.  Zero means they're
+    /// all dropped.
+    pub slip: u32,
+    /// Length of the prefix that IPv4 clients are grouped by
+    pub ipv4_prefix_len: u8,
+    /// Length of the prefix that IPv6 clients are grouped by
+    pub ipv6_prefix_len: u8,
+    /// Number of token buckets that source prefixes are hashed into.  Prefixes
+    /// that hash to the same bucket share its limit.
+    pub max_tracked_prefixes: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            max_concurrent_requests: 1024,
+            max_tcp_connections: 256,
+            responses_per_second: 0,
+            slip: 2,
+            ipv4_prefix_len: 24,
+            ipv6_prefix_len: 56,
+            max_tracked_prefixes: 65536,
+        }
+    }
+}
+
+/// Describes what to do with a query from a particular client
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Verdict {
+    /// Handle the query normally
+    Allow,
+    /// Respond with an empty, truncated message so that the client retries
+    /// over TCP
+    Slip,
+    /// Drop the query without responding
+    Drop,
+}
+
+/// Token bucket for the source prefixes that hash to it
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+    /// number of queries that found the bucket empty (used to decide which
+    /// ones slip through)
+    nlimited: u32,
+}
+
+/// Tracks per-prefix response rates for UDP clients
+///
+/// `S` builds the hasher that assigns prefixes to buckets.  Outside of tests,
+/// this is always the randomly-keyed [`RandomState`].
+#[derive(Debug)]
+pub struct RateLimiter<S = RandomState> {
+    config: Config,
+    hasher: S,
+    buckets: Mutex<Vec<Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &Config) -> RateLimiter {
+        RateLimiter::with_hasher(config, RandomState::new())
+    }
+}
+
+impl<S: BuildHasher> RateLimiter<S> {
+    fn with_hasher(config: &Config, hasher: S) -> RateLimiter<S> {
+        // There's no need for any buckets if rate limiting is disabled.
+        let nbuckets = if config.responses_per_second == 0 {
+            0
+        } else {
+            config.max_tracked_prefixes.max(1)
+        };
+        let now = Instant::now();
+        let buckets = (0..nbuckets)
+            .map(|_| Bucket {
+                tokens: f64::from(config.responses_per_second),
+                last_refill: now,
+                nlimited: 0,
+            })
+            .collect();
+        RateLimiter {
+            config: config.clone(),
+            hasher,
+            buckets: Mutex::new(buckets),
+        }
+    }
+
+    /// Decides what to do with a query that just arrived from `client`
+    pub fn check(&self, client: IpAddr) -> Verdict {
+        self.check_at(client, Instant::now())
+    }
+
+    fn check_at(&self, client: IpAddr, now: Instant) -> Verdict {
+        let rate = self.config.responses_per_second;
+        if rate == 0 {
+            return Verdict::Allow;
+        }
+
+        // Each prefix can burst up to one second's worth of responses.
+        let capacity = f64::from(rate);
+        let mut hasher = self.hasher.build_hasher();
+        self.prefix(client).hash(&mut hasher);
+        let mut buckets = self.buckets.lock().unwrap();
+        let nbuckets = buckets.len() as u64;
+        let bucket = &mut buckets[(hasher.finish() % nbuckets) as usize];
+        let elapsed = now
+            .checked_duration_since(bucket.last_refill)
+            .unwrap_or_default()
+            .as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * capacity).min(capacity);
+        bucket.last_refill = bucket.last_refill.max(now);
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            bucket.nlimited = 0;
+            return Verdict::Allow;
+        }
+
+        bucket.nlimited = bucket.nlimited.wrapping_add(1);
+        if self.config.slip != 0 && bucket.nlimited % self.config.slip == 0 {
+            Verdict::Slip
+        } else {
+            Verdict::Drop
+        }
+    }
+
+    /// Returns the prefix that `client` is grouped into
+    fn prefix(&self, client: IpAddr) -> IpAddr {
+        // Treat IPv4-mapped IPv6 addresses the same as the IPv4 addresses
+        // they represent.
+        let client = match client {
+            IpAddr::V6(addr) => {
+                addr.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(client)
+            }
+            IpAddr::V4(_) => client,
+        };
+        match client {
+            IpAddr::V4(addr) => {
+                let len = u32::from(self.config.ipv4_prefix_len.min(32));
+                let mask = u32::MAX.checked_shl(32 - len).unwrap_or(0);
+                IpAddr::V4(Ipv4Addr::from(u32::from(addr) & mask))
+            }
+            IpAddr::V6(addr) => {
+                let len = u32::from(self.config.ipv6_prefix_len.min(128));
+                let mask = u128::MAX.checked_shl(128 - len).unwrap_or(0);
+                IpAddr::V6(Ipv6Addr::from(u128::from(addr) & mask))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Config;
+    use super::RateLimiter;
+    use super::Verdict;
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::BuildHasherDefault;
+    use std::net::IpAddr;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    /// Hashes prefixes the same way every time, so that whether two prefixes
+    /// share a bucket doesn't vary from run to run
+    type FixedState = BuildHasherDefault<DefaultHasher>;
+
+    fn limiter(
+        responses_per_second: u32,
+        slip: u32,
+    ) -> RateLimiter<FixedState> {
+        RateLimiter::with_hasher(
+            &Config { responses_per_second, slip, ..Default::default() },
+            FixedState::default(),
+        )
+    }
+
+    #[test]
+    fn test_rate_limit_disabled() {
+        let limiter = limiter(0, 2);
+        let client: IpAddr = "192.0.2.1".parse().unwrap();
+        for _ in 0..1000 {
+            assert_eq!(limiter.check(client), Verdict::Allow);
+        }
+    }
+
+    #[test]
+    fn test_rate_limit_slip_and_refill() {
+        let limiter = limiter(4, 2);
+        let client: IpAddr = "192.0.2.1".parse().unwrap();
+        let now = Instant::now();
+
+        // The bucket starts full, so a burst of up to the rate is allowed.
+        for _ in 0..4 {
+            assert_eq!(limiter.check_at(client, now), Verdict::Allow);
+        }
+
+        // After that, every other query slips through as a truncated
+        // response, and the rest are dropped.
+        assert_eq!(limiter.check_at(client, now), Verdict::Drop);
+        assert_eq!(limiter.check_at(client, now), Verdict::Slip);
+        assert_eq!(limiter.check_at(client, now), Verdict::Drop);
+        assert_eq!(limiter.check_at(client, now), Verdict::Slip);
+
+        // Clients in the same prefix share the limit, but others don't.
+        let neighbor: IpAddr = "192.0.2.200".parse().unwrap();
+        assert_eq!(limiter.check_at(neighbor, now), Verdict::Drop);
+        let other: IpAddr = "198.51.100.1".parse().unwrap();
+        assert_eq!(limiter.check_at(other, now), Verdict::Allow);
+        let mapped: IpAddr = "::ffff:192.0.2.7".parse().unwrap();
+        assert_eq!(limiter.check_at(mapped, now), Verdict::Slip);
+
+        // The bucket refills over time.
+        let later = now + Duration::from_millis(500);
+        assert_eq!(limiter.check_at(client, later), Verdict::Allow);
+        assert_eq!(limiter.check_at(client, later), Verdict::Allow);
+        assert_eq!(limiter.check_at(client, later), Verdict::Drop);
+    }
+
+    #[test]
+    fn test_rate_limit_no_slip() {
+        let limiter = limiter(1, 0);
+        let client: IpAddr = "2001:db8::1".parse().unwrap();
+        let now = Instant::now();
+        assert_eq!(limiter.check_at(client, now), Verdict::Allow);
+        for _ in 0..10 {
+            assert_eq!(limiter.check_at(client, now), Verdict::Drop);
+        }
+
+        // IPv6 clients are grouped by /56 by default.
+        let neighbor: IpAddr = "2001:db8:0:ff::1".parse().unwrap();
+        assert_eq!(limiter.check_at(neighbor, now), Verdict::Drop);
+        let other: IpAddr = "2001:db8:0:100::1".parse().unwrap();
+        assert_eq!(limiter.check_at(other, now), Verdict::Allow);
+    }
+
+    #[test]
+    fn test_rate_limit_shared_buckets() {
+        // With a single bucket, every prefix shares the same limit: new
+        // prefixes don't get a fresh allowance of their own.
+        let limiter = RateLimiter::new(&Config {
+            responses_per_second: 2,
+            slip: 0,
+            max_tracked_prefixes: 1,
+            ..Default::default()
+        });
+        let now = Instant::now();
+        for i in 0..2 {
+            let client = IpAddr::from([192, 0, 2 + i, 1]);
+            assert_eq!(limiter.check_at(client, now), Verdict::Allow);
+        }
+        for i in 0..100 {
+            let client = IpAddr::from([198, 51, i, 1]);
+            assert_eq!(limiter.check_at(client, now), Verdict::Drop);
+        }
+    }
+}
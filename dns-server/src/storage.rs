@@ -36,7 +36,8 @@
 // - "generation_$generation_zone_$zoneid": describes the DNS data for this
 //   zone.  Keys in this tree represent DNS names (excluding the zone's DNS name
 //   itself, which needs to be appended to each key to get the fully-qualified
-//   domain name).  Each value is a Vec of DNS records.
+//   domain name).  Each value is a Vec of DNS records.  The one exception is
+//   the key "$nameservers", whose value is the list of the zone's nameservers.
 //
 // For all values in the sled database, we store JSON-serialized Rust
 // structures.  We don't have to worry about versioning or compatibility of any
@@ -98,17 +99,26 @@ use camino::Utf8PathBuf;
 use serde::{Deserialize, Serialize};
 use sled::transaction::ConflictableTransactionError;
 use slog::{debug, error, info, o, warn};
+use std::collections::HashMap;
 use std::str::FromStr;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use thiserror::Error;
+use tokio::sync::watch;
 use tokio::sync::Mutex;
 use trust_dns_client::rr::LowerName;
 use trust_dns_client::rr::Name;
 
 const KEY_CONFIG: &'static str = "config";
 
+/// Name under which records for a zone's apex (the zone's own name) are stored
+pub const ZONE_APEX_KEY: &str = "@";
+
+/// Key in each zone's tree under which the zone's nameservers are stored
+/// (which is not a name that can be given records)
+const KEY_ZONE_NAMESERVERS: &str = "$nameservers";
+
 /// Configuration for persistent storage of DNS data
 #[derive(Deserialize, Debug)]
 pub struct Config {
@@ -126,6 +136,14 @@ pub struct Store {
     keep: usize,
     updating: Arc<Mutex<Option<UpdateInfo>>>,
     poisoned: Arc<AtomicBool>,
+    /// publishes the current generation whenever an update is committed
+    generation_tx: Arc<watch::Sender<u64>>,
+    /// descriptions of the zones in the most recent generation that we've
+    /// answered queries for, along with that generation
+    ///
+    /// Each generation's data is immutable, so these only need to be loaded
+    /// once per generation.
+    zones_cache: Arc<std::sync::Mutex<Option<(u64, Arc<Vec<ZoneInfo>>)>>>,
 }
 
 #[derive(Debug, Deserialize, Eq, PartialEq, Serialize)]
@@ -187,6 +205,8 @@ impl Store {
             keep: config.keep_old_generations,
             updating: Arc::new(Mutex::new(None)),
             poisoned: Arc::new(AtomicBool::new(false)),
+            generation_tx: Arc::new(watch::channel(0).0),
+            zones_cache: Arc::new(std::sync::Mutex::new(None)),
         };
         if store.read_config_optional()?.is_none() {
             let now = chrono::Utc::now();
@@ -206,9 +226,16 @@ impl Store {
         let config = store.read_config()?;
         store.prune_newer(&config);
         store.prune_older(&config);
+        store.generation_tx.send_replace(config.generation);
         Ok(store)
     }
 
+    /// Returns a receiver that's notified with the new generation number
+    /// whenever an update to the DNS data is committed
+    pub(crate) fn watch_generation(&self) -> watch::Receiver<u64> {
+        self.generation_tx.subscribe()
+    }
+
     /// Returns true if this Store's database was newly created when this Store
     /// was created (i.e., we did not restore data from an old database)
     ///
@@ -263,29 +290,15 @@ impl Store {
                     .open_tree(&tree_name)
                     .with_context(|| format!("opening tree {:?}", tree_name))?;
 
-                let records = tree
-                    .iter()
-                    .map(|entry| {
-                        let (name_bytes, records_bytes) =
-                            entry.context("loading entry")?;
-                        let name = std::str::from_utf8(&name_bytes)
-                            .with_context(|| {
-                                format!("parsing {:?} key name", tree_name)
-                            })?;
-                        let records: Vec<DnsRecord> =
-                            serde_json::from_slice(&records_bytes)
-                                .with_context(|| {
-                                    format!(
-                                        "parsing {:?} key {:?}",
-                                        tree_name, name
-                                    )
-                                })?;
-                        Ok((name.to_owned(), records))
-                    })
-                    .collect::<anyhow::Result<_>>()
-                    .context("assembling records")?;
+                let records = Self::read_zone_tree(&tree, &tree_name)?;
+                let nameservers =
+                    Self::read_zone_nameservers(&tree, &tree_name)?;
 
-                Ok(DnsConfigZone { zone_name: zone_name.to_owned(), records })
+                Ok(DnsConfigZone {
+                    zone_name: zone_name.to_owned(),
+                    records,
+                    nameservers,
+                })
             })
             .collect::<anyhow::Result<_>>()?;
 
@@ -297,6 +310,50 @@ impl Store {
         })
     }
 
+    /// Reads all of the names and records in a zone's tree
+    fn read_zone_tree(
+        tree: &sled::Tree,
+        tree_name: &str,
+    ) -> anyhow::Result<HashMap<String, Vec<DnsRecord>>> {
+        tree.iter()
+            .filter(|entry| {
+                !matches!(entry, Ok((name_bytes, _))
+                    if name_bytes == KEY_ZONE_NAMESERVERS.as_bytes())
+            })
+            .map(|entry| {
+                let (name_bytes, records_bytes) =
+                    entry.context("loading entry")?;
+                let name =
+                    std::str::from_utf8(&name_bytes).with_context(|| {
+                        format!("parsing {:?} key name", tree_name)
+                    })?;
+                let records: Vec<DnsRecord> =
+                    serde_json::from_slice(&records_bytes).with_context(
+                        || format!("parsing {:?} key {:?}", tree_name, name),
+                    )?;
+                Ok((name.to_owned(), records))
+            })
+            .collect::<anyhow::Result<_>>()
+            .context("assembling records")
+    }
+
+    /// Reads the names of a zone's nameservers (relative to the zone) from its
+    /// tree
+    fn read_zone_nameservers(
+        tree: &sled::Tree,
+        tree_name: &str,
+    ) -> anyhow::Result<Vec<String>> {
+        tree.get(KEY_ZONE_NAMESERVERS)
+            .with_context(|| format!("loading {:?} nameservers", tree_name))?
+            .map(|bytes| {
+                serde_json::from_slice(&bytes).with_context(|| {
+                    format!("parsing {:?} nameservers", tree_name)
+                })
+            })
+            .transpose()
+            .map(Option::unwrap_or_default)
+    }
+
     async fn begin_update<'a, 'b>(
         &'a self,
         req_id: &'b str,
@@ -404,6 +461,21 @@ impl Store {
                 .open_tree(&tree_name)
                 .with_context(|| format!("creating tree {:?}", &tree_name))?;
 
+            if zone_config.records.contains_key(KEY_ZONE_NAMESERVERS) {
+                return Err(UpdateError::InternalError(anyhow!(
+                    "zone {:?} has records for reserved name {:?}",
+                    zone_name,
+                    KEY_ZONE_NAMESERVERS
+                )));
+            }
+            let nameservers_json = serde_json::to_vec(&zone_config.nameservers)
+                .with_context(|| {
+                    format!("serializing nameservers for zone {:?}", zone_name)
+                })?;
+            tree.insert(KEY_ZONE_NAMESERVERS, nameservers_json).with_context(
+                || format!("inserting nameservers for zone {:?}", zone_name),
+            )?;
+
             for (name, records) in &zone_config.records {
                 if records.is_empty() {
                     // There's no distinction between in DNS between a name that
@@ -486,6 +558,7 @@ impl Store {
         self.db.flush_async().await.context("flush")?;
 
         self.prune_older(&new_config);
+        self.generation_tx.send_replace(generation);
         Ok(())
     }
 
@@ -580,17 +653,92 @@ impl Store {
         self.prune_trees(trees_to_prune, "too old");
     }
 
-    /// Returns a non-empty list of DNS records associated with the name in the
-    /// given DNS request.
+    /// Describes each of the zones in the current generation
+    pub(crate) fn zones(&self) -> Result<Arc<Vec<ZoneInfo>>, QueryError> {
+        let config = self.read_config().map_err(QueryError::QueryFail)?;
+        self.zones_for_config(&config)
+    }
+
+    /// Describes each of the zones in the generation described by `config`,
+    /// loading them only if they're not already cached
+    fn zones_for_config(
+        &self,
+        config: &CurrentConfig,
+    ) -> Result<Arc<Vec<ZoneInfo>>, QueryError> {
+        if let Some((generation, zones)) = &*self.zones_cache.lock().unwrap() {
+            if *generation == config.generation {
+                return Ok(Arc::clone(zones));
+            }
+        }
+
+        let zones = config
+            .zones
+            .iter()
+            .map(|zone_name| {
+                let tree = self.zone_tree(zone_name, config.generation)?;
+                self.zone_info(&tree, zone_name, config.generation)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let zones = Arc::new(zones);
+
+        // If another query raced with us to load a newer generation, keep that
+        // one instead.
+        let mut cache = self.zones_cache.lock().unwrap();
+        if cache.as_ref().map_or(true, |(g, _)| *g < config.generation) {
+            *cache = Some((config.generation, Arc::clone(&zones)));
+        }
+        Ok(zones)
+    }
+
+    /// Returns the given zone as of the given generation, along with all of
+    /// its names and records
     ///
-    /// If the returned set would have been empty, returns `QueryError::NoName`.
+    /// Names are relative to the zone, as in [`DnsConfigZone`].  Returns `None`
+    /// if we don't have the zone's data for that generation, either because
+    /// it's been pruned or because the zone didn't exist then.
+    pub(crate) fn zone_records(
+        &self,
+        zone: &ZoneInfo,
+        generation: u64,
+    ) -> Result<Option<(ZoneInfo, HashMap<String, Vec<DnsRecord>>)>, QueryError>
+    {
+        let tree_name = Self::tree_name_for_zone(&zone.zone_name, generation);
+        // Check that the tree exists first because opening it would create it.
+        if !self.all_name_trees().any(|(_, name)| name == tree_name) {
+            return Ok(None);
+        }
+        let tree = self.zone_tree(&zone.zone_name, generation)?;
+        let zone_then = self.zone_info(&tree, &zone.zone_name, generation)?;
+        let records = Self::read_zone_tree(&tree, &tree_name)
+            .map_err(QueryError::QueryFail)?;
+        Ok(Some((zone_then, records)))
+    }
+
+    fn zone_tree(
+        &self,
+        zone_name: &str,
+        generation: u64,
+    ) -> Result<sled::Tree, QueryError> {
+        let tree_name = Self::tree_name_for_zone(zone_name, generation);
+        self.db
+            .open_tree(&tree_name)
+            .with_context(|| format!("open tree {:?}", tree_name))
+            .map_err(QueryError::QueryFail)
+    }
+
+    /// Looks up the name in the given DNS request in the zone that's
+    /// authoritative for it
+    ///
+    /// Unlike [`Store::query_name()`], a name that doesn't exist in its zone is
+    /// not an error here: the caller gets back the information it needs about
+    /// the zone to say so (namely, to construct an SOA record).
     pub(crate) fn query(
         &self,
         mr: &trust_dns_server::authority::MessageRequest,
-    ) -> Result<Vec<DnsRecord>, QueryError> {
+    ) -> Result<ZoneLookup, QueryError> {
         let name = mr.query().name();
         let orig_name = mr.query().original().name();
-        self.query_raw(name, orig_name)
+        self.lookup(name, orig_name)
     }
 
     /// Returns a non-empty list of DNS records associated with the given name.
@@ -608,50 +756,57 @@ impl Store {
         name: &LowerName,
         orig_name: &Name,
     ) -> Result<Vec<DnsRecord>, QueryError> {
-        let config = self.read_config().map_err(QueryError::QueryFail)?;
+        self.lookup(name, orig_name)?
+            .records
+            .ok_or_else(|| QueryError::NoName(orig_name.to_string()))
+    }
 
-        let zone_name = config
-            .zones
+    fn lookup(
+        &self,
+        name: &LowerName,
+        orig_name: &Name,
+    ) -> Result<ZoneLookup, QueryError> {
+        let zones = self.zones()?;
+        let zone = zones
             .iter()
-            .find(|z| {
-                let zone_name = LowerName::from(Name::from_str(&z).unwrap());
-                zone_name.zone_of(name)
-            })
-            .ok_or_else(|| QueryError::NoZone(orig_name.to_string()))?;
+            .find(|z| LowerName::new(&z.name).zone_of(name))
+            .ok_or_else(|| QueryError::NoZone(orig_name.to_string()))?
+            .clone();
 
-        let tree_name = Self::tree_name_for_zone(zone_name, config.generation);
-        let tree = self
-            .db
-            .open_tree(&tree_name)
-            .with_context(|| format!("open tree {:?}", tree_name))
-            .map_err(QueryError::QueryFail)?;
+        let tree_name =
+            Self::tree_name_for_zone(&zone.zone_name, zone.generation);
+        let tree = self.zone_tree(&zone.zone_name, zone.generation)?;
 
         // The name tree stores just the part of each name that doesn't include
         // the zone.  So we need to trim the zone part from the name provided in
         // the request.  (This basically duplicates work in `zone_of` above.)
-        let name_str = orig_name.to_string();
         let key = {
-            let zone_name = Name::from_str(zone_name).unwrap();
             // This is implied by passing the `zone_of()` check above.
-            assert!(zone_name.num_labels() <= orig_name.num_labels());
+            assert!(zone.name.num_labels() <= orig_name.num_labels());
             let name_only_labels =
-                usize::from(orig_name.num_labels() - zone_name.num_labels());
-            let mut name_only =
-                Name::from_labels(orig_name.iter().take(name_only_labels))
-                    .unwrap();
-            name_only.set_fqdn(false);
-            let key = name_only.to_string().to_lowercase();
-            assert!(!key.ends_with('.'));
-            key
+                usize::from(orig_name.num_labels() - zone.name.num_labels());
+            if name_only_labels == 0 {
+                String::from(ZONE_APEX_KEY)
+            } else {
+                let mut name_only =
+                    Name::from_labels(orig_name.iter().take(name_only_labels))
+                        .unwrap();
+                name_only.set_fqdn(false);
+                let key = name_only.to_string().to_lowercase();
+                assert!(!key.ends_with('.'));
+                key
+            }
         };
 
         debug!(&self.log, "query key"; "key" => &key);
 
-        let bits = tree
+        let Some(bits) = tree
             .get(key.as_bytes())
             .with_context(|| format!("query tree {:?}", tree_name))
             .map_err(QueryError::QueryFail)?
-            .ok_or_else(|| QueryError::NoName(name_str.clone()))?;
+        else {
+            return Ok(ZoneLookup { zone, records: None });
+        };
 
         let records: Vec<DnsRecord> = serde_json::from_slice(&bits)
             .with_context(|| format!("deserialize record for key {:?}", key))
@@ -666,13 +821,70 @@ impl Store {
                 "key" => &key
             );
 
-            return Err(QueryError::NoName(name_str));
+            return Ok(ZoneLookup { zone, records: None });
         }
 
-        Ok(records)
+        Ok(ZoneLookup { zone, records: Some(records) })
+    }
+
+    /// Describes the given zone as of the generation of `tree`
+    fn zone_info(
+        &self,
+        tree: &sled::Tree,
+        zone_name: &str,
+        generation: u64,
+    ) -> Result<ZoneInfo, QueryError> {
+        let mut name = Name::from_str(zone_name)
+            .with_context(|| format!("parse zone name {:?}", zone_name))
+            .map_err(QueryError::ParseFail)?;
+        name.set_fqdn(true);
+
+        let tree_name = Self::tree_name_for_zone(zone_name, generation);
+        let nameservers = Self::read_zone_nameservers(tree, &tree_name)
+            .map_err(QueryError::QueryFail)?
+            .iter()
+            .map(|nameserver| {
+                Name::from_str(nameserver)
+                    .and_then(|ns| ns.append_domain(&name))
+                    .with_context(|| {
+                        format!("parse nameserver name {:?}", nameserver)
+                    })
+                    .map_err(QueryError::ParseFail)
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(ZoneInfo {
+            name,
+            zone_name: zone_name.to_owned(),
+            generation,
+            nameservers,
+        })
     }
 }
 
+/// Describes a zone that this server is authoritative for
+#[derive(Clone, Debug)]
+pub(crate) struct ZoneInfo {
+    /// the zone's (fully-qualified) name
+    pub(crate) name: Name,
+    /// the zone's name as it appears in the DNS configuration
+    pub(crate) zone_name: String,
+    /// generation of the DNS data that this information came from
+    pub(crate) generation: u64,
+    /// fully-qualified names of the zone's nameservers, from the zone's
+    /// configuration
+    pub(crate) nameservers: Vec<Name>,
+}
+
+/// Describes the result of looking up a name in the zone that's authoritative
+/// for it
+#[derive(Debug)]
+pub(crate) struct ZoneLookup {
+    pub(crate) zone: ZoneInfo,
+    /// the name's records, or `None` if the name does not exist in the zone
+    pub(crate) records: Option<Vec<DnsRecord>>,
+}
+
 #[derive(Debug, Error)]
 pub(crate) enum QueryError {
     #[error("server is not authoritative for name: {0:?}")]
@@ -909,6 +1121,7 @@ mod test {
                     ("gen1_name".to_string(), vec![dummy_record.clone()]),
                     ("shared_name".to_string(), vec![dummy_record.clone()]),
                 ]),
+                nameservers: Vec::new(),
             }],
         };
 
@@ -950,6 +1163,7 @@ mod test {
                         "shared_name".to_string(),
                         vec![dummy_record.clone()],
                     )]),
+                    nameservers: Vec::new(),
                 },
                 DnsConfigZone {
                     zone_name: "zone2.internal".to_string(),
@@ -957,6 +1171,7 @@ mod test {
                         "gen2_name".to_string(),
                         vec![dummy_record.clone()],
                     )]),
+                    nameservers: Vec::new(),
                 },
             ],
         };
@@ -987,6 +1202,7 @@ mod test {
                     "gen8_name".to_string(),
                     vec![dummy_record.clone()],
                 )]),
+                nameservers: Vec::new(),
             }],
         };
         tc.store.dns_config_update(&update8, "my request id").await.unwrap();
@@ -1049,6 +1265,7 @@ mod test {
                     "gen8_name".to_string(),
                     vec![dummy_record.clone()],
                 )]),
+                nameservers: Vec::new(),
             }],
         };
         tc.store.dns_config_update(&update9, "my request id").await.unwrap();
@@ -1078,6 +1295,7 @@ mod test {
                     "gen1_name".to_string(),
                     vec![dummy_record.clone()],
                 )]),
+                nameservers: Vec::new(),
             }],
         };
 
@@ -1103,6 +1321,7 @@ mod test {
                     "gen2_name".to_string(),
                     vec![dummy_record.clone()],
                 )]),
+                nameservers: Vec::new(),
             }],
         };
 
@@ -1200,6 +1419,7 @@ mod test {
                     "gen1_name".to_string(),
                     vec![dummy_record.clone()],
                 )]),
+                nameservers: Vec::new(),
             }],
         };
 
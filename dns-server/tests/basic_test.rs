@@ -13,9 +13,17 @@ use slog::o;
 use std::{
     collections::HashMap,
     net::Ipv6Addr,
-    net::{IpAddr, Ipv4Addr},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    str::FromStr,
 };
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
 use trust_dns_resolver::error::ResolveErrorKind;
+use trust_dns_resolver::proto::op::{
+    Edns, Message, MessageType, OpCode, Query,
+};
+use trust_dns_resolver::proto::rr::rdata::SOA;
+use trust_dns_resolver::proto::rr::{Name, RData, Record, RecordType};
 use trust_dns_resolver::TokioAsyncResolver;
 use trust_dns_resolver::{
     config::{NameServerConfig, Protocol, ResolverConfig, ResolverOpts},
@@ -170,6 +178,761 @@ pub async fn multi_record_crud() -> Result<(), anyhow::Error> {
     Ok(())
 }
 
+// Number of SRV records (and targets) in the zone used to test responses that
+// don't fit in a single UDP message.  This is enough that the SRV answers
+// alone exceed 512 bytes and the answers plus the AAAA records for their
+// targets exceed 2048 bytes, but everything fits in 4096 bytes.
+const LARGE_SRV_COUNT: usize = 40;
+const LARGE_SRV_NAME: &str = "_nexus._tcp";
+
+/// Populates the test zone with `LARGE_SRV_COUNT` SRV records for
+/// `LARGE_SRV_NAME`, each with a distinct target that has an AAAA record
+async fn large_srv_zone_create(client: &Client) -> anyhow::Result<()> {
+    let mut input_records = HashMap::new();
+    let srvs = (0..LARGE_SRV_COUNT)
+        .map(|i| {
+            let target = format!("sled-{i:02}");
+            let addr = Ipv6Addr::new(0xfd, 0, 0, 0, 0, 0, 0, 0x100 + i as u16);
+            input_records.insert(target.clone(), vec![DnsRecord::Aaaa(addr)]);
+            DnsRecord::Srv(Srv {
+                prio: 0,
+                weight: 0,
+                port: 12221,
+                target: format!("{target}.{TEST_ZONE}"),
+            })
+        })
+        .collect();
+    input_records.insert(LARGE_SRV_NAME.to_string(), srvs);
+    dns_records_create(client, TEST_ZONE, input_records).await
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Transport {
+    Udp,
+    Tcp,
+}
+
+/// Sends a query for `name` directly to the DNS server at `server` and returns
+/// the response, advertising the given UDP payload size with EDNS0 if one is
+/// provided
+async fn dns_query(
+    server: SocketAddr,
+    transport: Transport,
+    name: &str,
+    record_type: RecordType,
+    edns_payload: Option<u16>,
+) -> anyhow::Result<Message> {
+    let mut query = Message::new();
+    query
+        .set_id(0x0de5)
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Query)
+        .add_query(Query::query(Name::from_str(name)?, record_type));
+    if let Some(max_payload) = edns_payload {
+        let mut edns = Edns::new();
+        edns.set_max_payload(max_payload);
+        query.set_edns(edns);
+    }
+    let query = query.to_vec()?;
+
+    let response = match transport {
+        Transport::Udp => {
+            let socket = UdpSocket::bind("[::1]:0").await?;
+            socket.send_to(&query, server).await?;
+            let mut buf = vec![0u8; 65536];
+            let n = socket.recv(&mut buf).await?;
+            buf.truncate(n);
+            buf
+        }
+        Transport::Tcp => {
+            // Messages over TCP are preceded by their two-byte length.
+            let mut stream = TcpStream::connect(server).await?;
+            let length = u16::try_from(query.len())?;
+            stream.write_all(&length.to_be_bytes()).await?;
+            stream.write_all(&query).await?;
+            let mut length = [0u8; 2];
+            stream.read_exact(&mut length).await?;
+            let mut buf = vec![0u8; usize::from(u16::from_be_bytes(length))];
+            stream.read_exact(&mut buf).await?;
+            buf
+        }
+    };
+    Ok(Message::from_vec(&response)?)
+}
+
+#[tokio::test]
+pub async fn large_srv_truncation() -> Result<(), anyhow::Error> {
+    let test_ctx = init_client_server("large_srv_truncation").await?;
+    let server = test_ctx.dns_server.local_address();
+    large_srv_zone_create(&test_ctx.client).await?;
+    let name = format!("{LARGE_SRV_NAME}.{TEST_ZONE}.");
+
+    // Without EDNS0, UDP responses are limited to 512 bytes, so the answers
+    // don't all fit and the client is told to retry over TCP.
+    let response =
+        dns_query(server, Transport::Udp, &name, RecordType::SRV, None).await?;
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert!(response.truncated());
+    assert!(response.answers().len() < LARGE_SRV_COUNT);
+    assert!(response.edns().is_none());
+
+    // With a larger EDNS0 payload size, the answers fit but the additional
+    // AAAA records don't.  Those are left out without truncating the
+    // response.
+    let response =
+        dns_query(server, Transport::Udp, &name, RecordType::SRV, Some(2048))
+            .await?;
+    assert!(!response.truncated());
+    assert_eq!(response.answers().len(), LARGE_SRV_COUNT);
+    assert!(response.additionals().is_empty());
+    assert!(response.edns().is_some());
+
+    // With a large enough payload size, everything fits.
+    let response =
+        dns_query(server, Transport::Udp, &name, RecordType::SRV, Some(4096))
+            .await?;
+    assert!(!response.truncated());
+    assert_eq!(response.answers().len(), LARGE_SRV_COUNT);
+    assert_eq!(response.additionals().len(), LARGE_SRV_COUNT);
+
+    // Clients can't ask for responses larger than we're willing to send.
+    let response = dns_query(
+        server,
+        Transport::Udp,
+        &name,
+        RecordType::SRV,
+        Some(u16::MAX),
+    )
+    .await?;
+    assert_eq!(
+        response.edns().map(|edns| edns.max_payload()),
+        Some(dns_server::dns_server::MAX_UDP_PAYLOAD)
+    );
+
+    // Over TCP, the whole response is always sent.
+    let response =
+        dns_query(server, Transport::Tcp, &name, RecordType::SRV, None).await?;
+    assert!(!response.truncated());
+    assert_eq!(response.answers().len(), LARGE_SRV_COUNT);
+    assert_eq!(response.additionals().len(), LARGE_SRV_COUNT);
+
+    test_ctx.cleanup().await;
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn large_srv_resolver() -> Result<(), anyhow::Error> {
+    let test_ctx = init_client_server("large_srv_resolver").await?;
+    large_srv_zone_create(&test_ctx.client).await?;
+
+    // The resolver doesn't use EDNS0 by default, so its UDP query gets a
+    // truncated response and it must retry over TCP to get all of the records.
+    let response = test_ctx
+        .resolver
+        .srv_lookup(format!("{LARGE_SRV_NAME}.{TEST_ZONE}."))
+        .await?;
+    assert_eq!(response.iter().count(), LARGE_SRV_COUNT);
+    assert_eq!(response.ip_iter().count(), LARGE_SRV_COUNT);
+
+    test_ctx.cleanup().await;
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn soa_ns_synthesized() -> Result<(), anyhow::Error> {
+    let test_ctx = init_client_server("soa_ns_synthesized").await?;
+    let client = &test_ctx.client;
+    let resolver = &test_ctx.resolver;
+
+    // The zone's nameservers come from its configuration, not from the names
+    // of its records.
+    let ns_addr = Ipv6Addr::new(0xfd, 0, 0, 0, 0, 0, 0, 0x53);
+    let input_records = HashMap::from([
+        ("ns1".to_string(), vec![DnsRecord::Aaaa(ns_addr)]),
+        ("ns2".to_string(), vec![DnsRecord::Aaaa(ns_addr)]),
+    ]);
+    dns_zone_update(client, TEST_ZONE, input_records, Some(vec!["ns1"]))
+        .await?;
+    let generation = client.dns_config_get().await?.into_inner().generation;
+
+    // The SOA serial tracks the generation of the DNS data.
+    let zone_apex = format!("{TEST_ZONE}.");
+    let response = resolver.soa_lookup(zone_apex.as_str()).await?;
+    let soa = response.iter().next().expect("no SOA record returned");
+    assert_eq!(u64::from(soa.serial()), generation);
+    assert_eq!(soa.mname().to_string(), format!("ns1.{TEST_ZONE}."));
+
+    let response = resolver.ns_lookup(zone_apex.as_str()).await?;
+    let nameservers =
+        response.iter().map(|ns| ns.to_string()).collect::<Vec<_>>();
+    assert_eq!(nameservers, [format!("ns1.{TEST_ZONE}.")]);
+
+    // A new generation of data gets a new serial.
+    let addr = Ipv4Addr::new(10, 1, 2, 3);
+    let input_records =
+        HashMap::from([("devron".to_string(), vec![DnsRecord::A(addr)])]);
+    dns_records_create(client, TEST_ZONE, input_records).await?;
+    let response = resolver.soa_lookup(zone_apex.as_str()).await?;
+    let soa = response.iter().next().expect("no SOA record returned");
+    assert_eq!(u64::from(soa.serial()), generation + 1);
+
+    test_ctx.cleanup().await;
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn query_type_filtering() -> Result<(), anyhow::Error> {
+    let test_ctx = init_client_server("query_type_filtering").await?;
+    let client = &test_ctx.client;
+    let server = test_ctx.dns_server.local_address();
+
+    let addr = Ipv6Addr::new(0xfd, 0, 0, 0, 0, 0, 0, 0x1);
+    let input_records =
+        HashMap::from([("devron".to_string(), vec![DnsRecord::Aaaa(addr)])]);
+    dns_records_create(client, TEST_ZONE, input_records).await?;
+    let name = format!("devron.{TEST_ZONE}.");
+
+    // Only records of the requested type are returned.
+    let response =
+        dns_query(server, Transport::Udp, &name, RecordType::AAAA, None)
+            .await?;
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert!(response.authoritative());
+    assert_eq!(response.answers().len(), 1);
+    assert_eq!(response.answers()[0].record_type(), RecordType::AAAA);
+
+    // If the name exists but has no records of the requested type, the answer
+    // is empty, with the zone's SOA record in the authority section.
+    let response =
+        dns_query(server, Transport::Udp, &name, RecordType::A, None).await?;
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert!(response.answers().is_empty());
+    assert_eq!(response.name_servers().len(), 1);
+    assert_eq!(response.name_servers()[0].record_type(), RecordType::SOA);
+
+    // Negative answers for names that don't exist include the SOA, too.
+    let response = dns_query(
+        server,
+        Transport::Udp,
+        &format!("unknown.{TEST_ZONE}."),
+        RecordType::AAAA,
+        None,
+    )
+    .await?;
+    assert_eq!(response.response_code(), ResponseCode::NXDomain);
+    assert!(response.answers().is_empty());
+    assert_eq!(response.name_servers().len(), 1);
+    assert_eq!(response.name_servers()[0].record_type(), RecordType::SOA);
+
+    // Negative answers may be cached briefly (RFC 2308).
+    let soa = &response.name_servers()[0];
+    let Some(RData::SOA(soa_data)) = soa.data() else {
+        panic!("expected SOA data, found {:?}", soa.data());
+    };
+    assert!(soa.ttl() > 0);
+    assert!(soa_data.minimum() > 0);
+
+    // ANY queries get every record.
+    let response =
+        dns_query(server, Transport::Udp, &name, RecordType::ANY, None).await?;
+    assert_eq!(response.answers().len(), 1);
+
+    test_ctx.cleanup().await;
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn cname_txt_ptr_crud() -> Result<(), anyhow::Error> {
+    let test_ctx = init_client_server("cname_txt_ptr_crud").await?;
+    let client = &test_ctx.client;
+    let resolver = &test_ctx.resolver;
+    let server = test_ctx.dns_server.local_address();
+
+    let addr = Ipv4Addr::new(10, 1, 2, 3);
+    let input_records = HashMap::from([
+        ("devron".to_string(), vec![DnsRecord::A(addr)]),
+        (
+            "alias".to_string(),
+            vec![DnsRecord::Cname(format!("devron.{TEST_ZONE}"))],
+        ),
+        (
+            "devron-info".to_string(),
+            vec![DnsRecord::Txt(vec![
+                "hello".to_string(),
+                "world".to_string(),
+            ])],
+        ),
+    ]);
+    dns_records_create(client, TEST_ZONE, input_records.clone()).await?;
+    let records = dns_records_list(client, TEST_ZONE).await?;
+    assert_eq!(records, input_records);
+
+    // Looking up an alias gets the CNAME record, followed by the target's
+    // records.
+    let alias = format!("alias.{TEST_ZONE}.");
+    let response =
+        dns_query(server, Transport::Udp, &alias, RecordType::A, None).await?;
+    let answer_types = response
+        .answers()
+        .iter()
+        .map(|record| record.record_type())
+        .collect::<Vec<_>>();
+    assert_eq!(answer_types, [RecordType::CNAME, RecordType::A]);
+    let response = resolver.ipv4_lookup(alias.as_str()).await?;
+    let address = response.iter().next().expect("no addresses returned!");
+    assert_eq!(*address, addr);
+
+    // TXT records
+    let response =
+        resolver.txt_lookup(format!("devron-info.{TEST_ZONE}.")).await?;
+    let txt = response.iter().next().expect("no TXT records returned!");
+    let strings = txt
+        .iter()
+        .map(|s| String::from_utf8_lossy(s).into_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(strings, ["hello", "world"]);
+
+    // PTR records, in a reverse zone
+    let reverse_zone = "2.1.10.in-addr.arpa";
+    let input_records = HashMap::from([(
+        "3".to_string(),
+        vec![DnsRecord::Ptr(format!("devron.{TEST_ZONE}"))],
+    )]);
+    dns_records_create(client, reverse_zone, input_records).await?;
+    let response = resolver.reverse_lookup(IpAddr::from(addr)).await?;
+    let name = response.iter().next().expect("no PTR records returned!");
+    assert_eq!(name.to_string(), format!("devron.{TEST_ZONE}."));
+
+    test_ctx.cleanup().await;
+    Ok(())
+}
+
+/// Requests a transfer of `zone` over TCP and returns the response code and
+/// the records in the response, which may span several messages
+///
+/// For an incremental transfer (IXFR), `client_serial` is the SOA serial number
+/// of the version of the zone that the client already has.
+async fn zone_transfer(
+    server: SocketAddr,
+    zone: &str,
+    record_type: RecordType,
+    client_serial: Option<u32>,
+) -> anyhow::Result<(ResponseCode, Vec<Record>)> {
+    let zone = Name::from_str(zone)?;
+    let mut query = Message::new();
+    query
+        .set_id(0x0de5)
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Query)
+        .add_query(Query::query(zone.clone(), record_type));
+    if let Some(serial) = client_serial {
+        let soa = SOA::new(zone.clone(), zone.clone(), serial, 0, 0, 0, 0);
+        query.add_name_server(Record::from_rdata(zone, 0, RData::SOA(soa)));
+    }
+    let query = query.to_vec()?;
+
+    let mut stream = TcpStream::connect(server).await?;
+    let length = u16::try_from(query.len())?;
+    stream.write_all(&length.to_be_bytes()).await?;
+    stream.write_all(&query).await?;
+
+    let mut records = Vec::new();
+    loop {
+        let mut length = [0u8; 2];
+        stream.read_exact(&mut length).await?;
+        let mut buf = vec![0u8; usize::from(u16::from_be_bytes(length))];
+        stream.read_exact(&mut buf).await?;
+        let response = Message::from_vec(&buf)?;
+        if response.response_code() != ResponseCode::NoError {
+            return Ok((response.response_code(), records));
+        }
+        records.extend(response.answers().iter().cloned());
+        if zone_transfer_complete(&records) {
+            return Ok((ResponseCode::NoError, records));
+        }
+    }
+}
+
+fn soa_serial(record: &Record) -> Option<u32> {
+    match record.data() {
+        Some(RData::SOA(soa)) => Some(soa.serial()),
+        _ => None,
+    }
+}
+
+/// Returns whether the given records make up a complete response to a zone
+/// transfer request
+///
+/// A transfer of the whole zone begins and ends with the zone's SOA record.
+/// An incremental transfer (which begins with two SOA records) includes the
+/// new SOA record before and after each set of additions, too.  A client
+/// that's up-to-date gets a lone SOA record.
+fn zone_transfer_complete(records: &[Record]) -> bool {
+    let Some(serial) = records.first().and_then(soa_serial) else {
+        return true;
+    };
+    if records.len() == 1 {
+        return true;
+    }
+    let incremental = records.get(1).and_then(soa_serial).is_some();
+    let nsoa = records.iter().filter(|r| soa_serial(r) == Some(serial)).count();
+    records.last().and_then(soa_serial) == Some(serial)
+        && nsoa >= if incremental { 3 } else { 2 }
+}
+
+/// Summarizes a record as a string for comparison in tests
+fn describe_record(record: &Record) -> String {
+    let data = match record.data() {
+        Some(RData::SOA(soa)) => format!("SOA {}", soa.serial()),
+        Some(RData::NS(name)) => format!("NS {}", name),
+        Some(RData::A(addr)) => format!("A {}", addr),
+        Some(RData::AAAA(addr)) => format!("AAAA {}", addr),
+        other => format!("{:?}", other),
+    };
+    format!("{} {}", record.name(), data)
+}
+
+#[tokio::test]
+pub async fn zone_transfer_full_and_incremental() -> Result<(), anyhow::Error> {
+    let test_ctx = init_client_server_with_config(
+        "zone_transfer_full_and_incremental",
+        dns_server::dns_server::Config {
+            transfer_peers: vec![IpAddr::from(Ipv6Addr::LOCALHOST)],
+            ..dns_server_config()
+        },
+    )
+    .await?;
+    let client = &test_ctx.client;
+    let server = test_ctx.dns_server.local_address();
+    let zone = format!("{TEST_ZONE}.");
+
+    let ns_addr = Ipv6Addr::new(0xfd, 0, 0, 0, 0, 0, 0, 0x53);
+    let addr1 = Ipv4Addr::new(10, 1, 2, 3);
+    let input_records = HashMap::from([
+        ("ns1".to_string(), vec![DnsRecord::Aaaa(ns_addr)]),
+        ("devron".to_string(), vec![DnsRecord::A(addr1)]),
+    ]);
+    dns_zone_update(client, TEST_ZONE, input_records, Some(vec!["ns1"]))
+        .await?;
+    let gen1 = client.dns_config_get().await?.into_inner().generation;
+    let serial1 = u32::try_from(gen1).unwrap();
+
+    // A full transfer has every record, between two copies of the SOA.
+    let (rcode, records) =
+        zone_transfer(server, &zone, RecordType::AXFR, None).await?;
+    assert_eq!(rcode, ResponseCode::NoError);
+    let records = records.iter().map(describe_record).collect::<Vec<_>>();
+    assert_eq!(
+        records,
+        [
+            format!("{zone} SOA {serial1}"),
+            format!("{zone} NS ns1.{zone}"),
+            format!("devron.{zone} A {addr1}"),
+            format!("ns1.{zone} AAAA {ns_addr}"),
+            format!("{zone} SOA {serial1}"),
+        ]
+    );
+
+    // Change one record and add another.
+    let addr2 = Ipv4Addr::new(10, 1, 2, 4);
+    let addr3 = Ipv4Addr::new(10, 1, 2, 5);
+    let input_records = HashMap::from([
+        ("devron".to_string(), vec![DnsRecord::A(addr2)]),
+        ("lyra".to_string(), vec![DnsRecord::A(addr3)]),
+    ]);
+    dns_records_create(client, TEST_ZONE, input_records).await?;
+    let serial2 = serial1 + 1;
+
+    // An incremental transfer has just the differences.
+    let (rcode, records) =
+        zone_transfer(server, &zone, RecordType::IXFR, Some(serial1)).await?;
+    assert_eq!(rcode, ResponseCode::NoError);
+    let records = records.iter().map(describe_record).collect::<Vec<_>>();
+    assert_eq!(
+        records,
+        [
+            format!("{zone} SOA {serial2}"),
+            format!("{zone} SOA {serial1}"),
+            format!("devron.{zone} A {addr1}"),
+            format!("{zone} SOA {serial2}"),
+            format!("devron.{zone} A {addr2}"),
+            format!("lyra.{zone} A {addr3}"),
+            format!("{zone} SOA {serial2}"),
+        ]
+    );
+
+    // A client that's up-to-date just gets the SOA.
+    let (rcode, records) =
+        zone_transfer(server, &zone, RecordType::IXFR, Some(serial2)).await?;
+    assert_eq!(rcode, ResponseCode::NoError);
+    let records = records.iter().map(describe_record).collect::<Vec<_>>();
+    assert_eq!(records, [format!("{zone} SOA {serial2}")]);
+
+    // If we don't have the client's version, it gets the whole zone.
+    let (rcode, records) =
+        zone_transfer(server, &zone, RecordType::IXFR, Some(0)).await?;
+    assert_eq!(rcode, ResponseCode::NoError);
+    assert_eq!(records.len(), 6);
+    assert_eq!(soa_serial(&records[0]), Some(serial2));
+    assert_eq!(soa_serial(&records[1]), None);
+
+    // Only whole zones can be transferred.
+    let (rcode, _) = zone_transfer(
+        server,
+        &format!("devron.{zone}"),
+        RecordType::AXFR,
+        None,
+    )
+    .await?;
+    assert_eq!(rcode, ResponseCode::NotAuth);
+
+    // Full transfers aren't supported over UDP.
+    let response =
+        dns_query(server, Transport::Udp, &zone, RecordType::AXFR, None)
+            .await?;
+    assert_eq!(response.response_code(), ResponseCode::Refused);
+
+    test_ctx.cleanup().await;
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn zone_transfer_large() -> Result<(), anyhow::Error> {
+    let test_ctx = init_client_server_with_config(
+        "zone_transfer_large",
+        dns_server::dns_server::Config {
+            transfer_peers: vec![IpAddr::from(Ipv6Addr::LOCALHOST)],
+            ..dns_server_config()
+        },
+    )
+    .await?;
+    let server = test_ctx.dns_server.local_address();
+
+    // This zone doesn't fit in a single 64 KiB message.
+    let nnames = 3000;
+    let input_records = (0..nnames)
+        .map(|i| {
+            let addr = Ipv6Addr::new(0xfd, 0, 0, 0, 0, 0, 0, i);
+            (format!("host-{i:04}"), vec![DnsRecord::Aaaa(addr)])
+        })
+        .collect();
+    dns_records_create(&test_ctx.client, TEST_ZONE, input_records).await?;
+
+    let (rcode, records) =
+        zone_transfer(server, &format!("{TEST_ZONE}."), RecordType::AXFR, None)
+            .await?;
+    assert_eq!(rcode, ResponseCode::NoError);
+    assert_eq!(records.len(), usize::from(nnames) + 2);
+
+    test_ctx.cleanup().await;
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn zone_transfer_refused() -> Result<(), anyhow::Error> {
+    // By default, no peers may transfer zones.
+    let test_ctx = init_client_server("zone_transfer_refused").await?;
+    let server = test_ctx.dns_server.local_address();
+    let input_records = HashMap::from([(
+        "devron".to_string(),
+        vec![DnsRecord::A(Ipv4Addr::new(10, 1, 2, 3))],
+    )]);
+    dns_records_create(&test_ctx.client, TEST_ZONE, input_records).await?;
+
+    let (rcode, records) =
+        zone_transfer(server, &format!("{TEST_ZONE}."), RecordType::AXFR, None)
+            .await?;
+    assert_eq!(rcode, ResponseCode::Refused);
+    assert!(records.is_empty());
+
+    test_ctx.cleanup().await;
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn notify_secondaries() -> Result<(), anyhow::Error> {
+    let secondary = UdpSocket::bind("[::1]:0").await?;
+    let test_ctx = init_client_server_with_config(
+        "notify_secondaries",
+        dns_server::dns_server::Config {
+            notify_secondaries: vec![secondary.local_addr()?],
+            ..dns_server_config()
+        },
+    )
+    .await?;
+    let input_records = HashMap::from([(
+        "devron".to_string(),
+        vec![DnsRecord::A(Ipv4Addr::new(10, 1, 2, 3))],
+    )]);
+    dns_records_create(&test_ctx.client, TEST_ZONE, input_records).await?;
+    let generation =
+        test_ctx.client.dns_config_get().await?.into_inner().generation;
+
+    // Committing the update sends a NOTIFY for the zone, with its new SOA.
+    let (notify, _) = receive_notify(&secondary).await?;
+    assert_eq!(notify.op_code(), OpCode::Notify);
+    assert_eq!(notify.message_type(), MessageType::Query);
+    assert_eq!(notify.queries()[0].name().to_string(), format!("{TEST_ZONE}."));
+    assert_eq!(
+        notify.answers().first().and_then(soa_serial),
+        Some(u32::try_from(generation).unwrap())
+    );
+
+    // If we don't acknowledge it, the server tries again.  Acknowledge the
+    // retry.
+    let (notify, from) = receive_notify(&secondary).await?;
+    assert_eq!(notify.op_code(), OpCode::Notify);
+    let mut ack = Message::new();
+    ack.set_id(notify.id())
+        .set_message_type(MessageType::Response)
+        .set_op_code(OpCode::Notify)
+        .add_query(notify.queries()[0].clone());
+    secondary.send_to(&ack.to_vec()?, from).await?;
+
+    test_ctx.cleanup().await;
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn rate_limit_slip() -> Result<(), anyhow::Error> {
+    let test_ctx = init_client_server_with_config(
+        "rate_limit_slip",
+        dns_server::dns_server::Config {
+            rate_limit: dns_server::rate_limit::Config {
+                responses_per_second: 2,
+                slip: 2,
+                ..Default::default()
+            },
+            ..dns_server_config()
+        },
+    )
+    .await?;
+    let server = test_ctx.dns_server.local_address();
+    let input_records = HashMap::from([(
+        "devron".to_string(),
+        vec![DnsRecord::A(Ipv4Addr::new(10, 1, 2, 3))],
+    )]);
+    dns_records_create(&test_ctx.client, TEST_ZONE, input_records).await?;
+    let name = Name::from_str(&format!("devron.{TEST_ZONE}."))?;
+
+    // Send a burst of queries over UDP.  The first two are answered normally.
+    // After that, every other one gets an empty, truncated response and the
+    // rest are dropped.
+    let socket = UdpSocket::bind("[::1]:0").await?;
+    for id in 0..6 {
+        let mut query = Message::new();
+        query
+            .set_id(id)
+            .set_message_type(MessageType::Query)
+            .set_op_code(OpCode::Query)
+            .add_query(Query::query(name.clone(), RecordType::A));
+        socket.send_to(&query.to_vec()?, server).await?;
+    }
+
+    let mut responses = Vec::new();
+    let mut buf = vec![0u8; 4096];
+    while let Ok(result) = tokio::time::timeout(
+        std::time::Duration::from_millis(500),
+        socket.recv(&mut buf),
+    )
+    .await
+    {
+        let n = result?;
+        responses.push(Message::from_vec(&buf[..n])?);
+    }
+    responses.sort_by_key(|response| response.id());
+    let summary = responses
+        .iter()
+        .map(|response| {
+            (response.id(), response.truncated(), response.answers().len())
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        [(0, false, 1), (1, false, 1), (3, true, 0), (5, true, 0)]
+    );
+
+    // Queries over TCP aren't rate-limited.
+    let response = dns_query(
+        server,
+        Transport::Tcp,
+        &name.to_string(),
+        RecordType::A,
+        None,
+    )
+    .await?;
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert_eq!(response.answers().len(), 1);
+
+    test_ctx.cleanup().await;
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn tcp_connection_limit() -> Result<(), anyhow::Error> {
+    let test_ctx = init_client_server_with_config(
+        "tcp_connection_limit",
+        dns_server::dns_server::Config {
+            rate_limit: dns_server::rate_limit::Config {
+                max_tcp_connections: 1,
+                ..Default::default()
+            },
+            ..dns_server_config()
+        },
+    )
+    .await?;
+    let server = test_ctx.dns_server.local_address();
+    let input_records = HashMap::from([(
+        "devron".to_string(),
+        vec![DnsRecord::A(Ipv4Addr::new(10, 1, 2, 3))],
+    )]);
+    dns_records_create(&test_ctx.client, TEST_ZONE, input_records).await?;
+    let name = format!("devron.{TEST_ZONE}.");
+
+    // While one connection is open, the server closes any others as soon as
+    // it accepts them.
+    let first = TcpStream::connect(server).await?;
+    let mut second = TcpStream::connect(server).await?;
+    let mut buf = [0u8; 1];
+    let n = tokio::time::timeout(
+        std::time::Duration::from_secs(5),
+        second.read(&mut buf),
+    )
+    .await??;
+    assert_eq!(n, 0);
+
+    // Once the first connection is closed, new ones are served again.
+    drop(first);
+    let response = loop {
+        match dns_query(server, Transport::Tcp, &name, RecordType::A, None)
+            .await
+        {
+            Ok(response) => break response,
+            Err(_) => {
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await
+            }
+        }
+    };
+    assert_eq!(response.response_code(), ResponseCode::NoError);
+    assert_eq!(response.answers().len(), 1);
+
+    test_ctx.cleanup().await;
+    Ok(())
+}
+
+async fn receive_notify(
+    socket: &UdpSocket,
+) -> anyhow::Result<(Message, SocketAddr)> {
+    let mut buf = vec![0u8; 4096];
+    let (n, from) = tokio::time::timeout(
+        std::time::Duration::from_secs(30),
+        socket.recv_from(&mut buf),
+    )
+    .await
+    .context("timed out waiting for NOTIFY")??;
+    Ok((Message::from_vec(&buf[..n])?, from))
+}
+
 async fn lookup_ip_expect_nxdomain(resolver: &TokioAsyncResolver, name: &str) {
     match resolver.lookup_ip(name).await {
         Ok(unexpected) => {
@@ -347,6 +1110,24 @@ impl TestContext {
 
 async fn init_client_server(
     test_name: &str,
+) -> Result<TestContext, anyhow::Error> {
+    init_client_server_with_config(test_name, dns_server_config()).await
+}
+
+/// Returns the default DNS server configuration for tests, which listens on
+/// any available port on localhost
+fn dns_server_config() -> dns_server::dns_server::Config {
+    dns_server::dns_server::Config {
+        bind_address: "[::1]:0".parse().unwrap(),
+        transfer_peers: vec![],
+        notify_secondaries: vec![],
+        rate_limit: Default::default(),
+    }
+}
+
+async fn init_client_server_with_config(
+    test_name: &str,
+    dns_server_config: dns_server::dns_server::Config,
 ) -> Result<TestContext, anyhow::Error> {
     // initialize dns server config
     let (tmp, config_storage, config_dropshot, logctx) =
@@ -362,9 +1143,6 @@ async fn init_client_server(
     assert!(store.is_new());
 
     // launch a dns server
-    let dns_server_config = dns_server::dns_server::Config {
-        bind_address: "[::1]:0".parse().unwrap(),
-    };
     let (dns_server, dropshot_server) = dns_server::start_servers(
         log.clone(),
         store,
@@ -374,13 +1152,15 @@ async fn init_client_server(
     .await?;
 
     let mut rc = ResolverConfig::new();
-    rc.add_name_server(NameServerConfig {
-        socket_addr: dns_server.local_address(),
-        protocol: Protocol::Udp,
-        tls_dns_name: None,
-        trust_nx_responses: false,
-        bind_addr: None,
-    });
+    for protocol in [Protocol::Udp, Protocol::Tcp] {
+        rc.add_name_server(NameServerConfig {
+            socket_addr: dns_server.local_address(),
+            protocol,
+            tls_dns_name: None,
+            trust_nx_responses: false,
+            bind_addr: None,
+        });
+    }
 
     let resolver =
         TokioAsyncResolver::tokio(rc, ResolverOpts::default()).unwrap();
@@ -417,7 +1197,9 @@ fn test_config(
         dns_server::storage::Config { storage_path, keep_old_generations: 3 };
     let config_dropshot = dropshot::ConfigDropshot {
         bind_address: "[::1]:0".to_string().parse().unwrap(),
-        request_body_max_bytes: 1024,
+        // This needs to be large enough for the large zones used to test
+        // responses that don't fit in one message.
+        request_body_max_bytes: 1024 * 1024,
         default_handler_task_mode: HandlerTaskMode::Detached,
     };
 
@@ -428,6 +1210,17 @@ async fn dns_records_create(
     client: &Client,
     zone_name: &str,
     records: HashMap<String, Vec<DnsRecord>>,
+) -> anyhow::Result<()> {
+    dns_zone_update(client, zone_name, records, None).await
+}
+
+/// Adds `records` to the given zone and, if `nameservers` is provided, replaces
+/// the zone's nameservers
+async fn dns_zone_update(
+    client: &Client,
+    zone_name: &str,
+    records: HashMap<String, Vec<DnsRecord>>,
+    nameservers: Option<Vec<&str>>,
 ) -> anyhow::Result<()> {
     let before = client
         .dns_config_get()
@@ -441,15 +1234,26 @@ async fn dns_records_create(
         .partition::<Vec<_>, _>(|z| z.zone_name == zone_name);
 
     assert!(our_zones.len() <= 1);
-    let zone_records = if let Some(our_zone) = our_zones.into_iter().next() {
-        our_zone.records.into_iter().chain(records.into_iter()).collect()
+    let (zone_records, zone_nameservers) = if let Some(our_zone) =
+        our_zones.into_iter().next()
+    {
+        (
+            our_zone.records.into_iter().chain(records.into_iter()).collect(),
+            our_zone.nameservers,
+        )
     } else {
-        records
+        (records, Vec::new())
     };
 
     let new_zone = DnsConfigZone {
         zone_name: zone_name.to_owned(),
         records: zone_records,
+        nameservers: match nameservers {
+            Some(nameservers) => {
+                nameservers.into_iter().map(String::from).collect()
+            }
+            None => zone_nameservers,
+        },
     };
 
     let zones =
@@ -34,6 +34,9 @@ async fn test_dnsadm() {
         store,
         &dns_server::dns_server::Config {
             bind_address: "[::1]:0".parse().unwrap(),
+            transfer_peers: vec![],
+            notify_secondaries: vec![],
+            rate_limit: Default::default(),
         },
         &dropshot::ConfigDropshot {
             bind_address: "[::1]:0".parse().unwrap(),
@@ -4,19 +4,23 @@
 
 // Copyright 2022 Oxide Computer Company
 
+use std::ops::Range;
+
 use dropshot::{
-    endpoint, ApiDescription, FreeformBody, HttpError, HttpResponseHeaders,
-    HttpResponseOk, HttpResponseUpdatedNoContent, Path, RequestContext,
-    TypedBody,
+    endpoint, ApiDescription, FreeformBody, HttpCodedResponse, HttpError,
+    HttpResponseUpdatedNoContent, Path, RequestContext, TypedBody,
+};
+use hyper::{
+    header::{self, HeaderValue},
+    Body, Response, StatusCode,
 };
-use hyper::{header, Body, StatusCode};
 use installinator_common::EventReport;
 use omicron_common::update::ArtifactHashId;
 use schemars::JsonSchema;
 use serde::Deserialize;
 use uuid::Uuid;
 
-use crate::{context::ServerContext, EventReportStatus};
+use crate::{context::ServerContext, store::resolve_range, EventReportStatus};
 
 type ArtifactServerApiDesc = ApiDescription<ServerContext>;
 
@@ -38,6 +42,12 @@ pub fn api() -> ArtifactServerApiDesc {
 }
 
 /// Fetch an artifact by hash.
+///
+/// A single range of bytes can be requested with a `Range` header such as
+/// `bytes=0-1023` or `bytes=1024-`, in which case just those bytes are
+/// returned with a 206 Partial Content response, or a 416 Range Not
+/// Satisfiable response if the range starts past the end of the artifact.
+/// Other kinds of ranges are ignored, and the whole artifact is returned.
 #[endpoint {
     method = GET,
     path = "/artifacts/by-hash/{kind}/{hash}",
@@ -45,20 +55,103 @@ pub fn api() -> ArtifactServerApiDesc {
 async fn get_artifact_by_hash(
     rqctx: RequestContext<ServerContext>,
     path: Path<ArtifactHashId>,
-) -> Result<HttpResponseHeaders<HttpResponseOk<FreeformBody>>, HttpError> {
-    match rqctx
-        .context()
-        .artifact_store
-        .get_artifact_by_hash(&path.into_inner())
+) -> Result<ArtifactResponse, HttpError> {
+    let id = path.into_inner();
+    let artifact_store = &rqctx.context().artifact_store;
+    let range =
+        rqctx.request.headers().get(header::RANGE).and_then(parse_range);
+
+    let Some(range) = range else {
+        let (size, body) = artifact_store
+            .get_artifact_by_hash(&id)
+            .await
+            .ok_or_else(artifact_not_found)?;
+        return Ok(ArtifactResponse::Whole { size, body });
+    };
+
+    let (size, body) = artifact_store
+        .get_artifact_range_by_hash(&id, range.clone())
         .await
-    {
-        Some((size, body)) => Ok(body_to_artifact_response(size, body)),
-        None => {
-            Err(HttpError::for_not_found(None, "Artifact not found".into()))
-        }
+        .ok_or_else(artifact_not_found)?;
+    match resolve_range(range, size) {
+        Some(range) => Ok(ArtifactResponse::Partial { size, range, body }),
+        None => Ok(ArtifactResponse::RangeNotSatisfiable { size }),
     }
 }
 
+fn artifact_not_found() -> HttpError {
+    HttpError::for_not_found(None, "Artifact not found".into())
+}
+
+/// The response to a request for an artifact.
+///
+/// A dropshot response has a single status code, so this is described as a
+/// 200 response in the OpenAPI spec, although range requests are answered with
+/// a 206 or 416 response.
+enum ArtifactResponse {
+    /// The whole artifact.
+    Whole { size: u64, body: Body },
+    /// The part of the artifact in `range`.
+    Partial { size: u64, range: Range<u64>, body: Body },
+    /// The requested range starts past the end of the artifact.
+    RangeNotSatisfiable { size: u64 },
+}
+
+impl HttpCodedResponse for ArtifactResponse {
+    type Body = FreeformBody;
+    const STATUS_CODE: StatusCode = StatusCode::OK;
+    const DESCRIPTION: &'static str = "successful operation";
+}
+
+impl From<ArtifactResponse> for Result<Response<Body>, HttpError> {
+    fn from(response: ArtifactResponse) -> Self {
+        let builder =
+            Response::builder().header(header::ACCEPT_RANGES, "bytes");
+        let response = match response {
+            ArtifactResponse::Whole { size, body } => builder
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, "application/octet-stream")
+                .header(header::CONTENT_LENGTH, size)
+                .body(body)?,
+            ArtifactResponse::Partial { size, range, body } => builder
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(header::CONTENT_TYPE, "application/octet-stream")
+                .header(header::CONTENT_LENGTH, range.end - range.start)
+                .header(
+                    header::CONTENT_RANGE,
+                    format!("bytes {}-{}/{size}", range.start, range.end - 1),
+                )
+                .body(body)?,
+            ArtifactResponse::RangeNotSatisfiable { size } => builder
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{size}"))
+                .body(Body::empty())?,
+        };
+        Ok(response)
+    }
+}
+
+/// Parses a `Range` header asking for a single range of bytes, such as
+/// `bytes=0-1023` or `bytes=1024-`, into the half-open range it covers.
+///
+/// Returns `None` for anything else, including suffix ranges (`bytes=-1024`)
+/// and requests for several ranges. Servers are free to ignore `Range`
+/// headers, so these get the whole artifact instead.
+fn parse_range(value: &HeaderValue) -> Option<Range<u64>> {
+    let spec = value.to_str().ok()?.strip_prefix("bytes=")?;
+    let (start, last) = spec.trim().split_once('-')?;
+    let start: u64 = start.parse().ok()?;
+    if last.is_empty() {
+        return Some(start..u64::MAX);
+    }
+    // HTTP ranges include their last byte.
+    let last: u64 = last.parse().ok()?;
+    if last < start {
+        return None;
+    }
+    Some(start..last.checked_add(1)?)
+}
+
 #[derive(Debug, Deserialize, JsonSchema)]
 pub(crate) struct ReportQuery {
     /// A unique identifier for the update.
@@ -102,14 +195,3 @@ async fn report_progress(
         )),
     }
 }
-
-fn body_to_artifact_response(
-    size: u64,
-    body: Body,
-) -> HttpResponseHeaders<HttpResponseOk<FreeformBody>> {
-    let mut response =
-        HttpResponseHeaders::new_unnamed(HttpResponseOk(body.into()));
-    let headers = response.headers_mut();
-    headers.append(header::CONTENT_LENGTH, size.into());
-    response
-}
@@ -11,7 +11,7 @@ mod store;
 
 pub use context::ServerContext;
 pub use server::ArtifactServer;
-pub use store::{ArtifactGetter, EventReportStatus};
+pub use store::{resolve_range, ArtifactGetter, EventReportStatus};
 
 use anyhow::Result;
 
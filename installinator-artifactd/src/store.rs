@@ -5,6 +5,7 @@
 // Copyright 2023 Oxide Computer Company
 
 use std::fmt;
+use std::ops::Range;
 
 use async_trait::async_trait;
 use dropshot::HttpError;
@@ -20,6 +21,17 @@ pub trait ArtifactGetter: fmt::Debug + Send + Sync + 'static {
     /// Gets an artifact by hash, returning it as a [`Body`].
     async fn get_by_hash(&self, id: &ArtifactHashId) -> Option<(u64, Body)>;
 
+    /// Gets the bytes of an artifact in `range` by hash.
+    ///
+    /// Returns the size of the whole artifact, along with the part of `range`
+    /// that lies within it (as found by [`resolve_range`]) as a [`Body`]. If
+    /// `range` can't be satisfied, the body is empty.
+    async fn get_range_by_hash(
+        &self,
+        id: &ArtifactHashId,
+        range: Range<u64>,
+    ) -> Option<(u64, Body)>;
+
     /// Reports update progress events from the installinator.
     async fn report_progress(
         &self,
@@ -28,6 +40,16 @@ pub trait ArtifactGetter: fmt::Debug + Send + Sync + 'static {
     ) -> Result<EventReportStatus, HttpError>;
 }
 
+/// Returns the part of `range` that lies within an artifact of `size` bytes,
+/// or `None` if `range` starts at or past the end of the artifact and so can't
+/// be satisfied.
+///
+/// Ranges that run past the end of the artifact are cut short. Artifact
+/// servers use this so that they all answer range requests the same way.
+pub fn resolve_range(range: Range<u64>, size: u64) -> Option<Range<u64>> {
+    (range.start < size).then(|| range.start..range.end.min(size))
+}
+
 /// The status returned by [`ArtifactGetter::report_progress`].
 #[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord, Hash)]
 #[must_use]
@@ -68,6 +90,20 @@ impl ArtifactStore {
         self.getter.get_by_hash(id).await
     }
 
+    pub(crate) async fn get_artifact_range_by_hash(
+        &self,
+        id: &ArtifactHashId,
+        range: Range<u64>,
+    ) -> Option<(u64, Body)> {
+        slog::debug!(
+            self.log,
+            "Artifact range requested by hash: {:?}, {:?}",
+            id,
+            range,
+        );
+        self.getter.get_range_by_hash(id, range).await
+    }
+
     pub(crate) async fn report_progress(
         &self,
         update_id: Uuid,
@@ -2,11 +2,12 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use std::net::SocketAddr;
+use std::{net::SocketAddr, ops::Range};
 
 use anyhow::{Context, Result};
 use clap::Args;
 use futures::StreamExt;
+use http::{header, HeaderMap, StatusCode};
 use installinator_artifact_client::ClientError;
 use installinator_common::EventReport;
 use ipcc_key_value::{InstallinatorImageId, Ipcc};
@@ -86,51 +87,101 @@ impl ArtifactClient {
         Self { log, client }
     }
 
-    pub(crate) async fn fetch(
+    /// Fetches the bytes of an artifact in `range`, which must not be empty.
+    ///
+    /// Returns the size of the whole artifact, along with a receiver for the
+    /// part of `range` that lies within it.
+    pub(crate) async fn fetch_range(
         &self,
         artifact_hash_id: ArtifactHashId,
+        range: Range<u64>,
     ) -> Result<(u64, FetchReceiver), HttpError> {
-        let artifact_bytes = self
+        // The generated client can't send a `Range` header, so make this
+        // request by hand.
+        let url = format!(
+            "{}/artifacts/by-hash/{}/{}",
+            self.client.baseurl(),
+            artifact_hash_id.kind.as_str(),
+            artifact_hash_id.hash,
+        );
+        // HTTP ranges include their last byte.
+        let range_header = format!("bytes={}-{}", range.start, range.end - 1);
+        slog::debug!(
+            &self.log,
+            "requesting {range_header} of artifact";
+            "uri" => &url,
+        );
+        let response = self
             .client
-            .get_artifact_by_hash(
-                artifact_hash_id.kind.as_str(),
-                &artifact_hash_id.hash.to_string(),
-            )
-            .await?;
+            .client()
+            .get(url)
+            .header(header::RANGE, range_header)
+            .send()
+            .await
+            .map_err(ClientError::CommunicationError)?;
+
+        // Work out the size of the artifact, and how many bytes at the start of
+        // the response to skip over.
+        let (size, mut skip) = match response.status() {
+            StatusCode::PARTIAL_CONTENT => {
+                match content_range(response.headers())? {
+                    (Some(start), size) if start == range.start => (size, 0),
+                    _ => return Err(HttpError::InvalidContentRange),
+                }
+            }
+            // Peers that don't support ranges send the whole artifact, so pick
+            // the range out of that.
+            StatusCode::OK => {
+                (content_length(response.headers())?, range.start)
+            }
+            // The range starts past the end of the artifact, so there's
+            // nothing to receive. (Dropping the sender closes the channel.)
+            StatusCode::RANGE_NOT_SATISFIABLE => {
+                let (_, size) = content_range(response.headers())?;
+                let (_, fetch_receiver) = mpsc::channel(1);
+                return Ok((size, fetch_receiver));
+            }
+            _ => return Err(ClientError::UnexpectedResponse(response).into()),
+        };
 
         slog::debug!(
             &self.log,
             "preparing to receive {:?} bytes from artifact",
-            artifact_bytes.content_length(),
+            response.content_length(),
         );
 
-        // We expect servers to set a Content-Length header.
-        let content_length =
-            match artifact_bytes.headers().get(http::header::CONTENT_LENGTH) {
-                Some(v) => {
-                    let s = v
-                        .to_str()
-                        .map_err(|_| HttpError::InvalidContentLength)?;
-                    s.parse().map_err(|_| HttpError::InvalidContentLength)?
-                }
-                None => return Err(HttpError::MissingContentLength),
-            };
-
         let (fetch_sender, fetch_receiver) = mpsc::channel(8);
+        let mut remaining = range.end.min(size).saturating_sub(range.start);
 
         tokio::spawn(async move {
-            let mut bytes = artifact_bytes.into_inner_stream();
+            let mut bytes = response.bytes_stream();
             while let Some(item) = bytes.next().await {
+                // Chunks that are skipped entirely are still sent (as empty
+                // chunks), so that the receiver knows the peer is making
+                // progress.
+                let item = item.map(|mut chunk| {
+                    let skipped = skip.min(chunk.len() as u64);
+                    skip -= skipped;
+                    let mut chunk = chunk.split_off(skipped as usize);
+                    chunk.truncate(remaining.min(chunk.len() as u64) as usize);
+                    remaining -= chunk.len() as u64;
+                    chunk
+                });
                 if let Err(_) =
                     fetch_sender.send(item.map_err(Into::into)).await
                 {
                     // The sender was dropped, which indicates that the job was cancelled.
                     return;
                 }
+                if remaining == 0 {
+                    // We have the whole range, so don't wait for the rest of
+                    // the artifact.
+                    return;
+                }
             }
         });
 
-        Ok((content_length, fetch_receiver))
+        Ok((size, fetch_receiver))
     }
 
     pub(crate) async fn report_progress(
@@ -144,3 +195,34 @@ impl ArtifactClient {
             .map(|resp| resp.into_inner())
     }
 }
+
+// We expect servers to set a Content-Length header on whole artifacts.
+fn content_length(headers: &HeaderMap) -> Result<u64, HttpError> {
+    match headers.get(header::CONTENT_LENGTH) {
+        Some(v) => {
+            let s = v.to_str().map_err(|_| HttpError::InvalidContentLength)?;
+            s.parse().map_err(|_| HttpError::InvalidContentLength)
+        }
+        None => Err(HttpError::MissingContentLength),
+    }
+}
+
+// Parses a Content-Range header (`bytes 0-1023/4096`, or `bytes */4096` if the
+// requested range wasn't satisfiable), returning the first byte in the range
+// if there is one, and the size of the whole artifact.
+fn content_range(headers: &HeaderMap) -> Result<(Option<u64>, u64), HttpError> {
+    let value = headers
+        .get(header::CONTENT_RANGE)
+        .ok_or(HttpError::MissingContentRange)?;
+    let parse = || {
+        let (range, size) =
+            value.to_str().ok()?.strip_prefix("bytes ")?.split_once('/')?;
+        let size = size.parse().ok()?;
+        if range == "*" {
+            return Some((None, size));
+        }
+        let (start, _) = range.split_once('-')?;
+        Some((Some(start.parse().ok()?), size))
+    };
+    parse().ok_or(HttpError::InvalidContentRange)
+}
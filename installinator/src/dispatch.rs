@@ -227,7 +227,9 @@ impl InstallOpts {
                     // (memory corruption, corruption under TCP, or wicketd gave
                     // us something other than what we requested) we want to
                     // know immediately and not retry: it's likely an operator
-                    // could miss any warnings we emit if a retry succeeds.
+                    // could miss any warnings we emit if a retry succeeds. This
+                    // also checks that chunks fetched from different peers were
+                    // put back together correctly.
                     check_downloaded_artifact_hash(
                         "host phase 2",
                         host_phase_2_artifact.artifact.clone(),
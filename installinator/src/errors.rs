@@ -2,7 +2,7 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use std::{net::SocketAddr, time::Duration};
+use std::{net::SocketAddr, ops::Range, time::Duration};
 
 use installinator_artifact_client::ClientError;
 use thiserror::Error;
@@ -19,8 +19,15 @@ pub(crate) enum ArtifactFetchError {
     #[error("peer {peer} timed out ({timeout:?}) after returning {bytes_fetched} bytes")]
     Timeout { peer: SocketAddr, timeout: Duration, bytes_fetched: usize },
 
-    #[error("artifact size in Content-Length header ({artifact_size}) did not match downloaded size ({downloaded_bytes})")]
-    SizeMismatch { artifact_size: u64, downloaded_bytes: u64 },
+    #[error("peer {peer} returned {downloaded_bytes} bytes for bytes {range:?} of the artifact")]
+    SizeMismatch { peer: SocketAddr, range: Range<u64>, downloaded_bytes: u64 },
+
+    #[error("peer {peer} reported an artifact size of {reported_size} bytes, but an earlier peer reported {expected_size} bytes")]
+    ArtifactSizeMismatch {
+        peer: SocketAddr,
+        reported_size: u64,
+        expected_size: u64,
+    },
 }
 
 #[derive(Debug, Error)]
@@ -44,4 +51,10 @@ pub(crate) enum HttpError {
 
     #[error("Content-Length header could not be parsed into an integer")]
     InvalidContentLength,
+
+    #[error("missing Content-Range header")]
+    MissingContentRange,
+
+    #[error("Content-Range header could not be parsed, or did not match the requested range")]
+    InvalidContentRange,
 }
@@ -7,9 +7,10 @@
 #![allow(clippy::arc_with_non_send_sync)]
 
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     fmt,
     net::{IpAddr, Ipv6Addr, SocketAddr},
+    ops::Range,
     time::Duration,
 };
 
@@ -101,29 +102,58 @@ impl MockPeersUniverse {
         )
     }
 
-    /// On success this returns (successful attempt, peer).
+    /// On success this returns (successful attempt, peer that sent the most of the artifact).
     ///
     /// On failure this returns the number of attempts that failed.
     fn expected_result(
         &self,
         timeout: Duration,
+        chunk_size: u64,
     ) -> Result<(usize, SocketAddr), usize> {
-        self.attempts()
-            .enumerate()
-            .filter_map(|(attempt, peers)| {
-                peers
-                    .ok()?
-                    .successful_peer(timeout)
-                    // attempt is zero-indexed here, but the attempt returned by FetchedArtifact is
-                    // 1-indexed.
-                    .map(|addr| (attempt + 1, addr))
-            })
-            .next()
-            .ok_or_else(|| {
-                // We're going to try one last time after the attempt bitmaps
-                // run out, then abort. Hence + 1.
-                self.attempt_bitmaps.len() + 1
-            })
+        let size = self.artifact.len() as u64;
+        // An empty artifact still has one (empty) chunk.
+        let chunk_count = ((size + chunk_size - 1) / chunk_size).max(1);
+        let chunk_len = |index: u64| {
+            let start = index * chunk_size;
+            ((start + chunk_size).min(size) - start) as usize
+        };
+
+        // Chunks fetched by one attempt are kept for the next.
+        let mut fetched = BTreeSet::new();
+        let mut bytes_by_peer = BTreeMap::new();
+        for (attempt, peers) in self.attempts().enumerate() {
+            let Ok(peers) = peers else { continue };
+            for index in 0..chunk_count {
+                if fetched.contains(&index) {
+                    continue;
+                }
+                match peers.serving_peer(index, chunk_len(index), timeout) {
+                    Some(addr) => {
+                        fetched.insert(index);
+                        *bytes_by_peer.entry(addr).or_insert(0) +=
+                            chunk_len(index);
+                    }
+                    // Until the first chunk is fetched the size of the artifact isn't known, so
+                    // nothing else is fetched.
+                    None if index == 0 => break,
+                    None => {}
+                }
+            }
+
+            if fetched.len() as u64 == chunk_count {
+                let (&addr, _) = bytes_by_peer
+                    .iter()
+                    .max_by_key(|(_, bytes)| **bytes)
+                    .expect("at least one chunk was fetched");
+                // attempt is zero-indexed here, but the attempt returned by FetchedArtifact is
+                // 1-indexed.
+                return Ok((attempt + 1, addr));
+            }
+        }
+
+        // We're going to try one last time after the attempt bitmaps run out, then abort. Hence
+        // + 1.
+        Err(self.attempt_bitmaps.len() + 1)
     }
 
     fn attempts(&self) -> impl Iterator<Item = Result<MockPeers>> + '_ {
@@ -182,52 +212,29 @@ impl MockPeers {
         self.selected_peers.get(&addr)
     }
 
-    fn peers(&self) -> impl Iterator<Item = (&SocketAddr, &MockPeer)> + '_ {
-        self.selected_peers.iter()
-    }
-
-    /// Returns the peer that can return the entire dataset within the timeout.
-    fn successful_peer(&self, timeout: Duration) -> Option<SocketAddr> {
-        self.peers()
-            .filter_map(|(addr, peer)| {
+    /// Returns the peer that chunk `index`, of length `len`, is fetched from: the first peer that
+    /// can return it within the timeout, starting from a different peer for each chunk.
+    fn serving_peer(
+        &self,
+        index: u64,
+        len: usize,
+        timeout: Duration,
+    ) -> Option<SocketAddr> {
+        let peer_count = self.selected_peers.len();
+        let first_peer = (index % peer_count.max(1) as u64) as usize;
+        self.selected_peers
+            .iter()
+            .cycle()
+            .skip(first_peer)
+            .take(peer_count)
+            .find(|(_, peer)| {
                 if peer.artifact != self.artifact {
                     // We don't handle the case where the peer returns the wrong artifact yet.
                     panic!("peer artifact not the same as self.artifact -- can't happen in normal use");
                 }
-
-                match &peer.response {
-                    MockResponse::Response(actions) => {
-                        let mut total_count = 0;
-                        for action in actions {
-                            match action {
-                                ResponseAction::Response { after, count } => {
-                                    // Each action must finish under the timeout. Note that within Tokio,
-                                    // timers of the same duration should fire in the order that they were
-                                    // created, because that's the order they'll be added to the linked list
-                                    // for that timer wheel slot. While this is not yet guaranteed in
-                                    // Tokio's documentation, it is the only reasonable implementation so we
-                                    // rely on it here.
-                                    //
-                                    // Since Peers creates the timeout BEFORE MockPeersUniverse sets its
-                                    // delay, action.after must be less than timeout.
-                                    if *after >= timeout {
-                                        return None;
-                                    }
-
-                                    total_count += count;
-                                    if total_count >= peer.artifact.len() {
-                                        return Some(*addr);
-                                    }
-                                }
-                                ResponseAction::Error => return None,
-                            }
-                        }
-                        None
-                    }
-                    MockResponse::Forbidden { .. } | MockResponse::NotFound { .. } => None,
-                }
+                peer.can_send(len, timeout)
             })
-            .next()
+            .map(|(addr, _)| *addr)
     }
 }
 
@@ -241,20 +248,27 @@ impl PeersImpl for MockPeers {
         self.selected_peers.len()
     }
 
-    async fn fetch_from_peer_impl(
+    async fn fetch_range_from_peer_impl(
         &self,
         peer: SocketAddr,
         // We don't (yet) use the artifact ID in MockPeers
         _artifact_hash_id: ArtifactHashId,
+        range: Range<u64>,
     ) -> Result<(u64, FetchReceiver), HttpError> {
         let peer_data = self
             .get(peer)
             .unwrap_or_else(|| panic!("peer {peer} not found in selection"))
             .clone();
         let artifact_size = peer_data.artifact.len() as u64;
+        // Only send the part of the range within the artifact.
+        let end = range.end.min(artifact_size);
+        let start = range.start.min(end);
+        let data = peer_data.artifact.slice(start as usize..end as usize);
 
         let (sender, receiver) = mpsc::channel(8);
-        tokio::spawn(async move { peer_data.send_response(sender).await });
+        tokio::spawn(
+            async move { peer_data.send_response(data, sender).await },
+        );
         // TODO: add tests to ensure an invalid artifact size is correctly detected
         Ok((artifact_size, receiver))
     }
@@ -288,11 +302,50 @@ impl fmt::Debug for MockPeer {
 }
 
 impl MockPeer {
+    /// Returns true if this peer can send `len` bytes of the artifact within the timeout.
+    fn can_send(&self, len: usize, timeout: Duration) -> bool {
+        match &self.response {
+            MockResponse::Response(actions) => {
+                let mut total_count = 0;
+                for action in actions {
+                    match action {
+                        ResponseAction::Response { after, count } => {
+                            // Each action must finish under the timeout. Note that within Tokio,
+                            // timers of the same duration should fire in the order that they were
+                            // created, because that's the order they'll be added to the linked list
+                            // for that timer wheel slot. While this is not yet guaranteed in
+                            // Tokio's documentation, it is the only reasonable implementation so we
+                            // rely on it here.
+                            //
+                            // Since Peers creates the timeout BEFORE MockPeersUniverse sets its
+                            // delay, action.after must be less than timeout.
+                            if *after >= timeout {
+                                return false;
+                            }
+
+                            total_count += count;
+                            if total_count >= len {
+                                return true;
+                            }
+                        }
+                        ResponseAction::Error => return false,
+                    }
+                }
+                false
+            }
+            MockResponse::Forbidden { .. } | MockResponse::NotFound { .. } => {
+                false
+            }
+        }
+    }
+
+    /// Sends `data`, the requested part of the artifact, according to this peer's response.
     async fn send_response(
         self,
+        data: Bytes,
         sender: mpsc::Sender<Result<Bytes, ClientError>>,
     ) {
-        let mut artifact = self.artifact;
+        let mut artifact = data;
         match self.response {
             MockResponse::Response(actions) => {
                 for action in actions {
@@ -503,10 +556,11 @@ impl PeersImpl for MockReportPeers {
         3
     }
 
-    async fn fetch_from_peer_impl(
+    async fn fetch_range_from_peer_impl(
         &self,
         _peer: SocketAddr,
         _artifact_hash_id: ArtifactHashId,
+        _range: Range<u64>,
     ) -> Result<(u64, FetchReceiver), HttpError> {
         unimplemented!(
             "this should never be called -- \
@@ -572,12 +626,14 @@ mod tests {
         universe: MockPeersUniverse,
         #[strategy((0..2000u64).prop_map(Duration::from_millis))]
         timeout: Duration,
+        // Artifacts are up to 4096 bytes, so this covers both single and multiple chunks.
+        #[strategy(256..8192u64)] chunk_size: u64,
         #[strategy(any::<[u8; 16]>().prop_map(Uuid::from_bytes))]
         update_id: Uuid,
     ) {
         with_test_runtime(move || async move {
             let logctx = test_setup_log("proptest_fetch_artifact");
-            let expected_result = universe.expected_result(timeout);
+            let expected_result = universe.expected_result(timeout, chunk_size);
             let expected_artifact = universe.artifact.clone();
 
             let attempts = universe.attempts();
@@ -617,9 +673,10 @@ mod tests {
                     InstallinatorStepId::Download,
                     "Downloading artifact",
                     |cx| async move {
-                        let artifact =
-                            fetch_artifact(&cx, &log, attempts, timeout)
-                                .await?;
+                        let artifact = fetch_artifact(
+                            &cx, &log, attempts, timeout, chunk_size,
+                        )
+                        .await?;
                         let address = artifact.addr;
                         StepSuccess::new(artifact)
                             .with_metadata(
@@ -660,7 +717,8 @@ mod tests {
                     );
                     assert_eq!(
                         expected_addr, addr,
-                        "expected successful peer is the same as actual peer"
+                        "expected peer that sent the most of the artifact \
+                         is the same as actual peer"
                     );
                     let artifact = artifact.copy_to_bytes(artifact.num_bytes());
                     assert_eq!(
@@ -689,15 +747,17 @@ mod tests {
         log: &slog::Logger,
         attempts: impl IntoIterator<Item = Result<MockPeers>>,
         timeout: Duration,
+        chunk_size: u64,
     ) -> Result<FetchedArtifact> {
         let mut attempts = attempts.into_iter();
         FetchedArtifact::loop_fetch_from_peers(
             cx,
             log,
             || match attempts.next() {
-                Some(Ok(peers)) => {
-                    future::ok(Peers::new(&log, Box::new(peers), timeout))
-                }
+                Some(Ok(peers)) => future::ok(
+                    Peers::new(&log, Box::new(peers), timeout)
+                        .with_chunk_size(chunk_size),
+                ),
                 Some(Err(error)) => {
                     future::err(DiscoverPeersError::Retry(error))
                 }
@@ -742,22 +802,17 @@ mod tests {
 
         for event in all_step_events {
             match &event.kind {
-                StepEventKind::ProgressReset { attempt, metadata, .. } => {
-                    if *attempt == expected_attempt {
-                        match metadata {
-                            InstallinatorProgressMetadata::Download {
-                                peer,
-                            } => {
-                                assert_ne!(
-                                    *peer, expected_addr,
-                                    "peer cannot match since this is the last attempt"
-                                );
-                            }
-                            other => {
-                                panic!("expected download metadata, found {other:?}");
-                            }
-                        };
-                    }
+                StepEventKind::ProgressReset { metadata, .. } => {
+                    // A peer failing to send one chunk may still have sent others (possibly the
+                    // most of the artifact), so there isn't much to say about resets.
+                    match metadata {
+                        InstallinatorProgressMetadata::Download { .. } => {}
+                        other => {
+                            panic!(
+                                "expected download metadata, found {other:?}"
+                            );
+                        }
+                    };
                 }
                 StepEventKind::AttemptRetry { next_attempt, .. } => {
                     // It's hard to say anything about failing attempts for now
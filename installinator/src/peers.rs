@@ -3,10 +3,13 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use std::{
+    collections::BTreeMap,
     fmt,
     future::Future,
     net::{IpAddr, SocketAddr},
+    ops::Range,
     str::FromStr,
+    sync::atomic::{AtomicU64, Ordering},
     time::Duration,
 };
 
@@ -118,6 +121,7 @@ impl FromStr for DiscoveryMechanism {
 /// A fetched artifact.
 pub(crate) struct FetchedArtifact {
     pub(crate) attempt: usize,
+    /// The peer that sent us the most of the artifact.
     pub(crate) addr: SocketAddr,
     pub(crate) artifact: BufList,
 }
@@ -127,6 +131,9 @@ impl FetchedArtifact {
     ///
     /// If `discover_fn` returns [`DiscoverPeersError::Retry`], this function will retry. If it
     /// returns `DiscoverPeersError::Abort`, this function will exit with the underlying error.
+    ///
+    /// Chunks of the artifact fetched by failed attempts are kept, so each attempt only fetches
+    /// what earlier ones couldn't.
     pub(crate) async fn loop_fetch_from_peers<F, Fut>(
         cx: &StepContext,
         log: &slog::Logger,
@@ -141,6 +148,7 @@ impl FetchedArtifact {
         // to fetch an artifact from a found peer.
         const RETRY_DELAY: Duration = Duration::from_secs(5);
 
+        let mut download = PartialDownload::default();
         let mut attempt = 0;
         loop {
             attempt += 1;
@@ -170,7 +178,10 @@ impl FetchedArtifact {
                 peers.peer_count(),
                 peers.display(),
             );
-            match peers.fetch_artifact(&cx, artifact_hash_id).await {
+            match peers
+                .fetch_artifact(&cx, artifact_hash_id, &mut download)
+                .await
+            {
                 Some((addr, artifact)) => {
                     return Ok(Self { attempt, addr, artifact })
                 }
@@ -180,7 +191,7 @@ impl FetchedArtifact {
                         "unable to fetch artifact from peers, retrying discovery",
                     );
                     cx.send_progress(StepProgress::retry(format!(
-                        "unable to fetch artifact from any of {} peers, retrying",
+                        "unable to fetch artifact from {} peers, retrying",
                         peers.peer_count(),
                     )))
                     .await;
@@ -208,56 +219,256 @@ impl fmt::Debug for FetchedArtifact {
     }
 }
 
+/// The chunks of an artifact downloaded so far.
+#[derive(Debug, Default)]
+pub(crate) struct PartialDownload {
+    /// The size of the artifact, once a peer has told us.
+    size: Option<u64>,
+    /// The chunks downloaded so far, by index.
+    chunks: BTreeMap<u64, BufList>,
+    /// The number of bytes of the artifact each peer has sent us.
+    bytes_by_peer: BTreeMap<SocketAddr, u64>,
+}
+
+impl PartialDownload {
+    fn insert(&mut self, index: u64, chunk: FetchedChunk) {
+        self.size = Some(chunk.size);
+        *self.bytes_by_peer.entry(chunk.peer).or_default() +=
+            chunk.data.num_bytes() as u64;
+        self.chunks.insert(index, chunk.data);
+    }
+
+    fn downloaded_bytes(&self) -> u64 {
+        self.chunks.values().map(|chunk| chunk.num_bytes() as u64).sum()
+    }
+
+    /// Assembles the downloaded chunks into the artifact, returning the peer
+    /// that sent us the most of it along with it.
+    fn finish(self) -> Option<(SocketAddr, BufList)> {
+        let (&peer, _) =
+            self.bytes_by_peer.iter().max_by_key(|(_, bytes)| **bytes)?;
+        let mut artifact = BufList::new();
+        for chunk in self.chunks.into_values() {
+            for bytes in chunk.iter() {
+                artifact.push_chunk(bytes.clone());
+            }
+        }
+        Some((peer, artifact))
+    }
+}
+
+/// A chunk of an artifact fetched from a peer.
+#[derive(Debug)]
+struct FetchedChunk {
+    peer: SocketAddr,
+    /// The size of the whole artifact, as reported by the peer.
+    size: u64,
+    data: BufList,
+}
+
 #[derive(Debug)]
 pub(crate) struct Peers {
     log: slog::Logger,
     imp: Box<dyn PeersImpl>,
     timeout: Duration,
+    chunk_size: u64,
 }
 
 impl Peers {
+    /// The size of the chunks artifacts are fetched in.
+    const CHUNK_SIZE: u64 = 16 * 1024 * 1024;
+
+    /// The most chunks fetched at once.
+    const MAX_CONCURRENT_CHUNKS: usize = 8;
+
     pub(crate) fn new(
         log: &slog::Logger,
         imp: Box<dyn PeersImpl>,
         timeout: Duration,
     ) -> Self {
         let log = log.new(slog::o!("component" => "Peers"));
-        Self { log, imp, timeout }
+        Self { log, imp, timeout, chunk_size: Self::CHUNK_SIZE }
     }
 
+    /// Sets the size of the chunks artifacts are fetched in.
+    #[cfg(test)]
+    pub(crate) fn with_chunk_size(mut self, chunk_size: u64) -> Self {
+        assert!(chunk_size > 0, "chunk size must be non-zero");
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Fetches the rest of an artifact into `download`.
+    ///
+    /// The artifact is fetched in chunks, several at a time and from different
+    /// peers, and each chunk is tried against every peer until one of them
+    /// returns it. If some chunk can't be fetched from any peer, this returns
+    /// `None`, leaving the chunks that were fetched in `download` for the next
+    /// attempt.
+    ///
+    /// On success, returns the peer that sent us the most of the artifact,
+    /// along with the artifact.
     pub(crate) async fn fetch_artifact(
         &self,
         cx: &StepContext,
         artifact_hash_id: &ArtifactHashId,
+        download: &mut PartialDownload,
     ) -> Option<(SocketAddr, BufList)> {
         // TODO: do we want a check phase that happens before the download?
-        let peers = self.peers();
-        let mut remaining_peers = self.peer_count();
+        let peers: Vec<_> = self.peers().collect();
 
         let log = self.log.new(
             slog::o!("artifact_hash_id" => format!("{artifact_hash_id:?}")),
         );
 
-        slog::debug!(log, "start fetch from peers"; "remaining_peers" => remaining_peers);
+        slog::debug!(
+            log,
+            "start fetch from peers";
+            "peer_count" => peers.len(),
+            "chunks_downloaded" => download.chunks.len(),
+        );
+
+        // Progress is reported across all the chunks of the artifact.
+        let downloaded_bytes = AtomicU64::new(download.downloaded_bytes());
+
+        // A peer tells us the size of the artifact when it sends a chunk of it,
+        // and we need the size to know what chunks there are. If we don't know
+        // it yet, fetch the first chunk on its own.
+        let size = match download.size {
+            Some(size) => size,
+            None => {
+                let chunk = self
+                    .fetch_chunk(
+                        cx,
+                        &log,
+                        &peers,
+                        artifact_hash_id,
+                        0,
+                        None,
+                        &downloaded_bytes,
+                    )
+                    .await?;
+                let size = chunk.size;
+                download.insert(0, chunk);
+                size
+            }
+        };
+
+        // An empty artifact still has one (empty) chunk.
+        let chunk_count =
+            ((size + self.chunk_size - 1) / self.chunk_size).max(1);
+        let missing: Vec<_> = (0..chunk_count)
+            .filter(|index| !download.chunks.contains_key(index))
+            .collect();
+
+        // Keep going if a chunk fails, so as much as possible is fetched for
+        // the next attempt.
+        let (log, peers, downloaded_bytes) = (&log, &peers, &downloaded_bytes);
+        let fetched: Vec<_> = futures::stream::iter(missing)
+            .map(|index| async move {
+                let chunk = self
+                    .fetch_chunk(
+                        cx,
+                        log,
+                        peers,
+                        artifact_hash_id,
+                        index,
+                        Some(size),
+                        downloaded_bytes,
+                    )
+                    .await;
+                (index, chunk)
+            })
+            .buffer_unordered(Self::MAX_CONCURRENT_CHUNKS)
+            .collect()
+            .await;
+        for (index, chunk) in fetched {
+            if let Some(chunk) = chunk {
+                download.insert(index, chunk);
+            }
+        }
+
+        let missing_count = chunk_count - download.chunks.len() as u64;
+        if missing_count > 0 {
+            slog::warn!(
+                log,
+                "unable to fetch {missing_count} of {chunk_count} chunks from peers",
+            );
+            return None;
+        }
+
+        slog::info!(
+            log,
+            "fetched artifact ({size} bytes in {chunk_count} chunks) from {} peers",
+            download.bytes_by_peer.len(),
+        );
+        std::mem::take(download).finish()
+    }
+
+    pub(crate) fn peers(&self) -> impl Iterator<Item = SocketAddr> + '_ {
+        self.imp.peers()
+    }
+
+    pub(crate) fn peer_count(&self) -> usize {
+        self.imp.peer_count()
+    }
+
+    pub(crate) fn display(&self) -> impl fmt::Display {
+        self.peers().join(", ")
+    }
 
-        for peer in peers {
+    /// Fetches chunk `index` of an artifact, trying each peer in turn until one
+    /// of them returns it.
+    ///
+    /// `size` is the size of the artifact, if we know it yet. Each chunk starts
+    /// with a different peer, to spread chunks out across peers.
+    #[allow(clippy::too_many_arguments)]
+    async fn fetch_chunk(
+        &self,
+        cx: &StepContext,
+        log: &slog::Logger,
+        peers: &[SocketAddr],
+        artifact_hash_id: &ArtifactHashId,
+        index: u64,
+        size: Option<u64>,
+        downloaded_bytes: &AtomicU64,
+    ) -> Option<FetchedChunk> {
+        let chunk_start = index * self.chunk_size;
+        let chunk_end = chunk_start + self.chunk_size;
+        let range =
+            chunk_start..size.map_or(chunk_end, |size| chunk_end.min(size));
+
+        let first_peer = (index % peers.len().max(1) as u64) as usize;
+        let mut remaining_peers = peers.len();
+        for &peer in peers.iter().cycle().skip(first_peer).take(peers.len()) {
             remaining_peers -= 1;
 
             slog::debug!(
                 log,
-                "start fetch from peer {peer:?}"; "remaining_peers" => remaining_peers,
+                "start fetch of bytes {range:?} from peer {peer:?}";
+                "remaining_peers" => remaining_peers,
             );
 
-            // Attempt to download data from this peer.
+            // Attempt to download the chunk from this peer.
             let start = Instant::now();
-            match self.fetch_from_peer(cx, peer, artifact_hash_id).await {
-                Ok(artifact_bytes) => {
+            match self
+                .fetch_range_from_peer(
+                    cx,
+                    peer,
+                    artifact_hash_id,
+                    range.clone(),
+                    size,
+                    downloaded_bytes,
+                )
+                .await
+            {
+                Ok((size, data)) => {
                     let elapsed = start.elapsed();
-                    slog::info!(
+                    slog::debug!(
                         log,
-                        "fetched artifact from peer {peer} in {elapsed:?}"
+                        "fetched bytes {range:?} from peer {peer} in {elapsed:?}"
                     );
-                    return Some((peer, artifact_bytes));
+                    return Some(FetchedChunk { peer, size, data });
                 }
                 Err(error) => {
                     let elapsed = start.elapsed();
@@ -274,35 +485,36 @@ impl Peers {
         None
     }
 
-    pub(crate) fn peers(&self) -> impl Iterator<Item = SocketAddr> + '_ {
-        self.imp.peers()
-    }
-
-    pub(crate) fn peer_count(&self) -> usize {
-        self.imp.peer_count()
-    }
-
-    pub(crate) fn display(&self) -> impl fmt::Display {
-        self.peers().join(", ")
-    }
-
-    async fn fetch_from_peer(
+    /// Fetches the bytes of an artifact in `range` from `peer`, returning the
+    /// size of the artifact along with them.
+    ///
+    /// The bytes received count towards `downloaded_bytes` while this is in
+    /// progress, and are taken back out if it fails.
+    async fn fetch_range_from_peer(
         &self,
         cx: &StepContext,
         peer: SocketAddr,
         artifact_hash_id: &ArtifactHashId,
-    ) -> Result<BufList, ArtifactFetchError> {
+        range: Range<u64>,
+        expected_size: Option<u64>,
+        downloaded_bytes: &AtomicU64,
+    ) -> Result<(u64, BufList), ArtifactFetchError> {
         let log = self.log.new(slog::o!("peer" => peer.to_string()));
+        let metadata = InstallinatorProgressMetadata::Download { peer };
 
         let (total_bytes, mut receiver) = match self
             .imp
-            .fetch_from_peer_impl(peer, artifact_hash_id.clone())
+            .fetch_range_from_peer_impl(
+                peer,
+                artifact_hash_id.clone(),
+                range.clone(),
+            )
             .await
         {
             Ok(x) => x,
             Err(error) => {
                 cx.send_progress(StepProgress::Reset {
-                    metadata: InstallinatorProgressMetadata::Download { peer },
+                    metadata,
                     message: error.to_string().into(),
                 })
                 .await;
@@ -310,11 +522,28 @@ impl Peers {
             }
         };
 
-        let mut artifact_bytes = BufList::new();
-        let mut downloaded_bytes = 0u64;
-        let metadata = InstallinatorProgressMetadata::Download { peer };
+        // Peers serve artifacts by hash, so they had all better agree on the
+        // size.
+        if let Some(expected_size) = expected_size {
+            if total_bytes != expected_size {
+                let error = ArtifactFetchError::ArtifactSizeMismatch {
+                    peer,
+                    reported_size: total_bytes,
+                    expected_size,
+                };
+                cx.send_progress(StepProgress::reset(
+                    metadata,
+                    error.to_string(),
+                ))
+                .await;
+                return Err(error);
+            }
+        }
 
-        loop {
+        let mut chunk_bytes = BufList::new();
+        let mut fetched_bytes = 0u64;
+
+        let result = loop {
             match tokio::time::timeout(self.timeout, receiver.recv()).await {
                 Ok(Some(Ok(bytes))) => {
                     slog::debug!(
@@ -322,10 +551,13 @@ impl Peers {
                         "received chunk of {} bytes from peer",
                         bytes.len()
                     );
-                    downloaded_bytes += bytes.len() as u64;
-                    artifact_bytes.push_chunk(bytes);
+                    fetched_bytes += bytes.len() as u64;
+                    let current = downloaded_bytes
+                        .fetch_add(bytes.len() as u64, Ordering::Relaxed)
+                        + bytes.len() as u64;
+                    chunk_bytes.push_chunk(bytes);
                     cx.send_progress(StepProgress::with_current_and_total(
-                        downloaded_bytes,
+                        current,
                         total_bytes,
                         ProgressUnits::BYTES,
                         metadata.clone(),
@@ -338,52 +570,49 @@ impl Peers {
                         "received error from peer, sending cancellation: {}",
                         DisplayErrorChain::new(&error),
                     );
-                    cx.send_progress(StepProgress::Reset {
-                        metadata: metadata.clone(),
-                        message: error.to_string().into(),
-                    })
-                    .await;
-                    return Err(ArtifactFetchError::HttpError {
+                    break Err(ArtifactFetchError::HttpError {
                         peer,
                         error: error.into(),
                     });
                 }
                 Ok(None) => {
-                    // The entire artifact has been downloaded.
-                    break;
+                    // The entire range has been downloaded.
+                    break Ok(());
                 }
                 Err(_) => {
                     // The operation timed out.
-                    cx.send_progress(StepProgress::Reset {
-                        metadata,
-                        message: format!(
-                            "operation timed out ({:?})",
-                            self.timeout
-                        )
-                        .into(),
-                    })
-                    .await;
-                    return Err(ArtifactFetchError::Timeout {
+                    break Err(ArtifactFetchError::Timeout {
                         peer,
                         timeout: self.timeout,
-                        bytes_fetched: artifact_bytes.num_bytes(),
+                        bytes_fetched: chunk_bytes.num_bytes(),
                     });
                 }
             }
-        }
+        };
 
-        // Check that the artifact size matches the returned size.
-        if total_bytes != artifact_bytes.num_bytes() as u64 {
-            let error = ArtifactFetchError::SizeMismatch {
-                artifact_size: total_bytes,
-                downloaded_bytes,
-            };
+        // Check that we got the part of the range within the artifact.
+        let expected_range =
+            range.start..range.end.min(total_bytes).max(range.start);
+        let result = result.and_then(|()| {
+            if fetched_bytes != expected_range.end - expected_range.start {
+                Err(ArtifactFetchError::SizeMismatch {
+                    peer,
+                    range: expected_range,
+                    downloaded_bytes: fetched_bytes,
+                })
+            } else {
+                Ok(())
+            }
+        });
+
+        if let Err(error) = result {
+            downloaded_bytes.fetch_sub(fetched_bytes, Ordering::Relaxed);
             cx.send_progress(StepProgress::reset(metadata, error.to_string()))
                 .await;
             return Err(error);
         }
 
-        Ok(artifact_bytes)
+        Ok((total_bytes, chunk_bytes))
     }
 
     pub(crate) fn broadcast_report(
@@ -446,11 +675,16 @@ pub(crate) trait PeersImpl: fmt::Debug + Send + Sync {
     fn peers(&self) -> Box<dyn Iterator<Item = SocketAddr> + Send + '_>;
     fn peer_count(&self) -> usize;
 
-    /// Returns (size, receiver) on success, and an error on failure.
-    async fn fetch_from_peer_impl(
+    /// Fetches the bytes of an artifact in `range`.
+    ///
+    /// Returns (size, receiver) on success, and an error on failure. `size` is
+    /// the size of the whole artifact, and the receiver gets the part of
+    /// `range` that lies within it.
+    async fn fetch_range_from_peer_impl(
         &self,
         peer: SocketAddr,
         artifact_hash_id: ArtifactHashId,
+        range: Range<u64>,
     ) -> Result<(u64, FetchReceiver), HttpError>;
 
     async fn report_progress_impl(
@@ -488,14 +722,15 @@ impl PeersImpl for HttpPeers {
         self.peers.len()
     }
 
-    async fn fetch_from_peer_impl(
+    async fn fetch_range_from_peer_impl(
         &self,
         peer: SocketAddr,
         artifact_hash_id: ArtifactHashId,
+        range: Range<u64>,
     ) -> Result<(u64, FetchReceiver), HttpError> {
         // TODO: be able to fetch from sled-agent clients as well
         let artifact_client = ArtifactClient::new(peer, &self.log);
-        artifact_client.fetch(artifact_hash_id).await
+        artifact_client.fetch_range(artifact_hash_id, range).await
     }
 
     async fn report_progress_impl(
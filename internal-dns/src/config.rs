@@ -60,7 +60,7 @@
 //!
 //! This module provides types used to assemble that configuration.
 
-use crate::names::{ServiceName, DNS_ZONE};
+use crate::names::{nameserver_dns_name, ServiceName, DNS_ZONE};
 use anyhow::{anyhow, ensure};
 use dns_service_client::types::{DnsConfigParams, DnsConfigZone, DnsRecord};
 use std::collections::BTreeMap;
@@ -340,6 +340,24 @@ impl DnsConfigBuilder {
     /// our DNS servers) for the control plane DNS zone described up to this
     /// point
     pub fn build(self) -> DnsConfigParams {
+        // Assemble the set of "AAAA" records for the zone's nameservers, which
+        // are the zones running our own DNS servers.
+        let nameserver_records: Vec<_> = self
+            .service_instances_zones
+            .get(&ServiceName::InternalDns)
+            .into_iter()
+            .flat_map(|zone2port| zone2port.keys())
+            .enumerate()
+            .map(|(i, zone)| {
+                (
+                    nameserver_dns_name(i + 1),
+                    vec![DnsRecord::Aaaa(self.zones[zone])],
+                )
+            })
+            .collect();
+        let nameservers =
+            nameserver_records.iter().map(|(name, _)| name.clone()).collect();
+
         // Assemble the set of "AAAA" records for sleds.
         let sled_records = self.sleds.into_iter().map(|(sled, sled_ip)| {
             let name = Host::Sled(sled.0).dns_name();
@@ -399,6 +417,7 @@ impl DnsConfigBuilder {
             .chain(zone_records)
             .chain(srv_records_sleds)
             .chain(srv_records_zones)
+            .chain(nameserver_records)
             .collect();
 
         DnsConfigParams {
@@ -407,6 +426,7 @@ impl DnsConfigBuilder {
             zones: vec![DnsConfigZone {
                 zone_name: DNS_ZONE.to_owned(),
                 records: all_records,
+                nameservers,
             }],
         }
     }
@@ -415,7 +435,9 @@ impl DnsConfigBuilder {
 #[cfg(test)]
 mod test {
     use super::{DnsConfigBuilder, Host, ServiceName, ZoneVariant};
+    use crate::names::is_nameserver_dns_name;
     use crate::DNS_ZONE;
+    use dns_service_client::types::DnsRecord;
     use std::{collections::BTreeMap, io::Write, net::Ipv6Addr};
     use uuid::Uuid;
 
@@ -563,6 +585,34 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_builder_nameservers() {
+        let zone1_uuid: Uuid = ZONE1_UUID.parse().unwrap();
+        let zone2_uuid: Uuid = ZONE2_UUID.parse().unwrap();
+        let zone3_uuid: Uuid = ZONE3_UUID.parse().unwrap();
+
+        // Without any DNS servers, the zone has no nameservers.
+        let config = DnsConfigBuilder::new().build();
+        assert!(config.zones[0].nameservers.is_empty());
+
+        // Each zone running a DNS server gets a nameserver name.
+        let mut b = DnsConfigBuilder::new();
+        let zone1 = b.host_zone(zone1_uuid, ZONE1_IP).unwrap();
+        let zone2 = b.host_zone(zone2_uuid, ZONE2_IP).unwrap();
+        let zone3 = b.host_zone(zone3_uuid, ZONE3_IP).unwrap();
+        b.service_backend_zone(ServiceName::InternalDns, &zone1, 123).unwrap();
+        b.service_backend_zone(ServiceName::InternalDns, &zone2, 123).unwrap();
+        b.service_backend_zone(ServiceName::Nexus, &zone3, 124).unwrap();
+        let config = b.build();
+        let zone = &config.zones[0];
+        assert_eq!(zone.nameservers, ["ns1", "ns2"]);
+        assert_eq!(zone.records["ns1"], [DnsRecord::Aaaa(ZONE1_IP)]);
+        assert_eq!(zone.records["ns2"], [DnsRecord::Aaaa(ZONE2_IP)]);
+        assert!(zone.nameservers.iter().all(|n| is_nameserver_dns_name(n)));
+        assert!(!is_nameserver_dns_name("ns"));
+        assert!(!is_nameserver_dns_name("nsa"));
+    }
+
     #[test]
     fn test_builder_errors() {
         let sled1_uuid: Uuid = SLED1_UUID.parse().unwrap();
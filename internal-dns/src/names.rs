@@ -13,6 +13,22 @@ pub const DNS_ZONE: &str = "control-plane.oxide.internal";
 /// development
 pub const DNS_ZONE_EXTERNAL_TESTING: &str = "oxide-dev.test";
 
+/// Returns the name (relative to its DNS zone) of the zone's `n`th nameserver,
+/// counting from one (e.g., "ns1")
+///
+/// Each of the DNS zones we operate, internal and external, has one of these
+/// names for each of the DNS servers that serve it.
+pub fn nameserver_dns_name(n: usize) -> String {
+    format!("ns{}", n)
+}
+
+/// Returns whether `name` (relative to its DNS zone) is one of the names
+/// produced by [`nameserver_dns_name()`]
+pub fn is_nameserver_dns_name(name: &str) -> bool {
+    name.strip_prefix("ns")
+        .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+}
+
 /// Names of services within the control plane
 #[derive(Clone, Copy, Debug, Hash, Eq, Ord, PartialEq, PartialOrd)]
 pub enum ServiceName {
@@ -429,6 +429,9 @@ mod test {
                 store,
                 &dns_server::dns_server::Config {
                     bind_address: "[::1]:0".parse().unwrap(),
+                    transfer_peers: vec![],
+                    notify_secondaries: vec![],
+                    rate_limit: Default::default(),
                 },
                 &dropshot::ConfigDropshot {
                     bind_address: "[::1]:0".parse().unwrap(),
@@ -0,0 +1,72 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::schema::audit_log;
+use chrono::{DateTime, Utc};
+use nexus_types::external_api::views;
+use uuid::Uuid;
+
+/// An entry in the audit log, describing one request to the external API
+#[derive(Clone, Debug, Insertable, Queryable, Selectable)]
+#[diesel(table_name = audit_log)]
+pub struct AuditLogEntry {
+    pub id: Uuid,
+    pub time_started: DateTime<Utc>,
+
+    pub request_id: String,
+    pub operation_id: String,
+    pub http_method: String,
+    pub request_uri: String,
+
+    pub actor_id: Option<Uuid>,
+    pub actor_silo_id: Option<Uuid>,
+
+    pub time_completed: Option<DateTime<Utc>>,
+    pub http_status_code: Option<i32>,
+}
+
+impl AuditLogEntry {
+    /// Returns a new entry for a request that is about to be carried out
+    pub fn new(
+        request_id: String,
+        operation_id: String,
+        http_method: String,
+        request_uri: String,
+        actor_id: Option<Uuid>,
+        actor_silo_id: Option<Uuid>,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            time_started: Utc::now(),
+            request_id,
+            operation_id,
+            http_method,
+            request_uri,
+            actor_id,
+            actor_silo_id,
+            time_completed: None,
+            http_status_code: None,
+        }
+    }
+}
+
+impl From<AuditLogEntry> for views::AuditLogEntry {
+    fn from(entry: AuditLogEntry) -> Self {
+        Self {
+            id: entry.id,
+            time_started: entry.time_started,
+            request_id: entry.request_id,
+            operation_id: entry.operation_id,
+            http_method: entry.http_method,
+            request_uri: entry.request_uri,
+            actor_id: entry.actor_id,
+            actor_silo_id: entry.actor_silo_id,
+            time_completed: entry.time_completed,
+            // Status codes are always written from a `u16`.
+            http_status_code: entry
+                .http_status_code
+                .and_then(|code| u16::try_from(code).ok()),
+        }
+    }
+}
@@ -58,7 +58,7 @@ const TOKEN_LENGTH: usize = 20;
 /// Generate a random token/device code.
 // TODO: this should be merged with session::generate_session_token,
 // and probably also the key generation in the disk creation saga.
-fn generate_token() -> String {
+pub(crate) fn generate_token() -> String {
     let mut bytes: [u8; TOKEN_LENGTH] = [0; TOKEN_LENGTH];
     let mut rng = StdRng::from_entropy();
     rng.fill_bytes(&mut bytes);
@@ -124,6 +124,8 @@ pub struct DeviceAccessToken {
     pub time_requested: DateTime<Utc>,
     pub time_created: DateTime<Utc>,
     pub time_expires: Option<DateTime<Utc>>,
+    pub id: Uuid,
+    pub description: String,
 }
 
 impl DeviceAccessToken {
@@ -143,6 +145,33 @@ impl DeviceAccessToken {
             time_requested,
             time_created: now,
             time_expires: None,
+            id: Uuid::new_v4(),
+            description: String::new(),
+        }
+    }
+
+    /// Create a personal access token, requested directly by a user rather
+    /// than through a device authorization flow.
+    ///
+    /// Such tokens have no client, so they are given a random client ID and
+    /// device code. This satisfies the uniqueness constraint on those columns
+    /// and ensures that no device authorization flow will ever claim them.
+    pub fn new_personal(
+        silo_user_id: Uuid,
+        description: String,
+        ttl: Option<Duration>,
+    ) -> Self {
+        let now = Utc::now();
+        Self {
+            token: generate_token(),
+            client_id: Uuid::new_v4(),
+            device_code: generate_token(),
+            silo_user_id,
+            time_requested: now,
+            time_created: now,
+            time_expires: ttl.map(|ttl| now + ttl),
+            id: Uuid::new_v4(),
+            description,
         }
     }
 
@@ -156,6 +185,29 @@ impl DeviceAccessToken {
     }
 }
 
+impl From<DeviceAccessToken> for views::AccessToken {
+    fn from(access_token: DeviceAccessToken) -> Self {
+        Self {
+            id: access_token.id,
+            description: access_token.description,
+            time_created: access_token.time_created,
+            time_expires: access_token.time_expires,
+        }
+    }
+}
+
+impl From<DeviceAccessToken> for views::AccessTokenCreated {
+    fn from(access_token: DeviceAccessToken) -> Self {
+        Self {
+            id: access_token.id,
+            description: access_token.description,
+            time_created: access_token.time_created,
+            time_expires: access_token.time_expires,
+            access_token: format!("oxide-token-{}", access_token.token),
+        }
+    }
+}
+
 impl From<DeviceAccessToken> for views::DeviceAccessTokenGrant {
     fn from(access_token: DeviceAccessToken) -> Self {
         Self {
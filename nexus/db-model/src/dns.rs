@@ -132,6 +132,9 @@ pub enum DnsRecord {
     A(Ipv4Addr),
     AAAA(Ipv6Addr),
     SRV(SRV),
+    CNAME(String),
+    TXT(Vec<String>),
+    PTR(String),
 }
 
 impl From<params::DnsRecord> for DnsRecord {
@@ -140,6 +143,9 @@ impl From<params::DnsRecord> for DnsRecord {
             params::DnsRecord::A(addr) => DnsRecord::A(addr),
             params::DnsRecord::Aaaa(addr) => DnsRecord::AAAA(addr),
             params::DnsRecord::Srv(srv) => DnsRecord::SRV(SRV::from(srv)),
+            params::DnsRecord::Cname(target) => DnsRecord::CNAME(target),
+            params::DnsRecord::Txt(strings) => DnsRecord::TXT(strings),
+            params::DnsRecord::Ptr(target) => DnsRecord::PTR(target),
         }
     }
 }
@@ -152,6 +158,9 @@ impl From<DnsRecord> for params::DnsRecord {
             DnsRecord::SRV(srv) => {
                 params::DnsRecord::Srv(params::Srv::from(srv))
             }
+            DnsRecord::CNAME(target) => params::DnsRecord::Cname(target),
+            DnsRecord::TXT(strings) => params::DnsRecord::Txt(strings),
+            DnsRecord::PTR(target) => params::DnsRecord::Ptr(target),
         }
     }
 }
@@ -3,7 +3,9 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use crate::impl_enum_type;
-use crate::schema::{identity_provider, saml_identity_provider};
+use crate::schema::{
+    identity_provider, oidc_identity_provider, saml_identity_provider,
+};
 use db_macros::Resource;
 use nexus_types::identity::Resource;
 
@@ -22,12 +24,14 @@ impl_enum_type!(
 
     // Enum values
     Saml => b"saml"
+    Oidc => b"oidc"
 );
 
 impl From<IdentityProviderType> for views::IdentityProviderType {
     fn from(idp_type: IdentityProviderType) -> Self {
         match idp_type {
             IdentityProviderType::Saml => views::IdentityProviderType::Saml,
+            IdentityProviderType::Oidc => views::IdentityProviderType::Oidc,
         }
     }
 }
@@ -101,3 +105,54 @@ impl From<SamlIdentityProvider> for views::SamlIdentityProvider {
         }
     }
 }
+
+#[derive(Queryable, Insertable, Clone, Debug, Selectable, Resource)]
+#[diesel(table_name = oidc_identity_provider)]
+pub struct OidcIdentityProvider {
+    #[diesel(embed)]
+    pub identity: OidcIdentityProviderIdentity,
+
+    pub silo_id: Uuid,
+
+    /// the issuer identifier of the provider
+    pub issuer: String,
+
+    /// endpoints taken from the provider's discovery document
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+
+    /// credentials of the client registered with the provider
+    pub client_id: String,
+    pub client_secret: String,
+
+    /// endpoint where the provider will send the authorization response
+    pub redirect_uri: String,
+
+    /// space-separated list of scopes requested in addition to "openid"
+    pub scopes: String,
+
+    /// if set, the ID token claim with this name will be considered to denote
+    /// a user's group membership
+    pub group_claim_name: Option<String>,
+}
+
+impl From<OidcIdentityProvider> for views::OidcIdentityProvider {
+    fn from(oidc_idp: OidcIdentityProvider) -> Self {
+        Self {
+            identity: oidc_idp.identity(),
+            issuer: oidc_idp.issuer,
+            authorization_endpoint: oidc_idp.authorization_endpoint,
+            token_endpoint: oidc_idp.token_endpoint,
+            jwks_uri: oidc_idp.jwks_uri,
+            client_id: oidc_idp.client_id,
+            redirect_uri: oidc_idp.redirect_uri,
+            scopes: oidc_idp
+                .scopes
+                .split_whitespace()
+                .map(String::from)
+                .collect(),
+            group_claim_name: oidc_idp.group_claim_name,
+        }
+    }
+}
@@ -10,6 +10,8 @@ extern crate diesel;
 extern crate newtype_derive;
 
 mod address_lot;
+mod affinity;
+mod audit_log;
 mod bgp;
 mod block_size;
 mod bytecount;
@@ -59,6 +61,7 @@ mod role_assignment;
 mod role_builtin;
 pub mod saga_types;
 pub mod schema;
+mod scim_client_bearer_token;
 mod service;
 mod service_kind;
 mod silo;
@@ -76,6 +79,7 @@ mod unsigned;
 mod update_artifact;
 mod user_builtin;
 mod virtual_provisioning_collection;
+mod virtual_provisioning_quota;
 mod virtual_provisioning_resource;
 mod vni;
 mod volume;
@@ -97,6 +101,8 @@ mod db {
 pub use self::macaddr::*;
 pub use self::unsigned::*;
 pub use address_lot::*;
+pub use affinity::*;
+pub use audit_log::*;
 pub use bgp::*;
 pub use block_size::*;
 pub use bytecount::*;
@@ -135,6 +141,7 @@ pub use region::*;
 pub use region_snapshot::*;
 pub use role_assignment::*;
 pub use role_builtin::*;
+pub use scim_client_bearer_token::*;
 pub use semver_version::*;
 pub use service::*;
 pub use service_kind::*;
@@ -155,6 +162,7 @@ pub use system_update::*;
 pub use update_artifact::*;
 pub use user_builtin::*;
 pub use virtual_provisioning_collection::*;
+pub use virtual_provisioning_quota::*;
 pub use virtual_provisioning_resource::*;
 pub use vni::*;
 pub use volume::*;
@@ -4,9 +4,13 @@
 
 use super::SqlU16;
 use crate::schema::metric_producer;
+use crate::schema::metric_push_producer;
+use chrono::DateTime;
+use chrono::Utc;
 use db_macros::Asset;
 use nexus_types::identity::Asset;
 use omicron_common::api::internal;
+use omicron_common::api::internal::nexus::PUSH_PRODUCER_LEASE_DURATION;
 use uuid::Uuid;
 
 /// Information announced by a metric server, used so that clients can contact it and collect
@@ -46,3 +50,28 @@ impl ProducerEndpoint {
         format!("{}/{}", &self.base_route, self.id())
     }
 }
+
+/// A metric producer that pushes its samples to an Oximeter collector, rather
+/// than being polled by it.
+#[derive(Queryable, Insertable, Debug, Clone, Selectable, Asset)]
+#[diesel(table_name = metric_push_producer)]
+pub struct PushProducer {
+    #[diesel(embed)]
+    identity: PushProducerIdentity,
+
+    pub oximeter_id: Uuid,
+    /// When the producer's registration runs out, unless it registers again
+    pub time_expires: DateTime<Utc>,
+}
+
+impl PushProducer {
+    /// Create a new push producer, assigned to the chosen Oximeter instance.
+    ///
+    /// Its registration lasts for [`PUSH_PRODUCER_LEASE_DURATION`].
+    pub fn new(id: Uuid, oximeter_id: Uuid) -> Self {
+        let identity = PushProducerIdentity::new(id);
+        let time_expires = identity.time_modified
+            + chrono::Duration::from_std(PUSH_PRODUCER_LEASE_DURATION).unwrap();
+        Self { identity, oximeter_id, time_expires }
+    }
+}
@@ -2,9 +2,11 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use super::{Disk, Generation, Instance, Name, Snapshot, Vpc};
+use super::{AffinityGroup, Disk, Generation, Instance, Name, Snapshot, Vpc};
 use crate::collection::DatastoreCollectionConfig;
-use crate::schema::{disk, image, instance, project, snapshot, vpc};
+use crate::schema::{
+    affinity_group, disk, image, instance, project, snapshot, vpc,
+};
 use crate::Image;
 use chrono::{DateTime, Utc};
 use db_macros::Resource;
@@ -69,6 +71,13 @@ impl DatastoreCollectionConfig<Instance> for Project {
     type CollectionIdColumn = instance::dsl::project_id;
 }
 
+impl DatastoreCollectionConfig<AffinityGroup> for Project {
+    type CollectionId = Uuid;
+    type GenerationNumberColumn = project::dsl::rcgen;
+    type CollectionTimeDeletedColumn = project::dsl::time_deleted;
+    type CollectionIdColumn = affinity_group::dsl::project_id;
+}
+
 impl DatastoreCollectionConfig<Disk> for Project {
     type CollectionId = Uuid;
     type GenerationNumberColumn = project::dsl::rcgen;
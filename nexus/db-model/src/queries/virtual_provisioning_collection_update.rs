@@ -9,6 +9,7 @@
 
 use crate::schema::silo;
 use crate::schema::virtual_provisioning_collection;
+use crate::schema::virtual_provisioning_quota;
 
 table! {
     parent_silo {
@@ -36,3 +37,8 @@ diesel::allow_tables_to_appear_in_same_query!(
     all_collections,
     do_update,
 );
+
+diesel::allow_tables_to_appear_in_same_query!(
+    virtual_provisioning_quota,
+    all_collections,
+);
@@ -360,6 +360,27 @@ table! {
     }
 }
 
+table! {
+    affinity_group (id) {
+        id -> Uuid,
+        name -> Text,
+        description -> Text,
+        time_created -> Timestamptz,
+        time_modified -> Timestamptz,
+        time_deleted -> Nullable<Timestamptz>,
+        project_id -> Uuid,
+        kind -> crate::AffinityGroupKindEnum,
+        policy -> crate::AffinityPolicyEnum,
+    }
+}
+
+table! {
+    affinity_group_instance_membership (group_id, instance_id) {
+        group_id -> Uuid,
+        instance_id -> Uuid,
+    }
+}
+
 table! {
     sled_instance (id) {
         id -> Uuid,
@@ -389,6 +410,16 @@ table! {
     }
 }
 
+table! {
+    metric_push_producer (id) {
+        id -> Uuid,
+        time_created -> Timestamptz,
+        time_modified -> Timestamptz,
+        oximeter_id -> Uuid,
+        time_expires -> Timestamptz,
+    }
+}
+
 table! {
     network_interface (id) {
         id -> Uuid,
@@ -487,6 +518,24 @@ table! {
         ip -> Inet,
         first_port -> Int4,
         last_port -> Int4,
+        project_id -> Nullable<Uuid>,
+    }
+}
+
+table! {
+    floating_ip (id) {
+        id -> Uuid,
+        name -> Text,
+        description -> Text,
+        time_created -> Timestamptz,
+        time_modified -> Timestamptz,
+        time_deleted -> Nullable<Timestamptz>,
+        ip_pool_id -> Uuid,
+        ip_pool_range_id -> Uuid,
+        is_service -> Bool,
+        parent_id -> Nullable<Uuid>,
+        ip -> Inet,
+        project_id -> Uuid,
     }
 }
 
@@ -518,6 +567,7 @@ table! {
 
         silo_id -> Uuid,
         external_id -> Text,
+        active -> Bool,
     }
 }
 
@@ -594,6 +644,31 @@ table! {
     }
 }
 
+table! {
+    oidc_identity_provider (id) {
+        id -> Uuid,
+        name -> Text,
+        description -> Text,
+        time_created -> Timestamptz,
+        time_modified -> Timestamptz,
+        time_deleted -> Nullable<Timestamptz>,
+
+        silo_id -> Uuid,
+
+        issuer -> Text,
+        authorization_endpoint -> Text,
+        token_endpoint -> Text,
+        jwks_uri -> Text,
+
+        client_id -> Text,
+        client_secret -> Text,
+        redirect_uri -> Text,
+        scopes -> Text,
+
+        group_claim_name -> Nullable<Text>,
+    }
+}
+
 table! {
     ssh_key (id) {
         id -> Uuid,
@@ -801,6 +876,21 @@ table! {
     }
 }
 
+table! {
+    virtual_provisioning_quota {
+        id -> Uuid,
+        // This type isn't actually "Nullable" - it's just handy to use the
+        // same type for insertion and querying, and doing so requires this
+        // field to appear optional so we can let this (default) field appear
+        // optional.
+        time_modified -> Nullable<Timestamptz>,
+        collection_type -> Text,
+        virtual_disk_bytes_limit -> Nullable<Int8>,
+        cpus_limit -> Nullable<Int8>,
+        ram_limit -> Nullable<Int8>,
+    }
+}
+
 table! {
     zpool (id) {
         id -> Uuid,
@@ -1020,6 +1110,17 @@ table! {
         time_requested -> Timestamptz,
         time_created -> Timestamptz,
         time_expires -> Nullable<Timestamptz>,
+        id -> Uuid,
+        description -> Text,
+    }
+}
+
+table! {
+    scim_client_bearer_token (id) {
+        id -> Uuid,
+        time_created -> Timestamptz,
+        silo_id -> Uuid,
+        bearer_token -> Text,
     }
 }
 
@@ -1115,6 +1216,21 @@ table! {
     }
 }
 
+table! {
+    audit_log (id) {
+        id -> Uuid,
+        time_started -> Timestamptz,
+        request_id -> Text,
+        operation_id -> Text,
+        http_method -> Text,
+        request_uri -> Text,
+        actor_id -> Nullable<Uuid>,
+        actor_silo_id -> Nullable<Uuid>,
+        time_completed -> Nullable<Timestamptz>,
+        http_status_code -> Nullable<Int4>,
+    }
+}
+
 table! {
     db_metadata (singleton) {
         singleton -> Bool,
@@ -1130,7 +1246,7 @@ table! {
 ///
 /// This should be updated whenever the schema is changed. For more details,
 /// refer to: schema/crdb/README.adoc
-pub const SCHEMA_VERSION: SemverVersion = SemverVersion::new(4, 0, 0);
+pub const SCHEMA_VERSION: SemverVersion = SemverVersion::new(13, 0, 0);
 
 allow_tables_to_appear_in_same_query!(
     system_update,
@@ -1143,6 +1259,8 @@ allow_tables_to_appear_in_same_query!(ip_pool_range, ip_pool);
 joinable!(ip_pool_range -> ip_pool (ip_pool_id));
 
 allow_tables_to_appear_in_same_query!(
+    affinity_group,
+    affinity_group_instance_membership,
     dataset,
     disk,
     image,
@@ -1150,6 +1268,7 @@ allow_tables_to_appear_in_same_query!(
     silo_image,
     instance,
     metric_producer,
+    metric_push_producer,
     network_interface,
     instance_network_interface,
     service_network_interface,
@@ -1178,6 +1297,10 @@ allow_tables_to_appear_in_same_query!(
 );
 
 allow_tables_to_appear_in_same_query!(dns_zone, dns_version, dns_name);
+allow_tables_to_appear_in_same_query!(
+    virtual_provisioning_collection,
+    virtual_provisioning_quota
+);
 allow_tables_to_appear_in_same_query!(external_ip, service);
 
 allow_tables_to_appear_in_same_query!(
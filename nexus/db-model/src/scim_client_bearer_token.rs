@@ -0,0 +1,54 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::device_auth::generate_token;
+use crate::schema::scim_client_bearer_token;
+use chrono::{DateTime, Utc};
+use nexus_types::external_api::views;
+use uuid::Uuid;
+
+/// Prefix used on SCIM bearer tokens, so that they can be told apart from
+/// (and are never accepted as) the access tokens used by API clients
+pub const SCIM_TOKEN_PREFIX: &str = "oxide-scim-";
+
+/// A bearer token with which an identity provider's SCIM client provisions
+/// the users and groups of a Silo
+#[derive(Clone, Debug, Insertable, Queryable, Selectable)]
+#[diesel(table_name = scim_client_bearer_token)]
+pub struct ScimClientBearerToken {
+    pub id: Uuid,
+    pub time_created: DateTime<Utc>,
+    pub silo_id: Uuid,
+    pub bearer_token: String,
+}
+
+impl ScimClientBearerToken {
+    pub fn new(silo_id: Uuid) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            time_created: Utc::now(),
+            silo_id,
+            bearer_token: generate_token(),
+        }
+    }
+}
+
+impl From<ScimClientBearerToken> for views::ScimClientBearerToken {
+    fn from(token: ScimClientBearerToken) -> Self {
+        Self { id: token.id, time_created: token.time_created }
+    }
+}
+
+impl From<ScimClientBearerToken> for views::ScimClientBearerTokenCreated {
+    fn from(token: ScimClientBearerToken) -> Self {
+        Self {
+            id: token.id,
+            time_created: token.time_created,
+            bearer_token: format!(
+                "{}{}",
+                SCIM_TOKEN_PREFIX, token.bearer_token
+            ),
+        }
+    }
+}
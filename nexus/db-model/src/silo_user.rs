@@ -20,6 +20,11 @@ pub struct SiloUser {
 
     /// The identity provider's ID for this user.
     pub external_id: String,
+
+    /// Whether the user may log in.  Users that have been deactivated by
+    /// their identity provider are kept (along with their role assignments)
+    /// in case they are later reactivated.
+    pub active: bool,
 }
 
 impl SiloUser {
@@ -29,6 +34,7 @@ impl SiloUser {
             time_deleted: None,
             silo_id,
             external_id,
+            active: true,
         }
     }
 }
@@ -157,12 +157,18 @@ impl DatastoreCollectionConfig<super::Service> for Sled {
 #[derive(Debug)]
 pub struct SledReservationConstraints {
     must_select_from: Vec<Uuid>,
+    affinity_instance_id: Option<Uuid>,
+    affinity_group_ids: Vec<Uuid>,
 }
 
 impl SledReservationConstraints {
     /// Creates a constraint set with no constraints in it.
     pub fn none() -> Self {
-        Self { must_select_from: Vec::new() }
+        Self {
+            must_select_from: Vec::new(),
+            affinity_instance_id: None,
+            affinity_group_ids: Vec::new(),
+        }
     }
 
     /// If the constraints include a set of sleds that the caller must select
@@ -176,6 +182,18 @@ impl SledReservationConstraints {
             Some(&self.must_select_from)
         }
     }
+
+    /// Returns the ID of the instance whose affinity groups constrain the
+    /// selection, if any.
+    pub fn affinity_instance_id(&self) -> Option<Uuid> {
+        self.affinity_instance_id
+    }
+
+    /// Returns the IDs of affinity groups whose rules apply to the selection
+    /// in addition to those of the groups the instance already belongs to.
+    pub fn affinity_group_ids(&self) -> &[Uuid] {
+        &self.affinity_group_ids
+    }
 }
 
 #[derive(Debug)]
@@ -198,6 +216,21 @@ impl SledReservationConstraintBuilder {
         self
     }
 
+    /// Adds the rules of the affinity groups that the given instance belongs
+    /// to. Other members of those groups are placed relative to the sleds
+    /// their instances occupy; the instance itself is ignored.
+    pub fn affinity_instance(mut self, instance_id: Uuid) -> Self {
+        self.constraints.affinity_instance_id = Some(instance_id);
+        self
+    }
+
+    /// Adds the rules of the given affinity groups, which the instance is
+    /// about to join.
+    pub fn affinity_groups(mut self, group_ids: &[Uuid]) -> Self {
+        self.constraints.affinity_group_ids.extend(group_ids);
+        self
+    }
+
     /// Builds a set of constraints from this builder's current state.
     pub fn build(self) -> SledReservationConstraints {
         self.constraints
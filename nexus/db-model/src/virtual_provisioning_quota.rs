@@ -0,0 +1,62 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::schema::virtual_provisioning_quota;
+use crate::ByteCount;
+use crate::CollectionTypeProvisioned;
+use chrono::{DateTime, Utc};
+use nexus_types::external_api::params;
+use nexus_types::external_api::views;
+use uuid::Uuid;
+
+/// Describes the limits on virtual_provisioning_collection for a collection.
+///
+/// A limit of "None" means that the corresponding resource is unlimited.
+#[derive(Clone, Selectable, Queryable, Insertable, Debug)]
+#[diesel(table_name = virtual_provisioning_quota)]
+#[diesel(treat_none_as_default_value = true)]
+pub struct VirtualProvisioningQuota {
+    pub id: Uuid,
+    pub time_modified: Option<DateTime<Utc>>,
+    pub collection_type: String,
+
+    pub virtual_disk_bytes_limit: Option<ByteCount>,
+    pub cpus_limit: Option<i64>,
+    pub ram_limit: Option<ByteCount>,
+}
+
+impl VirtualProvisioningQuota {
+    pub fn new(
+        id: Uuid,
+        collection_type: CollectionTypeProvisioned,
+        params: params::ResourceQuotasUpdate,
+    ) -> Self {
+        Self {
+            id,
+            time_modified: None,
+            collection_type: collection_type.to_string(),
+            virtual_disk_bytes_limit: params.storage.map(ByteCount::from),
+            cpus_limit: params.cpus,
+            ram_limit: params.memory.map(ByteCount::from),
+        }
+    }
+
+    /// Returns the quota for a collection which has no limits set.
+    pub fn unlimited(
+        id: Uuid,
+        collection_type: CollectionTypeProvisioned,
+    ) -> Self {
+        Self::new(id, collection_type, params::ResourceQuotasUpdate::default())
+    }
+}
+
+impl From<VirtualProvisioningQuota> for views::ResourceQuotas {
+    fn from(quota: VirtualProvisioningQuota) -> Self {
+        Self {
+            cpus: quota.cpus_limit,
+            memory: quota.ram_limit.map(|b| b.0),
+            storage: quota.virtual_disk_bytes_limit.map(|b| b.0),
+        }
+    }
+}
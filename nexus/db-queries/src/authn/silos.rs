@@ -10,9 +10,18 @@ use crate::db::lookup::LookupPath;
 use crate::db::{model, DataStore};
 use omicron_common::api::external::LookupResult;
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use base64::Engine;
+use chrono::{DateTime, Utc};
 use dropshot::HttpError;
+use openssl::bn::BigNum;
+use openssl::ec::{EcGroup, EcKey};
+use openssl::ecdsa::EcdsaSig;
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Public};
+use openssl::rsa::Rsa;
+use openssl::sign::Verifier;
 use samael::metadata::ContactPerson;
 use samael::metadata::ContactType;
 use samael::metadata::EntityDescriptor;
@@ -64,8 +73,54 @@ impl TryFrom<model::SamlIdentityProvider> for SamlIdentityProvider {
     }
 }
 
+#[derive(Deserialize)]
+pub struct OidcIdentityProvider {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub redirect_uri: String,
+    pub scopes: String,
+    pub group_claim_name: Option<String>,
+}
+
+impl TryFrom<model::OidcIdentityProvider> for OidcIdentityProvider {
+    type Error = anyhow::Error;
+    fn try_from(
+        model: model::OidcIdentityProvider,
+    ) -> Result<Self, Self::Error> {
+        let provider = OidcIdentityProvider {
+            issuer: model.issuer,
+            authorization_endpoint: model.authorization_endpoint,
+            token_endpoint: model.token_endpoint,
+            jwks_uri: model.jwks_uri,
+            client_id: model.client_id,
+            client_secret: model.client_secret,
+            redirect_uri: model.redirect_uri,
+            scopes: model.scopes,
+            group_claim_name: model.group_claim_name,
+        };
+
+        // check that each of the endpoints is a valid url
+        for (label, url) in [
+            ("authorization_endpoint", &provider.authorization_endpoint),
+            ("token_endpoint", &provider.token_endpoint),
+            ("jwks_uri", &provider.jwks_uri),
+            ("redirect_uri", &provider.redirect_uri),
+        ] {
+            reqwest::Url::parse(url)
+                .with_context(|| format!("{} is not a valid url", label))?;
+        }
+
+        Ok(provider)
+    }
+}
+
 pub enum IdentityProviderType {
     Saml(SamlIdentityProvider),
+    Oidc(OidcIdentityProvider),
 }
 
 impl IdentityProviderType {
@@ -115,6 +170,32 @@ impl IdentityProviderType {
 
                 Ok((authz_silo, db_silo, saml_identity_provider))
             }
+
+            model::IdentityProviderType::Oidc => {
+                let (.., oidc_identity_provider) =
+                    LookupPath::new(opctx, datastore)
+                        .silo_name(silo_name)
+                        .oidc_identity_provider_name(provider_name)
+                        .fetch()
+                        .await?;
+
+                let oidc_identity_provider = IdentityProviderType::Oidc(
+                    oidc_identity_provider.try_into().map_err(
+                        |e: anyhow::Error| {
+                            // As above, the provider was validated before it
+                            // went into the DB.
+                            omicron_common::api::external::Error::internal_error(
+                                &format!(
+                                    "oidc_identity_provider.try_into() failed! {}",
+                                    &e.to_string()
+                                ),
+                            )
+                        },
+                    )?,
+                );
+
+                Ok((authz_silo, db_silo, oidc_identity_provider))
+            }
         }
     }
 }
@@ -445,6 +526,376 @@ pub struct SamlLoginPost {
     pub relay_state: Option<String>,
 }
 
+/// Leeway allowed for clock skew between Nexus and an OpenID provider when
+/// checking the expiration time of an ID token
+const OIDC_CLOCK_SKEW_LEEWAY_SECONDS: i64 = 60;
+
+impl OidcIdentityProvider {
+    /// Returns the URL of the provider's authorization endpoint to which a user
+    /// should be sent to begin the authorization code flow.
+    ///
+    /// `state` and `nonce` must be unguessable values that are checked when
+    /// the user returns, and `code_challenge` is the S256 PKCE challenge
+    /// derived from the code verifier that will be used to redeem the code.
+    pub fn authorization_url(
+        &self,
+        state: &str,
+        nonce: &str,
+        code_challenge: &str,
+    ) -> Result<String> {
+        let scope = std::iter::once("openid")
+            .chain(
+                self.scopes
+                    .split_whitespace()
+                    .filter(|scope| *scope != "openid"),
+            )
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let url = reqwest::Url::parse_with_params(
+            &self.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", &self.client_id),
+                ("redirect_uri", &self.redirect_uri),
+                ("scope", &scope),
+                ("state", state),
+                ("nonce", nonce),
+                ("code_challenge", code_challenge),
+                ("code_challenge_method", "S256"),
+            ],
+        )?;
+
+        Ok(url.to_string())
+    }
+
+    /// Redeem an authorization code at the provider's token endpoint,
+    /// returning the ID token that was issued.
+    pub async fn exchange_code(
+        &self,
+        client: &reqwest::Client,
+        code: &str,
+        code_verifier: &str,
+    ) -> Result<String> {
+        let response = client
+            .post(&self.token_endpoint)
+            .basic_auth(&self.client_id, Some(&self.client_secret))
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", &self.redirect_uri),
+                ("code_verifier", code_verifier),
+            ])
+            .send()
+            .await
+            .context("error querying token endpoint")?;
+
+        if !response.status().is_success() {
+            bail!("token endpoint returned: {}", response.status());
+        }
+
+        let token_response: OidcTokenResponse = response
+            .json()
+            .await
+            .context("error parsing token endpoint response")?;
+
+        Ok(token_response.id_token)
+    }
+
+    /// Fetch the provider's current signing keys.
+    pub async fn fetch_jwks(
+        &self,
+        client: &reqwest::Client,
+    ) -> Result<JsonWebKeySet> {
+        let response = client
+            .get(&self.jwks_uri)
+            .send()
+            .await
+            .context("error querying jwks uri")?;
+
+        if !response.status().is_success() {
+            bail!("jwks uri returned: {}", response.status());
+        }
+
+        response.json().await.context("error parsing json web key set")
+    }
+
+    /// Validate an ID token issued by this provider, and extract the subject
+    /// it authenticates.
+    pub fn authenticated_subject(
+        &self,
+        id_token: &str,
+        jwks: &JsonWebKeySet,
+        nonce: &str,
+        now: DateTime<Utc>,
+    ) -> Result<AuthenticatedSubject, HttpError> {
+        let claims = self
+            .validate_id_token(id_token, jwks, nonce, now)
+            .map_err(|e| {
+                HttpError::for_bad_request(
+                    None,
+                    format!("could not validate ID token! {:#}", e),
+                )
+            })?;
+
+        // Extract group membership claims
+        let mut groups = vec![];
+
+        if let Some(group_claim_name) = &self.group_claim_name {
+            let values = match claims.other.get(group_claim_name) {
+                // Read a list of group names
+                Some(serde_json::Value::Array(values)) => values
+                    .iter()
+                    .filter_map(|value| value.as_str())
+                    .map(String::from)
+                    .collect(),
+
+                // Read comma separated group names
+                Some(serde_json::Value::String(value)) => {
+                    value.split(',').map(String::from).collect()
+                }
+
+                _ => vec![],
+            };
+
+            for group in values {
+                // Trim whitespace
+                let group = group.trim().to_string();
+
+                // Skip empty groups
+                if group.is_empty() {
+                    continue;
+                }
+
+                groups.push(group);
+            }
+        }
+
+        Ok(AuthenticatedSubject { external_id: claims.sub, groups })
+    }
+
+    fn validate_id_token(
+        &self,
+        id_token: &str,
+        jwks: &JsonWebKeySet,
+        nonce: &str,
+        now: DateTime<Utc>,
+    ) -> Result<OidcIdTokenClaims> {
+        // The signature covers the "header.payload" prefix of the token.
+        let (signing_input, signature) = id_token
+            .rsplit_once('.')
+            .ok_or_else(|| anyhow!("ID token is not a signed JWT"))?;
+        let (header, payload) = signing_input
+            .split_once('.')
+            .ok_or_else(|| anyhow!("ID token is not a signed JWT"))?;
+
+        let decode = |part: &str| {
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(part)
+        };
+        let header: JwtHeader = serde_json::from_slice(
+            &decode(header).context("error base64 decoding JWT header")?,
+        )
+        .context("error parsing JWT header")?;
+        let signature =
+            decode(signature).context("error base64 decoding JWT signature")?;
+
+        // Only accept asymmetric signature algorithms. In particular, this
+        // rejects "none", and the HMAC algorithms that would let anyone who
+        // knows the client secret mint tokens.
+        let (kty, digest) = match header.alg.as_str() {
+            "RS256" => ("RSA", MessageDigest::sha256()),
+            "RS384" => ("RSA", MessageDigest::sha384()),
+            "RS512" => ("RSA", MessageDigest::sha512()),
+            "ES256" => ("EC", MessageDigest::sha256()),
+            "ES384" => ("EC", MessageDigest::sha384()),
+            alg => bail!("signature algorithm {} is not allowed", alg),
+        };
+
+        // Find the key that signed the token. If the token does not name its
+        // key, try each of the provider's keys of the right type.
+        let candidates = jwks
+            .keys
+            .iter()
+            .filter(|key| key.kty == kty)
+            .filter(|key| key.key_use.as_deref().unwrap_or("sig") == "sig")
+            .filter(|key| match (&header.kid, &key.kid) {
+                (Some(kid), Some(key_kid)) => kid == key_kid,
+                (Some(_), None) => false,
+                (None, _) => true,
+            })
+            .collect::<Vec<_>>();
+        if candidates.is_empty() {
+            bail!("no matching signing key found in provider's key set");
+        }
+
+        let signature = if kty == "EC" {
+            ecdsa_signature_to_der(&signature)?
+        } else {
+            signature
+        };
+        let mut verified = false;
+        for key in candidates {
+            let public_key = key.public_key()?;
+            let mut verifier = Verifier::new(digest, &public_key)?;
+            verifier.update(signing_input.as_bytes())?;
+            if verifier.verify(&signature).unwrap_or(false) {
+                verified = true;
+                break;
+            }
+        }
+        if !verified {
+            bail!("signature validation failed!");
+        }
+
+        // Only once the signature is known to be good, check the claims.
+        let claims: OidcIdTokenClaims = serde_json::from_slice(
+            &decode(payload).context("error base64 decoding JWT payload")?,
+        )
+        .context("error parsing ID token claims")?;
+
+        if claims.iss != self.issuer {
+            bail!(
+                "ID token issuer {} does not match configured issuer {}",
+                claims.iss,
+                self.issuer,
+            );
+        }
+
+        let audiences = match &claims.aud {
+            OidcAudience::One(aud) => vec![aud.as_str()],
+            OidcAudience::Many(auds) => {
+                auds.iter().map(|aud| aud.as_str()).collect()
+            }
+        };
+        if !audiences.contains(&self.client_id.as_str()) {
+            bail!("ID token was not issued to this client");
+        }
+        if audiences.len() > 1
+            && claims.azp.as_deref() != Some(self.client_id.as_str())
+        {
+            bail!("ID token was not issued to this client");
+        }
+
+        if claims.exp + OIDC_CLOCK_SKEW_LEEWAY_SECONDS < now.timestamp() {
+            bail!("ID token has expired");
+        }
+
+        if claims.nonce.as_deref() != Some(nonce) {
+            bail!("ID token nonce does not match");
+        }
+
+        Ok(claims)
+    }
+}
+
+/// JWS encodes ECDSA signatures as the concatenation of the fixed-width `r`
+/// and `s` values, but openssl expects them DER encoded.
+fn ecdsa_signature_to_der(signature: &[u8]) -> Result<Vec<u8>> {
+    if signature.is_empty() || signature.len() % 2 != 0 {
+        bail!("malformed ECDSA signature");
+    }
+    let (r, s) = signature.split_at(signature.len() / 2);
+    let signature = EcdsaSig::from_private_components(
+        BigNum::from_slice(r)?,
+        BigNum::from_slice(s)?,
+    )?;
+    Ok(signature.to_der()?)
+}
+
+/// A set of public keys, as published by an OpenID provider at its `jwks_uri`
+/// (RFC 7517)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JsonWebKeySet {
+    pub keys: Vec<JsonWebKey>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JsonWebKey {
+    pub kty: String,
+    pub kid: Option<String>,
+    #[serde(rename = "use")]
+    pub key_use: Option<String>,
+
+    // RSA public key parameters
+    pub n: Option<String>,
+    pub e: Option<String>,
+
+    // EC public key parameters
+    pub crv: Option<String>,
+    pub x: Option<String>,
+    pub y: Option<String>,
+}
+
+impl JsonWebKey {
+    fn public_key(&self) -> Result<PKey<Public>> {
+        let param = |name: &str, value: &Option<String>| -> Result<BigNum> {
+            let value = value
+                .as_ref()
+                .ok_or_else(|| anyhow!("key is missing parameter {}", name))?;
+            let bytes = base64::engine::general_purpose::URL_SAFE_NO_PAD
+                .decode(value)
+                .with_context(|| format!("error base64 decoding {}", name))?;
+            Ok(BigNum::from_slice(&bytes)?)
+        };
+
+        match self.kty.as_str() {
+            "RSA" => {
+                let rsa = Rsa::from_public_components(
+                    param("n", &self.n)?,
+                    param("e", &self.e)?,
+                )?;
+                Ok(PKey::from_rsa(rsa)?)
+            }
+            "EC" => {
+                let nid = match self.crv.as_deref() {
+                    Some("P-256") => Nid::X9_62_PRIME256V1,
+                    Some("P-384") => Nid::SECP384R1,
+                    crv => bail!("unsupported curve {:?}", crv),
+                };
+                let group = EcGroup::from_curve_name(nid)?;
+                let ec = EcKey::from_public_key_affine_coordinates(
+                    &group,
+                    &param("x", &self.x)?,
+                    &param("y", &self.y)?,
+                )?;
+                Ok(PKey::from_ec_key(ec)?)
+            }
+            kty => bail!("unsupported key type {}", kty),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct JwtHeader {
+    alg: String,
+    kid: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OidcTokenResponse {
+    id_token: String,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OidcAudience {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Deserialize)]
+struct OidcIdTokenClaims {
+    iss: String,
+    sub: String,
+    aud: OidcAudience,
+    exp: i64,
+    azp: Option<String>,
+    nonce: Option<String>,
+
+    #[serde(flatten)]
+    other: serde_json::Map<String, serde_json::Value>,
+}
+
 pub struct AuthenticatedSubject {
     pub external_id: String,
     pub groups: Vec<String>,
@@ -102,6 +102,7 @@ pub fn make_omicron_oso(log: &slog::Logger) -> Result<OsoInit, anyhow::Error> {
         // Hand-written classes
         Action::get_polar_class(),
         AnyActor::get_polar_class(),
+        AuditLog::get_polar_class(),
         AuthenticatedActor::get_polar_class(),
         Database::get_polar_class(),
         DnsConfig::get_polar_class(),
@@ -122,6 +123,8 @@ pub fn make_omicron_oso(log: &slog::Logger) -> Result<OsoInit, anyhow::Error> {
         Project::init(),
         Disk::init(),
         Snapshot::init(),
+        FloatingIp::init(),
+        AffinityGroup::init(),
         ProjectImage::init(),
         Instance::init(),
         IpPool::init(),
@@ -149,6 +152,7 @@ pub fn make_omicron_oso(log: &slog::Logger) -> Result<OsoInit, anyhow::Error> {
         SiloGroup::init(),
         IdentityProvider::init(),
         SamlIdentityProvider::init(),
+        OidcIdentityProvider::init(),
         Sled::init(),
         Zpool::init(),
         Service::init(),
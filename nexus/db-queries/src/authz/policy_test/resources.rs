@@ -68,6 +68,7 @@ pub async fn make_resources(
     builder.new_resource(authz::DNS_CONFIG);
     builder.new_resource(authz::DEVICE_AUTH_REQUEST_LIST);
     builder.new_resource(authz::IP_POOL_LIST);
+    builder.new_resource(authz::AUDIT_LOG);
 
     // Silo/organization/project hierarchy
     make_silo(&mut builder, "silo1", main_silo_id, true).await;
@@ -209,6 +210,11 @@ async fn make_silo(
         idp_id,
         LookupType::ByName(format!("{}-saml-identity-provider", silo_name)),
     ));
+    builder.new_resource(authz::OidcIdentityProvider::new(
+        silo.clone(),
+        idp_id,
+        LookupType::ByName(format!("{}-oidc-identity-provider", silo_name)),
+    ));
 
     builder.new_resource(authz::SiloUserList::new(silo.clone()));
     let silo_user_id = Uuid::new_v4();
@@ -318,6 +324,20 @@ async fn make_project(
         Uuid::new_v4(),
         LookupType::ByName(image_name),
     ));
+
+    let fip_name = format!("{}-fip1", project_name);
+    builder.new_resource(authz::FloatingIp::new(
+        project.clone(),
+        Uuid::new_v4(),
+        LookupType::ByName(fip_name),
+    ));
+
+    let affinity_group_name = format!("{}-affinity-group1", project_name);
+    builder.new_resource(authz::AffinityGroup::new(
+        project.clone(),
+        Uuid::new_v4(),
+        LookupType::ByName(affinity_group_name),
+    ));
 }
 
 /// Returns the set of authz classes exempted from the coverage test
@@ -0,0 +1,656 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! [`DataStore`] methods on [`AffinityGroup`]s.
+
+use super::DataStore;
+use crate::authz;
+use crate::context::OpContext;
+use crate::db;
+use crate::db::collection_insert::AsyncInsertError;
+use crate::db::collection_insert::DatastoreCollection;
+use crate::db::error::public_error_from_diesel_pool;
+use crate::db::error::ErrorHandler;
+use crate::db::error::TransactionError;
+use crate::db::identity::Resource;
+use crate::db::model::AffinityGroup;
+use crate::db::model::AffinityGroupInstanceMembership;
+use crate::db::model::AffinityGroupKind;
+use crate::db::model::AffinityPolicy;
+use crate::db::model::Instance;
+use crate::db::model::InstanceState;
+use crate::db::model::Name;
+use crate::db::model::Project;
+use crate::db::pagination::paginated;
+use crate::db::pool::DbConnection;
+use async_bb8_diesel::AsyncConnection;
+use async_bb8_diesel::AsyncRunQueryDsl;
+use chrono::Utc;
+use diesel::prelude::*;
+use omicron_common::api::external;
+use omicron_common::api::external::http_pagination::PaginatedBy;
+use omicron_common::api::external::CreateResult;
+use omicron_common::api::external::DeleteResult;
+use omicron_common::api::external::Error;
+use omicron_common::api::external::ListResultVec;
+use omicron_common::api::external::LookupType;
+use omicron_common::api::external::ResourceType;
+use omicron_common::api::external::UpdateResult;
+use ref_cast::RefCast;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use uuid::Uuid;
+
+/// Returns the states in which an instance doesn't occupy a sled, and so
+/// doesn't constrain the placement of the other members of its groups.
+fn instance_states_without_sled() -> Vec<InstanceState> {
+    vec![
+        InstanceState::new(external::InstanceState::Stopped),
+        InstanceState::new(external::InstanceState::Failed),
+        InstanceState::new(external::InstanceState::Destroyed),
+    ]
+}
+
+/// The placement rule of an affinity group, along with the sleds occupied by
+/// the group's members.
+#[derive(Debug)]
+pub(super) struct AffinityRule {
+    pub group: AffinityGroup,
+    pub member_sleds: BTreeSet<Uuid>,
+}
+
+impl AffinityRule {
+    /// Returns true if placing an instance on `sled_id` satisfies this rule.
+    pub fn is_satisfied_by(&self, sled_id: Uuid) -> bool {
+        match self.group.kind {
+            AffinityGroupKind::Affinity => {
+                self.member_sleds.is_empty()
+                    || self.member_sleds.contains(&sled_id)
+            }
+            AffinityGroupKind::AntiAffinity => {
+                !self.member_sleds.contains(&sled_id)
+            }
+        }
+    }
+
+    /// Returns a description of this rule suitable for error messages.
+    pub fn describe(&self) -> String {
+        let kind = match self.group.kind {
+            AffinityGroupKind::Affinity => "affinity",
+            AffinityGroupKind::AntiAffinity => "anti-affinity",
+        };
+        format!("{} group \"{}\"", kind, self.group.name())
+    }
+}
+
+/// Chooses a sled from `candidates`, which are in order of preference,
+/// according to `rules`.
+///
+/// Rules with the "fail" policy remove candidates that don't satisfy them; if
+/// that leaves no candidates, the first such rule to do so is returned as the
+/// error. Among the remaining candidates, the first one satisfying the most
+/// rules with the "allow" policy is chosen.
+pub(super) fn select_sled<'a>(
+    candidates: &[Uuid],
+    rules: &'a [AffinityRule],
+) -> Result<Uuid, &'a AffinityRule> {
+    let mut candidates = candidates.to_vec();
+    for rule in rules.iter().filter(|r| r.group.policy == AffinityPolicy::Fail)
+    {
+        candidates.retain(|sled_id| rule.is_satisfied_by(*sled_id));
+        if candidates.is_empty() {
+            return Err(rule);
+        }
+    }
+
+    let preferences_satisfied = |sled_id: Uuid| {
+        rules
+            .iter()
+            .filter(|r| r.group.policy == AffinityPolicy::Allow)
+            .filter(|r| r.is_satisfied_by(sled_id))
+            .count()
+    };
+    let mut best = candidates[0];
+    let mut best_count = preferences_satisfied(best);
+    for sled_id in candidates.into_iter().skip(1) {
+        let count = preferences_satisfied(sled_id);
+        if count > best_count {
+            best = sled_id;
+            best_count = count;
+        }
+    }
+    Ok(best)
+}
+
+impl DataStore {
+    pub async fn affinity_group_list(
+        &self,
+        opctx: &OpContext,
+        authz_project: &authz::Project,
+        pagparams: &PaginatedBy<'_>,
+    ) -> ListResultVec<AffinityGroup> {
+        opctx.authorize(authz::Action::ListChildren, authz_project).await?;
+
+        use db::schema::affinity_group::dsl;
+        match pagparams {
+            PaginatedBy::Id(pagparams) => {
+                paginated(dsl::affinity_group, dsl::id, &pagparams)
+            }
+            PaginatedBy::Name(pagparams) => paginated(
+                dsl::affinity_group,
+                dsl::name,
+                &pagparams.map_name(|n| Name::ref_cast(n)),
+            ),
+        }
+        .filter(dsl::project_id.eq(authz_project.id()))
+        .filter(dsl::time_deleted.is_null())
+        .select(AffinityGroup::as_select())
+        .load_async(self.pool_authorized(opctx).await?)
+        .await
+        .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    pub async fn affinity_group_create(
+        &self,
+        opctx: &OpContext,
+        authz_project: &authz::Project,
+        group: AffinityGroup,
+    ) -> CreateResult<AffinityGroup> {
+        use db::schema::affinity_group::dsl;
+
+        assert_eq!(authz_project.id(), group.project_id);
+        opctx.authorize(authz::Action::CreateChild, authz_project).await?;
+
+        let name = group.name().clone();
+        let project_id = group.project_id;
+        Project::insert_resource(
+            project_id,
+            diesel::insert_into(dsl::affinity_group).values(group),
+        )
+        .insert_and_get_result_async(self.pool_authorized(opctx).await?)
+        .await
+        .map_err(|e| match e {
+            AsyncInsertError::CollectionNotFound => Error::ObjectNotFound {
+                type_name: ResourceType::Project,
+                lookup_type: LookupType::ById(project_id),
+            },
+            AsyncInsertError::DatabaseError(e) => {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::Conflict(
+                        ResourceType::AffinityGroup,
+                        name.as_str(),
+                    ),
+                )
+            }
+        })
+    }
+
+    /// Deletes an affinity group, along with the record of its members.
+    pub async fn affinity_group_delete(
+        &self,
+        opctx: &OpContext,
+        authz_group: &authz::AffinityGroup,
+    ) -> DeleteResult {
+        opctx.authorize(authz::Action::Delete, authz_group).await?;
+
+        use db::schema::affinity_group::dsl;
+        use db::schema::affinity_group_instance_membership::dsl as membership_dsl;
+        let conn = self.pool_authorized(opctx).await?;
+        diesel::update(dsl::affinity_group)
+            .filter(dsl::id.eq(authz_group.id()))
+            .filter(dsl::time_deleted.is_null())
+            .set(dsl::time_deleted.eq(Utc::now()))
+            .execute_async(conn)
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByResource(authz_group),
+                )
+            })?;
+
+        // Memberships of a deleted group have no effect on placement, so it's
+        // fine for them to briefly outlive the group.
+        diesel::delete(membership_dsl::affinity_group_instance_membership)
+            .filter(membership_dsl::group_id.eq(authz_group.id()))
+            .execute_async(conn)
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(e, ErrorHandler::Server)
+            })?;
+        Ok(())
+    }
+
+    /// Lists the instances that are members of an affinity group.
+    pub async fn affinity_group_member_list(
+        &self,
+        opctx: &OpContext,
+        authz_group: &authz::AffinityGroup,
+        pagparams: &PaginatedBy<'_>,
+    ) -> ListResultVec<Instance> {
+        opctx.authorize(authz::Action::ListChildren, authz_group).await?;
+
+        use db::schema::affinity_group_instance_membership::dsl as membership_dsl;
+        use db::schema::instance::dsl;
+        match pagparams {
+            PaginatedBy::Id(pagparams) => {
+                paginated(dsl::instance, dsl::id, &pagparams)
+            }
+            PaginatedBy::Name(pagparams) => paginated(
+                dsl::instance,
+                dsl::name,
+                &pagparams.map_name(|n| Name::ref_cast(n)),
+            ),
+        }
+        .filter(
+            dsl::id.eq_any(
+                membership_dsl::affinity_group_instance_membership
+                    .filter(membership_dsl::group_id.eq(authz_group.id()))
+                    .select(membership_dsl::instance_id),
+            ),
+        )
+        .filter(dsl::time_deleted.is_null())
+        .select(Instance::as_select())
+        .load_async(self.pool_authorized(opctx).await?)
+        .await
+        .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    /// Adds an instance to an affinity group.
+    ///
+    /// If the group's policy is "fail" and the instance occupies a sled, that
+    /// sled must satisfy the group's rule; a stopped instance is instead
+    /// checked when it starts. Adding an instance that is already a member
+    /// succeeds without changing anything.
+    pub async fn affinity_group_member_add(
+        &self,
+        opctx: &OpContext,
+        authz_group: &authz::AffinityGroup,
+        authz_instance: &authz::Instance,
+    ) -> UpdateResult<()> {
+        opctx.authorize(authz::Action::Modify, authz_group).await?;
+        opctx.authorize(authz::Action::Modify, authz_instance).await?;
+
+        #[derive(Debug)]
+        enum MemberAddError {
+            RuleViolated(String),
+        }
+        type TxnError = TransactionError<MemberAddError>;
+
+        let group_id = authz_group.id();
+        let instance_id = authz_instance.id();
+        self.pool_authorized(opctx)
+            .await?
+            .transaction_async(|conn| async move {
+                use db::schema::affinity_group_instance_membership::dsl;
+                use db::schema::instance::dsl as instance_dsl;
+
+                let (instance_sled, instance_state) = instance_dsl::instance
+                    .filter(instance_dsl::id.eq(instance_id))
+                    .filter(instance_dsl::time_deleted.is_null())
+                    .select((instance_dsl::active_sled_id, instance_dsl::state))
+                    .get_result_async::<(Uuid, InstanceState)>(&conn)
+                    .await?;
+                let rules = Self::affinity_rules_on_connection(
+                    &conn,
+                    Some(instance_id),
+                    &[group_id],
+                )
+                .await?;
+                if !instance_states_without_sled().contains(&instance_state) {
+                    for rule in rules.iter().filter(|r| {
+                        r.group.id() == group_id
+                            && r.group.policy == AffinityPolicy::Fail
+                    }) {
+                        if !rule.is_satisfied_by(instance_sled) {
+                            return Err(TxnError::CustomError(
+                                MemberAddError::RuleViolated(rule.describe()),
+                            ));
+                        }
+                    }
+                }
+
+                diesel::insert_into(dsl::affinity_group_instance_membership)
+                    .values(AffinityGroupInstanceMembership::new(
+                        group_id,
+                        instance_id,
+                    ))
+                    .on_conflict((dsl::group_id, dsl::instance_id))
+                    .do_nothing()
+                    .execute_async(&conn)
+                    .await?;
+                Ok(())
+            })
+            .await
+            .map_err(|e| match e {
+                TxnError::CustomError(MemberAddError::RuleViolated(rule)) => {
+                    Error::invalid_request(&format!(
+                        "instance's current sled does not satisfy the rule of \
+                        {}",
+                        rule
+                    ))
+                }
+                TxnError::Pool(e) => public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByResource(authz_instance),
+                ),
+            })
+    }
+
+    /// Removes an instance from an affinity group.
+    pub async fn affinity_group_member_remove(
+        &self,
+        opctx: &OpContext,
+        authz_group: &authz::AffinityGroup,
+        authz_instance: &authz::Instance,
+    ) -> DeleteResult {
+        opctx.authorize(authz::Action::Modify, authz_group).await?;
+
+        use db::schema::affinity_group_instance_membership::dsl;
+        let deleted = diesel::delete(dsl::affinity_group_instance_membership)
+            .filter(dsl::group_id.eq(authz_group.id()))
+            .filter(dsl::instance_id.eq(authz_instance.id()))
+            .execute_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(e, ErrorHandler::Server)
+            })?;
+        if deleted == 0 {
+            return Err(Error::invalid_request(
+                "instance is not a member of the affinity group",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Records that a newly-created instance is a member of the given
+    /// affinity groups, which must be in the instance's project, then checks
+    /// that the sled it was placed on still satisfies their hard rules.
+    ///
+    /// The sled is chosen before the instance record exists, so a concurrent
+    /// placement in the same group can't see it at that point; this check
+    /// catches such placements, and the caller must give up the sled if it
+    /// fails.
+    pub async fn instance_affinity_groups_join(
+        &self,
+        opctx: &OpContext,
+        authz_instance: &authz::Instance,
+        group_ids: &[Uuid],
+    ) -> CreateResult<()> {
+        opctx.authorize(authz::Action::Modify, authz_instance).await?;
+        if group_ids.is_empty() {
+            return Ok(());
+        }
+
+        use db::schema::affinity_group_instance_membership::dsl;
+        let memberships: Vec<_> = group_ids
+            .iter()
+            .map(|group_id| {
+                AffinityGroupInstanceMembership::new(
+                    *group_id,
+                    authz_instance.id(),
+                )
+            })
+            .collect();
+        diesel::insert_into(dsl::affinity_group_instance_membership)
+            .values(memberships)
+            .on_conflict((dsl::group_id, dsl::instance_id))
+            .do_nothing()
+            .execute_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(e, ErrorHandler::Server)
+            })?;
+
+        // This must happen after the memberships above are visible to other
+        // placements, so that of two conflicting placements, at least the
+        // later one to get here sees the other.
+        self.instance_affinity_rules_check(opctx, authz_instance).await
+    }
+
+    /// Checks that the sled an instance occupies satisfies the hard rules of
+    /// all of its affinity groups, returning a conflict error if it doesn't.
+    ///
+    /// Callers use this after an instance starts occupying a sled without
+    /// going through [`DataStore::sled_reservation_create`], e.g. when a
+    /// stopped instance starts again on its previous sled. Since the rules are
+    /// loaded with the groups locked, two such checks for conflicting members
+    /// can't both succeed.
+    pub async fn instance_affinity_rules_check(
+        &self,
+        opctx: &OpContext,
+        authz_instance: &authz::Instance,
+    ) -> UpdateResult<()> {
+        opctx.authorize(authz::Action::Read, authz_instance).await?;
+
+        #[derive(Debug)]
+        enum RulesCheckError {
+            RuleViolated(String),
+        }
+        type TxnError = TransactionError<RulesCheckError>;
+
+        let instance_id = authz_instance.id();
+        self.pool_authorized(opctx)
+            .await?
+            .transaction_async(|conn| async move {
+                use db::schema::instance::dsl as instance_dsl;
+
+                let instance_sled = instance_dsl::instance
+                    .filter(instance_dsl::id.eq(instance_id))
+                    .filter(instance_dsl::time_deleted.is_null())
+                    .select(instance_dsl::active_sled_id)
+                    .get_result_async::<Uuid>(&conn)
+                    .await?;
+                let rules = Self::affinity_rules_on_connection(
+                    &conn,
+                    Some(instance_id),
+                    &[],
+                )
+                .await?;
+                match rules.iter().find(|r| {
+                    r.group.policy == AffinityPolicy::Fail
+                        && !r.is_satisfied_by(instance_sled)
+                }) {
+                    Some(rule) => Err(TxnError::CustomError(
+                        RulesCheckError::RuleViolated(rule.describe()),
+                    )),
+                    None => Ok(()),
+                }
+            })
+            .await
+            .map_err(|e| match e {
+                TxnError::CustomError(RulesCheckError::RuleViolated(rule)) => {
+                    Error::conflict(&format!(
+                        "instance's sled does not satisfy the rule of {}",
+                        rule
+                    ))
+                }
+                TxnError::Pool(e) => public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByResource(authz_instance),
+                ),
+            })
+    }
+
+    /// Removes an instance from all of the affinity groups it belongs to.
+    pub async fn instance_affinity_groups_leave(
+        &self,
+        opctx: &OpContext,
+        authz_instance: &authz::Instance,
+    ) -> DeleteResult {
+        opctx.authorize(authz::Action::Modify, authz_instance).await?;
+
+        use db::schema::affinity_group_instance_membership::dsl;
+        diesel::delete(dsl::affinity_group_instance_membership)
+            .filter(dsl::instance_id.eq(authz_instance.id()))
+            .execute_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(e, ErrorHandler::Server)
+            })?;
+        Ok(())
+    }
+
+    /// Loads the rules that apply to placing an instance on a sled: those of
+    /// the groups that `instance_id` is a member of, plus those of
+    /// `group_ids`.
+    ///
+    /// The sleds occupied by `instance_id` itself are not included in the
+    /// rules' member sleds, so that the instance doesn't constrain its own
+    /// placement, and neither are those of members that are stopped.
+    ///
+    /// The groups' rows are locked until the end of the enclosing transaction,
+    /// so placements that involve the same group are serialized.
+    pub(super) async fn affinity_rules_on_connection<ConnErr>(
+        conn: &(impl AsyncConnection<DbConnection, ConnErr> + Sync),
+        instance_id: Option<Uuid>,
+        group_ids: &[Uuid],
+    ) -> Result<Vec<AffinityRule>, ConnErr>
+    where
+        ConnErr: From<diesel::result::Error> + Send + 'static,
+    {
+        use db::schema::affinity_group::dsl as group_dsl;
+        use db::schema::affinity_group_instance_membership::dsl as membership_dsl;
+        use db::schema::instance::dsl as instance_dsl;
+
+        let mut group_ids: BTreeSet<Uuid> = group_ids.iter().copied().collect();
+        if let Some(instance_id) = instance_id {
+            group_ids.extend(
+                membership_dsl::affinity_group_instance_membership
+                    .filter(membership_dsl::instance_id.eq(instance_id))
+                    .select(membership_dsl::group_id)
+                    .load_async::<Uuid>(conn)
+                    .await?,
+            );
+        }
+        if group_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let group_ids: Vec<Uuid> = group_ids.into_iter().collect();
+
+        let groups = group_dsl::affinity_group
+            .filter(group_dsl::id.eq_any(group_ids.clone()))
+            .filter(group_dsl::time_deleted.is_null())
+            .select(AffinityGroup::as_select())
+            .for_update()
+            .load_async(conn)
+            .await?;
+
+        let mut query = membership_dsl::affinity_group_instance_membership
+            .inner_join(
+                instance_dsl::instance
+                    .on(instance_dsl::id.eq(membership_dsl::instance_id)),
+            )
+            .filter(membership_dsl::group_id.eq_any(group_ids))
+            .filter(instance_dsl::time_deleted.is_null())
+            .filter(instance_dsl::state.ne_all(instance_states_without_sled()))
+            .select((membership_dsl::group_id, instance_dsl::active_sled_id))
+            .into_boxed();
+        if let Some(instance_id) = instance_id {
+            query = query.filter(instance_dsl::id.ne(instance_id));
+        }
+        let mut member_sleds: BTreeMap<Uuid, BTreeSet<Uuid>> = BTreeMap::new();
+        for (group_id, sled_id) in
+            query.load_async::<(Uuid, Uuid)>(conn).await?
+        {
+            member_sleds.entry(group_id).or_default().insert(sled_id);
+        }
+
+        Ok(groups
+            .into_iter()
+            .map(|group| {
+                let member_sleds =
+                    member_sleds.remove(&group.id()).unwrap_or_default();
+                AffinityRule { group, member_sleds }
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::model::AffinityGroupIdentity;
+    use omicron_common::api::external::IdentityMetadataCreateParams;
+
+    fn rule(
+        kind: AffinityGroupKind,
+        policy: AffinityPolicy,
+        member_sleds: &[Uuid],
+    ) -> AffinityRule {
+        AffinityRule {
+            group: AffinityGroup {
+                identity: AffinityGroupIdentity::new(
+                    Uuid::new_v4(),
+                    IdentityMetadataCreateParams {
+                        name: "group".parse().unwrap(),
+                        description: String::new(),
+                    },
+                ),
+                project_id: Uuid::new_v4(),
+                kind,
+                policy,
+            },
+            member_sleds: member_sleds.iter().copied().collect(),
+        }
+    }
+
+    #[test]
+    fn test_select_sled() {
+        let sleds: Vec<Uuid> = (0..3).map(|_| Uuid::new_v4()).collect();
+
+        // With no rules, the first candidate is chosen.
+        assert_eq!(select_sled(&sleds, &[]).unwrap(), sleds[0]);
+
+        // Hard anti-affinity excludes the sleds of other members.
+        let rules = [rule(
+            AffinityGroupKind::AntiAffinity,
+            AffinityPolicy::Fail,
+            &sleds[..2],
+        )];
+        assert_eq!(select_sled(&sleds, &rules).unwrap(), sleds[2]);
+
+        // Hard affinity requires the sleds of other members, unless there
+        // are none.
+        let rules = [rule(
+            AffinityGroupKind::Affinity,
+            AffinityPolicy::Fail,
+            &sleds[1..2],
+        )];
+        assert_eq!(select_sled(&sleds, &rules).unwrap(), sleds[1]);
+        let rules =
+            [rule(AffinityGroupKind::Affinity, AffinityPolicy::Fail, &[])];
+        assert_eq!(select_sled(&sleds, &rules).unwrap(), sleds[0]);
+
+        // A hard rule that can't be satisfied is reported.
+        let rules = [
+            rule(AffinityGroupKind::AntiAffinity, AffinityPolicy::Fail, &[]),
+            rule(AffinityGroupKind::AntiAffinity, AffinityPolicy::Fail, &sleds),
+        ];
+        let err = select_sled(&sleds, &rules).unwrap_err();
+        assert_eq!(err.group.id(), rules[1].group.id());
+
+        // Soft rules are preferences, and never cause placement to fail.
+        let rules = [rule(
+            AffinityGroupKind::AntiAffinity,
+            AffinityPolicy::Allow,
+            &sleds,
+        )];
+        assert_eq!(select_sled(&sleds, &rules).unwrap(), sleds[0]);
+        let rules = [
+            rule(
+                AffinityGroupKind::AntiAffinity,
+                AffinityPolicy::Allow,
+                &sleds[..1],
+            ),
+            rule(
+                AffinityGroupKind::Affinity,
+                AffinityPolicy::Allow,
+                &sleds[2..],
+            ),
+        ];
+        assert_eq!(select_sled(&sleds, &rules).unwrap(), sleds[2]);
+    }
+}
@@ -0,0 +1,92 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! [`DataStore`] methods on the audit log.
+
+use super::DataStore;
+use crate::authz;
+use crate::context::OpContext;
+use crate::db;
+use crate::db::error::public_error_from_diesel_pool;
+use crate::db::error::ErrorHandler;
+use crate::db::model::AuditLogEntry;
+use crate::db::pagination::paginated_multicolumn;
+use async_bb8_diesel::AsyncRunQueryDsl;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use omicron_common::api::external::CreateResult;
+use omicron_common::api::external::DataPageParams;
+use omicron_common::api::external::ListResultVec;
+use omicron_common::api::external::UpdateResult;
+use uuid::Uuid;
+
+impl DataStore {
+    /// Record the start of a request in the audit log
+    pub async fn audit_log_entry_init(
+        &self,
+        opctx: &OpContext,
+        entry: AuditLogEntry,
+    ) -> CreateResult<AuditLogEntry> {
+        opctx.authorize(authz::Action::CreateChild, &authz::AUDIT_LOG).await?;
+
+        use db::schema::audit_log::dsl;
+        diesel::insert_into(dsl::audit_log)
+            .values(entry)
+            .returning(AuditLogEntry::as_returning())
+            .get_result_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    /// Record the result of a request whose start was recorded with
+    /// [`DataStore::audit_log_entry_init()`]
+    pub async fn audit_log_entry_complete(
+        &self,
+        opctx: &OpContext,
+        entry_id: Uuid,
+        http_status_code: Option<u16>,
+    ) -> UpdateResult<()> {
+        opctx.authorize(authz::Action::CreateChild, &authz::AUDIT_LOG).await?;
+
+        use db::schema::audit_log::dsl;
+        diesel::update(dsl::audit_log)
+            .filter(dsl::id.eq(entry_id))
+            .filter(dsl::time_completed.is_null())
+            .set((
+                dsl::time_completed.eq(Utc::now()),
+                dsl::http_status_code.eq(http_status_code.map(i32::from)),
+            ))
+            .execute_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(e, ErrorHandler::Server)
+            })?;
+        Ok(())
+    }
+
+    /// List the entries of the audit log for requests received within the
+    /// given time range, ordered by the time they were received
+    pub async fn audit_log_list(
+        &self,
+        opctx: &OpContext,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+        pagparams: &DataPageParams<'_, (DateTime<Utc>, Uuid)>,
+    ) -> ListResultVec<AuditLogEntry> {
+        opctx.authorize(authz::Action::ListChildren, &authz::AUDIT_LOG).await?;
+
+        use db::schema::audit_log::dsl;
+        paginated_multicolumn(
+            dsl::audit_log,
+            (dsl::time_started, dsl::id),
+            pagparams,
+        )
+        .filter(dsl::time_started.ge(start_time))
+        .filter(dsl::time_started.lt(end_time))
+        .select(AuditLogEntry::as_select())
+        .load_async(self.pool_authorized(opctx).await?)
+        .await
+        .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+}
@@ -13,11 +13,15 @@ use crate::db::error::ErrorHandler;
 use crate::db::error::TransactionError;
 use crate::db::model::DeviceAccessToken;
 use crate::db::model::DeviceAuthRequest;
+use crate::db::pagination::paginated;
 use async_bb8_diesel::AsyncConnection;
 use async_bb8_diesel::AsyncRunQueryDsl;
 use diesel::prelude::*;
 use omicron_common::api::external::CreateResult;
+use omicron_common::api::external::DataPageParams;
+use omicron_common::api::external::DeleteResult;
 use omicron_common::api::external::Error;
+use omicron_common::api::external::ListResultVec;
 use omicron_common::api::external::LookupResult;
 use omicron_common::api::external::LookupType;
 use omicron_common::api::external::ResourceType;
@@ -109,6 +113,75 @@ impl DataStore {
             })
     }
 
+    /// Create an access token for a user directly, outside of any device
+    /// authorization flow.
+    pub async fn access_token_create(
+        &self,
+        opctx: &OpContext,
+        authz_user: &authz::SiloUser,
+        access_token: DeviceAccessToken,
+    ) -> CreateResult<DeviceAccessToken> {
+        assert_eq!(authz_user.id(), access_token.silo_user_id);
+        opctx.authorize(authz::Action::CreateChild, authz_user).await?;
+
+        use db::schema::device_access_token::dsl;
+        diesel::insert_into(dsl::device_access_token)
+            .values(access_token)
+            .returning(DeviceAccessToken::as_returning())
+            .get_result_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    /// List the access tokens granted to a user, however they were obtained.
+    ///
+    /// Access tokens are credentials for the user, so listing them requires
+    /// permission to modify the user (not merely to read it, which everybody
+    /// in the user's Silo may do).
+    pub async fn access_tokens_list(
+        &self,
+        opctx: &OpContext,
+        authz_user: &authz::SiloUser,
+        pagparams: &DataPageParams<'_, Uuid>,
+    ) -> ListResultVec<DeviceAccessToken> {
+        opctx.authorize(authz::Action::Modify, authz_user).await?;
+
+        use db::schema::device_access_token::dsl;
+        paginated(dsl::device_access_token, dsl::id, pagparams)
+            .filter(dsl::silo_user_id.eq(authz_user.id()))
+            .select(DeviceAccessToken::as_select())
+            .load_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    /// Revoke one of a user's access tokens.
+    pub async fn access_token_delete(
+        &self,
+        opctx: &OpContext,
+        authz_user: &authz::SiloUser,
+        token_id: Uuid,
+    ) -> DeleteResult {
+        opctx.authorize(authz::Action::Modify, authz_user).await?;
+
+        use db::schema::device_access_token::dsl;
+        let deleted = diesel::delete(dsl::device_access_token)
+            .filter(dsl::silo_user_id.eq(authz_user.id()))
+            .filter(dsl::id.eq(token_id))
+            .execute_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(e, ErrorHandler::Server)
+            })?;
+        if deleted == 0 {
+            return Err(Error::ObjectNotFound {
+                type_name: ResourceType::DeviceAccessToken,
+                lookup_type: LookupType::ById(token_id),
+            });
+        }
+        Ok(())
+    }
+
     /// Look up a granted device access token.
     /// Note: since this lookup is not by a primary key or name,
     /// (though it does use a unique index), it does not fit the
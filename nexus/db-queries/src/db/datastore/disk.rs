@@ -17,11 +17,14 @@ use crate::db::collection_insert::AsyncInsertError;
 use crate::db::collection_insert::DatastoreCollection;
 use crate::db::error::public_error_from_diesel_pool;
 use crate::db::error::ErrorHandler;
+use crate::db::error::TransactionError;
 use crate::db::identity::Resource;
 use crate::db::lookup::LookupPath;
+use crate::db::model::ByteCount;
 use crate::db::model::Disk;
 use crate::db::model::DiskRuntimeState;
 use crate::db::model::DiskUpdate;
+use crate::db::model::Generation;
 use crate::db::model::Instance;
 use crate::db::model::Name;
 use crate::db::model::Project;
@@ -29,7 +32,9 @@ use crate::db::pagination::paginated;
 use crate::db::queries::disk::DiskSetClauseForAttach;
 use crate::db::update_and_check::UpdateAndCheck;
 use crate::db::update_and_check::UpdateStatus;
+use async_bb8_diesel::AsyncConnection;
 use async_bb8_diesel::AsyncRunQueryDsl;
+use async_bb8_diesel::PoolError;
 use chrono::Utc;
 use diesel::prelude::*;
 use omicron_common::api;
@@ -454,6 +459,222 @@ impl DataStore {
         Ok(updated)
     }
 
+    /// Moves a disk into the "maintenance" state so that it may be resized.
+    ///
+    /// Only disks which are detached, or which are attached to a stopped
+    /// instance, may be resized. A disk which is attached to an instance
+    /// remains attached while it is being resized, but the instance cannot be
+    /// started until the disk leaves the maintenance state.
+    ///
+    /// `db_disk` is the disk record observed by the caller. This fails if the
+    /// disk has changed since then, but succeeds if this operation has already
+    /// been applied to that record (for idempotency).
+    pub async fn disk_start_resize(
+        &self,
+        opctx: &OpContext,
+        authz_disk: &authz::Disk,
+        db_disk: &Disk,
+    ) -> UpdateResult<Disk> {
+        opctx.authorize(authz::Action::Modify, authz_disk).await?;
+
+        use api::external::DiskState;
+        use api::external::InstanceState;
+        use db::schema::disk::dsl;
+        use db::schema::instance::dsl as instance_dsl;
+
+        let expected_gen = db_disk.runtime().gen;
+        let maintenance_gen = Generation::from(expected_gen.next());
+
+        type TxnError = TransactionError<Error>;
+        self.pool_authorized(opctx)
+            .await?
+            .transaction_async(|conn| async move {
+                let disk = dsl::disk
+                    .filter(dsl::time_deleted.is_null())
+                    .filter(dsl::id.eq(authz_disk.id()))
+                    .select(Disk::as_select())
+                    .get_result_async(&conn)
+                    .await
+                    .map_err(|e| {
+                        public_error_from_diesel_pool(
+                            PoolError::from(e),
+                            ErrorHandler::NotFoundByResource(authz_disk),
+                        )
+                    })?;
+
+                let disk_state: DiskState = disk.state().into();
+                if disk.runtime().gen == maintenance_gen
+                    && disk_state == DiskState::Maintenance
+                {
+                    return Ok(disk);
+                }
+                if disk.runtime().gen != expected_gen {
+                    return Err(TxnError::CustomError(Error::conflict(
+                        "disk changed state before it could be resized",
+                    )));
+                }
+
+                match disk_state {
+                    DiskState::Detached => {}
+                    DiskState::Attached(instance_id) => {
+                        let instance = instance_dsl::instance
+                            .filter(instance_dsl::time_deleted.is_null())
+                            .filter(instance_dsl::id.eq(instance_id))
+                            .select(Instance::as_select())
+                            .get_result_async(&conn)
+                            .await?;
+                        let instance_state = instance.runtime().state.state();
+                        if *instance_state != InstanceState::Stopped {
+                            return Err(TxnError::CustomError(
+                                Error::conflict(&format!(
+                                    "disk is attached to an instance in state \
+                                    {} but the instance must be {} for the \
+                                    disk to be resized",
+                                    instance_state,
+                                    InstanceState::Stopped,
+                                )),
+                            ));
+                        }
+                    }
+                    _ => {
+                        return Err(TxnError::CustomError(Error::conflict(
+                            &format!(
+                                "disk is in state {} but must be {}, or \
+                                attached to a stopped instance, to be resized",
+                                disk_state,
+                                DiskState::Detached,
+                            ),
+                        )));
+                    }
+                }
+
+                let disk = diesel::update(dsl::disk)
+                    .filter(dsl::id.eq(authz_disk.id()))
+                    .filter(dsl::state_generation.eq(expected_gen))
+                    .set((
+                        dsl::disk_state.eq(DiskState::Maintenance.label()),
+                        dsl::state_generation.eq(maintenance_gen),
+                        dsl::time_state_updated.eq(Utc::now()),
+                    ))
+                    .returning(Disk::as_returning())
+                    .get_result_async(&conn)
+                    .await?;
+                Ok(disk)
+            })
+            .await
+            .map_err(|e| match e {
+                TxnError::CustomError(e) => e,
+                TxnError::Pool(e) => {
+                    public_error_from_diesel_pool(e, ErrorHandler::Server)
+                }
+            })
+    }
+
+    /// Moves a disk out of the "maintenance" state entered by
+    /// [`DataStore::disk_start_resize`], recording the volume and size which
+    /// now back the disk.
+    ///
+    /// An abandoned resize is undone by passing the disk's original volume and
+    /// size. A disk which was attached to an instance is reattached to it,
+    /// unless the instance was deleted in the meantime.
+    ///
+    /// `maintenance_gen` is the state generation returned by
+    /// `disk_start_resize`. This fails if the disk has changed since then, but
+    /// succeeds if this operation has already been applied (for idempotency),
+    /// even if the disk has changed again since it left the maintenance state.
+    pub async fn disk_finish_resize(
+        &self,
+        opctx: &OpContext,
+        authz_disk: &authz::Disk,
+        maintenance_gen: Generation,
+        volume_id: Uuid,
+        size: ByteCount,
+    ) -> UpdateResult<Disk> {
+        opctx.authorize(authz::Action::Modify, authz_disk).await?;
+
+        use api::external::DiskState;
+        use db::schema::disk::dsl;
+        use db::schema::instance::dsl as instance_dsl;
+
+        let finished_gen = Generation::from(maintenance_gen.next());
+
+        type TxnError = TransactionError<Error>;
+        self.pool_authorized(opctx)
+            .await?
+            .transaction_async(|conn| async move {
+                let disk = dsl::disk
+                    .filter(dsl::time_deleted.is_null())
+                    .filter(dsl::id.eq(authz_disk.id()))
+                    .select(Disk::as_select())
+                    .get_result_async(&conn)
+                    .await
+                    .map_err(|e| {
+                        public_error_from_diesel_pool(
+                            PoolError::from(e),
+                            ErrorHandler::NotFoundByResource(authz_disk),
+                        )
+                    })?;
+
+                let disk_state: DiskState = disk.state().into();
+                if disk.runtime().gen >= finished_gen
+                    && disk_state != DiskState::Maintenance
+                    && disk.volume_id == volume_id
+                {
+                    return Ok(disk);
+                }
+                if disk.runtime().gen != maintenance_gen
+                    || disk_state != DiskState::Maintenance
+                {
+                    return Err(TxnError::CustomError(Error::conflict(
+                        "disk changed state while being resized",
+                    )));
+                }
+
+                let attach_instance_id = match disk.runtime().attach_instance_id
+                {
+                    Some(instance_id) => instance_dsl::instance
+                        .filter(instance_dsl::time_deleted.is_null())
+                        .filter(instance_dsl::id.eq(instance_id))
+                        .select(instance_dsl::id)
+                        .load_async::<Uuid>(&conn)
+                        .await?
+                        .into_iter()
+                        .next(),
+                    None => None,
+                };
+                let (disk_state, slot) = match attach_instance_id {
+                    Some(instance_id) => {
+                        (DiskState::Attached(instance_id), disk.slot)
+                    }
+                    None => (DiskState::Detached, None),
+                };
+
+                let disk = diesel::update(dsl::disk)
+                    .filter(dsl::id.eq(authz_disk.id()))
+                    .filter(dsl::state_generation.eq(maintenance_gen))
+                    .set((
+                        dsl::volume_id.eq(volume_id),
+                        dsl::size_bytes.eq(size),
+                        dsl::disk_state.eq(disk_state.label()),
+                        dsl::attach_instance_id.eq(attach_instance_id),
+                        dsl::slot.eq(slot),
+                        dsl::state_generation.eq(finished_gen),
+                        dsl::time_state_updated.eq(Utc::now()),
+                    ))
+                    .returning(Disk::as_returning())
+                    .get_result_async(&conn)
+                    .await?;
+                Ok(disk)
+            })
+            .await
+            .map_err(|e| match e {
+                TxnError::CustomError(e) => e,
+                TxnError::Pool(e) => {
+                    public_error_from_diesel_pool(e, ErrorHandler::Server)
+                }
+            })
+    }
+
     pub async fn disk_set_pantry(
         &self,
         opctx: &OpContext,
@@ -19,6 +19,7 @@ use crate::db::TransactionError;
 use async_bb8_diesel::AsyncRunQueryDsl;
 use async_bb8_diesel::PoolError;
 use diesel::prelude::*;
+use internal_dns::names::is_nameserver_dns_name;
 use nexus_types::internal_api::params::DnsConfigParams;
 use nexus_types::internal_api::params::DnsConfigZone;
 use nexus_types::internal_api::params::DnsRecord;
@@ -298,9 +299,19 @@ impl DataStore {
             );
 
             if !zone_records.is_empty() {
+                // Each zone's nameservers are the DNS servers that serve it,
+                // which were given names like "ns1" in the zone when it was
+                // created.
+                let nameservers = zone_records
+                    .iter()
+                    .map(|(name, _)| name)
+                    .filter(|name| is_nameserver_dns_name(name))
+                    .cloned()
+                    .collect();
                 zones.push(DnsConfigZone {
                     zone_name: zone.zone_name,
                     records: zone_records.into_iter().collect(),
+                    nameservers,
                 });
             }
         }
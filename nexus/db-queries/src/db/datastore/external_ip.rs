@@ -11,22 +11,34 @@ use crate::context::OpContext;
 use crate::db;
 use crate::db::error::public_error_from_diesel_pool;
 use crate::db::error::ErrorHandler;
+use crate::db::error::TransactionError;
 use crate::db::lookup::LookupPath;
 use crate::db::model::ExternalIp;
+use crate::db::model::FloatingIp;
 use crate::db::model::IncompleteExternalIp;
 use crate::db::model::IpKind;
+use crate::db::model::IpPool;
 use crate::db::model::Name;
+use crate::db::pagination::paginated;
 use crate::db::pool::DbConnection;
 use crate::db::queries::external_ip::NextExternalIp;
 use crate::db::update_and_check::UpdateAndCheck;
 use crate::db::update_and_check::UpdateStatus;
-use async_bb8_diesel::{AsyncRunQueryDsl, PoolError};
+use async_bb8_diesel::{AsyncConnection, AsyncRunQueryDsl, PoolError};
 use chrono::Utc;
 use diesel::prelude::*;
+use nexus_types::external_api::params;
 use nexus_types::identity::Resource;
+use omicron_common::api::external::http_pagination::PaginatedBy;
 use omicron_common::api::external::CreateResult;
+use omicron_common::api::external::DeleteResult;
 use omicron_common::api::external::Error;
+use omicron_common::api::external::ListResultVec;
 use omicron_common::api::external::LookupResult;
+use omicron_common::api::external::NameOrId;
+use omicron_common::api::external::ResourceType;
+use omicron_common::api::external::UpdateResult;
+use ref_cast::RefCast;
 use std::net::IpAddr;
 use uuid::Uuid;
 
@@ -47,22 +59,33 @@ impl DataStore {
         self.allocate_external_ip(opctx, data).await
     }
 
-    /// Create an Ephemeral IP address for an instance.
-    pub async fn allocate_instance_ephemeral_ip(
+    /// Look up the IP pool from which an instance's Ephemeral or Floating IP
+    /// address should be allocated: either the named pool, or the default pool
+    /// for the current Silo.
+    async fn resolve_pool_for_allocation(
         &self,
         opctx: &OpContext,
-        ip_id: Uuid,
-        instance_id: Uuid,
-        pool_name: Option<Name>,
-    ) -> CreateResult<ExternalIp> {
-        let pool = match pool_name {
-            Some(name) => {
-                let (.., authz_pool, pool) = LookupPath::new(opctx, &self)
-                    .ip_pool_name(&name)
-                    // any authenticated user can CreateChild on an IP pool. this is
-                    // meant to represent allocating an IP
-                    .fetch_for(authz::Action::CreateChild)
-                    .await?;
+        pool: Option<NameOrId>,
+    ) -> LookupResult<IpPool> {
+        let pool = match pool {
+            Some(pool) => {
+                // any authenticated user can CreateChild on an IP pool. this is
+                // meant to represent allocating an IP
+                let (.., authz_pool, pool) = match pool {
+                    NameOrId::Name(name) => {
+                        let name = Name(name);
+                        LookupPath::new(opctx, &self)
+                            .ip_pool_name(&name)
+                            .fetch_for(authz::Action::CreateChild)
+                            .await?
+                    }
+                    NameOrId::Id(id) => {
+                        LookupPath::new(opctx, &self)
+                            .ip_pool_id(id)
+                            .fetch_for(authz::Action::CreateChild)
+                            .await?
+                    }
+                };
 
                 // If the named pool conflicts with user's current scope, i.e.,
                 // if it has a silo and it's different from the current silo,
@@ -81,6 +104,23 @@ impl DataStore {
             // If no name given, use the default logic
             None => self.ip_pools_fetch_default(&opctx).await?,
         };
+        Ok(pool)
+    }
+
+    /// Create an Ephemeral IP address for an instance.
+    pub async fn allocate_instance_ephemeral_ip(
+        &self,
+        opctx: &OpContext,
+        ip_id: Uuid,
+        instance_id: Uuid,
+        pool_name: Option<Name>,
+    ) -> CreateResult<ExternalIp> {
+        let pool = self
+            .resolve_pool_for_allocation(
+                opctx,
+                pool_name.map(|name| NameOrId::Name(name.0)),
+            )
+            .await?;
 
         let pool_id = pool.identity.id;
         let data =
@@ -88,6 +128,29 @@ impl DataStore {
         self.allocate_external_ip(opctx, data).await
     }
 
+    /// Create a Floating IP address in a project.
+    pub async fn allocate_floating_ip(
+        &self,
+        opctx: &OpContext,
+        authz_project: &authz::Project,
+        params: params::FloatingIpCreate,
+    ) -> CreateResult<FloatingIp> {
+        opctx.authorize(authz::Action::CreateChild, authz_project).await?;
+
+        let pool = self.resolve_pool_for_allocation(opctx, params.pool).await?;
+        let data = IncompleteExternalIp::for_floating(
+            Uuid::new_v4(),
+            &Name(params.identity.name),
+            &params.identity.description,
+            authz_project.id(),
+            pool.id(),
+            params.address,
+        );
+        self.allocate_external_ip(opctx, data)
+            .await
+            .and_then(FloatingIp::try_from)
+    }
+
     /// Allocates an IP address for internal service usage.
     pub async fn allocate_service_ip(
         &self,
@@ -147,12 +210,30 @@ impl DataStore {
         PoolError: From<ConnErr>,
     {
         let explicit_ip = data.explicit_ip().is_some();
+        // Floating IPs owned by a project have names that are unique within
+        // that project.
+        let floating_ip_name = data
+            .project_id()
+            .and_then(|_| data.name().as_ref().map(|name| name.to_string()));
         NextExternalIp::new(data).get_result_async(conn).await.map_err(|e| {
             use async_bb8_diesel::ConnectionError::Query;
             use async_bb8_diesel::PoolError::Connection;
+            use diesel::result::DatabaseErrorKind::UniqueViolation;
+            use diesel::result::Error::DatabaseError;
             use diesel::result::Error::NotFound;
             let e = PoolError::from(e);
             match e {
+                Connection(Query(DatabaseError(UniqueViolation, _)))
+                    if floating_ip_name.is_some() =>
+                {
+                    public_error_from_diesel_pool(
+                        e,
+                        ErrorHandler::Conflict(
+                            ResourceType::FloatingIp,
+                            floating_ip_name.as_deref().unwrap(),
+                        ),
+                    )
+                }
                 Connection(Query(NotFound)) => {
                     if explicit_ip {
                         Error::invalid_request(
@@ -248,13 +329,12 @@ impl DataStore {
     }
 
     /// Delete all external IP addresses associated with the provided instance
-    /// ID.
+    /// ID, other than Floating IPs, which are instead detached by
+    /// [Self::detach_floating_ips_by_instance_id].
     ///
     /// This method returns the number of records deleted, rather than the usual
     /// `DeleteResult`. That's mostly useful for tests, but could be important
     /// if callers have some invariants they'd like to check.
-    // TODO-correctness: This can't be used for Floating IPs, we'll need a
-    // _detatch_ method for that.
     pub async fn deallocate_external_ip_by_instance_id(
         &self,
         opctx: &OpContext,
@@ -289,4 +369,234 @@ impl DataStore {
             .await
             .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
     }
+
+    /// Detach all Floating IP addresses from the provided instance.
+    ///
+    /// This is used when deleting an instance: unlike Ephemeral IPs, Floating
+    /// IPs outlive the instances they are attached to. Returns the number of
+    /// records that were detached.
+    pub async fn detach_floating_ips_by_instance_id(
+        &self,
+        opctx: &OpContext,
+        instance_id: Uuid,
+    ) -> Result<usize, Error> {
+        use db::schema::external_ip::dsl;
+        diesel::update(dsl::external_ip)
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::is_service.eq(false))
+            .filter(dsl::parent_id.eq(instance_id))
+            .filter(dsl::kind.eq(IpKind::Floating))
+            .set((
+                dsl::parent_id.eq(Option::<Uuid>::None),
+                dsl::time_modified.eq(Utc::now()),
+            ))
+            .execute_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    /// List the Floating IP addresses in a project.
+    pub async fn floating_ips_list(
+        &self,
+        opctx: &OpContext,
+        authz_project: &authz::Project,
+        pagparams: &PaginatedBy<'_>,
+    ) -> ListResultVec<FloatingIp> {
+        opctx.authorize(authz::Action::ListChildren, authz_project).await?;
+
+        use db::schema::floating_ip::dsl;
+        match pagparams {
+            PaginatedBy::Id(pagparams) => {
+                paginated(dsl::floating_ip, dsl::id, &pagparams)
+            }
+            PaginatedBy::Name(pagparams) => paginated(
+                dsl::floating_ip,
+                dsl::name,
+                &pagparams.map_name(|n| Name::ref_cast(n)),
+            ),
+        }
+        .filter(dsl::project_id.eq(authz_project.id()))
+        .filter(dsl::time_deleted.is_null())
+        .select(FloatingIp::as_select())
+        .get_results_async(self.pool_authorized(opctx).await?)
+        .await
+        .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    /// Delete a Floating IP address, which must not be attached to an
+    /// instance.
+    pub async fn floating_ip_delete(
+        &self,
+        opctx: &OpContext,
+        authz_fip: &authz::FloatingIp,
+    ) -> DeleteResult {
+        opctx.authorize(authz::Action::Delete, authz_fip).await?;
+
+        use db::schema::external_ip::dsl;
+        let now = Utc::now();
+        let result = diesel::update(dsl::external_ip)
+            .filter(dsl::id.eq(authz_fip.id()))
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::parent_id.is_null())
+            .set(dsl::time_deleted.eq(now))
+            .check_if_exists::<ExternalIp>(authz_fip.id())
+            .execute_and_check(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByResource(authz_fip),
+                )
+            })?;
+
+        match result.status {
+            UpdateStatus::Updated => Ok(()),
+            UpdateStatus::NotUpdatedButExists => {
+                if result.found.time_deleted.is_some() {
+                    Err(authz_fip.not_found())
+                } else {
+                    Err(Error::invalid_request(
+                        "Floating IP cannot be deleted while attached to an \
+                        instance",
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Attach a Floating IP address to an instance.
+    ///
+    /// The caller is responsible for checking that the instance is in the same
+    /// project as the Floating IP. The instance may have at most
+    /// `max_external_ips` external addresses (other than its source NAT
+    /// address) once this one is attached.
+    ///
+    /// Attaching a Floating IP to the instance it is already attached to
+    /// succeeds without modifying anything.
+    pub async fn floating_ip_attach(
+        &self,
+        opctx: &OpContext,
+        authz_fip: &authz::FloatingIp,
+        authz_instance: &authz::Instance,
+        max_external_ips: usize,
+    ) -> UpdateResult<ExternalIp> {
+        opctx.authorize(authz::Action::Modify, authz_fip).await?;
+        opctx.authorize(authz::Action::Modify, authz_instance).await?;
+
+        use db::schema::external_ip::dsl;
+        let fip_id = authz_fip.id();
+        let instance_id = authz_instance.id();
+
+        type TxnError = TransactionError<Error>;
+        self.pool_authorized(opctx)
+            .await?
+            .transaction_async(|conn| async move {
+                let fip = dsl::external_ip
+                    .filter(dsl::id.eq(fip_id))
+                    .filter(dsl::time_deleted.is_null())
+                    .select(ExternalIp::as_select())
+                    .get_result_async(&conn)
+                    .await?;
+                match fip.parent_id {
+                    Some(parent_id) if parent_id == instance_id => {
+                        return Ok(fip);
+                    }
+                    Some(_) => {
+                        return Err(TxnError::CustomError(
+                            Error::invalid_request(
+                                "Floating IP is already attached to an \
+                                instance",
+                            ),
+                        ));
+                    }
+                    None => (),
+                }
+
+                let n_external_ips: i64 = dsl::external_ip
+                    .filter(dsl::time_deleted.is_null())
+                    .filter(dsl::is_service.eq(false))
+                    .filter(dsl::parent_id.eq(instance_id))
+                    .filter(dsl::kind.ne(IpKind::SNat))
+                    .count()
+                    .get_result_async(&conn)
+                    .await?;
+                if usize::try_from(n_external_ips).unwrap_or(usize::MAX)
+                    >= max_external_ips
+                {
+                    return Err(TxnError::CustomError(Error::invalid_request(
+                        &format!(
+                            "An instance may not have more than {} external \
+                            IP addresses",
+                            max_external_ips,
+                        ),
+                    )));
+                }
+
+                let fip = diesel::update(dsl::external_ip)
+                    .filter(dsl::id.eq(fip_id))
+                    .filter(dsl::time_deleted.is_null())
+                    .filter(dsl::parent_id.is_null())
+                    .set((
+                        dsl::parent_id.eq(Some(instance_id)),
+                        dsl::time_modified.eq(Utc::now()),
+                    ))
+                    .returning(ExternalIp::as_returning())
+                    .get_result_async(&conn)
+                    .await?;
+                Ok(fip)
+            })
+            .await
+            .map_err(|e| match e {
+                TxnError::CustomError(e) => e,
+                TxnError::Pool(e) => public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByResource(authz_fip),
+                ),
+            })
+    }
+
+    /// Detach a Floating IP address from the instance with ID `instance_id`.
+    ///
+    /// Fails if the Floating IP is not attached to that instance, such as when
+    /// it has been concurrently detached or moved to another instance.
+    pub async fn floating_ip_detach(
+        &self,
+        opctx: &OpContext,
+        authz_fip: &authz::FloatingIp,
+        instance_id: Uuid,
+    ) -> UpdateResult<ExternalIp> {
+        opctx.authorize(authz::Action::Modify, authz_fip).await?;
+
+        use db::schema::external_ip::dsl;
+        let result = diesel::update(dsl::external_ip)
+            .filter(dsl::id.eq(authz_fip.id()))
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::parent_id.eq(instance_id))
+            .set((
+                dsl::parent_id.eq(Option::<Uuid>::None),
+                dsl::time_modified.eq(Utc::now()),
+            ))
+            .check_if_exists::<ExternalIp>(authz_fip.id())
+            .execute_and_check(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByResource(authz_fip),
+                )
+            })?;
+
+        match result.status {
+            UpdateStatus::Updated => Ok(result.found),
+            UpdateStatus::NotUpdatedButExists => {
+                if result.found.time_deleted.is_some() {
+                    Err(authz_fip.not_found())
+                } else {
+                    Err(Error::invalid_request(
+                        "Floating IP is not attached to the instance",
+                    ))
+                }
+            }
+        }
+    }
 }
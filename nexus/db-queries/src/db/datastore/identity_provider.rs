@@ -103,4 +103,57 @@ impl DataStore {
                 )
             })
     }
+
+    pub async fn oidc_identity_provider_create(
+        &self,
+        opctx: &OpContext,
+        authz_idp_list: &authz::SiloIdentityProviderList,
+        provider: db::model::OidcIdentityProvider,
+    ) -> CreateResult<db::model::OidcIdentityProvider> {
+        opctx.authorize(authz::Action::CreateChild, authz_idp_list).await?;
+        assert_eq!(provider.silo_id, authz_idp_list.silo().id());
+
+        let name = provider.identity().name.to_string();
+        self.pool_authorized(opctx)
+            .await?
+            .transaction_async(|conn| async move {
+                // insert silo identity provider record with type Oidc
+                use db::schema::identity_provider::dsl as idp_dsl;
+                diesel::insert_into(idp_dsl::identity_provider)
+                    .values(db::model::IdentityProvider {
+                        identity: db::model::IdentityProviderIdentity {
+                            id: provider.identity.id,
+                            name: provider.identity.name.clone(),
+                            description: provider.identity.description.clone(),
+                            time_created: provider.identity.time_created,
+                            time_modified: provider.identity.time_modified,
+                            time_deleted: provider.identity.time_deleted,
+                        },
+                        silo_id: provider.silo_id,
+                        provider_type: db::model::IdentityProviderType::Oidc,
+                    })
+                    .execute_async(&conn)
+                    .await?;
+
+                // insert silo oidc identity provider record
+                use db::schema::oidc_identity_provider::dsl;
+                let result = diesel::insert_into(dsl::oidc_identity_provider)
+                    .values(provider)
+                    .returning(db::model::OidcIdentityProvider::as_returning())
+                    .get_result_async(&conn)
+                    .await?;
+
+                Ok(result)
+            })
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::Conflict(
+                        ResourceType::OidcIdentityProvider,
+                        &name,
+                    ),
+                )
+            })
+    }
 }
@@ -15,16 +15,24 @@ use crate::db::collection_insert::AsyncInsertError;
 use crate::db::collection_insert::DatastoreCollection;
 use crate::db::error::public_error_from_diesel_pool;
 use crate::db::error::ErrorHandler;
+use crate::db::error::TransactionError;
 use crate::db::identity::Resource;
 use crate::db::lookup::LookupPath;
+use crate::db::model::ByteCount;
+use crate::db::model::Generation;
 use crate::db::model::Instance;
+use crate::db::model::InstanceCpuCount;
 use crate::db::model::InstanceRuntimeState;
 use crate::db::model::Name;
 use crate::db::model::Project;
 use crate::db::pagination::paginated;
+use crate::db::queries::virtual_provisioning_collection_update;
+use crate::db::queries::virtual_provisioning_collection_update::VirtualProvisioningCollectionUpdate;
 use crate::db::update_and_check::UpdateAndCheck;
 use crate::db::update_and_check::UpdateStatus;
+use async_bb8_diesel::AsyncConnection;
 use async_bb8_diesel::AsyncRunQueryDsl;
+use async_bb8_diesel::PoolError;
 use chrono::Utc;
 use diesel::prelude::*;
 use omicron_common::api;
@@ -36,6 +44,7 @@ use omicron_common::api::external::ListResultVec;
 use omicron_common::api::external::LookupResult;
 use omicron_common::api::external::LookupType;
 use omicron_common::api::external::ResourceType;
+use omicron_common::api::external::UpdateResult;
 use omicron_common::bail_unless;
 use ref_cast::RefCast;
 use uuid::Uuid;
@@ -244,6 +253,108 @@ impl DataStore {
         Ok(updated)
     }
 
+    /// Updates the vCPU count, memory size, and hostname of a stopped
+    /// Instance.
+    ///
+    /// The new configuration takes effect the next time the instance is
+    /// started. The instance's state generation is advanced, so that any
+    /// concurrent operation which observed the old configuration (such as an
+    /// attempt to start the instance) fails instead of overwriting it.
+    ///
+    /// The resources provisioned by the instance's project, silo, and fleet
+    /// are adjusted within the same transaction. This fails if growing the
+    /// instance would exceed the quota of any of those collections.
+    pub async fn instance_reconfigure(
+        &self,
+        opctx: &OpContext,
+        authz_instance: &authz::Instance,
+        ncpus: InstanceCpuCount,
+        memory: ByteCount,
+        hostname: String,
+    ) -> UpdateResult<Instance> {
+        opctx.authorize(authz::Action::Modify, authz_instance).await?;
+
+        use api::external::InstanceState as ApiInstanceState;
+        use db::schema::instance::dsl;
+
+        type TxnError = TransactionError<Error>;
+        let (instance, provisions) = self
+            .pool_authorized(opctx)
+            .await?
+            .transaction_async(|conn| async move {
+                let old_instance = dsl::instance
+                    .filter(dsl::time_deleted.is_null())
+                    .filter(dsl::id.eq(authz_instance.id()))
+                    .select(Instance::as_select())
+                    .get_result_async(&conn)
+                    .await
+                    .map_err(|e| {
+                        public_error_from_diesel_pool(
+                            PoolError::from(e),
+                            ErrorHandler::NotFoundByResource(authz_instance),
+                        )
+                    })?;
+
+                let old_runtime = old_instance.runtime();
+                let instance_state = old_runtime.state.state();
+                if *instance_state != ApiInstanceState::Stopped {
+                    return Err(TxnError::CustomError(Error::conflict(
+                        &format!(
+                            "instance is in state {} but must be {} to be \
+                            reconfigured",
+                            instance_state,
+                            ApiInstanceState::Stopped,
+                        ),
+                    )));
+                }
+
+                let instance = diesel::update(dsl::instance)
+                    .filter(dsl::time_deleted.is_null())
+                    .filter(dsl::id.eq(authz_instance.id()))
+                    .set((
+                        dsl::ncpus.eq(ncpus),
+                        dsl::memory.eq(memory),
+                        dsl::hostname.eq(hostname),
+                        dsl::state_generation
+                            .eq(Generation::from(old_runtime.gen.next())),
+                        dsl::time_state_updated.eq(Utc::now()),
+                    ))
+                    .returning(Instance::as_returning())
+                    .get_result_async(&conn)
+                    .await?;
+
+                let provisions =
+                    VirtualProvisioningCollectionUpdate::new_update_instance(
+                        instance.id(),
+                        i64::from(old_runtime.ncpus.0 .0),
+                        old_runtime.memory,
+                        i64::from(ncpus.0 .0),
+                        memory,
+                        instance.project_id,
+                    )
+                    .get_results_async(&conn)
+                    .await
+                    .map_err(|e| {
+                        virtual_provisioning_collection_update::from_pool(
+                            PoolError::from(e),
+                        )
+                    })?;
+
+                Ok((instance, provisions))
+            })
+            .await
+            .map_err(|e| match e {
+                TxnError::CustomError(e) => e,
+                TxnError::Pool(e) => {
+                    public_error_from_diesel_pool(e, ErrorHandler::Server)
+                }
+            })?;
+
+        self.virtual_provisioning_collection_producer
+            .append_cpu_metrics(&provisions)?;
+        Ok(instance)
+    }
+
     pub async fn project_delete_instance(
         &self,
         opctx: &OpContext,
@@ -313,6 +424,10 @@ impl DataStore {
             }
         })?;
 
+        // A deleted instance no longer constrains where the other members of
+        // its affinity groups are placed.
+        self.instance_affinity_groups_leave(opctx, authz_instance).await?;
+
         Ok(())
     }
 }
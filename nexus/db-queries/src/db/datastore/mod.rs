@@ -48,6 +48,8 @@ use std::sync::Arc;
 use uuid::Uuid;
 
 mod address_lot;
+mod affinity;
+mod audit_log;
 mod certificate;
 mod console_session;
 mod dataset;
@@ -69,6 +71,7 @@ mod region;
 mod region_snapshot;
 mod role;
 mod saga;
+mod scim_client_bearer_token;
 mod service;
 mod silo;
 mod silo_group;
@@ -82,6 +85,7 @@ mod switch_interface;
 mod switch_port;
 mod update;
 mod virtual_provisioning_collection;
+mod virtual_provisioning_quota;
 mod volume;
 mod vpc;
 mod zpool;
@@ -1425,6 +1429,7 @@ mod test {
                 ))),
                 first_port: crate::db::model::SqlU16(0),
                 last_port: crate::db::model::SqlU16(10),
+                project_id: None,
             })
             .collect::<Vec<_>>();
         diesel::insert_into(dsl::external_ip)
@@ -1484,6 +1489,7 @@ mod test {
             ))),
             first_port: crate::db::model::SqlU16(0),
             last_port: crate::db::model::SqlU16(10),
+            project_id: None,
         };
         diesel::insert_into(dsl::external_ip)
             .values(ip.clone())
@@ -1553,6 +1559,7 @@ mod test {
             ip: addresses.next().unwrap().into(),
             first_port: crate::db::model::SqlU16(0),
             last_port: crate::db::model::SqlU16(10),
+            project_id: None,
         };
 
         // Combinations of NULL and non-NULL for:
@@ -1578,6 +1585,12 @@ mod test {
                             ip: addresses.next().unwrap().into(),
                             is_service,
                             parent_id: *parent_id,
+                            // Floating IPs for instances belong to a project.
+                            project_id: if is_service {
+                                None
+                            } else {
+                                Some(Uuid::new_v4())
+                            },
                             ..ip
                         };
                         let res = diesel::insert_into(dsl::external_ip)
@@ -1704,6 +1717,41 @@ mod test {
                 }
             }
         }
+
+        // Floating IPs for instances must belong to a project, and those for
+        // services must not.
+        for is_service in [false, true] {
+            let new_ip = ExternalIp {
+                id: Uuid::new_v4(),
+                name: names[1].clone(),
+                description: descriptions[1].clone(),
+                ip: addresses.next().unwrap().into(),
+                is_service,
+                project_id: if is_service {
+                    Some(Uuid::new_v4())
+                } else {
+                    None
+                },
+                ..ip
+            };
+            let err = diesel::insert_into(dsl::external_ip)
+                .values(new_ip)
+                .execute_async(datastore.pool())
+                .await
+                .expect_err(
+                    "Expected a CHECK violation when inserting a Floating IP \
+                     record with an invalid project ID",
+                );
+            assert!(
+                matches!(
+                    err,
+                    Connection(Query(DatabaseError(CheckViolation, _)))
+                ),
+                "Expected a CHECK violation when inserting a Floating IP \
+                 record with an invalid project ID",
+            );
+        }
+
         db.cleanup().await.unwrap();
         logctx.cleanup_successful();
     }
@@ -10,6 +10,7 @@ use crate::db::error::public_error_from_diesel_pool;
 use crate::db::error::ErrorHandler;
 use crate::db::model::OximeterInfo;
 use crate::db::model::ProducerEndpoint;
+use crate::db::model::PushProducer;
 use crate::db::pagination::paginated;
 use async_bb8_diesel::AsyncRunQueryDsl;
 use chrono::Utc;
@@ -123,4 +124,74 @@ impl DataStore {
                 )
             })
     }
+
+    // Create a record for a new push producer, or renew the registration of an
+    // existing one, reassigning it to another collector
+    pub async fn push_producer_create(
+        &self,
+        producer: &PushProducer,
+    ) -> Result<(), Error> {
+        use db::schema::metric_push_producer::dsl;
+
+        // A producer registers again when its collector has forgotten it, for
+        // example after a restart. Move it to the newly-chosen collector.
+        diesel::insert_into(dsl::metric_push_producer)
+            .values(producer.clone())
+            .on_conflict(dsl::id)
+            .do_update()
+            .set((
+                dsl::time_modified.eq(Utc::now()),
+                dsl::oximeter_id.eq(producer.oximeter_id),
+                dsl::time_expires.eq(producer.time_expires),
+            ))
+            .execute_async(self.pool())
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::Conflict(
+                        ResourceType::MetricProducer,
+                        "Push Producer",
+                    ),
+                )
+            })?;
+        Ok(())
+    }
+
+    // List the push producer records by the oximeter instance to which they're
+    // assigned, other than those whose registrations have expired.
+    pub async fn push_producers_list_by_oximeter_id(
+        &self,
+        oximeter_id: Uuid,
+        pagparams: &DataPageParams<'_, Uuid>,
+    ) -> ListResultVec<PushProducer> {
+        use db::schema::metric_push_producer::dsl;
+        paginated(dsl::metric_push_producer, dsl::id, &pagparams)
+            .filter(dsl::oximeter_id.eq(oximeter_id))
+            .filter(dsl::time_expires.gt(Utc::now()))
+            .order_by((dsl::oximeter_id, dsl::id))
+            .select(PushProducer::as_select())
+            .load_async(self.pool())
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::Conflict(
+                        ResourceType::MetricProducer,
+                        "By Oximeter ID",
+                    ),
+                )
+            })
+    }
+
+    // Delete the records of push producers whose registrations have expired,
+    // returning how many were deleted.
+    pub async fn push_producers_delete_expired(&self) -> Result<usize, Error> {
+        use db::schema::metric_push_producer::dsl;
+        diesel::delete(dsl::metric_push_producer)
+            .filter(dsl::time_expires.le(Utc::now()))
+            .execute_async(self.pool())
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
 }
@@ -213,6 +213,8 @@ impl DataStore {
     generate_fn_to_ensure_none_in_project!(project_image, name, String);
     generate_fn_to_ensure_none_in_project!(snapshot, name, String);
     generate_fn_to_ensure_none_in_project!(vpc, name, String);
+    generate_fn_to_ensure_none_in_project!(floating_ip, name, String);
+    generate_fn_to_ensure_none_in_project!(affinity_group, name, String);
 
     /// Delete a project
     pub async fn project_delete(
@@ -229,6 +231,9 @@ impl DataStore {
         self.ensure_no_project_images_in_project(opctx, authz_project).await?;
         self.ensure_no_snapshots_in_project(opctx, authz_project).await?;
         self.ensure_no_vpcs_in_project(opctx, authz_project).await?;
+        self.ensure_no_floating_ips_in_project(opctx, authz_project).await?;
+        self.ensure_no_affinity_groups_in_project(opctx, authz_project)
+            .await?;
 
         use db::schema::project::dsl;
 
@@ -265,6 +270,11 @@ impl DataStore {
                     db_project.id(),
                 )
                 .await?;
+                self.virtual_provisioning_quota_delete_on_connection(
+                    &conn,
+                    db_project.id(),
+                )
+                .await?;
                 Ok(())
             })
             .await
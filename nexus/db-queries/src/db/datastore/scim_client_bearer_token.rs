@@ -0,0 +1,126 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! [`DataStore`] methods on [`ScimClientBearerToken`]s.
+
+use super::DataStore;
+use crate::authz;
+use crate::context::OpContext;
+use crate::db;
+use crate::db::error::public_error_from_diesel_pool;
+use crate::db::error::ErrorHandler;
+use crate::db::model::ScimClientBearerToken;
+use crate::db::pagination::paginated;
+use async_bb8_diesel::AsyncRunQueryDsl;
+use diesel::prelude::*;
+use omicron_common::api::external::CreateResult;
+use omicron_common::api::external::DataPageParams;
+use omicron_common::api::external::DeleteResult;
+use omicron_common::api::external::Error;
+use omicron_common::api::external::ListResultVec;
+use omicron_common::api::external::LookupResult;
+use omicron_common::api::external::LookupType;
+use omicron_common::api::external::ResourceType;
+use uuid::Uuid;
+
+impl DataStore {
+    // A SCIM client can create and modify all of a Silo's users and groups, so
+    // managing these tokens requires the same privileges as configuring the
+    // Silo's identity providers.  That's more than is needed to list the
+    // identity providers, which everybody in the Silo can do.
+
+    pub async fn scim_client_bearer_token_create(
+        &self,
+        opctx: &OpContext,
+        authz_silo: &authz::Silo,
+        token: ScimClientBearerToken,
+    ) -> CreateResult<ScimClientBearerToken> {
+        assert_eq!(authz_silo.id(), token.silo_id);
+        let authz_idp_list =
+            authz::SiloIdentityProviderList::new(authz_silo.clone());
+        opctx.authorize(authz::Action::CreateChild, &authz_idp_list).await?;
+
+        use db::schema::scim_client_bearer_token::dsl;
+        diesel::insert_into(dsl::scim_client_bearer_token)
+            .values(token)
+            .returning(ScimClientBearerToken::as_returning())
+            .get_result_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    pub async fn scim_client_bearer_tokens_list(
+        &self,
+        opctx: &OpContext,
+        authz_silo: &authz::Silo,
+        pagparams: &DataPageParams<'_, Uuid>,
+    ) -> ListResultVec<ScimClientBearerToken> {
+        let authz_idp_list =
+            authz::SiloIdentityProviderList::new(authz_silo.clone());
+        opctx.authorize(authz::Action::CreateChild, &authz_idp_list).await?;
+
+        use db::schema::scim_client_bearer_token::dsl;
+        paginated(dsl::scim_client_bearer_token, dsl::id, pagparams)
+            .filter(dsl::silo_id.eq(authz_silo.id()))
+            .select(ScimClientBearerToken::as_select())
+            .load_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    pub async fn scim_client_bearer_token_delete(
+        &self,
+        opctx: &OpContext,
+        authz_silo: &authz::Silo,
+        token_id: Uuid,
+    ) -> DeleteResult {
+        let authz_idp_list =
+            authz::SiloIdentityProviderList::new(authz_silo.clone());
+        opctx.authorize(authz::Action::CreateChild, &authz_idp_list).await?;
+
+        use db::schema::scim_client_bearer_token::dsl;
+        let deleted = diesel::delete(dsl::scim_client_bearer_token)
+            .filter(dsl::silo_id.eq(authz_silo.id()))
+            .filter(dsl::id.eq(token_id))
+            .execute_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(e, ErrorHandler::Server)
+            })?;
+        if deleted == 0 {
+            return Err(Error::ObjectNotFound {
+                type_name: ResourceType::ScimClientBearerToken,
+                lookup_type: LookupType::ById(token_id),
+            });
+        }
+        Ok(())
+    }
+
+    /// Look up a SCIM client bearer token.
+    ///
+    /// Like device access tokens, these are looked up by the (high-entropy,
+    /// random) token itself before we know which Silo the request is for, so
+    /// this does not include any authz checks.
+    pub async fn scim_client_bearer_token_fetch(
+        &self,
+        opctx: &OpContext,
+        bearer_token: String,
+    ) -> LookupResult<ScimClientBearerToken> {
+        use db::schema::scim_client_bearer_token::dsl;
+        dsl::scim_client_bearer_token
+            .filter(dsl::bearer_token.eq(bearer_token))
+            .select(ScimClientBearerToken::as_select())
+            .get_result_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByLookup(
+                        ResourceType::ScimClientBearerToken,
+                        LookupType::ByCompositeId("bearer_token".to_string()),
+                    ),
+                )
+            })
+    }
+}
@@ -26,6 +26,7 @@ use omicron_common::api::external::Error;
 use omicron_common::api::external::InternalContext;
 use omicron_common::api::external::ListResultVec;
 use omicron_common::api::external::LookupResult;
+use omicron_common::api::external::ResourceType;
 use omicron_common::api::external::UpdateResult;
 use uuid::Uuid;
 
@@ -181,6 +182,104 @@ impl DataStore {
             .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
     }
 
+    /// List the memberships of a silo group
+    pub async fn silo_group_membership_for_group(
+        &self,
+        opctx: &OpContext,
+        authz_silo_group: &authz::SiloGroup,
+    ) -> ListResultVec<SiloGroupMembership> {
+        opctx.authorize(authz::Action::Read, authz_silo_group).await?;
+
+        use db::schema::silo_group_membership::dsl;
+        dsl::silo_group_membership
+            .filter(dsl::silo_group_id.eq(authz_silo_group.id()))
+            .select(SiloGroupMembership::as_returning())
+            .get_results_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    /// Replace the members of a silo group
+    ///
+    /// Like `silo_group_membership_replace_for_user`, this is done in one
+    /// transaction so that a crash half way through does not leave the group
+    /// with the wrong members.
+    pub async fn silo_group_membership_replace_for_group(
+        &self,
+        opctx: &OpContext,
+        authz_silo_group: &authz::SiloGroup,
+        silo_user_ids: Vec<Uuid>,
+    ) -> UpdateResult<()> {
+        opctx.authorize(authz::Action::Modify, authz_silo_group).await?;
+
+        self.pool_authorized(opctx)
+            .await?
+            .transaction_async(|conn| async move {
+                use db::schema::silo_group_membership::dsl;
+
+                // Delete existing memberships for group
+                let silo_group_id = authz_silo_group.id();
+                diesel::delete(dsl::silo_group_membership)
+                    .filter(dsl::silo_group_id.eq(silo_group_id))
+                    .execute_async(&conn)
+                    .await?;
+
+                // Create new memberships for group
+                let silo_group_memberships: Vec<
+                    db::model::SiloGroupMembership,
+                > = silo_user_ids
+                    .iter()
+                    .map(|user_id| {
+                        db::model::SiloGroupMembership::new(
+                            silo_group_id,
+                            *user_id,
+                        )
+                    })
+                    .collect();
+
+                diesel::insert_into(dsl::silo_group_membership)
+                    .values(silo_group_memberships)
+                    .execute_async(&conn)
+                    .await?;
+
+                Ok(())
+            })
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    /// Change the external id (the identity provider's name) of a silo group
+    pub async fn silo_group_update_external_id(
+        &self,
+        opctx: &OpContext,
+        authz_silo_group: &authz::SiloGroup,
+        external_id: String,
+    ) -> UpdateResult<SiloGroup> {
+        opctx.authorize(authz::Action::Modify, authz_silo_group).await?;
+
+        use db::schema::silo_group::dsl;
+        let conflict_name = external_id.clone();
+        diesel::update(dsl::silo_group)
+            .filter(dsl::id.eq(authz_silo_group.id()))
+            .filter(dsl::time_deleted.is_null())
+            .set((
+                dsl::external_id.eq(external_id),
+                dsl::time_modified.eq(Utc::now()),
+            ))
+            .returning(SiloGroup::as_returning())
+            .get_result_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::Conflict(
+                        ResourceType::SiloGroup,
+                        &conflict_name,
+                    ),
+                )
+            })
+    }
+
     pub async fn silo_group_delete(
         &self,
         opctx: &OpContext,
@@ -247,6 +346,50 @@ impl DataStore {
             })
     }
 
+    /// List a Silo's groups in a stable order, starting from a position in
+    /// that order rather than from a marker
+    ///
+    /// This is how SCIM clients page through a Silo's groups.
+    pub async fn silo_groups_list_by_offset(
+        &self,
+        opctx: &OpContext,
+        authz_silo: &authz::Silo,
+        offset: i64,
+        limit: i64,
+    ) -> ListResultVec<SiloGroup> {
+        use db::schema::silo_group::dsl;
+
+        opctx.authorize(authz::Action::Read, authz_silo).await?;
+        dsl::silo_group
+            .filter(dsl::silo_id.eq(authz_silo.id()))
+            .filter(dsl::time_deleted.is_null())
+            .order(dsl::id)
+            .offset(offset)
+            .limit(limit)
+            .select(SiloGroup::as_select())
+            .load_async::<SiloGroup>(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    /// Count a Silo's groups
+    pub async fn silo_groups_count(
+        &self,
+        opctx: &OpContext,
+        authz_silo: &authz::Silo,
+    ) -> Result<i64, Error> {
+        use db::schema::silo_group::dsl;
+
+        opctx.authorize(authz::Action::Read, authz_silo).await?;
+        dsl::silo_group
+            .filter(dsl::silo_id.eq(authz_silo.id()))
+            .filter(dsl::time_deleted.is_null())
+            .count()
+            .get_result_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
     pub async fn silo_groups_list_by_id(
         &self,
         opctx: &OpContext,
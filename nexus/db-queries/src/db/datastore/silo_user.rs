@@ -242,6 +242,122 @@ impl DataStore {
             .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
     }
 
+    /// List a Silo's users in a stable order, starting from a position in
+    /// that order rather than from a marker
+    ///
+    /// This is how SCIM clients page through a Silo's users.
+    pub async fn silo_users_list_by_offset(
+        &self,
+        opctx: &OpContext,
+        authz_silo_user_list: &authz::SiloUserList,
+        offset: i64,
+        limit: i64,
+    ) -> ListResultVec<SiloUser> {
+        use db::schema::silo_user::dsl;
+
+        opctx
+            .authorize(authz::Action::ListChildren, authz_silo_user_list)
+            .await?;
+
+        dsl::silo_user
+            .filter(dsl::silo_id.eq(authz_silo_user_list.silo().id()))
+            .filter(dsl::time_deleted.is_null())
+            .order(dsl::id)
+            .offset(offset)
+            .limit(limit)
+            .select(SiloUser::as_select())
+            .load_async::<SiloUser>(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    /// Count a Silo's users
+    pub async fn silo_users_count(
+        &self,
+        opctx: &OpContext,
+        authz_silo_user_list: &authz::SiloUserList,
+    ) -> Result<i64, Error> {
+        use db::schema::silo_user::dsl;
+
+        opctx
+            .authorize(authz::Action::ListChildren, authz_silo_user_list)
+            .await?;
+
+        dsl::silo_user
+            .filter(dsl::silo_id.eq(authz_silo_user_list.silo().id()))
+            .filter(dsl::time_deleted.is_null())
+            .count()
+            .get_result_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    /// Update a Silo user's external id and whether they are active
+    ///
+    /// Deactivating a user also revokes their console sessions and device
+    /// access tokens, so that they immediately lose access to the system
+    /// (rather than when they next try to log in).
+    pub async fn silo_user_update(
+        &self,
+        opctx: &OpContext,
+        authz_silo_user: &authz::SiloUser,
+        external_id: String,
+        active: bool,
+    ) -> UpdateResult<SiloUser> {
+        opctx.authorize(authz::Action::Modify, authz_silo_user).await?;
+
+        let authz_silo_user_id = authz_silo_user.id();
+        let conflict_name = external_id.clone();
+        self.pool_authorized(opctx)
+            .await?
+            .transaction_async(|conn| async move {
+                let db_silo_user = {
+                    use db::schema::silo_user::dsl;
+                    diesel::update(dsl::silo_user)
+                        .filter(dsl::id.eq(authz_silo_user_id))
+                        .filter(dsl::time_deleted.is_null())
+                        .set((
+                            dsl::external_id.eq(external_id),
+                            dsl::active.eq(active),
+                            dsl::time_modified.eq(Utc::now()),
+                        ))
+                        .returning(SiloUser::as_returning())
+                        .get_result_async(&conn)
+                        .await?
+                };
+
+                if !active {
+                    {
+                        use db::schema::console_session::dsl;
+                        diesel::delete(dsl::console_session)
+                            .filter(dsl::silo_user_id.eq(authz_silo_user_id))
+                            .execute_async(&conn)
+                            .await?;
+                    }
+
+                    {
+                        use db::schema::device_access_token::dsl;
+                        diesel::delete(dsl::device_access_token)
+                            .filter(dsl::silo_user_id.eq(authz_silo_user_id))
+                            .execute_async(&conn)
+                            .await?;
+                    }
+                }
+
+                Ok(db_silo_user)
+            })
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::Conflict(
+                        ResourceType::SiloUser,
+                        &conflict_name,
+                    ),
+                )
+            })
+    }
+
     /// Updates or deletes the password hash for a given Silo user
     ///
     /// If `password_hash` is `Some(...)`, the provided value is stored as the
@@ -4,6 +4,7 @@
 
 //! [`DataStore`] methods on [`Sled`]s.
 
+use super::affinity;
 use super::DataStore;
 use crate::authz;
 use crate::context::OpContext;
@@ -84,6 +85,7 @@ impl DataStore {
         #[derive(Debug)]
         enum SledReservationError {
             NotFound,
+            AffinityRuleViolated(String),
         }
         type TxnError = TransactionError<SledReservationError>;
 
@@ -151,7 +153,6 @@ impl DataStore {
                 sql_function!(fn random() -> diesel::sql_types::Float);
                 let sled_targets = sled_targets
                     .order(random())
-                    .limit(1)
                     .get_results_async::<Uuid>(&conn)
                     .await?;
 
@@ -161,11 +162,34 @@ impl DataStore {
                     ));
                 }
 
+                // Choose among the sleds according to the rules of the
+                // affinity groups the instance belongs to, or is joining.
+                //
+                // Loading the rules locks the groups, so this is serialized
+                // with other placements in them. A new instance's sled is only
+                // known to other placements once its record exists, though,
+                // so instance creation checks the rules again at that point
+                // (see `DataStore::instance_affinity_groups_join`).
+                let rules = Self::affinity_rules_on_connection(
+                    &conn,
+                    constraints.affinity_instance_id(),
+                    constraints.affinity_group_ids(),
+                )
+                .await?;
+                let sled_id = affinity::select_sled(&sled_targets, &rules)
+                    .map_err(|rule| {
+                        TxnError::CustomError(
+                            SledReservationError::AffinityRuleViolated(
+                                rule.describe(),
+                            ),
+                        )
+                    })?;
+
                 // Create a SledResource record, associate it with the target
                 // sled.
                 let resource = SledResource::new(
                     resource_id,
-                    sled_targets[0],
+                    sled_id,
                     resource_kind,
                     resources,
                 );
@@ -183,6 +207,13 @@ impl DataStore {
                         "No sleds can fit the requested instance",
                     )
                 }
+                TxnError::CustomError(
+                    SledReservationError::AffinityRuleViolated(rule),
+                ) => external::Error::conflict(&format!(
+                    "No sleds can fit the requested instance while \
+                    satisfying the rule of {}",
+                    rule
+                )),
                 TxnError::Pool(e) => {
                     public_error_from_diesel_pool(e, ErrorHandler::Server)
                 }
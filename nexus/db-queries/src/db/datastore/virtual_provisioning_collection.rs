@@ -12,6 +12,7 @@ use crate::db::error::ErrorHandler;
 use crate::db::model::ByteCount;
 use crate::db::model::VirtualProvisioningCollection;
 use crate::db::pool::DbConnection;
+use crate::db::queries::virtual_provisioning_collection_update;
 use crate::db::queries::virtual_provisioning_collection_update::VirtualProvisioningCollectionUpdate;
 use async_bb8_diesel::{AsyncRunQueryDsl, PoolError};
 use diesel::prelude::*;
@@ -194,6 +195,9 @@ impl DataStore {
     }
 
     /// Transitively updates all provisioned disk provisions from project -> fleet.
+    ///
+    /// Fails if the update would exceed the storage quota of the project or
+    /// its silo.
     async fn virtual_provisioning_collection_insert_storage(
         &self,
         opctx: &OpContext,
@@ -211,9 +215,7 @@ impl DataStore {
             )
             .get_results_async(self.pool_authorized(opctx).await?)
             .await
-            .map_err(|e| {
-                public_error_from_diesel_pool(e, ErrorHandler::Server)
-            })?;
+            .map_err(virtual_provisioning_collection_update::from_pool)?;
         self.virtual_provisioning_collection_producer
             .append_disk_metrics(&provisions)?;
         Ok(provisions)
@@ -235,6 +237,34 @@ impl DataStore {
         .await
     }
 
+    /// Transitively updates the provisioned size of a disk which is being
+    /// resized from project -> fleet.
+    ///
+    /// Fails if growing the disk would exceed the storage quota of the project
+    /// or its silo.
+    pub async fn virtual_provisioning_collection_update_disk(
+        &self,
+        opctx: &OpContext,
+        id: Uuid,
+        project_id: Uuid,
+        old_disk_bytes: ByteCount,
+        new_disk_bytes: ByteCount,
+    ) -> Result<Vec<VirtualProvisioningCollection>, Error> {
+        let provisions =
+            VirtualProvisioningCollectionUpdate::new_update_storage(
+                id,
+                old_disk_bytes,
+                new_disk_bytes,
+                project_id,
+            )
+            .get_results_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(virtual_provisioning_collection_update::from_pool)?;
+        self.virtual_provisioning_collection_producer
+            .append_disk_metrics(&provisions)?;
+        Ok(provisions)
+    }
+
     pub async fn virtual_provisioning_collection_delete_snapshot(
         &self,
         opctx: &OpContext,
@@ -276,6 +306,9 @@ impl DataStore {
     }
 
     /// Transitively updates all CPU/RAM provisions from project -> fleet.
+    ///
+    /// Fails if the update would exceed the CPU or RAM quota of the project or
+    /// its silo.
     pub async fn virtual_provisioning_collection_insert_instance(
         &self,
         opctx: &OpContext,
@@ -290,9 +323,7 @@ impl DataStore {
             )
             .get_results_async(self.pool_authorized(opctx).await?)
             .await
-            .map_err(|e| {
-                public_error_from_diesel_pool(e, ErrorHandler::Server)
-            })?;
+            .map_err(virtual_provisioning_collection_update::from_pool)?;
         self.virtual_provisioning_collection_producer
             .append_cpu_metrics(&provisions)?;
         Ok(provisions)
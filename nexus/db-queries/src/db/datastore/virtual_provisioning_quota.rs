@@ -0,0 +1,177 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! [`DataStore`] methods on [`VirtualProvisioningQuota`]s.
+
+use super::DataStore;
+use crate::authz;
+use crate::context::OpContext;
+use crate::db;
+use crate::db::error::diesel_pool_result_optional;
+use crate::db::error::public_error_from_diesel_pool;
+use crate::db::error::ErrorHandler;
+use crate::db::model::CollectionTypeProvisioned;
+use crate::db::model::VirtualProvisioningQuota;
+use crate::db::pool::DbConnection;
+use async_bb8_diesel::{AsyncRunQueryDsl, PoolError};
+use chrono::Utc;
+use diesel::prelude::*;
+use diesel::upsert::excluded;
+use omicron_common::api::external::DeleteResult;
+use omicron_common::api::external::Error;
+use omicron_common::api::external::LookupResult;
+use omicron_common::api::external::UpdateResult;
+use uuid::Uuid;
+
+impl DataStore {
+    /// Fetch the quota of a silo.
+    ///
+    /// Silos for which no quota has been set are unlimited.
+    pub async fn silo_quotas_view(
+        &self,
+        opctx: &OpContext,
+        authz_silo: &authz::Silo,
+    ) -> LookupResult<VirtualProvisioningQuota> {
+        opctx.authorize(authz::Action::Read, authz_silo).await?;
+        self.virtual_provisioning_quota_get(
+            opctx,
+            authz_silo.id(),
+            CollectionTypeProvisioned::Silo,
+        )
+        .await
+    }
+
+    /// Replace the quota of a silo.
+    ///
+    /// Only fleet operators may set the quota of a silo, as silo
+    /// administrators would otherwise be able to raise their own limits.
+    pub async fn silo_quotas_update(
+        &self,
+        opctx: &OpContext,
+        authz_silo: &authz::Silo,
+        quota: VirtualProvisioningQuota,
+    ) -> UpdateResult<VirtualProvisioningQuota> {
+        assert_eq!(authz_silo.id(), quota.id);
+        opctx.authorize(authz::Action::Read, authz_silo).await?;
+        opctx.authorize(authz::Action::Modify, &authz::FLEET).await?;
+        self.virtual_provisioning_quota_upsert(opctx, quota).await
+    }
+
+    /// Fetch the quota of a project.
+    ///
+    /// Projects for which no quota has been set are unlimited (but are still
+    /// subject to the quota of their silo).
+    pub async fn project_quotas_view(
+        &self,
+        opctx: &OpContext,
+        authz_project: &authz::Project,
+    ) -> LookupResult<VirtualProvisioningQuota> {
+        opctx.authorize(authz::Action::Read, authz_project).await?;
+        self.virtual_provisioning_quota_get(
+            opctx,
+            authz_project.id(),
+            CollectionTypeProvisioned::Project,
+        )
+        .await
+    }
+
+    /// Replace the quota of a project.
+    ///
+    /// Only those who may modify the project's silo may set the quota of a
+    /// project, as project collaborators would otherwise be able to raise
+    /// their own limits.
+    pub async fn project_quotas_update(
+        &self,
+        opctx: &OpContext,
+        authz_silo: &authz::Silo,
+        authz_project: &authz::Project,
+        quota: VirtualProvisioningQuota,
+    ) -> UpdateResult<VirtualProvisioningQuota> {
+        assert_eq!(authz_project.id(), quota.id);
+        opctx.authorize(authz::Action::Read, authz_project).await?;
+        opctx.authorize(authz::Action::Modify, authz_silo).await?;
+        self.virtual_provisioning_quota_upsert(opctx, quota).await
+    }
+
+    async fn virtual_provisioning_quota_get(
+        &self,
+        opctx: &OpContext,
+        id: Uuid,
+        collection_type: CollectionTypeProvisioned,
+    ) -> LookupResult<VirtualProvisioningQuota> {
+        use db::schema::virtual_provisioning_quota::dsl;
+
+        let quota = diesel_pool_result_optional(
+            dsl::virtual_provisioning_quota
+                .find(id)
+                .select(VirtualProvisioningQuota::as_select())
+                .get_result_async(self.pool_authorized(opctx).await?)
+                .await,
+        )
+        .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))?;
+
+        Ok(quota.unwrap_or_else(|| {
+            VirtualProvisioningQuota::unlimited(id, collection_type)
+        }))
+    }
+
+    async fn virtual_provisioning_quota_upsert(
+        &self,
+        opctx: &OpContext,
+        quota: VirtualProvisioningQuota,
+    ) -> UpdateResult<VirtualProvisioningQuota> {
+        use db::schema::virtual_provisioning_quota::dsl;
+
+        if quota.cpus_limit.map(|cpus| cpus < 0).unwrap_or(false) {
+            return Err(Error::InvalidValue {
+                label: String::from("cpus"),
+                message: String::from("quota limits must not be negative"),
+            });
+        }
+
+        diesel::insert_into(dsl::virtual_provisioning_quota)
+            .values(quota)
+            .on_conflict(dsl::id)
+            .do_update()
+            .set((
+                dsl::time_modified.eq(Utc::now()),
+                dsl::virtual_disk_bytes_limit
+                    .eq(excluded(dsl::virtual_disk_bytes_limit)),
+                dsl::cpus_limit.eq(excluded(dsl::cpus_limit)),
+                dsl::ram_limit.eq(excluded(dsl::ram_limit)),
+            ))
+            .returning(VirtualProvisioningQuota::as_returning())
+            .get_result_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    /// Delete the quota (if any) of a collection which is being deleted.
+    pub(crate) async fn virtual_provisioning_quota_delete_on_connection<
+        ConnErr,
+    >(
+        &self,
+        conn: &(impl async_bb8_diesel::AsyncConnection<DbConnection, ConnErr>
+              + Sync),
+        id: Uuid,
+    ) -> DeleteResult
+    where
+        ConnErr: From<diesel::result::Error> + Send + 'static,
+        PoolError: From<ConnErr>,
+    {
+        use db::schema::virtual_provisioning_quota::dsl;
+
+        diesel::delete(dsl::virtual_provisioning_quota)
+            .filter(dsl::id.eq(id))
+            .execute_async(conn)
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    PoolError::from(e),
+                    ErrorHandler::Server,
+                )
+            })?;
+        Ok(())
+    }
+}
@@ -1725,6 +1725,7 @@ mod tests {
             network_interfaces: InstanceNetworkInterfaceAttachment::None,
             external_ips: vec![],
             disks: vec![],
+            affinity_groups: vec![],
             start: true,
         };
         let runtime = InstanceRuntimeState {
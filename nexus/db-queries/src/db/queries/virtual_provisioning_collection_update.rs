@@ -11,18 +11,70 @@ use crate::db::model::VirtualProvisioningCollection;
 use crate::db::model::VirtualProvisioningResource;
 use crate::db::pool::DbConnection;
 use crate::db::schema::virtual_provisioning_collection;
+use crate::db::schema::virtual_provisioning_quota;
 use crate::db::schema::virtual_provisioning_resource;
 use crate::db::subquery::{AsQuerySource, Cte, CteBuilder, CteQuery};
+use crate::db::true_or_cast_error::{matches_sentinel, TrueOrCastError};
 use db_macros::Subquery;
 use diesel::pg::Pg;
 use diesel::query_builder::{AstPass, Query, QueryFragment, QueryId};
 use diesel::{
-    sql_types, CombineDsl, ExpressionMethods, IntoSql,
-    NullableExpressionMethods, QueryDsl, RunQueryDsl, SelectableHelper,
+    sql_types, BoolExpressionMethods, CombineDsl, ExpressionMethods, IntoSql,
+    JoinOnDsl, NullableExpressionMethods, QueryDsl, RunQueryDsl,
+    SelectableHelper,
 };
 use nexus_db_model::queries::virtual_provisioning_collection_update::{
     all_collections, do_update, parent_silo,
 };
+use omicron_common::api::external;
+
+const NOT_ENOUGH_CPUS_SENTINEL: &'static str = "Not enough cpus";
+const NOT_ENOUGH_MEMORY_SENTINEL: &'static str = "Not enough memory";
+const NOT_ENOUGH_STORAGE_SENTINEL: &'static str = "Not enough storage";
+
+/// Translates a generic pool error to an external error based
+/// on messages which may be emitted when a provisioning update would exceed
+/// the quota of a project or silo.
+pub fn from_pool(e: async_bb8_diesel::PoolError) -> external::Error {
+    use crate::db::error;
+
+    let sentinels = [
+        NOT_ENOUGH_CPUS_SENTINEL,
+        NOT_ENOUGH_MEMORY_SENTINEL,
+        NOT_ENOUGH_STORAGE_SENTINEL,
+    ];
+    if let Some(sentinel) = matches_sentinel(&e, &sentinels) {
+        match sentinel {
+            NOT_ENOUGH_CPUS_SENTINEL => {
+                return external::Error::InvalidRequest {
+                    message: "vCPU quota exceeded: the project or silo does \
+                        not have enough vCPUs available for this request"
+                        .to_string(),
+                };
+            }
+            NOT_ENOUGH_MEMORY_SENTINEL => {
+                return external::Error::InvalidRequest {
+                    message: "memory quota exceeded: the project or silo \
+                        does not have enough memory available for this \
+                        request"
+                        .to_string(),
+                };
+            }
+            NOT_ENOUGH_STORAGE_SENTINEL => {
+                return external::Error::InvalidRequest {
+                    message: "storage quota exceeded: the project or silo \
+                        does not have enough storage available for this \
+                        request"
+                        .to_string(),
+                };
+            }
+            // Fall-through to the generic error conversion.
+            _ => {}
+        }
+    }
+
+    error::public_error_from_diesel_pool(e, error::ErrorHandler::Server)
+}
 
 #[derive(Subquery, QueryId)]
 #[subquery(name = parent_silo)]
@@ -82,11 +134,114 @@ struct DoUpdate {
 }
 
 impl DoUpdate {
-    fn new_for_insert(id: uuid::Uuid) -> Self {
+    // Inserting a resource which consumes storage is permitted if:
+    // - The resource has not already been inserted (for idempotency), and
+    // - No collection containing the resource would exceed its storage quota.
+    //
+    // A resource which has already been inserted is never considered to
+    // exceed a quota, as its usage has already been accounted for.
+    fn new_for_insert_storage(
+        id: uuid::Uuid,
+        all_collections: &AllCollections,
+        disk_byte_diff: ByteCount,
+    ) -> Self {
+        use virtual_provisioning_collection::dsl as collection_dsl;
+        use virtual_provisioning_quota::dsl as quota_dsl;
         use virtual_provisioning_resource::dsl;
 
-        let not_allocted = dsl::virtual_provisioning_resource
-            .find(id)
+        let allocated_count = || {
+            dsl::virtual_provisioning_resource
+                .find(id)
+                .count()
+                .single_value()
+                .assume_not_null()
+        };
+
+        let within_storage_quota =
+            collection_dsl::virtual_provisioning_collection
+                .inner_join(
+                    quota_dsl::virtual_provisioning_quota
+                        .on(quota_dsl::id.eq(collection_dsl::id)),
+                )
+                .filter(collection_dsl::id.eq_any(
+                    all_collections.query_source().select(all_collections::id),
+                ))
+                .filter(
+                    (collection_dsl::virtual_disk_bytes_provisioned
+                        + disk_byte_diff)
+                        .nullable()
+                        .gt(quota_dsl::virtual_disk_bytes_limit),
+                )
+                .count()
+                .single_value()
+                .assume_not_null()
+                .eq(0);
+
+        Self {
+            query: Box::new(diesel::select((ExpressionAlias::new::<
+                do_update::update,
+            >(
+                allocated_count().eq(0).and(TrueOrCastError::new(
+                    allocated_count().eq(1).or(within_storage_quota),
+                    NOT_ENOUGH_STORAGE_SENTINEL,
+                )),
+            ),))),
+        }
+    }
+
+    // Inserting an instance is permitted if:
+    // - The instance has not already been inserted (for idempotency), and
+    // - No collection containing the instance would exceed its CPU or RAM
+    // quota.
+    fn new_for_insert_instance(
+        id: uuid::Uuid,
+        all_collections: &AllCollections,
+        cpus_diff: i64,
+        ram_diff: ByteCount,
+    ) -> Self {
+        use virtual_provisioning_collection::dsl as collection_dsl;
+        use virtual_provisioning_quota::dsl as quota_dsl;
+        use virtual_provisioning_resource::dsl;
+
+        let allocated_count = || {
+            dsl::virtual_provisioning_resource
+                .find(id)
+                .count()
+                .single_value()
+                .assume_not_null()
+        };
+
+        let within_cpus_quota = collection_dsl::virtual_provisioning_collection
+            .inner_join(
+                quota_dsl::virtual_provisioning_quota
+                    .on(quota_dsl::id.eq(collection_dsl::id)),
+            )
+            .filter(collection_dsl::id.eq_any(
+                all_collections.query_source().select(all_collections::id),
+            ))
+            .filter(
+                (collection_dsl::cpus_provisioned + cpus_diff)
+                    .nullable()
+                    .gt(quota_dsl::cpus_limit),
+            )
+            .count()
+            .single_value()
+            .assume_not_null()
+            .eq(0);
+
+        let within_ram_quota = collection_dsl::virtual_provisioning_collection
+            .inner_join(
+                quota_dsl::virtual_provisioning_quota
+                    .on(quota_dsl::id.eq(collection_dsl::id)),
+            )
+            .filter(collection_dsl::id.eq_any(
+                all_collections.query_source().select(all_collections::id),
+            ))
+            .filter(
+                (collection_dsl::ram_provisioned + ram_diff)
+                    .nullable()
+                    .gt(quota_dsl::ram_limit),
+            )
             .count()
             .single_value()
             .assume_not_null()
@@ -95,7 +250,161 @@ impl DoUpdate {
         Self {
             query: Box::new(diesel::select((ExpressionAlias::new::<
                 do_update::update,
-            >(not_allocted),))),
+            >(
+                allocated_count()
+                    .eq(0)
+                    .and(TrueOrCastError::new(
+                        allocated_count().eq(1).or(within_cpus_quota),
+                        NOT_ENOUGH_CPUS_SENTINEL,
+                    ))
+                    .and(TrueOrCastError::new(
+                        allocated_count().eq(1).or(within_ram_quota),
+                        NOT_ENOUGH_MEMORY_SENTINEL,
+                    )),
+            ),))),
+        }
+    }
+
+    // Resizing a resource which consumes storage is permitted if:
+    // - The resource's provisioning record still holds its old size (for
+    // idempotency), and
+    // - No collection containing the resource would exceed its storage quota
+    // as a result of growing the resource.
+    fn new_for_update_storage(
+        id: uuid::Uuid,
+        all_collections: &AllCollections,
+        old_disk_bytes: ByteCount,
+        disk_byte_diff: i64,
+    ) -> Self {
+        use virtual_provisioning_collection::dsl as collection_dsl;
+        use virtual_provisioning_quota::dsl as quota_dsl;
+        use virtual_provisioning_resource::dsl;
+
+        let pending_count = || {
+            dsl::virtual_provisioning_resource
+                .filter(dsl::id.eq(id))
+                .filter(dsl::virtual_disk_bytes_provisioned.eq(old_disk_bytes))
+                .count()
+                .single_value()
+                .assume_not_null()
+        };
+
+        let within_storage_quota =
+            collection_dsl::virtual_provisioning_collection
+                .inner_join(
+                    quota_dsl::virtual_provisioning_quota
+                        .on(quota_dsl::id.eq(collection_dsl::id)),
+                )
+                .filter(collection_dsl::id.eq_any(
+                    all_collections.query_source().select(all_collections::id),
+                ))
+                .filter(disk_byte_diff.into_sql::<sql_types::BigInt>().gt(0))
+                .filter(
+                    (collection_dsl::virtual_disk_bytes_provisioned
+                        + disk_byte_diff)
+                        .nullable()
+                        .gt(quota_dsl::virtual_disk_bytes_limit),
+                )
+                .count()
+                .single_value()
+                .assume_not_null()
+                .eq(0);
+
+        Self {
+            query: Box::new(diesel::select((ExpressionAlias::new::<
+                do_update::update,
+            >(
+                pending_count().eq(1).and(TrueOrCastError::new(
+                    pending_count().eq(0).or(within_storage_quota),
+                    NOT_ENOUGH_STORAGE_SENTINEL,
+                )),
+            ),))),
+        }
+    }
+
+    // Resizing an instance is permitted if:
+    // - The instance's provisioning record still holds its old size (for
+    // idempotency), and
+    // - No collection containing the instance would exceed its CPU or RAM
+    // quota as a result of growing the instance.
+    //
+    // Shrinking an instance is never considered to exceed a quota, even if
+    // the quota has been lowered below the current usage.
+    fn new_for_update_instance(
+        id: uuid::Uuid,
+        all_collections: &AllCollections,
+        old_cpus: i64,
+        old_ram: ByteCount,
+        cpus_diff: i64,
+        ram_diff: i64,
+    ) -> Self {
+        use virtual_provisioning_collection::dsl as collection_dsl;
+        use virtual_provisioning_quota::dsl as quota_dsl;
+        use virtual_provisioning_resource::dsl;
+
+        let pending_count = || {
+            dsl::virtual_provisioning_resource
+                .filter(dsl::id.eq(id))
+                .filter(dsl::cpus_provisioned.eq(old_cpus))
+                .filter(dsl::ram_provisioned.eq(old_ram))
+                .count()
+                .single_value()
+                .assume_not_null()
+        };
+
+        let within_cpus_quota = collection_dsl::virtual_provisioning_collection
+            .inner_join(
+                quota_dsl::virtual_provisioning_quota
+                    .on(quota_dsl::id.eq(collection_dsl::id)),
+            )
+            .filter(collection_dsl::id.eq_any(
+                all_collections.query_source().select(all_collections::id),
+            ))
+            .filter(cpus_diff.into_sql::<sql_types::BigInt>().gt(0))
+            .filter(
+                (collection_dsl::cpus_provisioned + cpus_diff)
+                    .nullable()
+                    .gt(quota_dsl::cpus_limit),
+            )
+            .count()
+            .single_value()
+            .assume_not_null()
+            .eq(0);
+
+        let within_ram_quota = collection_dsl::virtual_provisioning_collection
+            .inner_join(
+                quota_dsl::virtual_provisioning_quota
+                    .on(quota_dsl::id.eq(collection_dsl::id)),
+            )
+            .filter(collection_dsl::id.eq_any(
+                all_collections.query_source().select(all_collections::id),
+            ))
+            .filter(ram_diff.into_sql::<sql_types::BigInt>().gt(0))
+            .filter(
+                (collection_dsl::ram_provisioned + ram_diff)
+                    .nullable()
+                    .gt(quota_dsl::ram_limit),
+            )
+            .count()
+            .single_value()
+            .assume_not_null()
+            .eq(0);
+
+        Self {
+            query: Box::new(diesel::select((ExpressionAlias::new::<
+                do_update::update,
+            >(
+                pending_count()
+                    .eq(1)
+                    .and(TrueOrCastError::new(
+                        pending_count().eq(0).or(within_cpus_quota),
+                        NOT_ENOUGH_CPUS_SENTINEL,
+                    ))
+                    .and(TrueOrCastError::new(
+                        pending_count().eq(0).or(within_ram_quota),
+                        NOT_ENOUGH_MEMORY_SENTINEL,
+                    )),
+            ),))),
         }
     }
 
@@ -212,15 +521,17 @@ impl VirtualProvisioningCollectionUpdate {
     // - Fleet
     //
     // Arguments:
-    // - do_update: A boolean SQL query to answer the question: "Should this update
-    // be applied"? This query is necessary for idempotency.
+    // - do_update: Constructs a boolean SQL query to answer the question:
+    // "Should this update be applied"? This query is necessary for idempotency,
+    // and may also reject the update (by returning an error) if it would
+    // exceed the quota of any of the provided collections.
     // - update: A SQL query to actually modify the resource record. Generally
     // this is an "INSERT", "UPDATE", or "DELETE".
     // - project_id: The project to which the resource belongs.
     // - values: The updated values to propagate through collections (iff
     // "do_update" evaluates to "true").
     fn apply_update<U, V>(
-        do_update: DoUpdate,
+        do_update: impl FnOnce(&AllCollections) -> DoUpdate,
         update: U,
         project_id: uuid::Uuid,
         values: V,
@@ -237,6 +548,7 @@ impl VirtualProvisioningCollectionUpdate {
             &parent_silo,
             *crate::db::fixed_data::FLEET_ID,
         );
+        let do_update = do_update(&all_collections);
         let updated_collections =
             UpdatedProvisions::new(&all_collections, &do_update, values);
 
@@ -273,8 +585,15 @@ impl VirtualProvisioningCollectionUpdate {
         provision.virtual_disk_bytes_provisioned = disk_byte_diff;
 
         Self::apply_update(
-            // We should insert the record if it does not already exist.
-            DoUpdate::new_for_insert(id),
+            // We should insert the record if it does not already exist, and
+            // if it fits within the storage quota of each collection.
+            |all_collections| {
+                DoUpdate::new_for_insert_storage(
+                    id,
+                    all_collections,
+                    disk_byte_diff,
+                )
+            },
             // The query to actually insert the record.
             UnreferenceableSubquery(
                 diesel::insert_into(
@@ -306,7 +625,7 @@ impl VirtualProvisioningCollectionUpdate {
 
         Self::apply_update(
             // We should delete the record if it exists.
-            DoUpdate::new_for_delete(id),
+            |_| DoUpdate::new_for_delete(id),
             // The query to actually delete the record.
             UnreferenceableSubquery(
                 diesel::delete(resource_dsl::virtual_provisioning_resource)
@@ -325,6 +644,57 @@ impl VirtualProvisioningCollectionUpdate {
         )
     }
 
+    pub fn new_update_storage(
+        id: uuid::Uuid,
+        old_disk_bytes: ByteCount,
+        new_disk_bytes: ByteCount,
+        project_id: uuid::Uuid,
+    ) -> Self {
+        use virtual_provisioning_collection::dsl as collection_dsl;
+        use virtual_provisioning_resource::dsl as resource_dsl;
+
+        let disk_byte_diff =
+            i64::from(new_disk_bytes) - i64::from(old_disk_bytes);
+
+        Self::apply_update(
+            // We should update the record if it still holds the old size, and
+            // if the new size fits within the storage quota of each
+            // collection.
+            |all_collections| {
+                DoUpdate::new_for_update_storage(
+                    id,
+                    all_collections,
+                    old_disk_bytes,
+                    disk_byte_diff,
+                )
+            },
+            // The query to actually update the record.
+            UnreferenceableSubquery(
+                diesel::update(resource_dsl::virtual_provisioning_resource)
+                    .filter(resource_dsl::id.eq(id))
+                    .filter(
+                        resource_dsl::virtual_disk_bytes_provisioned
+                            .eq(old_disk_bytes),
+                    )
+                    .set((
+                        resource_dsl::time_modified.eq(diesel::dsl::now),
+                        resource_dsl::virtual_disk_bytes_provisioned
+                            .eq(new_disk_bytes),
+                    ))
+                    .returning(virtual_provisioning_resource::all_columns),
+            ),
+            // Within this project, silo, fleet...
+            project_id,
+            // ... We apply the change in disk usage.
+            (
+                collection_dsl::time_modified.eq(diesel::dsl::now),
+                collection_dsl::virtual_disk_bytes_provisioned
+                    .eq(collection_dsl::virtual_disk_bytes_provisioned
+                        + disk_byte_diff),
+            ),
+        )
+    }
+
     pub fn new_insert_instance(
         id: uuid::Uuid,
         cpus_diff: i64,
@@ -342,8 +712,16 @@ impl VirtualProvisioningCollectionUpdate {
         provision.ram_provisioned = ram_diff;
 
         Self::apply_update(
-            // We should insert the record if it does not already exist.
-            DoUpdate::new_for_insert(id),
+            // We should insert the record if it does not already exist, and
+            // if it fits within the CPU and RAM quota of each collection.
+            |all_collections| {
+                DoUpdate::new_for_insert_instance(
+                    id,
+                    all_collections,
+                    cpus_diff,
+                    ram_diff,
+                )
+            },
             // The query to actually insert the record.
             UnreferenceableSubquery(
                 diesel::insert_into(
@@ -366,6 +744,59 @@ impl VirtualProvisioningCollectionUpdate {
         )
     }
 
+    pub fn new_update_instance(
+        id: uuid::Uuid,
+        old_cpus: i64,
+        old_ram: ByteCount,
+        new_cpus: i64,
+        new_ram: ByteCount,
+        project_id: uuid::Uuid,
+    ) -> Self {
+        use virtual_provisioning_collection::dsl as collection_dsl;
+        use virtual_provisioning_resource::dsl as resource_dsl;
+
+        let cpus_diff = new_cpus - old_cpus;
+        let ram_diff = i64::from(new_ram) - i64::from(old_ram);
+
+        Self::apply_update(
+            // We should update the record if it still holds the old size, and
+            // if the new size fits within the quota of each collection.
+            |all_collections| {
+                DoUpdate::new_for_update_instance(
+                    id,
+                    all_collections,
+                    old_cpus,
+                    old_ram,
+                    cpus_diff,
+                    ram_diff,
+                )
+            },
+            // The query to actually update the record.
+            UnreferenceableSubquery(
+                diesel::update(resource_dsl::virtual_provisioning_resource)
+                    .filter(resource_dsl::id.eq(id))
+                    .filter(resource_dsl::cpus_provisioned.eq(old_cpus))
+                    .filter(resource_dsl::ram_provisioned.eq(old_ram))
+                    .set((
+                        resource_dsl::time_modified.eq(diesel::dsl::now),
+                        resource_dsl::cpus_provisioned.eq(new_cpus),
+                        resource_dsl::ram_provisioned.eq(new_ram),
+                    ))
+                    .returning(virtual_provisioning_resource::all_columns),
+            ),
+            // Within this project, silo, fleet...
+            project_id,
+            // ... We apply the change in resource usage.
+            (
+                collection_dsl::time_modified.eq(diesel::dsl::now),
+                collection_dsl::cpus_provisioned
+                    .eq(collection_dsl::cpus_provisioned + cpus_diff),
+                collection_dsl::ram_provisioned
+                    .eq(collection_dsl::ram_provisioned + ram_diff),
+            ),
+        )
+    }
+
     pub fn new_delete_instance(
         id: uuid::Uuid,
         cpus_diff: i64,
@@ -377,7 +808,7 @@ impl VirtualProvisioningCollectionUpdate {
 
         Self::apply_update(
             // We should delete the record if it exists.
-            DoUpdate::new_for_delete(id),
+            |_| DoUpdate::new_for_delete(id),
             // The query to actually delete the record.
             UnreferenceableSubquery(
                 diesel::delete(resource_dsl::virtual_provisioning_resource)
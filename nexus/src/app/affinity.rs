@@ -0,0 +1,200 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Affinity groups, which constrain where instances are placed
+
+use crate::external_api::params;
+use nexus_db_queries::authz;
+use nexus_db_queries::context::OpContext;
+use nexus_db_queries::db;
+use nexus_db_queries::db::lookup;
+use nexus_db_queries::db::lookup::LookupPath;
+use omicron_common::api::external::http_pagination::PaginatedBy;
+use omicron_common::api::external::CreateResult;
+use omicron_common::api::external::DeleteResult;
+use omicron_common::api::external::Error;
+use omicron_common::api::external::ListResultVec;
+use omicron_common::api::external::LookupResult;
+use omicron_common::api::external::NameOrId;
+use omicron_common::api::external::UpdateResult;
+use uuid::Uuid;
+
+impl super::Nexus {
+    pub(crate) fn affinity_group_lookup<'a>(
+        &'a self,
+        opctx: &'a OpContext,
+        group_selector: params::AffinityGroupSelector,
+    ) -> LookupResult<lookup::AffinityGroup<'a>> {
+        match group_selector {
+            params::AffinityGroupSelector {
+                affinity_group: NameOrId::Id(id),
+                project: None,
+            } => {
+                let group = LookupPath::new(opctx, &self.db_datastore)
+                    .affinity_group_id(id);
+                Ok(group)
+            }
+            params::AffinityGroupSelector {
+                affinity_group: NameOrId::Name(name),
+                project: Some(project),
+            } => {
+                let group = self
+                    .project_lookup(opctx, params::ProjectSelector { project })?
+                    .affinity_group_name_owned(name.into());
+                Ok(group)
+            }
+            params::AffinityGroupSelector {
+                affinity_group: NameOrId::Id(_),
+                ..
+            } => Err(Error::invalid_request(
+                "when providing affinity group as an ID project should not be specified",
+            )),
+            _ => Err(Error::invalid_request(
+                "affinity group should either be UUID or project should be specified",
+            )),
+        }
+    }
+
+    pub(crate) async fn affinity_group_list(
+        &self,
+        opctx: &OpContext,
+        project_lookup: &lookup::Project<'_>,
+        pagparams: &PaginatedBy<'_>,
+    ) -> ListResultVec<db::model::AffinityGroup> {
+        let (.., authz_project) =
+            project_lookup.lookup_for(authz::Action::ListChildren).await?;
+        self.db_datastore
+            .affinity_group_list(opctx, &authz_project, pagparams)
+            .await
+    }
+
+    pub(crate) async fn affinity_group_create(
+        &self,
+        opctx: &OpContext,
+        project_lookup: &lookup::Project<'_>,
+        params: params::AffinityGroupCreate,
+    ) -> CreateResult<db::model::AffinityGroup> {
+        let (.., authz_project) =
+            project_lookup.lookup_for(authz::Action::CreateChild).await?;
+        let group = db::model::AffinityGroup::new(authz_project.id(), params);
+        self.db_datastore
+            .affinity_group_create(opctx, &authz_project, group)
+            .await
+    }
+
+    pub(crate) async fn affinity_group_delete(
+        &self,
+        opctx: &OpContext,
+        group_lookup: &lookup::AffinityGroup<'_>,
+    ) -> DeleteResult {
+        let (.., authz_group) =
+            group_lookup.lookup_for(authz::Action::Delete).await?;
+        self.db_datastore.affinity_group_delete(opctx, &authz_group).await
+    }
+
+    pub(crate) async fn affinity_group_member_list(
+        &self,
+        opctx: &OpContext,
+        group_lookup: &lookup::AffinityGroup<'_>,
+        pagparams: &PaginatedBy<'_>,
+    ) -> ListResultVec<db::model::Instance> {
+        let (.., authz_group) =
+            group_lookup.lookup_for(authz::Action::ListChildren).await?;
+        self.db_datastore
+            .affinity_group_member_list(opctx, &authz_group, pagparams)
+            .await
+    }
+
+    /// Adds an existing instance to an affinity group in the same project.
+    ///
+    /// Instances are only placed when they're created or migrated, so adding
+    /// an instance to a group doesn't move it. If the group's rule is a hard
+    /// one, the instance must already be on a sled that satisfies it.
+    pub(crate) async fn affinity_group_member_add(
+        &self,
+        opctx: &OpContext,
+        group_lookup: &lookup::AffinityGroup<'_>,
+        member: params::AffinityGroupMember,
+    ) -> UpdateResult<()> {
+        let (.., authz_group, db_group) =
+            group_lookup.fetch_for(authz::Action::Modify).await?;
+        let authz_instance = self
+            .affinity_group_member_lookup(opctx, &db_group, member.instance)
+            .await?;
+        self.db_datastore
+            .affinity_group_member_add(opctx, &authz_group, &authz_instance)
+            .await
+    }
+
+    pub(crate) async fn affinity_group_member_remove(
+        &self,
+        opctx: &OpContext,
+        group_lookup: &lookup::AffinityGroup<'_>,
+        member: params::AffinityGroupMember,
+    ) -> DeleteResult {
+        let (.., authz_group, db_group) =
+            group_lookup.fetch_for(authz::Action::Modify).await?;
+        let authz_instance = self
+            .affinity_group_member_lookup(opctx, &db_group, member.instance)
+            .await?;
+        self.db_datastore
+            .affinity_group_member_remove(opctx, &authz_group, &authz_instance)
+            .await
+    }
+
+    /// Resolves the affinity groups named when creating an instance to their
+    /// IDs. The groups must be in the instance's project.
+    pub(crate) async fn affinity_groups_resolve(
+        &self,
+        opctx: &OpContext,
+        authz_project: &authz::Project,
+        groups: &[NameOrId],
+    ) -> LookupResult<Vec<Uuid>> {
+        let mut group_ids = Vec::with_capacity(groups.len());
+        for group in groups {
+            let group_lookup = match group {
+                NameOrId::Id(id) => LookupPath::new(opctx, &self.db_datastore)
+                    .affinity_group_id(*id),
+                NameOrId::Name(name) => {
+                    LookupPath::new(opctx, &self.db_datastore)
+                        .project_id(authz_project.id())
+                        .affinity_group_name_owned(name.clone().into())
+                }
+            };
+            let (.., group_project, authz_group) =
+                group_lookup.lookup_for(authz::Action::Modify).await?;
+            if group_project.id() != authz_project.id() {
+                return Err(Error::invalid_request(
+                    "affinity groups must be in the same project as the instance",
+                ));
+            }
+            group_ids.push(authz_group.id());
+        }
+        Ok(group_ids)
+    }
+
+    async fn affinity_group_member_lookup(
+        &self,
+        opctx: &OpContext,
+        db_group: &db::model::AffinityGroup,
+        instance: NameOrId,
+    ) -> LookupResult<authz::Instance> {
+        let instance_lookup = match instance {
+            NameOrId::Id(id) => {
+                LookupPath::new(opctx, &self.db_datastore).instance_id(id)
+            }
+            NameOrId::Name(name) => LookupPath::new(opctx, &self.db_datastore)
+                .project_id(db_group.project_id)
+                .instance_name_owned(name.into()),
+        };
+        let (.., authz_project, authz_instance) =
+            instance_lookup.lookup_for(authz::Action::Modify).await?;
+        if authz_project.id() != db_group.project_id {
+            return Err(Error::invalid_request(
+                "affinity group and instance must be in the same project",
+            ));
+        }
+        Ok(authz_instance)
+    }
+}
@@ -0,0 +1,71 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Audit log of external API requests
+
+use chrono::{DateTime, Utc};
+use nexus_db_queries::context::OpContext;
+use nexus_db_queries::db::model::AuditLogEntry;
+use omicron_common::api::external::CreateResult;
+use omicron_common::api::external::DataPageParams;
+use omicron_common::api::external::ListResultVec;
+use omicron_common::api::external::UpdateResult;
+use uuid::Uuid;
+
+impl super::Nexus {
+    /// Record the start of a request made by the given actor (if any)
+    ///
+    /// Entries are written on behalf of every actor, regardless of their
+    /// privileges (or whether they authenticated at all), so this uses the
+    /// external authentication context.
+    pub(crate) async fn audit_log_entry_init(
+        &self,
+        request_id: String,
+        operation_id: &str,
+        http_method: String,
+        request_uri: String,
+        actor_id: Option<Uuid>,
+        actor_silo_id: Option<Uuid>,
+    ) -> CreateResult<AuditLogEntry> {
+        let entry = AuditLogEntry::new(
+            request_id,
+            operation_id.to_string(),
+            http_method,
+            request_uri,
+            actor_id,
+            actor_silo_id,
+        );
+        self.db_datastore
+            .audit_log_entry_init(&self.opctx_external_authn, entry)
+            .await
+    }
+
+    /// Record the result of a request whose start was recorded with
+    /// [`Self::audit_log_entry_init()`]
+    pub(crate) async fn audit_log_entry_complete(
+        &self,
+        entry_id: Uuid,
+        http_status_code: Option<u16>,
+    ) -> UpdateResult<()> {
+        self.db_datastore
+            .audit_log_entry_complete(
+                &self.opctx_external_authn,
+                entry_id,
+                http_status_code,
+            )
+            .await
+    }
+
+    pub(crate) async fn audit_log_list(
+        &self,
+        opctx: &OpContext,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+        pagparams: &DataPageParams<'_, (DateTime<Utc>, Uuid)>,
+    ) -> ListResultVec<AuditLogEntry> {
+        self.db_datastore
+            .audit_log_list(opctx, start_time, end_time, pagparams)
+            .await
+    }
+}
@@ -9,6 +9,7 @@ use super::dns_config;
 use super::dns_propagation;
 use super::dns_servers;
 use super::external_endpoints;
+use super::push_producer_gc;
 use nexus_db_model::DnsGroup;
 use nexus_db_queries::context::OpContext;
 use nexus_db_queries::db::DataStore;
@@ -42,6 +43,9 @@ pub struct BackgroundTasks {
     pub external_endpoints: tokio::sync::watch::Receiver<
         Option<external_endpoints::ExternalEndpoints>,
     >,
+
+    /// task handle for the task that deletes expired push producers
+    pub task_push_producer_gc: common::TaskHandle,
 }
 
 impl BackgroundTasks {
@@ -70,8 +74,9 @@ impl BackgroundTasks {
 
         // Background task: External endpoints list watcher
         let (task_external_endpoints, external_endpoints) = {
-            let watcher =
-                external_endpoints::ExternalEndpointsWatcher::new(datastore);
+            let watcher = external_endpoints::ExternalEndpointsWatcher::new(
+                datastore.clone(),
+            );
             let watcher_channel = watcher.watcher();
             let task = driver.register(
                 String::from("external_endpoints"),
@@ -88,6 +93,19 @@ impl BackgroundTasks {
             (task, watcher_channel)
         };
 
+        // Background task: expired push producer cleanup
+        let task_push_producer_gc = driver.register(
+            String::from("push_producer_gc"),
+            String::from(
+                "deletes the records of metric producers that push samples to \
+                a collector once their registrations have expired",
+            ),
+            config.push_producer_gc.period_secs,
+            Box::new(push_producer_gc::PushProducerGc::new(datastore)),
+            opctx.child(BTreeMap::new()),
+            vec![],
+        );
+
         BackgroundTasks {
             driver,
             task_internal_dns_config,
@@ -96,6 +114,7 @@ impl BackgroundTasks {
             task_external_dns_servers,
             task_external_endpoints,
             external_endpoints,
+            task_push_producer_gc,
         }
     }
 
@@ -263,6 +282,9 @@ pub mod test {
             store,
             &dns_server::dns_server::Config {
                 bind_address: "[::1]:0".parse().unwrap(),
+                transfer_peers: vec![],
+                notify_secondaries: vec![],
+                rate_limit: Default::default(),
             },
             &dropshot::ConfigDropshot {
                 bind_address: "[::1]:0".parse().unwrap(),
@@ -0,0 +1,71 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Background task for deleting the records of metric producers that push
+//! their samples to a collector, once their registrations have expired
+//!
+//! Push producers are often short-lived processes, each of which registers
+//! under a new ID, so their records would otherwise accumulate forever.
+
+use super::common::BackgroundTask;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use nexus_db_queries::context::OpContext;
+use nexus_db_queries::db::DataStore;
+use serde_json::json;
+use std::sync::Arc;
+
+/// Background task that deletes expired push producers
+pub struct PushProducerGc {
+    datastore: Arc<DataStore>,
+}
+
+impl PushProducerGc {
+    pub fn new(datastore: Arc<DataStore>) -> PushProducerGc {
+        PushProducerGc { datastore }
+    }
+}
+
+impl BackgroundTask for PushProducerGc {
+    fn activate<'a, 'b, 'c>(
+        &'a mut self,
+        opctx: &'b OpContext,
+    ) -> BoxFuture<'c, serde_json::Value>
+    where
+        'a: 'c,
+        'b: 'c,
+    {
+        async {
+            let log = &opctx.log;
+            match self.datastore.push_producers_delete_expired().await {
+                Ok(ndeleted) => {
+                    if ndeleted > 0 {
+                        info!(
+                            &log,
+                            "deleted expired push producers";
+                            "count" => ndeleted,
+                        );
+                    }
+                    json!({ "deleted": ndeleted })
+                }
+                Err(error) => {
+                    warn!(
+                        &log,
+                        "failed to delete expired push producers";
+                        "error" => format!("{:#}", error)
+                    );
+                    json!({
+                        "error":
+                            format!(
+                                "failed to delete expired push producers: \
+                                {:#}",
+                                error
+                            )
+                    })
+                }
+            }
+        }
+        .boxed()
+    }
+}
@@ -45,17 +45,25 @@
 //! but that may change in the future.
 
 use crate::external_api::device_auth::DeviceAccessTokenResponse;
+use crate::external_api::params;
+use anyhow::anyhow;
 use nexus_db_queries::authn::{Actor, Reason};
 use nexus_db_queries::authz;
 use nexus_db_queries::context::OpContext;
 use nexus_db_queries::db::lookup::LookupPath;
 use nexus_db_queries::db::model::{DeviceAccessToken, DeviceAuthRequest};
 
-use omicron_common::api::external::{CreateResult, Error};
+use omicron_common::api::external::{
+    CreateResult, DataPageParams, DeleteResult, Error, ListResultVec,
+};
 
-use chrono::Utc;
+use chrono::{Duration, Utc};
 use uuid::Uuid;
 
+/// The maximum length, in characters, of a personal access token's
+/// description (the size of the database column which stores it)
+const MAX_ACCESS_TOKEN_DESCRIPTION_LENGTH: usize = 512;
+
 impl super::Nexus {
     /// Start a device authorization grant flow.
     /// Corresponds to steps 1 & 2 in the flow description above.
@@ -179,7 +187,88 @@ impl super::Nexus {
                 e => Reason::UnknownError { source: e },
             })?;
         let silo_id = db_silo_user.silo_id;
+        let actor = Actor::SiloUser { silo_user_id, silo_id };
+
+        // Tokens which were granted after their device authorization request
+        // expired, or which were created with a TTL, stop working once they
+        // expire.
+        if let Some(time_expires) = db_access_token.time_expires {
+            if time_expires <= Utc::now() {
+                return Err(Reason::BadCredentials {
+                    actor,
+                    source: anyhow!("access token expired at {}", time_expires),
+                });
+            }
+        }
+
+        Ok(actor)
+    }
+
+    // Personal access tokens
 
-        Ok(Actor::SiloUser { silo_user_id, silo_id })
+    /// Create an access token for a user without going through the device
+    /// authorization flow.
+    pub(crate) async fn access_token_create(
+        &self,
+        opctx: &OpContext,
+        silo_user_id: Uuid,
+        params: &params::AccessTokenCreate,
+    ) -> CreateResult<DeviceAccessToken> {
+        let (.., authz_user) = LookupPath::new(opctx, &self.datastore())
+            .silo_user_id(silo_user_id)
+            .lookup_for(authz::Action::CreateChild)
+            .await?;
+        assert_eq!(authz_user.id(), silo_user_id);
+
+        if params.description.chars().count()
+            > MAX_ACCESS_TOKEN_DESCRIPTION_LENGTH
+        {
+            return Err(Error::invalid_request(&format!(
+                "access token description may not exceed {} characters",
+                MAX_ACCESS_TOKEN_DESCRIPTION_LENGTH
+            )));
+        }
+
+        let ttl = params
+            .ttl_seconds
+            .map(|ttl| Duration::seconds(i64::from(ttl.get())));
+        let token = DeviceAccessToken::new_personal(
+            silo_user_id,
+            params.description.clone(),
+            ttl,
+        );
+        self.db_datastore.access_token_create(opctx, &authz_user, token).await
+    }
+
+    pub(crate) async fn access_tokens_list(
+        &self,
+        opctx: &OpContext,
+        silo_user_id: Uuid,
+        pagparams: &DataPageParams<'_, Uuid>,
+    ) -> ListResultVec<DeviceAccessToken> {
+        let (.., authz_user) = LookupPath::new(opctx, &self.datastore())
+            .silo_user_id(silo_user_id)
+            .lookup_for(authz::Action::Modify)
+            .await?;
+        assert_eq!(authz_user.id(), silo_user_id);
+        self.db_datastore
+            .access_tokens_list(opctx, &authz_user, pagparams)
+            .await
+    }
+
+    pub(crate) async fn access_token_delete(
+        &self,
+        opctx: &OpContext,
+        silo_user_id: Uuid,
+        token_id: Uuid,
+    ) -> DeleteResult {
+        let (.., authz_user) = LookupPath::new(opctx, &self.datastore())
+            .silo_user_id(silo_user_id)
+            .lookup_for(authz::Action::Modify)
+            .await?;
+        assert_eq!(authz_user.id(), silo_user_id);
+        self.db_datastore
+            .access_token_delete(opctx, &authz_user, token_id)
+            .await
     }
 }
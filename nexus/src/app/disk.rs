@@ -137,54 +137,7 @@ impl super::Nexus {
             }
         };
 
-        // Reject disks where the block size doesn't evenly divide the
-        // total size
-        if (params.size.to_bytes() % block_size) != 0 {
-            return Err(Error::InvalidValue {
-                label: String::from("size and block_size"),
-                message: format!(
-                    "total size must be a multiple of block size {}",
-                    block_size,
-                ),
-            });
-        }
-
-        // Reject disks where the size isn't at least
-        // MIN_DISK_SIZE_BYTES
-        if params.size.to_bytes() < MIN_DISK_SIZE_BYTES as u64 {
-            return Err(Error::InvalidValue {
-                label: String::from("size"),
-                message: format!(
-                    "total size must be at least {}",
-                    ByteCount::from(MIN_DISK_SIZE_BYTES)
-                ),
-            });
-        }
-
-        // Reject disks where the MIN_DISK_SIZE_BYTES doesn't evenly
-        // divide the size
-        if (params.size.to_bytes() % MIN_DISK_SIZE_BYTES as u64) != 0 {
-            return Err(Error::InvalidValue {
-                label: String::from("size"),
-                message: format!(
-                    "total size must be a multiple of {}",
-                    ByteCount::from(MIN_DISK_SIZE_BYTES)
-                ),
-            });
-        }
-
-        // Reject disks where the size is greated than MAX_DISK_SIZE_BYTES
-        if params.size.to_bytes() > MAX_DISK_SIZE_BYTES {
-            return Err(Error::InvalidValue {
-                label: String::from("size"),
-                message: format!(
-                    "total size must be less than {}",
-                    ByteCount::try_from(MAX_DISK_SIZE_BYTES).unwrap()
-                ),
-            });
-        }
-
-        Ok(())
+        validate_disk_size(params.size, block_size)
     }
 
     pub(crate) async fn project_create_disk(
@@ -212,6 +165,47 @@ impl super::Nexus {
         Ok(disk_created)
     }
 
+    /// Grow a disk to a new size.
+    ///
+    /// The disk must be detached, or attached to an instance which is
+    /// stopped. Disks attached to a running instance cannot be resized.
+    pub(crate) async fn disk_resize(
+        self: &Arc<Self>,
+        opctx: &OpContext,
+        disk_lookup: &lookup::Disk<'_>,
+        params: &params::DiskResize,
+    ) -> UpdateResult<db::model::Disk> {
+        let (.., authz_project, _, db_disk) =
+            disk_lookup.fetch_for(authz::Action::Modify).await?;
+
+        if params.size.to_bytes() <= db_disk.size.to_bytes() {
+            return Err(Error::InvalidValue {
+                label: String::from("size"),
+                message: format!(
+                    "disks may only grow: new size must be greater than \
+                    the current size {}",
+                    db_disk.size.0,
+                ),
+            });
+        }
+        validate_disk_size(params.size, db_disk.block_size.to_bytes().into())?;
+
+        let saga_params = sagas::disk_resize::Params {
+            serialized_authn: authn::saga::Serialized::for_opctx(opctx),
+            project_id: authz_project.id(),
+            disk: db_disk,
+            new_size: params.size,
+        };
+        let saga_outputs = self
+            .execute_saga::<sagas::disk_resize::SagaDiskResize>(saga_params)
+            .await?;
+        let disk_resized = saga_outputs
+            .lookup_node_output::<db::model::Disk>("resized_disk")
+            .map_err(|e| Error::internal_error(&format!("{:#}", &e)))
+            .internal_context("looking up output from disk resize saga")?;
+        Ok(disk_resized)
+    }
+
     pub(crate) async fn disk_list(
         &self,
         opctx: &OpContext,
@@ -619,3 +613,55 @@ impl super::Nexus {
         Ok(())
     }
 }
+
+/// Checks that `size` is a valid size for a disk with the given block size.
+fn validate_disk_size(size: ByteCount, block_size: u64) -> Result<(), Error> {
+    // Reject disks where the block size doesn't evenly divide the
+    // total size
+    if (size.to_bytes() % block_size) != 0 {
+        return Err(Error::InvalidValue {
+            label: String::from("size and block_size"),
+            message: format!(
+                "total size must be a multiple of block size {}",
+                block_size,
+            ),
+        });
+    }
+
+    // Reject disks where the size isn't at least
+    // MIN_DISK_SIZE_BYTES
+    if size.to_bytes() < MIN_DISK_SIZE_BYTES as u64 {
+        return Err(Error::InvalidValue {
+            label: String::from("size"),
+            message: format!(
+                "total size must be at least {}",
+                ByteCount::from(MIN_DISK_SIZE_BYTES)
+            ),
+        });
+    }
+
+    // Reject disks where the MIN_DISK_SIZE_BYTES doesn't evenly
+    // divide the size
+    if (size.to_bytes() % MIN_DISK_SIZE_BYTES as u64) != 0 {
+        return Err(Error::InvalidValue {
+            label: String::from("size"),
+            message: format!(
+                "total size must be a multiple of {}",
+                ByteCount::from(MIN_DISK_SIZE_BYTES)
+            ),
+        });
+    }
+
+    // Reject disks where the size is greated than MAX_DISK_SIZE_BYTES
+    if size.to_bytes() > MAX_DISK_SIZE_BYTES {
+        return Err(Error::InvalidValue {
+            label: String::from("size"),
+            message: format!(
+                "total size must be less than {}",
+                ByteCount::try_from(MAX_DISK_SIZE_BYTES).unwrap()
+            ),
+        });
+    }
+
+    Ok(())
+}
@@ -5,11 +5,24 @@
 //! External IP addresses for instances
 
 use crate::external_api::views::ExternalIp;
+use crate::external_api::views::FloatingIp;
 use nexus_db_queries::authz;
 use nexus_db_queries::context::OpContext;
+use nexus_db_queries::db;
 use nexus_db_queries::db::lookup;
+use nexus_db_queries::db::lookup::LookupPath;
 use nexus_db_queries::db::model::IpKind;
+use nexus_types::external_api::params;
+use nexus_types::identity::Resource;
+use omicron_common::api::external::http_pagination::PaginatedBy;
+use omicron_common::api::external::CreateResult;
+use omicron_common::api::external::DeleteResult;
+use omicron_common::api::external::Error;
+use omicron_common::api::external::InstanceState;
 use omicron_common::api::external::ListResultVec;
+use omicron_common::api::external::LookupResult;
+use omicron_common::api::external::NameOrId;
+use omicron_common::api::external::UpdateResult;
 
 impl super::Nexus {
     pub(crate) async fn instance_list_external_ips(
@@ -33,4 +46,220 @@ impl super::Nexus {
             })
             .collect::<Vec<_>>())
     }
+
+    pub(crate) fn floating_ip_lookup<'a>(
+        &'a self,
+        opctx: &'a OpContext,
+        fip_selector: params::FloatingIpSelector,
+    ) -> LookupResult<lookup::FloatingIp<'a>> {
+        match fip_selector {
+            params::FloatingIpSelector {
+                floating_ip: NameOrId::Id(id),
+                project: None,
+            } => {
+                let floating_ip =
+                    LookupPath::new(opctx, &self.db_datastore)
+                        .floating_ip_id(id);
+                Ok(floating_ip)
+            }
+            params::FloatingIpSelector {
+                floating_ip: NameOrId::Name(name),
+                project: Some(project),
+            } => {
+                let floating_ip = self
+                    .project_lookup(opctx, params::ProjectSelector { project })?
+                    .floating_ip_name_owned(name.into());
+                Ok(floating_ip)
+            }
+            params::FloatingIpSelector {
+                floating_ip: NameOrId::Id(_),
+                ..
+            } => Err(Error::invalid_request(
+                "when providing Floating IP as an ID project should not be specified",
+            )),
+            _ => Err(Error::invalid_request(
+                "Floating IP should either be UUID or project should be specified",
+            )),
+        }
+    }
+
+    pub(crate) async fn floating_ips_list(
+        &self,
+        opctx: &OpContext,
+        project_lookup: &lookup::Project<'_>,
+        pagparams: &PaginatedBy<'_>,
+    ) -> ListResultVec<FloatingIp> {
+        let (.., authz_project) =
+            project_lookup.lookup_for(authz::Action::ListChildren).await?;
+
+        Ok(self
+            .db_datastore
+            .floating_ips_list(opctx, &authz_project, pagparams)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    pub(crate) async fn floating_ip_create(
+        &self,
+        opctx: &OpContext,
+        project_lookup: &lookup::Project<'_>,
+        params: params::FloatingIpCreate,
+    ) -> CreateResult<FloatingIp> {
+        let (.., authz_project) =
+            project_lookup.lookup_for(authz::Action::CreateChild).await?;
+
+        Ok(self
+            .db_datastore
+            .allocate_floating_ip(opctx, &authz_project, params)
+            .await?
+            .into())
+    }
+
+    pub(crate) async fn floating_ip_delete(
+        &self,
+        opctx: &OpContext,
+        ip_lookup: lookup::FloatingIp<'_>,
+    ) -> DeleteResult {
+        let (.., authz_fip) =
+            ip_lookup.lookup_for(authz::Action::Delete).await?;
+
+        self.db_datastore.floating_ip_delete(opctx, &authz_fip).await
+    }
+
+    /// Attaches a Floating IP to an instance in the same project.
+    ///
+    /// The instance must be stopped or running. A stopped instance's external
+    /// addresses are provided to the sled agent, and NAT for them is
+    /// configured on the boundary switches, when the instance starts. If the
+    /// instance is running, NAT for the new address is configured right away;
+    /// if that fails, the Floating IP is detached again. Attaching a Floating
+    /// IP to the instance it is already attached to succeeds, and ensures that
+    /// the NAT configuration of a running instance is present.
+    pub(crate) async fn floating_ip_attach(
+        &self,
+        opctx: &OpContext,
+        ip_lookup: lookup::FloatingIp<'_>,
+        attach: params::FloatingIpAttach,
+    ) -> UpdateResult<FloatingIp> {
+        let (.., authz_fip, db_fip) =
+            ip_lookup.fetch_for(authz::Action::Modify).await?;
+
+        let instance_lookup = match attach.instance {
+            NameOrId::Id(id) => {
+                LookupPath::new(opctx, &self.db_datastore).instance_id(id)
+            }
+            NameOrId::Name(name) => LookupPath::new(opctx, &self.db_datastore)
+                .project_id(db_fip.project_id)
+                .instance_name_owned(name.into()),
+        };
+        let (.., authz_project, authz_instance, db_instance) =
+            instance_lookup.fetch_for(authz::Action::Modify).await?;
+        if authz_project.id() != db_fip.project_id {
+            return Err(Error::invalid_request(
+                "Floating IP and instance must be in the same project",
+            ));
+        }
+        Self::check_floating_ip_instance_state(&db_instance)?;
+
+        let external_ip = self
+            .db_datastore
+            .floating_ip_attach(
+                opctx,
+                &authz_fip,
+                &authz_instance,
+                super::MAX_EXTERNAL_IPS_PER_INSTANCE,
+            )
+            .await?;
+
+        if db_instance.runtime().state.0 == InstanceState::Running {
+            if let Err(e) = self
+                .instance_ensure_dpd_config_for_ip(
+                    opctx,
+                    authz_instance.id(),
+                    db_instance.runtime().sled_id,
+                    &external_ip,
+                )
+                .await
+            {
+                if let Err(detach_error) = self
+                    .db_datastore
+                    .floating_ip_detach(opctx, &authz_fip, authz_instance.id())
+                    .await
+                {
+                    error!(self.log, "failed to detach floating ip";
+                           "floating_ip_id" => %db_fip.id(),
+                           "instance_id" => %authz_instance.id(),
+                           "error" => %detach_error);
+                }
+                return Err(e);
+            }
+        }
+
+        Ok(db::model::FloatingIp::try_from(external_ip)?.into())
+    }
+
+    /// Detaches a Floating IP from the instance it's attached to.
+    ///
+    /// As with attaching, the instance must be stopped or running. The NAT
+    /// configuration for the address is removed from the boundary switches
+    /// first, both for running instances and because stopping an instance
+    /// leaves it in place; if that fails, the Floating IP remains attached and
+    /// the request can be retried.
+    pub(crate) async fn floating_ip_detach(
+        &self,
+        opctx: &OpContext,
+        ip_lookup: lookup::FloatingIp<'_>,
+    ) -> UpdateResult<FloatingIp> {
+        let (.., authz_fip, db_fip) =
+            ip_lookup.fetch_for(authz::Action::Modify).await?;
+
+        let Some(instance_id) = db_fip.parent_id else {
+            return Err(Error::invalid_request(
+                "Floating IP is not attached to an instance",
+            ));
+        };
+        let (.., db_instance) = LookupPath::new(opctx, &self.db_datastore)
+            .instance_id(instance_id)
+            .fetch_for(authz::Action::Modify)
+            .await?;
+        Self::check_floating_ip_instance_state(&db_instance)?;
+
+        let attached_ip = self
+            .db_datastore
+            .instance_lookup_external_ips(opctx, instance_id)
+            .await?
+            .into_iter()
+            .find(|ip| ip.id == db_fip.id())
+            .ok_or_else(|| {
+                Error::conflict("Floating IP is not attached to the instance")
+            })?;
+        self.instance_delete_dpd_config_for_ip(
+            opctx,
+            instance_id,
+            &attached_ip,
+        )
+        .await?;
+
+        let external_ip = self
+            .db_datastore
+            .floating_ip_detach(opctx, &authz_fip, instance_id)
+            .await?;
+
+        Ok(db::model::FloatingIp::try_from(external_ip)?.into())
+    }
+
+    fn check_floating_ip_instance_state(
+        db_instance: &db::model::Instance,
+    ) -> Result<(), Error> {
+        match db_instance.runtime().state.0 {
+            InstanceState::Stopped | InstanceState::Running => Ok(()),
+            state => Err(Error::invalid_request(&format!(
+                "Floating IPs can only be attached to or detached from \
+                stopped or running instances, but instance is {}",
+                state.label(),
+            ))),
+        }
+    }
 }
@@ -31,7 +31,9 @@ use omicron_common::api::external::ByteCount;
 use omicron_common::api::external::CreateResult;
 use omicron_common::api::external::DataPageParams;
 use omicron_common::api::external::DeleteResult;
+use omicron_common::api::external::DiskState;
 use omicron_common::api::external::Error;
+use omicron_common::api::external::InstanceCpuCount;
 use omicron_common::api::external::InstanceState;
 use omicron_common::api::external::InternalContext;
 use omicron_common::api::external::ListResultVec;
@@ -104,6 +106,57 @@ impl super::Nexus {
         }
     }
 
+    /// Checks that an instance's vCPU count and memory size are within the
+    /// limits supported by the control plane.
+    fn validate_instance_shape(
+        ncpus: InstanceCpuCount,
+        memory: ByteCount,
+    ) -> Result<(), Error> {
+        if ncpus.0 > MAX_VCPU_PER_INSTANCE {
+            return Err(Error::invalid_request(&format!(
+                "cannot have more than {} vCPUs per instance",
+                MAX_VCPU_PER_INSTANCE
+            )));
+        }
+
+        // Reject instances where the memory is not at least
+        // MIN_MEMORY_BYTES_PER_INSTANCE
+        if memory.to_bytes() < MIN_MEMORY_BYTES_PER_INSTANCE as u64 {
+            return Err(Error::InvalidValue {
+                label: String::from("size"),
+                message: format!(
+                    "memory must be at least {}",
+                    ByteCount::from(MIN_MEMORY_BYTES_PER_INSTANCE)
+                ),
+            });
+        }
+
+        // Reject instances where the memory is not divisible by
+        // MIN_MEMORY_BYTES_PER_INSTANCE
+        if (memory.to_bytes() % MIN_MEMORY_BYTES_PER_INSTANCE as u64) != 0 {
+            return Err(Error::InvalidValue {
+                label: String::from("size"),
+                message: format!(
+                    "memory must be divisible by {}",
+                    ByteCount::from(MIN_MEMORY_BYTES_PER_INSTANCE)
+                ),
+            });
+        }
+
+        // Reject instances where the memory is greated than the limit
+        if memory.to_bytes() > MAX_MEMORY_BYTES_PER_INSTANCE {
+            return Err(Error::InvalidValue {
+                label: String::from("size"),
+                message: format!(
+                    "memory must be less than or equal to {}",
+                    ByteCount::try_from(MAX_MEMORY_BYTES_PER_INSTANCE).unwrap()
+                ),
+            });
+        }
+
+        Ok(())
+    }
+
     pub(crate) async fn project_create_instance(
         self: &Arc<Self>,
         opctx: &OpContext,
@@ -126,12 +179,7 @@ impl super::Nexus {
                     .await?;
             }
         }
-        if params.ncpus.0 > MAX_VCPU_PER_INSTANCE {
-            return Err(Error::invalid_request(&format!(
-                "cannot have more than {} vCPUs per instance",
-                MAX_VCPU_PER_INSTANCE
-            )));
-        }
+        Self::validate_instance_shape(params.ncpus, params.memory)?;
         if params.external_ips.len() > MAX_EXTERNAL_IPS_PER_INSTANCE {
             return Err(Error::invalid_request(&format!(
                 "An instance may not have more than {} external IP addresses",
@@ -164,42 +212,13 @@ impl super::Nexus {
             }
         }
 
-        // Reject instances where the memory is not at least
-        // MIN_MEMORY_BYTES_PER_INSTANCE
-        if params.memory.to_bytes() < MIN_MEMORY_BYTES_PER_INSTANCE as u64 {
-            return Err(Error::InvalidValue {
-                label: String::from("size"),
-                message: format!(
-                    "memory must be at least {}",
-                    ByteCount::from(MIN_MEMORY_BYTES_PER_INSTANCE)
-                ),
-            });
-        }
-
-        // Reject instances where the memory is not divisible by
-        // MIN_MEMORY_BYTES_PER_INSTANCE
-        if (params.memory.to_bytes() % MIN_MEMORY_BYTES_PER_INSTANCE as u64)
-            != 0
-        {
-            return Err(Error::InvalidValue {
-                label: String::from("size"),
-                message: format!(
-                    "memory must be divisible by {}",
-                    ByteCount::from(MIN_MEMORY_BYTES_PER_INSTANCE)
-                ),
-            });
-        }
-
-        // Reject instances where the memory is greated than the limit
-        if params.memory.to_bytes() > MAX_MEMORY_BYTES_PER_INSTANCE {
-            return Err(Error::InvalidValue {
-                label: String::from("size"),
-                message: format!(
-                    "memory must be less than or equal to {}",
-                    ByteCount::try_from(MAX_MEMORY_BYTES_PER_INSTANCE).unwrap()
-                ),
-            });
-        }
+        let affinity_group_ids = self
+            .affinity_groups_resolve(
+                opctx,
+                &authz_project,
+                &params.affinity_groups,
+            )
+            .await?;
 
         let saga_params = sagas::instance_create::Params {
             serialized_authn: authn::saga::Serialized::for_opctx(opctx),
@@ -208,6 +227,7 @@ impl super::Nexus {
             boundary_switches: self
                 .boundary_switches(&self.opctx_alloc)
                 .await?,
+            affinity_group_ids,
         };
 
         let saga_outputs = self
@@ -263,6 +283,30 @@ impl super::Nexus {
         Ok(db_instance)
     }
 
+    /// Changes the vCPU count, memory size, and hostname of a stopped
+    /// instance.
+    pub(crate) async fn instance_reconfigure(
+        &self,
+        opctx: &OpContext,
+        instance_lookup: &lookup::Instance<'_>,
+        params: &params::InstanceUpdate,
+    ) -> UpdateResult<db::model::Instance> {
+        let (.., authz_instance) =
+            instance_lookup.lookup_for(authz::Action::Modify).await?;
+
+        Self::validate_instance_shape(params.ncpus, params.memory)?;
+
+        self.db_datastore
+            .instance_reconfigure(
+                opctx,
+                &authz_instance,
+                params.ncpus.into(),
+                params.memory.into(),
+                params.hostname.clone(),
+            )
+            .await
+    }
+
     pub(crate) async fn instance_list(
         &self,
         opctx: &OpContext,
@@ -675,6 +719,18 @@ impl super::Nexus {
 
         let mut disk_reqs = vec![];
         for disk in &disks {
+            // A disk which is undergoing maintenance (e.g., being resized)
+            // remains associated with its instance, but cannot be used by it
+            // until the maintenance completes.
+            let disk_state: DiskState = disk.state().into();
+            if !matches!(disk_state, DiskState::Attached(_)) {
+                return Err(Error::conflict(&format!(
+                    "disk {} is in state {} and cannot be used by an instance",
+                    disk.name(),
+                    disk_state,
+                )));
+            }
+
             // Disks that are attached to an instance should always have a slot
             // assignment, but if for some reason this one doesn't, return an
             // error instead of taking down the whole process.
@@ -711,9 +767,8 @@ impl super::Nexus {
             .derive_guest_network_interface_info(&opctx, &authz_instance)
             .await?;
 
-        // Collect the external IPs for the instance.
-        // TODO-correctness: Handle Floating IPs, see
-        //  https://github.com/oxidecomputer/omicron/issues/1334
+        // Collect the external IPs for the instance. Floating IPs attached to
+        // the instance are provided alongside any Ephemeral IP.
         let (snat_ip, external_ips): (Vec<_>, Vec<_>) = self
             .db_datastore
             .instance_lookup_external_ips(&opctx, authz_instance.id())
@@ -9,6 +9,7 @@ use nexus_db_queries::authz;
 use nexus_db_queries::context::OpContext;
 use nexus_db_queries::db::identity::Asset;
 use nexus_db_queries::db::lookup::LookupPath;
+use nexus_db_queries::db::model::ExternalIp;
 use omicron_common::api::external::DataPageParams;
 use omicron_common::api::external::Error;
 use omicron_common::api::internal::shared::SwitchLocation;
@@ -313,6 +314,114 @@ impl super::Nexus {
     ) -> Result<(), Error> {
         let log = &self.log;
 
+        let Some((mac_address, vni)) =
+            self.instance_nat_target(opctx, instance_id).await?
+        else {
+            return Ok(());
+        };
+
+        info!(log, "looking up instance's external IPs";
+              "instance_id" => %instance_id);
+
+        let ips = self
+            .db_datastore
+            .instance_lookup_external_ips(&opctx, instance_id)
+            .await?;
+
+        if let Some(wanted_index) = ip_index_filter {
+            if let None = ips.get(wanted_index) {
+                return Err(Error::internal_error(&format!(
+                    "failed to find external ip address at index: {}",
+                    wanted_index
+                )));
+            }
+        }
+
+        for target_ip in ips
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| {
+                if let Some(wanted_index) = ip_index_filter {
+                    *index == wanted_index
+                } else {
+                    true
+                }
+            })
+            .map(|(_, ip)| ip)
+        {
+            self.ensure_nat_entry(
+                target_ip,
+                mac_address,
+                vni,
+                sled_ip_address,
+                dpd_client,
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Ensures that every boundary switch has a NAT entry for a single
+    /// external IP, which has just been attached to the instance identified by
+    /// `instance_id`, without touching the entries for its other IPs.
+    ///
+    /// # Parameters
+    ///
+    /// - `opctx`: An operation context that grants read and list-children
+    ///   permissions on the identified instance.
+    /// - `instance_id`: The ID of the instance to act on.
+    /// - `sled_id`: The ID of the sled on which the instance resides.
+    /// - `target_ip`: The external IP to configure.
+    pub(crate) async fn instance_ensure_dpd_config_for_ip(
+        &self,
+        opctx: &OpContext,
+        instance_id: Uuid,
+        sled_id: Uuid,
+        target_ip: &ExternalIp,
+    ) -> Result<(), Error> {
+        let Some((mac_address, vni)) =
+            self.instance_nat_target(opctx, instance_id).await?
+        else {
+            return Ok(());
+        };
+
+        let (.., sled) = LookupPath::new(&self.opctx_alloc, &self.db_datastore)
+            .sled_id(sled_id)
+            .fetch()
+            .await?;
+
+        let boundary_switches =
+            self.boundary_switches(&self.opctx_alloc).await?;
+        for switch in &boundary_switches {
+            let dpd_client = self.dpd_clients.get(switch).ok_or_else(|| {
+                Error::internal_error(&format!(
+                    "unable to find dendrite client for {switch}"
+                ))
+            })?;
+            self.ensure_nat_entry(
+                target_ip,
+                mac_address,
+                vni,
+                &sled.address(),
+                dpd_client,
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the MAC address and VNI of the primary network interface of the
+    /// instance identified by `instance_id`, to which all of its external IPs
+    /// map, or `None` if it has no primary interface.
+    async fn instance_nat_target(
+        &self,
+        opctx: &OpContext,
+        instance_id: Uuid,
+    ) -> Result<Option<(macaddr::MacAddr6, u32)>, Error> {
+        let log = &self.log;
+
         info!(log, "looking up instance's primary network interface";
               "instance_id" => %instance_id);
 
@@ -336,7 +445,7 @@ impl super::Nexus {
             None => {
                 info!(log, "Instance has no primary network interface";
                       "instance_id" => %instance_id);
-                return Ok(());
+                return Ok(None);
             }
         };
 
@@ -350,57 +459,38 @@ impl super::Nexus {
 
         let vni: u32 = network_interface.vni.into();
 
-        info!(log, "looking up instance's external IPs";
-              "instance_id" => %instance_id);
-
-        let ips = self
-            .db_datastore
-            .instance_lookup_external_ips(&opctx, instance_id)
-            .await?;
-
-        if let Some(wanted_index) = ip_index_filter {
-            if let None = ips.get(wanted_index) {
-                return Err(Error::internal_error(&format!(
-                    "failed to find external ip address at index: {}",
-                    wanted_index
-                )));
-            }
-        }
+        Ok(Some((mac_address, vni)))
+    }
 
-        for target_ip in ips
-            .iter()
-            .enumerate()
-            .filter(|(index, _)| {
-                if let Some(wanted_index) = ip_index_filter {
-                    *index == wanted_index
-                } else {
-                    true
-                }
-            })
-            .map(|(_, ip)| ip)
-        {
-            retry_until_known_result(log, || async {
-                dpd_client
-                    .ensure_nat_entry(
-                        &log,
-                        target_ip.ip,
-                        dpd_client::types::MacAddr {
-                            a: mac_address.into_array(),
-                        },
-                        *target_ip.first_port,
-                        *target_ip.last_port,
-                        vni,
-                        sled_ip_address.ip(),
-                    )
-                    .await
-            })
-            .await
-            .map_err(|e| {
-                Error::internal_error(&format!(
-                    "failed to ensure dpd entry: {e}"
-                ))
-            })?;
-        }
+    /// Ensures that the switch managed by `dpd_client` maps `target_ip` to
+    /// the given primary interface of an instance on the sled with address
+    /// `sled_ip_address`.
+    async fn ensure_nat_entry(
+        &self,
+        target_ip: &ExternalIp,
+        mac_address: macaddr::MacAddr6,
+        vni: u32,
+        sled_ip_address: &std::net::SocketAddrV6,
+        dpd_client: &Arc<dpd_client::Client>,
+    ) -> Result<(), Error> {
+        let log = &self.log;
+        retry_until_known_result(log, || async {
+            dpd_client
+                .ensure_nat_entry(
+                    &log,
+                    target_ip.ip,
+                    dpd_client::types::MacAddr { a: mac_address.into_array() },
+                    *target_ip.first_port,
+                    *target_ip.last_port,
+                    vni,
+                    sled_ip_address.ip(),
+                )
+                .await
+        })
+        .await
+        .map_err(|e| {
+            Error::internal_error(&format!("failed to ensure dpd entry: {e}"))
+        })?;
 
         Ok(())
     }
@@ -437,55 +527,14 @@ impl super::Nexus {
 
         let mut errors = vec![];
         for entry in external_ips {
-            for switch in &boundary_switches {
-                debug!(log, "deleting instance nat mapping";
-                       "instance_id" => %instance_id,
-                       "switch" => switch.to_string(),
-                       "entry" => #?entry);
-
-                let client_result =
-                    self.dpd_clients.get(switch).ok_or_else(|| {
-                        Error::internal_error(&format!(
-                            "unable to find dendrite client for {switch}"
-                        ))
-                    });
-
-                let dpd_client = match client_result {
-                    Ok(client) => client,
-                    Err(new_error) => {
-                        errors.push(new_error);
-                        continue;
-                    }
-                };
-
-                let result = retry_until_known_result(log, || async {
-                    dpd_client
-                        .ensure_nat_entry_deleted(
-                            log,
-                            entry.ip,
-                            *entry.first_port,
-                        )
-                        .await
-                })
-                .await;
-
-                if let Err(e) = result {
-                    let e = Error::internal_error(&format!(
-                        "failed to delete nat entry via dpd: {e}"
-                    ));
-
-                    error!(log, "error deleting nat mapping: {e:#?}";
-                           "instance_id" => %instance_id,
-                           "switch" => switch.to_string(),
-                           "entry" => #?entry);
-                    errors.push(e);
-                } else {
-                    debug!(log, "deleting nat mapping successful";
-                           "instance_id" => %instance_id,
-                           "switch" => switch.to_string(),
-                           "entry" => #?entry);
-                }
-            }
+            errors.extend(
+                self.delete_nat_entries(
+                    instance_id,
+                    &entry,
+                    &boundary_switches,
+                )
+                .await,
+            );
         }
 
         if let Some(e) = errors.into_iter().nth(0) {
@@ -494,4 +543,90 @@ impl super::Nexus {
 
         Ok(())
     }
+
+    /// Attempts to delete the Dendrite NAT configuration for a single external
+    /// IP, which is about to be detached from the instance identified by
+    /// `instance_id`.
+    ///
+    /// As with [`Self::instance_delete_dpd_config`], this tries to delete the
+    /// entry from every boundary switch, returning the first error it
+    /// encountered.
+    pub(crate) async fn instance_delete_dpd_config_for_ip(
+        &self,
+        opctx: &OpContext,
+        instance_id: Uuid,
+        entry: &ExternalIp,
+    ) -> Result<(), Error> {
+        info!(&self.log, "deleting dpd configuration for external ip";
+              "instance_id" => %instance_id,
+              "external_ip_id" => %entry.id);
+
+        let boundary_switches = self.boundary_switches(opctx).await?;
+        match self
+            .delete_nat_entries(instance_id, entry, &boundary_switches)
+            .await
+            .into_iter()
+            .nth(0)
+        {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Deletes the NAT entry for `entry` from each of `boundary_switches`,
+    /// continuing past failures and returning all of the errors encountered.
+    async fn delete_nat_entries(
+        &self,
+        instance_id: Uuid,
+        entry: &ExternalIp,
+        boundary_switches: &HashSet<SwitchLocation>,
+    ) -> Vec<Error> {
+        let log = &self.log;
+        let mut errors = vec![];
+        for switch in boundary_switches {
+            debug!(log, "deleting instance nat mapping";
+                   "instance_id" => %instance_id,
+                   "switch" => switch.to_string(),
+                   "entry" => #?entry);
+
+            let client_result = self.dpd_clients.get(switch).ok_or_else(|| {
+                Error::internal_error(&format!(
+                    "unable to find dendrite client for {switch}"
+                ))
+            });
+
+            let dpd_client = match client_result {
+                Ok(client) => client,
+                Err(new_error) => {
+                    errors.push(new_error);
+                    continue;
+                }
+            };
+
+            let result = retry_until_known_result(log, || async {
+                dpd_client
+                    .ensure_nat_entry_deleted(log, entry.ip, *entry.first_port)
+                    .await
+            })
+            .await;
+
+            if let Err(e) = result {
+                let e = Error::internal_error(&format!(
+                    "failed to delete nat entry via dpd: {e}"
+                ));
+
+                error!(log, "error deleting nat mapping: {e:#?}";
+                       "instance_id" => %instance_id,
+                       "switch" => switch.to_string(),
+                       "entry" => #?entry);
+                errors.push(e);
+            } else {
+                debug!(log, "deleting nat mapping successful";
+                       "instance_id" => %instance_id,
+                       "switch" => switch.to_string(),
+                       "entry" => #?entry);
+            }
+        }
+        errors
+    }
 }
@@ -4,15 +4,17 @@
 
 //! Metrics
 
+use crate::external_api::http_entrypoints::InstanceMetricName;
 use crate::external_api::http_entrypoints::SystemMetricName;
+use crate::external_api::params::InstanceMetricsSelector;
 use crate::external_api::params::ResourceMetrics;
 use dropshot::PaginationParams;
 use nexus_db_queries::authz;
 use nexus_db_queries::{
     context::OpContext,
-    db::{fixed_data::FLEET_ID, lookup},
+    db::{fixed_data::FLEET_ID, lookup, lookup::LookupPath},
 };
-use omicron_common::api::external::{Error, InternalContext};
+use omicron_common::api::external::{Error, InternalContext, NameOrId};
 use oximeter_db::Measurement;
 use std::num::NonZeroU32;
 
@@ -96,4 +98,164 @@ impl super::Nexus {
         )
         .await
     }
+
+    /// Fetch a metric describing an instance or one of its devices.
+    ///
+    /// Instance metrics are visible to anyone who can read the instance's
+    /// project.
+    pub(crate) async fn instance_metric_list(
+        &self,
+        opctx: &OpContext,
+        instance_lookup: &lookup::Instance<'_>,
+        metric_name: InstanceMetricName,
+        selector: &InstanceMetricsSelector,
+        pagination: PaginationParams<ResourceMetrics, ResourceMetrics>,
+        limit: NonZeroU32,
+    ) -> Result<dropshot::ResultsPage<Measurement>, Error> {
+        let (.., authz_project, authz_instance) =
+            instance_lookup.lookup_for(authz::Action::Read).await?;
+        opctx.authorize(authz::Action::Read, &authz_project).await?;
+
+        let (vcpu, network_interface, disk) = match metric_name {
+            InstanceMetricName::VcpuUsage => (true, false, false),
+            InstanceMetricName::NicPacketsReceived
+            | InstanceMetricName::NicPacketsSent
+            | InstanceMetricName::NicBytesReceived
+            | InstanceMetricName::NicBytesSent => (false, true, false),
+            InstanceMetricName::DiskRead
+            | InstanceMetricName::DiskReadBytes
+            | InstanceMetricName::DiskWrite
+            | InstanceMetricName::DiskWriteBytes => (false, false, true),
+        };
+        for (name, expected, given) in [
+            ("vcpu", vcpu, selector.vcpu.is_some()),
+            (
+                "network_interface",
+                network_interface,
+                selector.network_interface.is_some(),
+            ),
+            ("disk", disk, selector.disk.is_some()),
+        ] {
+            if expected && !given {
+                return Err(Error::invalid_request(&format!(
+                    "metric \"{metric_name}\" requires \"{name}\""
+                )));
+            }
+            if !expected && given {
+                return Err(Error::invalid_request(&format!(
+                    "\"{name}\" does not apply to metric \"{metric_name}\""
+                )));
+            }
+        }
+
+        let device_criterion = if let Some(vcpu) = selector.vcpu {
+            format!("vcpu_id=={vcpu}")
+        } else if let Some(nic) = &selector.network_interface {
+            let authz_nic = match nic {
+                NameOrId::Id(id) => {
+                    let (.., authz_nic_instance, authz_nic) =
+                        LookupPath::new(opctx, &self.db_datastore)
+                            .instance_network_interface_id(*id)
+                            .lookup_for(authz::Action::Read)
+                            .await?;
+                    if authz_nic_instance.id() != authz_instance.id() {
+                        return Err(Error::invalid_request(&format!(
+                            "network interface {} does not belong to \
+                            instance {}",
+                            id,
+                            authz_instance.id()
+                        )));
+                    }
+                    authz_nic
+                }
+                NameOrId::Name(name) => {
+                    let (.., authz_nic) =
+                        LookupPath::new(opctx, &self.db_datastore)
+                            .instance_id(authz_instance.id())
+                            .instance_network_interface_name_owned(
+                                name.clone().into(),
+                            )
+                            .lookup_for(authz::Action::Read)
+                            .await?;
+                    authz_nic
+                }
+            };
+            format!("nic_id=={}", authz_nic.id())
+        } else if let Some(disk) = &selector.disk {
+            // Disks may be detached after the fact, so we only require that
+            // the disk live in the same project as the instance. The criteria
+            // below restrict the results to I/O through this instance.
+            let (.., authz_disk_project, authz_disk) = match disk {
+                NameOrId::Id(id) => {
+                    LookupPath::new(opctx, &self.db_datastore)
+                        .disk_id(*id)
+                        .lookup_for(authz::Action::Read)
+                        .await?
+                }
+                NameOrId::Name(name) => {
+                    LookupPath::new(opctx, &self.db_datastore)
+                        .project_id(authz_project.id())
+                        .disk_name_owned(name.clone().into())
+                        .lookup_for(authz::Action::Read)
+                        .await?
+                }
+            };
+            if authz_disk_project.id() != authz_project.id() {
+                return Err(Error::invalid_request(&format!(
+                    "disk {} is not in the same project as instance {}",
+                    authz_disk.id(),
+                    authz_instance.id()
+                )));
+            }
+            format!("disk_id=={}", authz_disk.id())
+        } else {
+            return Err(Error::internal_error(&format!(
+                "instance metric \"{metric_name}\" has no device"
+            )));
+        };
+
+        self.select_timeseries(
+            instance_metric_timeseries(&metric_name),
+            &[
+                &format!("instance_id=={}", authz_instance.id()),
+                &device_criterion,
+            ],
+            pagination,
+            limit,
+        )
+        .await
+    }
+}
+
+/// Returns the name of the timeseries that holds an instance metric.
+///
+/// Instance metrics are expected from the Propolis server running each
+/// instance: a `virtual_machine` target identifying the instance by
+/// `instance_id`, and a metric for each measurement identifying the device by
+/// `vcpu_id`, `nic_id`, or `disk_id`. Propolis doesn't report these timeseries
+/// yet, so for now only the simulated sled agent produces them (see
+/// `omicron_sled_agent::sim::instance`), and listing them for a real instance
+/// returns no measurements. This mapping must be kept in sync with both.
+fn instance_metric_timeseries(
+    metric_name: &InstanceMetricName,
+) -> &'static str {
+    match metric_name {
+        InstanceMetricName::VcpuUsage => "virtual_machine:vcpu_usage",
+        InstanceMetricName::NicPacketsReceived => {
+            "virtual_machine:nic_packets_received"
+        }
+        InstanceMetricName::NicPacketsSent => {
+            "virtual_machine:nic_packets_sent"
+        }
+        InstanceMetricName::NicBytesReceived => {
+            "virtual_machine:nic_bytes_received"
+        }
+        InstanceMetricName::NicBytesSent => "virtual_machine:nic_bytes_sent",
+        InstanceMetricName::DiskRead => "virtual_machine:disk_read",
+        InstanceMetricName::DiskReadBytes => "virtual_machine:disk_read_bytes",
+        InstanceMetricName::DiskWrite => "virtual_machine:disk_write",
+        InstanceMetricName::DiskWriteBytes => {
+            "virtual_machine:disk_write_bytes"
+        }
+    }
 }
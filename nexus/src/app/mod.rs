@@ -32,6 +32,8 @@ use uuid::Uuid;
 // The implementation of Nexus is large, and split into a number of submodules
 // by resource.
 mod address_lot;
+mod affinity;
+mod audit_log;
 pub(crate) mod background;
 mod certificate;
 mod device_auth;
@@ -50,6 +52,7 @@ mod oximeter;
 mod project;
 mod rack;
 pub(crate) mod saga;
+mod scim;
 mod session;
 mod silo;
 mod sled;
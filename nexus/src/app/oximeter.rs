@@ -4,6 +4,7 @@
 
 //! Oximeter-related functionality
 
+use crate::external_api::params::MetricAggregation;
 use crate::external_api::params::ResourceMetrics;
 use crate::internal_api::params::OximeterInfo;
 use dropshot::PaginationParams;
@@ -19,6 +20,8 @@ use omicron_common::api::internal::nexus;
 use omicron_common::backoff;
 use oximeter_client::Client as OximeterClient;
 use oximeter_db::query::Timestamp;
+use oximeter_db::Aggregation;
+use oximeter_db::AggregationOp;
 use oximeter_db::Measurement;
 use oximeter_producer::register;
 use slog::Logger;
@@ -90,29 +93,34 @@ impl super::Nexus {
         // Regardless, notify the collector of any assigned metric producers. This should be empty
         // if this Oximeter collector is registering for the first time, but may not be if the
         // service is re-registering after failure.
-        let pagparams = DataPageParams {
-            marker: None,
-            direction: PaginationOrder::Ascending,
-            limit: std::num::NonZeroU32::new(100).unwrap(),
-        };
-        let producers = self
-            .db_datastore
-            .producers_list_by_oximeter_id(
-                oximeter_info.collector_id,
-                &pagparams,
-            )
-            .await?;
-        if !producers.is_empty() {
+        let client = self.build_oximeter_client(
+            &oximeter_info.collector_id,
+            oximeter_info.address,
+        );
+        let mut marker = None;
+        loop {
+            let pagparams = DataPageParams {
+                marker: marker.as_ref(),
+                direction: PaginationOrder::Ascending,
+                limit: NonZeroU32::new(100).unwrap(),
+            };
+            let producers = self
+                .db_datastore
+                .producers_list_by_oximeter_id(
+                    oximeter_info.collector_id,
+                    &pagparams,
+                )
+                .await?;
+            let Some(last) = producers.last() else {
+                break;
+            };
+            marker = Some(last.id());
             debug!(
                 self.log,
                 "registered oximeter collector that is already assigned producers, re-assigning them to the collector";
                 "n_producers" => producers.len(),
                 "collector_id" => ?oximeter_info.collector_id,
             );
-            let client = self.build_oximeter_client(
-                &oximeter_info.collector_id,
-                oximeter_info.address,
-            );
             for producer in producers.into_iter() {
                 let producer_info = oximeter_client::types::ProducerEndpoint {
                     id: producer.id(),
@@ -132,6 +140,45 @@ impl super::Nexus {
                     .map_err(Error::from)?;
             }
         }
+
+        // Likewise for the producers that push their samples to this
+        // collector, which otherwise would be refused until they register
+        // again.
+        let mut marker = None;
+        loop {
+            let pagparams = DataPageParams {
+                marker: marker.as_ref(),
+                direction: PaginationOrder::Ascending,
+                limit: NonZeroU32::new(100).unwrap(),
+            };
+            let push_producers = self
+                .db_datastore
+                .push_producers_list_by_oximeter_id(
+                    oximeter_info.collector_id,
+                    &pagparams,
+                )
+                .await?;
+            let Some(last) = push_producers.last() else {
+                break;
+            };
+            marker = Some(last.id());
+            debug!(
+                self.log,
+                "registered oximeter collector that is already assigned push producers, re-assigning them to the collector";
+                "n_producers" => push_producers.len(),
+                "collector_id" => ?oximeter_info.collector_id,
+            );
+            for producer in push_producers.into_iter() {
+                client
+                    .push_producers_post(
+                        &oximeter_client::types::PushProducerInfo {
+                            id: producer.id(),
+                        },
+                    )
+                    .await
+                    .map_err(Error::from)?;
+            }
+        }
         Ok(())
     }
 
@@ -169,7 +216,7 @@ impl super::Nexus {
         &self,
         producer_info: nexus::ProducerEndpoint,
     ) -> Result<(), Error> {
-        let (collector, id) = self.next_collector().await?;
+        let (collector, id, _) = self.next_collector().await?;
         let db_info = db::model::ProducerEndpoint::new(&producer_info, id);
         self.db_datastore.producer_endpoint_create(&db_info).await?;
         collector
@@ -187,6 +234,38 @@ impl super::Nexus {
         Ok(())
     }
 
+    /// Assign a metric producer that pushes its samples to an oximeter
+    /// collector server.
+    ///
+    /// A producer registers again when its collector no longer knows about it,
+    /// and before its registration expires (see
+    /// [`nexus::PUSH_PRODUCER_LEASE_DURATION`]). Each time, it's assigned to
+    /// whichever collector is chosen now.
+    pub(crate) async fn assign_push_producer(
+        &self,
+        producer_info: nexus::PushProducerInfo,
+    ) -> Result<nexus::PushProducerAssignment, Error> {
+        let (collector, id, address) = self.next_collector().await?;
+        let db_info = db::model::PushProducer::new(producer_info.id, id);
+        self.db_datastore.push_producer_create(&db_info).await?;
+        collector
+            .push_producers_post(&oximeter_client::types::PushProducerInfo {
+                id: producer_info.id,
+            })
+            .await
+            .map_err(Error::from)?;
+        info!(
+            self.log,
+            "assigned collector to push producer";
+            "producer_id" => ?producer_info.id,
+            "collector_id" => ?id,
+        );
+        Ok(nexus::PushProducerAssignment {
+            collector_id: id,
+            collector_address: address,
+        })
+    }
+
     /// Returns a results from the timeseries DB based on the provided query
     /// parameters.
     ///
@@ -225,8 +304,16 @@ impl super::Nexus {
             // we'd duplicate the returned measurement. To return each
             // measurement exactly once, we make the start time "exclusive"
             // on all "next" pages.
+            //
+            // Aggregated measurements are different: they're timestamped with
+            // the start of their window, and the page token starts at the
+            // following window, so the bound remains inclusive.
             dropshot::WhichPage::Next(query) => (
-                Timestamp::Exclusive(query.start_time),
+                if query.aggregation.is_some() {
+                    Timestamp::Inclusive(query.start_time)
+                } else {
+                    Timestamp::Exclusive(query.start_time)
+                },
                 Timestamp::Exclusive(query.end_time),
                 query.order,
                 query,
@@ -235,61 +322,93 @@ impl super::Nexus {
         if query.start_time >= query.end_time {
             return Ok(no_results());
         }
+        let aggregation = metric_aggregation(&query)?;
 
-        let timeseries_list = self
-            .timeseries_client
-            .get()
-            .await
-            .map_err(|e| {
-                Error::internal_error(&format!(
-                    "Cannot access timeseries DB: {}",
-                    e
-                ))
-            })?
-            .select_timeseries_with(
-                timeseries_name,
-                criteria,
-                Some(start_time),
-                Some(end_time),
-                Some(limit),
-                order,
-            )
-            .await
-            .or_else(|err| {
-                // If the timeseries name exists in the API, but not in Clickhouse,
-                // it might just not have been populated yet.
-                match err {
-                    oximeter_db::Error::TimeseriesNotFound(_) => Ok(vec![]),
-                    _ => Err(err),
-                }
-            })
-            .map_err(map_oximeter_err)?;
+        let client = self.timeseries_client.get().await.map_err(|e| {
+            Error::internal_error(&format!(
+                "Cannot access timeseries DB: {}",
+                e
+            ))
+        })?;
+        let measurements_list = match &aggregation {
+            None => client
+                .select_timeseries_with(
+                    timeseries_name,
+                    criteria,
+                    Some(start_time),
+                    Some(end_time),
+                    Some(limit),
+                    order,
+                )
+                .await
+                .map(|list| {
+                    list.into_iter()
+                        .map(|timeseries| timeseries.measurements)
+                        .collect::<Vec<_>>()
+                }),
+            Some(aggregation) => client
+                .select_aggregated_timeseries_with(
+                    timeseries_name,
+                    criteria,
+                    Some(start_time),
+                    Some(end_time),
+                    Some(limit),
+                    order,
+                    aggregation.clone(),
+                )
+                .await
+                .map(|list| {
+                    list.into_iter()
+                        .map(|timeseries| timeseries.measurements)
+                        .collect::<Vec<_>>()
+                }),
+        }
+        .or_else(|err| {
+            // If the timeseries name exists in the API, but not in Clickhouse,
+            // it might just not have been populated yet.
+            match err {
+                oximeter_db::Error::TimeseriesNotFound(_) => Ok(vec![]),
+                _ => Err(err),
+            }
+        })
+        .map_err(map_oximeter_err)?;
 
-        if timeseries_list.len() > 1 {
+        if measurements_list.len() > 1 {
             return Err(Error::internal_error(&format!(
                 "expected 1 timeseries but got {} ({:?} {:?})",
-                timeseries_list.len(),
+                measurements_list.len(),
                 timeseries_name,
                 criteria
             )));
         }
 
         // If we received no data, exit early.
-        let timeseries =
-            if let Some(timeseries) = timeseries_list.into_iter().next() {
-                timeseries
+        let measurements =
+            if let Some(measurements) = measurements_list.into_iter().next() {
+                measurements
             } else {
                 return Ok(no_results());
             };
 
         Ok(dropshot::ResultsPage::new(
-            timeseries.measurements,
+            measurements,
             &query,
             |last_measurement: &Measurement, query: &ResourceMetrics| {
+                let start_time = match &aggregation {
+                    None => last_measurement.timestamp(),
+                    Some(aggregation) => {
+                        last_measurement.timestamp()
+                            + chrono::Duration::from_std(aggregation.interval)
+                                .unwrap()
+                    }
+                };
                 ResourceMetrics {
-                    start_time: last_measurement.timestamp(),
+                    start_time,
                     end_time: query.end_time,
                     order: None,
+                    aggregation: query.aggregation,
+                    interval: query.interval,
+                    quantile: query.quantile,
                 }
             },
         )
@@ -316,7 +435,9 @@ impl super::Nexus {
     }
 
     // Return an oximeter collector to assign a newly-registered producer
-    async fn next_collector(&self) -> Result<(OximeterClient, Uuid), Error> {
+    async fn next_collector(
+        &self,
+    ) -> Result<(OximeterClient, Uuid, SocketAddr), Error> {
         // TODO-robustness Replace with a real load-balancing strategy.
         let page_params = DataPageParams {
             marker: None,
@@ -330,15 +451,62 @@ impl super::Nexus {
         let address =
             SocketAddr::from((info.ip.ip(), info.port.try_into().unwrap()));
         let id = info.id;
-        Ok((self.build_oximeter_client(&id, address), id))
+        Ok((self.build_oximeter_client(&id, address), id, address))
     }
 }
 
+// Convert the aggregation requested in the query parameters of a metrics
+// endpoint into the one used by the timeseries database.
+fn metric_aggregation(
+    query: &ResourceMetrics,
+) -> Result<Option<Aggregation>, Error> {
+    const DEFAULT_INTERVAL: u32 = 60;
+    let Some(aggregation) = query.aggregation else {
+        if query.interval.is_some() || query.quantile.is_some() {
+            return Err(Error::invalid_request(
+                "\"interval\" and \"quantile\" require \"aggregation\"",
+            ));
+        }
+        return Ok(None);
+    };
+    let op = match (aggregation, query.quantile) {
+        (MetricAggregation::Quantile, Some(quantile)) => {
+            AggregationOp::Quantile(quantile)
+        }
+        (MetricAggregation::Quantile, None) => {
+            return Err(Error::invalid_request(
+                "the \"quantile\" aggregation requires \"quantile\"",
+            ));
+        }
+        (_, Some(_)) => {
+            return Err(Error::invalid_request(
+                "\"quantile\" only applies to the \"quantile\" aggregation",
+            ));
+        }
+        (MetricAggregation::Mean, None) => AggregationOp::Mean,
+        (MetricAggregation::Min, None) => AggregationOp::Min,
+        (MetricAggregation::Max, None) => AggregationOp::Max,
+        (MetricAggregation::Sum, None) => AggregationOp::Sum,
+        (MetricAggregation::Delta, None) => AggregationOp::Delta,
+        (MetricAggregation::Rate, None) => AggregationOp::Rate,
+    };
+    let interval = query.interval.unwrap_or(DEFAULT_INTERVAL);
+    Ok(Some(Aggregation {
+        op,
+        interval: Duration::from_secs(u64::from(interval)),
+        group_by: vec![],
+    }))
+}
+
 fn map_oximeter_err(error: oximeter_db::Error) -> Error {
     match error {
         oximeter_db::Error::DatabaseUnavailable(_) => {
             Error::ServiceUnavailable { internal_message: error.to_string() }
         }
+        oximeter_db::Error::AggregationNotSupported { .. }
+        | oximeter_db::Error::InvalidAggregation(_) => {
+            Error::invalid_request(&error.to_string())
+        }
         _ => Error::InternalError { internal_message: error.to_string() },
     }
 }
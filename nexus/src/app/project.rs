@@ -150,6 +150,36 @@ impl super::Nexus {
         Ok(shared::Policy { role_assignments })
     }
 
+    // Quotas
+
+    pub(crate) async fn project_quotas_view(
+        &self,
+        opctx: &OpContext,
+        project_lookup: &lookup::Project<'_>,
+    ) -> LookupResult<db::model::VirtualProvisioningQuota> {
+        let (.., authz_project) =
+            project_lookup.lookup_for(authz::Action::Read).await?;
+        self.db_datastore.project_quotas_view(opctx, &authz_project).await
+    }
+
+    pub(crate) async fn project_quotas_update(
+        &self,
+        opctx: &OpContext,
+        project_lookup: &lookup::Project<'_>,
+        updates: &params::ResourceQuotasUpdate,
+    ) -> UpdateResult<db::model::VirtualProvisioningQuota> {
+        let (authz_silo, authz_project) =
+            project_lookup.lookup_for(authz::Action::Read).await?;
+        let quota = db::model::VirtualProvisioningQuota::new(
+            authz_project.id(),
+            db::model::CollectionTypeProvisioned::Project,
+            updates.clone(),
+        );
+        self.db_datastore
+            .project_quotas_update(opctx, &authz_silo, &authz_project, quota)
+            .await
+    }
+
     pub(crate) async fn project_ip_pools_list(
         &self,
         opctx: &OpContext,
@@ -8,6 +8,7 @@ use super::silo::silo_dns_name;
 use crate::external_api::params::CertificateCreate;
 use crate::external_api::shared::ServiceUsingCertificate;
 use crate::internal_api::params::RackInitializationRequest;
+use internal_dns::names::nameserver_dns_name;
 use nexus_db_model::DnsGroup;
 use nexus_db_model::InitialDnsGroup;
 use nexus_db_queries::authz;
@@ -136,12 +137,32 @@ impl super::Nexus {
             dns_zone.records,
         );
 
+        // The external DNS zone's nameservers are the external DNS servers,
+        // at their external addresses.
+        let external_dns_records = request
+            .services
+            .iter()
+            .filter_map(|s| {
+                match &s.kind {
+                nexus_types::internal_api::params::ServiceKind::ExternalDns {
+                    external_address,
+                    ..
+                } => Some(match external_address {
+                    IpAddr::V4(addr) => DnsRecord::A(*addr),
+                    IpAddr::V6(addr) => DnsRecord::Aaaa(*addr),
+                }),
+                _ => None,
+            }
+            })
+            .enumerate()
+            .map(|(i, record)| (nameserver_dns_name(i + 1), vec![record]))
+            .collect();
         let external_dns = InitialDnsGroup::new(
             DnsGroup::External,
             request.external_dns_zone_name.as_str(),
             &self.id.to_string(),
             "rack setup",
-            HashMap::new(),
+            external_dns_records,
         );
 
         let silo_name = &request.recovery_silo.silo_name;
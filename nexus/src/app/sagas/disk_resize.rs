@@ -0,0 +1,1086 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Grow a disk by moving its contents onto a new, larger volume.
+//!
+//! Crucible regions cannot be resized in place, so this saga:
+//!
+//! - moves the disk into the "maintenance" state,
+//! - allocates and ensures a new set of regions of the requested size,
+//! - builds a volume whose read-only parent is the disk's existing volume,
+//!   and asks a Pantry to scrub that volume (copying every block from the
+//!   read-only parent into the new regions),
+//! - points the disk at the new volume (without the read-only parent), and
+//!   deletes the old volume.
+//!
+//! These last two happen in the saga's final node. Once the disk depends on
+//! its new volume, unwinding could neither restore the old volume (which may
+//! be partly deleted) nor safely delete the new one, so the final node retries
+//! deleting the old volume until it succeeds rather than failing. Because the
+//! node is part of the saga, the deletion is resumed if Nexus crashes partway
+//! through it, and the old volume's regions are never leaked.
+//!
+//! Only disks which are detached, or attached to a stopped instance, may be
+//! resized: a running instance's Propolis server holds the disk's volume open,
+//! and there's no way yet to move it onto a new volume. Disks only ever grow:
+//! the blocks beyond the end of the original volume read as zeroes.
+
+use super::{
+    common_storage::{
+        delete_crucible_regions, ensure_all_datasets_and_regions,
+        get_pantry_address,
+    },
+    ActionRegistry, NexusActionContext, NexusSaga, SagaInitError,
+    ACTION_GENERATE_ID,
+};
+use crate::app::sagas::declare_saga_actions;
+use crate::app::sagas::retry_until_known_result;
+use crate::app::sagas::volume_delete;
+use crate::external_api::params;
+use nexus_db_queries::db::datastore::RegionAllocationStrategy;
+use nexus_db_queries::db::identity::Asset;
+use nexus_db_queries::db::lookup::LookupPath;
+use nexus_db_queries::{authn, authz, db};
+use omicron_common::api::external::ByteCount;
+use omicron_common::api::external::Error;
+use omicron_common::backoff;
+use rand::{rngs::StdRng, RngCore, SeedableRng};
+use serde::Deserialize;
+use serde::Serialize;
+use sled_agent_client::types::{CrucibleOpts, VolumeConstructionRequest};
+use std::net::SocketAddrV6;
+use steno::ActionError;
+use steno::Node;
+use uuid::Uuid;
+
+// disk resize saga: input parameters
+
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct Params {
+    pub serialized_authn: authn::saga::Serialized,
+    pub project_id: Uuid,
+    /// The disk as observed when the resize was requested
+    pub disk: db::model::Disk,
+    pub new_size: ByteCount,
+}
+
+// disk resize saga: actions
+
+declare_saga_actions! {
+    disk_resize;
+    SET_MAINTENANCE_STATE -> "maintenance_disk" {
+        + sdr_set_maintenance_state
+        - sdr_set_maintenance_state_undo
+    }
+    SPACE_ACCOUNT -> "no_result" {
+        + sdr_account_space
+        - sdr_account_space_undo
+    }
+    REGIONS_ALLOC -> "datasets_and_regions" {
+        + sdr_alloc_regions
+        - sdr_alloc_regions_undo
+    }
+    REGIONS_ENSURE_UNDO -> "regions_ensure_undo" {
+        + sdr_noop
+        - sdr_regions_ensure_undo
+    }
+    REGIONS_ENSURE -> "regions_ensure" {
+        + sdr_regions_ensure
+    }
+    GET_PANTRY_ADDRESS -> "pantry_address" {
+        + sdr_get_pantry_address
+    }
+    CALL_PANTRY_ATTACH -> "call_pantry_attach" {
+        + sdr_call_pantry_attach
+        - sdr_call_pantry_attach_undo
+    }
+    CALL_PANTRY_SCRUB -> "call_pantry_scrub" {
+        + sdr_call_pantry_scrub
+    }
+    WAIT_FOR_SCRUB -> "wait_for_scrub" {
+        + sdr_wait_for_scrub
+    }
+    CALL_PANTRY_DETACH -> "call_pantry_detach" {
+        + sdr_call_pantry_detach
+    }
+    CREATE_VOLUME_RECORD -> "created_volume" {
+        + sdr_create_volume_record
+        - sdr_create_volume_record_undo
+    }
+    FINALIZE_DISK_RECORD -> "resized_disk" {
+        + sdr_finalize_disk_record
+    }
+}
+
+// disk resize saga: definition
+
+#[derive(Debug)]
+pub(crate) struct SagaDiskResize;
+impl NexusSaga for SagaDiskResize {
+    const NAME: &'static str = "disk-resize";
+    type Params = Params;
+
+    fn register_actions(registry: &mut ActionRegistry) {
+        disk_resize_register_actions(registry);
+    }
+
+    fn make_saga_dag(
+        _params: &Self::Params,
+        mut builder: steno::DagBuilder,
+    ) -> Result<steno::Dag, SagaInitError> {
+        builder.append(Node::action(
+            "new_volume_id",
+            "GenerateVolumeId",
+            ACTION_GENERATE_ID.as_ref(),
+        ));
+
+        builder.append(set_maintenance_state_action());
+        builder.append(space_account_action());
+        builder.append(regions_alloc_action());
+        builder.append(regions_ensure_undo_action());
+        builder.append(regions_ensure_action());
+
+        // Copy the disk's existing contents into the new regions.
+        builder.append(get_pantry_address_action());
+        builder.append(call_pantry_attach_action());
+        builder.append(call_pantry_scrub_action());
+        builder.append(wait_for_scrub_action());
+        builder.append(call_pantry_detach_action());
+
+        builder.append(create_volume_record_action());
+
+        // This must remain the last node, and must not fail: see the module
+        // documentation.
+        builder.append(finalize_disk_record_action());
+
+        Ok(builder.build()?)
+    }
+}
+
+// disk resize saga: action implementations
+
+async fn sdr_set_maintenance_state(
+    sagactx: NexusActionContext,
+) -> Result<db::model::Disk, ActionError> {
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params::<Params>()?;
+    let opctx = crate::context::op_context_for_saga_action(
+        &sagactx,
+        &params.serialized_authn,
+    );
+
+    let (.., authz_disk) = LookupPath::new(&opctx, &osagactx.datastore())
+        .disk_id(params.disk.id())
+        .lookup_for(authz::Action::Modify)
+        .await
+        .map_err(ActionError::action_failed)?;
+
+    osagactx
+        .datastore()
+        .disk_start_resize(&opctx, &authz_disk, &params.disk)
+        .await
+        .map_err(ActionError::action_failed)
+}
+
+async fn sdr_set_maintenance_state_undo(
+    sagactx: NexusActionContext,
+) -> Result<(), anyhow::Error> {
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params::<Params>()?;
+    let opctx = crate::context::op_context_for_saga_action(
+        &sagactx,
+        &params.serialized_authn,
+    );
+
+    let maintenance_disk =
+        sagactx.lookup::<db::model::Disk>("maintenance_disk")?;
+    let (.., authz_disk) = LookupPath::new(&opctx, &osagactx.datastore())
+        .disk_id(params.disk.id())
+        .lookup_for(authz::Action::Modify)
+        .await?;
+
+    // Put the disk back the way it was found. This succeeds if the disk has
+    // already been put back, so that this undo action may be repeated.
+    osagactx
+        .datastore()
+        .disk_finish_resize(
+            &opctx,
+            &authz_disk,
+            maintenance_disk.runtime().gen,
+            params.disk.volume_id,
+            params.disk.size,
+        )
+        .await?;
+    Ok(())
+}
+
+async fn sdr_account_space(
+    sagactx: NexusActionContext,
+) -> Result<(), ActionError> {
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params::<Params>()?;
+    let opctx = crate::context::op_context_for_saga_action(
+        &sagactx,
+        &params.serialized_authn,
+    );
+    osagactx
+        .datastore()
+        .virtual_provisioning_collection_update_disk(
+            &opctx,
+            params.disk.id(),
+            params.project_id,
+            params.disk.size,
+            params.new_size.into(),
+        )
+        .await
+        .map_err(ActionError::action_failed)?;
+    Ok(())
+}
+
+async fn sdr_account_space_undo(
+    sagactx: NexusActionContext,
+) -> Result<(), anyhow::Error> {
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params::<Params>()?;
+    let opctx = crate::context::op_context_for_saga_action(
+        &sagactx,
+        &params.serialized_authn,
+    );
+    osagactx
+        .datastore()
+        .virtual_provisioning_collection_update_disk(
+            &opctx,
+            params.disk.id(),
+            params.project_id,
+            params.new_size.into(),
+            params.disk.size,
+        )
+        .await?;
+    Ok(())
+}
+
+async fn sdr_alloc_regions(
+    sagactx: NexusActionContext,
+) -> Result<Vec<(db::model::Dataset, db::model::Region)>, ActionError> {
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params::<Params>()?;
+    let new_volume_id = sagactx.lookup::<Uuid>("new_volume_id")?;
+    let opctx = crate::context::op_context_for_saga_action(
+        &sagactx,
+        &params.serialized_authn,
+    );
+
+    // The new regions hold a copy of the disk's contents, so they must use
+    // the disk's existing block size.
+    let disk_source = params::DiskSource::Blank {
+        block_size: params::BlockSize(params.disk.block_size.to_bytes()),
+    };
+
+    let datasets_and_regions = osagactx
+        .datastore()
+        .region_allocate(
+            &opctx,
+            new_volume_id,
+            &disk_source,
+            params.new_size,
+            &RegionAllocationStrategy::Random(None),
+        )
+        .await
+        .map_err(ActionError::action_failed)?;
+    Ok(datasets_and_regions)
+}
+
+async fn sdr_alloc_regions_undo(
+    sagactx: NexusActionContext,
+) -> Result<(), anyhow::Error> {
+    let osagactx = sagactx.user_data();
+    let log = osagactx.log();
+
+    let region_ids = sagactx
+        .lookup::<Vec<(db::model::Dataset, db::model::Region)>>(
+            "datasets_and_regions",
+        )?
+        .into_iter()
+        .map(|(_, region)| region.id())
+        .collect::<Vec<Uuid>>();
+
+    osagactx.datastore().regions_hard_delete(log, region_ids).await?;
+    Ok(())
+}
+
+async fn sdr_noop(_sagactx: NexusActionContext) -> Result<(), ActionError> {
+    Ok(())
+}
+
+/// Returns the volume construction request used to scrub the disk's contents
+/// into its new regions.
+///
+/// The request's read-only parent is the disk's existing volume. Once the
+/// scrub has completed, the read-only parent is no longer needed.
+async fn sdr_regions_ensure(
+    sagactx: NexusActionContext,
+) -> Result<VolumeConstructionRequest, ActionError> {
+    let log = sagactx.user_data().log();
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params::<Params>()?;
+
+    let datasets_and_regions = ensure_all_datasets_and_regions(
+        &log,
+        sagactx.lookup::<Vec<(db::model::Dataset, db::model::Region)>>(
+            "datasets_and_regions",
+        )?,
+    )
+    .await?;
+
+    let block_size = datasets_and_regions[0].1.block_size;
+    let blocks_per_extent = datasets_and_regions[0].1.extent_size;
+    let extent_count = datasets_and_regions[0].1.extent_count;
+
+    debug!(
+        log,
+        "grabbing disk {} volume {}",
+        params.disk.id(),
+        params.disk.volume_id,
+    );
+
+    let volume = osagactx
+        .datastore()
+        .volume_checkout(params.disk.volume_id)
+        .await
+        .map_err(ActionError::action_failed)?;
+
+    let old_volume: VolumeConstructionRequest =
+        serde_json::from_str(volume.data()).map_err(|e| {
+            ActionError::action_failed(Error::internal_error(&format!(
+                "failed to deserialize volume data: {}",
+                e,
+            )))
+        })?;
+
+    let disk_id = params.disk.id();
+    let mut rng = StdRng::from_entropy();
+    Ok(VolumeConstructionRequest::Volume {
+        id: disk_id,
+        block_size,
+        sub_volumes: vec![VolumeConstructionRequest::Region {
+            block_size,
+            blocks_per_extent,
+            extent_count: extent_count.try_into().unwrap(),
+            gen: 1,
+            opts: CrucibleOpts {
+                id: disk_id,
+                target: datasets_and_regions
+                    .iter()
+                    .map(|(dataset, region)| {
+                        dataset
+                            .address_with_port(region.port_number)
+                            .to_string()
+                    })
+                    .collect(),
+
+                lossy: false,
+                flush_timeout: None,
+
+                // all downstairs will expect encrypted blocks
+                key: Some(base64::Engine::encode(
+                    &base64::engine::general_purpose::STANDARD,
+                    {
+                        let mut random_bytes: [u8; 32] = [0; 32];
+                        rng.fill_bytes(&mut random_bytes);
+                        random_bytes
+                    },
+                )),
+
+                cert_pem: None,
+                key_pem: None,
+                root_cert_pem: None,
+
+                control: None,
+
+                read_only: false,
+            },
+        }],
+        read_only_parent: Some(Box::new(
+            read_only_volume_construction_request(&old_volume),
+        )),
+    })
+}
+
+async fn sdr_regions_ensure_undo(
+    sagactx: NexusActionContext,
+) -> Result<(), anyhow::Error> {
+    let log = sagactx.user_data().log();
+
+    warn!(log, "sdr_regions_ensure_undo: Deleting crucible regions");
+
+    delete_crucible_regions(
+        log,
+        sagactx.lookup::<Vec<(db::model::Dataset, db::model::Region)>>(
+            "datasets_and_regions",
+        )?,
+    )
+    .await?;
+
+    info!(log, "sdr_regions_ensure_undo: Deleted crucible regions");
+    Ok(())
+}
+
+async fn sdr_get_pantry_address(
+    sagactx: NexusActionContext,
+) -> Result<SocketAddrV6, ActionError> {
+    let log = sagactx.user_data().log();
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params::<Params>()?;
+
+    let pantry_address = get_pantry_address(osagactx.nexus()).await?;
+
+    info!(
+        log,
+        "using pantry at {} to resize disk {}",
+        pantry_address,
+        params.disk.id(),
+    );
+
+    Ok(pantry_address)
+}
+
+async fn sdr_call_pantry_attach(
+    sagactx: NexusActionContext,
+) -> Result<(), ActionError> {
+    let log = sagactx.user_data().log();
+    let new_volume_id = sagactx.lookup::<Uuid>("new_volume_id")?;
+    let pantry_address = sagactx.lookup::<SocketAddrV6>("pantry_address")?;
+    let volume_construction_request =
+        sagactx.lookup::<VolumeConstructionRequest>("regions_ensure")?;
+
+    let endpoint = format!("http://{}", pantry_address);
+
+    info!(
+        log,
+        "sending attach for volume {} to endpoint {}", new_volume_id, endpoint,
+    );
+
+    // Convert between the sled agent's and the Pantry's representation of the
+    // volume construction request.
+    let volume_construction_request: crucible_pantry_client::types::VolumeConstructionRequest =
+        serde_json::to_value(&volume_construction_request)
+            .and_then(serde_json::from_value)
+            .map_err(|e| {
+                ActionError::action_failed(Error::internal_error(&format!(
+                    "failed to convert volume {} data: {}",
+                    new_volume_id, e,
+                )))
+            })?;
+
+    let client = crucible_pantry_client::Client::new(&endpoint);
+
+    let attach_request = crucible_pantry_client::types::AttachRequest {
+        volume_construction_request,
+    };
+
+    retry_until_known_result(log, || async {
+        client.attach(&new_volume_id.to_string(), &attach_request).await
+    })
+    .await
+    .map_err(|e| {
+        ActionError::action_failed(format!("pantry attach failed with {:?}", e))
+    })?;
+
+    Ok(())
+}
+
+async fn sdr_call_pantry_attach_undo(
+    sagactx: NexusActionContext,
+) -> Result<(), anyhow::Error> {
+    let log = sagactx.user_data().log();
+    let new_volume_id = sagactx.lookup::<Uuid>("new_volume_id")?;
+    let pantry_address = sagactx.lookup::<SocketAddrV6>("pantry_address")?;
+
+    call_pantry_detach(log, new_volume_id, pantry_address).await?;
+    Ok(())
+}
+
+async fn sdr_call_pantry_scrub(
+    sagactx: NexusActionContext,
+) -> Result<String, ActionError> {
+    let log = sagactx.user_data().log();
+    let new_volume_id = sagactx.lookup::<Uuid>("new_volume_id")?;
+    let pantry_address = sagactx.lookup::<SocketAddrV6>("pantry_address")?;
+
+    let endpoint = format!("http://{}", pantry_address);
+
+    info!(
+        log,
+        "sending scrub for volume {} to endpoint {}", new_volume_id, endpoint,
+    );
+
+    let client = crucible_pantry_client::Client::new(&endpoint);
+
+    let response = retry_until_known_result(log, || async {
+        client.scrub(&new_volume_id.to_string()).await
+    })
+    .await
+    .map_err(|e| {
+        ActionError::action_failed(format!("pantry scrub failed with {:?}", e))
+    })?;
+
+    Ok(response.job_id.clone())
+}
+
+async fn sdr_wait_for_scrub(
+    sagactx: NexusActionContext,
+) -> Result<(), ActionError> {
+    let log = sagactx.user_data().log();
+    let new_volume_id = sagactx.lookup::<Uuid>("new_volume_id")?;
+    let pantry_address = sagactx.lookup::<SocketAddrV6>("pantry_address")?;
+    let job_id = sagactx.lookup::<String>("call_pantry_scrub")?;
+
+    let endpoint = format!("http://{}", pantry_address);
+
+    let client = crucible_pantry_client::Client::new(&endpoint);
+
+    info!(
+        log,
+        "waiting for scrub job {} for volume {} to complete on pantry {}",
+        job_id,
+        new_volume_id,
+        endpoint,
+    );
+
+    loop {
+        let result = retry_until_known_result(log, || async {
+            client.is_job_finished(&job_id).await
+        })
+        .await
+        .map_err(|e| {
+            ActionError::action_failed(format!(
+                "is_job_finished failed with {:?}",
+                e
+            ))
+        })?;
+
+        if result.job_is_finished {
+            break;
+        }
+
+        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+    }
+
+    info!(
+        log,
+        "scrub job {} for volume {} on pantry {} completed",
+        job_id,
+        new_volume_id,
+        endpoint,
+    );
+
+    let response = retry_until_known_result(log, || async {
+        client.job_result_ok(&job_id).await
+    })
+    .await
+    .map_err(|e| {
+        ActionError::action_failed(format!("job_result_ok failed with {:?}", e))
+    })?;
+
+    if !response.job_result_ok {
+        return Err(ActionError::action_failed(format!("Job {job_id} failed")));
+    }
+
+    Ok(())
+}
+
+async fn sdr_call_pantry_detach(
+    sagactx: NexusActionContext,
+) -> Result<(), ActionError> {
+    let log = sagactx.user_data().log();
+    let new_volume_id = sagactx.lookup::<Uuid>("new_volume_id")?;
+    let pantry_address = sagactx.lookup::<SocketAddrV6>("pantry_address")?;
+
+    call_pantry_detach(log, new_volume_id, pantry_address).await
+}
+
+async fn call_pantry_detach(
+    log: &slog::Logger,
+    volume_id: Uuid,
+    pantry_address: SocketAddrV6,
+) -> Result<(), ActionError> {
+    let endpoint = format!("http://{}", pantry_address);
+
+    info!(
+        log,
+        "sending detach for volume {} to endpoint {}", volume_id, endpoint,
+    );
+
+    let client = crucible_pantry_client::Client::new(&endpoint);
+
+    retry_until_known_result(log, || async {
+        client.detach(&volume_id.to_string()).await
+    })
+    .await
+    .map_err(|e| {
+        ActionError::action_failed(format!("pantry detach failed with {:?}", e))
+    })?;
+
+    Ok(())
+}
+
+async fn sdr_create_volume_record(
+    sagactx: NexusActionContext,
+) -> Result<(), ActionError> {
+    let osagactx = sagactx.user_data();
+
+    let new_volume_id = sagactx.lookup::<Uuid>("new_volume_id")?;
+
+    // The scrub copied every block of the read-only parent into the new
+    // regions, so the disk's new volume does not need one.
+    let volume_construction_request =
+        match sagactx.lookup::<VolumeConstructionRequest>("regions_ensure")? {
+            VolumeConstructionRequest::Volume {
+                id,
+                block_size,
+                sub_volumes,
+                read_only_parent: _,
+            } => VolumeConstructionRequest::Volume {
+                id,
+                block_size,
+                sub_volumes,
+                read_only_parent: None,
+            },
+            vcr => vcr,
+        };
+
+    let volume_data = serde_json::to_string(&volume_construction_request)
+        .map_err(|e| {
+            ActionError::action_failed(Error::internal_error(&e.to_string()))
+        })?;
+
+    let volume = db::model::Volume::new(new_volume_id, volume_data);
+
+    osagactx
+        .datastore()
+        .volume_create(volume)
+        .await
+        .map_err(ActionError::action_failed)?;
+
+    Ok(())
+}
+
+async fn sdr_create_volume_record_undo(
+    sagactx: NexusActionContext,
+) -> Result<(), anyhow::Error> {
+    let osagactx = sagactx.user_data();
+
+    let new_volume_id = sagactx.lookup::<Uuid>("new_volume_id")?;
+    // The new volume has no read-only parent, and its regions are cleaned up
+    // by the undo actions of the earlier nodes.
+    osagactx.datastore().volume_hard_delete(new_volume_id).await?;
+
+    Ok(())
+}
+
+async fn sdr_finalize_disk_record(
+    sagactx: NexusActionContext,
+) -> Result<db::model::Disk, ActionError> {
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params::<Params>()?;
+    let opctx = crate::context::op_context_for_saga_action(
+        &sagactx,
+        &params.serialized_authn,
+    );
+
+    let new_volume_id = sagactx.lookup::<Uuid>("new_volume_id")?;
+    let maintenance_disk =
+        sagactx.lookup::<db::model::Disk>("maintenance_disk")?;
+    let (.., authz_disk) = LookupPath::new(&opctx, &osagactx.datastore())
+        .disk_id(params.disk.id())
+        .lookup_for(authz::Action::Modify)
+        .await
+        .map_err(ActionError::action_failed)?;
+
+    let resized_disk = osagactx
+        .datastore()
+        .disk_finish_resize(
+            &opctx,
+            &authz_disk,
+            maintenance_disk.runtime().gen,
+            new_volume_id,
+            params.new_size.into(),
+        )
+        .await
+        .map_err(ActionError::action_failed)?;
+
+    // The disk no longer refers to its old volume, so delete it. From here on,
+    // this action must not fail (see the module documentation), so keep
+    // retrying. Deleting a volume is idempotent, so this is safe to repeat
+    // after an attempt that failed partway through.
+    let log = osagactx.log();
+    let old_volume_id = params.disk.volume_id;
+    backoff::retry_notify(
+        backoff::retry_policy_internal_service(),
+        || async {
+            let params = volume_delete::Params {
+                serialized_authn: params.serialized_authn.clone(),
+                volume_id: old_volume_id,
+            };
+            osagactx
+                .nexus()
+                .execute_saga::<volume_delete::SagaVolumeDelete>(params)
+                .await
+                .map_err(backoff::BackoffError::transient)
+        },
+        |error, delay| {
+            warn!(
+                log,
+                "failed to delete old volume of resized disk, \
+                will retry in {:?}", delay;
+                "disk_id" => %params.disk.id(),
+                "volume_id" => %old_volume_id,
+                "error" => #%error,
+            );
+        },
+    )
+    .await
+    .map_err(ActionError::action_failed)?;
+
+    Ok(resized_disk)
+}
+
+/// Returns a copy of `input` in which every region is opened read-only.
+fn read_only_volume_construction_request(
+    input: &VolumeConstructionRequest,
+) -> VolumeConstructionRequest {
+    match input {
+        VolumeConstructionRequest::Volume {
+            id,
+            block_size,
+            sub_volumes,
+            read_only_parent,
+        } => VolumeConstructionRequest::Volume {
+            id: *id,
+            block_size: *block_size,
+            sub_volumes: sub_volumes
+                .iter()
+                .map(read_only_volume_construction_request)
+                .collect(),
+            read_only_parent: read_only_parent.as_ref().map(|parent| {
+                Box::new(read_only_volume_construction_request(parent))
+            }),
+        },
+
+        VolumeConstructionRequest::Region {
+            block_size,
+            blocks_per_extent,
+            extent_count,
+            opts,
+            gen,
+        } => {
+            let mut opts = opts.clone();
+            opts.read_only = true;
+
+            VolumeConstructionRequest::Region {
+                block_size: *block_size,
+                blocks_per_extent: *blocks_per_extent,
+                extent_count: *extent_count,
+                opts,
+                gen: *gen,
+            }
+        }
+
+        VolumeConstructionRequest::Url { .. }
+        | VolumeConstructionRequest::File { .. } => input.clone(),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::{
+        app::saga::create_saga_dag, app::sagas::disk_resize::Params,
+        app::sagas::disk_resize::SagaDiskResize,
+    };
+    use async_bb8_diesel::{
+        AsyncConnection, AsyncRunQueryDsl, AsyncSimpleConnection,
+    };
+    use crucible_agent_client::types::State as RegionState;
+    use diesel::{ExpressionMethods, QueryDsl};
+    use dropshot::test_util::ClientTestContext;
+    use nexus_db_queries::context::OpContext;
+    use nexus_db_queries::db;
+    use nexus_db_queries::db::identity::Asset;
+    use nexus_db_queries::db::lookup::LookupPath;
+    use nexus_db_queries::{authn::saga::Serialized, db::datastore::DataStore};
+    use nexus_test_utils::resource_helpers::create_disk;
+    use nexus_test_utils::resource_helpers::create_ip_pool;
+    use nexus_test_utils::resource_helpers::create_project;
+    use nexus_test_utils::resource_helpers::DiskTest;
+    use nexus_test_utils_macros::nexus_test;
+    use omicron_common::api::external::ByteCount;
+    use omicron_common::api::external::DiskState;
+    use uuid::Uuid;
+
+    type ControlPlaneTestContext =
+        nexus_test_utils::ControlPlaneTestContext<crate::Server>;
+
+    const DISK_NAME: &str = "my-disk";
+    const PROJECT_NAME: &str = "springfield-squidport";
+
+    /// Creates a project containing a 1 GiB disk, returning the IDs of both.
+    async fn create_project_and_disk(
+        client: &ClientTestContext,
+    ) -> (Uuid, Uuid) {
+        create_ip_pool(&client, "p0", None).await;
+        let project = create_project(client, PROJECT_NAME).await;
+        let disk = create_disk(client, PROJECT_NAME, DISK_NAME).await;
+        (project.identity.id, disk.identity.id)
+    }
+
+    fn test_opctx(cptestctx: &ControlPlaneTestContext) -> OpContext {
+        OpContext::for_tests(
+            cptestctx.logctx.log.new(o!()),
+            cptestctx.server.apictx().nexus.datastore().clone(),
+        )
+    }
+
+    async fn fetch_disk(
+        opctx: &OpContext,
+        datastore: &DataStore,
+        disk_id: Uuid,
+    ) -> db::model::Disk {
+        let (.., disk) = LookupPath::new(opctx, datastore)
+            .disk_id(disk_id)
+            .fetch()
+            .await
+            .unwrap();
+        disk
+    }
+
+    // Helper for creating disk resize parameters. The saga expects the disk
+    // record as it is when the saga starts, so this must be called again
+    // before each saga is run.
+    async fn new_test_params(
+        opctx: &OpContext,
+        datastore: &DataStore,
+        project_id: Uuid,
+        disk_id: Uuid,
+    ) -> Params {
+        Params {
+            serialized_authn: Serialized::for_opctx(opctx),
+            project_id,
+            disk: fetch_disk(opctx, datastore, disk_id).await,
+            new_size: ByteCount::from_gibibytes_u32(2),
+        }
+    }
+
+    async fn volume_ids(datastore: &DataStore) -> Vec<Uuid> {
+        use nexus_db_queries::db::schema::volume::dsl;
+
+        datastore
+            .pool_for_tests()
+            .await
+            .unwrap()
+            .transaction_async(|conn| async move {
+                conn.batch_execute_async(
+                    nexus_test_utils::db::ALLOW_FULL_TABLE_SCAN_SQL,
+                )
+                .await
+                .unwrap();
+                Ok::<_, nexus_db_queries::db::TransactionError<()>>(
+                    dsl::volume
+                        .filter(dsl::time_deleted.is_null())
+                        .select(dsl::id)
+                        .load_async::<Uuid>(&conn)
+                        .await
+                        .unwrap(),
+                )
+            })
+            .await
+            .unwrap()
+    }
+
+    /// Asserts that the disk, and the resources backing it, are exactly as
+    /// they were before a resize saga was attempted.
+    async fn verify_disk_unchanged(
+        cptestctx: &ControlPlaneTestContext,
+        test: &DiskTest,
+        project_id: Uuid,
+        original: &db::model::Disk,
+    ) {
+        let sled_agent = &cptestctx.sled_agent.sled_agent;
+        let datastore = cptestctx.server.apictx().nexus.datastore();
+        let opctx = test_opctx(cptestctx);
+
+        crate::app::sagas::test_helpers::assert_no_failed_undo_steps(
+            &cptestctx.logctx.log,
+            datastore,
+        )
+        .await;
+
+        let disk = fetch_disk(&opctx, datastore, original.id()).await;
+        assert_eq!(DiskState::from(disk.state()), DiskState::Detached);
+        assert_eq!(disk.volume_id, original.volume_id);
+        assert_eq!(disk.size, original.size);
+        assert_eq!(volume_ids(datastore).await, vec![original.volume_id]);
+
+        let virtual_provisioning_collection = datastore
+            .virtual_provisioning_collection_get(&opctx, project_id)
+            .await
+            .unwrap();
+        assert_eq!(
+            virtual_provisioning_collection.virtual_disk_bytes_provisioned.0,
+            original.size.0,
+        );
+
+        for zpool in &test.zpools {
+            for dataset in &zpool.datasets {
+                assert_eq!(
+                    datastore
+                        .regions_total_occupied_size(dataset.id)
+                        .await
+                        .unwrap(),
+                    original.size.0.to_bytes(),
+                );
+
+                let crucible_dataset =
+                    sled_agent.get_crucible_dataset(zpool.id, dataset.id).await;
+                let live_regions = crucible_dataset
+                    .list()
+                    .await
+                    .into_iter()
+                    .filter(|region| region.state != RegionState::Destroyed)
+                    .count();
+                assert_eq!(live_regions, 1);
+            }
+        }
+    }
+
+    #[nexus_test(server = crate::Server)]
+    async fn test_saga_basic_usage_succeeds(
+        cptestctx: &ControlPlaneTestContext,
+    ) {
+        DiskTest::new(cptestctx).await;
+
+        let client = &cptestctx.external_client;
+        let nexus = &cptestctx.server.apictx().nexus;
+        let (project_id, disk_id) = create_project_and_disk(&client).await;
+
+        let opctx = test_opctx(cptestctx);
+        let params =
+            new_test_params(&opctx, nexus.datastore(), project_id, disk_id)
+                .await;
+        let old_volume_id = params.disk.volume_id;
+        let dag = create_saga_dag::<SagaDiskResize>(params).unwrap();
+        let runnable_saga = nexus.create_runnable_saga(dag).await.unwrap();
+
+        let output = nexus.run_saga(runnable_saga).await.unwrap();
+
+        let disk = output
+            .lookup_node_output::<db::model::Disk>("resized_disk")
+            .unwrap();
+        assert_eq!(disk.size.0, ByteCount::from_gibibytes_u32(2));
+        assert_eq!(DiskState::from(disk.state()), DiskState::Detached);
+        assert_ne!(disk.volume_id, old_volume_id);
+
+        // The saga deleted the disk's old volume.
+        assert_eq!(volume_ids(nexus.datastore()).await, vec![disk.volume_id]);
+    }
+
+    #[nexus_test(server = crate::Server)]
+    async fn test_action_failure_can_unwind(
+        cptestctx: &ControlPlaneTestContext,
+    ) {
+        let test = DiskTest::new(cptestctx).await;
+        let log = &cptestctx.logctx.log;
+
+        let client = &cptestctx.external_client;
+        let nexus = &cptestctx.server.apictx().nexus;
+        let (project_id, disk_id) = create_project_and_disk(&client).await;
+        let opctx = test_opctx(cptestctx);
+        let original = fetch_disk(&opctx, nexus.datastore(), disk_id).await;
+
+        crate::app::sagas::test_helpers::action_failure_can_unwind::<
+            SagaDiskResize,
+            _,
+            _,
+        >(
+            nexus,
+            || {
+                Box::pin(new_test_params(
+                    &opctx,
+                    nexus.datastore(),
+                    project_id,
+                    disk_id,
+                ))
+            },
+            || {
+                Box::pin(async {
+                    verify_disk_unchanged(
+                        &cptestctx, &test, project_id, &original,
+                    )
+                    .await;
+                })
+            },
+            log,
+        )
+        .await;
+    }
+
+    #[nexus_test(server = crate::Server)]
+    async fn test_action_failure_can_unwind_idempotently(
+        cptestctx: &ControlPlaneTestContext,
+    ) {
+        let test = DiskTest::new(cptestctx).await;
+        let log = &cptestctx.logctx.log;
+
+        let client = &cptestctx.external_client;
+        let nexus = &cptestctx.server.apictx().nexus;
+        let (project_id, disk_id) = create_project_and_disk(&client).await;
+        let opctx = test_opctx(cptestctx);
+        let original = fetch_disk(&opctx, nexus.datastore(), disk_id).await;
+
+        crate::app::sagas::test_helpers::action_failure_can_unwind_idempotently::<
+            SagaDiskResize,
+            _,
+            _
+        >(
+            nexus,
+            || Box::pin(new_test_params(
+                &opctx,
+                nexus.datastore(),
+                project_id,
+                disk_id,
+            )),
+            || Box::pin(async {
+                verify_disk_unchanged(
+                    &cptestctx, &test, project_id, &original,
+                ).await;
+            }),
+            log
+        ).await;
+    }
+
+    #[nexus_test(server = crate::Server)]
+    async fn test_actions_succeed_idempotently(
+        cptestctx: &ControlPlaneTestContext,
+    ) {
+        DiskTest::new(cptestctx).await;
+
+        let client = &cptestctx.external_client;
+        let nexus = &cptestctx.server.apictx().nexus;
+        let (project_id, disk_id) = create_project_and_disk(&client).await;
+        let opctx = test_opctx(cptestctx);
+
+        let params =
+            new_test_params(&opctx, nexus.datastore(), project_id, disk_id)
+                .await;
+        let dag = create_saga_dag::<SagaDiskResize>(params).unwrap();
+        crate::app::sagas::test_helpers::actions_succeed_idempotently(
+            nexus, dag,
+        )
+        .await;
+
+        let disk = fetch_disk(&opctx, nexus.datastore(), disk_id).await;
+        assert_eq!(disk.size.0, ByteCount::from_gibibytes_u32(2));
+        assert_eq!(DiskState::from(disk.state()), DiskState::Detached);
+        assert_eq!(volume_ids(nexus.datastore()).await, vec![disk.volume_id]);
+    }
+}
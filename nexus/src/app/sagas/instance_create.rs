@@ -50,6 +50,9 @@ pub(crate) struct Params {
     pub project_id: Uuid,
     pub create_params: params::InstanceCreate,
     pub boundary_switches: HashSet<SwitchLocation>,
+    /// IDs of the affinity groups named in `create_params`, resolved within
+    /// the instance's project
+    pub affinity_group_ids: Vec<Uuid>,
 }
 
 // Several nodes in this saga are wrapped in their own subsaga so that they can
@@ -507,9 +510,9 @@ async fn sic_alloc_server(
     //   multi-rack, this is going to fling the sled to an arbitrary system.
     //   Maybe that's okay, but worth knowing about explicitly.
     //
-    // - This doesn't take into account anti-affinity - users will want to
-    //   schedule instances that belong to a cluster on different failure
-    //   domains. See https://github.com/oxidecomputer/omicron/issues/1705.
+    // - Affinity groups only constrain which sled is chosen; they say
+    //   nothing about other failure domains (e.g., racks or power
+    //   supplies). See https://github.com/oxidecomputer/omicron/issues/1705.
 
     // TODO: Fix these values. They're wrong now, but they let us move
     // forward with plumbing.
@@ -522,19 +525,27 @@ async fn sic_alloc_server(
     // Propolis consumes its own resources, and an instance can have multiple
     // Propolises during a live migration.
     let propolis_id = sagactx.lookup::<Uuid>("propolis_id")?;
+    let instance_id = sagactx.lookup::<Uuid>("instance_id")?;
     let resources = db::model::Resources::new(
         hardware_threads.into(),
         rss_ram.into(),
         reservoir_ram.into(),
     );
 
+    // The instance record doesn't exist yet, so its affinity groups come from
+    // the saga parameters rather than from its memberships.
+    let constraints = db::model::SledReservationConstraintBuilder::new()
+        .affinity_instance(instance_id)
+        .affinity_groups(&params.affinity_group_ids)
+        .build();
+
     let resource = osagactx
         .nexus()
         .reserve_on_random_sled(
             propolis_id,
             db::model::SledResourceKind::Instance,
             resources,
-            db::model::SledReservationConstraints::none(),
+            constraints,
         )
         .await
         .map_err(ActionError::action_failed)?;
@@ -1116,6 +1127,23 @@ async fn sic_create_instance_record(
         .await
         .map_err(ActionError::action_failed)?;
 
+    // If this fails, undoing this action deletes the instance, which also
+    // removes it from any groups it joined.
+    let (.., authz_instance) = LookupPath::new(&opctx, &osagactx.datastore())
+        .instance_id(instance_id)
+        .lookup_for(authz::Action::Modify)
+        .await
+        .map_err(ActionError::action_failed)?;
+    osagactx
+        .datastore()
+        .instance_affinity_groups_join(
+            &opctx,
+            &authz_instance,
+            &params.affinity_group_ids,
+        )
+        .await
+        .map_err(ActionError::action_failed)?;
+
     Ok(instance.name().clone().into())
 }
 
@@ -1432,9 +1460,11 @@ pub mod test {
                         name: DISK_NAME.parse().unwrap(),
                     },
                 )],
+                affinity_groups: vec![],
                 start: false,
             },
             boundary_switches: HashSet::from([SwitchLocation::Switch0]),
+            affinity_group_ids: vec![],
         }
     }
 
@@ -221,6 +221,13 @@ async fn sid_deallocate_external_ip(
         )
         .await
         .map_err(ActionError::action_failed)?;
+    // Floating IPs outlive the instance, so they're detached rather than
+    // deallocated.
+    osagactx
+        .datastore()
+        .detach_floating_ips_by_instance_id(&opctx, params.authz_instance.id())
+        .await
+        .map_err(ActionError::action_failed)?;
     Ok(())
 }
 
@@ -363,6 +370,8 @@ mod test {
             disks: vec![params::InstanceDiskAttachment::Attach(
                 params::InstanceDiskAttach { name: DISK_NAME.parse().unwrap() },
             )],
+            affinity_groups: vec![],
+            affinity_groups: vec![],
             start: false,
         }
     }
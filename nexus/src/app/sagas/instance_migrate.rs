@@ -224,9 +224,11 @@ async fn sim_reserve_sled_resources(
     );
 
     // Add a constraint that the only allowed sled is the one specified in the
-    // parameters.
+    // parameters, and that the rules of the instance's affinity groups hold
+    // there.
     let constraints = db::model::SledReservationConstraintBuilder::new()
         .must_select_from(&[params.migrate_params.dst_sled_id])
+        .affinity_instance(params.instance.id())
         .build();
 
     let propolis_id = sagactx.lookup::<Uuid>("dst_propolis_id")?;
@@ -546,6 +548,7 @@ mod tests {
                     params::InstanceNetworkInterfaceAttachment::None,
                 external_ips: vec![],
                 disks: vec![],
+                affinity_groups: vec![],
                 start: true,
             },
         )
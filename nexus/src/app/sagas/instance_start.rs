@@ -41,6 +41,10 @@ declare_saga_actions! {
         - sis_move_to_starting_undo
     }
 
+    CHECK_AFFINITY -> "check_affinity" {
+        + sis_check_affinity
+    }
+
     // TODO(#3879) This can be replaced with an action that triggers the NAT RPW
     // once such an RPW is available.
     DPD_ENSURE -> "dpd_ensure" {
@@ -78,6 +82,7 @@ impl NexusSaga for SagaInstanceStart {
         mut builder: steno::DagBuilder,
     ) -> Result<steno::Dag, SagaInitError> {
         builder.append(mark_as_starting_action());
+        builder.append(check_affinity_action());
         builder.append(dpd_ensure_action());
         builder.append(v2p_ensure_action());
         builder.append(ensure_registered_action());
@@ -203,6 +208,32 @@ async fn sis_move_to_starting_undo(
     Ok(())
 }
 
+async fn sis_check_affinity(
+    sagactx: NexusActionContext,
+) -> Result<(), ActionError> {
+    let params = sagactx.saga_params::<Params>()?;
+    let osagactx = sagactx.user_data();
+    let opctx = crate::context::op_context_for_saga_action(
+        &sagactx,
+        &params.serialized_authn,
+    );
+
+    // An instance starts on the sled it last ran on, which other members of
+    // its affinity groups may have been placed alongside while it was
+    // stopped. Now that it's Starting, it counts as occupying that sled, so
+    // checking its groups' rules here can't race with placing another member.
+    let (.., authz_instance) = LookupPath::new(&opctx, &osagactx.datastore())
+        .instance_id(params.instance.id())
+        .lookup_for(authz::Action::Read)
+        .await
+        .map_err(ActionError::action_failed)?;
+    osagactx
+        .datastore()
+        .instance_affinity_rules_check(&opctx, &authz_instance)
+        .await
+        .map_err(ActionError::action_failed)
+}
+
 async fn sis_dpd_ensure(
     sagactx: NexusActionContext,
 ) -> Result<(), ActionError> {
@@ -547,6 +578,7 @@ mod test {
                     params::InstanceNetworkInterfaceAttachment::None,
                 external_ips: vec![],
                 disks: vec![],
+                affinity_groups: vec![],
                 start: false,
             },
         )
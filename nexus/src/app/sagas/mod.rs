@@ -21,6 +21,7 @@ use uuid::Uuid;
 
 pub mod disk_create;
 pub mod disk_delete;
+pub mod disk_resize;
 pub mod finalize_disk;
 pub mod import_blocks_from_url;
 pub mod instance_create;
@@ -120,6 +121,7 @@ fn make_action_registry() -> ActionRegistry {
 
     <disk_create::SagaDiskCreate as NexusSaga>::register_actions(&mut registry);
     <disk_delete::SagaDiskDelete as NexusSaga>::register_actions(&mut registry);
+    <disk_resize::SagaDiskResize as NexusSaga>::register_actions(&mut registry);
     <finalize_disk::SagaFinalizeDisk as NexusSaga>::register_actions(
         &mut registry,
     );
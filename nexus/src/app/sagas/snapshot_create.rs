@@ -1955,6 +1955,7 @@ mod test {
                     params::InstanceNetworkInterfaceAttachment::None,
                 disks: disks_to_attach,
                 external_ips: vec![],
+                affinity_groups: vec![],
                 start: true,
             },
         )
@@ -0,0 +1,520 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! SCIM 2.0 provisioning of Silo users and groups
+//!
+//! With just-in-time provisioning, a user's record and group memberships are
+//! only updated when they log in.  SCIM (RFC 7643, RFC 7644) lets the identity
+//! provider push changes as they happen instead: in particular, a user who is
+//! deactivated in the identity provider is deactivated here right away, which
+//! also revokes their sessions and access tokens.
+//!
+//! The identity provider's SCIM client authenticates with a bearer token that
+//! is scoped to a single Silo.  Requests from it are carried out using the
+//! Nexus "external authentication" context, as with logins.
+
+use nexus_db_model::UserProvisionType;
+use nexus_db_model::SCIM_TOKEN_PREFIX;
+use nexus_db_queries::authz;
+use nexus_db_queries::context::OpContext;
+use nexus_db_queries::db;
+use nexus_db_queries::db::identity::Asset;
+use nexus_db_queries::db::lookup;
+use nexus_db_queries::db::lookup::LookupPath;
+use nexus_db_queries::db::model::ScimClientBearerToken;
+use omicron_common::api::external::CreateResult;
+use omicron_common::api::external::DataPageParams;
+use omicron_common::api::external::DeleteResult;
+use omicron_common::api::external::Error;
+use omicron_common::api::external::ListResultVec;
+use omicron_common::api::external::LookupResult;
+use omicron_common::api::external::LookupType;
+use omicron_common::api::external::ResourceType;
+use omicron_common::api::external::UpdateResult;
+use std::collections::BTreeSet;
+use uuid::Uuid;
+
+impl super::Nexus {
+    // SCIM client bearer tokens
+
+    pub(crate) async fn scim_client_bearer_token_create(
+        &self,
+        opctx: &OpContext,
+        silo_lookup: &lookup::Silo<'_>,
+    ) -> CreateResult<ScimClientBearerToken> {
+        let (authz_silo, db_silo) = silo_lookup.fetch().await?;
+
+        // Users of a Silo with API-only provisioning are managed through the
+        // Oxide API directly.
+        if db_silo.user_provision_type != UserProvisionType::Jit {
+            return Err(Error::invalid_request(
+                "SCIM provisioning requires a silo whose users are \
+                provisioned by an identity provider",
+            ));
+        }
+
+        self.db_datastore
+            .scim_client_bearer_token_create(
+                opctx,
+                &authz_silo,
+                ScimClientBearerToken::new(authz_silo.id()),
+            )
+            .await
+    }
+
+    pub(crate) async fn scim_client_bearer_tokens_list(
+        &self,
+        opctx: &OpContext,
+        silo_lookup: &lookup::Silo<'_>,
+        pagparams: &DataPageParams<'_, Uuid>,
+    ) -> ListResultVec<ScimClientBearerToken> {
+        let (authz_silo,) = silo_lookup.lookup_for(authz::Action::Read).await?;
+        self.db_datastore
+            .scim_client_bearer_tokens_list(opctx, &authz_silo, pagparams)
+            .await
+    }
+
+    pub(crate) async fn scim_client_bearer_token_delete(
+        &self,
+        opctx: &OpContext,
+        silo_lookup: &lookup::Silo<'_>,
+        token_id: Uuid,
+    ) -> DeleteResult {
+        let (authz_silo,) = silo_lookup.lookup_for(authz::Action::Read).await?;
+        self.db_datastore
+            .scim_client_bearer_token_delete(opctx, &authz_silo, token_id)
+            .await
+    }
+
+    /// Authenticate a SCIM client, returning the Silo that it may provision
+    ///
+    /// `opctx` should be the external authentication context.
+    pub(crate) async fn scim_authenticate(
+        &self,
+        opctx: &OpContext,
+        bearer_token: &str,
+    ) -> LookupResult<(authz::Silo, db::model::Silo)> {
+        let unauthenticated = |internal_message: &str| Error::Unauthenticated {
+            internal_message: internal_message.to_string(),
+        };
+
+        let token = bearer_token
+            .strip_prefix(SCIM_TOKEN_PREFIX)
+            .ok_or_else(|| unauthenticated("not a SCIM bearer token"))?;
+        let db_token = self
+            .db_datastore
+            .scim_client_bearer_token_fetch(opctx, token.to_string())
+            .await
+            .map_err(|e| match e {
+                Error::ObjectNotFound { .. } => {
+                    unauthenticated("unknown SCIM bearer token")
+                }
+                e => e,
+            })?;
+
+        let (authz_silo, db_silo) = LookupPath::new(opctx, &self.db_datastore)
+            .silo_id(db_token.silo_id)
+            .fetch()
+            .await
+            .map_err(|e| match e {
+                Error::ObjectNotFound { .. } => {
+                    unauthenticated("SCIM bearer token's silo does not exist")
+                }
+                e => e,
+            })?;
+        Ok((authz_silo, db_silo))
+    }
+
+    // SCIM users
+
+    /// List the users in a Silo, returning the total number of users that
+    /// match along with the requested page of them
+    ///
+    /// If `external_id` is given, only the user with that external id (if
+    /// any) matches.
+    pub(crate) async fn scim_users_list(
+        &self,
+        opctx: &OpContext,
+        authz_silo: &authz::Silo,
+        external_id: Option<&str>,
+        offset: i64,
+        limit: i64,
+    ) -> Result<(i64, Vec<db::model::SiloUser>), Error> {
+        if let Some(external_id) = external_id {
+            let users = self
+                .db_datastore
+                .silo_user_fetch_by_external_id(opctx, authz_silo, external_id)
+                .await?
+                .map(|(_, db_silo_user)| db_silo_user)
+                .into_iter()
+                .collect::<Vec<_>>();
+            let total = users.len() as i64;
+            let users = users
+                .into_iter()
+                .skip(usize::try_from(offset).unwrap_or(usize::MAX))
+                .take(usize::try_from(limit).unwrap_or(0))
+                .collect();
+            return Ok((total, users));
+        }
+
+        let authz_silo_user_list = authz::SiloUserList::new(authz_silo.clone());
+        let total = self
+            .db_datastore
+            .silo_users_count(opctx, &authz_silo_user_list)
+            .await?;
+        let users = self
+            .db_datastore
+            .silo_users_list_by_offset(
+                opctx,
+                &authz_silo_user_list,
+                offset,
+                limit,
+            )
+            .await?;
+        Ok((total, users))
+    }
+
+    pub(crate) async fn scim_user_fetch(
+        &self,
+        opctx: &OpContext,
+        authz_silo: &authz::Silo,
+        silo_user_id: Uuid,
+    ) -> LookupResult<db::model::SiloUser> {
+        let (_, db_silo_user) = self
+            .silo_user_lookup_by_id(
+                opctx,
+                authz_silo,
+                silo_user_id,
+                authz::Action::Read,
+            )
+            .await?;
+        Ok(db_silo_user)
+    }
+
+    pub(crate) async fn scim_user_create(
+        &self,
+        opctx: &OpContext,
+        authz_silo: &authz::Silo,
+        external_id: String,
+        active: bool,
+    ) -> CreateResult<db::model::SiloUser> {
+        opctx.authorize(authz::Action::CreateChild, authz_silo).await?;
+
+        let silo_user = db::model::SiloUser::new(
+            authz_silo.id(),
+            Uuid::new_v4(),
+            external_id,
+        );
+        let (authz_silo_user, db_silo_user) =
+            self.db_datastore.silo_user_create(authz_silo, silo_user).await?;
+
+        if active {
+            Ok(db_silo_user)
+        } else {
+            self.db_datastore
+                .silo_user_update(
+                    opctx,
+                    &authz_silo_user,
+                    db_silo_user.external_id,
+                    active,
+                )
+                .await
+        }
+    }
+
+    /// Replace a Silo user's external id and whether they are active
+    pub(crate) async fn scim_user_update(
+        &self,
+        opctx: &OpContext,
+        authz_silo: &authz::Silo,
+        silo_user_id: Uuid,
+        external_id: String,
+        active: bool,
+    ) -> UpdateResult<db::model::SiloUser> {
+        let (authz_silo_user, _) = self
+            .silo_user_lookup_by_id(
+                opctx,
+                authz_silo,
+                silo_user_id,
+                authz::Action::Modify,
+            )
+            .await?;
+        self.db_datastore
+            .silo_user_update(opctx, &authz_silo_user, external_id, active)
+            .await
+    }
+
+    pub(crate) async fn scim_user_delete(
+        &self,
+        opctx: &OpContext,
+        authz_silo: &authz::Silo,
+        silo_user_id: Uuid,
+    ) -> DeleteResult {
+        let (authz_silo_user, _) = self
+            .silo_user_lookup_by_id(
+                opctx,
+                authz_silo,
+                silo_user_id,
+                authz::Action::Delete,
+            )
+            .await?;
+        self.db_datastore.silo_user_delete(opctx, &authz_silo_user).await
+    }
+
+    // SCIM groups
+
+    /// List the groups in a Silo, along with their members, returning the
+    /// total number of groups that match along with the requested page of them
+    ///
+    /// If `external_id` is given, only the group with that external id (if
+    /// any) matches.
+    pub(crate) async fn scim_groups_list(
+        &self,
+        opctx: &OpContext,
+        authz_silo: &authz::Silo,
+        external_id: Option<&str>,
+        offset: i64,
+        limit: i64,
+    ) -> Result<(i64, Vec<(db::model::SiloGroup, Vec<Uuid>)>), Error> {
+        let (total, groups) = if let Some(external_id) = external_id {
+            let groups = self
+                .db_datastore
+                .silo_group_optional_lookup(
+                    opctx,
+                    authz_silo,
+                    external_id.to_string(),
+                )
+                .await?
+                .into_iter()
+                .collect::<Vec<_>>();
+            let total = groups.len() as i64;
+            let groups = groups
+                .into_iter()
+                .skip(usize::try_from(offset).unwrap_or(usize::MAX))
+                .take(usize::try_from(limit).unwrap_or(0))
+                .collect();
+            (total, groups)
+        } else {
+            let total =
+                self.db_datastore.silo_groups_count(opctx, authz_silo).await?;
+            let groups = self
+                .db_datastore
+                .silo_groups_list_by_offset(opctx, authz_silo, offset, limit)
+                .await?;
+            (total, groups)
+        };
+
+        let mut groups_with_members = Vec::with_capacity(groups.len());
+        for db_silo_group in groups {
+            let authz_silo_group = authz::SiloGroup::new(
+                authz_silo.clone(),
+                db_silo_group.id(),
+                LookupType::ById(db_silo_group.id()),
+            );
+            let members =
+                self.scim_group_members(opctx, &authz_silo_group).await?;
+            groups_with_members.push((db_silo_group, members));
+        }
+        Ok((total, groups_with_members))
+    }
+
+    pub(crate) async fn scim_group_fetch(
+        &self,
+        opctx: &OpContext,
+        authz_silo: &authz::Silo,
+        silo_group_id: Uuid,
+    ) -> LookupResult<(db::model::SiloGroup, Vec<Uuid>)> {
+        let (authz_silo_group, db_silo_group) = self
+            .scim_group_lookup(
+                opctx,
+                authz_silo,
+                silo_group_id,
+                authz::Action::Read,
+            )
+            .await?;
+        let members = self.scim_group_members(opctx, &authz_silo_group).await?;
+        Ok((db_silo_group, members))
+    }
+
+    pub(crate) async fn scim_group_create(
+        &self,
+        opctx: &OpContext,
+        authz_silo: &authz::Silo,
+        external_id: String,
+        members: Vec<Uuid>,
+    ) -> CreateResult<(db::model::SiloGroup, Vec<Uuid>)> {
+        // `silo_group_ensure` would quietly return a group that already
+        // exists, but SCIM clients expect to be told about the conflict.
+        if self
+            .db_datastore
+            .silo_group_optional_lookup(opctx, authz_silo, external_id.clone())
+            .await?
+            .is_some()
+        {
+            return Err(Error::ObjectAlreadyExists {
+                type_name: ResourceType::SiloGroup,
+                object_name: external_id,
+            });
+        }
+        let members = self
+            .scim_group_validate_members(opctx, authz_silo, members)
+            .await?;
+
+        let db_silo_group = self
+            .db_datastore
+            .silo_group_ensure(
+                opctx,
+                authz_silo,
+                db::model::SiloGroup::new(
+                    Uuid::new_v4(),
+                    authz_silo.id(),
+                    external_id,
+                ),
+            )
+            .await?;
+        let authz_silo_group = authz::SiloGroup::new(
+            authz_silo.clone(),
+            db_silo_group.id(),
+            LookupType::ById(db_silo_group.id()),
+        );
+        self.db_datastore
+            .silo_group_membership_replace_for_group(
+                opctx,
+                &authz_silo_group,
+                members.clone(),
+            )
+            .await?;
+        Ok((db_silo_group, members))
+    }
+
+    /// Replace a Silo group's external id and members
+    pub(crate) async fn scim_group_update(
+        &self,
+        opctx: &OpContext,
+        authz_silo: &authz::Silo,
+        silo_group_id: Uuid,
+        external_id: String,
+        members: Vec<Uuid>,
+    ) -> UpdateResult<(db::model::SiloGroup, Vec<Uuid>)> {
+        let (authz_silo_group, db_silo_group) = self
+            .scim_group_lookup(
+                opctx,
+                authz_silo,
+                silo_group_id,
+                authz::Action::Modify,
+            )
+            .await?;
+        let members = self
+            .scim_group_validate_members(opctx, authz_silo, members)
+            .await?;
+
+        let db_silo_group = if db_silo_group.external_id != external_id {
+            self.db_datastore
+                .silo_group_update_external_id(
+                    opctx,
+                    &authz_silo_group,
+                    external_id,
+                )
+                .await?
+        } else {
+            db_silo_group
+        };
+        self.db_datastore
+            .silo_group_membership_replace_for_group(
+                opctx,
+                &authz_silo_group,
+                members.clone(),
+            )
+            .await?;
+        Ok((db_silo_group, members))
+    }
+
+    pub(crate) async fn scim_group_delete(
+        &self,
+        opctx: &OpContext,
+        authz_silo: &authz::Silo,
+        silo_group_id: Uuid,
+    ) -> DeleteResult {
+        let (authz_silo_group, _) = self
+            .scim_group_lookup(
+                opctx,
+                authz_silo,
+                silo_group_id,
+                authz::Action::Delete,
+            )
+            .await?;
+
+        // Groups that still have members cannot be deleted, but the identity
+        // provider is authoritative here: removing the group removes its
+        // memberships too.
+        self.db_datastore
+            .silo_group_membership_replace_for_group(
+                opctx,
+                &authz_silo_group,
+                vec![],
+            )
+            .await?;
+        self.db_datastore.silo_group_delete(opctx, &authz_silo_group).await
+    }
+
+    async fn scim_group_lookup(
+        &self,
+        opctx: &OpContext,
+        authz_silo: &authz::Silo,
+        silo_group_id: Uuid,
+        action: authz::Action,
+    ) -> LookupResult<(authz::SiloGroup, db::model::SiloGroup)> {
+        let (_, authz_silo_group, db_silo_group) =
+            LookupPath::new(opctx, &self.db_datastore)
+                .silo_group_id(silo_group_id)
+                .fetch_for(action)
+                .await?;
+        if db_silo_group.silo_id != authz_silo.id() {
+            return Err(authz_silo_group.not_found());
+        }
+        Ok((authz_silo_group, db_silo_group))
+    }
+
+    async fn scim_group_members(
+        &self,
+        opctx: &OpContext,
+        authz_silo_group: &authz::SiloGroup,
+    ) -> ListResultVec<Uuid> {
+        Ok(self
+            .db_datastore
+            .silo_group_membership_for_group(opctx, authz_silo_group)
+            .await?
+            .into_iter()
+            .map(|membership| membership.silo_user_id)
+            .collect())
+    }
+
+    /// Check that the proposed members of a group are users in the group's
+    /// Silo, returning them without duplicates
+    async fn scim_group_validate_members(
+        &self,
+        opctx: &OpContext,
+        authz_silo: &authz::Silo,
+        members: Vec<Uuid>,
+    ) -> ListResultVec<Uuid> {
+        let members: BTreeSet<Uuid> = members.into_iter().collect();
+        for silo_user_id in &members {
+            self.silo_user_lookup_by_id(
+                opctx,
+                authz_silo,
+                *silo_user_id,
+                authz::Action::Read,
+            )
+            .await
+            .map_err(|e| match e {
+                Error::ObjectNotFound { .. } => Error::InvalidValue {
+                    label: String::from("members"),
+                    message: format!("no such user: {}", silo_user_id),
+                },
+                e => e,
+            })?;
+        }
+        Ok(members.into_iter().collect())
+    }
+}
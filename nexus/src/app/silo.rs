@@ -217,13 +217,41 @@ impl super::Nexus {
         Ok(shared::Policy { role_assignments })
     }
 
+    // Quotas
+
+    pub(crate) async fn silo_quotas_view(
+        &self,
+        opctx: &OpContext,
+        silo_lookup: &lookup::Silo<'_>,
+    ) -> LookupResult<db::model::VirtualProvisioningQuota> {
+        let (.., authz_silo) =
+            silo_lookup.lookup_for(authz::Action::Read).await?;
+        self.db_datastore.silo_quotas_view(opctx, &authz_silo).await
+    }
+
+    pub(crate) async fn silo_quotas_update(
+        &self,
+        opctx: &OpContext,
+        silo_lookup: &lookup::Silo<'_>,
+        updates: &params::ResourceQuotasUpdate,
+    ) -> UpdateResult<db::model::VirtualProvisioningQuota> {
+        let (.., authz_silo) =
+            silo_lookup.lookup_for(authz::Action::Read).await?;
+        let quota = db::model::VirtualProvisioningQuota::new(
+            authz_silo.id(),
+            db::model::CollectionTypeProvisioned::Silo,
+            updates.clone(),
+        );
+        self.db_datastore.silo_quotas_update(opctx, &authz_silo, quota).await
+    }
+
     // Users
 
     /// Helper function for looking up a user in a Silo
     ///
     /// `LookupPath` lets you look up users directly, regardless of what Silo
     /// they're in.  This helper validates that they're in the expected Silo.
-    async fn silo_user_lookup_by_id(
+    pub(super) async fn silo_user_lookup_by_id(
         &self,
         opctx: &OpContext,
         authz_silo: &authz::Silo,
@@ -275,6 +303,28 @@ impl super::Nexus {
         Ok(db_silo_user)
     }
 
+    /// List the access tokens held by a Silo user
+    pub(crate) async fn silo_user_access_tokens_list(
+        &self,
+        opctx: &OpContext,
+        silo_lookup: &lookup::Silo<'_>,
+        silo_user_id: Uuid,
+        pagparams: &DataPageParams<'_, Uuid>,
+    ) -> ListResultVec<db::model::DeviceAccessToken> {
+        let (authz_silo,) = silo_lookup.lookup_for(authz::Action::Read).await?;
+        let (authz_silo_user, _) = self
+            .silo_user_lookup_by_id(
+                opctx,
+                &authz_silo,
+                silo_user_id,
+                authz::Action::Read,
+            )
+            .await?;
+        self.db_datastore
+            .access_tokens_list(opctx, &authz_silo_user, pagparams)
+            .await
+    }
+
     // The "local" identity provider (available only in `LocalOnly` Silos)
 
     /// Helper function for looking up a LocalOnly Silo by name
@@ -378,6 +428,11 @@ impl super::Nexus {
 
         let (authz_silo_user, db_silo_user) =
             if let Some(existing_silo_user) = fetch_result {
+                // Users who have been deactivated by their identity provider
+                // cannot log in, even if the IdP still vouches for them.
+                if !existing_silo_user.1.active {
+                    return Ok(None);
+                }
                 existing_silo_user
             } else {
                 // In this branch, no user exists for the authenticated subject
@@ -610,7 +665,7 @@ impl super::Nexus {
                 "passed password verification without a valid user"
             );
             let db_user = fetch_user.unwrap().1;
-            Ok(Some(db_user))
+            Ok(Some(db_user).filter(|db_user| db_user.active))
         } else {
             Ok(None)
         }
@@ -749,6 +804,41 @@ impl super::Nexus {
         }
     }
 
+    pub fn oidc_identity_provider_lookup<'a>(
+        &'a self,
+        opctx: &'a OpContext,
+        oidc_identity_provider_selector: params::OidcIdentityProviderSelector,
+    ) -> LookupResult<lookup::OidcIdentityProvider<'a>> {
+        match oidc_identity_provider_selector {
+            params::OidcIdentityProviderSelector {
+                oidc_identity_provider: NameOrId::Id(id),
+                silo: None,
+            } => {
+                let oidc_provider = LookupPath::new(opctx, &self.db_datastore)
+                    .oidc_identity_provider_id(id);
+                Ok(oidc_provider)
+            }
+            params::OidcIdentityProviderSelector {
+                oidc_identity_provider: NameOrId::Name(name),
+                silo: Some(silo),
+            } => {
+                let oidc_provider = self
+                    .silo_lookup(opctx, silo)?
+                    .oidc_identity_provider_name_owned(name.into());
+                Ok(oidc_provider)
+            }
+            params::OidcIdentityProviderSelector {
+                oidc_identity_provider: NameOrId::Id(_),
+                silo: _,
+            } => Err(Error::invalid_request(
+                "when providing provider as an ID, silo should not be specified",
+            )),
+            _ => Err(Error::invalid_request(
+                "provider should either be a UUID or silo should be specified",
+            )),
+        }
+    }
+
     pub(crate) async fn identity_provider_list(
         &self,
         opctx: &OpContext,
@@ -808,18 +898,7 @@ impl super::Nexus {
                 // Importantly, do this only once and store it. It would
                 // introduce attack surface to download it each time it was
                 // required.
-                let dur = std::time::Duration::from_secs(5);
-                let client = reqwest::ClientBuilder::new()
-                    .connect_timeout(dur)
-                    .timeout(dur)
-                    .dns_resolver(self.external_resolver.clone())
-                    .build()
-                    .map_err(|e| {
-                        Error::internal_error(&format!(
-                            "failed to build reqwest client: {}",
-                            e
-                        ))
-                    })?;
+                let client = self.identity_provider_http_client()?;
 
                 let response = client.get(url).send().await.map_err(|e| {
                     Error::InvalidValue {
@@ -951,6 +1030,175 @@ impl super::Nexus {
             .await
     }
 
+    pub(crate) async fn oidc_identity_provider_create(
+        &self,
+        opctx: &OpContext,
+        silo_lookup: &lookup::Silo<'_>,
+        params: params::OidcIdentityProviderCreate,
+    ) -> CreateResult<db::model::OidcIdentityProvider> {
+        let (authz_silo, db_silo) = silo_lookup.fetch().await?;
+        let authz_idp_list = authz::SiloIdentityProviderList::new(authz_silo);
+
+        if db_silo.user_provision_type != UserProvisionType::Jit {
+            return Err(Error::invalid_request(
+                "cannot create identity providers in this kind of Silo",
+            ));
+        }
+
+        // As with SAML, check this now to protect the code that fetches the
+        // discovery document from an external source.
+        opctx.authorize(authz::Action::CreateChild, &authz_idp_list).await?;
+
+        // The authentication mode is immutable so it's safe to check this here
+        // and bail out.
+        if db_silo.authentication_mode
+            != nexus_db_model::AuthenticationMode::Oidc
+        {
+            return Err(Error::invalid_request(&format!(
+                "cannot create OIDC identity provider for this Silo type \
+                (expected authentication mode {:?}, found {:?})",
+                nexus_db_model::AuthenticationMode::Oidc,
+                &db_silo.authentication_mode,
+            )));
+        }
+
+        // Fetch the provider's discovery document once, and store the
+        // endpoints it describes.  As with SAML metadata, fetching it each
+        // time it was required would introduce attack surface.
+        let discovery_url = format!(
+            "{}/.well-known/openid-configuration",
+            params.issuer.trim_end_matches('/')
+        );
+        let client = self.identity_provider_http_client()?;
+        let response =
+            client.get(&discovery_url).send().await.map_err(|e| {
+                Error::InvalidValue {
+                    label: String::from("issuer"),
+                    message: format!("error querying discovery url: {}", e),
+                }
+            })?;
+
+        if !response.status().is_success() {
+            return Err(Error::InvalidValue {
+                label: String::from("issuer"),
+                message: format!(
+                    "querying discovery url returned: {}",
+                    response.status()
+                ),
+            });
+        }
+
+        let discovery: OidcProviderMetadata =
+            response.json().await.map_err(|e| Error::InvalidValue {
+                label: String::from("issuer"),
+                message: format!("error parsing discovery document: {}", e),
+            })?;
+
+        // OpenID Connect Discovery 1.0, section 4.3: the issuer in the
+        // document must exactly match the one used to retrieve it.
+        if discovery.issuer != params.issuer {
+            return Err(Error::InvalidValue {
+                label: String::from("issuer"),
+                message: format!(
+                    "discovery document issuer {} does not match {}",
+                    discovery.issuer, params.issuer
+                ),
+            });
+        }
+
+        if let Some(methods) = &discovery.code_challenge_methods_supported {
+            if !methods.iter().any(|method| method == "S256") {
+                return Err(Error::invalid_request(
+                    "identity provider does not support S256 PKCE challenges",
+                ));
+            }
+        }
+
+        let provider = db::model::OidcIdentityProvider {
+            identity: db::model::OidcIdentityProviderIdentity::new(
+                Uuid::new_v4(),
+                params.identity,
+            ),
+            silo_id: db_silo.id(),
+
+            issuer: discovery.issuer,
+            authorization_endpoint: discovery.authorization_endpoint,
+            token_endpoint: discovery.token_endpoint,
+            jwks_uri: discovery.jwks_uri,
+
+            client_id: params.client_id,
+            client_secret: params.client_secret,
+            redirect_uri: params.redirect_uri,
+            scopes: params.scopes.join(" "),
+
+            group_claim_name: params.group_claim_name,
+        };
+
+        let _authn_provider: authn::silos::OidcIdentityProvider =
+            provider.clone().try_into().map_err(|e: anyhow::Error|
+                // If an error is encountered converting from the model to the
+                // authn type here, this is a request error: something about the
+                // parameters of this request doesn't work.
+                Error::invalid_request(&format!("{:#}", e)))?;
+
+        self.db_datastore
+            .oidc_identity_provider_create(opctx, &authz_idp_list, provider)
+            .await
+    }
+
+    /// Redeem an authorization code issued by an OIDC identity provider, and
+    /// return the subject authenticated by the resulting ID token.
+    pub(crate) async fn oidc_authenticated_subject(
+        &self,
+        provider: &authn::silos::OidcIdentityProvider,
+        code: &str,
+        code_verifier: &str,
+        nonce: &str,
+    ) -> Result<authn::silos::AuthenticatedSubject, Error> {
+        let client = self.identity_provider_http_client()?;
+
+        let id_token = provider
+            .exchange_code(&client, code, code_verifier)
+            .await
+            .map_err(|e| Error::ServiceUnavailable {
+                internal_message: format!(
+                    "failed to redeem authorization code: {:#}",
+                    e
+                ),
+            })?;
+
+        let jwks = provider.fetch_jwks(&client).await.map_err(|e| {
+            Error::ServiceUnavailable {
+                internal_message: format!(
+                    "failed to fetch identity provider keys: {:#}",
+                    e
+                ),
+            }
+        })?;
+
+        provider
+            .authenticated_subject(&id_token, &jwks, nonce, chrono::Utc::now())
+            .map_err(|e| Error::Unauthenticated {
+                internal_message: e.internal_message,
+            })
+    }
+
+    /// Returns a client for talking to external identity providers
+    fn identity_provider_http_client(&self) -> Result<reqwest::Client, Error> {
+        let dur = std::time::Duration::from_secs(5);
+        reqwest::ClientBuilder::new()
+            .connect_timeout(dur)
+            .timeout(dur)
+            .dns_resolver(self.external_resolver.clone())
+            .build()
+            .map_err(|e| {
+                Error::internal_error(&format!(
+                    "failed to build reqwest client: {}",
+                    e
+                ))
+            })
+    }
+
     pub fn silo_group_lookup<'a>(
         &'a self,
         opctx: &'a OpContext,
@@ -960,6 +1208,17 @@ impl super::Nexus {
     }
 }
 
+/// The subset of an OpenID provider's discovery document (OpenID Connect
+/// Discovery 1.0, section 3) that Nexus uses
+#[derive(serde::Deserialize)]
+struct OidcProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+    code_challenge_methods_supported: Option<Vec<String>>,
+}
+
 /// Returns the (relative) DNS name for this Silo's API and console endpoints
 /// _within_ the external DNS zone (i.e., without that zone's suffix)
 ///
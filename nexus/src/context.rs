@@ -24,6 +24,7 @@ use oximeter::types::ProducerRegistry;
 use oximeter_instruments::http::{HttpService, LatencyTracker};
 use slog::Logger;
 use std::env;
+use std::future::Future;
 use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
@@ -238,6 +239,189 @@ impl ServerContext {
             },
         }))
     }
+
+    /// Runs the handler for an external API request that may modify state,
+    /// recording it in the audit log as well as tracking its latency
+    ///
+    /// The entry is written when the handler first authenticates the request
+    /// (see [`op_context_for_external_api()`] and
+    /// [`audit_log_entry_init_for_actor()`]), before any change is made, and
+    /// is completed with the result once the handler returns.  If the entry
+    /// cannot be written, the request fails.  Requests that never
+    /// authenticate, such as failed logins, are recorded without an actor once
+    /// the handler returns.
+    pub(crate) async fn instrument_audited_handler<R, H>(
+        &self,
+        rqctx: &dropshot::RequestContext<Arc<ServerContext>>,
+        operation_id: &'static str,
+        handler: H,
+    ) -> Result<R, dropshot::HttpError>
+    where
+        R: dropshot::HttpResponse,
+        H: Future<Output = Result<R, dropshot::HttpError>>,
+    {
+        let audited = self.audit_handler(rqctx, operation_id, handler, |_| {
+            R::response_metadata().success
+        });
+        self.external_latencies
+            .instrument_dropshot_handler(rqctx, audited)
+            .await
+    }
+
+    /// Runs the handler for an external API request that may modify state and
+    /// that builds its own response, recording it in the audit log as
+    /// [`Self::instrument_audited_handler()`] does
+    ///
+    /// The latency of these requests is not tracked, because the status codes
+    /// of their responses are not known in advance.
+    pub(crate) async fn instrument_audited_raw_handler<H>(
+        &self,
+        rqctx: &dropshot::RequestContext<Arc<ServerContext>>,
+        operation_id: &'static str,
+        handler: H,
+    ) -> Result<http::Response<hyper::Body>, dropshot::HttpError>
+    where
+        H: Future<
+            Output = Result<http::Response<hyper::Body>, dropshot::HttpError>,
+        >,
+    {
+        self.audit_handler(rqctx, operation_id, handler, |response| {
+            Some(response.status())
+        })
+        .await
+    }
+
+    async fn audit_handler<R, H, S>(
+        &self,
+        rqctx: &dropshot::RequestContext<Arc<ServerContext>>,
+        operation_id: &'static str,
+        handler: H,
+        success_status: S,
+    ) -> Result<R, dropshot::HttpError>
+    where
+        H: Future<Output = Result<R, dropshot::HttpError>>,
+        S: FnOnce(&R) -> Option<http::StatusCode>,
+    {
+        let audit = Arc::new(AuditedRequest {
+            operation_id,
+            entry_id: std::sync::Mutex::new(None),
+        });
+        let result = AUDITED_REQUEST.scope(Arc::clone(&audit), handler).await;
+
+        let http_status_code = match &result {
+            Ok(response) => success_status(response),
+            Err(error) => Some(error.status_code),
+        }
+        .map(|code| code.as_u16());
+
+        // Requests that never authenticated an actor are recorded now, so
+        // that failed attempts to authenticate are audited too.
+        let entry_id = audit.entry_id.lock().unwrap().take();
+        let entry_id = match entry_id {
+            Some(entry_id) => Ok(entry_id),
+            None => self
+                .nexus
+                .audit_log_entry_init(
+                    rqctx.request_id.clone(),
+                    operation_id,
+                    rqctx.request.method().to_string(),
+                    audited_request_uri(&rqctx.request),
+                    None,
+                    None,
+                )
+                .await
+                .map(|entry| entry.id),
+        };
+        let completed = match entry_id {
+            Ok(entry_id) => {
+                self.nexus
+                    .audit_log_entry_complete(entry_id, http_status_code)
+                    .await
+            }
+            Err(error) => Err(error),
+        };
+        if let Err(error) = completed {
+            warn!(rqctx.log, "failed to complete audit log entry";
+                "error" => #%error,
+            );
+        }
+        result
+    }
+}
+
+tokio::task_local! {
+    /// The external API request being handled, if it is being recorded in the
+    /// audit log (see [`ServerContext::instrument_audited_handler()`])
+    static AUDITED_REQUEST: Arc<AuditedRequest>;
+}
+
+struct AuditedRequest {
+    operation_id: &'static str,
+    /// The request's entry in the audit log, once it has been written
+    entry_id: std::sync::Mutex<Option<Uuid>>,
+}
+
+/// Returns the URI of a request as it is recorded in the audit log
+///
+/// Only the path is recorded, because query strings may carry secrets, like
+/// the authorization code passed to an OpenID Connect callback.
+fn audited_request_uri(request: &dropshot::RequestInfo) -> String {
+    request.uri().path().to_string()
+}
+
+/// If the request being handled is recorded in the audit log, records that it
+/// is about to be carried out by the actor of `opctx`
+async fn audit_log_entry_init(
+    rqctx: &dropshot::RequestContext<Arc<ServerContext>>,
+    opctx: &OpContext,
+) -> Result<(), dropshot::HttpError> {
+    match opctx.authn.actor() {
+        Some(actor) => {
+            audit_log_entry_init_for_actor(
+                rqctx,
+                Some(actor.actor_id()),
+                actor.silo_id(),
+            )
+            .await
+        }
+        None => Ok(()),
+    }
+}
+
+/// If the request being handled is recorded in the audit log, records that it
+/// is about to be carried out by the given actor
+///
+/// This is used directly by requests which do not authenticate through
+/// [`op_context_for_external_api()`]: logins, which identify the user being
+/// logged in, and SCIM requests, which identify only the Silo whose SCIM
+/// client made them.
+pub(crate) async fn audit_log_entry_init_for_actor(
+    rqctx: &dropshot::RequestContext<Arc<ServerContext>>,
+    actor_id: Option<Uuid>,
+    actor_silo_id: Option<Uuid>,
+) -> Result<(), dropshot::HttpError> {
+    let Ok(audit) = AUDITED_REQUEST.try_with(Arc::clone) else {
+        return Ok(());
+    };
+    if audit.entry_id.lock().unwrap().is_some() {
+        return Ok(());
+    }
+
+    let request = &rqctx.request;
+    let entry = rqctx
+        .context()
+        .nexus
+        .audit_log_entry_init(
+            rqctx.request_id.clone(),
+            audit.operation_id,
+            request.method().to_string(),
+            audited_request_uri(request),
+            actor_id,
+            actor_silo_id,
+        )
+        .await?;
+    *audit.entry_id.lock().unwrap() = Some(entry.id);
+    Ok(())
 }
 
 /// Authenticates an incoming request to the external API and produces a new
@@ -246,7 +430,7 @@ pub(crate) async fn op_context_for_external_api(
     rqctx: &dropshot::RequestContext<Arc<ServerContext>>,
 ) -> Result<OpContext, dropshot::HttpError> {
     let apictx = rqctx.context();
-    OpContext::new_async(
+    let opctx = OpContext::new_async(
         &rqctx.log,
         async {
             let authn =
@@ -262,7 +446,9 @@ pub(crate) async fn op_context_for_external_api(
         |metadata| OpContext::load_request_metadata(rqctx, metadata),
         OpKind::ExternalApiRequest,
     )
-    .await
+    .await?;
+    audit_log_entry_init(rqctx, &opctx).await?;
+    Ok(opctx)
 }
 
 pub(crate) async fn op_context_for_internal_api(
@@ -8,6 +8,7 @@
 //! external API, but in order to avoid CORS issues for now, we are serving
 //! these routes directly from the external API.
 
+use crate::context::audit_log_entry_init_for_actor;
 use crate::ServerContext;
 use anyhow::Context;
 use dropshot::{
@@ -15,7 +16,7 @@ use dropshot::{
     HttpResponseFound, HttpResponseHeaders, HttpResponseSeeOther,
     HttpResponseUpdatedNoContent, Path, Query, RequestContext,
 };
-use http::{header, Response, StatusCode, Uri};
+use http::{header, HeaderValue, Response, StatusCode, Uri};
 use hyper::Body;
 use lazy_static::lazy_static;
 use mime_guess;
@@ -37,6 +38,7 @@ use nexus_types::identity::Resource;
 use omicron_common::api::external::http_pagination::PaginatedBy;
 use omicron_common::api::external::{DataPageParams, Error, NameOrId};
 use parse_display::Display;
+use rand::RngCore;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_urlencoded;
@@ -73,6 +75,11 @@ use std::{collections::HashSet, ffi::OsString, path::PathBuf, sync::Arc};
 // in a RelayState query param). On successful login in the IdP, the IdP will
 // POST /login/{silo}/saml/{provider} with a body including that redirect_uri,
 // so that on success, we can redirect to the original target page.
+//
+// OIDC is similar, but there is no console page: /login/{silo}/oidc/{provider}
+// redirects straight to the IdP's authorization endpoint, and the IdP sends the
+// user back to GET /login/{silo}/oidc/{provider}/callback.  The redirect_uri
+// travels in a cookie alongside the values used to validate the response.
 
 // -------------------------------
 // Detailed overview of SAML login
@@ -284,6 +291,11 @@ pub(crate) async fn login_saml_redirect(
 
                 http_response_found(sign_in_url)
             }
+
+            IdentityProviderType::Oidc(_) => Err(HttpError::for_bad_request(
+                None,
+                String::from("identity provider is not a SAML provider"),
+            )),
         }
     };
 
@@ -329,6 +341,14 @@ pub(crate) async fn login_saml(
                         nexus.samael_max_issue_delay(),
                     )?
                 }
+                IdentityProviderType::Oidc(_) => {
+                    return Err(HttpError::for_bad_request(
+                        None,
+                        String::from(
+                            "identity provider is not a SAML provider",
+                        ),
+                    ));
+                }
             };
 
         let relay_state =
@@ -349,7 +369,7 @@ pub(crate) async fn login_saml(
             )
             .await?;
 
-        let session = create_session(opctx, apictx, user).await?;
+        let session = create_session(&rqctx, opctx, user).await?;
         let next_url = relay_state
             .and_then(|r| r.redirect_uri)
             .map(|u| u.to_string())
@@ -370,9 +390,339 @@ pub(crate) async fn login_saml(
         }
         Ok(response)
     };
+    apictx.instrument_audited_handler(&rqctx, "login_saml", handler).await
+}
+
+// -------------------------------
+// Overview of OIDC login
+// -------------------------------
+//
+// Nexus implements the OpenID Connect authorization code flow with PKCE (RFC
+// 7636).  When the user starts to log in:
+//
+//   GET /login/{silo_name}/oidc/{provider_name}
+//
+// Nexus generates a random `state`, `nonce`, and PKCE code verifier, stores
+// them (along with any `redirect_uri`) in a short-lived cookie, and redirects
+// the user to the provider's authorization endpoint.  After authenticating,
+// the provider sends the user back to the redirect URI configured for the
+// provider, which should be:
+//
+//   GET /login/{silo_name}/oidc/{provider_name}/callback?code=...&state=...
+//
+// Nexus checks that `state` matches the cookie, redeems the code (along with
+// the code verifier) at the provider's token endpoint, and validates the
+// resulting ID token against the provider's published keys and the `nonce`.
+// The token's subject is then used to fetch or create a silo user exactly as
+// for SAML.
+
+/// Name of the cookie that holds the state of an in-progress OIDC login
+const OIDC_LOGIN_COOKIE_NAME: &str = "oidc-login";
+
+/// How long a user has to complete an OIDC login at their identity provider
+const OIDC_LOGIN_TIMEOUT_SECONDS: i64 = 600;
+
+/// State kept by the user's browser between being sent to an OIDC identity
+/// provider and being sent back to Nexus
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct OidcLoginState {
+    /// Sent to the IdP and returned unchanged, to tie the response to this
+    /// browser
+    pub state: String,
+    /// Sent to the IdP and included in the ID token, to tie the token to this
+    /// login attempt
+    pub nonce: String,
+    /// PKCE code verifier, whose hash is sent to the IdP
+    pub code_verifier: String,
+    pub redirect_uri: Option<RelativeUri>,
+}
+
+impl OidcLoginState {
+    fn new(redirect_uri: Option<RelativeUri>) -> Self {
+        Self {
+            state: random_token(),
+            nonce: random_token(),
+            code_verifier: random_token(),
+            redirect_uri,
+        }
+    }
+
+    /// Returns the S256 PKCE code challenge for this login's code verifier
+    fn code_challenge(&self) -> String {
+        base64::Engine::encode(
+            &base64::engine::general_purpose::URL_SAFE_NO_PAD,
+            openssl::sha::sha256(self.code_verifier.as_bytes()),
+        )
+    }
+
+    fn to_encoded(&self) -> Result<String, anyhow::Error> {
+        Ok(base64::Engine::encode(
+            &base64::engine::general_purpose::URL_SAFE_NO_PAD,
+            serde_json::to_string(&self).context("encoding login state")?,
+        ))
+    }
+
+    fn from_encoded(encoded: &str) -> Result<Self, anyhow::Error> {
+        serde_json::from_slice(
+            &base64::Engine::decode(
+                &base64::engine::general_purpose::URL_SAFE_NO_PAD,
+                encoded,
+            )
+            .context("base64 decoding login state")?,
+        )
+        .context("json from login state")
+    }
+}
+
+/// Returns a random, URL-safe string with 256 bits of entropy
+fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    base64::Engine::encode(
+        &base64::engine::general_purpose::URL_SAFE_NO_PAD,
+        bytes,
+    )
+}
+
+fn oidc_login_cookie_header_value(
+    value: &str,
+    max_age_seconds: i64,
+    secure: bool,
+) -> Result<HeaderValue, HttpError> {
+    // The cookie is only needed by the login routes.  It must be sent when
+    // the IdP redirects the user back to Nexus, which SameSite=Lax permits
+    // since that is a top-level GET navigation.
+    let value = format!(
+        "{}={}; Path=/login/; HttpOnly; SameSite=Lax;{} Max-Age={}",
+        OIDC_LOGIN_COOKIE_NAME,
+        value,
+        if secure { " Secure;" } else { "" },
+        max_age_seconds,
+    );
+    HeaderValue::from_str(&value).map_err(|_e| {
+        HttpError::for_internal_error(format!(
+            "unsupported cookie value: {:#}",
+            value
+        ))
+    })
+}
+
+/// Redirect to an OIDC IdP to begin logging in
+#[endpoint {
+   method = GET,
+   path = "/login/{silo_name}/oidc/{provider_name}",
+   tags = ["login"],
+   unpublished = true,
+}]
+pub(crate) async fn login_oidc_begin(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<LoginToProviderPathParam>,
+    query_params: Query<LoginUrlQuery>,
+) -> Result<HttpResponseFound, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let nexus = &apictx.nexus;
+        let path_params = path_params.into_inner();
+
+        // Use opctx_external_authn because this request will be
+        // unauthenticated.
+        let opctx = nexus.opctx_external_authn();
+
+        let (.., identity_provider) = IdentityProviderType::lookup(
+            &nexus.datastore(),
+            &opctx,
+            &path_params.silo_name,
+            &path_params.provider_name,
+        )
+        .await?;
+
+        let oidc_identity_provider = match identity_provider {
+            IdentityProviderType::Oidc(oidc_identity_provider) => {
+                oidc_identity_provider
+            }
+            IdentityProviderType::Saml(_) => {
+                return Err(HttpError::for_bad_request(
+                    None,
+                    String::from("identity provider is not an OIDC provider"),
+                ));
+            }
+        };
+
+        let login_state =
+            OidcLoginState::new(query_params.into_inner().redirect_uri);
+        let sign_in_url = oidc_identity_provider
+            .authorization_url(
+                &login_state.state,
+                &login_state.nonce,
+                &login_state.code_challenge(),
+            )
+            .map_err(|e| HttpError::for_internal_error(e.to_string()))?;
+        let encoded_login_state = login_state.to_encoded().map_err(|e| {
+            HttpError::for_internal_error(format!(
+                "encoding login state failed: {}",
+                e
+            ))
+        })?;
+
+        let mut response = http_response_found(sign_in_url)?;
+        response.headers_mut().append(
+            header::SET_COOKIE,
+            oidc_login_cookie_header_value(
+                &encoded_login_state,
+                OIDC_LOGIN_TIMEOUT_SECONDS,
+                apictx.external_tls_enabled,
+            )?,
+        );
+        Ok(response)
+    };
+
     apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
 }
 
+/// Authorization response sent by an OIDC IdP
+#[derive(Deserialize, JsonSchema)]
+pub struct OidcAuthorizationResponse {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
+
+/// Authenticate a user via OIDC
+#[endpoint {
+   method = GET,
+   path = "/login/{silo_name}/oidc/{provider_name}/callback",
+   tags = ["login"],
+}]
+pub(crate) async fn login_oidc(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<LoginToProviderPathParam>,
+    query_params: Query<OidcAuthorizationResponse>,
+    cookies: Cookies,
+) -> Result<HttpResponseSeeOther, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let nexus = &apictx.nexus;
+        let path_params = path_params.into_inner();
+        let query = query_params.into_inner();
+
+        // The state must match the one we stored in the user's browser before
+        // sending them to the IdP.  Otherwise, this response may have been
+        // generated for someone else.
+        let login_state = cookies
+            .get(OIDC_LOGIN_COOKIE_NAME)
+            .ok_or_else(|| {
+                HttpError::for_bad_request(
+                    None,
+                    String::from("no OIDC login is in progress"),
+                )
+            })
+            .and_then(|cookie| {
+                OidcLoginState::from_encoded(cookie.value()).map_err(|e| {
+                    HttpError::for_bad_request(
+                        None,
+                        format!("invalid OIDC login state: {:#}", e),
+                    )
+                })
+            })?;
+        if query.state.as_deref() != Some(login_state.state.as_str()) {
+            return Err(HttpError::for_bad_request(
+                None,
+                String::from("OIDC state does not match"),
+            ));
+        }
+
+        if let Some(error) = query.error {
+            return Err(Error::Unauthenticated {
+                internal_message: format!(
+                    "identity provider returned error {}: {}",
+                    error,
+                    query.error_description.unwrap_or_default(),
+                ),
+            }
+            .into());
+        }
+        let code = query.code.ok_or_else(|| {
+            HttpError::for_bad_request(
+                None,
+                String::from("missing authorization code"),
+            )
+        })?;
+
+        // By definition, this request is not authenticated.  These operations
+        // happen using the Nexus "external authentication" context, which we
+        // keep specifically for this purpose.
+        let opctx = nexus.opctx_external_authn();
+
+        let (authz_silo, db_silo, identity_provider) =
+            IdentityProviderType::lookup(
+                &nexus.datastore(),
+                &opctx,
+                &path_params.silo_name,
+                &path_params.provider_name,
+            )
+            .await?;
+
+        let authenticated_subject = match identity_provider {
+            IdentityProviderType::Oidc(oidc_identity_provider) => {
+                nexus
+                    .oidc_authenticated_subject(
+                        &oidc_identity_provider,
+                        &code,
+                        &login_state.code_verifier,
+                        &login_state.nonce,
+                    )
+                    .await?
+            }
+            IdentityProviderType::Saml(_) => {
+                return Err(HttpError::for_bad_request(
+                    None,
+                    String::from("identity provider is not an OIDC provider"),
+                ));
+            }
+        };
+
+        let user = nexus
+            .silo_user_from_authenticated_subject(
+                &opctx,
+                &authz_silo,
+                &db_silo,
+                &authenticated_subject,
+            )
+            .await?;
+
+        let session = create_session(&rqctx, opctx, user).await?;
+        let next_url = login_state
+            .redirect_uri
+            .map(|u| u.to_string())
+            .unwrap_or_else(|| "/".to_string());
+        let mut response = http_response_see_other(next_url)?;
+
+        {
+            let headers = response.headers_mut();
+            let cookie = session_cookie_header_value(
+                &session.token,
+                // use absolute timeout even though session might idle out first.
+                // browser expiration is mostly for convenience, as the API will
+                // reject requests with an expired session regardless
+                apictx.session_absolute_timeout(),
+                apictx.external_tls_enabled,
+            )?;
+            headers.append(header::SET_COOKIE, cookie);
+            headers.append(
+                header::SET_COOKIE,
+                oidc_login_cookie_header_value(
+                    "",
+                    0,
+                    apictx.external_tls_enabled,
+                )?,
+            );
+        }
+        Ok(response)
+    };
+    apictx.instrument_audited_handler(&rqctx, "login_oidc", handler).await
+}
+
 #[derive(Deserialize, JsonSchema)]
 pub struct LoginPathParam {
     pub silo_name: nexus_db_queries::db::model::Name,
@@ -421,7 +771,7 @@ pub(crate) async fn login_local(
         let silo_lookup = nexus.silo_lookup(&opctx, silo)?;
         let user = nexus.login_local(&opctx, &silo_lookup, credentials).await?;
 
-        let session = create_session(opctx, apictx, user).await?;
+        let session = create_session(&rqctx, opctx, user).await?;
         let mut response =
             HttpResponseHeaders::new_unnamed(HttpResponseUpdatedNoContent());
 
@@ -439,17 +789,25 @@ pub(crate) async fn login_local(
         }
         Ok(response)
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_audited_handler(&rqctx, "login_local", handler).await
 }
 
 async fn create_session(
+    rqctx: &RequestContext<Arc<ServerContext>>,
     opctx: &OpContext,
-    apictx: &ServerContext,
     user: Option<nexus_db_queries::db::model::SiloUser>,
 ) -> Result<nexus_db_queries::db::model::ConsoleSession, HttpError> {
-    let nexus = &apictx.nexus;
+    let nexus = &rqctx.context().nexus;
     let session = match user {
-        Some(user) => nexus.session_create(&opctx, user.id()).await?,
+        Some(user) => {
+            audit_log_entry_init_for_actor(
+                rqctx,
+                Some(user.id()),
+                Some(user.silo_id),
+            )
+            .await?;
+            nexus.session_create(&opctx, user.id()).await?
+        }
         None => Err(Error::Unauthenticated {
             internal_message: String::from(
                 "no matching user found or credentials were not valid",
@@ -504,7 +862,7 @@ pub(crate) async fn logout(
         Ok(response)
     };
 
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_audited_handler(&rqctx, "logout", handler).await
 }
 
 #[derive(Deserialize, JsonSchema)]
@@ -601,11 +959,12 @@ async fn get_login_url(
             );
         }
 
-        format!(
-            "/login/{}/saml/{}",
-            silo.name(),
-            idps.into_iter().next().unwrap().name()
-        )
+        let idp = idps.into_iter().next().unwrap();
+        let protocol = match idp.provider_type {
+            nexus_db_model::IdentityProviderType::Saml => "saml",
+            nexus_db_model::IdentityProviderType::Oidc => "oidc",
+        };
+        format!("/login/{}/{}/{}", silo.name(), protocol, idp.name())
     };
 
     // Stick redirect_url into the state param and URL encode it so it can be
@@ -163,7 +163,9 @@ pub(crate) async fn device_auth_confirm(
             .await?;
         Ok(HttpResponseUpdatedNoContent())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(&rqctx, "device_auth_confirm", handler)
+        .await
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
@@ -247,7 +249,7 @@ pub(crate) async fn device_access_token(
             ),
         }
     };
-    // TODO: instrumentation doesn't work because we use `Response<Body>`
-    //apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
-    handler.await
+    apictx
+        .instrument_audited_raw_handler(&rqctx, "device_access_token", handler)
+        .await
 }
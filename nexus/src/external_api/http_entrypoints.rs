@@ -5,16 +5,17 @@
 //! Handler functions (entrypoints) for external HTTP APIs
 
 use super::{
-    console_api, device_auth, params,
+    console_api, device_auth, params, scim,
     views::{
-        self, Certificate, Group, IdentityProvider, Image, IpPool, IpPoolRange,
+        self, AccessToken, AccessTokenCreated, AffinityGroup, Certificate,
+        FloatingIp, Group, IdentityProvider, Image, IpPool, IpPoolRange,
         PhysicalDisk, Project, Rack, Role, Silo, Sled, Snapshot, SshKey, User,
         UserBuiltin, Vpc, VpcRouter, VpcSubnet,
     },
 };
 use crate::external_api::shared;
 use crate::ServerContext;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use dropshot::ApiDescription;
 use dropshot::EmptyScanParams;
 use dropshot::HttpError;
@@ -111,6 +112,8 @@ pub(crate) fn external_api() -> NexusApiDescription {
         api.register(project_update)?;
         api.register(project_policy_view)?;
         api.register(project_policy_update)?;
+        api.register(project_quotas_view)?;
+        api.register(project_quotas_update)?;
         api.register(project_ip_pool_list)?;
         api.register(project_ip_pool_view)?;
 
@@ -143,10 +146,12 @@ pub(crate) fn external_api() -> NexusApiDescription {
         api.register(disk_bulk_write_import_stop)?;
         api.register(disk_import_blocks_from_url)?;
         api.register(disk_finalize_import)?;
+        api.register(disk_resize)?;
 
         api.register(instance_list)?;
         api.register(instance_view)?;
         api.register(instance_create)?;
+        api.register(instance_update)?;
         api.register(instance_delete)?;
         api.register(instance_migrate)?;
         api.register(instance_reboot)?;
@@ -157,6 +162,7 @@ pub(crate) fn external_api() -> NexusApiDescription {
         api.register(instance_disk_detach)?;
         api.register(instance_serial_console)?;
         api.register(instance_serial_console_stream)?;
+        api.register(instance_metrics_list)?;
 
         api.register(image_list)?;
         api.register(image_create)?;
@@ -170,6 +176,21 @@ pub(crate) fn external_api() -> NexusApiDescription {
         api.register(snapshot_view)?;
         api.register(snapshot_delete)?;
 
+        api.register(floating_ip_list)?;
+        api.register(floating_ip_create)?;
+        api.register(floating_ip_view)?;
+        api.register(floating_ip_delete)?;
+        api.register(floating_ip_attach)?;
+        api.register(floating_ip_detach)?;
+
+        api.register(affinity_group_list)?;
+        api.register(affinity_group_create)?;
+        api.register(affinity_group_view)?;
+        api.register(affinity_group_delete)?;
+        api.register(affinity_group_member_list)?;
+        api.register(affinity_group_member_add)?;
+        api.register(affinity_group_member_remove)?;
+
         api.register(vpc_list)?;
         api.register(vpc_create)?;
         api.register(vpc_view)?;
@@ -228,6 +249,9 @@ pub(crate) fn external_api() -> NexusApiDescription {
         api.register(current_user_ssh_key_view)?;
         api.register(current_user_ssh_key_create)?;
         api.register(current_user_ssh_key_delete)?;
+        api.register(current_user_access_token_list)?;
+        api.register(current_user_access_token_create)?;
+        api.register(current_user_access_token_delete)?;
 
         // Customer network integration
         api.register(networking_address_lot_list)?;
@@ -255,12 +279,21 @@ pub(crate) fn external_api() -> NexusApiDescription {
         api.register(silo_delete)?;
         api.register(silo_policy_view)?;
         api.register(silo_policy_update)?;
+        api.register(silo_quotas_view)?;
+        api.register(silo_quotas_update)?;
 
         api.register(silo_identity_provider_list)?;
 
         api.register(saml_identity_provider_create)?;
         api.register(saml_identity_provider_view)?;
 
+        api.register(oidc_identity_provider_create)?;
+        api.register(oidc_identity_provider_view)?;
+
+        api.register(scim_token_list)?;
+        api.register(scim_token_create)?;
+        api.register(scim_token_delete)?;
+
         api.register(local_idp_user_create)?;
         api.register(local_idp_user_delete)?;
         api.register(local_idp_user_set_password)?;
@@ -273,6 +306,8 @@ pub(crate) fn external_api() -> NexusApiDescription {
         api.register(system_metric)?;
         api.register(silo_metric)?;
 
+        api.register(audit_log_list)?;
+
         api.register(system_update_refresh)?;
         api.register(system_version)?;
         api.register(system_component_version_list)?;
@@ -287,6 +322,7 @@ pub(crate) fn external_api() -> NexusApiDescription {
         api.register(user_list)?;
         api.register(silo_user_list)?;
         api.register(silo_user_view)?;
+        api.register(silo_user_access_token_list)?;
         api.register(group_list)?;
         api.register(group_view)?;
 
@@ -297,6 +333,8 @@ pub(crate) fn external_api() -> NexusApiDescription {
         api.register(console_api::login_saml_begin)?;
         api.register(console_api::login_saml_redirect)?;
         api.register(console_api::login_saml)?;
+        api.register(console_api::login_oidc_begin)?;
+        api.register(console_api::login_oidc)?;
         api.register(console_api::logout)?;
 
         api.register(console_api::console_projects)?;
@@ -315,6 +353,20 @@ pub(crate) fn external_api() -> NexusApiDescription {
         api.register(device_auth::device_auth_confirm)?;
         api.register(device_auth::device_access_token)?;
 
+        api.register(scim::scim_service_provider_config)?;
+        api.register(scim::scim_user_list)?;
+        api.register(scim::scim_user_create)?;
+        api.register(scim::scim_user_view)?;
+        api.register(scim::scim_user_replace)?;
+        api.register(scim::scim_user_patch)?;
+        api.register(scim::scim_user_delete)?;
+        api.register(scim::scim_group_list)?;
+        api.register(scim::scim_group_create)?;
+        api.register(scim::scim_group_view)?;
+        api.register(scim::scim_group_replace)?;
+        api.register(scim::scim_group_patch)?;
+        api.register(scim::scim_group_delete)?;
+
         Ok(())
     }
 
@@ -410,7 +462,9 @@ async fn system_policy_update(
         let policy = nexus.fleet_update_policy(&opctx, &new_policy).await?;
         Ok(HttpResponseOk(policy))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(&rqctx, "system_policy_update", handler)
+        .await
 }
 
 /// Fetch the current silo's IAM policy
@@ -469,7 +523,7 @@ async fn policy_update(
             nexus.silo_update_policy(&opctx, &silo_lookup, &new_policy).await?;
         Ok(HttpResponseOk(policy))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_audited_handler(&rqctx, "policy_update", handler).await
 }
 
 /// List silos
@@ -525,7 +579,7 @@ async fn silo_create(
             nexus.silo_create(&opctx, new_silo_params.into_inner()).await?;
         Ok(HttpResponseCreated(silo.try_into()?))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_audited_handler(&rqctx, "silo_create", handler).await
 }
 
 /// Fetch a silo
@@ -573,7 +627,7 @@ async fn silo_delete(
         nexus.silo_delete(&opctx, &silo_lookup).await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_audited_handler(&rqctx, "silo_delete", handler).await
 }
 
 /// Fetch a silo's IAM policy
@@ -623,9 +677,63 @@ async fn silo_policy_update(
             nexus.silo_update_policy(&opctx, &silo_lookup, &new_policy).await?;
         Ok(HttpResponseOk(policy))
     };
+    apictx
+        .instrument_audited_handler(&rqctx, "silo_policy_update", handler)
+        .await
+}
+
+/// Fetch a silo's resource quotas
+#[endpoint {
+    method = GET,
+    path = "/v1/system/silos/{silo}/quotas",
+    tags = ["system/silos"],
+}]
+async fn silo_quotas_view(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<params::SiloPath>,
+) -> Result<HttpResponseOk<views::ResourceQuotas>, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
+        let nexus = &apictx.nexus;
+        let path = path_params.into_inner();
+        let silo_lookup = nexus.silo_lookup(&opctx, path.silo)?;
+        let quotas = nexus.silo_quotas_view(&opctx, &silo_lookup).await?;
+        Ok(HttpResponseOk(quotas.into()))
+    };
     apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
 }
 
+/// Update a silo's resource quotas
+///
+/// New instances, disks, and snapshots in the silo will fail to be created if
+/// they would cause the silo's provisioned resources to exceed these quotas.
+#[endpoint {
+    method = PUT,
+    path = "/v1/system/silos/{silo}/quotas",
+    tags = ["system/silos"],
+}]
+async fn silo_quotas_update(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<params::SiloPath>,
+    new_quotas: TypedBody<params::ResourceQuotasUpdate>,
+) -> Result<HttpResponseOk<views::ResourceQuotas>, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
+        let nexus = &apictx.nexus;
+        let path = path_params.into_inner();
+        let new_quotas = new_quotas.into_inner();
+        let silo_lookup = nexus.silo_lookup(&opctx, path.silo)?;
+        let quotas =
+            nexus.silo_quotas_update(&opctx, &silo_lookup, &new_quotas).await?;
+        Ok(HttpResponseOk(quotas.into()))
+    };
+    apictx
+        .instrument_audited_handler(&rqctx, "silo_quotas_update", handler)
+        .await
+}
+
 // Silo-specific user endpoints
 
 /// List built-in (system) users in a silo
@@ -694,6 +802,47 @@ async fn silo_user_view(
     apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
 }
 
+/// List a user's access tokens
+#[endpoint {
+    method = GET,
+    path = "/v1/system/users/{user_id}/access-tokens",
+    tags = ["system/silos"],
+}]
+async fn silo_user_access_token_list(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<UserParam>,
+    query_params: Query<PaginatedById<params::SiloSelector>>,
+) -> Result<HttpResponseOk<ResultsPage<AccessToken>>, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
+        let nexus = &apictx.nexus;
+        let path = path_params.into_inner();
+        let query = query_params.into_inner();
+        let pag_params = data_page_params_for(&rqctx, &query)?;
+        let scan_params = ScanById::from_query(&query)?;
+        let silo_lookup =
+            nexus.silo_lookup(&opctx, scan_params.selector.silo.clone())?;
+        let tokens = nexus
+            .silo_user_access_tokens_list(
+                &opctx,
+                &silo_lookup,
+                path.user_id,
+                &pag_params,
+            )
+            .await?
+            .into_iter()
+            .map(AccessToken::from)
+            .collect();
+        Ok(HttpResponseOk(ScanById::results_page(
+            &query,
+            tokens,
+            &|_, token: &AccessToken| token.id,
+        )?))
+    };
+    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+}
+
 // Silo identity providers
 
 /// List a silo's IdP's name
@@ -759,7 +908,13 @@ async fn saml_identity_provider_create(
             .await?;
         Ok(HttpResponseCreated(provider.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(
+            &rqctx,
+            "saml_identity_provider_create",
+            handler,
+        )
+        .await
 }
 
 /// Fetch a SAML IdP
@@ -796,8 +951,181 @@ async fn saml_identity_provider_view(
     apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
 }
 
+// Silo OIDC identity providers
+
+/// Create an OIDC IdP
+#[endpoint {
+    method = POST,
+    path = "/v1/system/identity-providers/oidc",
+    tags = ["system/silos"],
+}]
+async fn oidc_identity_provider_create(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    query_params: Query<params::SiloSelector>,
+    new_provider: TypedBody<params::OidcIdentityProviderCreate>,
+) -> Result<HttpResponseCreated<views::OidcIdentityProvider>, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
+        let nexus = &apictx.nexus;
+        let query = query_params.into_inner();
+        let silo_lookup = nexus.silo_lookup(&opctx, query.silo)?;
+        let provider = nexus
+            .oidc_identity_provider_create(
+                &opctx,
+                &silo_lookup,
+                new_provider.into_inner(),
+            )
+            .await?;
+        Ok(HttpResponseCreated(provider.into()))
+    };
+    apictx
+        .instrument_audited_handler(
+            &rqctx,
+            "oidc_identity_provider_create",
+            handler,
+        )
+        .await
+}
+
+/// Fetch an OIDC IdP
+#[endpoint {
+    method = GET,
+    path = "/v1/system/identity-providers/oidc/{provider}",
+    tags = ["system/silos"],
+}]
+async fn oidc_identity_provider_view(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<params::OidcProviderPath>,
+    query_params: Query<params::SiloSelector>,
+) -> Result<HttpResponseOk<views::OidcIdentityProvider>, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
+        let nexus = &apictx.nexus;
+        let path = path_params.into_inner();
+        let query = query_params.into_inner();
+        let oidc_identity_provider_selector =
+            params::OidcIdentityProviderSelector {
+                silo: Some(query.silo),
+                oidc_identity_provider: path.provider,
+            };
+        let (.., provider) = nexus
+            .oidc_identity_provider_lookup(
+                &opctx,
+                oidc_identity_provider_selector,
+            )?
+            .fetch()
+            .await?;
+        Ok(HttpResponseOk(provider.into()))
+    };
+    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+}
+
 // TODO: no DELETE for identity providers?
 
+// SCIM client bearer tokens
+
+/// List a silo's SCIM client tokens
+///
+/// Tokens are only identified by their ID; the token itself is only revealed
+/// when it is created.
+#[endpoint {
+    method = GET,
+    path = "/v1/system/scim/tokens",
+    tags = ["system/silos"],
+}]
+async fn scim_token_list(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    query_params: Query<PaginatedById<params::SiloSelector>>,
+) -> Result<HttpResponseOk<ResultsPage<views::ScimClientBearerToken>>, HttpError>
+{
+    let apictx = rqctx.context();
+    let handler = async {
+        let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
+        let nexus = &apictx.nexus;
+        let query = query_params.into_inner();
+        let pag_params = data_page_params_for(&rqctx, &query)?;
+        let scan_params = ScanById::from_query(&query)?;
+        let silo_lookup =
+            nexus.silo_lookup(&opctx, scan_params.selector.silo.clone())?;
+        let tokens = nexus
+            .scim_client_bearer_tokens_list(&opctx, &silo_lookup, &pag_params)
+            .await?
+            .into_iter()
+            .map(views::ScimClientBearerToken::from)
+            .collect();
+        Ok(HttpResponseOk(ScanById::results_page(
+            &query,
+            tokens,
+            &|_, token: &views::ScimClientBearerToken| token.id,
+        )?))
+    };
+    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+}
+
+/// Create a SCIM client token
+///
+/// The token allows an identity provider's SCIM client to provision users and
+/// groups in the silo under `/scim/v2`. It is only included in this response,
+/// and cannot be retrieved later.
+#[endpoint {
+    method = POST,
+    path = "/v1/system/scim/tokens",
+    tags = ["system/silos"],
+}]
+async fn scim_token_create(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    query_params: Query<params::SiloSelector>,
+) -> Result<HttpResponseCreated<views::ScimClientBearerTokenCreated>, HttpError>
+{
+    let apictx = rqctx.context();
+    let handler = async {
+        let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
+        let nexus = &apictx.nexus;
+        let query = query_params.into_inner();
+        let silo_lookup = nexus.silo_lookup(&opctx, query.silo)?;
+        let token =
+            nexus.scim_client_bearer_token_create(&opctx, &silo_lookup).await?;
+        Ok(HttpResponseCreated(token.into()))
+    };
+    apictx
+        .instrument_audited_handler(&rqctx, "scim_token_create", handler)
+        .await
+}
+
+/// Revoke a SCIM client token
+#[endpoint {
+    method = DELETE,
+    path = "/v1/system/scim/tokens/{token_id}",
+    tags = ["system/silos"],
+}]
+async fn scim_token_delete(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<params::ScimClientBearerTokenPath>,
+    query_params: Query<params::SiloSelector>,
+) -> Result<HttpResponseDeleted, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
+        let nexus = &apictx.nexus;
+        let path = path_params.into_inner();
+        let query = query_params.into_inner();
+        let silo_lookup = nexus.silo_lookup(&opctx, query.silo)?;
+        nexus
+            .scim_client_bearer_token_delete(
+                &opctx,
+                &silo_lookup,
+                path.token_id,
+            )
+            .await?;
+        Ok(HttpResponseDeleted())
+    };
+    apictx
+        .instrument_audited_handler(&rqctx, "scim_token_delete", handler)
+        .await
+}
+
 // "Local" Identity Provider
 
 /// Create a user
@@ -830,7 +1158,9 @@ async fn local_idp_user_create(
             .await?;
         Ok(HttpResponseCreated(user.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(&rqctx, "local_idp_user_create", handler)
+        .await
 }
 
 /// Delete a user
@@ -854,7 +1184,9 @@ async fn local_idp_user_delete(
         nexus.local_idp_delete_user(&opctx, &silo_lookup, path.user_id).await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(&rqctx, "local_idp_user_delete", handler)
+        .await
 }
 
 /// Set or invalidate a user's password
@@ -889,7 +1221,13 @@ async fn local_idp_user_set_password(
             .await?;
         Ok(HttpResponseUpdatedNoContent())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(
+            &rqctx,
+            "local_idp_user_set_password",
+            handler,
+        )
+        .await
 }
 
 /// List projects
@@ -943,7 +1281,7 @@ async fn project_create(
             nexus.project_create(&opctx, &new_project.into_inner()).await?;
         Ok(HttpResponseCreated(project.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_audited_handler(&rqctx, "project_create", handler).await
 }
 
 /// Fetch a project
@@ -991,7 +1329,7 @@ async fn project_delete(
         nexus.project_delete(&opctx, &project_lookup).await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_audited_handler(&rqctx, "project_delete", handler).await
 }
 
 // TODO-correctness: Is it valid for PUT to accept application/json that's a
@@ -1024,7 +1362,7 @@ async fn project_update(
             .await?;
         Ok(HttpResponseOk(project.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_audited_handler(&rqctx, "project_update", handler).await
 }
 
 /// Fetch a project's IAM policy
@@ -1077,9 +1415,70 @@ async fn project_policy_update(
             .await?;
         Ok(HttpResponseOk(new_policy))
     };
+    apictx
+        .instrument_audited_handler(&rqctx, "project_policy_update", handler)
+        .await
+}
+
+/// Fetch a project's resource quotas
+#[endpoint {
+    method = GET,
+    path = "/v1/projects/{project}/quotas",
+    tags = ["projects"],
+}]
+async fn project_quotas_view(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<params::ProjectPath>,
+) -> Result<HttpResponseOk<views::ResourceQuotas>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let handler = async {
+        let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
+        let project_selector =
+            params::ProjectSelector { project: path.project };
+        let project_lookup = nexus.project_lookup(&opctx, project_selector)?;
+        let quotas = nexus.project_quotas_view(&opctx, &project_lookup).await?;
+        Ok(HttpResponseOk(quotas.into()))
+    };
     apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
 }
 
+/// Update a project's resource quotas
+///
+/// New instances, disks, and snapshots in the project will fail to be created
+/// if they would cause the project's provisioned resources to exceed these
+/// quotas. Updating a project's quotas requires permission to modify the
+/// project's silo.
+#[endpoint {
+    method = PUT,
+    path = "/v1/projects/{project}/quotas",
+    tags = ["projects"],
+}]
+async fn project_quotas_update(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<params::ProjectPath>,
+    new_quotas: TypedBody<params::ResourceQuotasUpdate>,
+) -> Result<HttpResponseOk<views::ResourceQuotas>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let new_quotas = new_quotas.into_inner();
+    let handler = async {
+        let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
+        let project_selector =
+            params::ProjectSelector { project: path.project };
+        let project_lookup = nexus.project_lookup(&opctx, project_selector)?;
+        let quotas = nexus
+            .project_quotas_update(&opctx, &project_lookup, &new_quotas)
+            .await?;
+        Ok(HttpResponseOk(quotas.into()))
+    };
+    apictx
+        .instrument_audited_handler(&rqctx, "project_quotas_update", handler)
+        .await
+}
+
 // IP Pools
 
 /// List all IP Pools that can be used by a given project.
@@ -1215,7 +1614,7 @@ async fn ip_pool_create(
         let pool = nexus.ip_pool_create(&opctx, &pool_params).await?;
         Ok(HttpResponseCreated(IpPool::from(pool)))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_audited_handler(&rqctx, "ip_pool_create", handler).await
 }
 
 /// Fetch an IP pool
@@ -1259,7 +1658,7 @@ async fn ip_pool_delete(
         nexus.ip_pool_delete(&opctx, &pool_lookup).await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_audited_handler(&rqctx, "ip_pool_delete", handler).await
 }
 
 /// Update an IP Pool
@@ -1283,7 +1682,7 @@ async fn ip_pool_update(
         let pool = nexus.ip_pool_update(&opctx, &pool_lookup, &updates).await?;
         Ok(HttpResponseOk(pool.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_audited_handler(&rqctx, "ip_pool_update", handler).await
 }
 
 /// Fetch the IP pool used for Oxide services
@@ -1374,7 +1773,9 @@ async fn ip_pool_range_add(
         let out = nexus.ip_pool_add_range(&opctx, &pool_lookup, &range).await?;
         Ok(HttpResponseCreated(out.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(&rqctx, "ip_pool_range_add", handler)
+        .await
 }
 
 /// Remove a range from an IP pool
@@ -1398,7 +1799,9 @@ async fn ip_pool_range_remove(
         nexus.ip_pool_delete_range(&opctx, &pool_lookup, &range).await?;
         Ok(HttpResponseUpdatedNoContent())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(&rqctx, "ip_pool_range_remove", handler)
+        .await
 }
 
 /// List ranges for the IP pool used for Oxide services
@@ -1463,7 +1866,13 @@ async fn ip_pool_service_range_add(
         let out = nexus.ip_pool_service_add_range(&opctx, &range).await?;
         Ok(HttpResponseCreated(out.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(
+            &rqctx,
+            "ip_pool_service_range_add",
+            handler,
+        )
+        .await
 }
 
 /// Remove a range from an IP pool used for Oxide services
@@ -1484,7 +1893,13 @@ async fn ip_pool_service_range_remove(
         nexus.ip_pool_service_delete_range(&opctx, &range).await?;
         Ok(HttpResponseUpdatedNoContent())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(
+            &rqctx,
+            "ip_pool_service_range_remove",
+            handler,
+        )
+        .await
 }
 
 // Disks
@@ -1547,7 +1962,7 @@ async fn disk_create(
             nexus.project_create_disk(&opctx, &project_lookup, &params).await?;
         Ok(HttpResponseCreated(disk.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_audited_handler(&rqctx, "disk_create", handler).await
 }
 
 /// Fetch a disk
@@ -1599,7 +2014,7 @@ async fn disk_delete(
         nexus.project_delete_disk(&opctx, &disk_lookup).await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_audited_handler(&rqctx, "disk_delete", handler).await
 }
 
 #[derive(Display, Serialize, Deserialize, JsonSchema)]
@@ -1692,7 +2107,13 @@ async fn disk_bulk_write_import_start(
 
         Ok(HttpResponseUpdatedNoContent())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(
+            &rqctx,
+            "disk_bulk_write_import_start",
+            handler,
+        )
+        .await
 }
 
 /// Import blocks into a disk
@@ -1723,7 +2144,9 @@ async fn disk_bulk_write_import(
 
         Ok(HttpResponseUpdatedNoContent())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(&rqctx, "disk_bulk_write_import", handler)
+        .await
 }
 
 /// Stop importing blocks into a disk
@@ -1754,7 +2177,13 @@ async fn disk_bulk_write_import_stop(
 
         Ok(HttpResponseUpdatedNoContent())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(
+            &rqctx,
+            "disk_bulk_write_import_stop",
+            handler,
+        )
+        .await
 }
 
 /// Request to import blocks from URL
@@ -1787,7 +2216,13 @@ async fn disk_import_blocks_from_url(
 
         Ok(HttpResponseUpdatedNoContent())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(
+            &rqctx,
+            "disk_import_blocks_from_url",
+            handler,
+        )
+        .await
 }
 
 /// Confirm disk block import completion
@@ -1817,22 +2252,55 @@ async fn disk_finalize_import(
 
         Ok(HttpResponseUpdatedNoContent())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(&rqctx, "disk_finalize_import", handler)
+        .await
 }
 
-// Instances
-
-/// List instances
+/// Grow a disk that is not in use
+///
+/// Disks may only grow. The disk must be detached, or attached to an instance
+/// which is stopped: disks attached to a running instance cannot be resized.
 #[endpoint {
-    method = GET,
-    path = "/v1/instances",
-    tags = ["instances"],
+    method = POST,
+    path = "/v1/disks/{disk}/resize",
+    tags = ["disks"],
 }]
-async fn instance_list(
+async fn disk_resize(
     rqctx: RequestContext<Arc<ServerContext>>,
-    query_params: Query<PaginatedByNameOrId<params::ProjectSelector>>,
-) -> Result<HttpResponseOk<ResultsPage<Instance>>, HttpError> {
-    let apictx = rqctx.context();
+    path_params: Path<params::DiskPath>,
+    query_params: Query<params::OptionalProjectSelector>,
+    resize_params: TypedBody<params::DiskResize>,
+) -> Result<HttpResponseOk<Disk>, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
+        let nexus = &apictx.nexus;
+        let path = path_params.into_inner();
+        let query = query_params.into_inner();
+        let params = resize_params.into_inner();
+        let disk_selector =
+            params::DiskSelector { disk: path.disk, project: query.project };
+        let disk_lookup = nexus.disk_lookup(&opctx, disk_selector)?;
+        let disk = nexus.disk_resize(&opctx, &disk_lookup, &params).await?;
+        Ok(HttpResponseOk(disk.into()))
+    };
+    apictx.instrument_audited_handler(&rqctx, "disk_resize", handler).await
+}
+
+// Instances
+
+/// List instances
+#[endpoint {
+    method = GET,
+    path = "/v1/instances",
+    tags = ["instances"],
+}]
+async fn instance_list(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    query_params: Query<PaginatedByNameOrId<params::ProjectSelector>>,
+) -> Result<HttpResponseOk<ResultsPage<Instance>>, HttpError> {
+    let apictx = rqctx.context();
     let handler = async {
         let nexus = &apictx.nexus;
         let query = query_params.into_inner();
@@ -1884,7 +2352,7 @@ async fn instance_create(
             .await?;
         Ok(HttpResponseCreated(instance.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_audited_handler(&rqctx, "instance_create", handler).await
 }
 
 /// Fetch an instance
@@ -1916,6 +2384,42 @@ async fn instance_view(
     apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
 }
 
+/// Update an instance
+///
+/// The instance must be stopped. The new configuration takes effect the next
+/// time the instance is started.
+#[endpoint {
+    method = PUT,
+    path = "/v1/instances/{instance}",
+    tags = ["instances"],
+}]
+async fn instance_update(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    query_params: Query<params::OptionalProjectSelector>,
+    path_params: Path<params::InstancePath>,
+    updated_instance: TypedBody<params::InstanceUpdate>,
+) -> Result<HttpResponseOk<Instance>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let query = query_params.into_inner();
+    let updated_instance = updated_instance.into_inner();
+    let handler = async {
+        let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
+        let instance_selector = params::InstanceSelector {
+            project: query.project,
+            instance: path.instance,
+        };
+        let instance_lookup =
+            nexus.instance_lookup(&opctx, instance_selector)?;
+        let instance = nexus
+            .instance_reconfigure(&opctx, &instance_lookup, &updated_instance)
+            .await?;
+        Ok(HttpResponseOk(instance.into()))
+    };
+    apictx.instrument_audited_handler(&rqctx, "instance_update", handler).await
+}
+
 /// Delete an instance
 #[endpoint {
     method = DELETE,
@@ -1942,7 +2446,7 @@ async fn instance_delete(
         nexus.project_destroy_instance(&opctx, &instance_lookup).await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_audited_handler(&rqctx, "instance_delete", handler).await
 }
 
 // TODO should this be in the public API?
@@ -1980,7 +2484,7 @@ async fn instance_migrate(
             .await?;
         Ok(HttpResponseOk(instance.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_audited_handler(&rqctx, "instance_migrate", handler).await
 }
 
 /// Reboot an instance
@@ -2009,7 +2513,7 @@ async fn instance_reboot(
         let instance = nexus.instance_reboot(&opctx, &instance_lookup).await?;
         Ok(HttpResponseAccepted(instance.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_audited_handler(&rqctx, "instance_reboot", handler).await
 }
 
 /// Boot an instance
@@ -2038,7 +2542,7 @@ async fn instance_start(
         let instance = nexus.instance_start(&opctx, &instance_lookup).await?;
         Ok(HttpResponseAccepted(instance.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_audited_handler(&rqctx, "instance_start", handler).await
 }
 
 /// Stop an instance
@@ -2067,7 +2571,7 @@ async fn instance_stop(
         let instance = nexus.instance_stop(&opctx, &instance_lookup).await?;
         Ok(HttpResponseAccepted(instance.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_audited_handler(&rqctx, "instance_stop", handler).await
 }
 
 /// Fetch an instance's serial console
@@ -2152,6 +2656,75 @@ async fn instance_serial_console_stream(
     }
 }
 
+#[derive(Display, Serialize, Deserialize, JsonSchema)]
+#[display(style = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum InstanceMetricName {
+    VcpuUsage,
+    NicPacketsReceived,
+    NicPacketsSent,
+    NicBytesReceived,
+    NicBytesSent,
+    DiskRead,
+    DiskReadBytes,
+    DiskWrite,
+    DiskWriteBytes,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct InstanceMetricsPath {
+    instance: NameOrId,
+    metric: InstanceMetricName,
+}
+
+/// Fetch instance metrics
+///
+/// vCPU metrics require the `vcpu` parameter, network interface metrics
+/// require `network_interface`, and disk metrics require `disk`.
+#[endpoint {
+    method = GET,
+    path = "/v1/instances/{instance}/metrics/{metric}",
+    tags = ["instances"],
+}]
+async fn instance_metrics_list(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<InstanceMetricsPath>,
+    query_params: Query<
+        PaginationParams<params::ResourceMetrics, params::ResourceMetrics>,
+    >,
+    selector_params: Query<params::InstanceMetricsSelector>,
+) -> Result<HttpResponseOk<ResultsPage<oximeter_db::Measurement>>, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let nexus = &apictx.nexus;
+        let path = path_params.into_inner();
+        let query = query_params.into_inner();
+
+        let selector = selector_params.into_inner();
+        let limit = rqctx.page_limit(&query)?;
+        let instance_selector = params::InstanceSelector {
+            project: selector.project.clone(),
+            instance: path.instance,
+        };
+        let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
+        let instance_lookup =
+            nexus.instance_lookup(&opctx, instance_selector)?;
+        let result = nexus
+            .instance_metric_list(
+                &opctx,
+                &instance_lookup,
+                path.metric,
+                &selector,
+                query,
+                limit,
+            )
+            .await?;
+
+        Ok(HttpResponseOk(result))
+    };
+    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+}
+
 /// List an instance's disks
 #[endpoint {
     method = GET,
@@ -2222,7 +2795,9 @@ async fn instance_disk_attach(
             nexus.instance_attach_disk(&opctx, &instance_lookup, disk).await?;
         Ok(HttpResponseAccepted(disk.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(&rqctx, "instance_disk_attach", handler)
+        .await
 }
 
 /// Detach a disk from an instance
@@ -2254,7 +2829,9 @@ async fn instance_disk_detach(
             nexus.instance_detach_disk(&opctx, &instance_lookup, disk).await?;
         Ok(HttpResponseAccepted(disk.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(&rqctx, "instance_disk_detach", handler)
+        .await
 }
 
 // Certificates
@@ -2317,7 +2894,9 @@ async fn certificate_create(
         let cert = nexus.certificate_create(&opctx, new_cert_params).await?;
         Ok(HttpResponseCreated(cert.try_into()?))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(&rqctx, "certificate_create", handler)
+        .await
 }
 
 /// Path parameters for Certificate requests
@@ -2375,7 +2954,9 @@ async fn certificate_delete(
             .await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(&rqctx, "certificate_delete", handler)
+        .await
 }
 
 /// Create an address lot
@@ -2401,7 +2982,13 @@ async fn networking_address_lot_create(
 
         Ok(HttpResponseCreated(AddressLotCreateResponse { lot, blocks }))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(
+            &rqctx,
+            "networking_address_lot_create",
+            handler,
+        )
+        .await
 }
 
 /// Delete an address lot
@@ -2424,7 +3011,13 @@ async fn networking_address_lot_delete(
         nexus.address_lot_delete(&opctx, &address_lot_lookup).await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(
+            &rqctx,
+            "networking_address_lot_delete",
+            handler,
+        )
+        .await
 }
 
 /// List address lots
@@ -2518,7 +3111,13 @@ async fn networking_loopback_address_create(
 
         Ok(HttpResponseCreated(addr))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(
+            &rqctx,
+            "networking_loopback_address_create",
+            handler,
+        )
+        .await
 }
 
 #[derive(Serialize, Deserialize, JsonSchema)]
@@ -2570,7 +3169,13 @@ async fn networking_loopback_address_delete(
             .await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(
+            &rqctx,
+            "networking_loopback_address_delete",
+            handler,
+        )
+        .await
 }
 
 /// Get loopback addresses, optionally filtering by id
@@ -2625,7 +3230,13 @@ async fn networking_switch_port_settings_create(
         let settings: SwitchPortSettingsView = result.into();
         Ok(HttpResponseCreated(settings))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(
+            &rqctx,
+            "networking_switch_port_settings_create",
+            handler,
+        )
+        .await
 }
 
 /// Delete switch port settings
@@ -2646,7 +3257,13 @@ async fn networking_switch_port_settings_delete(
         nexus.switch_port_settings_delete(&opctx, &selector).await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(
+            &rqctx,
+            "networking_switch_port_settings_delete",
+            handler,
+        )
+        .await
 }
 
 /// List switch port settings
@@ -2762,7 +3379,13 @@ async fn networking_switch_port_apply_settings(
             .await?;
         Ok(HttpResponseUpdatedNoContent {})
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(
+            &rqctx,
+            "networking_switch_port_apply_settings",
+            handler,
+        )
+        .await
 }
 
 /// Clear switch port settings
@@ -2785,7 +3408,13 @@ async fn networking_switch_port_clear_settings(
         nexus.switch_port_clear_settings(&opctx, &port, &query).await?;
         Ok(HttpResponseUpdatedNoContent {})
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(
+            &rqctx,
+            "networking_switch_port_clear_settings",
+            handler,
+        )
+        .await
 }
 
 // Images
@@ -2874,7 +3503,7 @@ async fn image_create(
         let image = nexus.image_create(&opctx, &parent_lookup, &params).await?;
         Ok(HttpResponseCreated(image.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_audited_handler(&rqctx, "image_create", handler).await
 }
 
 /// Fetch an image
@@ -2953,7 +3582,7 @@ async fn image_delete(
         nexus.image_delete(&opctx, &image_lookup).await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_audited_handler(&rqctx, "image_delete", handler).await
 }
 
 /// Promote a project image
@@ -2987,7 +3616,7 @@ async fn image_promote(
         let image = nexus.image_promote(&opctx, &image_lookup).await?;
         Ok(HttpResponseAccepted(image.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_audited_handler(&rqctx, "image_promote", handler).await
 }
 
 /// Demote a silo image
@@ -3022,7 +3651,7 @@ async fn image_demote(
             nexus.image_demote(&opctx, &image_lookup, &project_lookup).await?;
         Ok(HttpResponseAccepted(image.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_audited_handler(&rqctx, "image_demote", handler).await
 }
 
 /// List network interfaces
@@ -3090,7 +3719,13 @@ async fn instance_network_interface_create(
             .await?;
         Ok(HttpResponseCreated(iface.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(
+            &rqctx,
+            "instance_network_interface_create",
+            handler,
+        )
+        .await
 }
 
 /// Delete a network interface
@@ -3127,7 +3762,13 @@ async fn instance_network_interface_delete(
             .await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(
+            &rqctx,
+            "instance_network_interface_delete",
+            handler,
+        )
+        .await
 }
 
 /// Fetch a network interface
@@ -3200,7 +3841,13 @@ async fn instance_network_interface_update(
             .await?;
         Ok(HttpResponseOk(InstanceNetworkInterface::from(interface)))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(
+            &rqctx,
+            "instance_network_interface_update",
+            handler,
+        )
+        .await
 }
 
 // External IP addresses for instances
@@ -3214,39 +3861,359 @@ async fn instance_network_interface_update(
 async fn instance_external_ip_list(
     rqctx: RequestContext<Arc<ServerContext>>,
     query_params: Query<params::OptionalProjectSelector>,
-    path_params: Path<params::InstancePath>,
-) -> Result<HttpResponseOk<ResultsPage<views::ExternalIp>>, HttpError> {
+    path_params: Path<params::InstancePath>,
+) -> Result<HttpResponseOk<ResultsPage<views::ExternalIp>>, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let nexus = &apictx.nexus;
+        let path = path_params.into_inner();
+        let query = query_params.into_inner();
+        let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
+        let instance_selector = params::InstanceSelector {
+            project: query.project,
+            instance: path.instance,
+        };
+        let instance_lookup =
+            nexus.instance_lookup(&opctx, instance_selector)?;
+        let ips =
+            nexus.instance_list_external_ips(&opctx, &instance_lookup).await?;
+        Ok(HttpResponseOk(ResultsPage { items: ips, next_page: None }))
+    };
+    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+}
+
+// Snapshots
+
+/// List snapshots
+#[endpoint {
+    method = GET,
+    path = "/v1/snapshots",
+    tags = ["snapshots"],
+}]
+async fn snapshot_list(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    query_params: Query<PaginatedByNameOrId<params::ProjectSelector>>,
+) -> Result<HttpResponseOk<ResultsPage<Snapshot>>, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
+        let nexus = &apictx.nexus;
+        let query = query_params.into_inner();
+        let pag_params = data_page_params_for(&rqctx, &query)?;
+        let scan_params = ScanByNameOrId::from_query(&query)?;
+        let paginated_by = name_or_id_pagination(&pag_params, scan_params)?;
+        let project_lookup =
+            nexus.project_lookup(&opctx, scan_params.selector.clone())?;
+        let snapshots = nexus
+            .snapshot_list(&opctx, &project_lookup, &paginated_by)
+            .await?
+            .into_iter()
+            .map(|d| d.into())
+            .collect();
+        Ok(HttpResponseOk(ScanByNameOrId::results_page(
+            &query,
+            snapshots,
+            &marker_for_name_or_id,
+        )?))
+    };
+    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+}
+
+/// Create a snapshot
+///
+/// Creates a point-in-time snapshot from a disk.
+#[endpoint {
+    method = POST,
+    path = "/v1/snapshots",
+    tags = ["snapshots"],
+}]
+async fn snapshot_create(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    query_params: Query<params::ProjectSelector>,
+    new_snapshot: TypedBody<params::SnapshotCreate>,
+) -> Result<HttpResponseCreated<Snapshot>, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
+        let nexus = &apictx.nexus;
+        let query = query_params.into_inner();
+        let new_snapshot_params = &new_snapshot.into_inner();
+        let project_lookup = nexus.project_lookup(&opctx, query)?;
+        let snapshot = nexus
+            .snapshot_create(&opctx, project_lookup, &new_snapshot_params)
+            .await?;
+        Ok(HttpResponseCreated(snapshot.into()))
+    };
+    apictx.instrument_audited_handler(&rqctx, "snapshot_create", handler).await
+}
+
+/// Fetch a snapshot
+#[endpoint {
+    method = GET,
+    path = "/v1/snapshots/{snapshot}",
+    tags = ["snapshots"],
+}]
+async fn snapshot_view(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<params::SnapshotPath>,
+    query_params: Query<params::OptionalProjectSelector>,
+) -> Result<HttpResponseOk<Snapshot>, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
+        let nexus = &apictx.nexus;
+        let path = path_params.into_inner();
+        let query = query_params.into_inner();
+        let snapshot_selector = params::SnapshotSelector {
+            project: query.project,
+            snapshot: path.snapshot,
+        };
+        let (.., snapshot) =
+            nexus.snapshot_lookup(&opctx, snapshot_selector)?.fetch().await?;
+        Ok(HttpResponseOk(snapshot.into()))
+    };
+    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+}
+
+/// Delete a snapshot
+#[endpoint {
+    method = DELETE,
+    path = "/v1/snapshots/{snapshot}",
+    tags = ["snapshots"],
+}]
+async fn snapshot_delete(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<params::SnapshotPath>,
+    query_params: Query<params::OptionalProjectSelector>,
+) -> Result<HttpResponseDeleted, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
+        let nexus = &apictx.nexus;
+        let path = path_params.into_inner();
+        let query = query_params.into_inner();
+        let snapshot_selector = params::SnapshotSelector {
+            project: query.project,
+            snapshot: path.snapshot,
+        };
+        let snapshot_lookup =
+            nexus.snapshot_lookup(&opctx, snapshot_selector)?;
+        nexus.snapshot_delete(&opctx, &snapshot_lookup).await?;
+        Ok(HttpResponseDeleted())
+    };
+    apictx.instrument_audited_handler(&rqctx, "snapshot_delete", handler).await
+}
+
+// Floating IP Addresses
+
+/// List all floating IPs
+#[endpoint {
+    method = GET,
+    path = "/v1/floating-ips",
+    tags = ["floating-ips"],
+}]
+async fn floating_ip_list(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    query_params: Query<PaginatedByNameOrId<params::ProjectSelector>>,
+) -> Result<HttpResponseOk<ResultsPage<FloatingIp>>, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
+        let nexus = &apictx.nexus;
+        let query = query_params.into_inner();
+        let pag_params = data_page_params_for(&rqctx, &query)?;
+        let scan_params = ScanByNameOrId::from_query(&query)?;
+        let paginated_by = name_or_id_pagination(&pag_params, scan_params)?;
+        let project_lookup =
+            nexus.project_lookup(&opctx, scan_params.selector.clone())?;
+        let ips = nexus
+            .floating_ips_list(&opctx, &project_lookup, &paginated_by)
+            .await?;
+        Ok(HttpResponseOk(ScanByNameOrId::results_page(
+            &query,
+            ips,
+            &marker_for_name_or_id,
+        )?))
+    };
+    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+}
+
+/// Create a floating IP
+///
+/// Reserves an external IP address in a project, which can be attached to and
+/// detached from instances in that project.
+#[endpoint {
+    method = POST,
+    path = "/v1/floating-ips",
+    tags = ["floating-ips"],
+}]
+async fn floating_ip_create(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    query_params: Query<params::ProjectSelector>,
+    floating_params: TypedBody<params::FloatingIpCreate>,
+) -> Result<HttpResponseCreated<FloatingIp>, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
+        let nexus = &apictx.nexus;
+        let floating_params = floating_params.into_inner();
+        let project_lookup =
+            nexus.project_lookup(&opctx, query_params.into_inner())?;
+        let ip = nexus
+            .floating_ip_create(&opctx, &project_lookup, floating_params)
+            .await?;
+        Ok(HttpResponseCreated(ip))
+    };
+    apictx
+        .instrument_audited_handler(&rqctx, "floating_ip_create", handler)
+        .await
+}
+
+/// Fetch a floating IP
+#[endpoint {
+    method = GET,
+    path = "/v1/floating-ips/{floating_ip}",
+    tags = ["floating-ips"],
+}]
+async fn floating_ip_view(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<params::FloatingIpPath>,
+    query_params: Query<params::OptionalProjectSelector>,
+) -> Result<HttpResponseOk<FloatingIp>, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
+        let nexus = &apictx.nexus;
+        let path = path_params.into_inner();
+        let query = query_params.into_inner();
+        let floating_ip_selector = params::FloatingIpSelector {
+            floating_ip: path.floating_ip,
+            project: query.project,
+        };
+        let (.., fip) = nexus
+            .floating_ip_lookup(&opctx, floating_ip_selector)?
+            .fetch()
+            .await?;
+        Ok(HttpResponseOk(fip.into()))
+    };
+    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+}
+
+/// Delete a floating IP
+///
+/// The floating IP must not be attached to an instance.
+#[endpoint {
+    method = DELETE,
+    path = "/v1/floating-ips/{floating_ip}",
+    tags = ["floating-ips"],
+}]
+async fn floating_ip_delete(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<params::FloatingIpPath>,
+    query_params: Query<params::OptionalProjectSelector>,
+) -> Result<HttpResponseDeleted, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
+        let nexus = &apictx.nexus;
+        let path = path_params.into_inner();
+        let query = query_params.into_inner();
+        let floating_ip_selector = params::FloatingIpSelector {
+            floating_ip: path.floating_ip,
+            project: query.project,
+        };
+        let fip_lookup =
+            nexus.floating_ip_lookup(&opctx, floating_ip_selector)?;
+        nexus.floating_ip_delete(&opctx, fip_lookup).await?;
+        Ok(HttpResponseDeleted())
+    };
+    apictx
+        .instrument_audited_handler(&rqctx, "floating_ip_delete", handler)
+        .await
+}
+
+/// Attach a floating IP to an instance
+///
+/// The instance must be stopped or running, and must be in the same project as
+/// the floating IP.
+#[endpoint {
+    method = POST,
+    path = "/v1/floating-ips/{floating_ip}/attach",
+    tags = ["floating-ips"],
+}]
+async fn floating_ip_attach(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<params::FloatingIpPath>,
+    query_params: Query<params::OptionalProjectSelector>,
+    target: TypedBody<params::FloatingIpAttach>,
+) -> Result<HttpResponseAccepted<FloatingIp>, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
+        let nexus = &apictx.nexus;
+        let path = path_params.into_inner();
+        let query = query_params.into_inner();
+        let floating_ip_selector = params::FloatingIpSelector {
+            floating_ip: path.floating_ip,
+            project: query.project,
+        };
+        let fip_lookup =
+            nexus.floating_ip_lookup(&opctx, floating_ip_selector)?;
+        let ip = nexus
+            .floating_ip_attach(&opctx, fip_lookup, target.into_inner())
+            .await?;
+        Ok(HttpResponseAccepted(ip))
+    };
+    apictx
+        .instrument_audited_handler(&rqctx, "floating_ip_attach", handler)
+        .await
+}
+
+/// Detach a floating IP from an instance
+///
+/// The instance must be stopped or running.
+#[endpoint {
+    method = POST,
+    path = "/v1/floating-ips/{floating_ip}/detach",
+    tags = ["floating-ips"],
+}]
+async fn floating_ip_detach(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<params::FloatingIpPath>,
+    query_params: Query<params::OptionalProjectSelector>,
+) -> Result<HttpResponseAccepted<FloatingIp>, HttpError> {
     let apictx = rqctx.context();
     let handler = async {
+        let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
         let nexus = &apictx.nexus;
         let path = path_params.into_inner();
         let query = query_params.into_inner();
-        let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
-        let instance_selector = params::InstanceSelector {
+        let floating_ip_selector = params::FloatingIpSelector {
+            floating_ip: path.floating_ip,
             project: query.project,
-            instance: path.instance,
         };
-        let instance_lookup =
-            nexus.instance_lookup(&opctx, instance_selector)?;
-        let ips =
-            nexus.instance_list_external_ips(&opctx, &instance_lookup).await?;
-        Ok(HttpResponseOk(ResultsPage { items: ips, next_page: None }))
+        let fip_lookup =
+            nexus.floating_ip_lookup(&opctx, floating_ip_selector)?;
+        let ip = nexus.floating_ip_detach(&opctx, fip_lookup).await?;
+        Ok(HttpResponseAccepted(ip))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(&rqctx, "floating_ip_detach", handler)
+        .await
 }
 
-// Snapshots
+// Affinity Groups
 
-/// List snapshots
+/// List affinity groups
 #[endpoint {
     method = GET,
-    path = "/v1/snapshots",
-    tags = ["snapshots"],
+    path = "/v1/affinity-groups",
+    tags = ["affinity-groups"],
 }]
-async fn snapshot_list(
+async fn affinity_group_list(
     rqctx: RequestContext<Arc<ServerContext>>,
     query_params: Query<PaginatedByNameOrId<params::ProjectSelector>>,
-) -> Result<HttpResponseOk<ResultsPage<Snapshot>>, HttpError> {
+) -> Result<HttpResponseOk<ResultsPage<AffinityGroup>>, HttpError> {
     let apictx = rqctx.context();
     let handler = async {
         let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
@@ -3257,86 +4224,97 @@ async fn snapshot_list(
         let paginated_by = name_or_id_pagination(&pag_params, scan_params)?;
         let project_lookup =
             nexus.project_lookup(&opctx, scan_params.selector.clone())?;
-        let snapshots = nexus
-            .snapshot_list(&opctx, &project_lookup, &paginated_by)
+        let groups = nexus
+            .affinity_group_list(&opctx, &project_lookup, &paginated_by)
             .await?
             .into_iter()
-            .map(|d| d.into())
+            .map(|group| group.into())
             .collect();
         Ok(HttpResponseOk(ScanByNameOrId::results_page(
             &query,
-            snapshots,
+            groups,
             &marker_for_name_or_id,
         )?))
     };
     apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
 }
 
-/// Create a snapshot
+/// Create an affinity group
 ///
-/// Creates a point-in-time snapshot from a disk.
+/// Instances that are members of an affinity group are placed on the same
+/// sled (affinity) or on different sleds (anti-affinity), subject to the
+/// group's policy.
 #[endpoint {
     method = POST,
-    path = "/v1/snapshots",
-    tags = ["snapshots"],
+    path = "/v1/affinity-groups",
+    tags = ["affinity-groups"],
 }]
-async fn snapshot_create(
+async fn affinity_group_create(
     rqctx: RequestContext<Arc<ServerContext>>,
     query_params: Query<params::ProjectSelector>,
-    new_snapshot: TypedBody<params::SnapshotCreate>,
-) -> Result<HttpResponseCreated<Snapshot>, HttpError> {
+    new_group: TypedBody<params::AffinityGroupCreate>,
+) -> Result<HttpResponseCreated<AffinityGroup>, HttpError> {
     let apictx = rqctx.context();
     let handler = async {
         let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
         let nexus = &apictx.nexus;
-        let query = query_params.into_inner();
-        let new_snapshot_params = &new_snapshot.into_inner();
-        let project_lookup = nexus.project_lookup(&opctx, query)?;
-        let snapshot = nexus
-            .snapshot_create(&opctx, project_lookup, &new_snapshot_params)
+        let project_lookup =
+            nexus.project_lookup(&opctx, query_params.into_inner())?;
+        let group = nexus
+            .affinity_group_create(
+                &opctx,
+                &project_lookup,
+                new_group.into_inner(),
+            )
             .await?;
-        Ok(HttpResponseCreated(snapshot.into()))
+        Ok(HttpResponseCreated(group.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(&rqctx, "affinity_group_create", handler)
+        .await
 }
 
-/// Fetch a snapshot
+/// Fetch an affinity group
 #[endpoint {
     method = GET,
-    path = "/v1/snapshots/{snapshot}",
-    tags = ["snapshots"],
+    path = "/v1/affinity-groups/{affinity_group}",
+    tags = ["affinity-groups"],
 }]
-async fn snapshot_view(
+async fn affinity_group_view(
     rqctx: RequestContext<Arc<ServerContext>>,
-    path_params: Path<params::SnapshotPath>,
+    path_params: Path<params::AffinityGroupPath>,
     query_params: Query<params::OptionalProjectSelector>,
-) -> Result<HttpResponseOk<Snapshot>, HttpError> {
+) -> Result<HttpResponseOk<AffinityGroup>, HttpError> {
     let apictx = rqctx.context();
     let handler = async {
         let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
         let nexus = &apictx.nexus;
         let path = path_params.into_inner();
         let query = query_params.into_inner();
-        let snapshot_selector = params::SnapshotSelector {
+        let group_selector = params::AffinityGroupSelector {
+            affinity_group: path.affinity_group,
             project: query.project,
-            snapshot: path.snapshot,
         };
-        let (.., snapshot) =
-            nexus.snapshot_lookup(&opctx, snapshot_selector)?.fetch().await?;
-        Ok(HttpResponseOk(snapshot.into()))
+        let (.., group) = nexus
+            .affinity_group_lookup(&opctx, group_selector)?
+            .fetch()
+            .await?;
+        Ok(HttpResponseOk(group.into()))
     };
     apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
 }
 
-/// Delete a snapshot
+/// Delete an affinity group
+///
+/// Deleting a group doesn't move its member instances.
 #[endpoint {
     method = DELETE,
-    path = "/v1/snapshots/{snapshot}",
-    tags = ["snapshots"],
+    path = "/v1/affinity-groups/{affinity_group}",
+    tags = ["affinity-groups"],
 }]
-async fn snapshot_delete(
+async fn affinity_group_delete(
     rqctx: RequestContext<Arc<ServerContext>>,
-    path_params: Path<params::SnapshotPath>,
+    path_params: Path<params::AffinityGroupPath>,
     query_params: Query<params::OptionalProjectSelector>,
 ) -> Result<HttpResponseDeleted, HttpError> {
     let apictx = rqctx.context();
@@ -3345,18 +4323,149 @@ async fn snapshot_delete(
         let nexus = &apictx.nexus;
         let path = path_params.into_inner();
         let query = query_params.into_inner();
-        let snapshot_selector = params::SnapshotSelector {
+        let group_selector = params::AffinityGroupSelector {
+            affinity_group: path.affinity_group,
             project: query.project,
-            snapshot: path.snapshot,
         };
-        let snapshot_lookup =
-            nexus.snapshot_lookup(&opctx, snapshot_selector)?;
-        nexus.snapshot_delete(&opctx, &snapshot_lookup).await?;
+        let group_lookup =
+            nexus.affinity_group_lookup(&opctx, group_selector)?;
+        nexus.affinity_group_delete(&opctx, &group_lookup).await?;
         Ok(HttpResponseDeleted())
     };
+    apictx
+        .instrument_audited_handler(&rqctx, "affinity_group_delete", handler)
+        .await
+}
+
+/// List the instances in an affinity group
+#[endpoint {
+    method = GET,
+    path = "/v1/affinity-groups/{affinity_group}/members",
+    tags = ["affinity-groups"],
+}]
+async fn affinity_group_member_list(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<params::AffinityGroupPath>,
+    query_params: Query<PaginatedByNameOrId<params::OptionalProjectSelector>>,
+) -> Result<HttpResponseOk<ResultsPage<Instance>>, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
+        let nexus = &apictx.nexus;
+        let path = path_params.into_inner();
+        let query = query_params.into_inner();
+        let pag_params = data_page_params_for(&rqctx, &query)?;
+        let scan_params = ScanByNameOrId::from_query(&query)?;
+        let paginated_by = name_or_id_pagination(&pag_params, scan_params)?;
+        let group_selector = params::AffinityGroupSelector {
+            affinity_group: path.affinity_group,
+            project: scan_params.selector.project.clone(),
+        };
+        let group_lookup =
+            nexus.affinity_group_lookup(&opctx, group_selector)?;
+        let instances = nexus
+            .affinity_group_member_list(&opctx, &group_lookup, &paginated_by)
+            .await?
+            .into_iter()
+            .map(|instance| instance.into())
+            .collect();
+        Ok(HttpResponseOk(ScanByNameOrId::results_page(
+            &query,
+            instances,
+            &marker_for_name_or_id,
+        )?))
+    };
     apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
 }
 
+/// Add an instance to an affinity group
+///
+/// The instance must be in the same project as the group. Instances aren't
+/// moved when they join a group, so if the group's policy is `fail`, the
+/// instance must already be on a sled that satisfies the group's rule.
+#[endpoint {
+    method = POST,
+    path = "/v1/affinity-groups/{affinity_group}/members/add",
+    tags = ["affinity-groups"],
+}]
+async fn affinity_group_member_add(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<params::AffinityGroupPath>,
+    query_params: Query<params::OptionalProjectSelector>,
+    member: TypedBody<params::AffinityGroupMember>,
+) -> Result<HttpResponseUpdatedNoContent, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
+        let nexus = &apictx.nexus;
+        let path = path_params.into_inner();
+        let query = query_params.into_inner();
+        let group_selector = params::AffinityGroupSelector {
+            affinity_group: path.affinity_group,
+            project: query.project,
+        };
+        let group_lookup =
+            nexus.affinity_group_lookup(&opctx, group_selector)?;
+        nexus
+            .affinity_group_member_add(
+                &opctx,
+                &group_lookup,
+                member.into_inner(),
+            )
+            .await?;
+        Ok(HttpResponseUpdatedNoContent())
+    };
+    apictx
+        .instrument_audited_handler(
+            &rqctx,
+            "affinity_group_member_add",
+            handler,
+        )
+        .await
+}
+
+/// Remove an instance from an affinity group
+#[endpoint {
+    method = POST,
+    path = "/v1/affinity-groups/{affinity_group}/members/remove",
+    tags = ["affinity-groups"],
+}]
+async fn affinity_group_member_remove(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<params::AffinityGroupPath>,
+    query_params: Query<params::OptionalProjectSelector>,
+    member: TypedBody<params::AffinityGroupMember>,
+) -> Result<HttpResponseUpdatedNoContent, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
+        let nexus = &apictx.nexus;
+        let path = path_params.into_inner();
+        let query = query_params.into_inner();
+        let group_selector = params::AffinityGroupSelector {
+            affinity_group: path.affinity_group,
+            project: query.project,
+        };
+        let group_lookup =
+            nexus.affinity_group_lookup(&opctx, group_selector)?;
+        nexus
+            .affinity_group_member_remove(
+                &opctx,
+                &group_lookup,
+                member.into_inner(),
+            )
+            .await?;
+        Ok(HttpResponseUpdatedNoContent())
+    };
+    apictx
+        .instrument_audited_handler(
+            &rqctx,
+            "affinity_group_member_remove",
+            handler,
+        )
+        .await
+}
+
 // VPCs
 
 /// List VPCs
@@ -3418,7 +4527,7 @@ async fn vpc_create(
             .await?;
         Ok(HttpResponseCreated(vpc.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_audited_handler(&rqctx, "vpc_create", handler).await
 }
 
 /// Fetch a VPC
@@ -3473,7 +4582,7 @@ async fn vpc_update(
             .await?;
         Ok(HttpResponseOk(vpc.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_audited_handler(&rqctx, "vpc_update", handler).await
 }
 
 /// Delete a VPC
@@ -3499,7 +4608,7 @@ async fn vpc_delete(
         nexus.project_delete_vpc(&opctx, &vpc_lookup).await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_audited_handler(&rqctx, "vpc_delete", handler).await
 }
 
 /// List subnets
@@ -3559,7 +4668,9 @@ async fn vpc_subnet_create(
             nexus.vpc_create_subnet(&opctx, &vpc_lookup, &create).await?;
         Ok(HttpResponseCreated(subnet.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(&rqctx, "vpc_subnet_create", handler)
+        .await
 }
 
 /// Fetch a subnet
@@ -3617,7 +4728,9 @@ async fn vpc_subnet_delete(
         nexus.vpc_delete_subnet(&opctx, &subnet_lookup).await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(&rqctx, "vpc_subnet_delete", handler)
+        .await
 }
 
 /// Update a subnet
@@ -3650,7 +4763,9 @@ async fn vpc_subnet_update(
             .await?;
         Ok(HttpResponseOk(subnet.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(&rqctx, "vpc_subnet_update", handler)
+        .await
 }
 
 // This endpoint is likely temporary. We would rather list all IPs allocated in
@@ -3759,7 +4874,13 @@ async fn vpc_firewall_rules_update(
             rules: rules.into_iter().map(|rule| rule.into()).collect(),
         }))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(
+            &rqctx,
+            "vpc_firewall_rules_update",
+            handler,
+        )
+        .await
 }
 
 // VPC Routers
@@ -3856,7 +4977,9 @@ async fn vpc_router_create(
             .await?;
         Ok(HttpResponseCreated(router.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(&rqctx, "vpc_router_create", handler)
+        .await
 }
 
 /// Delete a router
@@ -3885,7 +5008,9 @@ async fn vpc_router_delete(
         nexus.vpc_delete_router(&opctx, &router_lookup).await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(&rqctx, "vpc_router_delete", handler)
+        .await
 }
 
 /// Update a router
@@ -3918,7 +5043,9 @@ async fn vpc_router_update(
             .await?;
         Ok(HttpResponseOk(router.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(&rqctx, "vpc_router_update", handler)
+        .await
 }
 
 /// List routes
@@ -4020,7 +5147,9 @@ async fn vpc_router_route_create(
             .await?;
         Ok(HttpResponseCreated(route.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(&rqctx, "vpc_router_route_create", handler)
+        .await
 }
 
 /// Delete a route
@@ -4051,7 +5180,9 @@ async fn vpc_router_route_delete(
         nexus.router_delete_route(&opctx, &route_lookup).await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(&rqctx, "vpc_router_route_delete", handler)
+        .await
 }
 
 /// Update a route
@@ -4086,7 +5217,9 @@ async fn vpc_router_route_update(
             .await?;
         Ok(HttpResponseOk(route.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(&rqctx, "vpc_router_route_update", handler)
+        .await
 }
 
 // Racks
@@ -4485,6 +5618,74 @@ async fn silo_metric(
     apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
 }
 
+// Audit log
+
+/// Page selector for the audit log, which is ordered by the time at which
+/// requests were received
+#[derive(Deserialize, JsonSchema, Serialize)]
+struct AuditLogPage {
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+    last_seen: (DateTime<Utc>, Uuid),
+}
+
+/// View the audit log
+///
+/// Lists the requests to the external API that may have modified state,
+/// ordered by the time at which they were received.
+#[endpoint {
+    method = GET,
+    path = "/v1/system/audit-log",
+    tags = ["system/audit-log"],
+}]
+async fn audit_log_list(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    query_params: Query<PaginationParams<params::AuditLogParams, AuditLogPage>>,
+) -> Result<HttpResponseOk<ResultsPage<views::AuditLogEntry>>, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
+        let nexus = &apictx.nexus;
+        let query = query_params.into_inner();
+
+        // Resolve the end of the time range on the first page, so that later
+        // pages cover the same range.
+        let (start_time, end_time, marker) = match &query.page {
+            WhichPage::First(params) => (
+                params.start_time,
+                params.end_time.unwrap_or_else(Utc::now),
+                None,
+            ),
+            WhichPage::Next(page) => {
+                (page.start_time, page.end_time, Some(&page.last_seen))
+            }
+        };
+        let pagparams = DataPageParams {
+            limit: rqctx.page_limit(&query)?,
+            direction: PaginationOrder::Ascending,
+            marker,
+        };
+        let entries = nexus
+            .audit_log_list(&opctx, start_time, end_time, &pagparams)
+            .await?
+            .into_iter()
+            .map(views::AuditLogEntry::from)
+            .collect();
+        Ok(HttpResponseOk(ResultsPage::new(
+            entries,
+            &(start_time, end_time),
+            |entry: &views::AuditLogEntry, &(start_time, end_time)| {
+                AuditLogPage {
+                    start_time,
+                    end_time,
+                    last_seen: (entry.time_started, entry.id),
+                }
+            },
+        )?))
+    };
+    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+}
+
 // Updates
 
 /// Refresh update data
@@ -4504,7 +5705,9 @@ async fn system_update_refresh(
         nexus.updates_refresh_metadata(&opctx).await?;
         Ok(HttpResponseUpdatedNoContent())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(&rqctx, "system_update_refresh", handler)
+        .await
 }
 
 /// View system version and update status
@@ -4705,7 +5908,9 @@ async fn system_update_start(
             status: views::UpdateStatus::Updating,
         }))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(&rqctx, "system_update_start", handler)
+        .await
 }
 
 /// Stop system update
@@ -4743,7 +5948,9 @@ async fn system_update_stop(
 
         Ok(HttpResponseUpdatedNoContent())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(&rqctx, "system_update_stop", handler)
+        .await
 }
 
 /// List all update deployments
@@ -5162,7 +6369,13 @@ async fn current_user_ssh_key_create(
             .await?;
         Ok(HttpResponseCreated(ssh_key.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx
+        .instrument_audited_handler(
+            &rqctx,
+            "current_user_ssh_key_create",
+            handler,
+        )
+        .await
 }
 
 /// Fetch an SSH public key
@@ -5228,9 +6441,128 @@ async fn current_user_ssh_key_delete(
         nexus.ssh_key_delete(&opctx, actor.actor_id(), &ssh_key_lookup).await?;
         Ok(HttpResponseDeleted())
     };
+    apictx
+        .instrument_audited_handler(
+            &rqctx,
+            "current_user_ssh_key_delete",
+            handler,
+        )
+        .await
+}
+
+/// List access tokens
+///
+/// Lists the access tokens held by the currently authenticated user, whether
+/// they were created directly or obtained through the device authorization
+/// flow.
+#[endpoint {
+    method = GET,
+    path = "/v1/me/access-tokens",
+    tags = ["session"],
+}]
+async fn current_user_access_token_list(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    query_params: Query<PaginatedById>,
+) -> Result<HttpResponseOk<ResultsPage<AccessToken>>, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
+        let nexus = &apictx.nexus;
+        let query = query_params.into_inner();
+        let pag_params = data_page_params_for(&rqctx, &query)?;
+        let &actor = opctx
+            .authn
+            .actor_required()
+            .internal_context("listing current user's access tokens")?;
+        let tokens = nexus
+            .access_tokens_list(&opctx, actor.actor_id(), &pag_params)
+            .await?
+            .into_iter()
+            .map(AccessToken::from)
+            .collect();
+        Ok(HttpResponseOk(ScanById::results_page(
+            &query,
+            tokens,
+            &|_, token: &AccessToken| token.id,
+        )?))
+    };
     apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
 }
 
+/// Create an access token
+///
+/// Create an access token for the currently authenticated user. The token is
+/// only included in this response, and cannot be retrieved later.
+#[endpoint {
+    method = POST,
+    path = "/v1/me/access-tokens",
+    tags = ["session"],
+}]
+async fn current_user_access_token_create(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    new_token: TypedBody<params::AccessTokenCreate>,
+) -> Result<HttpResponseCreated<AccessTokenCreated>, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
+        let nexus = &apictx.nexus;
+        let &actor = opctx
+            .authn
+            .actor_required()
+            .internal_context("creating access token for current user")?;
+        let token = nexus
+            .access_token_create(
+                &opctx,
+                actor.actor_id(),
+                &new_token.into_inner(),
+            )
+            .await?;
+        Ok(HttpResponseCreated(token.into()))
+    };
+    apictx
+        .instrument_audited_handler(
+            &rqctx,
+            "current_user_access_token_create",
+            handler,
+        )
+        .await
+}
+
+/// Revoke an access token
+///
+/// Revoke one of the currently authenticated user's access tokens.
+#[endpoint {
+    method = DELETE,
+    path = "/v1/me/access-tokens/{token_id}",
+    tags = ["session"],
+}]
+async fn current_user_access_token_delete(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<params::AccessTokenPath>,
+) -> Result<HttpResponseDeleted, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
+        let nexus = &apictx.nexus;
+        let path = path_params.into_inner();
+        let &actor = opctx
+            .authn
+            .actor_required()
+            .internal_context("revoking one of current user's access tokens")?;
+        nexus
+            .access_token_delete(&opctx, actor.actor_id(), path.token_id)
+            .await?;
+        Ok(HttpResponseDeleted())
+    };
+    apictx
+        .instrument_audited_handler(
+            &rqctx,
+            "current_user_access_token_delete",
+            handler,
+        )
+        .await
+}
+
 #[cfg(test)]
 mod test {
     use super::external_api;
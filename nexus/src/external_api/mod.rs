@@ -5,6 +5,7 @@
 pub mod console_api;
 pub mod device_auth;
 pub(crate) mod http_entrypoints;
+pub mod scim;
 
 pub(crate) use nexus_types::external_api::params;
 pub(crate) use nexus_types::external_api::shared;
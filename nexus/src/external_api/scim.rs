@@ -0,0 +1,1054 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Entrypoints for SCIM 2.0 provisioning of Silo users and groups.
+//!
+//! These are endpoints used by an identity provider's SCIM client, *not* by
+//! Oxide API clients, so they are not part of the published API.  They speak
+//! the protocol described in RFC 7644, which has its own resource
+//! representations and error format.  Only the parts of the protocol that
+//! identity providers use in practice are implemented: users are identified
+//! by `userName` and may be deactivated, and groups are identified by
+//! `displayName` and have members.  Other attributes are accepted but not
+//! stored.
+//!
+//! See [`crate::app::scim`] for how requests are authenticated.
+
+use crate::context::audit_log_entry_init_for_actor;
+use crate::ServerContext;
+use chrono::{DateTime, Utc};
+use dropshot::{endpoint, HttpError, Path, Query, RequestContext, UntypedBody};
+use headers::authorization::{Authorization, Bearer};
+use headers::HeaderMapExt;
+use http::{header, Response, StatusCode};
+use hyper::Body;
+use nexus_db_queries::authz;
+use nexus_db_queries::context::OpContext;
+use nexus_db_queries::db;
+use nexus_db_queries::db::identity::Asset;
+use omicron_common::api::external::Error;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::sync::Arc;
+use uuid::Uuid;
+
+const SCIM_CONTENT_TYPE: &str = "application/scim+json";
+
+const SCIM_SCHEMA_USER: &str = "urn:ietf:params:scim:schemas:core:2.0:User";
+const SCIM_SCHEMA_GROUP: &str = "urn:ietf:params:scim:schemas:core:2.0:Group";
+const SCIM_SCHEMA_LIST_RESPONSE: &str =
+    "urn:ietf:params:scim:api:messages:2.0:ListResponse";
+const SCIM_SCHEMA_ERROR: &str = "urn:ietf:params:scim:api:messages:2.0:Error";
+const SCIM_SCHEMA_SERVICE_PROVIDER_CONFIG: &str =
+    "urn:ietf:params:scim:schemas:core:2.0:ServiceProviderConfig";
+
+/// Maximum number of resources returned in one page of a list response
+const SCIM_MAX_RESULTS: i64 = 100;
+
+// Responses
+
+/// SCIM responses use their own media type (RFC 7644 §3.1) and error format
+/// (RFC 7644 §3.12), so like the OAuth endpoints we build them ourselves.
+fn build_scim_response<T>(
+    status: StatusCode,
+    body: &T,
+) -> Result<Response<Body>, HttpError>
+where
+    T: ?Sized + Serialize,
+{
+    let body = serde_json::to_string(body)
+        .map_err(|e| HttpError::for_internal_error(e.to_string()))?;
+    Ok(Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, SCIM_CONTENT_TYPE)
+        .body(body.into())?)
+}
+
+fn build_scim_empty_response() -> Result<Response<Body>, HttpError> {
+    Ok(Response::builder().status(StatusCode::NO_CONTENT).body(Body::empty())?)
+}
+
+/// Convert the result of a SCIM handler into a response, reporting any error
+/// in the SCIM format
+fn scim_result(
+    rqctx: &RequestContext<Arc<ServerContext>>,
+    result: Result<Response<Body>, HttpError>,
+) -> Result<Response<Body>, HttpError> {
+    let error = match result {
+        Ok(response) => return Ok(response),
+        Err(error) => error,
+    };
+
+    slog::info!(rqctx.log, "SCIM request failed";
+        "status" => error.status_code.as_u16(),
+        "error_code" => ?error.error_code,
+        "internal_message" => &error.internal_message,
+    );
+
+    // Map our error codes onto the "scimType" values that SCIM clients
+    // understand.
+    let (status, scim_type) = match error.error_code.as_deref() {
+        Some("ObjectAlreadyExists") => {
+            (StatusCode::CONFLICT, Some("uniqueness"))
+        }
+        Some(
+            code @ ("invalidFilter" | "invalidSyntax" | "invalidPath"
+            | "invalidValue" | "noTarget" | "mutability"),
+        ) => (error.status_code, Some(code)),
+        Some("InvalidValue") => (error.status_code, Some("invalidValue")),
+        _ => (error.status_code, None),
+    };
+
+    let mut body = serde_json::json!({
+        "schemas": [SCIM_SCHEMA_ERROR],
+        "status": status.as_u16().to_string(),
+        "detail": error.external_message,
+    });
+    if let Some(scim_type) = scim_type {
+        body["scimType"] = Value::from(scim_type);
+    }
+    build_scim_response(status, &body)
+}
+
+/// Runs the handler for a SCIM request that may modify state, recording it in
+/// the audit log, and reports any error in the SCIM format
+async fn scim_audited_result<H>(
+    rqctx: &RequestContext<Arc<ServerContext>>,
+    operation_id: &'static str,
+    handler: H,
+) -> Result<Response<Body>, HttpError>
+where
+    H: Future<Output = Result<Response<Body>, HttpError>>,
+{
+    rqctx
+        .context()
+        .instrument_audited_raw_handler(rqctx, operation_id, async {
+            scim_result(rqctx, handler.await)
+        })
+        .await
+}
+
+fn scim_bad_request(scim_type: &str, message: String) -> HttpError {
+    HttpError::for_bad_request(Some(scim_type.to_string()), message)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ScimMeta {
+    resource_type: &'static str,
+    created: DateTime<Utc>,
+    last_modified: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ScimUser {
+    schemas: [&'static str; 1],
+    id: Uuid,
+    user_name: String,
+    active: bool,
+    meta: ScimMeta,
+}
+
+impl From<db::model::SiloUser> for ScimUser {
+    fn from(user: db::model::SiloUser) -> Self {
+        Self {
+            schemas: [SCIM_SCHEMA_USER],
+            id: user.id(),
+            meta: ScimMeta {
+                resource_type: "User",
+                created: user.time_created(),
+                last_modified: user.time_modified(),
+            },
+            user_name: user.external_id,
+            active: user.active,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ScimGroupMember {
+    value: Uuid,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ScimGroup {
+    schemas: [&'static str; 1],
+    id: Uuid,
+    display_name: String,
+    members: Vec<ScimGroupMember>,
+    meta: ScimMeta,
+}
+
+impl From<(db::model::SiloGroup, Vec<Uuid>)> for ScimGroup {
+    fn from((group, members): (db::model::SiloGroup, Vec<Uuid>)) -> Self {
+        Self {
+            schemas: [SCIM_SCHEMA_GROUP],
+            id: group.id(),
+            meta: ScimMeta {
+                resource_type: "Group",
+                created: group.time_created(),
+                last_modified: group.time_modified(),
+            },
+            display_name: group.external_id,
+            members: members
+                .into_iter()
+                .map(|value| ScimGroupMember { value })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ScimListResponse<T> {
+    schemas: [&'static str; 1],
+    total_results: i64,
+    start_index: i64,
+    items_per_page: usize,
+    #[serde(rename = "Resources")]
+    resources: Vec<T>,
+}
+
+// Requests
+
+#[derive(Deserialize, JsonSchema)]
+pub struct ScimUserPath {
+    user_id: Uuid,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct ScimGroupPath {
+    group_id: Uuid,
+}
+
+/// Query parameters for listing resources (RFC 7644 §3.4.2)
+#[derive(Deserialize, JsonSchema)]
+pub struct ScimListQuery {
+    filter: Option<String>,
+    /// 1-based index of the first result to return
+    #[serde(rename = "startIndex")]
+    start_index: Option<i64>,
+    /// Maximum number of results to return
+    count: Option<i64>,
+}
+
+impl ScimListQuery {
+    /// Returns the requested (offset, limit), as used by the datastore
+    fn offset_limit(&self) -> (i64, i64) {
+        let start_index = self.start_index.unwrap_or(1).max(1);
+        let count = self.count.unwrap_or(SCIM_MAX_RESULTS);
+        (start_index - 1, count.clamp(0, SCIM_MAX_RESULTS))
+    }
+
+    /// Parse the filter, which may only test the given attribute for equality
+    fn equality_filter(
+        &self,
+        attribute: &str,
+    ) -> Result<Option<String>, HttpError> {
+        let filter = match &self.filter {
+            None => return Ok(None),
+            Some(filter) => filter,
+        };
+        match parse_equality_filter(filter) {
+            Some((attr, value)) if attr.eq_ignore_ascii_case(attribute) => {
+                Ok(Some(value))
+            }
+            _ => Err(scim_bad_request(
+                "invalidFilter",
+                format!(
+                    "unsupported filter {:?}: only `{} eq \"...\"` is \
+                    supported",
+                    filter, attribute
+                ),
+            )),
+        }
+    }
+}
+
+/// Parse a filter of the form `attribute eq "value"`
+fn parse_equality_filter(filter: &str) -> Option<(String, String)> {
+    let (attribute, rest) = filter.trim().split_once(char::is_whitespace)?;
+    let (op, value) = rest.trim_start().split_once(char::is_whitespace)?;
+    if !op.eq_ignore_ascii_case("eq") {
+        return None;
+    }
+    let value: String = serde_json::from_str(value.trim()).ok()?;
+    Some((attribute.to_string(), value))
+}
+
+/// Parse a request body, which SCIM clients send as `application/scim+json`
+fn parse_body(body: UntypedBody) -> Result<Map<String, Value>, HttpError> {
+    serde_json::from_slice(body.as_bytes()).map_err(|e| {
+        scim_bad_request(
+            "invalidSyntax",
+            format!("unable to parse JSON body: {}", e),
+        )
+    })
+}
+
+/// Look up an attribute of a resource.  Attribute names are case-insensitive
+/// (RFC 7643 §2.1).
+fn attribute<'a>(
+    object: &'a Map<String, Value>,
+    name: &str,
+) -> Option<&'a Value> {
+    object
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value)
+}
+
+fn string_value(name: &str, value: &Value) -> Result<String, HttpError> {
+    value.as_str().map(String::from).ok_or_else(|| {
+        scim_bad_request(
+            "invalidValue",
+            format!("\"{}\" must be a string", name),
+        )
+    })
+}
+
+fn bool_value(name: &str, value: &Value) -> Result<bool, HttpError> {
+    // Some identity providers send booleans as strings (e.g., "False").
+    match value {
+        Value::Bool(value) => Ok(*value),
+        Value::String(value) if value.eq_ignore_ascii_case("true") => Ok(true),
+        Value::String(value) if value.eq_ignore_ascii_case("false") => {
+            Ok(false)
+        }
+        _ => Err(scim_bad_request(
+            "invalidValue",
+            format!("\"{}\" must be a boolean", name),
+        )),
+    }
+}
+
+fn members_value(value: &Value) -> Result<Vec<Uuid>, HttpError> {
+    let invalid = || {
+        scim_bad_request(
+            "invalidValue",
+            String::from(
+                "\"members\" must be a list of objects with a user id as \
+                their \"value\"",
+            ),
+        )
+    };
+    let members = match value {
+        Value::Array(members) => members.as_slice(),
+        member @ Value::Object(_) => std::slice::from_ref(member),
+        _ => return Err(invalid()),
+    };
+    members
+        .iter()
+        .map(|member| {
+            member
+                .as_object()
+                .and_then(|member| attribute(member, "value"))
+                .and_then(|value| value.as_str())
+                .and_then(|value| value.parse().ok())
+                .ok_or_else(invalid)
+        })
+        .collect()
+}
+
+/// One operation of a PATCH request (RFC 7644 §3.5.2)
+struct PatchOperation {
+    op: PatchOp,
+    path: Option<String>,
+    value: Option<Value>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum PatchOp {
+    Add,
+    Remove,
+    Replace,
+}
+
+fn parse_patch(body: UntypedBody) -> Result<Vec<PatchOperation>, HttpError> {
+    let body = parse_body(body)?;
+    let operations = attribute(&body, "Operations")
+        .and_then(|operations| operations.as_array())
+        .ok_or_else(|| {
+            scim_bad_request(
+                "invalidSyntax",
+                String::from("PATCH request has no \"Operations\""),
+            )
+        })?;
+
+    operations
+        .iter()
+        .map(|operation| {
+            let operation = operation.as_object().ok_or_else(|| {
+                scim_bad_request(
+                    "invalidSyntax",
+                    String::from("PATCH operation must be an object"),
+                )
+            })?;
+            let op = match attribute(operation, "op").and_then(Value::as_str) {
+                Some(op) if op.eq_ignore_ascii_case("add") => PatchOp::Add,
+                Some(op) if op.eq_ignore_ascii_case("remove") => {
+                    PatchOp::Remove
+                }
+                Some(op) if op.eq_ignore_ascii_case("replace") => {
+                    PatchOp::Replace
+                }
+                op => {
+                    return Err(scim_bad_request(
+                        "invalidSyntax",
+                        format!("unsupported PATCH operation {:?}", op),
+                    ))
+                }
+            };
+            let path = attribute(operation, "path")
+                .map(|path| string_value("path", path))
+                .transpose()?;
+            let value = attribute(operation, "value").cloned();
+            Ok(PatchOperation { op, path, value })
+        })
+        .collect()
+}
+
+// Authentication
+
+/// Authenticate the SCIM client making this request, returning the context
+/// in which to carry it out and the Silo that it may provision
+async fn scim_authn(
+    rqctx: &RequestContext<Arc<ServerContext>>,
+) -> Result<(&OpContext, authz::Silo), HttpError> {
+    let nexus = &rqctx.context().nexus;
+    let bearer: Authorization<Bearer> =
+        rqctx.request.headers().typed_get().ok_or_else(|| {
+            Error::Unauthenticated {
+                internal_message: String::from("missing bearer token"),
+            }
+        })?;
+
+    // SCIM clients act on behalf of the Silo's identity provider, not any
+    // user, so they use the same context as logins do.
+    let opctx = nexus.opctx_external_authn();
+    let (authz_silo, _) =
+        nexus.scim_authenticate(opctx, bearer.token()).await?;
+    audit_log_entry_init_for_actor(rqctx, None, Some(authz_silo.id())).await?;
+    Ok((opctx, authz_silo))
+}
+
+// Service provider configuration
+
+/// Describe the parts of SCIM that are supported (RFC 7643 §5)
+#[endpoint {
+    method = GET,
+    path = "/scim/v2/ServiceProviderConfig",
+    unpublished = true,
+}]
+pub(crate) async fn scim_service_provider_config(
+    rqctx: RequestContext<Arc<ServerContext>>,
+) -> Result<Response<Body>, HttpError> {
+    let handler = async {
+        scim_authn(&rqctx).await?;
+        let unsupported = serde_json::json!({ "supported": false });
+        build_scim_response(
+            StatusCode::OK,
+            &serde_json::json!({
+                "schemas": [SCIM_SCHEMA_SERVICE_PROVIDER_CONFIG],
+                "patch": { "supported": true },
+                "bulk": {
+                    "supported": false,
+                    "maxOperations": 0,
+                    "maxPayloadSize": 0,
+                },
+                "filter": {
+                    "supported": true,
+                    "maxResults": SCIM_MAX_RESULTS,
+                },
+                "changePassword": unsupported,
+                "sort": unsupported,
+                "etag": unsupported,
+                "authenticationSchemes": [{
+                    "type": "oauthbearertoken",
+                    "name": "OAuth Bearer Token",
+                    "description":
+                        "Authentication using a SCIM client bearer token",
+                }],
+            }),
+        )
+    };
+    scim_result(&rqctx, handler.await)
+}
+
+// Users
+
+/// List users, optionally filtered by `userName`
+#[endpoint {
+    method = GET,
+    path = "/scim/v2/Users",
+    unpublished = true,
+}]
+pub(crate) async fn scim_user_list(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    query_params: Query<ScimListQuery>,
+) -> Result<Response<Body>, HttpError> {
+    let handler = async {
+        let nexus = &rqctx.context().nexus;
+        let (opctx, authz_silo) = scim_authn(&rqctx).await?;
+        let query = query_params.into_inner();
+        let user_name = query.equality_filter("userName")?;
+        let (offset, limit) = query.offset_limit();
+        let (total, users) = nexus
+            .scim_users_list(
+                opctx,
+                &authz_silo,
+                user_name.as_deref(),
+                offset,
+                limit,
+            )
+            .await?;
+        let resources: Vec<ScimUser> =
+            users.into_iter().map(ScimUser::from).collect();
+        build_scim_response(
+            StatusCode::OK,
+            &ScimListResponse {
+                schemas: [SCIM_SCHEMA_LIST_RESPONSE],
+                total_results: total,
+                start_index: offset + 1,
+                items_per_page: resources.len(),
+                resources,
+            },
+        )
+    };
+    scim_result(&rqctx, handler.await)
+}
+
+/// Fetch a user
+#[endpoint {
+    method = GET,
+    path = "/scim/v2/Users/{user_id}",
+    unpublished = true,
+}]
+pub(crate) async fn scim_user_view(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<ScimUserPath>,
+) -> Result<Response<Body>, HttpError> {
+    let handler = async {
+        let nexus = &rqctx.context().nexus;
+        let (opctx, authz_silo) = scim_authn(&rqctx).await?;
+        let path = path_params.into_inner();
+        let user =
+            nexus.scim_user_fetch(opctx, &authz_silo, path.user_id).await?;
+        build_scim_response(StatusCode::OK, &ScimUser::from(user))
+    };
+    scim_result(&rqctx, handler.await)
+}
+
+/// Returns the `userName` and `active` attributes of a user resource
+fn parse_user(body: UntypedBody) -> Result<(String, bool), HttpError> {
+    let body = parse_body(body)?;
+    let user_name = attribute(&body, "userName")
+        .map(|value| string_value("userName", value))
+        .transpose()?
+        .ok_or_else(|| {
+            scim_bad_request(
+                "invalidValue",
+                String::from("\"userName\" is required"),
+            )
+        })?;
+    let active = attribute(&body, "active")
+        .map(|value| bool_value("active", value))
+        .transpose()?
+        .unwrap_or(true);
+    Ok((user_name, active))
+}
+
+/// Create a user
+#[endpoint {
+    method = POST,
+    path = "/scim/v2/Users",
+    unpublished = true,
+}]
+pub(crate) async fn scim_user_create(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    body: UntypedBody,
+) -> Result<Response<Body>, HttpError> {
+    let handler = async {
+        let nexus = &rqctx.context().nexus;
+        let (opctx, authz_silo) = scim_authn(&rqctx).await?;
+        let (user_name, active) = parse_user(body)?;
+        let user = nexus
+            .scim_user_create(opctx, &authz_silo, user_name, active)
+            .await?;
+        build_scim_response(StatusCode::CREATED, &ScimUser::from(user))
+    };
+    scim_audited_result(&rqctx, "scim_user_create", handler).await
+}
+
+/// Replace a user
+#[endpoint {
+    method = PUT,
+    path = "/scim/v2/Users/{user_id}",
+    unpublished = true,
+}]
+pub(crate) async fn scim_user_replace(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<ScimUserPath>,
+    body: UntypedBody,
+) -> Result<Response<Body>, HttpError> {
+    let handler = async {
+        let nexus = &rqctx.context().nexus;
+        let (opctx, authz_silo) = scim_authn(&rqctx).await?;
+        let path = path_params.into_inner();
+        let (user_name, active) = parse_user(body)?;
+        let user = nexus
+            .scim_user_update(
+                opctx,
+                &authz_silo,
+                path.user_id,
+                user_name,
+                active,
+            )
+            .await?;
+        build_scim_response(StatusCode::OK, &ScimUser::from(user))
+    };
+    scim_audited_result(&rqctx, "scim_user_replace", handler).await
+}
+
+/// Modify a user
+///
+/// This is how identity providers usually deactivate users.
+#[endpoint {
+    method = PATCH,
+    path = "/scim/v2/Users/{user_id}",
+    unpublished = true,
+}]
+pub(crate) async fn scim_user_patch(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<ScimUserPath>,
+    body: UntypedBody,
+) -> Result<Response<Body>, HttpError> {
+    let handler = async {
+        let nexus = &rqctx.context().nexus;
+        let (opctx, authz_silo) = scim_authn(&rqctx).await?;
+        let path = path_params.into_inner();
+        let operations = parse_patch(body)?;
+        let user =
+            nexus.scim_user_fetch(opctx, &authz_silo, path.user_id).await?;
+
+        let mut user_name = user.external_id.clone();
+        let mut active = user.active;
+        for operation in operations {
+            if operation.op == PatchOp::Remove {
+                // Neither of the attributes that we store may be removed (RFC
+                // 7644 section 3.5.2.2).  Removing any other attribute changes
+                // nothing, since we don't store it.
+                let path = operation.path.ok_or_else(|| {
+                    scim_bad_request(
+                        "noTarget",
+                        String::from("remove operation requires a path"),
+                    )
+                })?;
+                if path.eq_ignore_ascii_case("userName")
+                    || path.eq_ignore_ascii_case("active")
+                {
+                    return Err(scim_bad_request(
+                        "mutability",
+                        format!("attribute {:?} may not be removed", path),
+                    ));
+                }
+                continue;
+            }
+            let value = operation.value.ok_or_else(|| {
+                scim_bad_request(
+                    "invalidSyntax",
+                    String::from("PATCH operation has no \"value\""),
+                )
+            })?;
+
+            // Without a path, the value holds the attributes to change.
+            let changes = match operation.path {
+                Some(path) => vec![(path, value)],
+                None => value
+                    .as_object()
+                    .ok_or_else(|| {
+                        scim_bad_request(
+                            "invalidSyntax",
+                            String::from("PATCH value must be an object"),
+                        )
+                    })?
+                    .clone()
+                    .into_iter()
+                    .collect(),
+            };
+            for (path, value) in changes {
+                if path.eq_ignore_ascii_case("userName") {
+                    user_name = string_value("userName", &value)?;
+                } else if path.eq_ignore_ascii_case("active") {
+                    active = bool_value("active", &value)?;
+                }
+            }
+        }
+
+        let user = if user_name != user.external_id || active != user.active {
+            nexus
+                .scim_user_update(
+                    opctx,
+                    &authz_silo,
+                    path.user_id,
+                    user_name,
+                    active,
+                )
+                .await?
+        } else {
+            user
+        };
+        build_scim_response(StatusCode::OK, &ScimUser::from(user))
+    };
+    scim_audited_result(&rqctx, "scim_user_patch", handler).await
+}
+
+/// Delete a user
+#[endpoint {
+    method = DELETE,
+    path = "/scim/v2/Users/{user_id}",
+    unpublished = true,
+}]
+pub(crate) async fn scim_user_delete(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<ScimUserPath>,
+) -> Result<Response<Body>, HttpError> {
+    let handler = async {
+        let nexus = &rqctx.context().nexus;
+        let (opctx, authz_silo) = scim_authn(&rqctx).await?;
+        let path = path_params.into_inner();
+        nexus.scim_user_delete(opctx, &authz_silo, path.user_id).await?;
+        build_scim_empty_response()
+    };
+    scim_audited_result(&rqctx, "scim_user_delete", handler).await
+}
+
+// Groups
+
+/// List groups, optionally filtered by `displayName`
+#[endpoint {
+    method = GET,
+    path = "/scim/v2/Groups",
+    unpublished = true,
+}]
+pub(crate) async fn scim_group_list(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    query_params: Query<ScimListQuery>,
+) -> Result<Response<Body>, HttpError> {
+    let handler = async {
+        let nexus = &rqctx.context().nexus;
+        let (opctx, authz_silo) = scim_authn(&rqctx).await?;
+        let query = query_params.into_inner();
+        let display_name = query.equality_filter("displayName")?;
+        let (offset, limit) = query.offset_limit();
+        let (total, groups) = nexus
+            .scim_groups_list(
+                opctx,
+                &authz_silo,
+                display_name.as_deref(),
+                offset,
+                limit,
+            )
+            .await?;
+        let resources: Vec<ScimGroup> =
+            groups.into_iter().map(ScimGroup::from).collect();
+        build_scim_response(
+            StatusCode::OK,
+            &ScimListResponse {
+                schemas: [SCIM_SCHEMA_LIST_RESPONSE],
+                total_results: total,
+                start_index: offset + 1,
+                items_per_page: resources.len(),
+                resources,
+            },
+        )
+    };
+    scim_result(&rqctx, handler.await)
+}
+
+/// Fetch a group
+#[endpoint {
+    method = GET,
+    path = "/scim/v2/Groups/{group_id}",
+    unpublished = true,
+}]
+pub(crate) async fn scim_group_view(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<ScimGroupPath>,
+) -> Result<Response<Body>, HttpError> {
+    let handler = async {
+        let nexus = &rqctx.context().nexus;
+        let (opctx, authz_silo) = scim_authn(&rqctx).await?;
+        let path = path_params.into_inner();
+        let group =
+            nexus.scim_group_fetch(opctx, &authz_silo, path.group_id).await?;
+        build_scim_response(StatusCode::OK, &ScimGroup::from(group))
+    };
+    scim_result(&rqctx, handler.await)
+}
+
+/// Returns the `displayName` and `members` attributes of a group resource
+fn parse_group(body: UntypedBody) -> Result<(String, Vec<Uuid>), HttpError> {
+    let body = parse_body(body)?;
+    let display_name = attribute(&body, "displayName")
+        .map(|value| string_value("displayName", value))
+        .transpose()?
+        .ok_or_else(|| {
+            scim_bad_request(
+                "invalidValue",
+                String::from("\"displayName\" is required"),
+            )
+        })?;
+    let members = attribute(&body, "members")
+        .map(members_value)
+        .transpose()?
+        .unwrap_or_default();
+    Ok((display_name, members))
+}
+
+/// Create a group
+#[endpoint {
+    method = POST,
+    path = "/scim/v2/Groups",
+    unpublished = true,
+}]
+pub(crate) async fn scim_group_create(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    body: UntypedBody,
+) -> Result<Response<Body>, HttpError> {
+    let handler = async {
+        let nexus = &rqctx.context().nexus;
+        let (opctx, authz_silo) = scim_authn(&rqctx).await?;
+        let (display_name, members) = parse_group(body)?;
+        let group = nexus
+            .scim_group_create(opctx, &authz_silo, display_name, members)
+            .await?;
+        build_scim_response(StatusCode::CREATED, &ScimGroup::from(group))
+    };
+    scim_audited_result(&rqctx, "scim_group_create", handler).await
+}
+
+/// Replace a group
+#[endpoint {
+    method = PUT,
+    path = "/scim/v2/Groups/{group_id}",
+    unpublished = true,
+}]
+pub(crate) async fn scim_group_replace(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<ScimGroupPath>,
+    body: UntypedBody,
+) -> Result<Response<Body>, HttpError> {
+    let handler = async {
+        let nexus = &rqctx.context().nexus;
+        let (opctx, authz_silo) = scim_authn(&rqctx).await?;
+        let path = path_params.into_inner();
+        let (display_name, members) = parse_group(body)?;
+        let group = nexus
+            .scim_group_update(
+                opctx,
+                &authz_silo,
+                path.group_id,
+                display_name,
+                members,
+            )
+            .await?;
+        build_scim_response(StatusCode::OK, &ScimGroup::from(group))
+    };
+    scim_audited_result(&rqctx, "scim_group_replace", handler).await
+}
+
+/// Parse a path selecting a single group member, as in
+/// `members[value eq "..."]`
+fn parse_member_path(path: &str) -> Option<Uuid> {
+    let filter = path
+        .get(.."members[".len())
+        .filter(|prefix| prefix.eq_ignore_ascii_case("members["))
+        .and_then(|_| path["members[".len()..].strip_suffix(']'))?;
+    match parse_equality_filter(filter) {
+        Some((attribute, value)) if attribute.eq_ignore_ascii_case("value") => {
+            value.parse().ok()
+        }
+        _ => None,
+    }
+}
+
+/// Modify a group
+///
+/// This is how identity providers usually add and remove members.
+#[endpoint {
+    method = PATCH,
+    path = "/scim/v2/Groups/{group_id}",
+    unpublished = true,
+}]
+pub(crate) async fn scim_group_patch(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<ScimGroupPath>,
+    body: UntypedBody,
+) -> Result<Response<Body>, HttpError> {
+    let handler = async {
+        let nexus = &rqctx.context().nexus;
+        let (opctx, authz_silo) = scim_authn(&rqctx).await?;
+        let path = path_params.into_inner();
+        let operations = parse_patch(body)?;
+        let (group, current_members) =
+            nexus.scim_group_fetch(opctx, &authz_silo, path.group_id).await?;
+
+        let mut display_name = group.external_id.clone();
+        let mut members: BTreeSet<Uuid> =
+            current_members.iter().copied().collect();
+        for operation in operations {
+            let path = operation.path.as_deref();
+            match (operation.op, path) {
+                // Remove all members, some of them, or one of them
+                (PatchOp::Remove, Some(path))
+                    if path.eq_ignore_ascii_case("members") =>
+                {
+                    match &operation.value {
+                        None => members.clear(),
+                        Some(value) => {
+                            for member in members_value(value)? {
+                                members.remove(&member);
+                            }
+                        }
+                    }
+                }
+                (PatchOp::Remove, Some(path)) => {
+                    let member = parse_member_path(path).ok_or_else(|| {
+                        scim_bad_request(
+                            "invalidPath",
+                            format!("unsupported path {:?}", path),
+                        )
+                    })?;
+                    members.remove(&member);
+                }
+                (PatchOp::Remove, None) => {
+                    return Err(scim_bad_request(
+                        "noTarget",
+                        String::from("remove operation requires a path"),
+                    ));
+                }
+
+                (op, path) => {
+                    let value = operation.value.as_ref().ok_or_else(|| {
+                        scim_bad_request(
+                            "invalidSyntax",
+                            String::from("PATCH operation has no \"value\""),
+                        )
+                    })?;
+
+                    // Without a path, the value holds the attributes to
+                    // change.
+                    let changes: Vec<(&str, &Value)> = match path {
+                        Some(path) => vec![(path, value)],
+                        None => value
+                            .as_object()
+                            .ok_or_else(|| {
+                                scim_bad_request(
+                                    "invalidSyntax",
+                                    String::from(
+                                        "PATCH value must be an object",
+                                    ),
+                                )
+                            })?
+                            .iter()
+                            .map(|(path, value)| (path.as_str(), value))
+                            .collect(),
+                    };
+                    for (path, value) in changes {
+                        if path.eq_ignore_ascii_case("displayName") {
+                            display_name = string_value("displayName", value)?;
+                        } else if path.eq_ignore_ascii_case("members") {
+                            if op == PatchOp::Replace {
+                                members.clear();
+                            }
+                            members.extend(members_value(value)?);
+                        }
+                    }
+                }
+            }
+        }
+
+        let members: Vec<Uuid> = members.into_iter().collect();
+        let group = if display_name != group.external_id
+            || members != current_members
+        {
+            nexus
+                .scim_group_update(
+                    opctx,
+                    &authz_silo,
+                    path.group_id,
+                    display_name,
+                    members,
+                )
+                .await?
+        } else {
+            (group, current_members)
+        };
+        build_scim_response(StatusCode::OK, &ScimGroup::from(group))
+    };
+    scim_audited_result(&rqctx, "scim_group_patch", handler).await
+}
+
+/// Delete a group
+#[endpoint {
+    method = DELETE,
+    path = "/scim/v2/Groups/{group_id}",
+    unpublished = true,
+}]
+pub(crate) async fn scim_group_delete(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<ScimGroupPath>,
+) -> Result<Response<Body>, HttpError> {
+    let handler = async {
+        let nexus = &rqctx.context().nexus;
+        let (opctx, authz_silo) = scim_authn(&rqctx).await?;
+        let path = path_params.into_inner();
+        nexus.scim_group_delete(opctx, &authz_silo, path.group_id).await?;
+        build_scim_empty_response()
+    };
+    scim_audited_result(&rqctx, "scim_group_delete", handler).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_equality_filter;
+    use super::parse_member_path;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_parse_equality_filter() {
+        assert_eq!(
+            parse_equality_filter(r#"userName eq "some@customer.com""#),
+            Some(("userName".to_string(), "some@customer.com".to_string()))
+        );
+        assert_eq!(
+            parse_equality_filter(r#"displayName EQ "SRE \"on call\"""#),
+            Some(("displayName".to_string(), "SRE \"on call\"".to_string()))
+        );
+        assert_eq!(parse_equality_filter(r#"userName sw "some""#), None);
+        assert_eq!(parse_equality_filter("userName eq some"), None);
+        assert_eq!(parse_equality_filter("userName"), None);
+    }
+
+    #[test]
+    fn test_parse_member_path() {
+        let id = Uuid::new_v4();
+        assert_eq!(
+            parse_member_path(&format!("members[value eq \"{}\"]", id)),
+            Some(id)
+        );
+        assert_eq!(
+            parse_member_path(&format!("Members[Value eq \"{}\"]", id)),
+            Some(id)
+        );
+        assert_eq!(parse_member_path("members"), None);
+        assert_eq!(parse_member_path("displayName"), None);
+    }
+}
@@ -36,6 +36,8 @@ use omicron_common::api::external::http_pagination::ScanParams;
 use omicron_common::api::internal::nexus::DiskRuntimeState;
 use omicron_common::api::internal::nexus::InstanceRuntimeState;
 use omicron_common::api::internal::nexus::ProducerEndpoint;
+use omicron_common::api::internal::nexus::PushProducerAssignment;
+use omicron_common::api::internal::nexus::PushProducerInfo;
 use omicron_common::api::internal::nexus::UpdateArtifactId;
 use oximeter::types::ProducerResults;
 use oximeter_producer::{collect, ProducerIdPathParams};
@@ -61,6 +63,7 @@ pub(crate) fn internal_api() -> NexusApiDescription {
         api.register(cpapi_volume_remove_read_only_parent)?;
         api.register(cpapi_disk_remove_read_only_parent)?;
         api.register(cpapi_producers_post)?;
+        api.register(cpapi_push_producers_post)?;
         api.register(cpapi_collectors_post)?;
         api.register(cpapi_metrics_collect)?;
         api.register(cpapi_artifact_download)?;
@@ -375,6 +378,32 @@ async fn cpapi_producers_post(
         .await
 }
 
+/// Accept a registration from a metric producer that pushes its samples.
+///
+/// The response names the collector to which the producer should push. The
+/// registration expires after an hour, so producers that run longer than that
+/// must register again.
+#[endpoint {
+     method = POST,
+     path = "/metrics/push-producers",
+ }]
+async fn cpapi_push_producers_post(
+    request_context: RequestContext<Arc<ServerContext>>,
+    producer_info: TypedBody<PushProducerInfo>,
+) -> Result<HttpResponseOk<PushProducerAssignment>, HttpError> {
+    let context = request_context.context();
+    let nexus = &context.nexus;
+    let producer_info = producer_info.into_inner();
+    let handler = async {
+        let assignment = nexus.assign_push_producer(producer_info).await?;
+        Ok(HttpResponseOk(assignment))
+    };
+    context
+        .internal_latencies
+        .instrument_dropshot_handler(&request_context, handler)
+        .await
+}
+
 /// Accept a notification of a new oximeter collection server.
 #[endpoint {
      method = POST,
@@ -838,10 +838,13 @@ pub async fn start_oximeter(
         address: Some(SocketAddr::new(Ipv6Addr::LOCALHOST.into(), db_port)),
         batch_size: 10,
         batch_interval: 1,
+        retention: vec![],
     };
     let config = oximeter_collector::Config {
         nexus_address: Some(nexus_address),
         db,
+        spool: None,
+        prometheus: None,
         log: ConfigLogging::StderrTerminal { level: ConfigLoggingLevel::Error },
     };
     let args = oximeter_collector::OximeterArguments {
@@ -985,6 +988,9 @@ pub async fn start_dns_server(
         store,
         &dns_server::dns_server::Config {
             bind_address: "[::1]:0".parse().unwrap(),
+            transfer_peers: vec![],
+            notify_secondaries: vec![],
+            rate_limit: Default::default(),
         },
         &dropshot::ConfigDropshot {
             bind_address: "[::1]:0".parse().unwrap(),
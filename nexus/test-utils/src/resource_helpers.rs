@@ -386,6 +386,7 @@ pub async fn create_instance_with(
             network_interfaces: nics.clone(),
             external_ips,
             disks,
+            affinity_groups: vec![],
             start: true,
         },
     )
@@ -0,0 +1,451 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests affinity groups and their effect on instance placement
+
+use crate::integration_tests::instances::instance_simulate;
+use dropshot::test_util::ClientTestContext;
+use dropshot::HttpErrorResponseBody;
+use dropshot::ResultsPage;
+use http::method::Method;
+use http::StatusCode;
+use nexus_test_utils::http_testing::AuthnMode;
+use nexus_test_utils::http_testing::NexusRequest;
+use nexus_test_utils::http_testing::RequestBuilder;
+use nexus_test_utils::http_testing::TestResponse;
+use nexus_test_utils::resource_helpers::create_project;
+use nexus_test_utils::resource_helpers::object_create;
+use nexus_test_utils::resource_helpers::populate_ip_pool;
+use nexus_test_utils_macros::nexus_test;
+use nexus_types::external_api::params;
+use nexus_types::external_api::shared::AffinityGroupKind;
+use nexus_types::external_api::shared::AffinityPolicy;
+use nexus_types::external_api::views::AffinityGroup;
+use omicron_common::api::external::ByteCount;
+use omicron_common::api::external::IdentityMetadataCreateParams;
+use omicron_common::api::external::Instance;
+use omicron_common::api::external::InstanceCpuCount;
+use omicron_common::api::external::InstanceState;
+use omicron_common::api::external::NameOrId;
+
+type ControlPlaneTestContext =
+    nexus_test_utils::ControlPlaneTestContext<omicron_nexus::Server>;
+
+const PROJECT_NAME: &str = "replicated-db";
+
+fn get_affinity_groups_url() -> String {
+    format!("/v1/affinity-groups?project={PROJECT_NAME}")
+}
+
+fn get_affinity_group_url(group_name: &str) -> String {
+    format!("/v1/affinity-groups/{group_name}?project={PROJECT_NAME}")
+}
+
+async fn affinity_group_create(
+    client: &ClientTestContext,
+    group_name: &str,
+    kind: AffinityGroupKind,
+    policy: AffinityPolicy,
+) -> AffinityGroup {
+    object_create(
+        client,
+        &get_affinity_groups_url(),
+        &params::AffinityGroupCreate {
+            identity: IdentityMetadataCreateParams {
+                name: group_name.parse().unwrap(),
+                description: String::from("an affinity group"),
+            },
+            kind,
+            policy,
+        },
+    )
+    .await
+}
+
+async fn affinity_group_members(
+    client: &ClientTestContext,
+    group_name: &str,
+) -> Vec<String> {
+    NexusRequest::object_get(
+        client,
+        &format!(
+            "/v1/affinity-groups/{group_name}/members?project={PROJECT_NAME}"
+        ),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .expect("failed to list affinity group members")
+    .parsed_body::<ResultsPage<Instance>>()
+    .unwrap()
+    .items
+    .into_iter()
+    .map(|instance| instance.identity.name.to_string())
+    .collect()
+}
+
+async fn affinity_group_member_post(
+    client: &ClientTestContext,
+    group_name: &str,
+    action: &str,
+    instance_name: &str,
+    expected_status: StatusCode,
+) {
+    let url = format!(
+        "/v1/affinity-groups/{group_name}/members/{action}?project={PROJECT_NAME}"
+    );
+    let member = params::AffinityGroupMember {
+        instance: NameOrId::Name(instance_name.parse().unwrap()),
+    };
+    NexusRequest::new(
+        RequestBuilder::new(client, Method::POST, &url)
+            .body(Some(&member))
+            .expect_status(Some(expected_status)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+}
+
+fn instance_params(
+    instance_name: &str,
+    group_names: &[&str],
+) -> params::InstanceCreate {
+    params::InstanceCreate {
+        identity: IdentityMetadataCreateParams {
+            name: instance_name.parse().unwrap(),
+            description: format!("instance {:?}", instance_name),
+        },
+        ncpus: InstanceCpuCount(1),
+        memory: ByteCount::from_gibibytes_u32(1),
+        hostname: String::from("db"),
+        user_data: vec![],
+        network_interfaces: params::InstanceNetworkInterfaceAttachment::Default,
+        external_ips: vec![],
+        disks: vec![],
+        affinity_groups: group_names
+            .iter()
+            .map(|name| NameOrId::Name(name.parse().unwrap()))
+            .collect(),
+        start: true,
+    }
+}
+
+async fn instance_create(
+    client: &ClientTestContext,
+    instance_name: &str,
+    group_names: &[&str],
+) -> Instance {
+    object_create(
+        client,
+        &format!("/v1/instances?project={PROJECT_NAME}"),
+        &instance_params(instance_name, group_names),
+    )
+    .await
+}
+
+async fn instance_create_error(
+    client: &ClientTestContext,
+    instance_name: &str,
+    group_names: &[&str],
+    expected_status: StatusCode,
+) -> HttpErrorResponseBody {
+    NexusRequest::new(
+        RequestBuilder::new(
+            client,
+            Method::POST,
+            &format!("/v1/instances?project={PROJECT_NAME}"),
+        )
+        .body(Some(&instance_params(instance_name, group_names)))
+        .expect_status(Some(expected_status)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap()
+}
+
+async fn instance_post(
+    client: &ClientTestContext,
+    instance_name: &str,
+    action: &str,
+    expected_status: StatusCode,
+) -> TestResponse {
+    NexusRequest::new(
+        RequestBuilder::new(
+            client,
+            Method::POST,
+            &format!(
+                "/v1/instances/{instance_name}/{action}?project={PROJECT_NAME}"
+            ),
+        )
+        .body(None as Option<&serde_json::Value>)
+        .expect_status(Some(expected_status)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+}
+
+async fn instance_get(
+    client: &ClientTestContext,
+    instance_name: &str,
+) -> Instance {
+    NexusRequest::object_get(
+        client,
+        &format!("/v1/instances/{instance_name}?project={PROJECT_NAME}"),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap()
+}
+
+/// Optionally requests `action` on an instance, then lets the simulated sled
+/// agent finish the instance's pending state transition.
+async fn instance_simulate_post(
+    cptestctx: &ControlPlaneTestContext,
+    instance_name: &str,
+    action: Option<&str>,
+) {
+    let client = &cptestctx.external_client;
+    if let Some(action) = action {
+        instance_post(client, instance_name, action, StatusCode::ACCEPTED)
+            .await;
+    }
+    let instance = instance_get(client, instance_name).await;
+    let nexus = &cptestctx.server.apictx().nexus;
+    instance_simulate(nexus, &instance.identity.id).await;
+}
+
+#[nexus_test]
+async fn test_affinity_group_crud(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    let project = create_project(client, PROJECT_NAME).await;
+
+    let group = affinity_group_create(
+        client,
+        "replicas",
+        AffinityGroupKind::AntiAffinity,
+        AffinityPolicy::Fail,
+    )
+    .await;
+    assert_eq!(group.identity.name.as_str(), "replicas");
+    assert_eq!(group.project_id, project.identity.id);
+    assert_eq!(group.kind, AffinityGroupKind::AntiAffinity);
+    assert_eq!(group.policy, AffinityPolicy::Fail);
+
+    // Names are unique within the project.
+    let error: HttpErrorResponseBody = NexusRequest::new(
+        RequestBuilder::new(client, Method::POST, &get_affinity_groups_url())
+            .body(Some(&params::AffinityGroupCreate {
+                identity: IdentityMetadataCreateParams {
+                    name: "replicas".parse().unwrap(),
+                    description: String::from("another affinity group"),
+                },
+                kind: AffinityGroupKind::Affinity,
+                policy: AffinityPolicy::Allow,
+            }))
+            .expect_status(Some(StatusCode::BAD_REQUEST)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert_eq!(error.message, "already exists: affinity-group \"replicas\"");
+
+    // Groups can be fetched by ID without a project.
+    let fetched = NexusRequest::object_get(
+        client,
+        &format!("/v1/affinity-groups/{}", group.identity.id),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body::<AffinityGroup>()
+    .unwrap();
+    assert_eq!(fetched.identity.name, group.identity.name);
+
+    // A project can't be deleted while it contains affinity groups.
+    NexusRequest::expect_failure(
+        client,
+        StatusCode::BAD_REQUEST,
+        Method::DELETE,
+        &format!("/v1/projects/{PROJECT_NAME}"),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+
+    NexusRequest::object_delete(client, &get_affinity_group_url("replicas"))
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap();
+    NexusRequest::expect_failure(
+        client,
+        StatusCode::NOT_FOUND,
+        Method::GET,
+        &get_affinity_group_url("replicas"),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+    let groups = NexusRequest::object_get(client, &get_affinity_groups_url())
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap()
+        .parsed_body::<ResultsPage<AffinityGroup>>()
+        .unwrap()
+        .items;
+    assert!(groups.is_empty());
+}
+
+// The simulated control plane has a single sled, so an anti-affinity group
+// can hold at most one running instance when its rule is a hard one.
+#[nexus_test]
+async fn test_affinity_group_placement(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    populate_ip_pool(&client, "default", None).await;
+    create_project(client, PROJECT_NAME).await;
+
+    affinity_group_create(
+        client,
+        "replicas",
+        AffinityGroupKind::AntiAffinity,
+        AffinityPolicy::Fail,
+    )
+    .await;
+    affinity_group_create(
+        client,
+        "replicas-best-effort",
+        AffinityGroupKind::AntiAffinity,
+        AffinityPolicy::Allow,
+    )
+    .await;
+    affinity_group_create(
+        client,
+        "together",
+        AffinityGroupKind::Affinity,
+        AffinityPolicy::Fail,
+    )
+    .await;
+
+    // The first member of a hard anti-affinity group can be placed, but a
+    // second one can't, and the error names the group.
+    instance_create(client, "db-1", &["replicas", "together"]).await;
+    assert_eq!(affinity_group_members(client, "replicas").await, ["db-1"]);
+    let error = instance_create_error(
+        client,
+        "db-2",
+        &["replicas"],
+        StatusCode::CONFLICT,
+    )
+    .await;
+    assert!(
+        error.message.contains("anti-affinity group \"replicas\""),
+        "{}",
+        error.message
+    );
+    assert_eq!(affinity_group_members(client, "replicas").await, ["db-1"]);
+
+    // Best-effort groups place their members anyway, and affinity groups are
+    // satisfied by sharing the sled.
+    instance_create(client, "db-2", &["replicas-best-effort", "together"])
+        .await;
+    instance_create(client, "db-3", &["replicas-best-effort"]).await;
+    assert_eq!(
+        affinity_group_members(client, "replicas-best-effort").await,
+        ["db-2", "db-3"]
+    );
+    assert_eq!(
+        affinity_group_members(client, "together").await,
+        ["db-1", "db-2"]
+    );
+
+    // Groups must exist in the instance's project.
+    instance_create_error(
+        client,
+        "db-4",
+        &["no-such-group"],
+        StatusCode::NOT_FOUND,
+    )
+    .await;
+
+    // Existing instances can't join a hard anti-affinity group if they share
+    // a sled with one of its members...
+    affinity_group_member_post(
+        client,
+        "replicas",
+        "add",
+        "db-2",
+        StatusCode::BAD_REQUEST,
+    )
+    .await;
+
+    // ...but can once that member leaves.
+    affinity_group_member_post(
+        client,
+        "replicas",
+        "remove",
+        "db-1",
+        StatusCode::NO_CONTENT,
+    )
+    .await;
+    affinity_group_member_post(
+        client,
+        "replicas",
+        "remove",
+        "db-1",
+        StatusCode::BAD_REQUEST,
+    )
+    .await;
+    affinity_group_member_post(
+        client,
+        "replicas",
+        "add",
+        "db-2",
+        StatusCode::NO_CONTENT,
+    )
+    .await;
+    assert_eq!(affinity_group_members(client, "replicas").await, ["db-2"]);
+
+    // A stopped member doesn't occupy its sled, so another member can be
+    // placed there...
+    instance_simulate_post(cptestctx, "db-2", None).await;
+    instance_simulate_post(cptestctx, "db-2", Some("stop")).await;
+    instance_create(client, "db-5", &["replicas"]).await;
+    assert_eq!(
+        affinity_group_members(client, "replicas").await,
+        ["db-2", "db-5"]
+    );
+
+    // ...but the stopped member can't start again alongside it.
+    let error: HttpErrorResponseBody =
+        instance_post(client, "db-2", "start", StatusCode::CONFLICT)
+            .await
+            .parsed_body()
+            .unwrap();
+    assert!(
+        error.message.contains("anti-affinity group \"replicas\""),
+        "{}",
+        error.message
+    );
+    assert_eq!(
+        instance_get(client, "db-2").await.runtime.run_state,
+        InstanceState::Stopped
+    );
+}
@@ -0,0 +1,274 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests for the audit log of external API requests
+
+use chrono::DateTime;
+use chrono::Utc;
+use dropshot::test_util::ClientTestContext;
+use dropshot::ResultsPage;
+use http::method::Method;
+use http::StatusCode;
+use nexus_db_queries::authn::USER_TEST_PRIVILEGED;
+use nexus_db_queries::db::identity::Asset;
+use nexus_test_utils::http_testing::AuthnMode;
+use nexus_test_utils::http_testing::NexusRequest;
+use nexus_test_utils::http_testing::RequestBuilder;
+use nexus_test_utils::resource_helpers::create_project;
+use nexus_test_utils::resource_helpers::create_silo;
+use nexus_test_utils::TEST_SUITE_PASSWORD;
+use nexus_test_utils_macros::nexus_test;
+use nexus_types::external_api::shared;
+use nexus_types::external_api::views;
+use nexus_types::external_api::views::DeviceAuthResponse;
+use omicron_nexus::external_api::device_auth::DeviceAccessTokenRequest;
+use omicron_nexus::external_api::device_auth::DeviceAuthRequest;
+use uuid::Uuid;
+
+type ControlPlaneTestContext =
+    nexus_test_utils::ControlPlaneTestContext<omicron_nexus::Server>;
+
+async fn audit_log_list(
+    client: &ClientTestContext,
+    start_time: DateTime<Utc>,
+) -> Vec<views::AuditLogEntry> {
+    NexusRequest::object_get(
+        client,
+        &format!("/v1/system/audit-log?start_time={:?}", start_time),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .expect("failed to list audit log")
+    .parsed_body::<ResultsPage<views::AuditLogEntry>>()
+    .unwrap()
+    .items
+}
+
+#[nexus_test]
+async fn test_audit_log(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    let start_time = Utc::now();
+
+    assert!(audit_log_list(client, start_time).await.is_empty());
+
+    // Mutating requests are recorded along with the user that made them.
+    create_project(client, "audited-project").await;
+    let entries = audit_log_list(client, start_time).await;
+    assert_eq!(entries.len(), 1);
+    let entry = &entries[0];
+    assert_eq!(entry.operation_id, "project_create");
+    assert_eq!(entry.http_method, "POST");
+    assert_eq!(entry.request_uri, "/v1/projects");
+    assert_eq!(entry.actor_id, Some(USER_TEST_PRIVILEGED.id()));
+    assert_eq!(entry.actor_silo_id, Some(USER_TEST_PRIVILEGED.silo_id));
+    assert_eq!(entry.http_status_code, Some(201));
+    assert!(entry.time_completed.is_some());
+
+    // Requests that only read state are not.
+    NexusRequest::object_get(client, "/v1/projects/audited-project")
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .expect("failed to fetch project");
+    assert_eq!(audit_log_list(client, start_time).await.len(), 1);
+
+    // Failed requests are recorded with their status code.
+    NexusRequest::expect_failure_with_body(
+        client,
+        StatusCode::BAD_REQUEST,
+        Method::POST,
+        "/v1/projects",
+        &serde_json::json!({ "name": "audited-project", "description": "" }),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .expect("expected project creation to fail");
+    let entries = audit_log_list(client, start_time).await;
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[1].operation_id, "project_create");
+    assert_eq!(entries[1].http_status_code, Some(400));
+
+    // Entries can be restricted to a time range.
+    let entries = NexusRequest::object_get(
+        client,
+        &format!(
+            "/v1/system/audit-log?start_time={:?}&end_time={:?}",
+            start_time, entries[1].time_started,
+        ),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .expect("failed to list audit log")
+    .parsed_body::<ResultsPage<views::AuditLogEntry>>()
+    .unwrap()
+    .items;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].operation_id, "project_create");
+    assert_eq!(entries[0].http_status_code, Some(201));
+
+    // Only fleet viewers can see the audit log.
+    NexusRequest::expect_failure(
+        client,
+        StatusCode::FORBIDDEN,
+        Method::GET,
+        &format!("/v1/system/audit-log?start_time={:?}", start_time),
+    )
+    .authn_as(AuthnMode::UnprivilegedUser)
+    .execute()
+    .await
+    .expect("expected audit log listing to fail");
+}
+
+#[nexus_test]
+async fn test_audit_log_unauthenticated(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    let start_time = Utc::now();
+
+    // Requests that fail to authenticate are recorded without an actor.
+    NexusRequest::expect_failure_with_body(
+        client,
+        StatusCode::UNAUTHORIZED,
+        Method::POST,
+        "/v1/projects",
+        &serde_json::json!({ "name": "unaudited", "description": "" }),
+    )
+    .execute()
+    .await
+    .expect("expected unauthenticated project creation to fail");
+    let entries = audit_log_list(client, start_time).await;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].operation_id, "project_create");
+    assert_eq!(entries[0].actor_id, None);
+    assert_eq!(entries[0].actor_silo_id, None);
+    assert_eq!(entries[0].http_status_code, Some(401));
+
+    // So are failed logins.  Only the path of each request is recorded, since
+    // query strings may carry secrets.
+    NexusRequest::expect_failure_with_body(
+        client,
+        StatusCode::UNAUTHORIZED,
+        Method::POST,
+        &format!("/v1/login/{}/local?state=secret", cptestctx.silo_name),
+        &serde_json::json!({
+            "username": "nobody",
+            "password": "not-a-password",
+        }),
+    )
+    .execute()
+    .await
+    .expect("expected login to fail");
+    let entries = audit_log_list(client, start_time).await;
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[1].operation_id, "login_local");
+    assert_eq!(
+        entries[1].request_uri,
+        format!("/v1/login/{}/local", cptestctx.silo_name)
+    );
+    assert_eq!(entries[1].actor_id, None);
+    assert_eq!(entries[1].http_status_code, Some(401));
+
+    // Successful logins are recorded along with the user who logged in.
+    RequestBuilder::new(
+        client,
+        Method::POST,
+        &format!("/v1/login/{}/local", cptestctx.silo_name),
+    )
+    .body(Some(&serde_json::json!({
+        "username": cptestctx.user_name,
+        "password": TEST_SUITE_PASSWORD,
+    })))
+    .expect_status(Some(StatusCode::NO_CONTENT))
+    .execute()
+    .await
+    .expect("failed to log in");
+    let entries = audit_log_list(client, start_time).await;
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries[2].operation_id, "login_local");
+    assert!(entries[2].actor_id.is_some());
+    assert!(entries[2].actor_silo_id.is_some());
+    assert_eq!(entries[2].http_status_code, Some(204));
+
+    // Polls for device access tokens, which may issue credentials, are
+    // recorded too.
+    let client_id = Uuid::new_v4();
+    let auth_response: DeviceAuthResponse =
+        RequestBuilder::new(client, Method::POST, "/device/auth")
+            .allow_non_dropshot_errors()
+            .body_urlencoded(Some(&DeviceAuthRequest { client_id }))
+            .expect_status(Some(StatusCode::OK))
+            .execute()
+            .await
+            .expect("failed to start device authorization")
+            .parsed_body()
+            .unwrap();
+    RequestBuilder::new(client, Method::POST, "/device/token")
+        .allow_non_dropshot_errors()
+        .body_urlencoded(Some(&DeviceAccessTokenRequest {
+            grant_type: "urn:ietf:params:oauth:grant-type:device_code"
+                .to_string(),
+            device_code: auth_response.device_code,
+            client_id,
+        }))
+        .expect_status(Some(StatusCode::BAD_REQUEST))
+        .execute()
+        .await
+        .expect("expected pending device access token");
+    let entries = audit_log_list(client, start_time).await;
+    assert_eq!(entries.len(), 4);
+    assert_eq!(entries[3].operation_id, "device_access_token");
+    assert_eq!(entries[3].actor_id, None);
+    assert_eq!(entries[3].http_status_code, Some(400));
+}
+
+#[nexus_test]
+async fn test_audit_log_scim(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+
+    let silo = create_silo(
+        client,
+        "scim-silo",
+        true,
+        shared::SiloIdentityMode::SamlJit,
+    )
+    .await;
+    let token: views::ScimClientBearerTokenCreated =
+        NexusRequest::objects_post(
+            client,
+            "/v1/system/scim/tokens?silo=scim-silo",
+            &serde_json::Value::Null,
+        )
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .expect("failed to create SCIM token")
+        .parsed_body()
+        .unwrap();
+    let start_time = Utc::now();
+
+    // Changes made by a Silo's SCIM client are recorded against the Silo.
+    RequestBuilder::new(client, Method::POST, "/scim/v2/Users")
+        .header(
+            http::header::AUTHORIZATION,
+            format!("Bearer {}", token.bearer_token),
+        )
+        .body(Some(&serde_json::json!({
+            "schemas": ["urn:ietf:params:scim:schemas:core:2.0:User"],
+            "userName": "scim-user",
+            "active": true,
+        })))
+        .expect_status(Some(StatusCode::CREATED))
+        .allow_non_dropshot_errors()
+        .execute()
+        .await
+        .expect("failed to create SCIM user");
+    let entries = audit_log_list(client, start_time).await;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].operation_id, "scim_user_create");
+    assert_eq!(entries[0].actor_id, None);
+    assert_eq!(entries[0].actor_silo_id, Some(silo.identity.id));
+    assert_eq!(entries[0].http_status_code, Some(201));
+}
@@ -2,15 +2,23 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use nexus_test_utils::http_testing::{AuthnMode, NexusRequest, RequestBuilder};
+use nexus_db_queries::authn::USER_TEST_UNPRIVILEGED;
+use nexus_test_utils::http_testing::{
+    AuthnMode, NexusRequest, RequestBuilder, TestResponse,
+};
+use nexus_test_utils::resource_helpers::objects_list_page_authz;
 use nexus_test_utils_macros::nexus_test;
+use nexus_types::external_api::params;
 use nexus_types::external_api::views::{
-    DeviceAccessTokenGrant, DeviceAccessTokenType, DeviceAuthResponse,
+    AccessToken, AccessTokenCreated, CurrentUser, DeviceAccessTokenGrant,
+    DeviceAccessTokenType, DeviceAuthResponse,
 };
 use omicron_nexus::external_api::device_auth::{
     DeviceAccessTokenRequest, DeviceAuthRequest, DeviceAuthVerify,
 };
+use std::num::NonZeroU32;
 
+use dropshot::HttpErrorResponseBody;
 use http::{header, method::Method, StatusCode};
 use serde::Deserialize;
 use uuid::Uuid;
@@ -147,3 +155,154 @@ async fn test_device_auth_flow(cptestctx: &ControlPlaneTestContext) {
     assert_eq!(token.access_token.len(), 52);
     assert!(token.access_token.starts_with("oxide-token-"));
 }
+
+async fn access_token_create(
+    testctx: &dropshot::test_util::ClientTestContext,
+    params: &params::AccessTokenCreate,
+) -> AccessTokenCreated {
+    NexusRequest::objects_post(testctx, "/v1/me/access-tokens", params)
+        .authn_as(AuthnMode::UnprivilegedUser)
+        .execute()
+        .await
+        .expect("failed to create access token")
+        .parsed_body()
+        .expect("failed to deserialize access token")
+}
+
+async fn whoami_with_token(
+    testctx: &dropshot::test_util::ClientTestContext,
+    token: &str,
+    expected_status: StatusCode,
+) -> TestResponse {
+    RequestBuilder::new(testctx, Method::GET, "/v1/me")
+        .header(header::AUTHORIZATION, format!("Bearer {}", token))
+        .expect_status(Some(expected_status))
+        .execute()
+        .await
+        .expect("failed to make request with access token")
+}
+
+#[nexus_test]
+async fn test_personal_access_tokens(cptestctx: &ControlPlaneTestContext) {
+    let testctx = &cptestctx.external_client;
+
+    let created = access_token_create(
+        testctx,
+        &params::AccessTokenCreate {
+            description: String::from("for scripts"),
+            ttl_seconds: None,
+        },
+    )
+    .await;
+    assert_eq!(created.description, "for scripts");
+    assert_eq!(created.time_expires, None);
+    assert!(created.access_token.starts_with("oxide-token-"));
+
+    // The token authenticates as the user that created it.
+    let me: CurrentUser =
+        whoami_with_token(testctx, &created.access_token, StatusCode::OK)
+            .await
+            .parsed_body()
+            .unwrap();
+    assert_eq!(me.user.id, USER_TEST_UNPRIVILEGED.id());
+
+    // Listing tokens shows the token, but never its secret.
+    let tokens = NexusRequest::object_get(testctx, "/v1/me/access-tokens")
+        .authn_as(AuthnMode::UnprivilegedUser)
+        .execute()
+        .await
+        .expect("failed to list access tokens")
+        .parsed_body::<serde_json::Value>()
+        .unwrap();
+    let items = tokens["items"].as_array().unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0]["id"], created.id.to_string());
+    assert!(items[0].get("access_token").is_none());
+
+    // Fleet administrators can see the tokens of any user.
+    let tokens = objects_list_page_authz::<AccessToken>(
+        testctx,
+        &format!(
+            "/v1/system/users/{}/access-tokens?silo={}",
+            USER_TEST_UNPRIVILEGED.id(),
+            cptestctx.silo_name,
+        ),
+    )
+    .await;
+    assert_eq!(tokens.items.len(), 1);
+    assert_eq!(tokens.items[0].id, created.id);
+
+    // ... but other users cannot.
+    NexusRequest::expect_failure(
+        testctx,
+        StatusCode::FORBIDDEN,
+        Method::GET,
+        &format!(
+            "/v1/system/users/{}/access-tokens?silo={}",
+            USER_TEST_UNPRIVILEGED.id(),
+            cptestctx.silo_name,
+        ),
+    )
+    .authn_as(AuthnMode::UnprivilegedUser)
+    .execute()
+    .await
+    .expect("expected listing another user's tokens to fail");
+
+    // Deleting the token revokes it.
+    NexusRequest::object_delete(
+        testctx,
+        &format!("/v1/me/access-tokens/{}", created.id),
+    )
+    .authn_as(AuthnMode::UnprivilegedUser)
+    .execute()
+    .await
+    .expect("failed to delete access token");
+    whoami_with_token(testctx, &created.access_token, StatusCode::UNAUTHORIZED)
+        .await;
+    NexusRequest::expect_failure(
+        testctx,
+        StatusCode::NOT_FOUND,
+        Method::DELETE,
+        &format!("/v1/me/access-tokens/{}", created.id),
+    )
+    .authn_as(AuthnMode::UnprivilegedUser)
+    .execute()
+    .await
+    .expect("expected deleting a deleted token to fail");
+
+    // Tokens with a TTL stop working once they expire.
+    let created = access_token_create(
+        testctx,
+        &params::AccessTokenCreate {
+            description: String::new(),
+            ttl_seconds: Some(NonZeroU32::new(1).unwrap()),
+        },
+    )
+    .await;
+    assert!(created.time_expires.is_some());
+    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+    whoami_with_token(testctx, &created.access_token, StatusCode::UNAUTHORIZED)
+        .await;
+
+    // Descriptions longer than the database allows are rejected up front.
+    let error: HttpErrorResponseBody = NexusRequest::expect_failure_with_body(
+        testctx,
+        StatusCode::BAD_REQUEST,
+        Method::POST,
+        "/v1/me/access-tokens",
+        &params::AccessTokenCreate {
+            description: "x".repeat(513),
+            ttl_seconds: None,
+        },
+    )
+    .authn_as(AuthnMode::UnprivilegedUser)
+    .execute()
+    .await
+    .expect("expected creating a token with a long description to fail")
+    .parsed_body()
+    .unwrap();
+    assert_eq!(
+        error.message,
+        "access token description may not exceed 512 characters"
+    );
+}
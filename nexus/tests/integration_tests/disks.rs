@@ -22,11 +22,13 @@ use nexus_test_utils::resource_helpers::create_disk;
 use nexus_test_utils::resource_helpers::create_instance;
 use nexus_test_utils::resource_helpers::create_instance_with;
 use nexus_test_utils::resource_helpers::create_project;
+use nexus_test_utils::resource_helpers::object_put;
 use nexus_test_utils::resource_helpers::objects_list_page_authz;
 use nexus_test_utils::resource_helpers::populate_ip_pool;
 use nexus_test_utils::resource_helpers::DiskTest;
 use nexus_test_utils_macros::nexus_test;
 use nexus_types::external_api::params;
+use nexus_types::external_api::views::ResourceQuotas;
 use omicron_common::api::external::ByteCount;
 use omicron_common::api::external::Disk;
 use omicron_common::api::external::DiskState;
@@ -1402,6 +1404,204 @@ async fn test_disk_size_accounting(cptestctx: &ControlPlaneTestContext) {
     }
 }
 
+#[nexus_test]
+async fn test_disk_resize(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    let nexus = &cptestctx.server.apictx().nexus;
+    let datastore = nexus.datastore();
+
+    let test = DiskTest::new(&cptestctx).await;
+    let project_id = create_org_and_project(client).await;
+    let opctx =
+        OpContext::for_tests(cptestctx.logctx.log.new(o!()), datastore.clone());
+
+    create_disk(&client, PROJECT_NAME, DISK_NAME).await;
+    let disk_url = get_disk_url(DISK_NAME);
+    let resize_url =
+        format!("/v1/disks/{}/resize?project={}", DISK_NAME, PROJECT_NAME);
+    let disk = disk_get(&client, &disk_url).await;
+    assert_eq!(disk.size, ByteCount::from_gibibytes_u32(1));
+
+    // Disks may not shrink, or stay the same size.
+    for size in [ByteCount::from_gibibytes_u32(1), ByteCount::from(0u32)] {
+        let error: HttpErrorResponseBody =
+            NexusRequest::expect_failure_with_body(
+                client,
+                StatusCode::BAD_REQUEST,
+                Method::POST,
+                &resize_url,
+                &params::DiskResize { size },
+            )
+            .authn_as(AuthnMode::PrivilegedUser)
+            .execute()
+            .await
+            .unwrap()
+            .parsed_body()
+            .unwrap();
+        assert!(
+            error.message.starts_with(
+                "unsupported value for \"size\": disks may only grow"
+            ),
+            "unexpected error message: {}",
+            error.message
+        );
+    }
+
+    // Grow the disk.
+    let resized_disk: Disk = NexusRequest::new(
+        RequestBuilder::new(client, Method::POST, &resize_url)
+            .body(Some(&params::DiskResize {
+                size: ByteCount::from_gibibytes_u32(2),
+            }))
+            .expect_status(Some(StatusCode::OK)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert_eq!(resized_disk.identity.id, disk.identity.id);
+    assert_eq!(resized_disk.size, ByteCount::from_gibibytes_u32(2));
+    assert_eq!(resized_disk.state, DiskState::Detached);
+    disks_eq(&resized_disk, &disk_get(&client, &disk_url).await);
+
+    // The disk's new size is accounted for, and its old regions are gone.
+    let virtual_provisioning_collection = datastore
+        .virtual_provisioning_collection_get(&opctx, project_id)
+        .await
+        .unwrap();
+    assert_eq!(
+        virtual_provisioning_collection.virtual_disk_bytes_provisioned.0,
+        ByteCount::from_gibibytes_u32(2),
+    );
+    for zpool in &test.zpools {
+        for dataset in &zpool.datasets {
+            assert_eq!(
+                datastore
+                    .regions_total_occupied_size(dataset.id)
+                    .await
+                    .unwrap(),
+                ByteCount::from_gibibytes_u32(2).to_bytes(),
+            );
+        }
+    }
+
+    // Growing the disk beyond the project's storage quota fails, and leaves
+    // the disk as it was.
+    let _: ResourceQuotas = object_put(
+        client,
+        &format!("/v1/projects/{}/quotas", PROJECT_NAME),
+        &params::ResourceQuotasUpdate {
+            storage: Some(ByteCount::from_gibibytes_u32(3)),
+            ..Default::default()
+        },
+    )
+    .await;
+
+    let error: HttpErrorResponseBody = NexusRequest::expect_failure_with_body(
+        client,
+        StatusCode::BAD_REQUEST,
+        Method::POST,
+        &resize_url,
+        &params::DiskResize { size: ByteCount::from_gibibytes_u32(4) },
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert!(
+        error.message.starts_with("storage quota exceeded"),
+        "unexpected error message: {}",
+        error.message
+    );
+    disks_eq(&resized_disk, &disk_get(&client, &disk_url).await);
+    for zpool in &test.zpools {
+        for dataset in &zpool.datasets {
+            assert_eq!(
+                datastore
+                    .regions_total_occupied_size(dataset.id)
+                    .await
+                    .unwrap(),
+                ByteCount::from_gibibytes_u32(2).to_bytes(),
+            );
+        }
+    }
+}
+
+#[nexus_test]
+async fn test_disk_resize_attached(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    let nexus = &cptestctx.server.apictx().nexus;
+
+    DiskTest::new(&cptestctx).await;
+    create_org_and_project(client).await;
+
+    create_disk(&client, PROJECT_NAME, DISK_NAME).await;
+    let disk_url = get_disk_url(DISK_NAME);
+    let resize_url =
+        format!("/v1/disks/{}/resize?project={}", DISK_NAME, PROJECT_NAME);
+
+    // Attach the disk to a stopped instance.
+    let instance = create_instance(&client, PROJECT_NAME, INSTANCE_NAME).await;
+    let instance_id = instance.identity.id;
+    set_instance_state(&client, INSTANCE_NAME, "stop").await;
+    instance_simulate(nexus, &instance_id).await;
+    let disk = disk_post(
+        client,
+        &get_disk_attach_url(&instance_id.into()),
+        DISK_NAME.parse().unwrap(),
+    )
+    .await;
+    assert_eq!(disk.state, DiskState::Attached(instance_id));
+
+    // The disk may be grown while the instance is stopped, and remains
+    // attached to it.
+    let resized_disk: Disk = NexusRequest::new(
+        RequestBuilder::new(client, Method::POST, &resize_url)
+            .body(Some(&params::DiskResize {
+                size: ByteCount::from_gibibytes_u32(2),
+            }))
+            .expect_status(Some(StatusCode::OK)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert_eq!(resized_disk.size, ByteCount::from_gibibytes_u32(2));
+    assert_eq!(resized_disk.state, DiskState::Attached(instance_id));
+    disks_eq(&resized_disk, &disk_get(&client, &disk_url).await);
+
+    // The instance can be started using the resized disk.
+    set_instance_state(&client, INSTANCE_NAME, "start").await;
+    instance_simulate(nexus, &instance_id).await;
+
+    // The disk may not be resized while the instance is running.
+    let error: HttpErrorResponseBody = NexusRequest::expect_failure_with_body(
+        client,
+        StatusCode::CONFLICT,
+        Method::POST,
+        &resize_url,
+        &params::DiskResize { size: ByteCount::from_gibibytes_u32(3) },
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert!(
+        error.message.contains("the instance must be stopped"),
+        "unexpected error message: {}",
+        error.message
+    );
+    disks_eq(&resized_disk, &disk_get(&client, &disk_url).await);
+}
+
 // Test creating two disks across six zpools
 #[nexus_test]
 async fn test_multiple_disks_multiple_zpools(
@@ -64,6 +64,8 @@ lazy_static! {
         format!("/v1/system/silos/{}", *DEMO_SILO_NAME);
     pub static ref DEMO_SILO_POLICY_URL: String =
         format!("/v1/system/silos/{}/policy", *DEMO_SILO_NAME);
+    pub static ref DEMO_SILO_QUOTAS_URL: String =
+        format!("/v1/system/silos/{}/quotas", *DEMO_SILO_NAME);
     pub static ref DEMO_SILO_CREATE: params::SiloCreate =
         params::SiloCreate {
             identity: IdentityMetadataCreateParams {
@@ -97,6 +99,10 @@ lazy_static! {
         "/v1/system/identity-providers/local/users/{{id}}/set-password?silo={}",
         DEFAULT_SILO.identity().name,
     );
+    pub static ref DEMO_SILO_USER_ID_ACCESS_TOKENS_URL: String = format!(
+        "/v1/system/users/{{id}}/access-tokens?silo={}",
+        DEFAULT_SILO.identity().name,
+    );
 
     // Project used for testing
     pub static ref DEMO_PROJECT_NAME: Name = "demo-project".parse().unwrap();
@@ -106,6 +112,8 @@ lazy_static! {
         format!("project={}", *DEMO_PROJECT_NAME);
     pub static ref DEMO_PROJECT_POLICY_URL: String =
         format!("/v1/projects/{}/policy", *DEMO_PROJECT_NAME);
+    pub static ref DEMO_PROJECT_QUOTAS_URL: String =
+        format!("/v1/projects/{}/quotas", *DEMO_PROJECT_NAME);
     pub static ref DEMO_PROJECT_URL_DISKS: String =
         format!("/v1/disks?project={}",  *DEMO_PROJECT_NAME);
     pub static ref DEMO_PROJECT_URL_IMAGES: String =
@@ -121,6 +129,14 @@ lazy_static! {
             },
         };
 
+    // Quotas used for testing
+    pub static ref DEMO_QUOTAS_UPDATE: params::ResourceQuotasUpdate =
+        params::ResourceQuotasUpdate {
+            cpus: Some(16),
+            memory: Some(ByteCount::from_gibibytes_u32(64)),
+            storage: Some(ByteCount::from_gibibytes_u32(1024)),
+        };
+
     // VPC used for testing
     pub static ref DEMO_VPC_NAME: Name = "demo-vpc".parse().unwrap();
     pub static ref DEMO_VPC_URL: String =
@@ -212,6 +228,14 @@ lazy_static! {
                 DiskTest::DEFAULT_ZPOOL_SIZE_GIB / 5
             ),
         };
+    pub static ref DEMO_DISK_RESIZE_URL: String =
+        format!("/v1/disks/{}/resize?{}", *DEMO_DISK_NAME, *DEMO_PROJECT_SELECTOR);
+    pub static ref DEMO_DISK_RESIZE: params::DiskResize =
+        params::DiskResize {
+            size: ByteCount::from_gibibytes_u32(
+                DiskTest::DEFAULT_ZPOOL_SIZE_GIB / 5 + 1
+            ),
+        };
     pub static ref DEMO_DISK_METRICS_URL: String =
         format!(
             "/v1/disks/{}/metrics/activated?start_time={:?}&end_time={:?}&{}",
@@ -272,6 +296,14 @@ lazy_static! {
         format!("/v1/instances/{}/serial-console?{}", *DEMO_INSTANCE_NAME, *DEMO_PROJECT_SELECTOR);
     pub static ref DEMO_INSTANCE_SERIAL_STREAM_URL: String =
         format!("/v1/instances/{}/serial-console/stream?{}", *DEMO_INSTANCE_NAME, *DEMO_PROJECT_SELECTOR);
+    pub static ref DEMO_INSTANCE_METRICS_URL: String =
+        format!(
+            "/v1/instances/{}/metrics/vcpu_usage?vcpu=0&start_time={:?}&end_time={:?}&{}",
+            *DEMO_INSTANCE_NAME,
+            Utc::now(),
+            Utc::now(),
+            *DEMO_PROJECT_SELECTOR,
+        );
 
     pub static ref DEMO_INSTANCE_DISKS_URL: String =
         format!("/v1/instances/{}/disks?{}", *DEMO_INSTANCE_NAME, *DEMO_PROJECT_SELECTOR);
@@ -300,8 +332,15 @@ lazy_static! {
                 params::ExternalIpCreate::Ephemeral { pool_name: Some(DEMO_IP_POOL_NAME.clone()) }
             ],
             disks: vec![],
+            affinity_groups: vec![],
             start: true,
         };
+    pub static ref DEMO_INSTANCE_UPDATE: params::InstanceUpdate =
+        params::InstanceUpdate {
+            ncpus: InstanceCpuCount(2),
+            memory: ByteCount::from_gibibytes_u32(16),
+            hostname: String::from("demo-instance-updated"),
+        };
 
     // The instance needs a network interface, too.
     pub static ref DEMO_INSTANCE_NIC_NAME: Name =
@@ -496,6 +535,56 @@ lazy_static! {
             disk: DEMO_DISK_NAME.clone().into(),
         };
 
+    // Floating IPs
+    pub static ref DEMO_FLOAT_IP_NAME: Name = "float-ip".parse().unwrap();
+    pub static ref DEMO_FLOAT_IPS_URL: String =
+        format!("/v1/floating-ips?project={}", *DEMO_PROJECT_NAME);
+    pub static ref DEMO_FLOAT_IP_URL: String =
+        format!("/v1/floating-ips/{}?project={}", *DEMO_FLOAT_IP_NAME, *DEMO_PROJECT_NAME);
+    pub static ref DEMO_FLOAT_IP_ATTACH_URL: String =
+        format!("/v1/floating-ips/{}/attach?project={}", *DEMO_FLOAT_IP_NAME, *DEMO_PROJECT_NAME);
+    pub static ref DEMO_FLOAT_IP_DETACH_URL: String =
+        format!("/v1/floating-ips/{}/detach?project={}", *DEMO_FLOAT_IP_NAME, *DEMO_PROJECT_NAME);
+    pub static ref DEMO_FLOAT_IP_CREATE: params::FloatingIpCreate =
+        params::FloatingIpCreate {
+            identity: IdentityMetadataCreateParams {
+                name: DEMO_FLOAT_IP_NAME.clone(),
+                description: String::from("a new floating IP"),
+            },
+            address: None,
+            pool: Some(DEMO_IP_POOL_NAME.clone().into()),
+        };
+    pub static ref DEMO_FLOAT_IP_ATTACH: params::FloatingIpAttach =
+        params::FloatingIpAttach {
+            instance: DEMO_INSTANCE_NAME.clone().into(),
+        };
+
+    // Affinity groups
+    pub static ref DEMO_AFFINITY_GROUP_NAME: Name = "affinity-group".parse().unwrap();
+    pub static ref DEMO_AFFINITY_GROUPS_URL: String =
+        format!("/v1/affinity-groups?project={}", *DEMO_PROJECT_NAME);
+    pub static ref DEMO_AFFINITY_GROUP_URL: String =
+        format!("/v1/affinity-groups/{}?project={}", *DEMO_AFFINITY_GROUP_NAME, *DEMO_PROJECT_NAME);
+    pub static ref DEMO_AFFINITY_GROUP_MEMBERS_URL: String =
+        format!("/v1/affinity-groups/{}/members?project={}", *DEMO_AFFINITY_GROUP_NAME, *DEMO_PROJECT_NAME);
+    pub static ref DEMO_AFFINITY_GROUP_MEMBER_ADD_URL: String =
+        format!("/v1/affinity-groups/{}/members/add?project={}", *DEMO_AFFINITY_GROUP_NAME, *DEMO_PROJECT_NAME);
+    pub static ref DEMO_AFFINITY_GROUP_MEMBER_REMOVE_URL: String =
+        format!("/v1/affinity-groups/{}/members/remove?project={}", *DEMO_AFFINITY_GROUP_NAME, *DEMO_PROJECT_NAME);
+    pub static ref DEMO_AFFINITY_GROUP_CREATE: params::AffinityGroupCreate =
+        params::AffinityGroupCreate {
+            identity: IdentityMetadataCreateParams {
+                name: DEMO_AFFINITY_GROUP_NAME.clone(),
+                description: String::from("a new affinity group"),
+            },
+            kind: shared::AffinityGroupKind::AntiAffinity,
+            policy: shared::AffinityPolicy::Allow,
+        };
+    pub static ref DEMO_AFFINITY_GROUP_MEMBER: params::AffinityGroupMember =
+        params::AffinityGroupMember {
+            instance: DEMO_INSTANCE_NAME.clone().into(),
+        };
+
     // SSH keys
     pub static ref DEMO_SSHKEYS_URL: &'static str = "/v1/me/ssh-keys";
     pub static ref DEMO_SSHKEY_NAME: Name = "aaaaa-ssh-key".parse().unwrap();
@@ -511,6 +600,16 @@ lazy_static! {
     pub static ref DEMO_SPECIFIC_SSHKEY_URL: String =
         format!("{}/{}", *DEMO_SSHKEYS_URL, *DEMO_SSHKEY_NAME);
 
+    // Personal access token used for testing
+    pub static ref DEMO_ACCESS_TOKENS_URL: &'static str = "/v1/me/access-tokens";
+    pub static ref DEMO_ACCESS_TOKEN_CREATE: params::AccessTokenCreate =
+        params::AccessTokenCreate {
+            description: String::from("a token for testing"),
+            ttl_seconds: None,
+        };
+    pub static ref DEMO_SPECIFIC_ACCESS_TOKEN_URL: String =
+        format!("{}/{{id}}", *DEMO_ACCESS_TOKENS_URL);
+
     // System update
 
     pub static ref DEMO_SYSTEM_UPDATE_PARAMS: params::SystemUpdatePath = params::SystemUpdatePath {
@@ -546,6 +645,47 @@ lazy_static! {
             group_attribute_name: None,
         };
 
+    // OIDC identity providers need a Silo of their own, since the demo Silo
+    // authenticates users with SAML.
+    pub static ref DEMO_OIDC_SILO_NAME: Name = "demo-oidc-silo".parse().unwrap();
+    pub static ref DEMO_OIDC_SILO_CREATE: params::SiloCreate =
+        params::SiloCreate {
+            identity: IdentityMetadataCreateParams {
+                name: DEMO_OIDC_SILO_NAME.clone(),
+                description: String::from(""),
+            },
+            discoverable: true,
+            identity_mode: shared::SiloIdentityMode::OidcJit,
+            admin_group_name: None,
+            tls_certificates: vec![],
+            mapped_fleet_roles: Default::default(),
+        };
+    pub static ref OIDC_IDENTITY_PROVIDERS_URL: String = format!("/v1/system/identity-providers/oidc?silo={}", *DEMO_OIDC_SILO_NAME);
+
+    pub static ref DEMO_OIDC_IDENTITY_PROVIDER_NAME: Name = "demo-oidc-provider".parse().unwrap();
+    pub static ref SPECIFIC_OIDC_IDENTITY_PROVIDER_URL: String = format!("/v1/system/identity-providers/oidc/{}?silo={}", *DEMO_OIDC_IDENTITY_PROVIDER_NAME, *DEMO_OIDC_SILO_NAME);
+
+    pub static ref OIDC_IDENTITY_PROVIDER: params::OidcIdentityProviderCreate =
+        params::OidcIdentityProviderCreate {
+            identity: IdentityMetadataCreateParams {
+                name: DEMO_OIDC_IDENTITY_PROVIDER_NAME.clone(),
+                description: "a demo provider".to_string(),
+            },
+
+            issuer: HTTP_SERVER.url("/oidc").to_string(),
+            client_id: "client_id".to_string(),
+            client_secret: "client_secret".to_string(),
+            redirect_uri: "http://callback".to_string(),
+            scopes: vec![],
+
+            group_claim_name: None,
+        };
+
+    // SCIM provisioning also requires a Silo whose users come from an identity
+    // provider, so it uses the OIDC Silo too.
+    pub static ref SCIM_TOKENS_URL: String = format!("/v1/system/scim/tokens?silo={}", *DEMO_OIDC_SILO_NAME);
+    pub static ref SPECIFIC_SCIM_TOKEN_URL: String = format!("/v1/system/scim/tokens/{{id}}?silo={}", *DEMO_OIDC_SILO_NAME);
+
     pub static ref DEMO_SYSTEM_METRICS_URL: String =
         format!(
             "/v1/system/metrics/virtual_disk_space_provisioned?start_time={:?}&end_time={:?}",
@@ -560,6 +700,9 @@ lazy_static! {
             Utc::now(),
         );
 
+    pub static ref AUDIT_LOG_URL: String =
+        format!("/v1/system/audit-log?start_time={:?}", Utc::now());
+
     // Users
     pub static ref DEMO_USER_CREATE: params::UserCreate = params::UserCreate {
         external_id: params::UserId::from_str("dummy-user").unwrap(),
@@ -883,6 +1026,17 @@ lazy_static! {
                 ),
             ],
         },
+        VerifyEndpoint {
+            url: &DEMO_SILO_QUOTAS_URL,
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![
+                AllowedMethod::Get,
+                AllowedMethod::Put(
+                    serde_json::to_value(&*DEMO_QUOTAS_UPDATE).unwrap()
+                ),
+            ],
+        },
         VerifyEndpoint {
             url: "/v1/policy",
             visibility: Visibility::Public,
@@ -976,6 +1130,15 @@ lazy_static! {
             ],
         },
 
+        VerifyEndpoint {
+            url: &DEMO_SILO_USER_ID_ACCESS_TOKENS_URL,
+            visibility: Visibility::Public,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![
+                AllowedMethod::Get,
+            ],
+        },
+
         /* Projects */
 
         // TODO-security TODO-correctness One thing that's a little strange
@@ -1033,6 +1196,18 @@ lazy_static! {
             ],
         },
 
+        VerifyEndpoint {
+            url: &DEMO_PROJECT_QUOTAS_URL,
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![
+                AllowedMethod::Get,
+                AllowedMethod::Put(
+                    serde_json::to_value(&*DEMO_QUOTAS_UPDATE).unwrap()
+                ),
+            ],
+        },
+
         /* VPCs */
         VerifyEndpoint {
             url: &DEMO_PROJECT_URL_VPCS,
@@ -1212,6 +1387,17 @@ lazy_static! {
             ],
         },
 
+        VerifyEndpoint {
+            url: &DEMO_DISK_RESIZE_URL,
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![
+                AllowedMethod::Post(
+                    serde_json::to_value(&*DEMO_DISK_RESIZE).unwrap()
+                ),
+            ],
+        },
+
         VerifyEndpoint {
             url: &DEMO_DISK_METRICS_URL,
             visibility: Visibility::Protected,
@@ -1376,6 +1562,103 @@ lazy_static! {
             ]
         },
 
+        /* Floating IPs */
+
+        VerifyEndpoint {
+            url: &DEMO_FLOAT_IPS_URL,
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![
+                AllowedMethod::Get,
+                AllowedMethod::Post(
+                    serde_json::to_value(&*DEMO_FLOAT_IP_CREATE).unwrap(),
+                ),
+            ],
+        },
+
+        VerifyEndpoint {
+            url: &DEMO_FLOAT_IP_URL,
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![
+                AllowedMethod::Get,
+                AllowedMethod::Delete,
+            ],
+        },
+
+        VerifyEndpoint {
+            url: &DEMO_FLOAT_IP_ATTACH_URL,
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![
+                AllowedMethod::Post(
+                    serde_json::to_value(&*DEMO_FLOAT_IP_ATTACH).unwrap(),
+                ),
+            ],
+        },
+
+        VerifyEndpoint {
+            url: &DEMO_FLOAT_IP_DETACH_URL,
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![
+                AllowedMethod::Post(serde_json::Value::Null),
+            ],
+        },
+
+        /* Affinity groups */
+
+        VerifyEndpoint {
+            url: &DEMO_AFFINITY_GROUPS_URL,
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![
+                AllowedMethod::Get,
+                AllowedMethod::Post(
+                    serde_json::to_value(&*DEMO_AFFINITY_GROUP_CREATE).unwrap(),
+                ),
+            ],
+        },
+
+        VerifyEndpoint {
+            url: &DEMO_AFFINITY_GROUP_URL,
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![
+                AllowedMethod::Get,
+                AllowedMethod::Delete,
+            ],
+        },
+
+        VerifyEndpoint {
+            url: &DEMO_AFFINITY_GROUP_MEMBERS_URL,
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![AllowedMethod::Get],
+        },
+
+        VerifyEndpoint {
+            url: &DEMO_AFFINITY_GROUP_MEMBER_ADD_URL,
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![
+                AllowedMethod::Post(
+                    serde_json::to_value(&*DEMO_AFFINITY_GROUP_MEMBER).unwrap(),
+                ),
+            ],
+        },
+
+        VerifyEndpoint {
+            url: &DEMO_AFFINITY_GROUP_MEMBER_REMOVE_URL,
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![
+                AllowedMethod::Post(
+                    serde_json::to_value(&*DEMO_AFFINITY_GROUP_MEMBER).unwrap(),
+                ),
+            ],
+        },
+
         /* Instances */
         VerifyEndpoint {
             url: &DEMO_PROJECT_URL_INSTANCES,
@@ -1395,6 +1678,9 @@ lazy_static! {
             unprivileged_access: UnprivilegedAccess::None,
             allowed_methods: vec![
                 AllowedMethod::Get,
+                AllowedMethod::Put(
+                    serde_json::to_value(&*DEMO_INSTANCE_UPDATE).unwrap()
+                ),
                 AllowedMethod::Delete,
             ],
         },
@@ -1452,6 +1738,15 @@ lazy_static! {
             ],
         },
 
+        VerifyEndpoint {
+            url: &DEMO_INSTANCE_METRICS_URL,
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![
+                AllowedMethod::Get,
+            ],
+        },
+
         /* Instance NICs */
         VerifyEndpoint {
             url: &DEMO_INSTANCE_NICS_URL,
@@ -1682,6 +1977,17 @@ lazy_static! {
             ],
         },
 
+        /* Audit log */
+
+        VerifyEndpoint {
+            url: &AUDIT_LOG_URL,
+            visibility: Visibility::Public,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![
+                AllowedMethod::Get,
+            ],
+        },
+
         /* Silo identity providers */
 
         VerifyEndpoint {
@@ -1714,6 +2020,37 @@ lazy_static! {
             unprivileged_access: UnprivilegedAccess::None,
             allowed_methods: vec![AllowedMethod::Get],
         },
+        VerifyEndpoint {
+            url: &OIDC_IDENTITY_PROVIDERS_URL,
+            // As with SAML, the provider lives in a Silo that unprivileged
+            // users can't see.
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![AllowedMethod::Post(
+                serde_json::to_value(&*OIDC_IDENTITY_PROVIDER).unwrap(),
+            )],
+        },
+        VerifyEndpoint {
+            url: &SPECIFIC_OIDC_IDENTITY_PROVIDER_URL,
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![AllowedMethod::Get],
+        },
+        VerifyEndpoint {
+            url: &SCIM_TOKENS_URL,
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![
+                AllowedMethod::Get,
+                AllowedMethod::Post(serde_json::Value::Null),
+            ],
+        },
+        VerifyEndpoint {
+            url: &SPECIFIC_SCIM_TOKEN_URL,
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![AllowedMethod::Delete],
+        },
 
         /* Misc */
 
@@ -1757,6 +2094,28 @@ lazy_static! {
             ],
         },
 
+        /* Personal access tokens */
+
+        VerifyEndpoint {
+            url: &DEMO_ACCESS_TOKENS_URL,
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::Full,
+            allowed_methods: vec![
+                AllowedMethod::Get,
+                AllowedMethod::Post(
+                    serde_json::to_value(&*DEMO_ACCESS_TOKEN_CREATE).unwrap(),
+                ),
+            ],
+        },
+        VerifyEndpoint {
+            url: &DEMO_SPECIFIC_ACCESS_TOKEN_URL,
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::Full,
+            allowed_methods: vec![
+                AllowedMethod::Delete,
+            ],
+        },
+
         /* Certificates */
         VerifyEndpoint {
             url: &DEMO_CERTIFICATES_URL,
@@ -0,0 +1,545 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests Floating IP support in the API
+
+use crate::integration_tests::instances::instance_simulate;
+use dropshot::test_util::ClientTestContext;
+use dropshot::ResultsPage;
+use http::method::Method;
+use http::StatusCode;
+use nexus_test_utils::http_testing::AuthnMode;
+use nexus_test_utils::http_testing::NexusRequest;
+use nexus_test_utils::http_testing::RequestBuilder;
+use nexus_test_utils::http_testing::TestResponse;
+use nexus_test_utils::resource_helpers::create_instance;
+use nexus_test_utils::resource_helpers::create_ip_pool;
+use nexus_test_utils::resource_helpers::create_project;
+use nexus_test_utils::resource_helpers::object_create;
+use nexus_test_utils::resource_helpers::populate_ip_pool;
+use nexus_test_utils_macros::nexus_test;
+use nexus_types::external_api::params;
+use nexus_types::external_api::views::ExternalIp;
+use nexus_types::external_api::views::FloatingIp;
+use nexus_types::external_api::views::Rack;
+use omicron_common::address::IpRange;
+use omicron_common::address::Ipv4Range;
+use omicron_common::api::external::AddressLotKind;
+use omicron_common::api::external::IdentityMetadataCreateParams;
+use omicron_common::api::external::Instance;
+use omicron_common::api::external::NameOrId;
+use omicron_common::api::internal::shared::SwitchLocation;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+
+type ControlPlaneTestContext =
+    nexus_test_utils::ControlPlaneTestContext<omicron_nexus::Server>;
+
+const PROJECT_NAME: &str = "rootbeer-patch";
+
+const FIP_NAMES: &[&str] = &["vanilla", "chocolate", "strawberry"];
+
+fn get_floating_ips_url(project_name: &str) -> String {
+    format!("/v1/floating-ips?project={project_name}")
+}
+
+fn get_floating_ip_by_name_url(fip_name: &str, project_name: &str) -> String {
+    format!("/v1/floating-ips/{fip_name}?project={project_name}")
+}
+
+async fn floating_ip_create(
+    client: &ClientTestContext,
+    fip_name: &str,
+    address: Option<IpAddr>,
+    pool: Option<&str>,
+) -> FloatingIp {
+    object_create(
+        client,
+        &get_floating_ips_url(PROJECT_NAME),
+        &params::FloatingIpCreate {
+            identity: IdentityMetadataCreateParams {
+                name: fip_name.parse().unwrap(),
+                description: String::from("a floating ip"),
+            },
+            address,
+            pool: pool.map(|name| NameOrId::Name(name.parse().unwrap())),
+        },
+    )
+    .await
+}
+
+async fn floating_ip_get(
+    client: &ClientTestContext,
+    fip_name: &str,
+) -> FloatingIp {
+    NexusRequest::object_get(
+        client,
+        &get_floating_ip_by_name_url(fip_name, PROJECT_NAME),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .expect("failed to fetch floating IP")
+    .parsed_body()
+    .unwrap()
+}
+
+async fn floating_ip_post(
+    client: &ClientTestContext,
+    fip_name: &str,
+    action: &str,
+    body: Option<&params::FloatingIpAttach>,
+    expected_status: StatusCode,
+) -> TestResponse {
+    let url =
+        format!("/v1/floating-ips/{fip_name}/{action}?project={PROJECT_NAME}");
+    NexusRequest::new(
+        RequestBuilder::new(client, Method::POST, &url)
+            .body(body)
+            .expect_status(Some(expected_status)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+}
+
+async fn instance_stop(
+    cptestctx: &ControlPlaneTestContext,
+    instance: &Instance,
+) {
+    let client = &cptestctx.external_client;
+    let url = format!(
+        "/v1/instances/{}/stop?project={}",
+        instance.identity.name, PROJECT_NAME
+    );
+    NexusRequest::new(
+        RequestBuilder::new(client, Method::POST, &url)
+            .body(None as Option<&serde_json::Value>)
+            .expect_status(Some(StatusCode::ACCEPTED)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+    let nexus = &cptestctx.server.apictx().nexus;
+    instance_simulate(nexus, &instance.identity.id).await;
+}
+
+// Gives switch0 an uplink, so that it's treated as a boundary switch on which
+// NAT for instances' external addresses is configured.
+async fn configure_boundary_switch(client: &ClientTestContext) {
+    NexusRequest::objects_post(
+        client,
+        "/v1/system/networking/address-lot",
+        &params::AddressLotCreate {
+            identity: IdentityMetadataCreateParams {
+                name: "parkinglot".parse().unwrap(),
+                description: "an address parking lot".into(),
+            },
+            kind: AddressLotKind::Infra,
+            blocks: vec![params::AddressLotBlockCreate {
+                first_address: "203.0.113.10".parse().unwrap(),
+                last_address: "203.0.113.20".parse().unwrap(),
+            }],
+        },
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+
+    let mut settings =
+        params::SwitchPortSettingsCreate::new(IdentityMetadataCreateParams {
+            name: "uplink".parse().unwrap(),
+            description: "an uplink".into(),
+        });
+    settings.links.insert(
+        "phy0".into(),
+        params::LinkConfig {
+            mtu: 4700,
+            lldp: params::LldpServiceConfig {
+                enabled: false,
+                lldp_config: None,
+            },
+        },
+    );
+    settings.interfaces.insert(
+        "phy0".into(),
+        params::SwitchInterfaceConfig {
+            v6_enabled: true,
+            kind: params::SwitchInterfaceKind::Primary,
+        },
+    );
+    settings.routes.insert(
+        "phy0".into(),
+        params::RouteConfig {
+            routes: vec![params::Route {
+                dst: "0.0.0.0/0".parse().unwrap(),
+                gw: "203.0.113.1".parse().unwrap(),
+                vid: None,
+            }],
+        },
+    );
+    settings.addresses.insert(
+        "phy0".into(),
+        params::AddressConfig {
+            addresses: vec![params::Address {
+                address: "203.0.113.10/24".parse().unwrap(),
+                address_lot: NameOrId::Name("parkinglot".parse().unwrap()),
+            }],
+        },
+    );
+    NexusRequest::objects_post(
+        client,
+        "/v1/system/networking/switch-port-settings",
+        &settings,
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+
+    let racks: Vec<Rack> = NexusRequest::iter_collection_authn(
+        client,
+        "/v1/system/hardware/racks",
+        "",
+        None,
+    )
+    .await
+    .expect("failed to list racks")
+    .all_items;
+    let rack_id = racks[0].identity.id;
+    NexusRequest::new(
+        RequestBuilder::new(
+            client,
+            Method::POST,
+            &format!(
+                "/v1/system/hardware/switch-port/qsfp0/settings\
+                ?rack_id={rack_id}&switch_location=switch0"
+            ),
+        )
+        .body(Some(&params::SwitchPortApplySettings {
+            port_settings: NameOrId::Name("uplink".parse().unwrap()),
+        }))
+        .expect_status(Some(StatusCode::NO_CONTENT)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+}
+
+#[nexus_test]
+async fn test_floating_ip_create(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+
+    populate_ip_pool(&client, "default", None).await;
+    let other_pool_range = IpRange::V4(
+        Ipv4Range::new(Ipv4Addr::new(10, 1, 0, 1), Ipv4Addr::new(10, 1, 0, 5))
+            .unwrap(),
+    );
+    create_ip_pool(&client, "other-pool", Some(other_pool_range)).await;
+    let project = create_project(client, PROJECT_NAME).await;
+
+    // A Floating IP can be allocated from the default pool...
+    let fip = floating_ip_create(client, FIP_NAMES[0], None, None).await;
+    assert_eq!(fip.identity.name.as_str(), FIP_NAMES[0]);
+    assert_eq!(fip.project_id, project.identity.id);
+    assert_eq!(fip.instance_id, None);
+    assert_eq!(fip.ip, IpAddr::from(Ipv4Addr::new(10, 0, 0, 0)));
+
+    // ...at a specific address within a pool...
+    let ip_addr = "10.1.0.3".parse().unwrap();
+    let fip = floating_ip_create(
+        client,
+        FIP_NAMES[1],
+        Some(ip_addr),
+        Some("other-pool"),
+    )
+    .await;
+    assert_eq!(fip.ip, ip_addr);
+
+    // ...but that address can't be reused.
+    let error: dropshot::HttpErrorResponseBody = NexusRequest::new(
+        RequestBuilder::new(
+            client,
+            Method::POST,
+            &get_floating_ips_url(PROJECT_NAME),
+        )
+        .body(Some(&params::FloatingIpCreate {
+            identity: IdentityMetadataCreateParams {
+                name: FIP_NAMES[2].parse().unwrap(),
+                description: String::from("a floating ip"),
+            },
+            address: Some(ip_addr),
+            pool: Some(NameOrId::Name("other-pool".parse().unwrap())),
+        }))
+        .expect_status(Some(StatusCode::BAD_REQUEST)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert!(error.message.contains("not available"), "{}", error.message);
+
+    // Names are unique within the project.
+    let error: dropshot::HttpErrorResponseBody = NexusRequest::new(
+        RequestBuilder::new(
+            client,
+            Method::POST,
+            &get_floating_ips_url(PROJECT_NAME),
+        )
+        .body(Some(&params::FloatingIpCreate {
+            identity: IdentityMetadataCreateParams {
+                name: FIP_NAMES[0].parse().unwrap(),
+                description: String::from("a floating ip"),
+            },
+            address: None,
+            pool: None,
+        }))
+        .expect_status(Some(StatusCode::BAD_REQUEST)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert_eq!(
+        error.message,
+        format!("already exists: floating-ip \"{}\"", FIP_NAMES[0])
+    );
+
+    let fips =
+        NexusRequest::object_get(client, &get_floating_ips_url(PROJECT_NAME))
+            .authn_as(AuthnMode::PrivilegedUser)
+            .execute()
+            .await
+            .unwrap()
+            .parsed_body::<ResultsPage<FloatingIp>>()
+            .unwrap()
+            .items;
+    assert_eq!(fips.len(), 2);
+
+    // Floating IPs can be fetched by ID without a project.
+    let fetched = NexusRequest::object_get(
+        client,
+        &format!("/v1/floating-ips/{}", fip.identity.id),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body::<FloatingIp>()
+    .unwrap();
+    assert_eq!(fetched.identity.name, fip.identity.name);
+    assert_eq!(fetched.ip, ip_addr);
+}
+
+#[nexus_test]
+async fn test_floating_ip_attach_detach(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+
+    populate_ip_pool(&client, "default", None).await;
+    create_project(client, PROJECT_NAME).await;
+    let fip = floating_ip_create(client, FIP_NAMES[0], None, None).await;
+    let instance = create_instance(client, PROJECT_NAME, "anonymous").await;
+    let attach = params::FloatingIpAttach {
+        instance: NameOrId::Name(instance.identity.name.clone()),
+    };
+
+    // Floating IPs can't be attached to an instance that's still starting.
+    floating_ip_post(
+        client,
+        FIP_NAMES[0],
+        "attach",
+        Some(&attach),
+        StatusCode::BAD_REQUEST,
+    )
+    .await;
+
+    let nexus = &cptestctx.server.apictx().nexus;
+    instance_simulate(nexus, &instance.identity.id).await;
+    instance_stop(cptestctx, &instance).await;
+    let attached: FloatingIp = floating_ip_post(
+        client,
+        FIP_NAMES[0],
+        "attach",
+        Some(&attach),
+        StatusCode::ACCEPTED,
+    )
+    .await
+    .parsed_body()
+    .unwrap();
+    assert_eq!(attached.instance_id, Some(instance.identity.id));
+    assert_eq!(attached.ip, fip.ip);
+
+    // Attaching again to the same instance is fine.
+    floating_ip_post(
+        client,
+        FIP_NAMES[0],
+        "attach",
+        Some(&attach),
+        StatusCode::ACCEPTED,
+    )
+    .await;
+
+    // The address is reported among the instance's external IPs.
+    let ips = NexusRequest::object_get(
+        client,
+        &format!(
+            "/v1/instances/{}/external-ips?project={}",
+            instance.identity.name, PROJECT_NAME
+        ),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body::<ResultsPage<ExternalIp>>()
+    .unwrap()
+    .items;
+    assert_eq!(ips.len(), 1);
+    assert_eq!(ips[0].ip, fip.ip);
+
+    // An attached Floating IP can't be deleted.
+    NexusRequest::expect_failure(
+        client,
+        StatusCode::BAD_REQUEST,
+        Method::DELETE,
+        &get_floating_ip_by_name_url(FIP_NAMES[0], PROJECT_NAME),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+
+    let detached: FloatingIp = floating_ip_post(
+        client,
+        FIP_NAMES[0],
+        "detach",
+        None,
+        StatusCode::ACCEPTED,
+    )
+    .await
+    .parsed_body()
+    .unwrap();
+    assert_eq!(detached.instance_id, None);
+    floating_ip_post(
+        client,
+        FIP_NAMES[0],
+        "detach",
+        None,
+        StatusCode::BAD_REQUEST,
+    )
+    .await;
+
+    // Deleting an instance detaches its Floating IPs, rather than releasing
+    // them.
+    floating_ip_post(
+        client,
+        FIP_NAMES[0],
+        "attach",
+        Some(&attach),
+        StatusCode::ACCEPTED,
+    )
+    .await;
+    NexusRequest::object_delete(
+        client,
+        &format!(
+            "/v1/instances/{}?project={}",
+            instance.identity.name, PROJECT_NAME
+        ),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+    let fip = floating_ip_get(client, FIP_NAMES[0]).await;
+    assert_eq!(fip.instance_id, None);
+
+    NexusRequest::object_delete(
+        client,
+        &get_floating_ip_by_name_url(FIP_NAMES[0], PROJECT_NAME),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+    NexusRequest::expect_failure(
+        client,
+        StatusCode::NOT_FOUND,
+        Method::GET,
+        &get_floating_ip_by_name_url(FIP_NAMES[0], PROJECT_NAME),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+}
+
+#[nexus_test]
+async fn test_floating_ip_attach_detach_running(
+    cptestctx: &ControlPlaneTestContext,
+) {
+    let client = &cptestctx.external_client;
+
+    populate_ip_pool(&client, "default", None).await;
+    create_project(client, PROJECT_NAME).await;
+    configure_boundary_switch(client).await;
+    let fip = floating_ip_create(client, FIP_NAMES[0], None, None).await;
+    let IpAddr::V4(fip_ip) = fip.ip else {
+        panic!("expected an IPv4 floating IP, found {}", fip.ip);
+    };
+    let instance = create_instance(client, PROJECT_NAME, "anonymous").await;
+    let nexus = &cptestctx.server.apictx().nexus;
+    instance_simulate(nexus, &instance.identity.id).await;
+
+    let dendrite = &cptestctx.dendrite[&SwitchLocation::Switch0];
+    let dpd_client = dpd_client::Client::new(
+        &format!("http://[::1]:{}", dendrite.port),
+        dpd_client::ClientState {
+            tag: String::from("test"),
+            log: cptestctx.logctx.log.clone(),
+        },
+    );
+    assert!(dpd_client.nat_ipv4_get(&fip_ip, 0).await.is_err());
+
+    // Attaching a Floating IP to a running instance configures NAT for it on
+    // the boundary switches.
+    let attached: FloatingIp = floating_ip_post(
+        client,
+        FIP_NAMES[0],
+        "attach",
+        Some(&params::FloatingIpAttach {
+            instance: NameOrId::Name(instance.identity.name.clone()),
+        }),
+        StatusCode::ACCEPTED,
+    )
+    .await
+    .parsed_body()
+    .unwrap();
+    assert_eq!(attached.instance_id, Some(instance.identity.id));
+    dpd_client
+        .nat_ipv4_get(&fip_ip, 0)
+        .await
+        .expect("expected a NAT entry for the attached floating IP");
+
+    // Detaching it removes that configuration again.
+    let detached: FloatingIp = floating_ip_post(
+        client,
+        FIP_NAMES[0],
+        "detach",
+        None,
+        StatusCode::ACCEPTED,
+    )
+    .await
+    .parsed_body()
+    .unwrap();
+    assert_eq!(detached.instance_id, None);
+    assert!(dpd_client.nat_ipv4_get(&fip_ip, 0).await.is_err());
+}
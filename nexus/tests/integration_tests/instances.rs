@@ -241,6 +241,7 @@ async fn test_instances_create_reboot_halt(
                     params::InstanceNetworkInterfaceAttachment::Default,
                 external_ips: vec![],
                 disks: vec![],
+                affinity_groups: vec![],
                 start: true,
             }))
             .expect_status(Some(StatusCode::BAD_REQUEST)),
@@ -992,6 +993,7 @@ async fn test_instances_create_stopped_start(
                 params::InstanceNetworkInterfaceAttachment::Default,
             external_ips: vec![],
             disks: vec![],
+            affinity_groups: vec![],
             start: false,
         },
     )
@@ -1068,6 +1070,161 @@ async fn test_instances_delete_fails_when_running_succeeds_when_stopped(
         .unwrap();
 }
 
+#[nexus_test]
+async fn test_instance_update(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    let apictx = &cptestctx.server.apictx();
+    let nexus = &apictx.nexus;
+    let datastore = nexus.datastore();
+    let opctx =
+        OpContext::for_tests(cptestctx.logctx.log.new(o!()), datastore.clone());
+    let instance_name = "just-rainsticks";
+
+    let project_id = create_org_and_project(&client).await;
+
+    // Create an instance and simulate it booting.
+    let instance_url = get_instance_url(instance_name);
+    let instance = create_instance(client, PROJECT_NAME, instance_name).await;
+    instance_simulate(nexus, &instance.identity.id).await;
+    let instance = instance_get(&client, &instance_url).await;
+    assert_eq!(instance.runtime.run_state, InstanceState::Running);
+
+    let update = params::InstanceUpdate {
+        ncpus: InstanceCpuCount(2),
+        memory: ByteCount::from_gibibytes_u32(2),
+        hostname: String::from("new-host"),
+    };
+
+    // A running instance cannot be reconfigured.
+    let error: HttpErrorResponseBody = NexusRequest::expect_failure_with_body(
+        client,
+        StatusCode::CONFLICT,
+        Method::PUT,
+        &instance_url,
+        &update,
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert_eq!(
+        error.message,
+        "instance is in state running but must be stopped to be reconfigured"
+    );
+
+    // Stop the instance.
+    let instance =
+        instance_post(&client, instance_name, InstanceOp::Stop).await;
+    instance_simulate(nexus, &instance.identity.id).await;
+    let instance = instance_get(&client, &instance_url).await;
+    assert_eq!(instance.runtime.run_state, InstanceState::Stopped);
+
+    // The new configuration is validated like that of a new instance.
+    let error: HttpErrorResponseBody = NexusRequest::expect_failure_with_body(
+        client,
+        StatusCode::BAD_REQUEST,
+        Method::PUT,
+        &instance_url,
+        &params::InstanceUpdate {
+            ncpus: InstanceCpuCount(MAX_VCPU_PER_INSTANCE + 1),
+            ..update.clone()
+        },
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert_eq!(
+        error.message,
+        format!(
+            "cannot have more than {} vCPUs per instance",
+            MAX_VCPU_PER_INSTANCE
+        )
+    );
+
+    // Now the instance can be reconfigured, and the resources provisioned by
+    // its project are adjusted accordingly.
+    let instance: Instance =
+        NexusRequest::object_put(client, &instance_url, Some(&update))
+            .authn_as(AuthnMode::PrivilegedUser)
+            .execute()
+            .await
+            .unwrap()
+            .parsed_body()
+            .unwrap();
+    assert_eq!(instance.ncpus.0, 2);
+    assert_eq!(instance.memory, ByteCount::from_gibibytes_u32(2));
+    assert_eq!(instance.hostname, "new-host");
+    assert_eq!(instance.runtime.run_state, InstanceState::Stopped);
+
+    let virtual_provisioning_collection = datastore
+        .virtual_provisioning_collection_get(&opctx, project_id)
+        .await
+        .unwrap();
+    assert_eq!(virtual_provisioning_collection.cpus_provisioned, 2);
+    assert_eq!(
+        virtual_provisioning_collection.ram_provisioned.0,
+        ByteCount::from_gibibytes_u32(2),
+    );
+
+    // Growing the instance beyond its project's quota fails, and leaves both
+    // the instance and the provisioned resources unchanged.
+    NexusRequest::object_put(
+        client,
+        &format!("/v1/projects/{}/quotas", PROJECT_NAME),
+        Some(&params::ResourceQuotasUpdate {
+            cpus: Some(4),
+            ..Default::default()
+        }),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+    let error: HttpErrorResponseBody = NexusRequest::expect_failure_with_body(
+        client,
+        StatusCode::BAD_REQUEST,
+        Method::PUT,
+        &instance_url,
+        &params::InstanceUpdate {
+            ncpus: InstanceCpuCount(8),
+            ..update.clone()
+        },
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert!(
+        error.message.starts_with("vCPU quota exceeded"),
+        "unexpected error message: {}",
+        error.message
+    );
+    let instance = instance_get(&client, &instance_url).await;
+    assert_eq!(instance.ncpus.0, 2);
+    let virtual_provisioning_collection = datastore
+        .virtual_provisioning_collection_get(&opctx, project_id)
+        .await
+        .unwrap();
+    assert_eq!(virtual_provisioning_collection.cpus_provisioned, 2);
+
+    // The new configuration is used when the instance is next started.
+    let instance =
+        instance_post(&client, instance_name, InstanceOp::Start).await;
+    instance_simulate(nexus, &instance.identity.id).await;
+    let instance = instance_get(&client, &instance_url).await;
+    assert_eq!(instance.runtime.run_state, InstanceState::Running);
+    assert_eq!(instance.ncpus.0, 2);
+    assert_eq!(instance.memory, ByteCount::from_gibibytes_u32(2));
+    assert_eq!(instance.hostname, "new-host");
+}
+
 #[nexus_test]
 async fn test_instances_invalid_creation_returns_bad_request(
     cptestctx: &ControlPlaneTestContext,
@@ -1185,6 +1342,7 @@ async fn test_instance_using_image_from_other_project_fails(
                         size: ByteCount::from_gibibytes_u32(4),
                     },
                 )],
+                affinity_groups: vec![],
                 start: true,
             }))
             .expect_status(Some(StatusCode::BAD_REQUEST)),
@@ -1247,6 +1405,7 @@ async fn test_instance_create_saga_removes_instance_database_record(
         network_interfaces: interface_params.clone(),
         external_ips: vec![],
         disks: vec![],
+        affinity_groups: vec![],
         start: true,
     };
     let response = NexusRequest::objects_post(
@@ -1274,6 +1433,7 @@ async fn test_instance_create_saga_removes_instance_database_record(
         network_interfaces: interface_params,
         external_ips: vec![],
         disks: vec![],
+        affinity_groups: vec![],
         start: true,
     };
     let _ = NexusRequest::objects_post(
@@ -1362,6 +1522,7 @@ async fn test_instance_with_single_explicit_ip_address(
         network_interfaces: interface_params,
         external_ips: vec![],
         disks: vec![],
+        affinity_groups: vec![],
         start: true,
     };
     let response = NexusRequest::objects_post(
@@ -1475,6 +1636,7 @@ async fn test_instance_with_new_custom_network_interfaces(
         network_interfaces: interface_params,
         external_ips: vec![],
         disks: vec![],
+        affinity_groups: vec![],
         start: true,
     };
     let response = NexusRequest::objects_post(
@@ -1588,6 +1750,7 @@ async fn test_instance_create_delete_network_interface(
         network_interfaces: params::InstanceNetworkInterfaceAttachment::None,
         external_ips: vec![],
         disks: vec![],
+        affinity_groups: vec![],
         start: true,
     };
     let response = NexusRequest::objects_post(
@@ -1828,6 +1991,7 @@ async fn test_instance_update_network_interfaces(
         network_interfaces: params::InstanceNetworkInterfaceAttachment::None,
         external_ips: vec![],
         disks: vec![],
+        affinity_groups: vec![],
         start: true,
     };
     let response = NexusRequest::objects_post(
@@ -2220,6 +2384,7 @@ async fn test_instance_with_multiple_nics_unwinds_completely(
         network_interfaces: interface_params,
         external_ips: vec![],
         disks: vec![],
+        affinity_groups: vec![],
         start: true,
     };
     let builder =
@@ -2289,6 +2454,7 @@ async fn test_attach_one_disk_to_instance(cptestctx: &ControlPlaneTestContext) {
                 name: Name::try_from(String::from("probablydata")).unwrap(),
             },
         )],
+        affinity_groups: vec![],
         start: true,
     };
 
@@ -2362,6 +2528,7 @@ async fn test_instance_create_attach_disks(
                 },
             ),
         ],
+        affinity_groups: vec![],
         start: true,
     };
 
@@ -2457,6 +2624,7 @@ async fn test_instance_create_attach_disks_undo(
                 params::InstanceDiskAttach { name: faulted_disk.identity.name },
             ),
         ],
+        affinity_groups: vec![],
         start: true,
     };
 
@@ -2533,6 +2701,7 @@ async fn test_attach_eight_disks_to_instance(
                 )
             })
             .collect(),
+        affinity_groups: vec![],
         start: true,
     };
 
@@ -2613,6 +2782,7 @@ async fn test_cannot_attach_nine_disks_to_instance(
                 )
             })
             .collect(),
+        affinity_groups: vec![],
         start: true,
     };
 
@@ -2707,6 +2877,7 @@ async fn test_cannot_attach_faulted_disks(cptestctx: &ControlPlaneTestContext) {
                 )
             })
             .collect(),
+        affinity_groups: vec![],
         start: true,
     };
 
@@ -2790,6 +2961,7 @@ async fn test_disks_detached_when_instance_destroyed(
                 )
             })
             .collect(),
+        affinity_groups: vec![],
         start: true,
     };
 
@@ -2874,6 +3046,7 @@ async fn test_disks_detached_when_instance_destroyed(
                 )
             })
             .collect(),
+        affinity_groups: vec![],
         start: true,
     };
 
@@ -2926,6 +3099,7 @@ async fn test_instances_memory_rejected_less_than_min_memory_size(
         network_interfaces: params::InstanceNetworkInterfaceAttachment::Default,
         external_ips: vec![],
         disks: vec![],
+        affinity_groups: vec![],
         start: true,
     };
 
@@ -2975,6 +3149,7 @@ async fn test_instances_memory_not_divisible_by_min_memory_size(
         network_interfaces: params::InstanceNetworkInterfaceAttachment::Default,
         external_ips: vec![],
         disks: vec![],
+        affinity_groups: vec![],
         start: true,
     };
 
@@ -3024,6 +3199,7 @@ async fn test_instances_memory_greater_than_max_size(
         network_interfaces: params::InstanceNetworkInterfaceAttachment::Default,
         external_ips: vec![],
         disks: vec![],
+        affinity_groups: vec![],
         start: true,
     };
 
@@ -3117,6 +3293,7 @@ async fn test_cannot_provision_instance_beyond_cpu_capacity(
         network_interfaces: params::InstanceNetworkInterfaceAttachment::Default,
         external_ips: vec![],
         disks: vec![],
+        affinity_groups: vec![],
         start: false,
     };
     let url_instances = get_instances_url();
@@ -3174,6 +3351,7 @@ async fn test_cannot_provision_instance_beyond_cpu_limit(
         network_interfaces: params::InstanceNetworkInterfaceAttachment::Default,
         external_ips: vec![],
         disks: vec![],
+        affinity_groups: vec![],
         start: false,
     };
     let url_instances = get_instances_url();
@@ -3221,6 +3399,7 @@ async fn test_cannot_provision_instance_beyond_ram_capacity(
         network_interfaces: params::InstanceNetworkInterfaceAttachment::Default,
         external_ips: vec![],
         disks: vec![],
+        affinity_groups: vec![],
         start: false,
     };
     let url_instances = get_instances_url();
@@ -3550,6 +3729,7 @@ async fn test_instance_create_in_silo(cptestctx: &ControlPlaneTestContext) {
             pool_name: Some(Name::try_from(String::from("default")).unwrap()),
         }],
         disks: vec![],
+        affinity_groups: vec![],
         start: true,
     };
     let url_instances = format!("/v1/instances?project={}", PROJECT_NAME);
@@ -7,13 +7,15 @@ use dropshot::test_util::ClientTestContext;
 use dropshot::ResultsPage;
 use http::{Method, StatusCode};
 use nexus_db_queries::db::fixed_data::silo::SILO_ID;
+use nexus_defaults::DEFAULT_PRIMARY_NIC_NAME;
 use nexus_test_utils::http_testing::{AuthnMode, NexusRequest, RequestBuilder};
 use nexus_test_utils::resource_helpers::{
-    create_disk, create_instance, create_project, objects_list_page_authz,
-    populate_ip_pool, DiskTest,
+    create_disk, create_instance, create_instance_with, create_project,
+    objects_list_page_authz, populate_ip_pool, DiskTest,
 };
 use nexus_test_utils::ControlPlaneTestContext;
 use nexus_test_utils_macros::nexus_test;
+use nexus_types::external_api::params;
 use oximeter::types::Datum;
 use oximeter::types::Measurement;
 use uuid::Uuid;
@@ -230,4 +232,121 @@ async fn test_metrics(
 
     // project 1 unaffected by project 2's resources
     assert_silo_metrics(&cptestctx, Some(project1_id), GIB, 4, GIB).await;
+
+    // metrics can be aggregated over windows of time
+    let url = format!(
+        "/v1/system/metrics/cpus_provisioned?start_time={:?}&end_time={:?}&order=descending&limit=1&aggregation=max&interval=3600",
+        cptestctx.start_time,
+        Utc::now(),
+    );
+    let measurements =
+        objects_list_page_authz::<Measurement>(client, &url).await;
+    assert_eq!(measurements.items.len(), 1);
+    assert_eq!(measurements.items[0].datum(), &Datum::F64(8.0));
+
+    // but only with an aggregation that applies to the metric
+    let url = format!(
+        "/v1/system/metrics/cpus_provisioned?start_time={:?}&end_time={:?}&aggregation=rate",
+        cptestctx.start_time,
+        Utc::now(),
+    );
+    NexusRequest::new(
+        RequestBuilder::new(client, Method::GET, &url)
+            .expect_status(Some(StatusCode::BAD_REQUEST)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .expect("unexpected success");
+}
+
+#[nexus_test]
+async fn test_instance_metrics(
+    cptestctx: &ControlPlaneTestContext<omicron_nexus::Server>,
+) {
+    let client = &cptestctx.external_client;
+    populate_ip_pool(&client, "default", None).await;
+    DiskTest::new(cptestctx).await;
+    create_project(&client, "p-1").await;
+    create_disk(&client, "p-1", "d-1").await;
+    create_instance_with(
+        &client,
+        "p-1",
+        "i-1",
+        &params::InstanceNetworkInterfaceAttachment::Default,
+        vec![params::InstanceDiskAttachment::Attach(
+            params::InstanceDiskAttach { name: "d-1".parse().unwrap() },
+        )],
+        Vec::<params::ExternalIpCreate>::new(),
+    )
+    .await;
+
+    // The simulated sled agent reports samples for each of the instance's
+    // vCPUs, NICs, and disks.
+    cptestctx.oximeter.force_collect().await;
+    cptestctx.oximeter.force_collect().await;
+    let metric_url = |metric: &str, device: &str| {
+        format!(
+            "/v1/instances/i-1/metrics/{metric}?{device}&start_time={:?}&end_time={:?}&project=p-1",
+            cptestctx.start_time,
+            Utc::now(),
+        )
+    };
+    let nic = format!("network_interface={DEFAULT_PRIMARY_NIC_NAME}");
+    for (metric, device) in [
+        ("vcpu_usage", "vcpu=3"),
+        ("nic_packets_received", nic.as_str()),
+        ("nic_packets_sent", nic.as_str()),
+        ("nic_bytes_received", nic.as_str()),
+        ("nic_bytes_sent", nic.as_str()),
+        ("disk_read", "disk=d-1"),
+        ("disk_read_bytes", "disk=d-1"),
+        ("disk_write", "disk=d-1"),
+        ("disk_write_bytes", "disk=d-1"),
+    ] {
+        let measurements =
+            query_for_metrics(client, &metric_url(metric, device)).await;
+        let mut last = 0;
+        for item in &measurements.items {
+            let cumulative = match item.datum() {
+                Datum::CumulativeI64(c) => c,
+                _ => panic!("Unexpected datum type {:?}", item.datum()),
+            };
+            assert!(cumulative.start_time() <= item.timestamp());
+            assert!(cumulative.value() >= last, "{metric} went backwards");
+            last = cumulative.value();
+        }
+    }
+
+    // There's no data for devices the instance doesn't have.
+    let measurements = objects_list_page_authz::<Measurement>(
+        client,
+        &metric_url("vcpu_usage", "vcpu=4"),
+    )
+    .await;
+    assert!(measurements.items.is_empty());
+
+    // Each metric must be given exactly the kind of device it describes.
+    for (metric, device) in [
+        ("vcpu_usage", ""),
+        ("vcpu_usage", "vcpu=0&disk=d-1"),
+        ("nic_bytes_sent", "vcpu=0"),
+        ("disk_read", nic.as_str()),
+    ] {
+        NexusRequest::new(
+            RequestBuilder::new(
+                client,
+                Method::GET,
+                &metric_url(metric, device),
+            )
+            .expect_status(Some(StatusCode::BAD_REQUEST)),
+        )
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .expect("unexpected success");
+    }
+
+    // Devices must exist.
+    assert_404(&cptestctx, &metric_url("disk_read", "disk=d-2")).await;
 }
@@ -4,6 +4,8 @@
 //! the way it is.
 
 mod address_lots;
+mod affinity_groups;
+mod audit_log;
 mod authn_http;
 mod authz;
 mod basic;
@@ -12,22 +14,26 @@ mod commands;
 mod console_api;
 mod device_auth;
 mod disks;
+mod external_ips;
 mod images;
 mod initialization;
 mod instances;
 mod ip_pools;
 mod loopback_address;
 mod metrics;
+mod oidc;
 mod oximeter;
 mod pantry;
 mod password_login;
 mod projects;
+mod quotas;
 mod rack;
 mod role_assignments;
 mod roles_builtin;
 mod router_routes;
 mod saml;
 mod schema;
+mod scim;
 mod silo_users;
 mod silos;
 mod sleds;
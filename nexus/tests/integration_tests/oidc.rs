@@ -0,0 +1,439 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests for OpenID Connect identity providers
+
+use nexus_test_utils::http_testing::{AuthnMode, NexusRequest, RequestBuilder};
+use nexus_test_utils::resource_helpers::{create_silo, object_create};
+use nexus_test_utils_macros::nexus_test;
+use nexus_types::external_api::views;
+use nexus_types::external_api::{params, shared};
+use omicron_common::api::external::IdentityMetadataCreateParams;
+
+use base64::Engine;
+use dropshot::HttpErrorResponseBody;
+use http::method::Method;
+use http::StatusCode;
+use httptest::{matchers::*, responders::*, Expectation, Server};
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, Private};
+use openssl::rsa::Rsa;
+use openssl::sign::Signer;
+
+type ControlPlaneTestContext =
+    nexus_test_utils::ControlPlaneTestContext<omicron_nexus::Server>;
+
+const SILO_NAME: &str = "oidc-silo";
+const PROVIDER_NAME: &str = "some-totally-real-oidc-provider";
+const CLIENT_ID: &str = "client_id";
+const KEY_ID: &str = "test-key";
+
+fn b64(data: impl AsRef<[u8]>) -> String {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(data)
+}
+
+/// A stand-in for an OpenID provider, serving discovery metadata and signing
+/// keys, and signing ID tokens on request.
+struct TestOidcProvider {
+    server: Server,
+    key: PKey<Private>,
+}
+
+impl TestOidcProvider {
+    fn new() -> Self {
+        let server = Server::run();
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let rsa = key.rsa().unwrap();
+
+        let issuer = server.url_str("/oidc");
+        server.expect(
+            Expectation::matching(request::method_path(
+                "GET",
+                "/oidc/.well-known/openid-configuration",
+            ))
+            .times(..)
+            .respond_with(json_encoded(serde_json::json!({
+                "issuer": issuer,
+                "authorization_endpoint": server.url_str("/oidc/authorize"),
+                "token_endpoint": server.url_str("/oidc/token"),
+                "jwks_uri": server.url_str("/oidc/jwks"),
+                "code_challenge_methods_supported": ["S256"],
+            }))),
+        );
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/oidc/jwks"))
+                .times(..)
+                .respond_with(json_encoded(serde_json::json!({
+                    "keys": [{
+                        "kty": "RSA",
+                        "kid": KEY_ID,
+                        "use": "sig",
+                        "n": b64(rsa.n().to_vec()),
+                        "e": b64(rsa.e().to_vec()),
+                    }],
+                }))),
+        );
+
+        Self { server, key }
+    }
+
+    fn issuer(&self) -> String {
+        self.server.url_str("/oidc")
+    }
+
+    /// Returns a signed ID token with the given claims
+    fn id_token(&self, claims: serde_json::Value) -> String {
+        let header = serde_json::json!({
+            "alg": "RS256",
+            "typ": "JWT",
+            "kid": KEY_ID,
+        });
+        let signing_input = format!(
+            "{}.{}",
+            b64(serde_json::to_vec(&header).unwrap()),
+            b64(serde_json::to_vec(&claims).unwrap()),
+        );
+        let mut signer =
+            Signer::new(MessageDigest::sha256(), &self.key).unwrap();
+        signer.update(signing_input.as_bytes()).unwrap();
+        format!("{}.{}", signing_input, b64(signer.sign_to_vec().unwrap()))
+    }
+
+    /// Have the token endpoint respond to the next code exchange with `token`
+    fn expect_code_exchange(&self, code: &'static str, id_token: String) {
+        self.server.expect(
+            Expectation::matching(all_of![
+                request::method_path("POST", "/oidc/token"),
+                request::body(url_decoded(contains((
+                    "grant_type",
+                    "authorization_code"
+                )))),
+                request::body(url_decoded(contains(("code", code)))),
+                request::body(url_decoded(contains(key("code_verifier")))),
+            ])
+            .respond_with(json_encoded(serde_json::json!({
+                "access_token": "unused",
+                "token_type": "Bearer",
+                "id_token": id_token,
+            }))),
+        );
+    }
+}
+
+fn provider_create_params(
+    issuer: String,
+) -> params::OidcIdentityProviderCreate {
+    params::OidcIdentityProviderCreate {
+        identity: IdentityMetadataCreateParams {
+            name: PROVIDER_NAME.parse().unwrap(),
+            description: "a demo provider".to_string(),
+        },
+        issuer,
+        client_id: CLIENT_ID.to_string(),
+        client_secret: "client_secret".to_string(),
+        redirect_uri: format!(
+            "https://customer.site/login/{}/oidc/{}/callback",
+            SILO_NAME, PROVIDER_NAME
+        ),
+        scopes: vec!["email".to_string()],
+        group_claim_name: Some("groups".to_string()),
+    }
+}
+
+async fn create_oidc_silo(
+    client: &dropshot::test_util::ClientTestContext,
+    provider: &TestOidcProvider,
+) -> views::OidcIdentityProvider {
+    create_silo(&client, SILO_NAME, true, shared::SiloIdentityMode::OidcJit)
+        .await;
+    object_create(
+        client,
+        &format!("/v1/system/identity-providers/oidc?silo={}", SILO_NAME),
+        &provider_create_params(provider.issuer()),
+    )
+    .await
+}
+
+/// Begin a login, returning the login cookie and the query parameters Nexus
+/// sent to the authorization endpoint.
+async fn begin_login(
+    client: &dropshot::test_util::ClientTestContext,
+) -> (String, Vec<(String, String)>) {
+    let result = NexusRequest::new(
+        RequestBuilder::new(
+            client,
+            Method::GET,
+            &format!("/login/{}/oidc/{}", SILO_NAME, PROVIDER_NAME),
+        )
+        .expect_status(Some(StatusCode::FOUND)),
+    )
+    .execute()
+    .await
+    .expect("expected success");
+
+    let location: reqwest::Url =
+        result.headers["Location"].to_str().unwrap().parse().unwrap();
+    let query = location
+        .query_pairs()
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect();
+    let cookie = result.headers["Set-Cookie"]
+        .to_str()
+        .unwrap()
+        .split(';')
+        .next()
+        .unwrap()
+        .to_string();
+    (cookie, query)
+}
+
+fn query_value<'a>(query: &'a [(String, String)], name: &str) -> &'a str {
+    query
+        .iter()
+        .find(|(k, _)| k == name)
+        .map(|(_, v)| v.as_str())
+        .unwrap_or_else(|| panic!("authorization URL is missing {}", name))
+}
+
+#[nexus_test]
+async fn test_create_an_oidc_identity_provider(
+    cptestctx: &ControlPlaneTestContext,
+) {
+    let client = &cptestctx.external_client;
+    let provider = TestOidcProvider::new();
+
+    let created = create_oidc_silo(client, &provider).await;
+    assert_eq!(created.issuer, provider.issuer());
+    assert_eq!(created.token_endpoint, provider.server.url_str("/oidc/token"));
+    assert_eq!(created.scopes, vec!["email".to_string()]);
+
+    let fetched: views::OidcIdentityProvider = NexusRequest::object_get(
+        client,
+        &format!(
+            "/v1/system/identity-providers/oidc/{}?silo={}",
+            PROVIDER_NAME, SILO_NAME
+        ),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .expect("failed to fetch provider")
+    .parsed_body()
+    .unwrap();
+    assert_eq!(fetched.identity.id, created.identity.id);
+
+    // The client secret must never be returned.
+    let raw = NexusRequest::object_get(
+        client,
+        &format!(
+            "/v1/system/identity-providers/oidc/{}?silo={}",
+            PROVIDER_NAME, SILO_NAME
+        ),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .expect("failed to fetch provider");
+    assert!(!String::from_utf8_lossy(&raw.body).contains("client_secret"));
+}
+
+#[nexus_test]
+async fn test_oidc_provider_requires_oidc_silo(
+    cptestctx: &ControlPlaneTestContext,
+) {
+    let client = &cptestctx.external_client;
+    let provider = TestOidcProvider::new();
+
+    create_silo(&client, SILO_NAME, true, shared::SiloIdentityMode::SamlJit)
+        .await;
+
+    NexusRequest::expect_failure_with_body(
+        client,
+        StatusCode::BAD_REQUEST,
+        Method::POST,
+        &format!("/v1/system/identity-providers/oidc?silo={}", SILO_NAME),
+        &provider_create_params(provider.issuer()),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .expect("expected provider creation to fail");
+}
+
+#[nexus_test]
+async fn test_oidc_provider_issuer_mismatch(
+    cptestctx: &ControlPlaneTestContext,
+) {
+    let client = &cptestctx.external_client;
+    let provider = TestOidcProvider::new();
+
+    create_silo(&client, SILO_NAME, true, shared::SiloIdentityMode::OidcJit)
+        .await;
+
+    // The discovery document names an issuer without the trailing slash.
+    let error: HttpErrorResponseBody = NexusRequest::expect_failure_with_body(
+        client,
+        StatusCode::BAD_REQUEST,
+        Method::POST,
+        &format!("/v1/system/identity-providers/oidc?silo={}", SILO_NAME),
+        &provider_create_params(format!("{}/", provider.issuer())),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .expect("expected provider creation to fail")
+    .parsed_body()
+    .unwrap();
+    assert!(
+        error.message.contains("does not match"),
+        "unexpected error message: {}",
+        error.message
+    );
+}
+
+#[nexus_test]
+async fn test_oidc_login(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    let provider = TestOidcProvider::new();
+    create_oidc_silo(client, &provider).await;
+
+    let (login_cookie, query) = begin_login(client).await;
+    assert_eq!(query_value(&query, "response_type"), "code");
+    assert_eq!(query_value(&query, "client_id"), CLIENT_ID);
+    assert_eq!(query_value(&query, "scope"), "openid email");
+    assert_eq!(query_value(&query, "code_challenge_method"), "S256");
+    let state = query_value(&query, "state").to_string();
+    let nonce = query_value(&query, "nonce").to_string();
+
+    provider.expect_code_exchange(
+        "the-code",
+        provider.id_token(serde_json::json!({
+            "iss": provider.issuer(),
+            "sub": "some@customer.com",
+            "aud": CLIENT_ID,
+            "iat": chrono::Utc::now().timestamp(),
+            "exp": chrono::Utc::now().timestamp() + 300,
+            "nonce": nonce,
+            "groups": ["SRE", "Admins"],
+        })),
+    );
+
+    let result = NexusRequest::new(
+        RequestBuilder::new(
+            client,
+            Method::GET,
+            &format!(
+                "/login/{}/oidc/{}/callback?code=the-code&state={}",
+                SILO_NAME, PROVIDER_NAME, state
+            ),
+        )
+        .header(http::header::COOKIE, login_cookie)
+        .expect_status(Some(StatusCode::SEE_OTHER)),
+    )
+    .execute()
+    .await
+    .expect("expected success");
+
+    assert_eq!(result.headers["Location"].to_str().unwrap(), "/");
+
+    let session_cookie_value = result
+        .headers
+        .get_all(http::header::SET_COOKIE)
+        .iter()
+        .map(|v| v.to_str().unwrap())
+        .find(|v| v.starts_with("session="))
+        .expect("expected a session cookie")
+        .to_string();
+
+    let session_me = NexusRequest::new(
+        RequestBuilder::new(client, Method::GET, "/v1/me")
+            .header(http::header::COOKIE, session_cookie_value.clone())
+            .expect_status(Some(StatusCode::OK)),
+    )
+    .execute_and_parse_unwrap::<views::CurrentUser>()
+    .await;
+    assert_eq!(session_me.user.display_name, "some@customer.com");
+
+    let groups: dropshot::ResultsPage<views::Group> = NexusRequest::new(
+        RequestBuilder::new(client, Method::GET, "/v1/me/groups")
+            .header(http::header::COOKIE, session_cookie_value)
+            .expect_status(Some(StatusCode::OK)),
+    )
+    .execute()
+    .await
+    .expect("expected success")
+    .parsed_body()
+    .unwrap();
+    nexus_test_utils::assert_same_items(
+        groups.items.iter().map(|g| g.display_name.as_str()).collect(),
+        vec!["SRE", "Admins"],
+    );
+}
+
+#[nexus_test]
+async fn test_oidc_login_rejects_mismatched_state(
+    cptestctx: &ControlPlaneTestContext,
+) {
+    let client = &cptestctx.external_client;
+    let provider = TestOidcProvider::new();
+    create_oidc_silo(client, &provider).await;
+
+    let (login_cookie, _) = begin_login(client).await;
+
+    NexusRequest::new(
+        RequestBuilder::new(
+            client,
+            Method::GET,
+            &format!(
+                "/login/{}/oidc/{}/callback?code=the-code&state=forged",
+                SILO_NAME, PROVIDER_NAME
+            ),
+        )
+        .header(http::header::COOKIE, login_cookie)
+        .expect_status(Some(StatusCode::BAD_REQUEST)),
+    )
+    .execute()
+    .await
+    .expect("expected failure");
+}
+
+#[nexus_test]
+async fn test_oidc_login_rejects_wrong_nonce(
+    cptestctx: &ControlPlaneTestContext,
+) {
+    let client = &cptestctx.external_client;
+    let provider = TestOidcProvider::new();
+    create_oidc_silo(client, &provider).await;
+
+    let (login_cookie, query) = begin_login(client).await;
+    let state = query_value(&query, "state").to_string();
+
+    // An ID token issued for some other login attempt must not be accepted.
+    provider.expect_code_exchange(
+        "the-code",
+        provider.id_token(serde_json::json!({
+            "iss": provider.issuer(),
+            "sub": "some@customer.com",
+            "aud": CLIENT_ID,
+            "exp": chrono::Utc::now().timestamp() + 300,
+            "nonce": "some-other-nonce",
+        })),
+    );
+
+    NexusRequest::new(
+        RequestBuilder::new(
+            client,
+            Method::GET,
+            &format!(
+                "/login/{}/oidc/{}/callback?code=the-code&state={}",
+                SILO_NAME, PROVIDER_NAME, state
+            ),
+        )
+        .header(http::header::COOKIE, login_cookie)
+        .expect_status(Some(StatusCode::UNAUTHORIZED)),
+    )
+    .execute()
+    .await
+    .expect("expected failure");
+}
@@ -4,11 +4,17 @@
 
 //! Integration tests for oximeter collectors and producers.
 
+use chrono::Utc;
+use dropshot::PaginationOrder;
+use nexus_db_queries::db::identity::Asset;
+use nexus_db_queries::db::model::PushProducer;
 use nexus_test_interface::NexusServer;
 use nexus_test_utils_macros::nexus_test;
+use omicron_common::api::external::DataPageParams;
 use omicron_test_utils::dev::poll::{wait_for_condition, CondCheckError};
 use oximeter_db::DbWrite;
 use std::net;
+use std::num::NonZeroU32;
 use std::time::Duration;
 use uuid::Uuid;
 
@@ -63,6 +69,81 @@ async fn test_oximeter_database_records(context: &ControlPlaneTestContext) {
     );
 }
 
+#[derive(Debug, Clone, oximeter::Target)]
+struct PushTarget {
+    pub target_name: String,
+}
+
+#[derive(Debug, Clone, oximeter::Metric)]
+struct PushMetric {
+    pub datum: i64,
+}
+
+#[nexus_test]
+async fn test_oximeter_push_producer(context: &ControlPlaneTestContext) {
+    let log = &context.logctx.log;
+    let push_client = oximeter_producer::PushClient::new(
+        Uuid::new_v4(),
+        context.server.get_http_server_internal_address().await,
+        log,
+    )
+    .await
+    .expect("Failed to register push producer");
+    assert_eq!(
+        push_client.collector_address(),
+        context.oximeter.server_address(),
+        "Nexus should assign the push producer to the only collector",
+    );
+
+    // Push a few samples, without running a producer server.
+    let target = PushTarget { target_name: String::from("push-test-target") };
+    let samples = (0..3)
+        .map(|datum| {
+            oximeter::types::Sample::new(&target, &PushMetric { datum })
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+    push_client.push(&samples).await.expect("Failed to push samples");
+
+    // The samples are inserted along with those collected from other
+    // producers.
+    let ch_address = net::SocketAddrV6::new(
+        "::1".parse().unwrap(),
+        context.clickhouse.port(),
+        0,
+        0,
+    );
+    let client = oximeter_db::Client::new(ch_address.into(), log);
+    let timeseries_name = "push_target:push_metric";
+    let timeseries = wait_for_condition(
+        || async {
+            match client
+                .select_timeseries_with(
+                    timeseries_name,
+                    &[],
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+                .await
+            {
+                Ok(timeseries) if !timeseries.is_empty() => Ok(timeseries),
+                Ok(_) | Err(oximeter_db::Error::TimeseriesNotFound(_)) => {
+                    Err(CondCheckError::NotYet)
+                }
+                Err(e) => Err(CondCheckError::from(e)),
+            }
+        },
+        &Duration::from_millis(100),
+        &Duration::from_secs(30),
+    )
+    .await
+    .expect("Failed to retrieve pushed timeseries");
+    assert_eq!(timeseries.len(), 1);
+    assert_eq!(timeseries[0].measurements.len(), samples.len());
+}
+
 #[tokio::test]
 async fn test_oximeter_reregistration() {
     let mut context = nexus_test_utils::test_setup::<omicron_nexus::Server>(
@@ -332,3 +413,35 @@ async fn test_oximeter_reregistration() {
     );
     context.teardown().await;
 }
+
+#[nexus_test]
+async fn test_oximeter_push_producer_expiration(
+    context: &ControlPlaneTestContext,
+) {
+    let datastore = context.server.apictx().nexus.datastore();
+    let oximeter_id = nexus_test_utils::OXIMETER_UUID.parse().unwrap();
+
+    let live = PushProducer::new(Uuid::new_v4(), oximeter_id);
+    let mut expired = PushProducer::new(Uuid::new_v4(), oximeter_id);
+    expired.time_expires = Utc::now() - chrono::Duration::seconds(1);
+    datastore.push_producer_create(&live).await.unwrap();
+    datastore.push_producer_create(&expired).await.unwrap();
+
+    // Producers whose registrations have expired aren't assigned to the
+    // collector any more...
+    let pagparams = DataPageParams {
+        marker: None,
+        direction: PaginationOrder::Ascending,
+        limit: NonZeroU32::new(100).unwrap(),
+    };
+    let producers = datastore
+        .push_producers_list_by_oximeter_id(oximeter_id, &pagparams)
+        .await
+        .unwrap();
+    let ids = producers.iter().map(|p| p.id()).collect::<Vec<_>>();
+    assert_eq!(ids, vec![live.id()]);
+
+    // ... and their records are deleted by the background task.
+    assert_eq!(datastore.push_producers_delete_expired().await.unwrap(), 1);
+    assert_eq!(datastore.push_producers_delete_expired().await.unwrap(), 0);
+}
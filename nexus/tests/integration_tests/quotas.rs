@@ -0,0 +1,226 @@
+//! Tests for silo and project resource quotas
+
+use dropshot::HttpErrorResponseBody;
+use http::method::Method;
+use http::StatusCode;
+use nexus_test_utils::http_testing::AuthnMode;
+use nexus_test_utils::http_testing::NexusRequest;
+use nexus_test_utils::resource_helpers::create_disk;
+use nexus_test_utils::resource_helpers::create_instance;
+use nexus_test_utils::resource_helpers::create_project;
+use nexus_test_utils::resource_helpers::object_put;
+use nexus_test_utils::resource_helpers::DiskTest;
+use nexus_test_utils_macros::nexus_test;
+use nexus_types::external_api::params;
+use nexus_types::external_api::views::ResourceQuotas;
+use omicron_common::api::external::ByteCount;
+use omicron_common::api::external::IdentityMetadataCreateParams;
+use omicron_common::api::external::InstanceCpuCount;
+
+type ControlPlaneTestContext =
+    nexus_test_utils::ControlPlaneTestContext<omicron_nexus::Server>;
+
+const PROJECT_NAME: &str = "quota-project";
+
+fn instance_create_params(name: &str, ncpus: u16) -> params::InstanceCreate {
+    params::InstanceCreate {
+        identity: IdentityMetadataCreateParams {
+            name: name.parse().unwrap(),
+            description: format!("instance {:?}", name),
+        },
+        ncpus: InstanceCpuCount(ncpus),
+        memory: ByteCount::from_gibibytes_u32(1),
+        hostname: String::from("the_host"),
+        user_data: vec![],
+        network_interfaces: params::InstanceNetworkInterfaceAttachment::Default,
+        external_ips: vec![],
+        disks: vec![],
+        affinity_groups: vec![],
+        affinity_groups: vec![],
+        start: true,
+    }
+}
+
+fn disk_create_params(name: &str, gibibytes: u32) -> params::DiskCreate {
+    params::DiskCreate {
+        identity: IdentityMetadataCreateParams {
+            name: name.parse().unwrap(),
+            description: String::from("a disk"),
+        },
+        disk_source: params::DiskSource::Blank {
+            block_size: params::BlockSize::try_from(512).unwrap(),
+        },
+        size: ByteCount::from_gibibytes_u32(gibibytes),
+    }
+}
+
+async fn expect_quota_error<T: serde::Serialize>(
+    client: &dropshot::test_util::ClientTestContext,
+    url: &str,
+    body: &T,
+) -> HttpErrorResponseBody {
+    NexusRequest::expect_failure_with_body(
+        client,
+        StatusCode::BAD_REQUEST,
+        Method::POST,
+        url,
+        body,
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .expect("expected provisioning to fail due to quota")
+    .parsed_body()
+    .unwrap()
+}
+
+#[nexus_test]
+async fn test_quotas_default_to_unlimited(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    create_project(client, PROJECT_NAME).await;
+
+    let quotas: ResourceQuotas = NexusRequest::object_get(
+        client,
+        &format!("/v1/projects/{}/quotas", PROJECT_NAME),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .expect("failed to fetch project quotas")
+    .parsed_body()
+    .unwrap();
+    assert_eq!(
+        quotas,
+        ResourceQuotas { cpus: None, memory: None, storage: None }
+    );
+
+    let quotas: ResourceQuotas = NexusRequest::object_get(
+        client,
+        &format!("/v1/system/silos/{}/quotas", cptestctx.silo_name),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .expect("failed to fetch silo quotas")
+    .parsed_body()
+    .unwrap();
+    assert_eq!(
+        quotas,
+        ResourceQuotas { cpus: None, memory: None, storage: None }
+    );
+}
+
+#[nexus_test]
+async fn test_project_cpu_quota(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    create_project(client, PROJECT_NAME).await;
+    let instances_url = format!("/v1/instances?project={}", PROJECT_NAME);
+
+    // Allow exactly one of the 4-vCPU instances created by `create_instance`.
+    let quotas: ResourceQuotas = object_put(
+        client,
+        &format!("/v1/projects/{}/quotas", PROJECT_NAME),
+        &params::ResourceQuotasUpdate { cpus: Some(6), ..Default::default() },
+    )
+    .await;
+    assert_eq!(quotas.cpus, Some(6));
+    assert_eq!(quotas.memory, None);
+    assert_eq!(quotas.storage, None);
+
+    create_instance(client, PROJECT_NAME, "inst0").await;
+
+    let error = expect_quota_error(
+        client,
+        &instances_url,
+        &instance_create_params("inst1", 4),
+    )
+    .await;
+    assert!(
+        error.message.starts_with("vCPU quota exceeded"),
+        "unexpected error message: {}",
+        error.message
+    );
+
+    // A smaller instance still fits.
+    NexusRequest::objects_post(
+        client,
+        &instances_url,
+        &instance_create_params("inst2", 2),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .expect("failed to create instance within quota");
+
+    // Removing the limit allows further instances to be created.
+    let _: ResourceQuotas = object_put(
+        client,
+        &format!("/v1/projects/{}/quotas", PROJECT_NAME),
+        &params::ResourceQuotasUpdate::default(),
+    )
+    .await;
+    create_instance(client, PROJECT_NAME, "inst3").await;
+}
+
+#[nexus_test]
+async fn test_silo_storage_quota(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    DiskTest::new(&cptestctx).await;
+    create_project(client, PROJECT_NAME).await;
+    let disks_url = format!("/v1/disks?project={}", PROJECT_NAME);
+
+    // Quotas set on the silo apply to all of the projects within it.
+    let quotas: ResourceQuotas = object_put(
+        client,
+        &format!("/v1/system/silos/{}/quotas", cptestctx.silo_name),
+        &params::ResourceQuotasUpdate {
+            storage: Some(ByteCount::from_gibibytes_u32(2)),
+            ..Default::default()
+        },
+    )
+    .await;
+    assert_eq!(quotas.storage, Some(ByteCount::from_gibibytes_u32(2)));
+
+    create_disk(client, PROJECT_NAME, "disk0").await;
+
+    let error =
+        expect_quota_error(client, &disks_url, &disk_create_params("disk1", 2))
+            .await;
+    assert!(
+        error.message.starts_with("storage quota exceeded"),
+        "unexpected error message: {}",
+        error.message
+    );
+
+    // The failed request must not have consumed any of the quota.
+    NexusRequest::objects_post(
+        client,
+        &disks_url,
+        &disk_create_params("disk2", 1),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .expect("failed to create disk within quota");
+}
+
+#[nexus_test]
+async fn test_project_quota_requires_silo_modify(
+    cptestctx: &ControlPlaneTestContext,
+) {
+    let client = &cptestctx.external_client;
+    create_project(client, PROJECT_NAME).await;
+
+    // The unprivileged user cannot raise a project's quota.
+    NexusRequest::expect_failure_with_body(
+        client,
+        StatusCode::NOT_FOUND,
+        Method::PUT,
+        &format!("/v1/projects/{}/quotas", PROJECT_NAME),
+        &params::ResourceQuotasUpdate::default(),
+    )
+    .authn_as(AuthnMode::UnprivilegedUser)
+    .execute()
+    .await
+    .expect("expected quota update to fail");
+}
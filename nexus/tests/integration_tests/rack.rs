@@ -12,6 +12,9 @@ use nexus_test_utils_macros::nexus_test;
 use nexus_types::external_api::params;
 use nexus_types::external_api::views::Rack;
 use omicron_nexus::TestInterfaces;
+use omicron_test_utils::dev::poll::{wait_for_condition, CondCheckError};
+use std::net::IpAddr;
+use std::time::Duration;
 
 type ControlPlaneTestContext =
     nexus_test_utils::ControlPlaneTestContext<omicron_nexus::Server>;
@@ -77,3 +80,83 @@ async fn test_rack_initialization(cptestctx: &ControlPlaneTestContext) {
     )
     .await;
 }
+
+#[nexus_test]
+async fn test_rack_dns_nameservers(cptestctx: &ControlPlaneTestContext) {
+    // Each DNS zone that Nexus configures names its DNS servers as its
+    // nameservers.  In the test suite, there's one of each kind.
+    verify_dns_zone_nameservers(
+        cptestctx,
+        &cptestctx.external_dns,
+        &cptestctx.external_dns_zone_name,
+    )
+    .await;
+    verify_dns_zone_nameservers(
+        cptestctx,
+        &cptestctx.internal_dns,
+        internal_dns::DNS_ZONE,
+    )
+    .await;
+}
+
+async fn verify_dns_zone_nameservers(
+    cptestctx: &ControlPlaneTestContext,
+    dns: &dns_server::TransientServer,
+    zone_name: &str,
+) {
+    // Wait for Nexus to propagate the zone's configuration to the DNS server.
+    let client = dns_service_client::Client::new(
+        &format!("http://{}", dns.dropshot_server.local_addr()),
+        cptestctx.logctx.log.clone(),
+    );
+    let config = wait_for_condition(
+        || async {
+            let config = client
+                .dns_config_get()
+                .await
+                .expect("failed to fetch DNS configuration")
+                .into_inner();
+            let has_nameservers = config.zones.iter().any(|zone| {
+                zone.zone_name == zone_name && !zone.nameservers.is_empty()
+            });
+            if has_nameservers {
+                Ok(config)
+            } else {
+                Err(CondCheckError::<()>::NotYet)
+            }
+        },
+        &Duration::from_millis(50),
+        &Duration::from_secs(30),
+    )
+    .await
+    .expect("DNS zone was never configured with nameservers");
+    let zone = config.zones.iter().find(|z| z.zone_name == zone_name).unwrap();
+    assert_eq!(zone.nameservers, ["ns1"]);
+
+    // The DNS server reports the nameserver in the zone's NS and SOA records.
+    let resolver = dns.resolver().await.expect("failed to create resolver");
+    let zone_apex = format!("{}.", zone_name);
+    let ns1 = format!("ns1.{}", zone_apex);
+    let response = resolver
+        .ns_lookup(zone_apex.as_str())
+        .await
+        .expect("failed to look up NS records");
+    let nameservers: Vec<_> =
+        response.iter().map(|ns| ns.to_string()).collect();
+    assert_eq!(nameservers, [ns1.clone()]);
+    let response = resolver
+        .soa_lookup(zone_apex.as_str())
+        .await
+        .expect("failed to look up SOA record");
+    let soa = response.iter().next().expect("no SOA record returned");
+    assert_eq!(soa.mname().to_string(), ns1);
+    assert_eq!(u64::from(soa.serial()), config.generation);
+
+    // The nameserver's name resolves to the DNS server itself.
+    let response = resolver
+        .lookup_ip(ns1.as_str())
+        .await
+        .expect("failed to look up nameserver");
+    let addrs: Vec<IpAddr> = response.iter().collect();
+    assert_eq!(addrs, [dns.dns_server.local_address().ip()]);
+}
@@ -125,6 +125,9 @@ async fn test_create_a_saml_idp(cptestctx: &ControlPlaneTestContext) {
         IdentityProviderType::Saml(_) => {
             // ok
         }
+        IdentityProviderType::Oidc(_) => {
+            panic!("expected a SAML identity provider");
+        }
     }
 
     // Expect the SSO redirect when trying to log in unauthenticated
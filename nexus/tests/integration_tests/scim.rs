@@ -0,0 +1,450 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests for SCIM provisioning of Silo users and groups
+
+use dropshot::test_util::ClientTestContext;
+use http::method::Method;
+use http::StatusCode;
+use nexus_db_queries::context::OpContext;
+use nexus_db_queries::db::lookup::LookupPath;
+use nexus_db_queries::db::model::ConsoleSession;
+use nexus_test_utils::http_testing::AuthnMode;
+use nexus_test_utils::http_testing::NexusRequest;
+use nexus_test_utils::http_testing::RequestBuilder;
+use nexus_test_utils::resource_helpers::create_silo;
+use nexus_test_utils_macros::nexus_test;
+use nexus_types::external_api::shared;
+use nexus_types::external_api::views;
+use serde_json::json;
+use serde_json::Value;
+use uuid::Uuid;
+
+type ControlPlaneTestContext =
+    nexus_test_utils::ControlPlaneTestContext<omicron_nexus::Server>;
+
+const SILO_NAME: &str = "scim-silo";
+
+async fn scim_token_create(
+    client: &ClientTestContext,
+    silo_name: &str,
+) -> views::ScimClientBearerTokenCreated {
+    NexusRequest::objects_post(
+        client,
+        &format!("/v1/system/scim/tokens?silo={}", silo_name),
+        &Value::Null,
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .expect("failed to create SCIM token")
+    .parsed_body()
+    .unwrap()
+}
+
+/// Make a SCIM request, returning the response body (if any)
+async fn scim_request(
+    client: &ClientTestContext,
+    method: Method,
+    uri: &str,
+    bearer_token: &str,
+    body: Option<Value>,
+    expected_status: StatusCode,
+) -> Value {
+    let response = RequestBuilder::new(client, method, uri)
+        .header(http::header::AUTHORIZATION, format!("Bearer {}", bearer_token))
+        .body(body.as_ref())
+        .expect_status(Some(expected_status))
+        .allow_non_dropshot_errors()
+        .execute()
+        .await
+        .unwrap();
+    if expected_status == StatusCode::NO_CONTENT {
+        Value::Null
+    } else {
+        response.parsed_body().unwrap()
+    }
+}
+
+async fn scim_user_create(
+    client: &ClientTestContext,
+    bearer_token: &str,
+    user_name: &str,
+) -> Uuid {
+    let user = scim_request(
+        client,
+        Method::POST,
+        "/scim/v2/Users",
+        bearer_token,
+        Some(json!({
+            "schemas": ["urn:ietf:params:scim:schemas:core:2.0:User"],
+            "userName": user_name,
+            "name": { "givenName": "Some", "familyName": "One" },
+            "active": true,
+        })),
+        StatusCode::CREATED,
+    )
+    .await;
+    assert_eq!(user["userName"], user_name);
+    user["id"].as_str().unwrap().parse().unwrap()
+}
+
+fn member_ids(group: &Value) -> Vec<Uuid> {
+    let mut members: Vec<Uuid> = group["members"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|member| member["value"].as_str().unwrap().parse().unwrap())
+        .collect();
+    members.sort();
+    members
+}
+
+#[nexus_test]
+async fn test_scim_token_authentication(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+
+    // Silos whose users are managed through the API can't be provisioned by
+    // SCIM.
+    NexusRequest::expect_failure_with_body(
+        client,
+        StatusCode::BAD_REQUEST,
+        Method::POST,
+        &format!("/v1/system/scim/tokens?silo={}", cptestctx.silo_name),
+        &Value::Null,
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .expect("expected SCIM token creation to fail");
+
+    create_silo(client, SILO_NAME, true, shared::SiloIdentityMode::SamlJit)
+        .await;
+    let token = scim_token_create(client, SILO_NAME).await;
+    assert!(token.bearer_token.starts_with("oxide-scim-"));
+
+    let tokens: dropshot::ResultsPage<views::ScimClientBearerToken> =
+        NexusRequest::object_get(
+            client,
+            &format!("/v1/system/scim/tokens?silo={}", SILO_NAME),
+        )
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .expect("failed to list SCIM tokens")
+        .parsed_body()
+        .unwrap();
+    assert_eq!(tokens.items.len(), 1);
+    assert_eq!(tokens.items[0].id, token.id);
+
+    let users = scim_request(
+        client,
+        Method::GET,
+        "/scim/v2/Users",
+        &token.bearer_token,
+        None,
+        StatusCode::OK,
+    )
+    .await;
+    assert_eq!(users["totalResults"], 0);
+
+    // Requests without a valid token are rejected with a SCIM error.
+    RequestBuilder::new(client, Method::GET, "/scim/v2/Users")
+        .expect_status(Some(StatusCode::UNAUTHORIZED))
+        .allow_non_dropshot_errors()
+        .execute()
+        .await
+        .unwrap();
+    let error = scim_request(
+        client,
+        Method::GET,
+        "/scim/v2/Users",
+        "oxide-scim-0000000000000000000000000000000000000000",
+        None,
+        StatusCode::UNAUTHORIZED,
+    )
+    .await;
+    assert_eq!(error["status"], "401");
+
+    // Revoked tokens no longer work.
+    NexusRequest::object_delete(
+        client,
+        &format!("/v1/system/scim/tokens/{}?silo={}", token.id, SILO_NAME),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .expect("failed to delete SCIM token");
+    scim_request(
+        client,
+        Method::GET,
+        "/scim/v2/Users",
+        &token.bearer_token,
+        None,
+        StatusCode::UNAUTHORIZED,
+    )
+    .await;
+}
+
+#[nexus_test]
+async fn test_scim_users(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    let nexus = &cptestctx.server.apictx().nexus;
+    let datastore = nexus.datastore();
+    let opctx =
+        OpContext::for_tests(cptestctx.logctx.log.new(o!()), datastore.clone());
+
+    create_silo(client, SILO_NAME, true, shared::SiloIdentityMode::SamlJit)
+        .await;
+    let token = scim_token_create(client, SILO_NAME).await.bearer_token;
+
+    let user_id = scim_user_create(client, &token, "alice@example.com").await;
+    scim_user_create(client, &token, "bob@example.com").await;
+
+    // User names are unique within the Silo.
+    let error = scim_request(
+        client,
+        Method::POST,
+        "/scim/v2/Users",
+        &token,
+        Some(json!({ "userName": "alice@example.com" })),
+        StatusCode::CONFLICT,
+    )
+    .await;
+    assert_eq!(error["scimType"], "uniqueness");
+
+    // Identity providers look users up by name before creating them.
+    let users = scim_request(
+        client,
+        Method::GET,
+        "/scim/v2/Users?filter=userName%20eq%20%22alice%40example.com%22",
+        &token,
+        None,
+        StatusCode::OK,
+    )
+    .await;
+    assert_eq!(users["totalResults"], 1);
+    assert_eq!(users["Resources"][0]["id"], user_id.to_string());
+    assert_eq!(users["Resources"][0]["active"], true);
+
+    let users = scim_request(
+        client,
+        Method::GET,
+        "/scim/v2/Users?startIndex=2&count=10",
+        &token,
+        None,
+        StatusCode::OK,
+    )
+    .await;
+    assert_eq!(users["totalResults"], 2);
+    assert_eq!(users["startIndex"], 2);
+    assert_eq!(users["itemsPerPage"], 1);
+
+    scim_request(
+        client,
+        Method::GET,
+        "/scim/v2/Users?filter=name.givenName%20sw%20%22A%22",
+        &token,
+        None,
+        StatusCode::BAD_REQUEST,
+    )
+    .await;
+
+    // Deactivating a user revokes their console sessions.
+    let session_token = "scim-test-session-token";
+    datastore
+        .session_create(
+            &opctx,
+            ConsoleSession::new(session_token.to_string(), user_id),
+        )
+        .await
+        .unwrap();
+    let user = scim_request(
+        client,
+        Method::PATCH,
+        &format!("/scim/v2/Users/{}", user_id),
+        &token,
+        Some(json!({
+            "schemas": ["urn:ietf:params:scim:api:messages:2.0:PatchOp"],
+            "Operations": [
+                { "op": "Replace", "value": { "active": "False" } },
+            ],
+        })),
+        StatusCode::OK,
+    )
+    .await;
+    assert_eq!(user["active"], false);
+    assert!(LookupPath::new(&opctx, &datastore)
+        .console_session_token(session_token)
+        .fetch()
+        .await
+        .is_err());
+
+    // The user still exists, and can be reactivated.
+    let user = scim_request(
+        client,
+        Method::PUT,
+        &format!("/scim/v2/Users/{}", user_id),
+        &token,
+        Some(json!({ "userName": "alice@example.com", "active": true })),
+        StatusCode::OK,
+    )
+    .await;
+    assert_eq!(user["active"], true);
+
+    // The attributes we store can't be removed.
+    let error = scim_request(
+        client,
+        Method::PATCH,
+        &format!("/scim/v2/Users/{}", user_id),
+        &token,
+        Some(json!({
+            "schemas": ["urn:ietf:params:scim:api:messages:2.0:PatchOp"],
+            "Operations": [{ "op": "remove", "path": "active" }],
+        })),
+        StatusCode::BAD_REQUEST,
+    )
+    .await;
+    assert_eq!(error["scimType"], "mutability");
+    let user = scim_request(
+        client,
+        Method::GET,
+        &format!("/scim/v2/Users/{}", user_id),
+        &token,
+        None,
+        StatusCode::OK,
+    )
+    .await;
+    assert_eq!(user["active"], true);
+
+    scim_request(
+        client,
+        Method::DELETE,
+        &format!("/scim/v2/Users/{}", user_id),
+        &token,
+        None,
+        StatusCode::NO_CONTENT,
+    )
+    .await;
+    scim_request(
+        client,
+        Method::GET,
+        &format!("/scim/v2/Users/{}", user_id),
+        &token,
+        None,
+        StatusCode::NOT_FOUND,
+    )
+    .await;
+}
+
+#[nexus_test]
+async fn test_scim_groups(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+
+    create_silo(client, SILO_NAME, true, shared::SiloIdentityMode::SamlJit)
+        .await;
+    let token = scim_token_create(client, SILO_NAME).await.bearer_token;
+
+    let alice = scim_user_create(client, &token, "alice@example.com").await;
+    let bob = scim_user_create(client, &token, "bob@example.com").await;
+
+    let group = scim_request(
+        client,
+        Method::POST,
+        "/scim/v2/Groups",
+        &token,
+        Some(json!({
+            "schemas": ["urn:ietf:params:scim:schemas:core:2.0:Group"],
+            "displayName": "engineering",
+            "members": [{ "value": alice }],
+        })),
+        StatusCode::CREATED,
+    )
+    .await;
+    assert_eq!(group["displayName"], "engineering");
+    assert_eq!(member_ids(&group), vec![alice]);
+    let group_url =
+        format!("/scim/v2/Groups/{}", group["id"].as_str().unwrap());
+
+    // Members must be users of the same Silo.
+    scim_request(
+        client,
+        Method::POST,
+        "/scim/v2/Groups",
+        &token,
+        Some(json!({
+            "displayName": "marketing",
+            "members": [{ "value": Uuid::new_v4() }],
+        })),
+        StatusCode::BAD_REQUEST,
+    )
+    .await;
+
+    let group = scim_request(
+        client,
+        Method::PATCH,
+        &group_url,
+        &token,
+        Some(json!({
+            "Operations": [
+                { "op": "add", "path": "members", "value": [{ "value": bob }] },
+                {
+                    "op": "remove",
+                    "path": format!("members[value eq \"{}\"]", alice),
+                },
+            ],
+        })),
+        StatusCode::OK,
+    )
+    .await;
+    assert_eq!(member_ids(&group), vec![bob]);
+
+    let group = scim_request(
+        client,
+        Method::PUT,
+        &group_url,
+        &token,
+        Some(json!({
+            "displayName": "eng",
+            "members": [{ "value": alice }, { "value": bob }],
+        })),
+        StatusCode::OK,
+    )
+    .await;
+    assert_eq!(group["displayName"], "eng");
+    let mut both = vec![alice, bob];
+    both.sort();
+    assert_eq!(member_ids(&group), both);
+
+    let groups = scim_request(
+        client,
+        Method::GET,
+        "/scim/v2/Groups?filter=displayName%20eq%20%22eng%22",
+        &token,
+        None,
+        StatusCode::OK,
+    )
+    .await;
+    assert_eq!(groups["totalResults"], 1);
+    assert_eq!(member_ids(&groups["Resources"][0]), both);
+
+    scim_request(
+        client,
+        Method::DELETE,
+        &group_url,
+        &token,
+        None,
+        StatusCode::NO_CONTENT,
+    )
+    .await;
+    scim_request(
+        client,
+        Method::GET,
+        &group_url,
+        &token,
+        None,
+        StatusCode::NOT_FOUND,
+    )
+    .await;
+}
@@ -772,6 +772,17 @@ async fn test_silo_user_provision_types(cptestctx: &ControlPlaneTestContext) {
             existing_silo_user: false,
             expect_user: true,
         },
+        // Silos whose users authenticate with OIDC are also JIT provisioned.
+        TestSiloUserProvisionTypes {
+            identity_mode: shared::SiloIdentityMode::OidcJit,
+            existing_silo_user: true,
+            expect_user: true,
+        },
+        TestSiloUserProvisionTypes {
+            identity_mode: shared::SiloIdentityMode::OidcJit,
+            existing_silo_user: false,
+            expect_user: true,
+        },
     ];
 
     for test_case in test_cases {
@@ -781,7 +792,8 @@ async fn test_silo_user_provision_types(cptestctx: &ControlPlaneTestContext) {
 
         if test_case.existing_silo_user {
             match test_case.identity_mode {
-                shared::SiloIdentityMode::SamlJit => {
+                shared::SiloIdentityMode::SamlJit
+                | shared::SiloIdentityMode::OidcJit => {
                     create_jit_user(datastore, &silo, "external-id-com").await;
                 }
                 shared::SiloIdentityMode::LocalOnly => {
@@ -146,6 +146,7 @@ async fn test_snapshot_basic(cptestctx: &ControlPlaneTestContext) {
                 params::InstanceDiskAttach { name: base_disk_name.clone() },
             )],
             external_ips: vec![],
+            affinity_groups: vec![],
             start: true,
         },
     )
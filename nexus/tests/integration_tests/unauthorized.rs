@@ -181,6 +181,21 @@ lazy_static! {
                 .respond_with(status_code(200).body(SAML_IDP_DESCRIPTOR)),
         );
 
+        let oidc_issuer = server.url("/oidc").to_string();
+        server.expect(
+            Expectation::matching(request::method_path(
+                "GET",
+                "/oidc/.well-known/openid-configuration",
+            ))
+            .times(1..)
+            .respond_with(json_encoded(serde_json::json!({
+                "issuer": oidc_issuer,
+                "authorization_endpoint": format!("{}/authorize", oidc_issuer),
+                "token_endpoint": format!("{}/token", oidc_issuer),
+                "jwks_uri": format!("{}/jwks", oidc_issuer),
+            }))),
+        );
+
         server
     };
 
@@ -200,6 +215,7 @@ lazy_static! {
                 &*DEMO_SILO_USER_ID_GET_URL,
                 &*DEMO_SILO_USER_ID_DELETE_URL,
                 &*DEMO_SILO_USER_ID_SET_PASSWORD_URL,
+                &*DEMO_SILO_USER_ID_ACCESS_TOKENS_URL,
             ],
         },
         // Get the default IP pool
@@ -278,18 +294,54 @@ lazy_static! {
             body: serde_json::to_value(&*DEMO_IMAGE_CREATE).unwrap(),
             id_routes: vec!["/v1/images/{id}"],
         },
+        // Create a Floating IP in the Project
+        SetupReq::Post {
+            url: &DEMO_FLOAT_IPS_URL,
+            body: serde_json::to_value(&*DEMO_FLOAT_IP_CREATE).unwrap(),
+            id_routes: vec!["/v1/floating-ips/{id}"],
+        },
+        // Create an affinity group in the Project
+        SetupReq::Post {
+            url: &DEMO_AFFINITY_GROUPS_URL,
+            body: serde_json::to_value(&*DEMO_AFFINITY_GROUP_CREATE).unwrap(),
+            id_routes: vec!["/v1/affinity-groups/{id}"],
+        },
         // Create a SAML identity provider
         SetupReq::Post {
             url: &SAML_IDENTITY_PROVIDERS_URL,
             body: serde_json::to_value(&*SAML_IDENTITY_PROVIDER).unwrap(),
             id_routes: vec![],
         },
+        // Create a separate Silo for OIDC
+        SetupReq::Post {
+            url: "/v1/system/silos",
+            body: serde_json::to_value(&*DEMO_OIDC_SILO_CREATE).unwrap(),
+            id_routes: vec![],
+        },
+        // Create an OIDC identity provider
+        SetupReq::Post {
+            url: &OIDC_IDENTITY_PROVIDERS_URL,
+            body: serde_json::to_value(&*OIDC_IDENTITY_PROVIDER).unwrap(),
+            id_routes: vec![],
+        },
+        // Create a SCIM client token
+        SetupReq::Post {
+            url: &SCIM_TOKENS_URL,
+            body: serde_json::Value::Null,
+            id_routes: vec![&*SPECIFIC_SCIM_TOKEN_URL],
+        },
         // Create a SSH key
         SetupReq::Post {
             url: &DEMO_SSHKEYS_URL,
             body: serde_json::to_value(&*DEMO_SSHKEY_CREATE).unwrap(),
             id_routes: vec![],
         },
+        // Create a personal access token
+        SetupReq::Post {
+            url: &DEMO_ACCESS_TOKENS_URL,
+            body: serde_json::to_value(&*DEMO_ACCESS_TOKEN_CREATE).unwrap(),
+            id_routes: vec![&*DEMO_SPECIFIC_ACCESS_TOKEN_URL],
+        },
         // Create a Certificate
         SetupReq::Post {
             url: &DEMO_CERTIFICATES_URL,
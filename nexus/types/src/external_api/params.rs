@@ -22,6 +22,7 @@ use serde::{
 use std::collections::BTreeMap;
 use std::collections::BTreeSet;
 use std::collections::HashMap;
+use std::num::NonZeroU32;
 use std::{net::IpAddr, str::FromStr};
 use uuid::Uuid;
 
@@ -57,13 +58,18 @@ path_param!(RoutePath, route, "route");
 path_param!(DiskPath, disk, "disk");
 path_param!(SnapshotPath, snapshot, "snapshot");
 path_param!(ImagePath, image, "image");
+path_param!(FloatingIpPath, floating_ip, "floating IP");
+path_param!(AffinityGroupPath, affinity_group, "affinity group");
 path_param!(SiloPath, silo, "silo");
 path_param!(ProviderPath, provider, "SAML identity provider");
+path_param!(OidcProviderPath, provider, "OIDC identity provider");
 path_param!(IpPoolPath, pool, "IP pool");
 path_param!(SshKeyPath, ssh_key, "SSH key");
 path_param!(AddressLotPath, address_lot, "address lot");
 
 id_path_param!(GroupPath, group_id, "group");
+id_path_param!(AccessTokenPath, token_id, "access token");
+id_path_param!(ScimClientBearerTokenPath, token_id, "SCIM client bearer token");
 
 // TODO: The hardware resources should be represented by its UUID or a hardware
 // ID that can be used to deterministically generate the UUID.
@@ -106,6 +112,14 @@ pub struct SamlIdentityProviderSelector {
     pub saml_identity_provider: NameOrId,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct OidcIdentityProviderSelector {
+    /// Name or ID of the silo in which the OIDC identity provider is associated
+    pub silo: Option<NameOrId>,
+    /// Name or ID of the OIDC identity provider
+    pub oidc_identity_provider: NameOrId,
+}
+
 // The shape of this selector is slightly different than the others given that
 // silos users can only be specified via ID and are automatically provided by
 // the environment the user is authetnicated in
@@ -153,6 +167,22 @@ pub struct ImageSelector {
     pub image: NameOrId,
 }
 
+#[derive(Deserialize, JsonSchema)]
+pub struct FloatingIpSelector {
+    /// Name or ID of the project, only required if `floating_ip` is provided as a `Name`
+    pub project: Option<NameOrId>,
+    /// Name or ID of the floating IP
+    pub floating_ip: NameOrId,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct AffinityGroupSelector {
+    /// Name or ID of the project, only required if `affinity_group` is provided as a `Name`
+    pub project: Option<NameOrId>,
+    /// Name or ID of the affinity group
+    pub affinity_group: NameOrId,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
 pub struct InstanceSelector {
     /// Name or ID of the project, only required if `instance` is provided as a `Name`
@@ -578,6 +608,36 @@ pub struct SamlIdentityProviderCreate {
     pub group_attribute_name: Option<String>,
 }
 
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct OidcIdentityProviderCreate {
+    #[serde(flatten)]
+    pub identity: IdentityMetadataCreateParams,
+
+    /// issuer identifier of the OpenID provider
+    ///
+    /// The provider's configuration is discovered from
+    /// `{issuer}/.well-known/openid-configuration`.
+    pub issuer: String,
+
+    /// client id registered with the provider
+    pub client_id: String,
+
+    /// client secret registered with the provider
+    pub client_secret: String,
+
+    /// service provider endpoint where the authorization response will be sent
+    pub redirect_uri: String,
+
+    /// scopes to request in addition to "openid"
+    #[serde(default)]
+    pub scopes: Vec<String>,
+
+    /// If set, the ID token claim with this name will be considered to denote
+    /// a user's group membership, where the claim value should be either a
+    /// list of group names or a comma-separated string of group names.
+    pub group_claim_name: Option<String>,
+}
+
 /// sign some junk data and validate it with the key pair
 fn sign_junk_data(key_pair: &DerEncodedKeyPair) -> Result<(), anyhow::Error> {
     let private_key = {
@@ -657,6 +717,25 @@ pub struct ProjectUpdate {
     pub identity: IdentityMetadataUpdateParams,
 }
 
+// QUOTAS
+
+/// Replacement limits on the virtual resources which may be provisioned
+/// within a Silo or Project
+///
+/// Omitting a limit removes it. Lowering a limit below the amount already
+/// provisioned does not affect existing resources, but prevents new ones from
+/// being provisioned until usage falls below the limit.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct ResourceQuotasUpdate {
+    /// The maximum number of virtual CPUs which may be provisioned
+    pub cpus: Option<i64>,
+    /// The maximum amount of RAM (in bytes) which may be provisioned
+    pub memory: Option<ByteCount>,
+    /// The maximum amount of disk and snapshot storage (in bytes) which may
+    /// be provisioned
+    pub storage: Option<ByteCount>,
+}
+
 // NETWORK INTERFACES
 
 /// Create-time parameters for an `InstanceNetworkInterface`
@@ -818,7 +897,8 @@ pub enum ExternalIpCreate {
     /// automatically-assigned from the provided IP Pool, or all available pools
     /// if not specified.
     Ephemeral { pool_name: Option<Name> },
-    // TODO: Add floating IPs: https://github.com/oxidecomputer/omicron/issues/1334
+    // TODO: Allow attaching existing floating IPs at instance creation. For
+    // now they're attached to stopped instances through the floating IP API.
 }
 
 /// Create-time parameters for an `Instance`
@@ -860,11 +940,29 @@ pub struct InstanceCreate {
     #[serde(default)]
     pub disks: Vec<InstanceDiskAttachment>,
 
+    /// The affinity groups that this instance joins.
+    ///
+    /// The groups must be in the same project as the instance. Their
+    /// placement rules are applied when choosing a sled for the instance.
+    #[serde(default)]
+    pub affinity_groups: Vec<NameOrId>,
+
     /// Should this instance be started upon creation; true by default.
     #[serde(default = "bool_true")]
     pub start: bool,
 }
 
+/// Updateable properties of an `Instance`
+///
+/// An instance may only be reconfigured while it is stopped. The new
+/// configuration takes effect the next time the instance is started.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct InstanceUpdate {
+    pub ncpus: InstanceCpuCount,
+    pub memory: ByteCount,
+    pub hostname: String,
+}
+
 #[inline]
 fn bool_true() -> bool {
     true
@@ -1165,6 +1263,14 @@ pub struct DiskCreate {
     pub size: ByteCount,
 }
 
+/// Parameters for resizing a `Disk`
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct DiskResize {
+    /// new total size of the Disk in bytes (must be greater than its current
+    /// size)
+    pub size: ByteCount,
+}
+
 // equivalent to crucible_pantry_client::types::ExpectedDigest
 #[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
 #[serde(rename_all = "snake_case")]
@@ -1586,6 +1692,56 @@ pub struct SnapshotCreate {
     pub disk: NameOrId,
 }
 
+// FLOATING IPS
+
+/// Parameters for creating a new floating IP address for instances.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct FloatingIpCreate {
+    #[serde(flatten)]
+    pub identity: IdentityMetadataCreateParams,
+
+    /// An IP address to reserve for use as a floating IP. This field is
+    /// optional: when not set, an address will be automatically chosen from
+    /// `pool`. If set, then the IP must be available in the resolved `pool`.
+    pub address: Option<IpAddr>,
+
+    /// The parent IP pool that a floating IP is pulled from. If unset, the
+    /// default pool is selected.
+    pub pool: Option<NameOrId>,
+}
+
+/// Parameters for attaching a floating IP address to an instance
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct FloatingIpAttach {
+    /// Name or ID of the instance, which must be in the same project as the
+    /// floating IP
+    pub instance: NameOrId,
+}
+
+// AFFINITY GROUPS
+
+/// Create-time parameters for an `AffinityGroup`
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct AffinityGroupCreate {
+    #[serde(flatten)]
+    pub identity: IdentityMetadataCreateParams,
+
+    /// Whether instances in the group are placed together or apart
+    pub kind: shared::AffinityGroupKind,
+
+    /// What happens when an instance can't be placed according to `kind`
+    pub policy: shared::AffinityPolicy,
+}
+
+/// Parameters for adding an instance to, or removing an instance from, an
+/// affinity group
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct AffinityGroupMember {
+    /// Name or ID of the instance, which must be in the same project as the
+    /// affinity group
+    pub instance: NameOrId,
+}
+
 // USERS AND GROUPS
 
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
@@ -1627,6 +1783,32 @@ pub struct SshKeyCreate {
     pub public_key: String,
 }
 
+// ACCESS TOKENS
+
+/// Create-time parameters for an access token
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct AccessTokenCreate {
+    /// A description of what the token is used for
+    #[serde(default)]
+    pub description: String,
+
+    /// How long the token remains valid, in seconds. If not specified, the
+    /// token does not expire (but may still be revoked).
+    pub ttl_seconds: Option<NonZeroU32>,
+}
+
+// AUDIT LOG
+
+/// Query parameters for listing the audit log
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema, PartialEq)]
+pub struct AuditLogParams {
+    /// An inclusive start time of entries to list
+    pub start_time: DateTime<Utc>,
+    /// An exclusive end time of entries to list.  Defaults to the time of the
+    /// first request for this listing.
+    pub end_time: Option<DateTime<Utc>>,
+}
+
 // METRICS
 
 /// Query parameters common to resource metrics endpoints.
@@ -1638,6 +1820,54 @@ pub struct ResourceMetrics {
     pub end_time: DateTime<Utc>,
     /// Query result order
     pub order: Option<PaginationOrder>,
+    /// Aggregate the metric's measurements over windows of time, returning
+    /// one measurement per window rather than every sample.
+    pub aggregation: Option<MetricAggregation>,
+    /// The width of each aggregation window, in seconds. Defaults to 60.
+    pub interval: Option<u32>,
+    /// For the `quantile` aggregation, the quantile to compute, between 0 and
+    /// 1.
+    pub quantile: Option<f64>,
+}
+
+/// Selects the instance and, for per-device metrics, the device whose
+/// metrics to fetch
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct InstanceMetricsSelector {
+    /// Name or ID of the project, only required if `instance` is provided as a `Name`
+    pub project: Option<NameOrId>,
+    /// Index of the vCPU, required for vCPU metrics
+    pub vcpu: Option<u16>,
+    /// Name or ID of the network interface, required for network interface
+    /// metrics
+    pub network_interface: Option<NameOrId>,
+    /// Name or ID of the disk, required for disk metrics. Disks named by
+    /// `Name` are looked up in the instance's project.
+    pub disk: Option<NameOrId>,
+}
+
+/// An operation used to aggregate metrics over windows of time
+///
+/// Each aggregated measurement is timestamped with the start of its window.
+/// Windows are aligned to multiples of the interval since the Unix epoch, so
+/// the first and last windows of the requested time range may be partial.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MetricAggregation {
+    /// The mean value of a gauge
+    Mean,
+    /// The minimum value of a gauge
+    Min,
+    /// The maximum value of a gauge
+    Max,
+    /// The sum of the values of a gauge
+    Sum,
+    /// The increase in a cumulative counter
+    Delta,
+    /// The increase in a cumulative counter, per second
+    Rate,
+    /// A quantile of the values recorded in a histogram
+    Quantile,
 }
 
 // SYSTEM UPDATE
@@ -164,6 +164,11 @@ pub enum SiloIdentityMode {
     /// groups).
     SamlJit,
 
+    /// Users are authenticated with OpenID Connect using an external identity
+    /// provider.  As with `SamlJit`, users and groups are created or updated
+    /// only during successful authentication.
+    OidcJit,
+
     /// The system is the source of truth about users.  There is no linkage to
     /// an external authentication provider or identity provider.
     // NOTE: authentication for these users is not supported yet at all.  It
@@ -176,6 +181,7 @@ impl SiloIdentityMode {
         match self {
             SiloIdentityMode::LocalOnly => AuthenticationMode::Local,
             SiloIdentityMode::SamlJit => AuthenticationMode::Saml,
+            SiloIdentityMode::OidcJit => AuthenticationMode::Oidc,
         }
     }
 
@@ -183,6 +189,7 @@ impl SiloIdentityMode {
         match self {
             SiloIdentityMode::LocalOnly => UserProvisionType::ApiOnly,
             SiloIdentityMode::SamlJit => UserProvisionType::Jit,
+            SiloIdentityMode::OidcJit => UserProvisionType::Jit,
         }
     }
 }
@@ -194,6 +201,10 @@ pub enum AuthenticationMode {
     /// Authentication is via SAML using an external authentication provider
     Saml,
 
+    /// Authentication is via OpenID Connect using an external identity
+    /// provider
+    Oidc,
+
     /// Authentication is local to the Oxide system
     Local,
 }
@@ -228,6 +239,27 @@ pub enum IpKind {
     Floating,
 }
 
+/// Whether the instances in an affinity group should be placed together or
+/// apart
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AffinityGroupKind {
+    /// Instances in the group should be placed on the same sled.
+    Affinity,
+    /// Instances in the group should be placed on different sleds.
+    AntiAffinity,
+}
+
+/// What to do when an affinity group's placement rule can't be satisfied
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AffinityPolicy {
+    /// Fail to place the instance.
+    Fail,
+    /// Place the instance anyway, preferring sleds that satisfy the rule.
+    Allow,
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum UpdateableComponentType {
@@ -56,6 +56,9 @@ pub struct Silo {
 pub enum IdentityProviderType {
     /// SAML identity provider
     Saml,
+
+    /// OpenID Connect identity provider
+    Oidc,
 }
 
 /// View of an Identity Provider
@@ -96,6 +99,37 @@ pub struct SamlIdentityProvider {
     pub group_attribute_name: Option<String>,
 }
 
+#[derive(ObjectIdentity, Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct OidcIdentityProvider {
+    #[serde(flatten)]
+    pub identity: IdentityMetadata,
+
+    /// Issuer identifier of the OpenID provider
+    pub issuer: String,
+
+    /// Provider endpoint to which users are sent to authenticate
+    pub authorization_endpoint: String,
+
+    /// Provider endpoint at which authorization codes are exchanged for tokens
+    pub token_endpoint: String,
+
+    /// Location of the provider's JSON Web Key Set
+    pub jwks_uri: String,
+
+    /// Client ID registered with the provider
+    pub client_id: String,
+
+    /// Service provider endpoint where the authorization response will be sent
+    pub redirect_uri: String,
+
+    /// Scopes requested in addition to "openid"
+    pub scopes: Vec<String>,
+
+    /// If set, the ID token claim with this name will be considered to denote
+    /// a user's group membership, where the values will be the group names.
+    pub group_claim_name: Option<String>,
+}
+
 // PROJECTS
 
 /// View of a Project
@@ -108,6 +142,23 @@ pub struct Project {
     // Important: Silo ID does not get presented to user
 }
 
+// QUOTAS
+
+/// View of the limits on virtual resources which may be provisioned within a
+/// Silo or Project
+///
+/// Any limit which is not set is unlimited.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema, PartialEq)]
+pub struct ResourceQuotas {
+    /// The maximum number of virtual CPUs which may be provisioned
+    pub cpus: Option<i64>,
+    /// The maximum amount of RAM (in bytes) which may be provisioned
+    pub memory: Option<ByteCount>,
+    /// The maximum amount of disk and snapshot storage (in bytes) which may
+    /// be provisioned
+    pub storage: Option<ByteCount>,
+}
+
 // CERTIFICATES
 
 /// View of a Certificate
@@ -177,6 +228,23 @@ pub struct Snapshot {
     pub size: ByteCount,
 }
 
+// AFFINITY GROUPS
+
+/// View of an affinity group
+#[derive(ObjectIdentity, Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct AffinityGroup {
+    #[serde(flatten)]
+    pub identity: IdentityMetadata,
+
+    pub project_id: Uuid,
+
+    /// Whether instances in the group are placed together or apart
+    pub kind: shared::AffinityGroupKind,
+
+    /// What happens when an instance can't be placed according to `kind`
+    pub policy: shared::AffinityPolicy,
+}
+
 // VPCs
 
 /// View of a VPC
@@ -265,6 +333,22 @@ pub struct ExternalIp {
     pub kind: IpKind,
 }
 
+/// A Floating IP is a well-known IP address which can be attached to and
+/// detached from instances.
+#[derive(ObjectIdentity, Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct FloatingIp {
+    #[serde(flatten)]
+    pub identity: IdentityMetadata,
+    /// The IP address held by this resource.
+    pub ip: IpAddr,
+    /// The project this resource exists within.
+    pub project_id: Uuid,
+    /// The ID of the instance that this Floating IP is attached to, if it is
+    /// presently in use.
+    pub instance_id: Option<Uuid>,
+}
+
 // RACKS
 
 /// View of an Rack
@@ -464,6 +548,93 @@ pub enum DeviceAccessTokenType {
     Bearer,
 }
 
+// PERSONAL ACCESS TOKENS
+
+/// View of an access token
+///
+/// The token itself is only revealed when it is created.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct AccessToken {
+    /// A unique, immutable, system-controlled identifier for the token
+    pub id: Uuid,
+    pub description: String,
+    pub time_created: DateTime<Utc>,
+    /// When the token expires, if ever
+    pub time_expires: Option<DateTime<Utc>>,
+}
+
+/// A newly created access token, including the token itself
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct AccessTokenCreated {
+    /// A unique, immutable, system-controlled identifier for the token
+    pub id: Uuid,
+    pub description: String,
+    pub time_created: DateTime<Utc>,
+    /// When the token expires, if ever
+    pub time_expires: Option<DateTime<Utc>>,
+    /// The bearer token. This is not shown again, and cannot be recovered if
+    /// lost.
+    pub access_token: String,
+}
+
+/// View of a bearer token used by a SCIM client to provision a Silo's users
+/// and groups
+///
+/// The token itself is only revealed when it is created.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct ScimClientBearerToken {
+    /// A unique, immutable, system-controlled identifier for the token
+    pub id: Uuid,
+    pub time_created: DateTime<Utc>,
+}
+
+/// A newly created SCIM client bearer token, including the token itself
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct ScimClientBearerTokenCreated {
+    /// A unique, immutable, system-controlled identifier for the token
+    pub id: Uuid,
+    pub time_created: DateTime<Utc>,
+    /// The bearer token. This is not shown again, and cannot be recovered if
+    /// lost.
+    pub bearer_token: String,
+}
+
+// AUDIT LOG
+
+/// An entry in the audit log
+///
+/// Each entry describes one request to the external API that may have
+/// modified state, including requests that failed to authenticate.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct AuditLogEntry {
+    /// Unique identifier for the entry
+    pub id: Uuid,
+    /// When the request was received
+    pub time_started: DateTime<Utc>,
+
+    /// The request id, as reported to the client in the `x-request-id` header
+    pub request_id: String,
+    /// The API operation that was requested, e.g., `instance_create`
+    pub operation_id: String,
+    pub http_method: String,
+    /// The path of the request, which identifies the resource acted upon.
+    /// The query string, if any, is not recorded.
+    pub request_uri: String,
+
+    /// The user who made the request.  This is not set for requests that
+    /// failed to authenticate, nor for requests made by a Silo's SCIM client.
+    pub actor_id: Option<Uuid>,
+    /// The Silo of the user (or SCIM client) who made the request, unless they
+    /// are a built-in user
+    pub actor_silo_id: Option<Uuid>,
+
+    /// When the request completed.  This is not set for requests that are
+    /// still in progress or that were interrupted.
+    pub time_completed: Option<DateTime<Utc>>,
+    /// The HTTP status code of the response
+    pub http_status_code: Option<u16>,
+}
+
 // SYSTEM UPDATES
 
 #[derive(Clone, Debug, Deserialize, Serialize, JsonSchema, PartialEq, Eq)]
@@ -8,16 +8,24 @@
 
 use dropshot::{
     endpoint, ApiDescription, ConfigDropshot, ConfigLogging, HttpError,
-    HttpResponseUpdatedNoContent, HttpServer, HttpServerStarter,
-    RequestContext, TypedBody,
+    HttpResponseOk, HttpResponseUpdatedNoContent, HttpServer,
+    HttpServerStarter, RequestContext, TypedBody,
 };
+use http::{header, Response, StatusCode};
+use hyper::Body;
 use internal_dns::resolver::{ResolveError, Resolver};
 use internal_dns::ServiceName;
 use omicron_common::address::{CLICKHOUSE_PORT, NEXUS_INTERNAL_PORT};
 use omicron_common::api::internal::nexus::ProducerEndpoint;
+use omicron_common::api::internal::nexus::PushProducerInfo;
+use omicron_common::api::internal::nexus::MAX_PUSHED_SAMPLES;
+use omicron_common::api::internal::nexus::PUSH_PRODUCER_LEASE_DURATION;
 use omicron_common::{backoff, FileKv};
-use oximeter::types::{ProducerResults, ProducerResultsItem};
-use oximeter_db::{Client, DbWrite};
+use oximeter::types::{ProducerResults, ProducerResultsItem, Sample};
+use oximeter_db::{
+    Client, DbWrite, RetentionPolicy, TimeseriesName, TimeseriesSchema,
+};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use slog::{debug, error, info, o, trace, warn, Drain, Logger};
 use std::collections::{btree_map::Entry, BTreeMap};
@@ -25,12 +33,21 @@ use std::net::{SocketAddr, SocketAddrV6};
 use std::path::Path;
 use std::sync::Arc;
 use std::time::Duration;
+use std::time::Instant;
 use thiserror::Error;
 use tokio::{
     sync::mpsc, sync::oneshot, sync::Mutex, task::JoinHandle, time::interval,
 };
 use uuid::Uuid;
 
+mod prometheus;
+mod spool;
+
+pub use prometheus::PrometheusConfig;
+use prometheus::{LatestSamples, OPENMETRICS_CONTENT_TYPE};
+use spool::Spool;
+pub use spool::SpoolConfig;
+
 /// Errors collecting metric data
 #[derive(Debug, Clone, Error)]
 pub enum Error {
@@ -45,8 +62,24 @@ pub enum Error {
 
     #[error(transparent)]
     ResolveError(#[from] ResolveError),
+
+    #[error("Error spooling metric data: {0}")]
+    Spool(String),
+
+    #[error("Producer id={0} is not registered with this collector")]
+    UnknownProducer(Uuid),
+
+    #[error("Too many samples pushed at once: {0}, at most {MAX_PUSHED_SAMPLES} are accepted")]
+    TooManySamples(usize),
+
+    #[error("Collector is busy, retry pushing samples later")]
+    Busy,
 }
 
+// The largest request body accepted by the collector's server, which must fit
+// a batch of pushed samples.
+const MAX_REQUEST_BODY_BYTES: usize = 16 * 1024 * 1024;
+
 type CollectionToken = oneshot::Sender<()>;
 
 // Messages for controlling a collection task
@@ -187,12 +220,93 @@ struct CollectionTask {
     pub task: JoinHandle<()>,
 }
 
+// Return true if inserting samples failed because the database couldn't be
+// reached, in which case the samples are worth retrying later.
+fn is_database_unavailable(err: &oximeter_db::Error) -> bool {
+    matches!(err, oximeter_db::Error::DatabaseUnavailable(_))
+}
+
+// Insert a batch of samples, along with any batches spooled while the database
+// was unavailable.
+//
+// Spooled batches are inserted first, oldest first. If the database can't be
+// reached, the new batch is spooled behind them, to be retried along with
+// the next batch. Batches that the database rejects outright are dropped.
+async fn insert_batch_with_spool(
+    log: &Logger,
+    client: &Client,
+    spool: &mut Spool,
+    batch: &[Sample],
+) {
+    let mut database_available = true;
+    loop {
+        let spooled = match spool.front() {
+            Ok(Some(spooled)) => spooled,
+            Ok(None) => break,
+            Err(e) => {
+                error!(log, "failed to read spool: {}", e);
+                break;
+            }
+        };
+        let inserted = match client.insert_samples(&spooled).await {
+            Ok(()) => {
+                debug!(log, "inserted {} spooled samples", spooled.len());
+                true
+            }
+            Err(e) if is_database_unavailable(&e) => {
+                database_available = false;
+                break;
+            }
+            Err(e) => {
+                warn!(
+                    log,
+                    "failed to insert spooled samples, dropping them: {}",
+                    e.to_string()
+                );
+                false
+            }
+        };
+        if let Err(e) = spool.pop_front(inserted) {
+            error!(log, "failed to remove batch from spool: {}", e);
+        }
+    }
+
+    if batch.is_empty() {
+        return;
+    }
+    if database_available {
+        match client.insert_samples(batch).await {
+            Ok(()) => {
+                trace!(log, "successfully inserted samples");
+                return;
+            }
+            Err(e) if is_database_unavailable(&e) => {}
+            Err(e) => {
+                warn!(
+                    log,
+                    "failed to insert some results into metric DB: {}",
+                    e.to_string()
+                );
+                spool.record_dropped(batch.len());
+                return;
+            }
+        }
+    }
+    warn!(log, "metric DB is unavailable, spooling {} samples", batch.len());
+    if let Err(e) = spool.push(batch) {
+        error!(log, "failed to spool samples, dropping them: {}", e);
+        spool.record_dropped(batch.len());
+    }
+}
+
 // Aggregation point for all results, from all collection tasks.
 async fn results_sink(
     log: Logger,
-    client: Client,
+    client: Arc<Client>,
     batch_size: usize,
     batch_interval: Duration,
+    mut spool: Option<Spool>,
+    latest_samples: Option<Arc<LatestSamples>>,
     mut rx: mpsc::Receiver<(Option<CollectionToken>, ProducerResults)>,
 ) {
     let mut timer = interval(batch_interval);
@@ -202,11 +316,14 @@ async fn results_sink(
         let mut collection_token = None;
         let insert = tokio::select! {
             _ = timer.tick() => {
-                if batch.is_empty() {
+                if !batch.is_empty() {
+                    true
+                } else if spool.as_ref().map_or(false, |spool| !spool.is_empty()) {
+                    trace!(log, "batch interval expired, retrying spooled samples");
+                    true
+                } else {
                     trace!(log, "batch interval expired, but no samples to insert");
                     false
-                } else {
-                    true
                 }
             }
             results = rx.recv() => {
@@ -228,6 +345,9 @@ async fn results_sink(
                             }
                             flattened
                         };
+                        if let Some(latest_samples) = &latest_samples {
+                            latest_samples.update(&flattened_results);
+                        }
                         batch.extend(flattened_results);
 
                         collection_token = token;
@@ -247,19 +367,30 @@ async fn results_sink(
 
         if insert {
             debug!(log, "inserting {} samples into database", batch.len());
-            match client.insert_samples(&batch).await {
-                Ok(()) => trace!(log, "successfully inserted samples"),
-                Err(e) => {
-                    warn!(
-                        log,
-                        "failed to insert some results into metric DB: {}",
-                        e.to_string()
-                    );
+            match spool.as_mut() {
+                Some(spool) => {
+                    // Report on the spool along with the samples themselves.
+                    match spool.samples() {
+                        Ok(samples) => batch.extend(samples),
+                        Err(e) => warn!(
+                            log,
+                            "failed to generate spool metrics: {}",
+                            e.to_string()
+                        ),
+                    }
+                    insert_batch_with_spool(&log, &client, spool, &batch).await;
                 }
+                None => match client.insert_samples(&batch).await {
+                    Ok(()) => trace!(log, "successfully inserted samples"),
+                    Err(e) => {
+                        warn!(
+                            log,
+                            "failed to insert some results into metric DB: {}",
+                            e.to_string()
+                        );
+                    }
+                },
             }
-            // TODO-correctness The `insert_samples` call above may fail. The method itself needs
-            // better handling of partially-inserted results in that case, but we may need to retry
-            // or otherwise handle an error here as well.
             batch.clear();
         }
 
@@ -270,7 +401,7 @@ async fn results_sink(
 }
 
 /// Configuration for interacting with the metric database.
-#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct DbConfig {
     /// Optional address of the ClickHouse server.
     ///
@@ -284,6 +415,13 @@ pub struct DbConfig {
     /// Interval on which to insert data into the database, regardless of the number of collected
     /// samples. Value is in seconds.
     pub batch_interval: u64,
+
+    /// Retention policies for particular timeseries.
+    ///
+    /// Timeseries without a policy keep their measurements for 30 days, without rollups. The
+    /// policies replace any applied to the database previously.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub retention: Vec<RetentionPolicy>,
 }
 
 /// The internal agent the oximeter server uses to collect metrics from producers.
@@ -296,6 +434,14 @@ pub struct OximeterAgent {
     result_sender: mpsc::Sender<(Option<CollectionToken>, ProducerResults)>,
     // The actual tokio tasks running the collection on a timer.
     collection_tasks: Arc<Mutex<BTreeMap<Uuid, CollectionTask>>>,
+    // Producers that push their samples to the collector, rather than being
+    // polled for them, and when each last registered. Nexus assigns these,
+    // like the producers we poll.
+    push_producers: Mutex<BTreeMap<Uuid, Instant>>,
+    // Client to the timeseries database, shared with the task inserting samples.
+    client: Arc<Client>,
+    // The latest samples exported to Prometheus, if that's configured.
+    latest_samples: Option<Arc<LatestSamples>>,
 }
 
 impl OximeterAgent {
@@ -303,6 +449,8 @@ impl OximeterAgent {
     pub async fn with_id(
         id: Uuid,
         db_config: DbConfig,
+        spool_config: Option<&SpoolConfig>,
+        prometheus_config: Option<&PrometheusConfig>,
         resolver: &Resolver,
         log: &Logger,
     ) -> Result<Self, Error> {
@@ -320,21 +468,35 @@ impl OximeterAgent {
                 CLICKHOUSE_PORT,
             )
         };
-        let client = Client::new(db_address, &log);
+        let client = Arc::new(Client::new(db_address, &log));
         let replicated = client.is_oximeter_cluster().await?;
         if !replicated {
             client.init_single_node_db().await?;
         } else {
             client.init_replicated_db().await?;
         }
+        client.apply_retention_policies(&db_config.retention).await?;
+
+        // Open the spool of batches that failed to be inserted, which may
+        // hold batches from a previous run of the collector.
+        let spool = spool_config
+            .map(|config| Spool::open(&insertion_log, id, config))
+            .transpose()?;
+
+        let latest_samples = prometheus_config
+            .map(|config| Arc::new(LatestSamples::new(config)));
 
         // Spawn the task for aggregating and inserting all metrics
+        let sink_client = Arc::clone(&client);
+        let sink_latest_samples = latest_samples.clone();
         tokio::spawn(async move {
             results_sink(
                 insertion_log,
-                client,
+                sink_client,
                 db_config.batch_size,
                 Duration::from_secs(db_config.batch_interval),
+                spool,
+                sink_latest_samples,
                 result_receiver,
             )
             .await
@@ -344,6 +506,9 @@ impl OximeterAgent {
             log,
             result_sender,
             collection_tasks: Arc::new(Mutex::new(BTreeMap::new())),
+            push_producers: Mutex::new(BTreeMap::new()),
+            client,
+            latest_samples,
         })
     }
 
@@ -388,6 +553,67 @@ impl OximeterAgent {
         Ok(())
     }
 
+    /// Register a producer that pushes its samples to this oximeter instance.
+    ///
+    /// This is for producers that can't be polled, such as short-lived
+    /// processes that don't run a server. Nexus calls this when it assigns the
+    /// producer to this collector, when the producer renews its registration,
+    /// and again if the collector re-registers with Nexus, so registering
+    /// again is harmless.
+    ///
+    /// Registrations last for [`PUSH_PRODUCER_LEASE_DURATION`]. Producers
+    /// whose registrations have expired are forgotten here.
+    pub async fn register_push_producer(&self, id: Uuid) {
+        let now = Instant::now();
+        let mut push_producers = self.push_producers.lock().await;
+        push_producers.retain(|_, registered| {
+            now.duration_since(*registered) < PUSH_PRODUCER_LEASE_DURATION
+        });
+        if push_producers.insert(id, now).is_none() {
+            info!(self.log, "registered new push metric producer";
+                  "producer_id" => id.to_string(),
+            );
+        }
+    }
+
+    /// Accept a batch of samples pushed by a registered producer.
+    ///
+    /// Only producers that Nexus has assigned to this collector with
+    /// [`OximeterAgent::register_push_producer`], and whose registrations
+    /// haven't expired, may push samples. The samples are inserted along with
+    /// those collected from polled producers. If the collector is falling
+    /// behind inserting samples, the batch is refused with [`Error::Busy`],
+    /// and the producer should retry it later.
+    pub async fn push_samples(
+        &self,
+        producer_id: Uuid,
+        samples: Vec<Sample>,
+    ) -> Result<(), Error> {
+        let registered =
+            self.push_producers.lock().await.get(&producer_id).copied();
+        if !registered.is_some_and(|registered| {
+            registered.elapsed() < PUSH_PRODUCER_LEASE_DURATION
+        }) {
+            return Err(Error::UnknownProducer(producer_id));
+        }
+        if samples.len() > MAX_PUSHED_SAMPLES {
+            return Err(Error::TooManySamples(samples.len()));
+        }
+        debug!(
+            self.log,
+            "received {} pushed samples", samples.len();
+            "producer_id" => producer_id.to_string(),
+        );
+        self.result_sender
+            .try_send((None, vec![ProducerResultsItem::Ok(samples)]))
+            .map_err(|e| match e {
+                mpsc::error::TrySendError::Full(_) => Error::Busy,
+                mpsc::error::TrySendError::Closed(_) => Error::Server(
+                    String::from("metric results queue is closed"),
+                ),
+            })
+    }
+
     /// Forces a collection from all producers.
     ///
     /// Returns once all those values have been inserted into Clickhouse,
@@ -412,6 +638,29 @@ impl OximeterAgent {
         // successfully, or an error occurred in the collection pathway.
         futures::future::join_all(collection_oneshots).await;
     }
+
+    /// List all versions of the schema for a timeseries.
+    pub async fn timeseries_schema_versions(
+        &self,
+        timeseries_name: &TimeseriesName,
+    ) -> Result<Vec<TimeseriesSchema>, Error> {
+        self.client
+            .timeseries_schema_versions(timeseries_name)
+            .await
+            .map_err(Error::from)
+    }
+
+    /// Migrate timeseries recorded with earlier versions of a timeseries
+    /// schema to the latest version.
+    pub async fn migrate_timeseries_schema(
+        &self,
+        timeseries_name: &TimeseriesName,
+    ) -> Result<TimeseriesSchema, Error> {
+        self.client
+            .migrate_timeseries_schema(timeseries_name)
+            .await
+            .map_err(Error::from)
+    }
 }
 
 /// Configuration used to initialize an oximeter server
@@ -426,6 +675,20 @@ pub struct Config {
     /// Configuration for working with ClickHouse
     pub db: DbConfig,
 
+    /// Configuration for spooling samples to disk while ClickHouse is
+    /// unavailable.
+    ///
+    /// If "None", samples that can't be inserted are dropped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spool: Option<SpoolConfig>,
+
+    /// Configuration for exporting the latest samples of some timeseries
+    /// for Prometheus to scrape.
+    ///
+    /// If "None", the `/metrics` endpoint reports that export isn't enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prometheus: Option<PrometheusConfig>,
+
     /// Logging configuration
     pub log: ConfigLogging,
 }
@@ -495,8 +758,15 @@ impl Oximeter {
         let make_agent = || async {
             debug!(log, "creating ClickHouse client");
             Ok(Arc::new(
-                OximeterAgent::with_id(args.id, config.db, &resolver, &log)
-                    .await?,
+                OximeterAgent::with_id(
+                    args.id,
+                    config.db.clone(),
+                    config.spool.as_ref(),
+                    config.prometheus.as_ref(),
+                    &resolver,
+                    &log,
+                )
+                .await?,
             ))
         };
         let log_client_failure = |error, delay| {
@@ -518,6 +788,7 @@ impl Oximeter {
         let server = HttpServerStarter::new(
             &ConfigDropshot {
                 bind_address: SocketAddr::V6(args.address),
+                request_body_max_bytes: MAX_REQUEST_BODY_BYTES,
                 ..Default::default()
             },
             oximeter_api(),
@@ -585,6 +856,11 @@ impl Oximeter {
         self.server.close().await.map_err(Error::Server)
     }
 
+    /// Return the address of the server's HTTP API.
+    pub fn server_address(&self) -> SocketAddr {
+        self.server.local_addr()
+    }
+
     /// Forces Oximeter to perform a collection immediately.
     ///
     /// This is particularly useful during tests, which would prefer to
@@ -599,6 +875,16 @@ pub fn oximeter_api() -> ApiDescription<Arc<OximeterAgent>> {
     let mut api = ApiDescription::new();
     api.register(producers_post)
         .expect("Could not register producers_post API handler");
+    api.register(push_producers_post)
+        .expect("Could not register push_producers_post API handler");
+    api.register(producer_samples_push)
+        .expect("Could not register producer_samples_push API handler");
+    api.register(timeseries_schema_versions)
+        .expect("Could not register timeseries_schema_versions API handler");
+    api.register(timeseries_schema_migrate)
+        .expect("Could not register timeseries_schema_migrate API handler");
+    api.register(metrics_export)
+        .expect("Could not register metrics_export API handler");
     api
 }
 
@@ -619,3 +905,148 @@ async fn producers_post(
         .map_err(|e| HttpError::for_internal_error(e.to_string()))?;
     Ok(HttpResponseUpdatedNoContent())
 }
+
+// Handle a request from Nexus to assign a producer that pushes its samples to
+// this collector, rather than being polled for them.
+#[endpoint {
+    method = POST,
+    path = "/push-producers",
+}]
+async fn push_producers_post(
+    request_context: RequestContext<Arc<OximeterAgent>>,
+    body: TypedBody<PushProducerInfo>,
+) -> Result<HttpResponseUpdatedNoContent, HttpError> {
+    let agent = request_context.context();
+    agent.register_push_producer(body.into_inner().id).await;
+    Ok(HttpResponseUpdatedNoContent())
+}
+
+/// Path parameters for requests about a producer
+#[derive(Deserialize, JsonSchema)]
+struct ProducerPath {
+    /// The ID of the producer
+    producer_id: Uuid,
+}
+
+// Accept a batch of samples pushed by a registered producer.
+#[endpoint {
+    method = POST,
+    path = "/producers/{producer_id}/samples",
+}]
+async fn producer_samples_push(
+    request_context: RequestContext<Arc<OximeterAgent>>,
+    path: dropshot::Path<ProducerPath>,
+    body: TypedBody<Vec<Sample>>,
+) -> Result<HttpResponseUpdatedNoContent, HttpError> {
+    let agent = request_context.context();
+    let producer_id = path.into_inner().producer_id;
+    agent.push_samples(producer_id, body.into_inner()).await.map_err(|e| {
+        match e {
+            Error::UnknownProducer(_) => {
+                HttpError::for_not_found(None, e.to_string())
+            }
+            Error::TooManySamples(_) => {
+                HttpError::for_bad_request(None, e.to_string())
+            }
+            Error::Busy => HttpError::for_unavail(None, e.to_string()),
+            _ => HttpError::for_internal_error(e.to_string()),
+        }
+    })?;
+    Ok(HttpResponseUpdatedNoContent())
+}
+
+/// Path parameters for requests about a timeseries schema
+#[derive(Deserialize, JsonSchema)]
+struct TimeseriesSchemaPath {
+    /// The name of the timeseries, of the form `target_name:metric_name`
+    timeseries_name: String,
+}
+
+impl TimeseriesSchemaPath {
+    fn timeseries_name(self) -> Result<TimeseriesName, HttpError> {
+        TimeseriesName::try_from(self.timeseries_name)
+            .map_err(|e| HttpError::for_bad_request(None, e.to_string()))
+    }
+}
+
+// Convert an error interacting with the timeseries database into a response.
+fn timeseries_schema_error(error: Error) -> HttpError {
+    match error {
+        Error::Database(oximeter_db::Error::TimeseriesNotFound(_)) => {
+            HttpError::for_not_found(None, error.to_string())
+        }
+        Error::Database(oximeter_db::Error::DatabaseUnavailable(_)) => {
+            HttpError::for_unavail(None, error.to_string())
+        }
+        _ => HttpError::for_internal_error(error.to_string()),
+    }
+}
+
+// List all versions of the schema for a timeseries, in order of their version.
+#[endpoint {
+    method = GET,
+    path = "/timeseries/{timeseries_name}/schema",
+}]
+async fn timeseries_schema_versions(
+    request_context: RequestContext<Arc<OximeterAgent>>,
+    path: dropshot::Path<TimeseriesSchemaPath>,
+) -> Result<HttpResponseOk<Vec<TimeseriesSchema>>, HttpError> {
+    let agent = request_context.context();
+    let timeseries_name = path.into_inner().timeseries_name()?;
+    let versions = agent
+        .timeseries_schema_versions(&timeseries_name)
+        .await
+        .map_err(timeseries_schema_error)?;
+    if versions.is_empty() {
+        return Err(HttpError::for_not_found(
+            None,
+            format!("Timeseries not found for: {timeseries_name}"),
+        ));
+    }
+    Ok(HttpResponseOk(versions))
+}
+
+// Migrate timeseries recorded with earlier versions of a timeseries schema to
+// the latest version, returning that version.
+#[endpoint {
+    method = POST,
+    path = "/timeseries/{timeseries_name}/schema/migrate",
+}]
+async fn timeseries_schema_migrate(
+    request_context: RequestContext<Arc<OximeterAgent>>,
+    path: dropshot::Path<TimeseriesSchemaPath>,
+) -> Result<HttpResponseOk<TimeseriesSchema>, HttpError> {
+    let agent = request_context.context();
+    let timeseries_name = path.into_inner().timeseries_name()?;
+    agent
+        .migrate_timeseries_schema(&timeseries_name)
+        .await
+        .map(HttpResponseOk)
+        .map_err(timeseries_schema_error)
+}
+
+// Export the latest samples of the configured timeseries in the OpenMetrics
+// text format, for Prometheus to scrape.
+//
+// This isn't part of the API used by the rest of the control plane, so it's
+// left out of the OpenAPI document.
+#[endpoint {
+    method = GET,
+    path = "/metrics",
+    unpublished = true,
+}]
+async fn metrics_export(
+    request_context: RequestContext<Arc<OximeterAgent>>,
+) -> Result<Response<Body>, HttpError> {
+    let agent = request_context.context();
+    let Some(latest_samples) = &agent.latest_samples else {
+        return Err(HttpError::for_not_found(
+            None,
+            String::from("Prometheus export is not enabled"),
+        ));
+    };
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, OPENMETRICS_CONTENT_TYPE)
+        .body(latest_samples.render().into())?)
+}
@@ -0,0 +1,678 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Export of the latest collected samples in the OpenMetrics text format, for
+//! scraping by Prometheus.
+//!
+//! Rather than query the metric database on every scrape, the collector keeps
+//! the most recent sample of each series of the allow-listed timeseries as
+//! samples arrive from producers. Each timeseries becomes a metric family
+//! named after it, with the `:` replaced by `_`, and the fields of its samples
+//! become labels. Gauges are exported as gauges, `Cumulative` datums as
+//! counters, and histograms as histograms. String and bytes datums can't be
+//! represented, and are skipped.
+//!
+//! Oximeter histogram bins exclude their right edge, while Prometheus buckets
+//! include it, so samples exactly on a bin edge are counted one bucket too
+//! high.
+
+// Copyright 2023 Oxide Computer Company
+
+use chrono::{DateTime, Utc};
+use oximeter::histogram::{BinRange, Histogram, HistogramSupport};
+use oximeter::types::{Cumulative, Datum, FieldValue, Sample};
+use oximeter_db::TimeseriesName;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Display, Write};
+use std::sync::Mutex;
+
+/// The media type of the exported metrics.
+pub const OPENMETRICS_CONTENT_TYPE: &str =
+    "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+fn default_max_series() -> usize {
+    1000
+}
+
+fn default_max_age() -> u64 {
+    300
+}
+
+/// Configuration for exporting the latest samples to Prometheus.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PrometheusConfig {
+    /// The timeseries to export.
+    ///
+    /// Samples of any other timeseries are not exported.
+    pub timeseries: Vec<TimeseriesName>,
+
+    /// The maximum number of series, i.e., distinct sets of field values,
+    /// exported for each timeseries.
+    ///
+    /// Samples from new series beyond this limit are not exported.
+    #[serde(default = "default_max_series")]
+    pub max_series: usize,
+
+    /// Series that haven't been collected for this many seconds are no longer
+    /// exported.
+    #[serde(default = "default_max_age")]
+    pub max_age: u64,
+}
+
+/// The most recent sample of each series of the exported timeseries.
+#[derive(Debug)]
+pub struct LatestSamples {
+    timeseries: BTreeSet<String>,
+    max_series: usize,
+    max_age: chrono::Duration,
+    // Samples indexed by timeseries name, and then by their rendered labels.
+    samples: Mutex<BTreeMap<String, BTreeMap<String, Sample>>>,
+}
+
+impl LatestSamples {
+    pub fn new(config: &PrometheusConfig) -> Self {
+        Self {
+            timeseries: config
+                .timeseries
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+            max_series: config.max_series,
+            max_age: chrono::Duration::seconds(
+                i64::try_from(config.max_age).unwrap_or(i64::MAX / 1000),
+            ),
+            samples: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Record any samples of the exported timeseries.
+    pub fn update<'a>(&self, samples: impl IntoIterator<Item = &'a Sample>) {
+        self.update_at(samples, Utc::now())
+    }
+
+    fn update_at<'a>(
+        &self,
+        samples: impl IntoIterator<Item = &'a Sample>,
+        now: DateTime<Utc>,
+    ) {
+        let mut latest = self.samples.lock().unwrap();
+        for sample in samples {
+            if !self.timeseries.contains(&sample.timeseries_name) {
+                continue;
+            }
+            let series =
+                latest.entry(sample.timeseries_name.clone()).or_default();
+            let labels = render_labels(sample);
+            match series.get_mut(&labels) {
+                Some(existing) => {
+                    if existing.measurement.timestamp()
+                        <= sample.measurement.timestamp()
+                    {
+                        *existing = sample.clone();
+                    }
+                }
+                None => {
+                    if series.len() >= self.max_series {
+                        series.retain(|_, sample| !self.expired(sample, now));
+                    }
+                    if series.len() < self.max_series {
+                        series.insert(labels, sample.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Render the exported samples in the OpenMetrics text format.
+    pub fn render(&self) -> String {
+        self.render_at(Utc::now())
+    }
+
+    fn render_at(&self, now: DateTime<Utc>) -> String {
+        let mut latest = self.samples.lock().unwrap();
+        let mut out = String::new();
+        for (timeseries_name, series) in latest.iter_mut() {
+            series.retain(|_, sample| !self.expired(sample, now));
+            let family = timeseries_name.replace(':', "_");
+            let mut family_type = None;
+            for (labels, sample) in series.iter() {
+                let Some(sample_type) =
+                    FamilyType::of(sample.measurement.datum())
+                else {
+                    continue;
+                };
+                // A timeseries whose schema changed may briefly have samples
+                // of different types. A family can only have one.
+                match family_type {
+                    None => {
+                        writeln!(out, "# TYPE {family} {sample_type}").unwrap();
+                        family_type = Some(sample_type);
+                    }
+                    Some(family_type) if family_type != sample_type => continue,
+                    Some(_) => {}
+                }
+                render_sample(&mut out, &family, labels, sample);
+            }
+        }
+        out.push_str("# EOF\n");
+        out
+    }
+
+    fn expired(&self, sample: &Sample, now: DateTime<Utc>) -> bool {
+        now - sample.measurement.timestamp() > self.max_age
+    }
+}
+
+/// The OpenMetrics types a timeseries can be exported as.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FamilyType {
+    Gauge,
+    Counter,
+    Histogram,
+}
+
+impl FamilyType {
+    fn of(datum: &Datum) -> Option<Self> {
+        match datum {
+            Datum::String(_) | Datum::Bytes(_) => None,
+            Datum::CumulativeI64(_)
+            | Datum::CumulativeU64(_)
+            | Datum::CumulativeF32(_)
+            | Datum::CumulativeF64(_) => Some(FamilyType::Counter),
+            Datum::HistogramI8(_)
+            | Datum::HistogramU8(_)
+            | Datum::HistogramI16(_)
+            | Datum::HistogramU16(_)
+            | Datum::HistogramI32(_)
+            | Datum::HistogramU32(_)
+            | Datum::HistogramI64(_)
+            | Datum::HistogramU64(_)
+            | Datum::HistogramF32(_)
+            | Datum::HistogramF64(_) => Some(FamilyType::Histogram),
+            _ => Some(FamilyType::Gauge),
+        }
+    }
+}
+
+impl std::fmt::Display for FamilyType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FamilyType::Gauge => write!(f, "gauge"),
+            FamilyType::Counter => write!(f, "counter"),
+            FamilyType::Histogram => write!(f, "histogram"),
+        }
+    }
+}
+
+// Render a sample's fields as labels, sorted by name and without the braces.
+fn render_labels(sample: &Sample) -> String {
+    let mut fields = sample.fields();
+    fields.sort_by(|a, b| a.name.cmp(&b.name));
+    fields
+        .iter()
+        .map(|field| {
+            format!("{}=\"{}\"", field.name, escape_label_value(&field.value))
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn escape_label_value(value: &FieldValue) -> String {
+    let mut escaped = String::new();
+    for c in value.to_string().chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// A number that can be written as a sample value or bucket bound.
+//
+// Numbers are written as Rust displays them, except that OpenMetrics spells the
+// non-finite floating-point values "+Inf", "-Inf", and "NaN".
+trait Number: Copy + Display {
+    fn non_finite(self) -> Option<&'static str> {
+        None
+    }
+}
+
+macro_rules! impl_integer_number {
+    ($($ty:ty),*) => {
+        $(impl Number for $ty {})*
+    };
+}
+
+impl_integer_number!(i8, u8, i16, u16, i32, u32, i64, u64);
+
+macro_rules! impl_float_number {
+    ($($ty:ty),*) => {
+        $(
+            impl Number for $ty {
+                fn non_finite(self) -> Option<&'static str> {
+                    if self.is_nan() {
+                        Some("NaN")
+                    } else if self == <$ty>::INFINITY {
+                        Some("+Inf")
+                    } else if self == <$ty>::NEG_INFINITY {
+                        Some("-Inf")
+                    } else {
+                        None
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_float_number!(f32, f64);
+
+// Render a number as OpenMetrics expects.
+fn number<T: Number>(value: T) -> String {
+    value.non_finite().map(String::from).unwrap_or_else(|| value.to_string())
+}
+
+// Write one line of a family, adding `extra` to the sample's labels.
+fn write_line(
+    out: &mut String,
+    name: &str,
+    labels: &str,
+    extra: Option<(&str, &str)>,
+    value: impl Display,
+    timestamp: DateTime<Utc>,
+) {
+    let labels = match (labels.is_empty(), extra) {
+        (true, None) => String::new(),
+        (false, None) => format!("{{{labels}}}"),
+        (true, Some((name, value))) => format!("{{{name}=\"{value}\"}}"),
+        (false, Some((name, value))) => {
+            format!("{{{labels},{name}=\"{value}\"}}")
+        }
+    };
+    writeln!(
+        out,
+        "{name}{labels} {value} {}.{:03}",
+        timestamp.timestamp(),
+        timestamp.timestamp_subsec_millis()
+    )
+    .unwrap();
+}
+
+fn render_sample(
+    out: &mut String,
+    family: &str,
+    labels: &str,
+    sample: &Sample,
+) {
+    let timestamp = sample.measurement.timestamp();
+    match sample.measurement.datum() {
+        Datum::Bool(x) => {
+            write_line(out, family, labels, None, u8::from(*x), timestamp)
+        }
+        Datum::I8(x) => {
+            write_line(out, family, labels, None, number(*x), timestamp)
+        }
+        Datum::U8(x) => {
+            write_line(out, family, labels, None, number(*x), timestamp)
+        }
+        Datum::I16(x) => {
+            write_line(out, family, labels, None, number(*x), timestamp)
+        }
+        Datum::U16(x) => {
+            write_line(out, family, labels, None, number(*x), timestamp)
+        }
+        Datum::I32(x) => {
+            write_line(out, family, labels, None, number(*x), timestamp)
+        }
+        Datum::U32(x) => {
+            write_line(out, family, labels, None, number(*x), timestamp)
+        }
+        Datum::I64(x) => {
+            write_line(out, family, labels, None, number(*x), timestamp)
+        }
+        Datum::U64(x) => {
+            write_line(out, family, labels, None, number(*x), timestamp)
+        }
+        Datum::F32(x) => {
+            write_line(out, family, labels, None, number(*x), timestamp)
+        }
+        Datum::F64(x) => {
+            write_line(out, family, labels, None, number(*x), timestamp)
+        }
+        Datum::String(_) | Datum::Bytes(_) => {}
+        Datum::CumulativeI64(x) => {
+            render_counter(out, family, labels, x, timestamp)
+        }
+        Datum::CumulativeU64(x) => {
+            render_counter(out, family, labels, x, timestamp)
+        }
+        Datum::CumulativeF32(x) => {
+            render_counter(out, family, labels, x, timestamp)
+        }
+        Datum::CumulativeF64(x) => {
+            render_counter(out, family, labels, x, timestamp)
+        }
+        Datum::HistogramI8(x) => {
+            render_histogram(out, family, labels, x, timestamp)
+        }
+        Datum::HistogramU8(x) => {
+            render_histogram(out, family, labels, x, timestamp)
+        }
+        Datum::HistogramI16(x) => {
+            render_histogram(out, family, labels, x, timestamp)
+        }
+        Datum::HistogramU16(x) => {
+            render_histogram(out, family, labels, x, timestamp)
+        }
+        Datum::HistogramI32(x) => {
+            render_histogram(out, family, labels, x, timestamp)
+        }
+        Datum::HistogramU32(x) => {
+            render_histogram(out, family, labels, x, timestamp)
+        }
+        Datum::HistogramI64(x) => {
+            render_histogram(out, family, labels, x, timestamp)
+        }
+        Datum::HistogramU64(x) => {
+            render_histogram(out, family, labels, x, timestamp)
+        }
+        Datum::HistogramF32(x) => {
+            render_histogram(out, family, labels, x, timestamp)
+        }
+        Datum::HistogramF64(x) => {
+            render_histogram(out, family, labels, x, timestamp)
+        }
+    }
+}
+
+// Render the start time of a counter or histogram, as seconds since the epoch.
+fn created(start_time: DateTime<Utc>) -> String {
+    format!(
+        "{}.{:03}",
+        start_time.timestamp(),
+        start_time.timestamp_subsec_millis()
+    )
+}
+
+fn render_counter<T>(
+    out: &mut String,
+    family: &str,
+    labels: &str,
+    counter: &Cumulative<T>,
+    timestamp: DateTime<Utc>,
+) where
+    T: oximeter::traits::Cumulative + Number,
+{
+    write_line(
+        out,
+        &format!("{family}_total"),
+        labels,
+        None,
+        number(counter.value()),
+        timestamp,
+    );
+    write_line(
+        out,
+        &format!("{family}_created"),
+        labels,
+        None,
+        created(counter.start_time()),
+        timestamp,
+    );
+}
+
+// Render a bucket's upper bound in the canonical form OpenMetrics expects for
+// the `le` label, which always includes a decimal point.
+fn bucket_bound<T: HistogramSupport + Number>(bound: T) -> String {
+    if let Some(bound) = bound.non_finite() {
+        return String::from(bound);
+    }
+    let bound = format!("{bound:?}");
+    if bound.contains(|c: char| !(c.is_ascii_digit() || c == '-')) {
+        bound
+    } else {
+        format!("{bound}.0")
+    }
+}
+
+fn render_histogram<T>(
+    out: &mut String,
+    family: &str,
+    labels: &str,
+    histogram: &Histogram<T>,
+    timestamp: DateTime<Utc>,
+) where
+    T: HistogramSupport + Number,
+{
+    let bucket = format!("{family}_bucket");
+    let mut count = 0;
+    for bin in histogram.iter() {
+        count += bin.count;
+        let bound = match bin.range {
+            BinRange::RangeTo { end } | BinRange::Range { end, .. } => {
+                bucket_bound(end)
+            }
+            BinRange::RangeFrom { .. } => String::from("+Inf"),
+        };
+        write_line(
+            out,
+            &bucket,
+            labels,
+            Some(("le", &bound)),
+            count,
+            timestamp,
+        );
+    }
+    write_line(
+        out,
+        &format!("{family}_created"),
+        labels,
+        None,
+        created(histogram.start_time()),
+        timestamp,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use oximeter::{Metric, Target};
+    use uuid::Uuid;
+
+    #[derive(Debug, Clone, Target)]
+    struct Server {
+        name: String,
+    }
+
+    #[derive(Debug, Clone, Metric)]
+    struct Temperature {
+        sensor: i64,
+        #[datum]
+        celsius: f64,
+    }
+
+    #[derive(Debug, Clone, Metric)]
+    struct Requests {
+        route: String,
+        #[datum]
+        count: Cumulative<i64>,
+    }
+
+    #[derive(Debug, Clone, Metric)]
+    struct Latency {
+        #[datum]
+        seconds: Histogram<f64>,
+    }
+
+    #[derive(Debug, Clone, Metric)]
+    struct Version {
+        id: Uuid,
+        #[datum]
+        version: String,
+    }
+
+    fn time(seconds: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(seconds, 0).unwrap()
+    }
+
+    fn config(timeseries: &[&str], max_series: usize) -> PrometheusConfig {
+        PrometheusConfig {
+            timeseries: timeseries
+                .iter()
+                .map(|name| TimeseriesName::try_from(*name).unwrap())
+                .collect(),
+            max_series,
+            max_age: 60,
+        }
+    }
+
+    fn temperature(at: i64, sensor: i64, celsius: f64) -> Sample {
+        Sample::new_with_timestamp(
+            time(at),
+            &Server { name: String::from("a\"b") },
+            &Temperature { sensor, celsius },
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_render_families() {
+        let latest = LatestSamples::new(&config(
+            &[
+                "server:temperature",
+                "server:requests",
+                "server:latency",
+                "server:version",
+            ],
+            10,
+        ));
+        let server = Server { name: String::from("s") };
+        let histogram = Histogram::from_arrays(
+            time(10),
+            vec![f64::MIN, 0.0, 0.5],
+            vec![0, 1, 1],
+        )
+        .unwrap();
+        let samples = vec![
+            temperature(100, 0, 20.5),
+            // Older samples don't replace newer ones.
+            temperature(90, 0, 10.0),
+            temperature(100, 1, 21.0),
+            Sample::new_with_timestamp(
+                time(100),
+                &server,
+                &Requests {
+                    route: String::from("/"),
+                    count: Cumulative::with_start_time(time(10), 7),
+                },
+            )
+            .unwrap(),
+            Sample::new_with_timestamp(
+                time(100),
+                &server,
+                &Latency { seconds: histogram },
+            )
+            .unwrap(),
+            Sample::new_with_timestamp(
+                time(100),
+                &server,
+                &Version { id: Uuid::nil(), version: String::from("1.0") },
+            )
+            .unwrap(),
+        ];
+        latest.update_at(&samples, time(100));
+        assert_eq!(
+            latest.render_at(time(110)),
+            "# TYPE server_latency histogram\n\
+             server_latency_bucket{name=\"s\",le=\"0.0\"} 0 100.000\n\
+             server_latency_bucket{name=\"s\",le=\"0.5\"} 1 100.000\n\
+             server_latency_bucket{name=\"s\",le=\"+Inf\"} 2 100.000\n\
+             server_latency_created{name=\"s\"} 10.000 100.000\n\
+             # TYPE server_requests counter\n\
+             server_requests_total{name=\"s\",route=\"/\"} 7 100.000\n\
+             server_requests_created{name=\"s\",route=\"/\"} 10.000 100.000\n\
+             # TYPE server_temperature gauge\n\
+             server_temperature{name=\"a\\\"b\",sensor=\"0\"} 20.5 100.000\n\
+             server_temperature{name=\"a\\\"b\",sensor=\"1\"} 21 100.000\n\
+             # EOF\n"
+        );
+    }
+
+    #[test]
+    fn test_render_non_finite() {
+        let latest = LatestSamples::new(&config(&["server:temperature"], 10));
+        latest.update_at(
+            &[
+                temperature(100, 0, f64::INFINITY),
+                temperature(100, 1, f64::NEG_INFINITY),
+                temperature(100, 2, f64::NAN),
+            ],
+            time(100),
+        );
+        assert_eq!(
+            latest.render_at(time(100)),
+            "# TYPE server_temperature gauge\n\
+             server_temperature{name=\"a\\\"b\",sensor=\"0\"} +Inf 100.000\n\
+             server_temperature{name=\"a\\\"b\",sensor=\"1\"} -Inf 100.000\n\
+             server_temperature{name=\"a\\\"b\",sensor=\"2\"} NaN 100.000\n\
+             # EOF\n"
+        );
+
+        assert_eq!(number(f32::INFINITY), "+Inf");
+        assert_eq!(number(f32::NEG_INFINITY), "-Inf");
+        assert_eq!(number(f32::NAN), "NaN");
+        assert_eq!(number(1.5f32), "1.5");
+        assert_eq!(number(-3i64), "-3");
+        assert_eq!(bucket_bound(f64::INFINITY), "+Inf");
+        assert_eq!(bucket_bound(f64::NEG_INFINITY), "-Inf");
+        assert_eq!(bucket_bound(2.0f64), "2.0");
+        assert_eq!(bucket_bound(2u64), "2.0");
+    }
+
+    #[test]
+    fn test_allow_list_and_limits() {
+        let latest = LatestSamples::new(&config(&["server:temperature"], 2));
+
+        // Timeseries that aren't allow-listed aren't exported.
+        let requests = Sample::new_with_timestamp(
+            time(100),
+            &Server { name: String::from("s") },
+            &Requests {
+                route: String::from("/"),
+                count: Cumulative::with_start_time(time(10), 7),
+            },
+        )
+        .unwrap();
+        latest.update_at([&requests], time(100));
+        assert_eq!(latest.render_at(time(100)), "# EOF\n");
+
+        // Neither are series beyond the limit, ...
+        latest.update_at(
+            &[
+                temperature(100, 0, 1.0),
+                temperature(100, 1, 1.0),
+                temperature(100, 2, 1.0),
+            ],
+            time(100),
+        );
+        let rendered = latest.render_at(time(100));
+        assert!(rendered.contains("sensor=\"1\""));
+        assert!(!rendered.contains("sensor=\"2\""));
+
+        // ... until the others expire.
+        latest.update_at(&[temperature(200, 2, 1.0)], time(200));
+        assert_eq!(
+            latest.render_at(time(200)),
+            "# TYPE server_temperature gauge\n\
+             server_temperature{name=\"a\\\"b\",sensor=\"2\"} 1 200.000\n\
+             # EOF\n"
+        );
+        assert_eq!(latest.render_at(time(300)), "# EOF\n");
+    }
+}
@@ -0,0 +1,420 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! An on-disk spool of sample batches that could not be inserted into the
+//! metric database.
+
+// Copyright 2023 Oxide Computer Company
+
+use crate::Error;
+use oximeter::types::{Cumulative, Sample};
+use oximeter::{Metric, MetricsError, Target};
+use serde::{Deserialize, Serialize};
+use slog::{debug, o, warn, Logger};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// Configuration for the spool of batches that failed to be inserted.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SpoolConfig {
+    /// Directory in which failed batches are stored, one file per batch.
+    pub directory: PathBuf,
+
+    /// The maximum total size of the spooled batches, in bytes.
+    ///
+    /// When spooling a batch would exceed this, the oldest batches are
+    /// discarded to make room.
+    pub max_bytes: u64,
+}
+
+/// The oximeter collector itself, as a target for metrics about its spool.
+#[derive(Debug, Clone, Target)]
+struct OximeterCollector {
+    collector_id: Uuid,
+}
+
+/// The number of batches waiting in the spool.
+#[derive(Debug, Clone, Metric)]
+struct SpoolBatches {
+    #[datum]
+    batches: i64,
+}
+
+/// The total size of the batches waiting in the spool.
+#[derive(Debug, Clone, Metric)]
+struct SpoolBytes {
+    #[datum]
+    bytes: i64,
+}
+
+/// The number of samples discarded without being inserted, either because
+/// the spool was full or because the database rejected them.
+#[derive(Debug, Clone, Metric)]
+struct DroppedSamples {
+    #[datum]
+    samples: Cumulative<i64>,
+}
+
+// A batch stored in the spool.
+//
+// The number of samples is part of the file name, so that the spool's
+// contents can be accounted for without reading every batch on startup.
+#[derive(Debug)]
+struct SpoolEntry {
+    sequence: u64,
+    n_samples: usize,
+    bytes: u64,
+}
+
+impl SpoolEntry {
+    fn file_name(&self) -> String {
+        format!("{:020}-{}.json", self.sequence, self.n_samples)
+    }
+
+    fn parse(file_name: &str, bytes: u64) -> Option<Self> {
+        let (sequence, n_samples) =
+            file_name.strip_suffix(".json")?.split_once('-')?;
+        Some(Self {
+            sequence: sequence.parse().ok()?,
+            n_samples: n_samples.parse().ok()?,
+            bytes,
+        })
+    }
+}
+
+/// A bounded, ordered queue of sample batches, persisted to disk.
+///
+/// Batches are replayed in the order in which they were spooled. Note that
+/// ClickHouse provides no transactions, so a batch that was partially
+/// inserted before failing may have some of its rows inserted twice when it
+/// is replayed.
+#[derive(Debug)]
+pub(crate) struct Spool {
+    log: Logger,
+    directory: PathBuf,
+    max_bytes: u64,
+    entries: VecDeque<SpoolEntry>,
+    next_sequence: u64,
+    bytes: u64,
+    target: OximeterCollector,
+    dropped_samples: DroppedSamples,
+}
+
+impl Spool {
+    /// Open the spool in the configured directory, creating it if needed.
+    ///
+    /// Any batches left from a previous run of the collector are kept, and
+    /// will be replayed before new ones.
+    pub fn open(
+        log: &Logger,
+        collector_id: Uuid,
+        config: &SpoolConfig,
+    ) -> Result<Self, Error> {
+        let directory = config.directory.clone();
+        fs::create_dir_all(&directory).map_err(|e| {
+            spool_error(&directory, "failed to create spool directory", e)
+        })?;
+        let mut entries = Vec::new();
+        let read_dir = fs::read_dir(&directory).map_err(|e| {
+            spool_error(&directory, "failed to read spool directory", e)
+        })?;
+        for dirent in read_dir {
+            let dirent = dirent.map_err(|e| {
+                spool_error(&directory, "failed to read spool directory", e)
+            })?;
+            let path = dirent.path();
+            let bytes = dirent
+                .metadata()
+                .map_err(|e| {
+                    spool_error(&path, "failed to read spooled batch", e)
+                })?
+                .len();
+            if path.extension().map_or(false, |ext| ext == "tmp") {
+                // A batch that was being written when the collector stopped.
+                let _ = fs::remove_file(&path);
+                continue;
+            }
+            match dirent
+                .file_name()
+                .to_str()
+                .and_then(|name| SpoolEntry::parse(name, bytes))
+            {
+                Some(entry) => entries.push(entry),
+                None => warn!(
+                    log,
+                    "ignoring unrecognized file in spool directory";
+                    "path" => %path.display(),
+                ),
+            }
+        }
+        entries.sort_by_key(|entry| entry.sequence);
+        let next_sequence =
+            entries.last().map_or(0, |entry| entry.sequence + 1);
+        let bytes = entries.iter().map(|entry| entry.bytes).sum();
+        let log = log.new(o!("component" => "spool"));
+        debug!(
+            log,
+            "opened spool";
+            "directory" => %directory.display(),
+            "batches" => entries.len(),
+            "bytes" => bytes,
+        );
+        Ok(Self {
+            log,
+            directory,
+            max_bytes: config.max_bytes,
+            entries: entries.into(),
+            next_sequence,
+            bytes,
+            target: OximeterCollector { collector_id },
+            dropped_samples: DroppedSamples { samples: Cumulative::new(0) },
+        })
+    }
+
+    /// Return true if there are no batches in the spool.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Add a batch to the back of the spool.
+    ///
+    /// If the spool would grow beyond its maximum size, the oldest batches are
+    /// discarded, and counted as dropped. A batch that is larger than the
+    /// whole spool is discarded itself.
+    pub fn push(&mut self, samples: &[Sample]) -> Result<(), Error> {
+        let contents = serde_json::to_vec(samples).map_err(|e| {
+            Error::Spool(format!("failed to serialize batch: {}", e))
+        })?;
+        let entry = SpoolEntry {
+            sequence: self.next_sequence,
+            n_samples: samples.len(),
+            bytes: contents.len() as u64,
+        };
+        if entry.bytes > self.max_bytes {
+            warn!(
+                self.log,
+                "batch is larger than the spool, dropping it";
+                "samples" => samples.len(),
+                "bytes" => entry.bytes,
+            );
+            self.record_dropped(samples.len());
+            return Ok(());
+        }
+        while self.bytes + entry.bytes > self.max_bytes {
+            let oldest = self.entries.pop_front().unwrap();
+            warn!(
+                self.log,
+                "spool is full, dropping oldest batch";
+                "samples" => oldest.n_samples,
+                "bytes" => oldest.bytes,
+            );
+            self.remove_file(&oldest)?;
+            self.record_dropped(oldest.n_samples);
+        }
+
+        // Write to a temporary file first, so that a crash never leaves a
+        // partially-written batch where it will be replayed.
+        let path = self.directory.join(entry.file_name());
+        let temp_path = path.with_extension("tmp");
+        fs::write(&temp_path, &contents).map_err(|e| {
+            spool_error(&temp_path, "failed to write spooled batch", e)
+        })?;
+        fs::rename(&temp_path, &path).map_err(|e| {
+            spool_error(&path, "failed to write spooled batch", e)
+        })?;
+        debug!(
+            self.log,
+            "spooled batch";
+            "samples" => entry.n_samples,
+            "bytes" => entry.bytes,
+        );
+        self.next_sequence += 1;
+        self.bytes += entry.bytes;
+        self.entries.push_back(entry);
+        Ok(())
+    }
+
+    /// Read the oldest batch in the spool, without removing it.
+    ///
+    /// A batch that can't be read is discarded, and counted as dropped.
+    pub fn front(&mut self) -> Result<Option<Vec<Sample>>, Error> {
+        while let Some(entry) = self.entries.front() {
+            let path = self.directory.join(entry.file_name());
+            let batch = fs::read(&path).map_err(|e| e.to_string()).and_then(
+                |contents| {
+                    serde_json::from_slice(&contents).map_err(|e| e.to_string())
+                },
+            );
+            match batch {
+                Ok(batch) => return Ok(Some(batch)),
+                Err(e) => {
+                    warn!(
+                        self.log,
+                        "failed to read spooled batch, dropping it";
+                        "path" => %path.display(),
+                        "error" => e,
+                    );
+                    self.pop_front(false)?;
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Remove the oldest batch from the spool, either because it was inserted
+    /// or because it should be dropped.
+    pub fn pop_front(&mut self, inserted: bool) -> Result<(), Error> {
+        if let Some(entry) = self.entries.pop_front() {
+            self.remove_file(&entry)?;
+            if !inserted {
+                self.record_dropped(entry.n_samples);
+            }
+        }
+        Ok(())
+    }
+
+    /// Count samples that were discarded without being inserted.
+    pub fn record_dropped(&mut self, n_samples: usize) {
+        self.dropped_samples.samples += n_samples as i64;
+    }
+
+    /// Return samples describing the current state of the spool.
+    pub fn samples(&self) -> Result<Vec<Sample>, MetricsError> {
+        Ok(vec![
+            Sample::new(
+                &self.target,
+                &SpoolBatches { batches: self.entries.len() as i64 },
+            )?,
+            Sample::new(
+                &self.target,
+                &SpoolBytes { bytes: self.bytes as i64 },
+            )?,
+            Sample::new(&self.target, &self.dropped_samples)?,
+        ])
+    }
+
+    fn remove_file(&mut self, entry: &SpoolEntry) -> Result<(), Error> {
+        self.bytes -= entry.bytes;
+        let path = self.directory.join(entry.file_name());
+        match fs::remove_file(&path) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => {
+                Err(spool_error(&path, "failed to remove spooled batch", e))
+            }
+        }
+    }
+}
+
+fn spool_error(path: &Path, message: &str, err: std::io::Error) -> Error {
+    Error::Spool(format!("{} \"{}\": {}", message, path.display(), err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use omicron_test_utils::dev::test_setup_log;
+
+    #[derive(Debug, Clone, Target)]
+    struct TestTarget {
+        name: String,
+    }
+
+    #[derive(Debug, Clone, Metric)]
+    struct TestMetric {
+        datum: i64,
+    }
+
+    fn batch(n: usize) -> Vec<Sample> {
+        (0..n)
+            .map(|i| {
+                Sample::new(
+                    &TestTarget { name: String::from("spool") },
+                    &TestMetric { datum: i as i64 },
+                )
+                .unwrap()
+            })
+            .collect()
+    }
+
+    fn dropped(spool: &Spool) -> i64 {
+        spool.dropped_samples.samples.value()
+    }
+
+    #[test]
+    fn test_spool_replays_in_order() {
+        let logctx = test_setup_log("test_spool_replays_in_order");
+        let dir = tempfile::tempdir().unwrap();
+        let config = SpoolConfig {
+            directory: dir.path().join("spool"),
+            max_bytes: 1 << 20,
+        };
+        let id = Uuid::new_v4();
+        let mut spool = Spool::open(&logctx.log, id, &config).unwrap();
+        assert!(spool.is_empty());
+        spool.push(&batch(1)).unwrap();
+        spool.push(&batch(2)).unwrap();
+        spool.push(&batch(3)).unwrap();
+
+        // Batches survive reopening the spool, and are read oldest first.
+        drop(spool);
+        let mut spool = Spool::open(&logctx.log, id, &config).unwrap();
+        for n in 1..=3 {
+            assert_eq!(spool.front().unwrap().unwrap().len(), n);
+            spool.pop_front(true).unwrap();
+        }
+        assert!(spool.front().unwrap().is_none());
+        assert!(spool.is_empty());
+        assert_eq!(spool.bytes, 0);
+        assert_eq!(dropped(&spool), 0);
+
+        // New batches are ordered after any that were replayed.
+        spool.push(&batch(4)).unwrap();
+        drop(spool);
+        let mut spool = Spool::open(&logctx.log, id, &config).unwrap();
+        assert_eq!(spool.front().unwrap().unwrap().len(), 4);
+        logctx.cleanup_successful();
+    }
+
+    #[test]
+    fn test_spool_is_bounded() {
+        let logctx = test_setup_log("test_spool_is_bounded");
+        let dir = tempfile::tempdir().unwrap();
+        let batch_size = serde_json::to_vec(&batch(2)).unwrap().len() as u64;
+        let config = SpoolConfig {
+            directory: dir.path().to_path_buf(),
+            max_bytes: batch_size * 2,
+        };
+        let mut spool =
+            Spool::open(&logctx.log, Uuid::new_v4(), &config).unwrap();
+
+        // Filling the spool drops the oldest batches.
+        spool.push(&batch(2)).unwrap();
+        spool.push(&batch(2)).unwrap();
+        spool.push(&batch(2)).unwrap();
+        assert_eq!(spool.entries.len(), 2);
+        assert!(spool.bytes <= config.max_bytes);
+        assert_eq!(dropped(&spool), 2);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+
+        // A batch that can never fit is dropped itself.
+        spool.push(&batch(10)).unwrap();
+        assert_eq!(spool.entries.len(), 2);
+        assert_eq!(dropped(&spool), 12);
+
+        // Dropping a batch explicitly is counted too.
+        spool.pop_front(false).unwrap();
+        assert_eq!(dropped(&spool), 14);
+
+        let samples = spool.samples().unwrap();
+        assert_eq!(samples.len(), 3);
+        assert_eq!(
+            samples[0].timeseries_name,
+            "oximeter_collector:spool_batches"
+        );
+        logctx.cleanup_successful();
+    }
+}
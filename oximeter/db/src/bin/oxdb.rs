@@ -12,10 +12,13 @@ use oximeter::{
     types::{Cumulative, Sample},
     Metric, Target,
 };
-use oximeter_db::{query, Client, DbWrite};
+use oximeter_db::{
+    query, Aggregation, AggregationOp, Client, DbWrite, TimeseriesName,
+};
 use slog::{debug, info, o, Drain, Level, Logger};
 use std::net::IpAddr;
 use std::net::SocketAddr;
+use std::time::Duration;
 use uuid::Uuid;
 
 // Samples are inserted in chunks of this size, to avoid large allocations when inserting huge
@@ -137,6 +140,47 @@ enum Subcommand {
         /// The start time to which the search is constrained, exclusive.
         #[clap(long, conflicts_with("end"), action)]
         end_exclusive: Option<DateTime<Utc>>,
+
+        /// Aggregate the measurements of the selected timeseries over windows of time, with one of
+        /// `mean`, `min`, `max`, `sum`, `delta`, `rate`, or a percentile such as `p99`.
+        #[clap(long, action)]
+        aggregate: Option<AggregationOp>,
+
+        /// The width of the aggregation windows, in seconds.
+        #[clap(long, default_value_t = 60, requires("aggregate"), action)]
+        interval: u64,
+
+        /// A target field by which to group timeseries when aggregating them. May be given more
+        /// than once.
+        #[clap(long, requires("aggregate"), action)]
+        group_by: Vec<String>,
+    },
+
+    /// List or migrate the versions of a timeseries schema
+    Schema {
+        #[clap(subcommand)]
+        cmd: SchemaSubcommand,
+    },
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum SchemaSubcommand {
+    /// List all versions of the schema for a timeseries, oldest first.
+    List {
+        /// The name of the timeseries, of the form `target_name:metric_name`.
+        #[clap(action)]
+        timeseries_name: String,
+    },
+
+    /// Migrate timeseries recorded with earlier versions of a schema to the latest version.
+    ///
+    /// Fields added in later versions are filled in with a default value (zero, an empty string,
+    /// etc.) for the timeseries that don't have them. Versions whose fields have changed type, or
+    /// whose datum type differs, can't be migrated and are left alone.
+    Migrate {
+        /// The name of the timeseries, of the form `target_name:metric_name`.
+        #[clap(action)]
+        timeseries_name: String,
     },
 }
 
@@ -278,9 +322,25 @@ async fn query(
     filters: Vec<String>,
     start: Option<query::Timestamp>,
     end: Option<query::Timestamp>,
+    aggregation: Option<Aggregation>,
 ) -> Result<(), anyhow::Error> {
     let client = make_client(address, port, &log).await?;
     let filters = filters.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+    if let Some(aggregation) = aggregation {
+        let timeseries = client
+            .select_aggregated_timeseries_with(
+                &timeseries_name,
+                filters.as_slice(),
+                start,
+                end,
+                None,
+                None,
+                aggregation,
+            )
+            .await?;
+        println!("{}", serde_json::to_string(&timeseries).unwrap());
+        return Ok(());
+    }
     let timeseries = client
         .select_timeseries_with(
             &timeseries_name,
@@ -295,6 +355,32 @@ async fn query(
     Ok(())
 }
 
+async fn schema(
+    address: IpAddr,
+    port: u16,
+    log: Logger,
+    cmd: SchemaSubcommand,
+) -> Result<(), anyhow::Error> {
+    let client = make_client(address, port, &log).await?;
+    match cmd {
+        SchemaSubcommand::List { timeseries_name } => {
+            let timeseries_name = TimeseriesName::try_from(timeseries_name)?;
+            let versions =
+                client.timeseries_schema_versions(&timeseries_name).await?;
+            println!("{}", serde_json::to_string(&versions).unwrap());
+        }
+        SchemaSubcommand::Migrate { timeseries_name } => {
+            let timeseries_name = TimeseriesName::try_from(timeseries_name)?;
+            let schema = client
+                .migrate_timeseries_schema(&timeseries_name)
+                .await
+                .context("Failed to migrate timeseries schema")?;
+            println!("{}", serde_json::to_string(&schema).unwrap());
+        }
+    }
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() {
     let args = OxDb::parse();
@@ -322,6 +408,9 @@ async fn main() {
             start_exclusive,
             end,
             end_exclusive,
+            aggregate,
+            interval,
+            group_by,
         } => {
             let start = match (start, start_exclusive) {
                 (Some(start), _) => Some(query::Timestamp::Inclusive(start)),
@@ -333,6 +422,11 @@ async fn main() {
                 (_, Some(end)) => Some(query::Timestamp::Exclusive(end)),
                 (None, None) => None,
             };
+            let aggregation = aggregate.map(|op| Aggregation {
+                op,
+                interval: Duration::from_secs(interval),
+                group_by,
+            });
             query(
                 args.address,
                 args.port,
@@ -341,9 +435,13 @@ async fn main() {
                 filters,
                 start,
                 end,
+                aggregation,
             )
             .await
             .unwrap();
         }
+        Subcommand::Schema { cmd } => {
+            schema(args.address, args.port, log, cmd).await.unwrap()
+        }
     }
 }
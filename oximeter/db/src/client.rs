@@ -8,8 +8,14 @@
 
 use crate::model;
 use crate::query;
+use crate::retention;
+use crate::AggregatedTimeseries;
 use crate::Error;
+use crate::Field;
+use crate::FieldSchema;
 use crate::Metric;
+use crate::RetentionPolicy;
+use crate::RollupResolution;
 use crate::Target;
 use crate::Timeseries;
 use crate::TimeseriesKey;
@@ -18,16 +24,20 @@ use crate::TimeseriesPageSelector;
 use crate::TimeseriesScanParams;
 use crate::TimeseriesSchema;
 use async_trait::async_trait;
+use chrono::DateTime;
+use chrono::Utc;
 use dropshot::EmptyScanParams;
 use dropshot::PaginationOrder;
 use dropshot::ResultsPage;
 use dropshot::WhichPage;
+use oximeter::types::DatumType;
 use oximeter::types::Sample;
 use slog::debug;
 use slog::error;
+use slog::info;
 use slog::trace;
+use slog::warn;
 use slog::Logger;
-use std::collections::btree_map::Entry;
 use std::collections::BTreeMap;
 use std::collections::BTreeSet;
 use std::convert::TryFrom;
@@ -36,6 +46,10 @@ use std::num::NonZeroU32;
 use std::sync::Mutex;
 use uuid::Uuid;
 
+// The number of times we'll try to record a new version of a timeseries schema whose version was
+// taken by another client at the same time.
+const MAX_SCHEMA_INSERT_ATTEMPTS: usize = 3;
+
 #[usdt::provider(provider = "clickhouse__client")]
 mod probes {
     fn query__start(_: &usdt::UniqueId, sql: &str) {}
@@ -49,7 +63,8 @@ pub struct Client {
     log: Logger,
     url: String,
     client: reqwest::Client,
-    schema: Mutex<BTreeMap<TimeseriesName, TimeseriesSchema>>,
+    // All versions of the schema of each timeseries, in order of their version.
+    schema: Mutex<BTreeMap<TimeseriesName, Vec<TimeseriesSchema>>>,
 }
 
 impl Client {
@@ -101,6 +116,152 @@ impl Client {
         //  values from the measurement rows, we avoid transferring the data from those columns
         //  to/from the database, as well as the cost of parsing them for each measurement, only to
         //  promptly throw away almost all of them (except for the first).
+        let (schema, query_builder) = self
+            .select_query_builder(
+                timeseries_name,
+                criteria,
+                start_time,
+                end_time,
+                limit,
+                order,
+            )
+            .await?;
+        let query = query_builder.build();
+        let info = match query.field_query() {
+            Some(field_query) => {
+                self.select_matching_timeseries_info(&field_query, &schema)
+                    .await?
+            }
+            None => BTreeMap::new(),
+        };
+
+        if info.is_empty() {
+            // allow queries that resolve to zero timeseries even with limit
+            // specified because the results are not misleading
+            Ok(vec![])
+        } else if limit.is_some() && info.len() != 1 {
+            // Error if a limit is specified with a query that resolves to
+            // multiple timeseries. Because query results are ordered in part by
+            // timeseries key, results with a limit will select measurements in
+            // a way that is arbitrary with respect to the query.
+            Err(Error::InvalidLimitQuery)
+        } else {
+            self.select_timeseries_with_keys(&query, &info, &schema).await
+        }
+    }
+
+    /// Select timeseries from criteria on the fields and start/end timestamps, and aggregate
+    /// their measurements.
+    ///
+    /// See [`query::Aggregation`] for how the measurements are aggregated. The aggregation is
+    /// performed by the database, so only the aggregated values are returned.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn select_aggregated_timeseries_with(
+        &self,
+        timeseries_name: &str,
+        criteria: &[&str],
+        start_time: Option<query::Timestamp>,
+        end_time: Option<query::Timestamp>,
+        limit: Option<NonZeroU32>,
+        order: Option<PaginationOrder>,
+        aggregation: query::Aggregation,
+    ) -> Result<Vec<AggregatedTimeseries>, Error> {
+        let (schema, query_builder) = self
+            .select_query_builder(
+                timeseries_name,
+                criteria,
+                start_time,
+                end_time,
+                limit,
+                order,
+            )
+            .await?;
+        let query = query_builder.aggregate(aggregation)?.build();
+        let info = match query.field_query() {
+            Some(field_query) => {
+                self.select_matching_timeseries_info(&field_query, &schema)
+                    .await?
+            }
+            None => BTreeMap::new(),
+        };
+        if info.is_empty() {
+            return Ok(vec![]);
+        }
+
+        // Assign each timeseries to a group by the values of the fields it's grouped by.
+        let group_by = &query.aggregation().unwrap().group_by;
+        let mut groups: Vec<Vec<Field>> = Vec::new();
+        let mut group_ids = BTreeMap::new();
+        for (key, (target, metric)) in info.iter() {
+            let fields = if group_by.is_empty() {
+                target
+                    .fields
+                    .iter()
+                    .chain(metric.fields.iter())
+                    .cloned()
+                    .collect()
+            } else {
+                group_by
+                    .iter()
+                    .map(|name| {
+                        target
+                            .fields
+                            .iter()
+                            .find(|field| &field.name == name)
+                            .expect("Group-by field checked against the timeseries schema")
+                            .clone()
+                    })
+                    .collect::<Vec<_>>()
+            };
+            let group_id =
+                match groups.iter().position(|group| *group == fields) {
+                    Some(index) => index,
+                    None => {
+                        groups.push(fields);
+                        groups.len() - 1
+                    }
+                };
+            group_ids.insert(*key, group_id as u64);
+        }
+
+        // As with unaggregated queries, a limit would select windows arbitrarily from among
+        // several groups.
+        if limit.is_some() && groups.len() != 1 {
+            return Err(Error::InvalidLimitQuery);
+        }
+
+        let measurement_query = query
+            .aggregated_measurement_query(&group_ids)
+            .expect("Query has an aggregation");
+        let mut timeseries = groups
+            .into_iter()
+            .map(|fields| AggregatedTimeseries {
+                timeseries_name: schema.timeseries_name.to_string(),
+                fields,
+                measurements: Vec::new(),
+            })
+            .collect::<Vec<_>>();
+        for line in self.execute_with_body(&measurement_query).await?.lines() {
+            let (group_id, measurement) =
+                model::parse_aggregated_measurement_from_row(line);
+            if let Some(measurement) = measurement {
+                timeseries[group_id as usize].measurements.push(measurement);
+            }
+        }
+        Ok(timeseries)
+    }
+
+    // Build a query selecting the timeseries with the given name that match the given criteria,
+    // returning the timeseries' schema along with the query.
+    async fn select_query_builder(
+        &self,
+        timeseries_name: &str,
+        criteria: &[&str],
+        start_time: Option<query::Timestamp>,
+        end_time: Option<query::Timestamp>,
+        limit: Option<NonZeroU32>,
+        order: Option<PaginationOrder>,
+    ) -> Result<(TimeseriesSchema, query::SelectQueryBuilder), Error> {
         let timeseries_name = TimeseriesName::try_from(timeseries_name)?;
         let schema =
             self.schema_for_timeseries(&timeseries_name).await?.ok_or_else(
@@ -109,6 +270,8 @@ impl Client {
         let query_builder = query::SelectQueryBuilder::new(&schema)
             .start_time(start_time)
             .end_time(end_time);
+        let query_builder =
+            self.select_resolution(query_builder, &schema, start_time).await?;
 
         let mut query_builder = if let Some(limit) = limit {
             query_builder.limit(limit)
@@ -123,28 +286,41 @@ impl Client {
         for criterion in criteria.iter() {
             query_builder = query_builder.filter_raw(criterion)?;
         }
+        Ok((schema, query_builder))
+    }
 
-        let query = query_builder.build();
-        let info = match query.field_query() {
-            Some(field_query) => {
-                self.select_matching_timeseries_info(&field_query, &schema)
-                    .await?
-            }
-            None => BTreeMap::new(),
+    // Select measurements from rollups of the timeseries, rather than at full resolution, if the
+    // measurements starting at `start_time` have expired under the timeseries' retention policy.
+    async fn select_resolution(
+        &self,
+        query_builder: query::SelectQueryBuilder,
+        schema: &TimeseriesSchema,
+        start_time: Option<query::Timestamp>,
+    ) -> Result<query::SelectQueryBuilder, Error> {
+        let start = match start_time {
+            Some(query::Timestamp::Inclusive(start))
+            | Some(query::Timestamp::Exclusive(start)) => start,
+            None => return Ok(query_builder),
         };
-
-        if info.is_empty() {
-            // allow queries that resolve to zero timeseries even with limit
-            // specified because the results are not misleading
-            Ok(vec![])
-        } else if limit.is_some() && info.len() != 1 {
-            // Error if a limit is specified with a query that resolves to
-            // multiple timeseries. Because query results are ordered in part by
-            // timeseries key, results with a limit will select measurements in
-            // a way that is arbitrary with respect to the query.
-            Err(Error::InvalidLimitQuery)
-        } else {
-            self.select_timeseries_with_keys(&query, &info, &schema).await
+        if !retention::supports_rollups(schema.datum_type) {
+            return Ok(query_builder);
+        }
+        let Some(policy) =
+            self.retention_policy(&schema.timeseries_name).await?
+        else {
+            return Ok(query_builder);
+        };
+        match policy.resolution_for(schema.datum_type, start, Utc::now()) {
+            Some(resolution) => {
+                debug!(
+                    self.log,
+                    "selecting measurements from rollups";
+                    "timeseries_name" => %schema.timeseries_name,
+                    "resolution" => %resolution,
+                );
+                query_builder.rollup(resolution)
+            }
+            None => Ok(query_builder),
         }
     }
 
@@ -177,6 +353,14 @@ impl Client {
             query_builder = query_builder.filter_str(criterion)?;
         }
 
+        let query_builder = self
+            .select_resolution(
+                query_builder,
+                &schema,
+                params.start_time.map(query::Timestamp::Inclusive),
+            )
+            .await?;
+
         let query = query_builder.build();
         let info = match query.field_query() {
             Some(field_query) => {
@@ -196,7 +380,11 @@ impl Client {
         .unwrap())
     }
 
-    /// Return the schema for a timeseries by name.
+    /// Return the latest version of the schema for a timeseries by name.
+    ///
+    /// Queries select timeseries using the latest version of their schema. Timeseries recorded
+    /// with earlier versions are only included if they've been migrated to the latest one (see
+    /// [`Client::migrate_timeseries_schema`]).
     ///
     /// Note
     /// ----
@@ -208,17 +396,147 @@ impl Client {
     ) -> Result<Option<TimeseriesSchema>, Error> {
         {
             let map = self.schema.lock().unwrap();
-            if let Some(s) = map.get(name) {
+            if let Some(s) = map.get(name).and_then(|versions| versions.last())
+            {
                 return Ok(Some(s.clone()));
             }
         }
         // `get_schema` acquires the lock internally, so the above scope is required to avoid
         // deadlock.
         self.get_schema().await?;
-        Ok(self.schema.lock().unwrap().get(name).map(Clone::clone))
+        Ok(self
+            .schema
+            .lock()
+            .unwrap()
+            .get(name)
+            .and_then(|versions| versions.last())
+            .cloned())
+    }
+
+    /// Return all versions of the schema for a timeseries, in order of their version.
+    ///
+    /// This always consults the database, since other clients may have added versions. If
+    /// clients recorded different schema with the same version, only the one created first is
+    /// returned.
+    pub async fn timeseries_schema_versions(
+        &self,
+        name: &TimeseriesName,
+    ) -> Result<Vec<TimeseriesSchema>, Error> {
+        let sql = format!(
+            concat!(
+                "SELECT * ",
+                "FROM {db_name}.timeseries_schema ",
+                "WHERE timeseries_name = '{timeseries_name}' ",
+                "FORMAT JSONEachRow;",
+            ),
+            db_name = crate::DATABASE_NAME,
+            timeseries_name = name,
+        );
+        let mut versions = Vec::new();
+        for line in self.execute_with_body(sql).await?.lines() {
+            let schema = TimeseriesSchema::from(
+                serde_json::from_str::<model::DbTimeseriesSchema>(line).expect(
+                    "Failed to deserialize TimeseriesSchema from database",
+                ),
+            );
+            add_schema_version(&mut versions, schema);
+        }
+        if !versions.is_empty() {
+            self.schema.lock().unwrap().insert(name.clone(), versions.clone());
+        }
+        Ok(versions)
+    }
+
+    /// Migrate timeseries recorded with earlier versions of a timeseries schema to the latest
+    /// version, returning that version.
+    ///
+    /// Timeseries whose schema lacks some fields of the latest version are given the default
+    /// values of those fields (see [`crate::FieldSchema::default_value`]), after which they're
+    /// selected by queries along with those recorded with the latest version. Only versions
+    /// that can be migrated are affected (see [`TimeseriesSchema::can_migrate_from`]). Timeseries
+    /// recorded with other versions remain in the database, but aren't selected by queries.
+    ///
+    /// Migrating a timeseries that's already been migrated has no effect.
+    pub async fn migrate_timeseries_schema(
+        &self,
+        name: &TimeseriesName,
+    ) -> Result<TimeseriesSchema, Error> {
+        let mut versions = self.timeseries_schema_versions(name).await?;
+        let latest = versions
+            .pop()
+            .ok_or_else(|| Error::TimeseriesNotFound(name.to_string()))?;
+        let migrated = versions
+            .iter()
+            .filter(|version| latest.can_migrate_from(version))
+            .collect::<Vec<_>>();
+        for field in latest.field_schema.iter() {
+            if migrated
+                .iter()
+                .all(|version| version.field_schema(&field.name).is_some())
+            {
+                continue;
+            }
+
+            // Give the field its default value in any timeseries that doesn't already have a
+            // value for it. This is determined from the field tables for each type the field has
+            // had in any version, so that timeseries from versions that can't be migrated aren't
+            // given a second value for the field.
+            let field_types = versions
+                .iter()
+                .chain(std::iter::once(&latest))
+                .filter_map(|version| version.field_schema(&field.name))
+                .map(|field| field.ty)
+                .collect::<BTreeSet<_>>();
+            let existing_keys = field_types
+                .into_iter()
+                .map(|ty| {
+                    format!(
+                        concat!(
+                            "SELECT timeseries_key FROM {db_name}.{table_name} ",
+                            "WHERE timeseries_name = '{timeseries_name}' ",
+                            "AND field_name = '{field_name}'",
+                        ),
+                        db_name = crate::DATABASE_NAME,
+                        table_name = query::field_table_name(ty),
+                        timeseries_name = name,
+                        field_name = field.name,
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(" UNION ALL ");
+            let sql = format!(
+                concat!(
+                    "INSERT INTO {db_name}.{field_table} ",
+                    "(timeseries_name, timeseries_key, field_name, field_value) ",
+                    "SELECT DISTINCT timeseries_name, timeseries_key, '{field_name}', {default} ",
+                    "FROM {db_name}.{measurement_table} ",
+                    "WHERE timeseries_name = '{timeseries_name}' ",
+                    "AND timeseries_key NOT IN ({existing_keys});",
+                ),
+                db_name = crate::DATABASE_NAME,
+                field_table = query::field_table_name(field.ty),
+                field_name = field.name,
+                default = query::field_as_db_str(&field.default_value()),
+                measurement_table =
+                    query::measurement_table_name(latest.datum_type),
+                timeseries_name = name,
+                existing_keys = existing_keys,
+            );
+            self.execute(sql).await?;
+            debug!(
+                self.log,
+                "migrated timeseries to latest schema version";
+                "timeseries_name" => %name,
+                "version" => latest.version.get(),
+                "field_name" => &field.name,
+            );
+        }
+        Ok(latest)
     }
 
     /// List timeseries schema, paginated.
+    ///
+    /// Only the latest version of the schema for each timeseries is listed.
     pub async fn timeseries_schema_list(
         &self,
         page: &WhichPage<EmptyScanParams, TimeseriesName>,
@@ -230,6 +548,8 @@ impl Client {
                     concat!(
                         "SELECT * ",
                         "FROM {}.timeseries_schema ",
+                        "ORDER BY timeseries_name, version DESC ",
+                        "LIMIT 1 BY timeseries_name ",
                         "LIMIT {} ",
                         "FORMAT JSONEachRow;",
                     ),
@@ -242,6 +562,8 @@ impl Client {
                     concat!(
                         "SELECT * FROM {}.timeseries_schema ",
                         "WHERE timeseries_name > '{}' ",
+                        "ORDER BY timeseries_name, version DESC ",
+                        "LIMIT 1 BY timeseries_name ",
                         "LIMIT {} ",
                         "FORMAT JSONEachRow;",
                     ),
@@ -269,6 +591,56 @@ impl Client {
         .map_err(|e| Error::Database(e.to_string()))
     }
 
+    /// Return the retention policies applied to the database, ordered by timeseries name.
+    ///
+    /// See [`DbWrite::apply_retention_policies`].
+    pub async fn retention_policies(
+        &self,
+    ) -> Result<Vec<RetentionPolicy>, Error> {
+        let sql = format!(
+            "SELECT * FROM {db_name}.timeseries_retention ORDER BY timeseries_name FORMAT JSONEachRow;",
+            db_name = crate::DATABASE_NAME,
+        );
+        let body = self.execute_with_body(sql).await?;
+        Ok(body
+            .lines()
+            .map(|line| {
+                RetentionPolicy::from(
+                    serde_json::from_str::<model::DbRetentionPolicy>(line)
+                        .expect(
+                        "Failed to deserialize RetentionPolicy from database",
+                    ),
+                )
+            })
+            .collect())
+    }
+
+    /// Return the retention policy applied to a timeseries, if there is one.
+    pub async fn retention_policy(
+        &self,
+        name: &TimeseriesName,
+    ) -> Result<Option<RetentionPolicy>, Error> {
+        let sql = format!(
+            concat!(
+                "SELECT * ",
+                "FROM {db_name}.timeseries_retention ",
+                "WHERE timeseries_name = '{name}' ",
+                "LIMIT 1 ",
+                "FORMAT JSONEachRow;",
+            ),
+            db_name = crate::DATABASE_NAME,
+            name = name,
+        );
+        let body = self.execute_with_body(sql).await?;
+        Ok(body.lines().next().map(|line| {
+            RetentionPolicy::from(
+                serde_json::from_str::<model::DbRetentionPolicy>(line).expect(
+                    "Failed to deserialize RetentionPolicy from database",
+                ),
+            )
+        }))
+    }
+
     // Verifies if instance is part of oximeter_cluster
     pub async fn is_oximeter_cluster(&self) -> Result<bool, Error> {
         let sql = String::from("SHOW CLUSTERS FORMAT JSONEachRow;");
@@ -276,40 +648,161 @@ impl Client {
         Ok(res.contains("oximeter_cluster"))
     }
 
-    // Verifies that the schema for a sample matches the schema in the database.
+    // Verifies that the schema for a sample matches a version of the schema in the database.
     //
-    // If the schema exists in the database, and the sample matches that schema, `None` is
-    // returned. If the schema does not match, an Err is returned (the caller skips the sample in
-    // this case). If the schema does not _exist_ in the database, Some(schema) is returned, so
-    // that the caller can insert it into the database at the appropriate time.
+    // If the sample matches any version of the schema, `None` is returned. Otherwise, the sample's
+    // schema is cached as a new version, and Some(schema) is returned, so that the caller can
+    // insert it into the database at the appropriate time. An Err is returned if there are
+    // already as many versions as we support (the caller skips the sample in this case).
+    //
+    // The cache is expected to hold any versions of the schema already in the database.
     async fn verify_sample_schema(
         &self,
         sample: &Sample,
-    ) -> Result<Option<String>, Error> {
-        let schema = model::schema_for(sample);
-        let name = schema.timeseries_name.clone();
-        let maybe_new_schema = match self.schema.lock().unwrap().entry(name) {
-            Entry::Vacant(entry) => Ok(Some(entry.insert(schema).clone())),
-            Entry::Occupied(entry) => {
-                let existing_schema = entry.get();
-                if existing_schema == &schema {
-                    Ok(None)
+    ) -> Result<Option<TimeseriesSchema>, Error> {
+        self.verify_schema(model::schema_for(sample))
+    }
+
+    // Verifies that a schema matches a version of the schema in the database, as for
+    // `verify_sample_schema`.
+    fn verify_schema(
+        &self,
+        mut schema: TimeseriesSchema,
+    ) -> Result<Option<TimeseriesSchema>, Error> {
+        let mut cache = self.schema.lock().unwrap();
+        let versions = cache.entry(schema.timeseries_name.clone()).or_default();
+        if versions.iter().any(|version| version.same_fields_and_type(&schema))
+        {
+            return Ok(None);
+        }
+        if let Some(latest) = versions.last() {
+            let Some(version) = latest.version.checked_add(1) else {
+                let err = error_for_schema_mismatch(&schema, latest);
+                error!(
+                    self.log,
+                    "too many versions of timeseries schema, sample will be skipped: {}",
+                    err
+                );
+                return Err(err);
+            };
+            schema.version = version;
+            info!(
+                self.log,
+                "new version of timeseries schema";
+                "timeseries_name" => %schema.timeseries_name,
+                "version" => schema.version.get(),
+                "can_migrate" => schema.can_migrate_from(latest),
+            );
+        }
+        versions.push(schema.clone());
+        Ok(Some(schema))
+    }
+
+    // Inserts new versions of timeseries schema into the database, returning them with the
+    // versions they were recorded with.
+    //
+    // After inserting, we read back the versions of each timeseries to check that ours were kept.
+    // If another client recorded a different schema with the same version first, ours is
+    // recorded again as a later version. That relies on reading what we've inserted: with a
+    // replicated database, a conflicting schema inserted through another replica may only be
+    // seen once it's replicated, but clients that read the schema after that still agree on
+    // which was kept.
+    async fn insert_new_schema(
+        &self,
+        mut pending: Vec<TimeseriesSchema>,
+    ) -> Result<Vec<TimeseriesSchema>, Error> {
+        let mut inserted = Vec::with_capacity(pending.len());
+        for _ in 0..MAX_SCHEMA_INSERT_ATTEMPTS {
+            if pending.is_empty() {
+                break;
+            }
+            debug!(
+                self.log,
+                "inserting {} new timeseries schema",
+                pending.len()
+            );
+            let body = format!(
+                "INSERT INTO {db_name}.timeseries_schema FORMAT JSONEachRow\n{row_data}\n",
+                db_name = crate::DATABASE_NAME,
+                row_data = pending
+                    .iter()
+                    .map(|schema| {
+                        serde_json::to_string(&model::DbTimeseriesSchema::from(
+                            schema.clone(),
+                        ))
+                        .expect("Failed to convert schema to DB model")
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            );
+            if let Err(e) = self.execute(body).await {
+                // The new schema were cached when they were verified. Forget them, so that
+                // they're inserted again if these samples are retried.
+                self.forget_schema(&pending);
+                return Err(e);
+            }
+
+            let mut conflicting = Vec::new();
+            for schema in std::mem::take(&mut pending) {
+                let versions = match self
+                    .timeseries_schema_versions(&schema.timeseries_name)
+                    .await
+                {
+                    Ok(versions) => versions,
+                    Err(e) => {
+                        self.forget_schema(&[schema]);
+                        return Err(e);
+                    }
+                };
+                let kept = versions.iter().any(|version| {
+                    version.version == schema.version
+                        && version.same_fields_and_type(&schema)
+                });
+                if kept {
+                    inserted.push(schema);
                 } else {
-                    let err =
-                        error_for_schema_mismatch(&schema, &existing_schema);
-                    error!(
-                        self.log,
-                        "timeseries schema mismatch, sample will be skipped: {}",
-                        err
-                    );
-                    Err(err)
+                    conflicting.push(schema);
                 }
             }
-        }?;
-        Ok(maybe_new_schema.map(|schema| {
-            serde_json::to_string(&model::DbTimeseriesSchema::from(schema))
-                .expect("Failed to convert schema to DB model")
-        }))
+
+            // Reading the versions back replaced them in the cache, so verifying the schema
+            // again gives it the next version (or finds that another client has since recorded
+            // the same schema).
+            for schema in conflicting {
+                warn!(
+                    self.log,
+                    "timeseries schema version was taken by another client";
+                    "timeseries_name" => %schema.timeseries_name,
+                    "version" => schema.version.get(),
+                );
+                if let Ok(Some(schema)) = self.verify_schema(schema) {
+                    pending.push(schema);
+                }
+            }
+        }
+        if !pending.is_empty() {
+            warn!(
+                self.log,
+                "failed to record new timeseries schema, will retry with later samples";
+                "timeseries_names" => ?pending
+                    .iter()
+                    .map(|schema| schema.timeseries_name.to_string())
+                    .collect::<Vec<_>>(),
+            );
+            self.forget_schema(&pending);
+        }
+        Ok(inserted)
+    }
+
+    // Removes versions of timeseries schema from the cache.
+    fn forget_schema(&self, schema: &[TimeseriesSchema]) {
+        let mut cache = self.schema.lock().unwrap();
+        for schema in schema.iter() {
+            if let Some(versions) = cache.get_mut(&schema.timeseries_name) {
+                versions.retain(|v| v != schema);
+            }
+        }
+        cache.retain(|_, versions| !versions.is_empty());
     }
 
     // Select the timeseries, including keys and field values, that match the given field-selection
@@ -420,14 +913,18 @@ impl Client {
                     concat!(
                         "SELECT * ",
                         "FROM {db_name}.timeseries_schema ",
-                        "WHERE timeseries_name NOT IN ",
+                        "WHERE (timeseries_name, version) NOT IN ",
                         "({current_keys}) ",
                         "FORMAT JSONEachRow;",
                     ),
                     db_name = crate::DATABASE_NAME,
                     current_keys = schema
-                        .keys()
-                        .map(|key| format!("'{}'", key))
+                        .values()
+                        .flatten()
+                        .map(|schema| format!(
+                            "('{}', {})",
+                            schema.timeseries_name, schema.version
+                        ))
                         .collect::<Vec<_>>()
                         .join(", ")
                 )
@@ -438,16 +935,18 @@ impl Client {
             trace!(self.log, "no new timeseries schema in database");
         } else {
             trace!(self.log, "extracting new timeseries schema");
-            let new = body.lines().map(|line| {
+            let mut cache = self.schema.lock().unwrap();
+            for line in body.lines() {
                 let schema = TimeseriesSchema::from(
                     serde_json::from_str::<model::DbTimeseriesSchema>(line)
                         .expect(
                         "Failed to deserialize TimeseriesSchema from database",
                     ),
                 );
-                (schema.timeseries_name.clone(), schema)
-            });
-            self.schema.lock().unwrap().extend(new);
+                let versions =
+                    cache.entry(schema.timeseries_name.clone()).or_default();
+                add_schema_version(versions, schema);
+            }
         }
         Ok(())
     }
@@ -464,6 +963,16 @@ pub trait DbWrite {
     /// Insert the given samples into the database.
     async fn insert_samples(&self, samples: &[Sample]) -> Result<(), Error>;
 
+    /// Apply the given retention policies, replacing any applied previously.
+    ///
+    /// This sets how long the measurements of each timeseries, and their rollups, are kept, and
+    /// maintains the materialized views populating those rollups. The measurements of timeseries
+    /// without a policy are kept for [`retention::DEFAULT_RETENTION_DAYS`], and aren't rolled up.
+    async fn apply_retention_policies(
+        &self,
+        policies: &[RetentionPolicy],
+    ) -> Result<(), Error>;
+
     /// Initialize the replicated telemetry database, creating tables as needed.
     async fn init_replicated_db(&self) -> Result<(), Error>;
 
@@ -486,6 +995,19 @@ impl DbWrite for Client {
         let mut rows = BTreeMap::new();
         let mut new_schema = Vec::new();
 
+        // Make sure we know about any existing versions of the schema for these samples, so that
+        // they're not inserted again as new versions.
+        let uncached = {
+            let cache = self.schema.lock().unwrap();
+            samples.iter().any(|sample| {
+                TimeseriesName::try_from(sample.timeseries_name.as_str())
+                    .map_or(false, |name| !cache.contains_key(&name))
+            })
+        };
+        if uncached {
+            self.get_schema().await?;
+        }
+
         for sample in samples.iter() {
             match self.verify_sample_schema(sample).await {
                 Err(_) => {
@@ -525,32 +1047,13 @@ impl DbWrite for Client {
             seen_timeseries.insert(key);
         }
 
-        // Insert the new schema into the database
-        //
-        // TODO-robustness There's still a race possible here. If two distinct clients receive new
-        // but conflicting schema, they will both try to insert those at some point into the schema
-        // tables. It's not clear how to handle this, since ClickHouse provides no transactions.
-        // This is unlikely to happen at this point, because the design is such that there will be
-        // a single `oximeter` instance, which has one client object, connected to a single
-        // ClickHouse server. But once we start replicating data, the window within which the race
-        // can occur is much larger, since it includes the time it takes ClickHouse to replicate
-        // data between nodes.
+        // Insert the new schema into the database.
         //
-        // NOTE: This is an issue even in the case where the schema don't conflict. Two clients may
-        // receive a sample with a new schema, and both would then try to insert that schema.
-        if !new_schema.is_empty() {
-            debug!(
-                self.log,
-                "inserting {} new timeseries schema",
-                new_schema.len()
-            );
-            let body = format!(
-                "INSERT INTO {db_name}.timeseries_schema FORMAT JSONEachRow\n{row_data}\n",
-                db_name = crate::DATABASE_NAME,
-                row_data = new_schema.join("\n"),
-            );
-            self.execute(body).await?;
-        }
+        // ClickHouse provides no transactions, so another client may record a different schema
+        // with the same version at about the same time. Every client resolves that the same way
+        // when reading the schema back, and the client whose schema isn't kept records it again
+        // as a later version (see `Client::insert_new_schema`).
+        let new_schema = self.insert_new_schema(new_schema).await?;
 
         // Insert the actual target/metric field rows and measurement rows.
         for (table_name, rows) in rows {
@@ -571,11 +1074,187 @@ impl DbWrite for Client {
             );
         }
 
+        // Timeseries recorded with earlier versions of any new schema are migrated to the new
+        // version, so that they're still selected by queries. This only fails if the database
+        // does, in which case the migration can be run again later.
+        for schema in
+            new_schema.iter().filter(|schema| schema.version.get() > 1)
+        {
+            if let Err(e) =
+                self.migrate_timeseries_schema(&schema.timeseries_name).await
+            {
+                warn!(
+                    self.log,
+                    "failed to migrate timeseries to new schema version";
+                    "timeseries_name" => %schema.timeseries_name,
+                    "version" => schema.version.get(),
+                    "error" => ?e,
+                );
+            }
+        }
+
         // TODO-correctness We'd like to return all errors to clients here, and there may be as
         // many as one per sample. It's not clear how to structure this in a way that's useful.
         Ok(())
     }
 
+    /// Apply the given retention policies, replacing any applied previously.
+    async fn apply_retention_policies(
+        &self,
+        policies: &[RetentionPolicy],
+    ) -> Result<(), Error> {
+        retention::validate_policies(policies)?;
+        let mut policies = policies.to_vec();
+        policies.sort_by(|a, b| a.timeseries_name.cmp(&b.timeseries_name));
+        let current = self.retention_policies().await?;
+        if policies == current {
+            debug!(self.log, "retention policies are unchanged");
+            return Ok(());
+        }
+        info!(
+            self.log,
+            "applying retention policies";
+            "n_policies" => policies.len(),
+        );
+        let replicated = self.is_oximeter_cluster().await?;
+
+        // Maintain rollups of the timeseries whose policies ask for them. Existing measurements
+        // of newly rolled-up timeseries are rolled up here, and later ones by the views.
+        //
+        // The views are only replaced if the set of timeseries they roll up has changed. The new
+        // view is created before the old one is dropped, so that every measurement is rolled up
+        // while they're replaced. A measurement inserted in between may be rolled up by both,
+        // which doesn't change the last value of a cumulative counter, and only briefly adds
+        // weight to one sample in the mean of a gauge.
+        let cutoff = Utc::now();
+        let rolled_up = |policies: &[RetentionPolicy],
+                         resolution: RollupResolution| {
+            policies
+                .iter()
+                .filter(|policy| {
+                    policy.rollup_retention_days(resolution).is_some()
+                })
+                .map(|policy| policy.timeseries_name.clone())
+                .collect::<BTreeSet<_>>()
+        };
+        for resolution in RollupResolution::ALL {
+            let names = rolled_up(&policies, resolution);
+            let current_names = rolled_up(&current, resolution);
+            let added = names
+                .difference(&current_names)
+                .cloned()
+                .collect::<BTreeSet<_>>();
+            for datum_type in retention::rollup_datum_types() {
+                for sql in retention::create_rollup_table(
+                    datum_type, resolution, replicated,
+                ) {
+                    self.execute(sql).await?;
+                }
+                if names == current_names {
+                    continue;
+                }
+                let old_views = self
+                    .execute_with_body(retention::select_rollup_views(
+                        datum_type, resolution,
+                    ))
+                    .await?;
+                if !names.is_empty() {
+                    self.execute(retention::create_rollup_view(
+                        datum_type, resolution, &names, cutoff, replicated,
+                    ))
+                    .await?;
+                }
+                for view in old_views.lines() {
+                    self.execute(retention::drop_rollup_view(view, replicated))
+                        .await?;
+                }
+
+                // Existing measurements are only kept so long, and their rollups are expired
+                // after their own retention period, so that bounds how far back we look.
+                let backfill_days = policies
+                    .iter()
+                    .filter(|policy| added.contains(&policy.timeseries_name))
+                    .filter_map(|policy| {
+                        policy
+                            .rollup_retention_days(resolution)
+                            .map(|days| days.min(policy.raw_retention_days))
+                    })
+                    .max()
+                    .unwrap_or(0);
+                for (start, end) in
+                    retention::backfill_windows(cutoff, backfill_days)
+                {
+                    self.execute(retention::backfill_rollup(
+                        datum_type, resolution, &added, start, end,
+                    ))
+                    .await?;
+                }
+            }
+        }
+
+        // Expire measurements and rollups. Rollups of timeseries without a policy asking for
+        // them are removed.
+        let raw_retention_days = policies
+            .iter()
+            .map(|policy| (&policy.timeseries_name, policy.raw_retention_days))
+            .collect::<Vec<_>>();
+        for datum_type in retention::DATUM_TYPES {
+            self.execute(retention::modify_ttl(
+                &query::measurement_table_name(datum_type),
+                &raw_retention_days,
+                retention::DEFAULT_RETENTION_DAYS,
+                replicated,
+            ))
+            .await?;
+        }
+        for resolution in RollupResolution::ALL {
+            let rollup_retention_days = policies
+                .iter()
+                .filter_map(|policy| {
+                    policy
+                        .rollup_retention_days(resolution)
+                        .map(|days| (&policy.timeseries_name, days))
+                })
+                .collect::<Vec<_>>();
+            for datum_type in retention::rollup_datum_types() {
+                self.execute(retention::modify_ttl(
+                    &retention::rollup_table_name(datum_type, resolution),
+                    &rollup_retention_days,
+                    0,
+                    replicated,
+                ))
+                .await?;
+            }
+        }
+
+        // Record the policies, for selecting the resolution of queries.
+        let on_cluster =
+            if replicated { " ON CLUSTER oximeter_cluster" } else { "" };
+        self.execute(format!(
+            "TRUNCATE TABLE {db_name}.timeseries_retention{on_cluster};",
+            db_name = crate::DATABASE_NAME,
+        ))
+        .await?;
+        if !policies.is_empty() {
+            self.execute(format!(
+                "INSERT INTO {db_name}.timeseries_retention FORMAT JSONEachRow\n{row_data}\n",
+                db_name = crate::DATABASE_NAME,
+                row_data = policies
+                    .iter()
+                    .map(|policy| {
+                        serde_json::to_string(&model::DbRetentionPolicy::from(
+                            policy,
+                        ))
+                        .expect("Failed to convert retention policy to DB model")
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ))
+            .await?;
+        }
+        Ok(())
+    }
+
     /// Initialize the replicated telemetry database, creating tables as needed.
     async fn init_replicated_db(&self) -> Result<(), Error> {
         // The HTTP client doesn't support multiple statements per query, so we break them out here
@@ -632,6 +1311,36 @@ async fn handle_db_response(
     }
 }
 
+// Adds a version of a timeseries schema read from the database to `versions`, which are kept in
+// order of version.
+//
+// Clients number a new version by adding one to the latest version they know of, so two clients
+// may record different schema with the same version at about the same time. Every client keeps
+// the same one of those: the one created first, or, if they were created at the same time, the
+// one whose datum type and fields sort first.
+fn add_schema_version(
+    versions: &mut Vec<TimeseriesSchema>,
+    schema: TimeseriesSchema,
+) {
+    fn precedence(
+        schema: &TimeseriesSchema,
+    ) -> (DateTime<Utc>, DatumType, BTreeSet<&FieldSchema>) {
+        (
+            schema.created,
+            schema.datum_type,
+            schema.field_schema.iter().collect(),
+        )
+    }
+    match versions.binary_search_by_key(&schema.version, |v| v.version) {
+        Ok(index) => {
+            if precedence(&schema) < precedence(&versions[index]) {
+                versions[index] = schema;
+            }
+        }
+        Err(index) => versions.insert(index, schema),
+    }
+}
+
 // Generate an error describing a schema mismatch
 fn error_for_schema_mismatch(
     schema: &TimeseriesSchema,
@@ -660,6 +1369,7 @@ mod tests {
     use crate::query;
     use crate::query::field_table_name;
     use crate::query::measurement_table_name;
+    use chrono::TimeZone;
     use chrono::Utc;
     use omicron_test_utils::dev::clickhouse::ClickHouseInstance;
     use omicron_test_utils::dev::test_setup_log;
@@ -1306,8 +2016,9 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_schema_mismatch() {
-        let log = slog::Logger::root(slog::Discard, o!());
+    async fn test_schema_versions() {
+        let logctx = test_setup_log("test_schema_versions");
+        let log = &logctx.log;
 
         // Let the OS assign a port and discover it after ClickHouse starts
         let mut db = ClickHouseInstance::new_single_node(0)
@@ -1315,28 +2026,174 @@ mod tests {
             .expect("Failed to start ClickHouse");
         let address = SocketAddr::new("::1".parse().unwrap(), db.port());
 
-        let client = Client::new(address, &log);
+        let client = Client::new(address, log);
         client
             .init_single_node_db()
             .await
             .expect("Failed to initialize timeseries database");
         let sample = test_util::make_sample();
+        let timeseries_name =
+            TimeseriesName::try_from(sample.timeseries_name.as_str()).unwrap();
         client.insert_samples(&[sample]).await.unwrap();
 
-        let bad_name = name_mismatch::TestTarget {
+        // A sample whose target has a renamed field is inserted with a new version of the schema,
+        // rather than being skipped.
+        let metric = test_util::TestMetric {
+            id: uuid::Uuid::new_v4(),
+            good: true,
+            datum: 1,
+        };
+        let renamed = name_mismatch::TestTarget {
             name: "first_name".into(),
             name2: "second_name".into(),
             num: 2,
         };
+        let sample = Sample::new(&renamed, &metric).unwrap();
+        client.insert_samples(&[sample]).await.unwrap();
+        let versions =
+            client.timeseries_schema_versions(&timeseries_name).await.unwrap();
+        assert_eq!(
+            versions.iter().map(|v| v.version.get()).collect::<Vec<_>>(),
+            [1, 2]
+        );
+        assert!(versions[1].field_schema("name").is_some());
+        assert!(versions[1].can_migrate_from(&versions[0]));
+
+        // Samples matching an earlier version don't create another one.
+        let result = client
+            .verify_sample_schema(&test_util::make_sample())
+            .await
+            .unwrap();
+        assert!(result.is_none());
+
+        // The timeseries recorded with the first version was migrated to the second, with the
+        // default value for the added field.
+        let select = |criteria: &'static [&'static str]| {
+            client.select_timeseries_with(
+                &timeseries_name,
+                criteria,
+                None,
+                None,
+                None,
+                None,
+            )
+        };
+        assert_eq!(select(&[]).await.unwrap().len(), 2);
+        assert_eq!(select(&["name==first_name"]).await.unwrap().len(), 1);
+        assert_eq!(select(&["num==0"]).await.unwrap().len(), 1);
+
+        // Changing the type of a field also creates a new version, but timeseries that have the
+        // field with its old type can't be migrated to it.
+        let retyped = type_mismatch::TestTarget {
+            name1: uuid::Uuid::new_v4(),
+            name2: "second_name".into(),
+            num: 3,
+        };
+        let sample = Sample::new(&retyped, &metric).unwrap();
+        client.insert_samples(&[sample]).await.unwrap();
+        let latest = client
+            .schema_for_timeseries(&timeseries_name)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(latest.version.get(), 3);
+        let timeseries = select(&[]).await.unwrap();
+        let mut nums = timeseries
+            .iter()
+            .map(|ts| {
+                ts.target
+                    .fields
+                    .iter()
+                    .find(|field| field.name == "num")
+                    .unwrap()
+                    .value
+                    .clone()
+            })
+            .collect::<Vec<_>>();
+        nums.sort_by_key(|num| num.to_string());
+        assert_eq!(nums, [FieldValue::I64(2), FieldValue::I64(3)]);
+
+        // Migrating again has no effect.
+        let migrated =
+            client.migrate_timeseries_schema(&timeseries_name).await.unwrap();
+        assert_eq!(migrated, latest);
+        assert_eq!(select(&[]).await.unwrap().len(), 2);
+
+        db.cleanup().await.expect("Failed to cleanup ClickHouse server");
+        logctx.cleanup_successful();
+    }
+
+    #[tokio::test]
+    async fn test_schema_version_conflict() {
+        let logctx = test_setup_log("test_schema_version_conflict");
+        let log = &logctx.log;
+
+        // Let the OS assign a port and discover it after ClickHouse starts
+        let mut db = ClickHouseInstance::new_single_node(0)
+            .await
+            .expect("Failed to start ClickHouse");
+        let address = SocketAddr::new("::1".parse().unwrap(), db.port());
+
+        let client = Client::new(address, log);
+        client
+            .init_single_node_db()
+            .await
+            .expect("Failed to initialize timeseries database");
+        let sample = test_util::make_sample();
+        let timeseries_name =
+            TimeseriesName::try_from(sample.timeseries_name.as_str()).unwrap();
+        client.insert_samples(&[sample]).await.unwrap();
+
+        // Another client learns about the first version of the schema.
+        let other = Client::new(address, log);
+        other.get_schema().await.unwrap();
+
+        // Each client then sees a different new schema for the timeseries, and numbers it as the
+        // second version, as they would if they saw them at the same time.
         let metric = test_util::TestMetric {
             id: uuid::Uuid::new_v4(),
             good: true,
             datum: 1,
         };
-        let sample = Sample::new(&bad_name, &metric).unwrap();
-        let result = client.verify_sample_schema(&sample).await;
-        assert!(matches!(result, Err(Error::SchemaMismatch { .. })));
+        let renamed = name_mismatch::TestTarget {
+            name: "first_name".into(),
+            name2: "second_name".into(),
+            num: 2,
+        };
+        let retyped = type_mismatch::TestTarget {
+            name1: uuid::Uuid::new_v4(),
+            name2: "second_name".into(),
+            num: 3,
+        };
+        client
+            .insert_samples(&[Sample::new(&renamed, &metric).unwrap()])
+            .await
+            .unwrap();
+        other
+            .insert_samples(&[Sample::new(&retyped, &metric).unwrap()])
+            .await
+            .unwrap();
+
+        // The schema created first keeps the second version, and the other is recorded as the
+        // third. Both clients agree.
+        for client in [&client, &other] {
+            let versions = client
+                .timeseries_schema_versions(&timeseries_name)
+                .await
+                .unwrap();
+            assert_eq!(
+                versions.iter().map(|v| v.version.get()).collect::<Vec<_>>(),
+                [1, 2, 3]
+            );
+            assert!(versions[1].field_schema("name").is_some());
+            assert_eq!(
+                versions[2].field_schema("name1").unwrap().ty,
+                oximeter::types::FieldType::Uuid
+            );
+        }
+
         db.cleanup().await.expect("Failed to cleanup ClickHouse server");
+        logctx.cleanup_successful();
     }
 
     #[tokio::test]
@@ -1379,6 +2236,7 @@ mod tests {
             .lock()
             .unwrap()
             .get(&timeseries_name)
+            .and_then(|versions| versions.last())
             .expect(
                 "After inserting a new sample, its schema should be included",
             )
@@ -1523,6 +2381,319 @@ mod tests {
         db.cleanup().await.expect("Failed to cleanup ClickHouse server");
     }
 
+    #[tokio::test]
+    async fn test_client_select_aggregated_timeseries() {
+        #[derive(oximeter::Target)]
+        struct Service {
+            name: String,
+            instance: i64,
+        }
+
+        #[derive(oximeter::Metric)]
+        struct Load {
+            datum: f64,
+        }
+
+        #[derive(oximeter::Metric)]
+        struct Requests {
+            datum: oximeter::types::Cumulative<i64>,
+        }
+
+        let logctx = test_setup_log("test_client_select_aggregated_timeseries");
+        let log = &logctx.log;
+        let mut db = ClickHouseInstance::new_single_node(0)
+            .await
+            .expect("Failed to start ClickHouse");
+        let address = SocketAddr::new("::1".parse().unwrap(), db.port());
+        let client = Client::new(address, log);
+        client
+            .init_single_node_db()
+            .await
+            .expect("Failed to initialize timeseries database");
+
+        // Samples every 5 seconds, starting at the beginning of a 10-second window.
+        let start = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        let targets = [("a", 0), ("a", 1), ("b", 0)]
+            .into_iter()
+            .map(|(name, instance)| Service {
+                name: String::from(name),
+                instance,
+            })
+            .collect::<Vec<_>>();
+        let loads = [[1.0, 3.0, 5.0, 7.0], [10.0; 4], [100.0; 4]];
+        let mut samples = Vec::new();
+        for (target, loads) in targets.iter().zip(loads) {
+            for (i, load) in loads.into_iter().enumerate() {
+                let timestamp = start + chrono::Duration::seconds(5 * i as i64);
+                samples.push(
+                    Sample::new_with_timestamp(
+                        timestamp,
+                        target,
+                        &Load { datum: load },
+                    )
+                    .unwrap(),
+                );
+                samples.push(
+                    Sample::new_with_timestamp(
+                        timestamp,
+                        target,
+                        &Requests {
+                            datum: oximeter::types::Cumulative::with_start_time(
+                                start - chrono::Duration::seconds(1),
+                                [0, 10, 30, 60][i],
+                            ),
+                        },
+                    )
+                    .unwrap(),
+                );
+            }
+        }
+        client.insert_samples(&samples).await.unwrap();
+
+        let window = |i: i64| start + chrono::Duration::seconds(10 * i);
+        let values = |timeseries: &AggregatedTimeseries| {
+            timeseries
+                .measurements
+                .iter()
+                .map(|m| match m.datum() {
+                    Datum::F64(value) => (m.timestamp(), *value),
+                    datum => panic!("Expected an f64 gauge, found {datum:?}"),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // The mean load, grouped by service name.
+        let results = client
+            .select_aggregated_timeseries_with(
+                "service:load",
+                &[],
+                None,
+                None,
+                None,
+                None,
+                query::Aggregation {
+                    op: query::AggregationOp::Mean,
+                    interval: Duration::from_secs(10),
+                    group_by: vec![String::from("name")],
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 2);
+        let group = |name: &str| {
+            results
+                .iter()
+                .find(|ts| ts.fields[0].value == FieldValue::from(name))
+                .unwrap()
+        };
+        assert_eq!(group("a").fields.len(), 1);
+        assert_eq!(values(group("a")), [(window(0), 6.0), (window(1), 8.0)]);
+        assert_eq!(
+            values(group("b")),
+            [(window(0), 100.0), (window(1), 100.0)]
+        );
+
+        // The request rate of a single service instance. The first sample is the baseline, so
+        // the first window covers the 10 requests after it.
+        let results = client
+            .select_aggregated_timeseries_with(
+                "service:requests",
+                &["name==b"],
+                None,
+                None,
+                Some(NonZeroU32::new(10).unwrap()),
+                None,
+                query::Aggregation {
+                    op: query::AggregationOp::Rate,
+                    interval: Duration::from_secs(10),
+                    group_by: vec![],
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].fields.len(), 2);
+        assert_eq!(values(&results[0]), [(window(0), 1.0), (window(1), 5.0)]);
+
+        // Rates only apply to cumulative timeseries.
+        let err = client
+            .select_aggregated_timeseries_with(
+                "service:load",
+                &[],
+                None,
+                None,
+                None,
+                None,
+                query::Aggregation {
+                    op: query::AggregationOp::Rate,
+                    interval: Duration::from_secs(10),
+                    group_by: vec![],
+                },
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::AggregationNotSupported { .. }));
+
+        db.cleanup().await.expect("Failed to cleanup ClickHouse server");
+        logctx.cleanup_successful();
+    }
+
+    #[tokio::test]
+    async fn test_retention_policies() {
+        #[derive(oximeter::Target)]
+        struct Service {
+            name: String,
+        }
+
+        #[derive(oximeter::Metric)]
+        struct Load {
+            datum: f64,
+        }
+
+        let logctx = test_setup_log("test_retention_policies");
+        let log = &logctx.log;
+        let mut db = ClickHouseInstance::new_single_node(0)
+            .await
+            .expect("Failed to start ClickHouse");
+        let address = SocketAddr::new("::1".parse().unwrap(), db.port());
+        let client = Client::new(address, log);
+        client
+            .init_single_node_db()
+            .await
+            .expect("Failed to initialize timeseries database");
+
+        let name = TimeseriesName::try_from("service:load").unwrap();
+        let policy = RetentionPolicy {
+            timeseries_name: name.clone(),
+            raw_retention_days: 1,
+            hourly_retention_days: Some(30),
+            daily_retention_days: None,
+        };
+        let err = client
+            .apply_retention_policies(&[policy.clone(), policy.clone()])
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidRetentionPolicy(_)));
+        client.apply_retention_policies(&[policy.clone()]).await.unwrap();
+        assert_eq!(
+            client.retention_policies().await.unwrap(),
+            [policy.clone()]
+        );
+
+        // Applying the same policies again leaves them as they are.
+        client.apply_retention_policies(&[policy.clone()]).await.unwrap();
+        assert_eq!(
+            client.retention_policy(&name).await.unwrap(),
+            Some(policy.clone())
+        );
+
+        // Samples every 10 minutes for two hours, starting two days ago. These have expired at
+        // full resolution, but are kept in the hourly rollups.
+        let now = Utc::now();
+        let start = Utc
+            .timestamp_opt(
+                (now - chrono::Duration::days(2)).timestamp() / 3600 * 3600,
+                0,
+            )
+            .unwrap();
+        let target = Service { name: String::from("a") };
+        let mut samples = (0..12)
+            .map(|i| {
+                Sample::new_with_timestamp(
+                    start + chrono::Duration::minutes(10 * i),
+                    &target,
+                    &Load { datum: i as f64 },
+                )
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+        samples.push(
+            Sample::new_with_timestamp(now, &target, &Load { datum: 100.0 })
+                .unwrap(),
+        );
+        client.insert_samples(&samples).await.unwrap();
+
+        let client = &client;
+        let select = |start| async move {
+            let timeseries = client
+                .select_timeseries_with(
+                    "service:load",
+                    &[],
+                    Some(query::Timestamp::Inclusive(start)),
+                    None,
+                    None,
+                    None,
+                )
+                .await
+                .unwrap();
+            assert_eq!(timeseries.len(), 1);
+            timeseries[0]
+                .measurements
+                .iter()
+                .map(|m| (m.timestamp(), m.datum().clone()))
+                .collect::<Vec<_>>()
+        };
+
+        // Selecting measurements from two days ago uses the rollups, with the mean of each hour.
+        assert_eq!(
+            select(start).await,
+            [
+                (start, Datum::F64(2.5)),
+                (start + chrono::Duration::hours(1), Datum::F64(8.5)),
+            ]
+        );
+
+        // Recent measurements are selected at full resolution.
+        assert_eq!(
+            select(now - chrono::Duration::hours(1)).await,
+            [(now, Datum::F64(100.0))]
+        );
+
+        // Rolling up another timeseries replaces the view, which keeps rolling up the first.
+        let rollup_views = || async move {
+            client
+                .execute_with_body(retention::select_rollup_views(
+                    DatumType::F64,
+                    RollupResolution::Hourly,
+                ))
+                .await
+                .unwrap()
+                .lines()
+                .map(String::from)
+                .collect::<Vec<_>>()
+        };
+        let views = rollup_views().await;
+        assert_eq!(views.len(), 1);
+        let other = RetentionPolicy {
+            timeseries_name: TimeseriesName::try_from("service:other").unwrap(),
+            ..policy.clone()
+        };
+        client.apply_retention_policies(&[policy, other]).await.unwrap();
+        let replaced = rollup_views().await;
+        assert_eq!(replaced.len(), 1);
+        assert_ne!(replaced, views);
+        let later = start + chrono::Duration::hours(2);
+        client
+            .insert_samples(&[Sample::new_with_timestamp(
+                later,
+                &target,
+                &Load { datum: 4.0 },
+            )
+            .unwrap()])
+            .await
+            .unwrap();
+        assert!(select(start).await.contains(&(later, Datum::F64(4.0))));
+
+        // Policies can also be removed, along with the views.
+        client.apply_retention_policies(&[]).await.unwrap();
+        assert!(client.retention_policies().await.unwrap().is_empty());
+        assert_eq!(client.retention_policy(&name).await.unwrap(), None);
+        assert!(rollup_views().await.is_empty());
+
+        db.cleanup().await.expect("Failed to cleanup ClickHouse server");
+        logctx.cleanup_successful();
+    }
+
     #[tokio::test]
     async fn test_field_record_count() {
         // This test verifies that the number of records in the field tables is as expected.
@@ -621,6 +621,7 @@ ORDER BY (timeseries_name, field_name, field_value, timeseries_key);
 CREATE TABLE IF NOT EXISTS oximeter.timeseries_schema ON CLUSTER oximeter_cluster
 (
     timeseries_name String,
+    version UInt8 DEFAULT 1,
     fields Nested(
         name String,
         type Enum(
@@ -650,3 +651,15 @@ CREATE TABLE IF NOT EXISTS oximeter.timeseries_schema ON CLUSTER oximeter_cluste
 )
 ENGINE = ReplicatedMergeTree()
 ORDER BY (timeseries_name, fields.name);
+--
+ALTER TABLE oximeter.timeseries_schema ON CLUSTER oximeter_cluster ADD COLUMN IF NOT EXISTS version UInt8 DEFAULT 1 AFTER timeseries_name;
+--
+CREATE TABLE IF NOT EXISTS oximeter.timeseries_retention ON CLUSTER oximeter_cluster
+(
+    timeseries_name String,
+    raw_retention_days UInt32,
+    hourly_retention_days UInt32,
+    daily_retention_days UInt32
+)
+ENGINE = ReplicatedMergeTree()
+ORDER BY timeseries_name;
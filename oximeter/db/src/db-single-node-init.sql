@@ -445,6 +445,7 @@ ORDER BY (timeseries_name, field_name, field_value, timeseries_key);
 CREATE TABLE IF NOT EXISTS oximeter.timeseries_schema
 (
     timeseries_name String,
+    version UInt8 DEFAULT 1,
     fields Nested(
         name String,
         type Enum(
@@ -474,3 +475,15 @@ CREATE TABLE IF NOT EXISTS oximeter.timeseries_schema
 )
 ENGINE = MergeTree()
 ORDER BY (timeseries_name, fields.name);
+--
+ALTER TABLE oximeter.timeseries_schema ADD COLUMN IF NOT EXISTS version UInt8 DEFAULT 1 AFTER timeseries_name;
+--
+CREATE TABLE IF NOT EXISTS oximeter.timeseries_retention
+(
+    timeseries_name String,
+    raw_retention_days UInt32,
+    hourly_retention_days UInt32,
+    daily_retention_days UInt32
+)
+ENGINE = MergeTree()
+ORDER BY timeseries_name;
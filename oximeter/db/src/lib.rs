@@ -9,18 +9,27 @@
 use crate::query::StringFieldSelector;
 use chrono::{DateTime, Utc};
 use dropshot::{EmptyScanParams, PaginationParams};
+use oximeter::FieldValue;
 pub use oximeter::{DatumType, Field, FieldType, Measurement, Sample};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::convert::TryFrom;
+use std::net::IpAddr;
+use std::net::Ipv6Addr;
 use std::num::NonZeroU32;
+use std::num::NonZeroU8;
 use thiserror::Error;
+use uuid::Uuid;
 
 mod client;
 pub mod model;
 pub mod query;
+pub mod retention;
 pub use client::{Client, DbWrite};
+pub use query::{Aggregation, AggregationOp};
+pub use retention::{RetentionPolicy, RollupResolution};
 
 #[derive(Clone, Debug, Error)]
 pub enum Error {
@@ -35,7 +44,8 @@ pub enum Error {
     #[error("Error interacting with telemetry database: {0}")]
     Database(String),
 
-    /// A schema provided when collecting samples did not match the expected schema
+    /// A schema provided when collecting samples could not be added as a new version of the
+    /// timeseries schema
     #[error("Schema mismatch for timeseries '{name}', expected fields {expected:?} found fields {actual:?}")]
     SchemaMismatch {
         name: String,
@@ -82,6 +92,21 @@ pub enum Error {
 
     #[error("Query must resolve to a single timeseries if limit is specified")]
     InvalidLimitQuery,
+
+    #[error("The aggregation '{op}' is not valid for timeseries with datum type {datum_type}")]
+    AggregationNotSupported { op: String, datum_type: DatumType },
+
+    #[error("Invalid aggregation: {0}")]
+    InvalidAggregation(String),
+
+    #[error("Cannot group timeseries '{timeseries_name}' by field '{field_name}', which is not a target field")]
+    InvalidGroupByField { timeseries_name: String, field_name: String },
+
+    #[error("Invalid retention policy: {0}")]
+    InvalidRetentionPolicy(String),
+
+    #[error("Measurements with datum type {0} cannot be rolled up")]
+    RollupNotSupported(DatumType),
 }
 
 /// A timeseries name.
@@ -91,7 +116,7 @@ pub enum Error {
 #[derive(
     Debug, Clone, PartialEq, PartialOrd, Ord, Eq, Hash, Serialize, Deserialize,
 )]
-#[serde(try_from = "&str")]
+#[serde(try_from = "String")]
 pub struct TimeseriesName(String);
 
 impl JsonSchema for TimeseriesName {
@@ -174,15 +199,48 @@ fn validate_timeseries_name(s: &str) -> Result<&str, Error> {
 ///
 /// This includes the name of the timeseries, as well as the datum type of its metric and the
 /// schema for each field.
+///
+/// A timeseries may have several versions of its schema, numbered from 1. A new version is
+/// created whenever samples arrive whose fields or datum type differ from every existing version,
+/// for example because the producer's target or metric type was changed.
 #[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
 pub struct TimeseriesSchema {
     pub timeseries_name: TimeseriesName,
+    #[serde(default = "first_schema_version")]
+    pub version: NonZeroU8,
     pub field_schema: Vec<FieldSchema>,
     pub datum_type: DatumType,
     pub created: DateTime<Utc>,
 }
 
+fn first_schema_version() -> NonZeroU8 {
+    NonZeroU8::new(1).unwrap()
+}
+
 impl TimeseriesSchema {
+    /// Return `true` if this schema has the same fields and datum type as `other`, regardless of
+    /// version or the order of the fields.
+    pub fn same_fields_and_type(&self, other: &TimeseriesSchema) -> bool {
+        self.datum_type == other.datum_type
+            && self.field_schema.iter().collect::<BTreeSet<_>>()
+                == other.field_schema.iter().collect::<BTreeSet<_>>()
+    }
+
+    /// Return `true` if timeseries recorded with the `older` schema can be migrated to this one.
+    ///
+    /// That's the case if the datum types are the same, and any field in both schema has the same
+    /// type and source in each. Fields that are only in this schema are given their default
+    /// values (see [`FieldSchema::default_value`]) when migrating, and fields that are only in
+    /// the older schema are ignored.
+    pub fn can_migrate_from(&self, older: &TimeseriesSchema) -> bool {
+        self.datum_type == older.datum_type
+            && self.field_schema.iter().all(|field| {
+                older.field_schema(&field.name).map_or(true, |old| {
+                    old.ty == field.ty && old.source == field.source
+                })
+            })
+    }
+
     /// Return the schema for the given field.
     pub fn field_schema<S>(&self, name: S) -> Option<&FieldSchema>
     where
@@ -202,6 +260,7 @@ impl TimeseriesSchema {
 impl PartialEq for TimeseriesSchema {
     fn eq(&self, other: &TimeseriesSchema) -> bool {
         self.timeseries_name == other.timeseries_name
+            && self.version == other.version
             && self.datum_type == other.datum_type
             && self.field_schema == other.field_schema
     }
@@ -214,6 +273,7 @@ impl From<model::DbTimeseriesSchema> for TimeseriesSchema {
                 schema.timeseries_name.as_str(),
             )
             .expect("Invalid timeseries name in database"),
+            version: schema.version,
             field_schema: schema.field_schema.into(),
             datum_type: schema.datum_type.into(),
             created: schema.created,
@@ -245,6 +305,19 @@ pub struct Timeseries {
     pub measurements: Vec<Measurement>,
 }
 
+/// Measurements aggregated from one or more timeseries.
+///
+/// Each measurement is a gauge holding the aggregated value for one window of time, timestamped
+/// with the start of that window.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AggregatedTimeseries {
+    pub timeseries_name: String,
+    /// The fields identifying the aggregated timeseries. These are the fields that the timeseries
+    /// were grouped by or, without grouping, all the fields of the single timeseries.
+    pub fields: Vec<Field>,
+    pub measurements: Vec<Measurement>,
+}
+
 /// The source from which a field is derived, the target or metric.
 #[derive(
     Clone,
@@ -307,6 +380,30 @@ pub struct FieldSchema {
     pub source: FieldSource,
 }
 
+impl FieldSchema {
+    /// Return the value of this field for timeseries recorded before it was added to the schema.
+    ///
+    /// This is the zero or empty value of the field's type.
+    pub fn default_value(&self) -> FieldValue {
+        match self.ty {
+            FieldType::String => FieldValue::String(String::new()),
+            FieldType::I8 => FieldValue::I8(0),
+            FieldType::U8 => FieldValue::U8(0),
+            FieldType::I16 => FieldValue::I16(0),
+            FieldType::U16 => FieldValue::U16(0),
+            FieldType::I32 => FieldValue::I32(0),
+            FieldType::U32 => FieldValue::U32(0),
+            FieldType::I64 => FieldValue::I64(0),
+            FieldType::U64 => FieldValue::U64(0),
+            FieldType::IpAddr => {
+                FieldValue::IpAddr(IpAddr::V6(Ipv6Addr::UNSPECIFIED))
+            }
+            FieldType::Uuid => FieldValue::Uuid(Uuid::nil()),
+            FieldType::Bool => FieldValue::Bool(false),
+        }
+    }
+}
+
 /// Type used to paginate request to list timeseries schema.
 pub type TimeseriesSchemaPaginationParams =
     PaginationParams<EmptyScanParams, TimeseriesName>;
@@ -370,8 +467,7 @@ const TIMESERIES_NAME_REGEX: &str =
 
 #[cfg(test)]
 mod tests {
-    use super::TimeseriesName;
-    use std::convert::TryFrom;
+    use super::*;
 
     #[test]
     fn test_timeseries_name() {
@@ -393,4 +489,65 @@ mod tests {
         assert!(TimeseriesName::try_from("a:").is_err());
         assert!(TimeseriesName::try_from("123").is_err());
     }
+
+    #[test]
+    fn test_timeseries_schema_versions() {
+        let field = |name: &str, ty| FieldSchema {
+            name: name.to_string(),
+            ty,
+            source: FieldSource::Target,
+        };
+        let v1 = TimeseriesSchema {
+            timeseries_name: TimeseriesName::try_from("foo:bar").unwrap(),
+            version: NonZeroU8::new(1).unwrap(),
+            field_schema: vec![
+                field("a", FieldType::I64),
+                field("b", FieldType::String),
+            ],
+            datum_type: DatumType::I64,
+            created: Utc::now(),
+        };
+
+        // Reordering fields doesn't change the schema.
+        let reordered = TimeseriesSchema {
+            field_schema: v1.field_schema.iter().rev().cloned().collect(),
+            ..v1.clone()
+        };
+        assert!(reordered.same_fields_and_type(&v1));
+
+        // Adding or removing fields can be migrated...
+        let added = TimeseriesSchema {
+            version: NonZeroU8::new(2).unwrap(),
+            field_schema: vec![
+                field("a", FieldType::I64),
+                field("b", FieldType::String),
+                field("c", FieldType::Uuid),
+            ],
+            ..v1.clone()
+        };
+        assert!(!added.same_fields_and_type(&v1));
+        assert!(added.can_migrate_from(&v1));
+        assert_eq!(
+            added.field_schema("c").unwrap().default_value(),
+            FieldValue::Uuid(Uuid::nil())
+        );
+        let removed = TimeseriesSchema {
+            field_schema: vec![field("a", FieldType::I64)],
+            ..added.clone()
+        };
+        assert!(removed.can_migrate_from(&v1));
+
+        // ... but changing the type of a field or the datum can't.
+        let retyped = TimeseriesSchema {
+            field_schema: vec![
+                field("a", FieldType::U64),
+                field("b", FieldType::String),
+            ],
+            ..added.clone()
+        };
+        assert!(!retyped.can_migrate_from(&v1));
+        let cumulative =
+            TimeseriesSchema { datum_type: DatumType::CumulativeI64, ..added };
+        assert!(!cumulative.can_migrate_from(&v1));
+    }
 }
@@ -10,6 +10,7 @@ use crate::DbFieldSource;
 use crate::FieldSchema;
 use crate::FieldSource;
 use crate::Metric;
+use crate::RetentionPolicy;
 use crate::Target;
 use crate::TimeseriesKey;
 use crate::TimeseriesName;
@@ -33,6 +34,7 @@ use std::collections::BTreeMap;
 use std::convert::TryFrom;
 use std::net::IpAddr;
 use std::net::Ipv6Addr;
+use std::num::NonZeroU8;
 use uuid::Uuid;
 
 // Wrapper type to represent a boolean in the database.
@@ -132,6 +134,7 @@ impl From<Vec<FieldSchema>> for DbFieldList {
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub(crate) struct DbTimeseriesSchema {
     pub timeseries_name: String,
+    pub version: NonZeroU8,
     #[serde(flatten)]
     pub field_schema: DbFieldList,
     pub datum_type: DbDatumType,
@@ -143,6 +146,7 @@ impl From<TimeseriesSchema> for DbTimeseriesSchema {
     fn from(schema: TimeseriesSchema) -> DbTimeseriesSchema {
         DbTimeseriesSchema {
             timeseries_name: schema.timeseries_name.to_string(),
+            version: schema.version,
             field_schema: schema.field_schema.into(),
             datum_type: schema.datum_type.into(),
             created: schema.created,
@@ -150,6 +154,27 @@ impl From<TimeseriesSchema> for DbTimeseriesSchema {
     }
 }
 
+// The `DbRetentionPolicy` type models the `oximeter.timeseries_retention` table. Rollups that
+// aren't maintained have a retention of zero days.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct DbRetentionPolicy {
+    pub timeseries_name: String,
+    pub raw_retention_days: u32,
+    pub hourly_retention_days: u32,
+    pub daily_retention_days: u32,
+}
+
+impl From<&RetentionPolicy> for DbRetentionPolicy {
+    fn from(policy: &RetentionPolicy) -> DbRetentionPolicy {
+        DbRetentionPolicy {
+            timeseries_name: policy.timeseries_name.to_string(),
+            raw_retention_days: policy.raw_retention_days,
+            hourly_retention_days: policy.hourly_retention_days.unwrap_or(0),
+            daily_retention_days: policy.daily_retention_days.unwrap_or(0),
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
 pub enum DbFieldType {
     String,
@@ -905,6 +930,9 @@ pub(crate) fn unroll_measurement_row(sample: &Sample) -> (String, String) {
 }
 
 /// Return the schema for a `Sample`.
+///
+/// The schema is the first version for its timeseries. Its actual version is determined when it's
+/// compared with those in the database.
 pub(crate) fn schema_for(sample: &Sample) -> TimeseriesSchema {
     let created = Utc::now();
     let field_schema = sample
@@ -925,6 +953,7 @@ pub(crate) fn schema_for(sample: &Sample) -> TimeseriesSchema {
             sample.timeseries_name.as_str(),
         )
         .expect("Failed to parse timeseries name"),
+        version: NonZeroU8::new(1).unwrap(),
         field_schema,
         datum_type: sample.measurement.datum_type(),
         created,
@@ -959,6 +988,7 @@ where
             target, metric,
         ))
         .expect("Failed to parse timeseries name"),
+        version: NonZeroU8::new(1).unwrap(),
         field_schema,
         datum_type: metric.datum_type(),
         created: Utc::now(),
@@ -986,6 +1016,17 @@ struct DbTimeseriesScalarCumulativeSample<T> {
     datum: T,
 }
 
+// A value aggregated from the measurements in one window of time, as extracted from a query to the
+// database.
+#[derive(Debug, Clone, Deserialize)]
+struct DbAggregatedSample {
+    group_id: u64,
+    #[serde(with = "serde_timestamp")]
+    window_start: DateTime<Utc>,
+    // Aggregations that aren't finite (e.g., the mean of infinite values) are reported as `null`.
+    value: Option<f64>,
+}
+
 // A histogram timestamped sample from a timeseries, as extracted from a query to the database.
 #[derive(Debug, Clone, Deserialize)]
 struct DbTimeseriesHistogramSample<T> {
@@ -1076,8 +1117,20 @@ where
     (sample.timeseries_key, sample.into())
 }
 
+// Parse a row selected by an aggregated measurement query into the group it belongs to and the
+// aggregated measurement, if the aggregated value is finite.
+pub(crate) fn parse_aggregated_measurement_from_row(
+    line: &str,
+) -> (u64, Option<Measurement>) {
+    let sample = serde_json::from_str::<DbAggregatedSample>(line).unwrap();
+    let measurement =
+        sample.value.map(|value| Measurement::new(sample.window_start, value));
+    (sample.group_id, measurement)
+}
+
 // Parse a line of JSON from the database resulting from `as_select_query`, into a measurement of
 // the expected type. Also returns the timeseries key from the line.
+
 pub(crate) fn parse_measurement_from_row(
     line: &str,
     datum_type: DatumType,
@@ -5,9 +5,10 @@
 //! Functions for querying the timeseries database.
 // Copyright 2021 Oxide Computer Company
 
+use crate::retention;
 use crate::{
-    Error, FieldSchema, FieldSource, TimeseriesKey, TimeseriesSchema,
-    DATABASE_NAME, DATABASE_SELECT_FORMAT,
+    Error, FieldSchema, FieldSource, RollupResolution, TimeseriesKey,
+    TimeseriesSchema, DATABASE_NAME, DATABASE_SELECT_FORMAT,
 };
 use chrono::{DateTime, Utc};
 use dropshot::PaginationOrder;
@@ -21,6 +22,7 @@ use std::fmt;
 use std::net::IpAddr;
 use std::num::NonZeroU32;
 use std::str::FromStr;
+use std::time::Duration;
 use uuid::Uuid;
 
 /// The `SelectQueryBuilder` is used to build queries that select timeseries by their names, field
@@ -39,6 +41,8 @@ pub struct SelectQueryBuilder {
     limit: Option<NonZeroU32>,
     offset: Option<u32>,
     order: Option<PaginationOrder>,
+    aggregation: Option<Aggregation>,
+    rollup: Option<RollupResolution>,
 }
 
 impl SelectQueryBuilder {
@@ -51,6 +55,8 @@ impl SelectQueryBuilder {
             limit: None,
             offset: None,
             order: None,
+            aggregation: None,
+            rollup: None,
         }
     }
 
@@ -84,6 +90,74 @@ impl SelectQueryBuilder {
         self
     }
 
+    /// Aggregate the selected measurements, rather than returning them as-is.
+    ///
+    /// An error is returned if the aggregation operation isn't valid for the datum type of the
+    /// timeseries, the window is shorter than a second, or any of the fields to group by is not a
+    /// target field of the timeseries.
+    pub fn aggregate(
+        mut self,
+        aggregation: Aggregation,
+    ) -> Result<Self, Error> {
+        let datum_type = self.timeseries_schema.datum_type;
+        if !aggregation.op.valid_for_type(datum_type) {
+            return Err(Error::AggregationNotSupported {
+                op: aggregation.op.to_string(),
+                datum_type,
+            });
+        }
+        if let AggregationOp::Quantile(q) = aggregation.op {
+            if !(0.0..=1.0).contains(&q) {
+                return Err(Error::InvalidAggregation(format!(
+                    "quantile must be between 0 and 1, found {q}"
+                )));
+            }
+        }
+        if aggregation.interval.as_secs() == 0 {
+            return Err(Error::InvalidAggregation(String::from(
+                "aggregation windows must be at least one second",
+            )));
+        }
+        for field_name in aggregation.group_by.iter() {
+            let timeseries_name =
+                self.timeseries_schema.timeseries_name.to_string();
+            match self.timeseries_schema.field_schema(field_name) {
+                None => {
+                    return Err(Error::NoSuchField {
+                        timeseries_name,
+                        field_name: field_name.clone(),
+                    })
+                }
+                Some(field) if field.source != FieldSource::Target => {
+                    return Err(Error::InvalidGroupByField {
+                        timeseries_name,
+                        field_name: field_name.clone(),
+                    })
+                }
+                Some(_) => (),
+            }
+        }
+        self.aggregation.replace(aggregation);
+        Ok(self)
+    }
+
+    /// Select measurements from the rollups of the timeseries at the given resolution, rather than
+    /// at full resolution.
+    ///
+    /// An error is returned if measurements with the datum type of the timeseries can't be rolled
+    /// up. See [`crate::retention`] for how measurements are rolled up.
+    pub fn rollup(
+        mut self,
+        resolution: RollupResolution,
+    ) -> Result<Self, Error> {
+        let datum_type = self.timeseries_schema.datum_type;
+        if !retention::supports_rollups(datum_type) {
+            return Err(Error::RollupNotSupported(datum_type));
+        }
+        self.rollup.replace(resolution);
+        Ok(self)
+    }
+
     /// Add a filter for a field with the given name, comparison operator, and value.
     ///
     /// An error is returned if the field cannot be found or the field value is not of the correct
@@ -291,6 +365,8 @@ impl SelectQueryBuilder {
             limit: self.limit,
             offset: self.offset,
             order: self.order.unwrap_or(PaginationOrder::Ascending),
+            aggregation: self.aggregation,
+            rollup: self.rollup,
         }
     }
 }
@@ -470,6 +546,127 @@ impl fmt::Display for FieldCmp {
     }
 }
 
+/// An operation used to combine the measurements in each window of an aggregated query.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AggregationOp {
+    /// The mean of the measurements of a gauge
+    Mean,
+    /// The smallest measurement of a gauge
+    Min,
+    /// The largest measurement of a gauge
+    Max,
+    /// The sum of the measurements of a gauge
+    Sum,
+    /// The increase in a cumulative counter
+    Delta,
+    /// The increase in a cumulative counter, per second
+    Rate,
+    /// The given quantile (between 0 and 1) of the samples added to a histogram
+    Quantile(f64),
+}
+
+impl AggregationOp {
+    // Return `true` if this operation may be applied to timeseries of the given datum type.
+    fn valid_for_type(&self, ty: DatumType) -> bool {
+        match self {
+            AggregationOp::Mean
+            | AggregationOp::Min
+            | AggregationOp::Max
+            | AggregationOp::Sum => matches!(
+                ty,
+                DatumType::I8
+                    | DatumType::U8
+                    | DatumType::I16
+                    | DatumType::U16
+                    | DatumType::I32
+                    | DatumType::U32
+                    | DatumType::I64
+                    | DatumType::U64
+                    | DatumType::F32
+                    | DatumType::F64
+            ),
+            AggregationOp::Delta | AggregationOp::Rate => matches!(
+                ty,
+                DatumType::CumulativeI64
+                    | DatumType::CumulativeU64
+                    | DatumType::CumulativeF32
+                    | DatumType::CumulativeF64
+            ),
+            AggregationOp::Quantile(_) => matches!(
+                ty,
+                DatumType::HistogramI8
+                    | DatumType::HistogramU8
+                    | DatumType::HistogramI16
+                    | DatumType::HistogramU16
+                    | DatumType::HistogramI32
+                    | DatumType::HistogramU32
+                    | DatumType::HistogramI64
+                    | DatumType::HistogramU64
+                    | DatumType::HistogramF32
+                    | DatumType::HistogramF64
+            ),
+        }
+    }
+}
+
+/// Parse an aggregation operation: one of `mean`, `min`, `max`, `sum`, `delta`, `rate`, or a
+/// percentile such as `p99` or `p99.9`.
+impl FromStr for AggregationOp {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mean" => Ok(AggregationOp::Mean),
+            "min" => Ok(AggregationOp::Min),
+            "max" => Ok(AggregationOp::Max),
+            "sum" => Ok(AggregationOp::Sum),
+            "delta" => Ok(AggregationOp::Delta),
+            "rate" => Ok(AggregationOp::Rate),
+            _ => s
+                .strip_prefix('p')
+                .and_then(|percentile| percentile.parse::<f64>().ok())
+                .filter(|percentile| (0.0..=100.0).contains(percentile))
+                .map(|percentile| AggregationOp::Quantile(percentile / 100.0))
+                .ok_or_else(|| {
+                    Error::InvalidAggregation(format!(
+                        "unknown aggregation operation '{s}'"
+                    ))
+                }),
+        }
+    }
+}
+
+impl fmt::Display for AggregationOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AggregationOp::Mean => write!(f, "mean"),
+            AggregationOp::Min => write!(f, "min"),
+            AggregationOp::Max => write!(f, "max"),
+            AggregationOp::Sum => write!(f, "sum"),
+            AggregationOp::Delta => write!(f, "delta"),
+            AggregationOp::Rate => write!(f, "rate"),
+            AggregationOp::Quantile(q) => write!(f, "p{}", q * 100.0),
+        }
+    }
+}
+
+/// Describes how to aggregate the measurements selected by a query.
+///
+/// Measurements are divided into fixed windows of time, aligned to multiples of the window width
+/// since the Unix epoch. The measurements in each window are combined into a single value by the
+/// aggregation operation.
+///
+/// By default, each timeseries is aggregated separately. If any fields are listed in `group_by`,
+/// the measurements of all timeseries with the same values for those fields are combined. Only
+/// target fields may be used for grouping. (Histograms that are grouped together are expected to
+/// have the same bins.)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aggregation {
+    pub op: AggregationOp,
+    /// The width of each window, truncated to whole seconds.
+    pub interval: Duration,
+    pub group_by: Vec<String>,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct TimeRange {
     pub start: Option<Timestamp>,
@@ -518,6 +715,8 @@ pub struct SelectQuery {
     limit: Option<NonZeroU32>,
     offset: Option<u32>,
     order: PaginationOrder,
+    aggregation: Option<Aggregation>,
+    rollup: Option<RollupResolution>,
 }
 
 fn create_join_on_condition(columns: &[&str], current: usize) -> String {
@@ -633,6 +832,180 @@ impl SelectQuery {
         }
     }
 
+    /// Return the aggregation applied to the selected measurements, if any.
+    pub fn aggregation(&self) -> Option<&Aggregation> {
+        self.aggregation.as_ref()
+    }
+
+    /// Return the resolution of the rollups from which measurements are selected, or `None` if
+    /// they're selected at full resolution.
+    pub fn rollup(&self) -> Option<RollupResolution> {
+        self.rollup
+    }
+
+    // Return the columns, table, and grouping from which measurements are selected. Rollups are
+    // grouped by timeseries and window, so that each row is a single measurement.
+    fn measurement_source(&self) -> (String, String, &'static str) {
+        let datum_type = self.timeseries_schema.datum_type;
+        match self.rollup {
+            None => (String::from("*"), measurement_table_name(datum_type), ""),
+            Some(resolution) => (
+                retention::rollup_measurement_columns(datum_type),
+                retention::rollup_table_name(datum_type, resolution),
+                "GROUP BY timeseries_name, timeseries_key, timestamp ",
+            ),
+        }
+    }
+
+    /// Construct and return the query used to aggregate the measurements of the timeseries with
+    /// the given keys, or `None` if the query doesn't aggregate its measurements.
+    ///
+    /// `groups` maps each timeseries key to the group whose measurements it's aggregated with.
+    /// Each row selected by the query contains a group, the start of a window, and the aggregated
+    /// value for that group and window.
+    pub fn aggregated_measurement_query(
+        &self,
+        groups: &BTreeMap<TimeseriesKey, u64>,
+    ) -> Option<String> {
+        let aggregation = self.aggregation.as_ref()?;
+        let keys = groups
+            .keys()
+            .map(|key| key.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let group_ids = groups
+            .values()
+            .map(|group| group.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let interval = aggregation.interval.as_secs();
+        let group_expr =
+            format!("transform(timeseries_key, [{keys}], [{group_ids}], 0)");
+        let window_expr = format!(
+            "toDateTime64(toStartOfInterval(timestamp, INTERVAL {interval} SECOND), 9, 'UTC')"
+        );
+        let (columns, table_name, group_clause) = self.measurement_source();
+        let condition = format!(
+            "timeseries_name = '{timeseries_name}' AND timeseries_key IN ({keys}){timestamp_clause}",
+            timeseries_name = self.timeseries_schema.timeseries_name,
+            timestamp_clause = self.time_range.as_query().trim_end(),
+        );
+        let source = if self.rollup.is_some() {
+            format!(
+                "(SELECT {columns} FROM {db_name}.{table_name} WHERE {condition} {group_clause})",
+                db_name = DATABASE_NAME,
+                group_clause = group_clause.trim_end(),
+            )
+        } else {
+            format!("{DATABASE_NAME}.{table_name} WHERE {condition}")
+        };
+        // Differences between consecutive samples of a cumulative timeseries. The first sample of
+        // each timeseries (and after each reset of its start time) is the baseline for the rest.
+        const PREVIOUS_SAMPLE: &str = "OVER (PARTITION BY timeseries_key, start_time ORDER BY timestamp ASC ROWS BETWEEN 1 PRECEDING AND CURRENT ROW)";
+
+        let select = match aggregation.op {
+            AggregationOp::Mean
+            | AggregationOp::Min
+            | AggregationOp::Max
+            | AggregationOp::Sum => {
+                let function = match aggregation.op {
+                    AggregationOp::Mean => "avg",
+                    AggregationOp::Min => "min",
+                    AggregationOp::Max => "max",
+                    _ => "sum",
+                };
+                format!(
+                    concat!(
+                        "SELECT {group_expr} AS group_id, {window_expr} AS window_start, ",
+                        "toFloat64({function}(datum)) AS value ",
+                        "FROM {source} ",
+                        "GROUP BY group_id, window_start ",
+                    ),
+                    group_expr = group_expr,
+                    window_expr = window_expr,
+                    function = function,
+                    source = source,
+                )
+            }
+            AggregationOp::Delta | AggregationOp::Rate => {
+                let value = if aggregation.op == AggregationOp::Rate {
+                    format!("toFloat64(sum(delta)) / {interval}")
+                } else {
+                    String::from("toFloat64(sum(delta))")
+                };
+                format!(
+                    concat!(
+                        "SELECT group_id, window_start, {value} AS value ",
+                        "FROM (",
+                        "SELECT {group_expr} AS group_id, {window_expr} AS window_start, ",
+                        "datum - lagInFrame(datum, 1, datum) {previous} AS delta ",
+                        "FROM {source}",
+                        ") ",
+                        "GROUP BY group_id, window_start ",
+                    ),
+                    value = value,
+                    group_expr = group_expr,
+                    window_expr = window_expr,
+                    previous = PREVIOUS_SAMPLE,
+                    source = source,
+                )
+            }
+            AggregationOp::Quantile(q) => {
+                // Sum the counts added to each bin during the window, then find the first bin by
+                // which the cumulative count reaches the quantile. The value reported is the left
+                // edge of that bin.
+                format!(
+                    concat!(
+                        "SELECT group_id, window_start, ",
+                        "toFloat64(window_bins[arrayFirstIndex(x -> x >= {q} * arraySum(delta_counts), arrayCumSum(delta_counts))]) AS value ",
+                        "FROM (",
+                        "SELECT group_id, window_start, any(bins) AS window_bins, sumForEach(delta) AS delta_counts ",
+                        "FROM (",
+                        "SELECT {group_expr} AS group_id, {window_expr} AS window_start, bins, ",
+                        "arrayMap((x, y) -> x - y, counts, lagInFrame(counts, 1, counts) {previous}) AS delta ",
+                        "FROM {source}",
+                        ") ",
+                        "GROUP BY group_id, window_start",
+                        ") ",
+                        "WHERE arraySum(delta_counts) > 0 ",
+                    ),
+                    q = q,
+                    group_expr = group_expr,
+                    window_expr = window_expr,
+                    previous = PREVIOUS_SAMPLE,
+                    source = source,
+                )
+            }
+        };
+        let order_dir = match self.order {
+            PaginationOrder::Descending => "DESC ",
+            PaginationOrder::Ascending => "",
+        };
+        Some(format!(
+            concat!(
+                "{select}",
+                "ORDER BY (group_id, window_start) {order_dir}",
+                "{pagination_clause}",
+                "FORMAT {fmt};",
+            ),
+            select = select,
+            order_dir = order_dir,
+            pagination_clause = self.pagination_clause(),
+            fmt = DATABASE_SELECT_FORMAT,
+        ))
+    }
+
+    fn pagination_clause(&self) -> String {
+        let mut clause = String::new();
+        if let Some(limit) = self.limit {
+            clause.push_str(&format!("LIMIT {} ", limit));
+        }
+        if let Some(offset) = self.offset {
+            clause.push_str(&format!("OFFSET {} ", offset));
+        };
+        clause
+    }
+
     /// Construct and return the query used to select the measurements, using the associated
     /// timeseries keys. If no keys are specified, then a query selecting the all timeseries with
     /// the given name will be returned. (This is probably not what you want.)
@@ -649,35 +1022,29 @@ impl SelectQuery {
                     .join(", "),
             )
         };
-        let pagination_clause = {
-            let mut clause = String::new();
-            if let Some(limit) = self.limit {
-                clause.push_str(&format!("LIMIT {} ", limit));
-            }
-            if let Some(offset) = self.offset {
-                clause.push_str(&format!("OFFSET {} ", offset));
-            };
-            clause
-        };
+        let pagination_clause = self.pagination_clause();
         let order_dir = match self.order {
             PaginationOrder::Descending => "DESC ",
             PaginationOrder::Ascending => "",
         };
+        let (columns, table_name, group_clause) = self.measurement_source();
         format!(
             concat!(
-                "SELECT * ",
+                "SELECT {columns} ",
                 "FROM {db_name}.{table_name} ",
                 "WHERE ",
                 "timeseries_name = '{timeseries_name}'",
                 "{key_clause}",
                 "{timestamp_clause}",
+                "{group_clause}",
                 "ORDER BY (timeseries_name, timeseries_key, timestamp) {order_dir}",
                 "{pagination_clause}",
                 "FORMAT {fmt};",
             ),
+            columns = columns,
             db_name = DATABASE_NAME,
-            table_name =
-                measurement_table_name(self.timeseries_schema.datum_type),
+            table_name = table_name,
+            group_clause = group_clause,
             timeseries_name = self.timeseries_schema.timeseries_name,
             key_clause = key_clause,
             timestamp_clause = self.time_range.as_query(),
@@ -689,7 +1056,7 @@ impl SelectQuery {
 }
 
 // Format the value for use in a query to the database, e.g., `... WHERE field_value = {}`.
-fn field_as_db_str(value: &FieldValue) -> String {
+pub(crate) fn field_as_db_str(value: &FieldValue) -> String {
     match value {
         FieldValue::Bool(ref inner) => {
             format!("{}", if *inner { 1 } else { 0 })
@@ -722,6 +1089,7 @@ mod tests {
     use crate::TimeseriesName;
     use chrono::NaiveDateTime;
     use std::convert::TryFrom;
+    use std::num::NonZeroU8;
 
     #[test]
     fn test_field_value_as_db_str() {
@@ -774,6 +1142,7 @@ mod tests {
     fn test_select_query_builder_filter_raw() {
         let schema = TimeseriesSchema {
             timeseries_name: TimeseriesName::try_from("foo:bar").unwrap(),
+            version: NonZeroU8::new(1).unwrap(),
             field_schema: vec![
                 FieldSchema {
                     name: "f0".to_string(),
@@ -905,6 +1274,7 @@ mod tests {
     fn test_select_query_builder_no_fields() {
         let schema = TimeseriesSchema {
             timeseries_name: TimeseriesName::try_from("foo:bar").unwrap(),
+            version: NonZeroU8::new(1).unwrap(),
             field_schema: vec![],
             datum_type: DatumType::I64,
             created: Utc::now(),
@@ -927,6 +1297,7 @@ mod tests {
     fn test_select_query_builder_limit_offset() {
         let schema = TimeseriesSchema {
             timeseries_name: TimeseriesName::try_from("foo:bar").unwrap(),
+            version: NonZeroU8::new(1).unwrap(),
             field_schema: vec![],
             datum_type: DatumType::I64,
             created: Utc::now(),
@@ -996,6 +1367,7 @@ mod tests {
     fn test_select_query_builder_no_selectors() {
         let schema = TimeseriesSchema {
             timeseries_name: TimeseriesName::try_from("foo:bar").unwrap(),
+            version: NonZeroU8::new(1).unwrap(),
             field_schema: vec![
                 FieldSchema {
                     name: "f0".to_string(),
@@ -1057,6 +1429,7 @@ mod tests {
     fn test_select_query_builder_field_selectors() {
         let schema = TimeseriesSchema {
             timeseries_name: TimeseriesName::try_from("foo:bar").unwrap(),
+            version: NonZeroU8::new(1).unwrap(),
             field_schema: vec![
                 FieldSchema {
                     name: "f0".to_string(),
@@ -1106,6 +1479,7 @@ mod tests {
     fn test_select_query_builder_full() {
         let schema = TimeseriesSchema {
             timeseries_name: TimeseriesName::try_from("foo:bar").unwrap(),
+            version: NonZeroU8::new(1).unwrap(),
             field_schema: vec![
                 FieldSchema {
                     name: "f0".to_string(),
@@ -1175,4 +1549,196 @@ mod tests {
             )
         );
     }
+
+    #[test]
+    fn test_aggregation_op_from_str() {
+        assert_eq!(
+            "mean".parse::<AggregationOp>().unwrap(),
+            AggregationOp::Mean
+        );
+        assert_eq!(
+            "rate".parse::<AggregationOp>().unwrap(),
+            AggregationOp::Rate
+        );
+        assert_eq!(
+            "p99".parse::<AggregationOp>().unwrap(),
+            AggregationOp::Quantile(0.99)
+        );
+        assert_eq!("p50".parse::<AggregationOp>().unwrap().to_string(), "p50");
+        assert!("median".parse::<AggregationOp>().is_err());
+        assert!("p101".parse::<AggregationOp>().is_err());
+    }
+
+    #[test]
+    fn test_select_query_builder_aggregate_validation() {
+        let schema = TimeseriesSchema {
+            timeseries_name: TimeseriesName::try_from("foo:bar").unwrap(),
+            version: NonZeroU8::new(1).unwrap(),
+            field_schema: vec![
+                FieldSchema {
+                    name: "f0".to_string(),
+                    ty: FieldType::I64,
+                    source: FieldSource::Target,
+                },
+                FieldSchema {
+                    name: "f1".to_string(),
+                    ty: FieldType::Bool,
+                    source: FieldSource::Metric,
+                },
+            ],
+            datum_type: DatumType::CumulativeI64,
+            created: Utc::now(),
+        };
+        let aggregation = Aggregation {
+            op: AggregationOp::Rate,
+            interval: Duration::from_secs(60),
+            group_by: vec![String::from("f0")],
+        };
+        assert!(SelectQueryBuilder::new(&schema)
+            .aggregate(aggregation.clone())
+            .is_ok());
+        assert!(matches!(
+            SelectQueryBuilder::new(&schema).aggregate(Aggregation {
+                op: AggregationOp::Mean,
+                ..aggregation.clone()
+            }),
+            Err(Error::AggregationNotSupported { .. })
+        ));
+        assert!(matches!(
+            SelectQueryBuilder::new(&schema).aggregate(Aggregation {
+                interval: Duration::from_millis(500),
+                ..aggregation.clone()
+            }),
+            Err(Error::InvalidAggregation(_))
+        ));
+        assert!(matches!(
+            SelectQueryBuilder::new(&schema).aggregate(Aggregation {
+                group_by: vec![String::from("f1")],
+                ..aggregation.clone()
+            }),
+            Err(Error::InvalidGroupByField { .. })
+        ));
+        assert!(matches!(
+            SelectQueryBuilder::new(&schema).aggregate(Aggregation {
+                group_by: vec![String::from("f2")],
+                ..aggregation
+            }),
+            Err(Error::NoSuchField { .. })
+        ));
+    }
+
+    #[test]
+    fn test_select_query_builder_aggregated_measurement_query() {
+        let schema = TimeseriesSchema {
+            timeseries_name: TimeseriesName::try_from("foo:bar").unwrap(),
+            version: NonZeroU8::new(1).unwrap(),
+            field_schema: vec![],
+            datum_type: DatumType::F64,
+            created: Utc::now(),
+        };
+        let query = SelectQueryBuilder::new(&schema).build();
+        assert!(query.aggregated_measurement_query(&BTreeMap::new()).is_none());
+
+        let query = SelectQueryBuilder::new(&schema)
+            .aggregate(Aggregation {
+                op: AggregationOp::Max,
+                interval: Duration::from_secs(60),
+                group_by: vec![],
+            })
+            .unwrap()
+            .limit(NonZeroU32::try_from(10).unwrap())
+            .build();
+        let groups = BTreeMap::from([(1, 0), (2, 0), (3, 1)]);
+        assert_eq!(
+            query.aggregated_measurement_query(&groups).unwrap(),
+            concat!(
+                "SELECT transform(timeseries_key, [1, 2, 3], [0, 0, 1], 0) AS group_id, ",
+                "toDateTime64(toStartOfInterval(timestamp, INTERVAL 60 SECOND), 9, 'UTC') ",
+                "AS window_start, ",
+                "toFloat64(max(datum)) AS value ",
+                "FROM oximeter.measurements_f64 ",
+                "WHERE timeseries_name = 'foo:bar' AND timeseries_key IN (1, 2, 3) ",
+                "GROUP BY group_id, window_start ",
+                "ORDER BY (group_id, window_start) ",
+                "LIMIT 10 ",
+                "FORMAT JSONEachRow;",
+            )
+        );
+    }
+
+    #[test]
+    fn test_select_query_builder_rollup() {
+        let schema = TimeseriesSchema {
+            timeseries_name: TimeseriesName::try_from("foo:bar").unwrap(),
+            version: NonZeroU8::new(1).unwrap(),
+            field_schema: vec![],
+            datum_type: DatumType::I64,
+            created: Utc::now(),
+        };
+        let query = SelectQueryBuilder::new(&schema)
+            .rollup(RollupResolution::Hourly)
+            .unwrap()
+            .build();
+        assert_eq!(query.rollup(), Some(RollupResolution::Hourly));
+        assert_eq!(
+            query.measurement_query(&[1, 2]),
+            concat!(
+                "SELECT timeseries_name, timeseries_key, timestamp, ",
+                "CAST(round(avgMerge(datum)) AS Int64) AS datum ",
+                "FROM oximeter.measurements_i64_hourly ",
+                "WHERE timeseries_name = 'foo:bar' AND timeseries_key IN (1, 2) ",
+                "GROUP BY timeseries_name, timeseries_key, timestamp ",
+                "ORDER BY (timeseries_name, timeseries_key, timestamp) ",
+                "FORMAT JSONEachRow;"
+            )
+        );
+
+        let query = SelectQueryBuilder::new(&TimeseriesSchema {
+            datum_type: DatumType::CumulativeF64,
+            ..schema.clone()
+        })
+        .rollup(RollupResolution::Daily)
+        .unwrap()
+        .aggregate(Aggregation {
+            op: AggregationOp::Delta,
+            interval: Duration::from_secs(86400 * 7),
+            group_by: vec![],
+        })
+        .unwrap()
+        .build();
+        assert_eq!(
+            query
+                .aggregated_measurement_query(&BTreeMap::from([(1, 0)]))
+                .unwrap(),
+            concat!(
+                "SELECT group_id, window_start, toFloat64(sum(delta)) AS value ",
+                "FROM (",
+                "SELECT transform(timeseries_key, [1], [0], 0) AS group_id, ",
+                "toDateTime64(toStartOfInterval(timestamp, INTERVAL 604800 SECOND), 9, 'UTC') ",
+                "AS window_start, ",
+                "datum - lagInFrame(datum, 1, datum) OVER (PARTITION BY timeseries_key, start_time ",
+                "ORDER BY timestamp ASC ROWS BETWEEN 1 PRECEDING AND CURRENT ROW) AS delta ",
+                "FROM (",
+                "SELECT timeseries_name, timeseries_key, argMaxMerge(start_time) AS start_time, ",
+                "timestamp, argMaxMerge(datum) AS datum ",
+                "FROM oximeter.measurements_cumulativef64_daily ",
+                "WHERE timeseries_name = 'foo:bar' AND timeseries_key IN (1) ",
+                "GROUP BY timeseries_name, timeseries_key, timestamp)",
+                ") ",
+                "GROUP BY group_id, window_start ",
+                "ORDER BY (group_id, window_start) ",
+                "FORMAT JSONEachRow;",
+            )
+        );
+
+        // Only scalar, numeric measurements are rolled up.
+        assert!(matches!(
+            SelectQueryBuilder::new(&TimeseriesSchema {
+                datum_type: DatumType::HistogramF64,
+                ..schema
+            })
+            .rollup(RollupResolution::Hourly),
+            Err(Error::RollupNotSupported(DatumType::HistogramF64))
+        ));
+    }
 }
@@ -0,0 +1,734 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Retention policies for timeseries, and rollups of their measurements.
+//!
+//! By default, the measurements of every timeseries are kept at full resolution for
+//! [`DEFAULT_RETENTION_DAYS`]. A [`RetentionPolicy`] overrides that for one timeseries, and may
+//! also ask for hourly or daily rollups of its measurements, which are kept for their own periods.
+//!
+//! Rollups are stored in tables alongside the measurement tables, e.g.,
+//! `measurements_f64_hourly`, which are populated by materialized views over the measurement
+//! tables. Each row of a rollup table summarizes the measurements of one timeseries during one
+//! window: gauges by their mean, and cumulative counters by their last value. Only scalar,
+//! numeric datum types can be rolled up.
+
+// Copyright 2023 Oxide Computer Company
+
+use crate::model::DbRetentionPolicy;
+use crate::query::measurement_table_name;
+use crate::{Error, TimeseriesName, DATABASE_NAME};
+use chrono::{DateTime, Utc};
+use oximeter::DatumType;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::fmt;
+
+/// The number of days for which the measurements of a timeseries without a retention policy are
+/// kept.
+pub const DEFAULT_RETENTION_DAYS: u32 = 30;
+
+// The cluster on which replicated tables are created.
+const CLUSTER_NAME: &str = "oximeter_cluster";
+
+/// How long the measurements of a timeseries are kept, at full resolution and as rollups.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RetentionPolicy {
+    /// The timeseries to which the policy applies.
+    pub timeseries_name: TimeseriesName,
+
+    /// The number of days for which measurements are kept at full resolution.
+    pub raw_retention_days: u32,
+
+    /// The number of days for which hourly rollups of the measurements are kept.
+    ///
+    /// If "None", hourly rollups aren't maintained.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hourly_retention_days: Option<u32>,
+
+    /// The number of days for which daily rollups of the measurements are kept.
+    ///
+    /// If "None", daily rollups aren't maintained.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily_retention_days: Option<u32>,
+}
+
+impl RetentionPolicy {
+    /// Return the number of days for which rollups at the given resolution are kept, if they're
+    /// maintained.
+    pub fn rollup_retention_days(
+        &self,
+        resolution: RollupResolution,
+    ) -> Option<u32> {
+        match resolution {
+            RollupResolution::Hourly => self.hourly_retention_days,
+            RollupResolution::Daily => self.daily_retention_days,
+        }
+    }
+
+    /// Return the resolution at which to select measurements of a timeseries with this policy,
+    /// starting at `start`, or `None` to select them at full resolution.
+    ///
+    /// Measurements are selected at full resolution until they would have expired. After that,
+    /// the finest rollup that's kept for long enough is used or, if no rollup is kept that long,
+    /// the one kept the longest.
+    pub fn resolution_for(
+        &self,
+        datum_type: DatumType,
+        start: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Option<RollupResolution> {
+        if !supports_rollups(datum_type) {
+            return None;
+        }
+        let age = now.signed_duration_since(start);
+        let covers = |days: u32| age <= chrono::Duration::days(i64::from(days));
+        if covers(self.raw_retention_days) {
+            return None;
+        }
+        let rollups = RollupResolution::ALL
+            .into_iter()
+            .filter_map(|resolution| {
+                self.rollup_retention_days(resolution)
+                    .filter(|days| *days > self.raw_retention_days)
+                    .map(|days| (resolution, days))
+            })
+            .collect::<Vec<_>>();
+        rollups
+            .iter()
+            .find(|(_, days)| covers(*days))
+            .or_else(|| rollups.iter().max_by_key(|(_, days)| *days))
+            .map(|(resolution, _)| *resolution)
+    }
+
+    // Check that the policy keeps measurements for some time at every resolution it names.
+    fn validate(&self) -> Result<(), Error> {
+        let invalid = |what: &str| {
+            Err(Error::InvalidRetentionPolicy(format!(
+                "{what} for timeseries '{}' must be kept for at least one day",
+                self.timeseries_name
+            )))
+        };
+        if self.raw_retention_days == 0 {
+            return invalid("Measurements");
+        }
+        for resolution in RollupResolution::ALL {
+            if self.rollup_retention_days(resolution) == Some(0) {
+                return invalid(&format!("{resolution} rollups"));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl From<DbRetentionPolicy> for RetentionPolicy {
+    fn from(policy: DbRetentionPolicy) -> RetentionPolicy {
+        let rollup = |days| if days == 0 { None } else { Some(days) };
+        RetentionPolicy {
+            timeseries_name: TimeseriesName::try_from(
+                policy.timeseries_name.as_str(),
+            )
+            .expect("Invalid timeseries name in database"),
+            raw_retention_days: policy.raw_retention_days,
+            hourly_retention_days: rollup(policy.hourly_retention_days),
+            daily_retention_days: rollup(policy.daily_retention_days),
+        }
+    }
+}
+
+/// Check that a set of retention policies is valid, with at most one policy for each timeseries.
+pub fn validate_policies(policies: &[RetentionPolicy]) -> Result<(), Error> {
+    let mut names = BTreeSet::new();
+    for policy in policies.iter() {
+        policy.validate()?;
+        if !names.insert(&policy.timeseries_name) {
+            return Err(Error::InvalidRetentionPolicy(format!(
+                "Multiple retention policies for timeseries '{}'",
+                policy.timeseries_name
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// The resolution of a rollup of measurements.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Deserialize,
+    Serialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum RollupResolution {
+    Hourly,
+    Daily,
+}
+
+impl RollupResolution {
+    /// All resolutions, from finest to coarsest.
+    pub const ALL: [RollupResolution; 2] =
+        [RollupResolution::Hourly, RollupResolution::Daily];
+
+    fn as_db_interval(&self) -> &'static str {
+        match self {
+            RollupResolution::Hourly => "INTERVAL 1 HOUR",
+            RollupResolution::Daily => "INTERVAL 1 DAY",
+        }
+    }
+}
+
+impl fmt::Display for RollupResolution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            RollupResolution::Hourly => "hourly",
+            RollupResolution::Daily => "daily",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+// The database type of the datum of a timeseries that can be rolled up, and whether the
+// timeseries is cumulative.
+fn rollup_datum_type(datum_type: DatumType) -> Option<(&'static str, bool)> {
+    let ty = match datum_type {
+        DatumType::I8 => ("Int8", false),
+        DatumType::U8 => ("UInt8", false),
+        DatumType::I16 => ("Int16", false),
+        DatumType::U16 => ("UInt16", false),
+        DatumType::I32 => ("Int32", false),
+        DatumType::U32 => ("UInt32", false),
+        DatumType::I64 => ("Int64", false),
+        DatumType::U64 => ("UInt64", false),
+        DatumType::F32 => ("Float32", false),
+        DatumType::F64 => ("Float64", false),
+        DatumType::CumulativeI64 => ("Int64", true),
+        DatumType::CumulativeU64 => ("UInt64", true),
+        DatumType::CumulativeF32 => ("Float32", true),
+        DatumType::CumulativeF64 => ("Float64", true),
+        _ => return None,
+    };
+    Some(ty)
+}
+
+/// Return `true` if measurements with the given datum type can be rolled up.
+pub fn supports_rollups(datum_type: DatumType) -> bool {
+    rollup_datum_type(datum_type).is_some()
+}
+
+/// Every datum type, each of which has its own measurement table.
+pub(crate) const DATUM_TYPES: [DatumType; 27] = [
+    DatumType::Bool,
+    DatumType::I8,
+    DatumType::U8,
+    DatumType::I16,
+    DatumType::U16,
+    DatumType::I32,
+    DatumType::U32,
+    DatumType::I64,
+    DatumType::U64,
+    DatumType::F32,
+    DatumType::F64,
+    DatumType::String,
+    DatumType::Bytes,
+    DatumType::CumulativeI64,
+    DatumType::CumulativeU64,
+    DatumType::CumulativeF32,
+    DatumType::CumulativeF64,
+    DatumType::HistogramI8,
+    DatumType::HistogramU8,
+    DatumType::HistogramI16,
+    DatumType::HistogramU16,
+    DatumType::HistogramI32,
+    DatumType::HistogramU32,
+    DatumType::HistogramI64,
+    DatumType::HistogramU64,
+    DatumType::HistogramF32,
+    DatumType::HistogramF64,
+];
+
+/// All datum types whose measurements can be rolled up.
+pub(crate) fn rollup_datum_types() -> impl Iterator<Item = DatumType> {
+    DATUM_TYPES.into_iter().filter(|ty| supports_rollups(*ty))
+}
+
+/// Return the name of the table holding rollups of measurements with the given datum type.
+pub(crate) fn rollup_table_name(
+    datum_type: DatumType,
+    resolution: RollupResolution,
+) -> String {
+    format!("{}_{}", measurement_table_name(datum_type), resolution)
+}
+
+// Return the prefix of the names of the materialized views populating the given rollup table.
+//
+// Each view is named for the time it was created, so that the view replacing it can be created
+// before it's dropped.
+fn rollup_view_prefix(
+    datum_type: DatumType,
+    resolution: RollupResolution,
+) -> String {
+    format!("{}_mv", rollup_table_name(datum_type, resolution))
+}
+
+// In a replicated database, statements that change tables apply to the `_local` table on each
+// node rather than the distributed table.
+fn local_table(table: &str, replicated: bool) -> String {
+    if replicated {
+        format!("{DATABASE_NAME}.{table}_local ON CLUSTER {CLUSTER_NAME}")
+    } else {
+        format!("{DATABASE_NAME}.{table}")
+    }
+}
+
+/// Return the statements creating the table for rollups of measurements with the given datum
+/// type and resolution, if it doesn't already exist.
+pub(crate) fn create_rollup_table(
+    datum_type: DatumType,
+    resolution: RollupResolution,
+    replicated: bool,
+) -> Vec<String> {
+    let (ty, cumulative) =
+        rollup_datum_type(datum_type).expect("Datum type supports rollups");
+    let table = rollup_table_name(datum_type, resolution);
+    let columns = if cumulative {
+        format!(
+            concat!(
+                "timeseries_name String, ",
+                "timeseries_key UInt64, ",
+                "start_time AggregateFunction(argMax, DateTime64(9, 'UTC'), DateTime64(9, 'UTC')), ",
+                "timestamp DateTime64(9, 'UTC'), ",
+                "datum AggregateFunction(argMax, {ty}, DateTime64(9, 'UTC'))",
+            ),
+            ty = ty,
+        )
+    } else {
+        format!(
+            concat!(
+                "timeseries_name String, ",
+                "timeseries_key UInt64, ",
+                "timestamp DateTime64(9, 'UTC'), ",
+                "datum AggregateFunction(avg, {ty})",
+            ),
+            ty = ty,
+        )
+    };
+    const ORDER_BY: &str =
+        "ORDER BY (timeseries_name, timeseries_key, timestamp)";
+    if replicated {
+        vec![
+            format!(
+                "CREATE TABLE IF NOT EXISTS {db}.{table}_local ON CLUSTER {cluster} ({columns}) \
+                 ENGINE = ReplicatedAggregatingMergeTree('/clickhouse/tables/{{shard}}/{table}_local', '{{replica}}') \
+                 {ORDER_BY};",
+                db = DATABASE_NAME,
+                cluster = CLUSTER_NAME,
+            ),
+            format!(
+                "CREATE TABLE IF NOT EXISTS {db}.{table} ON CLUSTER {cluster} ({columns}) \
+                 ENGINE = Distributed('{cluster}', '{db}', '{table}_local', xxHash64(splitByChar(':', timeseries_name)[1]));",
+                db = DATABASE_NAME,
+                cluster = CLUSTER_NAME,
+            ),
+        ]
+    } else {
+        vec![format!(
+            "CREATE TABLE IF NOT EXISTS {db}.{table} ({columns}) \
+             ENGINE = AggregatingMergeTree() {ORDER_BY};",
+            db = DATABASE_NAME,
+        )]
+    }
+}
+
+// Select the aggregate states summarizing the measurements in the given table that match
+// `condition`, one row per timeseries and window.
+//
+// The window and states are named after the columns of the rollup table, which are the same as
+// those of the measurement table. The measurement columns are renamed in a subquery, so that the
+// aggregate functions refer to them rather than to the aliases.
+fn rollup_select(
+    datum_type: DatumType,
+    resolution: RollupResolution,
+    source: &str,
+    condition: &str,
+) -> String {
+    let (_, cumulative) =
+        rollup_datum_type(datum_type).expect("Datum type supports rollups");
+    let (columns, states) = if cumulative {
+        (
+            "start_time AS sample_start_time, timestamp AS sample_timestamp, datum AS sample_datum",
+            "argMaxState(sample_start_time, sample_timestamp) AS start_time, argMaxState(sample_datum, sample_timestamp) AS datum",
+        )
+    } else {
+        (
+            "timestamp AS sample_timestamp, datum AS sample_datum",
+            "avgState(sample_datum) AS datum",
+        )
+    };
+    format!(
+        concat!(
+            "SELECT timeseries_name, timeseries_key, ",
+            "toDateTime64(toStartOfInterval(sample_timestamp, {interval}), 9, 'UTC') AS timestamp, ",
+            "{states} ",
+            "FROM (",
+            "SELECT timeseries_name, timeseries_key, {columns} ",
+            "FROM {source} ",
+            "WHERE {condition}",
+            ") ",
+            "GROUP BY timeseries_name, timeseries_key, timestamp",
+        ),
+        interval = resolution.as_db_interval(),
+        states = states,
+        columns = columns,
+        source = source,
+        condition = condition,
+    )
+}
+
+fn timeseries_names_condition<'a>(
+    names: impl IntoIterator<Item = &'a TimeseriesName>,
+) -> String {
+    format!(
+        "timeseries_name IN ({})",
+        names
+            .into_iter()
+            .map(|name| format!("'{name}'"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    )
+}
+
+/// Return the statement creating the materialized view that rolls up the measurements of the
+/// named timeseries, as they're inserted.
+///
+/// The view is named for the time it's `created`, which must differ from that of any view it
+/// replaces.
+pub(crate) fn create_rollup_view(
+    datum_type: DatumType,
+    resolution: RollupResolution,
+    names: &BTreeSet<TimeseriesName>,
+    created: DateTime<Utc>,
+    replicated: bool,
+) -> String {
+    let (suffix, on_cluster) = if replicated {
+        ("_local", format!(" ON CLUSTER {CLUSTER_NAME}"))
+    } else {
+        ("", String::new())
+    };
+    format!(
+        "CREATE MATERIALIZED VIEW IF NOT EXISTS {db}.{view}{on_cluster} TO {db}.{table}{suffix} AS {select};",
+        db = DATABASE_NAME,
+        view = format!(
+            "{}_{}",
+            rollup_view_prefix(datum_type, resolution),
+            created.timestamp_micros()
+        ),
+        table = rollup_table_name(datum_type, resolution),
+        select = rollup_select(
+            datum_type,
+            resolution,
+            &format!(
+                "{DATABASE_NAME}.{}{suffix}",
+                measurement_table_name(datum_type)
+            ),
+            &timeseries_names_condition(names),
+        ),
+    )
+}
+
+/// Return the query listing the materialized views that roll up measurements with the given datum
+/// type, one name per line.
+pub(crate) fn select_rollup_views(
+    datum_type: DatumType,
+    resolution: RollupResolution,
+) -> String {
+    format!(
+        concat!(
+            "SELECT name FROM system.tables ",
+            "WHERE database = '{db}' AND engine = 'MaterializedView' ",
+            "AND startsWith(name, '{prefix}') ",
+            "FORMAT TabSeparated;",
+        ),
+        db = DATABASE_NAME,
+        prefix = rollup_view_prefix(datum_type, resolution),
+    )
+}
+
+/// Return the statement dropping a materialized view listed by [`select_rollup_views`].
+pub(crate) fn drop_rollup_view(view: &str, replicated: bool) -> String {
+    if replicated {
+        format!(
+            "DROP VIEW IF EXISTS {DATABASE_NAME}.{view} ON CLUSTER {CLUSTER_NAME};"
+        )
+    } else {
+        format!("DROP VIEW IF EXISTS {DATABASE_NAME}.{view};")
+    }
+}
+
+/// Return the windows, of a day each, in which to roll up existing measurements taken in the
+/// `days` before `cutoff`, most recent first.
+///
+/// Existing measurements are rolled up a window at a time, so that no one statement has to read
+/// all of them.
+pub(crate) fn backfill_windows(
+    cutoff: DateTime<Utc>,
+    days: u32,
+) -> impl Iterator<Item = (DateTime<Utc>, DateTime<Utc>)> {
+    (0..i64::from(days)).map(move |day| {
+        (
+            cutoff - chrono::Duration::days(day + 1),
+            cutoff - chrono::Duration::days(day),
+        )
+    })
+}
+
+/// Return the statement rolling up the existing measurements of the named timeseries, taken
+/// from `start` until `end`.
+///
+/// This is used when rollups are first maintained for a timeseries. Later measurements are rolled
+/// up by the materialized view as they're inserted.
+pub(crate) fn backfill_rollup(
+    datum_type: DatumType,
+    resolution: RollupResolution,
+    names: &BTreeSet<TimeseriesName>,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> String {
+    format!(
+        "INSERT INTO {db}.{table} {select};",
+        db = DATABASE_NAME,
+        table = rollup_table_name(datum_type, resolution),
+        select = rollup_select(
+            datum_type,
+            resolution,
+            &format!("{DATABASE_NAME}.{}", measurement_table_name(datum_type)),
+            &format!(
+                "{} AND timestamp >= '{}' AND timestamp < '{}'",
+                timeseries_names_condition(names),
+                start.format(crate::DATABASE_TIMESTAMP_FORMAT),
+                end.format(crate::DATABASE_TIMESTAMP_FORMAT)
+            ),
+        ),
+    )
+}
+
+/// Return the statement setting the TTL of the rows in a table.
+///
+/// The rows of each timeseries in `retention_days` are deleted after the given number of days,
+/// and those of any other timeseries after `default_days`.
+pub(crate) fn modify_ttl(
+    table: &str,
+    retention_days: &[(&TimeseriesName, u32)],
+    default_days: u32,
+    replicated: bool,
+) -> String {
+    let expiry =
+        |days: u32| format!("toDateTime(timestamp) + INTERVAL {days} DAY");
+    let mut rules = retention_days
+        .iter()
+        .map(|(name, days)| {
+            format!("{} DELETE WHERE timeseries_name = '{name}'", expiry(*days))
+        })
+        .collect::<Vec<_>>();
+    if rules.is_empty() {
+        rules.push(expiry(default_days));
+    } else {
+        rules.push(format!(
+            "{} DELETE WHERE NOT ({})",
+            expiry(default_days),
+            timeseries_names_condition(
+                retention_days.iter().map(|(name, _)| *name)
+            ),
+        ));
+    }
+    format!(
+        "ALTER TABLE {table} MODIFY TTL {rules};",
+        table = local_table(table, replicated),
+        rules = rules.join(", "),
+    )
+}
+
+/// Return the expressions selecting the measurements of a rollup table, in the same form as those
+/// of the corresponding measurement table.
+///
+/// The rows of the rollup table must be grouped by timeseries and window.
+pub(crate) fn rollup_measurement_columns(datum_type: DatumType) -> String {
+    let (ty, cumulative) =
+        rollup_datum_type(datum_type).expect("Datum type supports rollups");
+    if cumulative {
+        String::from(
+            "timeseries_name, timeseries_key, argMaxMerge(start_time) AS start_time, timestamp, argMaxMerge(datum) AS datum",
+        )
+    } else {
+        // The mean of a window is reported with the datum type of the timeseries, rounding it
+        // for integer types.
+        let mean = if ty.starts_with("Float") {
+            String::from("avgMerge(datum)")
+        } else {
+            String::from("round(avgMerge(datum))")
+        };
+        format!(
+            "timeseries_name, timeseries_key, timestamp, CAST({mean} AS {ty}) AS datum"
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn make_policy(
+        raw: u32,
+        hourly: Option<u32>,
+        daily: Option<u32>,
+    ) -> RetentionPolicy {
+        RetentionPolicy {
+            timeseries_name: TimeseriesName::try_from("foo:bar").unwrap(),
+            raw_retention_days: raw,
+            hourly_retention_days: hourly,
+            daily_retention_days: daily,
+        }
+    }
+
+    #[test]
+    fn test_resolution_for() {
+        let now = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        let days_ago = |days| now - chrono::Duration::days(days);
+        let policy = make_policy(7, Some(30), Some(365));
+        let resolution =
+            |days| policy.resolution_for(DatumType::F64, days_ago(days), now);
+        assert_eq!(resolution(1), None);
+        assert_eq!(resolution(7), None);
+        assert_eq!(resolution(8), Some(RollupResolution::Hourly));
+        assert_eq!(resolution(30), Some(RollupResolution::Hourly));
+        assert_eq!(resolution(31), Some(RollupResolution::Daily));
+        assert_eq!(resolution(1000), Some(RollupResolution::Daily));
+
+        // Histograms aren't rolled up.
+        assert_eq!(
+            policy.resolution_for(DatumType::HistogramF64, days_ago(31), now),
+            None
+        );
+
+        // Rollups kept for less time than the measurements themselves are never useful.
+        let policy = make_policy(7, Some(3), None);
+        assert_eq!(
+            policy.resolution_for(DatumType::F64, days_ago(8), now),
+            None
+        );
+    }
+
+    #[test]
+    fn test_validate_policies() {
+        assert!(validate_policies(&[make_policy(7, Some(30), None)]).is_ok());
+        assert!(validate_policies(&[make_policy(0, None, None)]).is_err());
+        assert!(validate_policies(&[make_policy(7, None, Some(0))]).is_err());
+        assert!(validate_policies(&[
+            make_policy(7, None, None),
+            make_policy(1, None, None)
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_supports_rollups() {
+        assert!(supports_rollups(DatumType::I64));
+        assert!(supports_rollups(DatumType::CumulativeF64));
+        assert!(!supports_rollups(DatumType::Bool));
+        assert!(!supports_rollups(DatumType::String));
+        assert!(!supports_rollups(DatumType::HistogramI64));
+        assert_eq!(rollup_datum_types().count(), 14);
+    }
+
+    #[test]
+    fn test_modify_ttl() {
+        let foo = TimeseriesName::try_from("foo:bar").unwrap();
+        let baz = TimeseriesName::try_from("baz:bar").unwrap();
+        assert_eq!(
+            modify_ttl("measurements_f64", &[], 30, false),
+            "ALTER TABLE oximeter.measurements_f64 MODIFY TTL toDateTime(timestamp) + INTERVAL 30 DAY;",
+        );
+        assert_eq!(
+            modify_ttl("measurements_f64", &[(&foo, 7), (&baz, 60)], 30, true),
+            concat!(
+                "ALTER TABLE oximeter.measurements_f64_local ON CLUSTER oximeter_cluster MODIFY TTL ",
+                "toDateTime(timestamp) + INTERVAL 7 DAY DELETE WHERE timeseries_name = 'foo:bar', ",
+                "toDateTime(timestamp) + INTERVAL 60 DAY DELETE WHERE timeseries_name = 'baz:bar', ",
+                "toDateTime(timestamp) + INTERVAL 30 DAY DELETE WHERE NOT (timeseries_name IN ('foo:bar', 'baz:bar'));",
+            ),
+        );
+    }
+
+    #[test]
+    fn test_create_rollup_view() {
+        let names = [TimeseriesName::try_from("foo:bar").unwrap()]
+            .into_iter()
+            .collect();
+        let created = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        assert_eq!(
+            create_rollup_view(
+                DatumType::CumulativeI64,
+                RollupResolution::Hourly,
+                &names,
+                created,
+                false
+            ),
+            concat!(
+                "CREATE MATERIALIZED VIEW IF NOT EXISTS oximeter.measurements_cumulativei64_hourly_mv_1700000000000000 ",
+                "TO oximeter.measurements_cumulativei64_hourly AS ",
+                "SELECT timeseries_name, timeseries_key, ",
+                "toDateTime64(toStartOfInterval(sample_timestamp, INTERVAL 1 HOUR), 9, 'UTC') AS timestamp, ",
+                "argMaxState(sample_start_time, sample_timestamp) AS start_time, ",
+                "argMaxState(sample_datum, sample_timestamp) AS datum ",
+                "FROM (",
+                "SELECT timeseries_name, timeseries_key, ",
+                "start_time AS sample_start_time, timestamp AS sample_timestamp, datum AS sample_datum ",
+                "FROM oximeter.measurements_cumulativei64 ",
+                "WHERE timeseries_name IN ('foo:bar')",
+                ") ",
+                "GROUP BY timeseries_name, timeseries_key, timestamp;",
+            ),
+        );
+        assert_eq!(
+            create_rollup_view(
+                DatumType::F64,
+                RollupResolution::Daily,
+                &names,
+                created,
+                true
+            ),
+            concat!(
+                "CREATE MATERIALIZED VIEW IF NOT EXISTS oximeter.measurements_f64_daily_mv_1700000000000000 ON CLUSTER oximeter_cluster ",
+                "TO oximeter.measurements_f64_daily_local AS ",
+                "SELECT timeseries_name, timeseries_key, ",
+                "toDateTime64(toStartOfInterval(sample_timestamp, INTERVAL 1 DAY), 9, 'UTC') AS timestamp, ",
+                "avgState(sample_datum) AS datum ",
+                "FROM (",
+                "SELECT timeseries_name, timeseries_key, timestamp AS sample_timestamp, datum AS sample_datum ",
+                "FROM oximeter.measurements_f64_local ",
+                "WHERE timeseries_name IN ('foo:bar')",
+                ") ",
+                "GROUP BY timeseries_name, timeseries_key, timestamp;",
+            ),
+        );
+    }
+
+    #[test]
+    fn test_backfill_windows() {
+        let cutoff = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        let day = chrono::Duration::days(1);
+        assert_eq!(
+            backfill_windows(cutoff, 2).collect::<Vec<_>>(),
+            [(cutoff - day, cutoff), (cutoff - day * 2, cutoff - day)]
+        );
+        assert_eq!(backfill_windows(cutoff, 0).count(), 0);
+    }
+}
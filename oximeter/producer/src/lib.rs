@@ -33,6 +33,11 @@ use std::net::SocketAddr;
 use thiserror::Error;
 use uuid::Uuid;
 
+mod push;
+
+pub use omicron_common::api::internal::nexus::MAX_PUSHED_SAMPLES;
+pub use push::PushClient;
+
 #[derive(Debug, Clone, Error)]
 pub enum Error {
     #[error("Error running producer HTTP server: {0}")]
@@ -40,6 +45,9 @@ pub enum Error {
 
     #[error("Error registering as metric producer: {0}")]
     RegistrationError(String),
+
+    #[error("Error pushing metric data: {0}")]
+    Push(String),
 }
 
 /// Either configuration for building a logger, or an actual logger already
@@ -72,7 +80,8 @@ pub struct Config {
 ///
 /// This is a "batteries-included" HTTP server, meant to be used in applications that don't
 /// otherwise run a server. The standalone functions [`register`] and [`collect`] can be used as
-/// part of an existing Dropshot server's API.
+/// part of an existing Dropshot server's API. Applications that can't be polled, such as
+/// short-lived processes, can push their metrics with a [`PushClient`] instead.
 pub struct Server {
     registry: ProducerRegistry,
     server: HttpServer<ProducerRegistry>,
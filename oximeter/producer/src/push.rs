@@ -0,0 +1,237 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Pushing metric data to an Oximeter collector.
+//!
+//! Most producers run a [`Server`](crate::Server), which the collector polls
+//! for data. That doesn't work for processes that exit before they'd be
+//! polled, or that can't run a server at all. Those can instead push their
+//! samples to a collector with a [`PushClient`]. As with polled producers,
+//! Nexus assigns each one to a collector, which refuses samples from producers
+//! it hasn't been assigned.
+
+use crate::Error;
+use omicron_common::api::internal::nexus::MAX_PUSHED_SAMPLES;
+use omicron_common::api::internal::nexus::PUSH_PRODUCER_LEASE_DURATION;
+use omicron_common::backoff;
+use oximeter::types::ProducerRegistry;
+use oximeter::types::ProducerResultsItem;
+use oximeter::types::Sample;
+use reqwest::StatusCode;
+use slog::debug;
+use slog::o;
+use slog::warn;
+use slog::Logger;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+use uuid::Uuid;
+
+// How long we keep retrying a request the collector can't handle yet, before
+// giving up.
+const MAX_RETRY_DURATION: Duration = Duration::from_secs(60);
+
+/// A client for pushing samples to an Oximeter collector.
+///
+/// The producer is registered with Nexus when the client is created, which
+/// assigns it a collector. Registrations expire after
+/// [`PUSH_PRODUCER_LEASE_DURATION`], so the client registers again when it
+/// pushes samples once half of that has passed. It also registers again if the
+/// collector forgets about it, for example because it restarted. Either way, it
+/// may be assigned a different collector.
+#[derive(Debug, Clone)]
+pub struct PushClient {
+    producer_id: Uuid,
+    nexus: nexus_client::Client,
+    assignment: Arc<Mutex<Assignment>>,
+    client: reqwest::Client,
+    log: Logger,
+}
+
+// The collector the producer is currently assigned.
+#[derive(Debug, Clone, Copy)]
+struct Assignment {
+    collector_address: SocketAddr,
+    // When to register again, before the registration expires.
+    renew_at: Instant,
+}
+
+impl PushClient {
+    /// Register `producer_id` with the Nexus instance at `nexus_address`,
+    /// returning a client for pushing samples to the collector it assigns.
+    pub async fn new(
+        producer_id: Uuid,
+        nexus_address: SocketAddr,
+        log: &Logger,
+    ) -> Result<Self, Error> {
+        let log = log.new(o!(
+            "component" => "metric-push-client",
+            "producer_id" => producer_id.to_string(),
+        ));
+        let nexus = nexus_client::Client::new(
+            &format!("http://{}", nexus_address),
+            log.clone(),
+        );
+        let assignment = backoff::retry_notify(
+            retry_policy(),
+            || async {
+                register(&nexus, producer_id)
+                    .await
+                    .map_err(backoff::BackoffError::transient)
+            },
+            |error, delay| {
+                warn!(
+                    log,
+                    "failed to register with nexus, will retry in {:?}", delay;
+                    "error" => %error,
+                );
+            },
+        )
+        .await?;
+        Ok(Self {
+            producer_id,
+            nexus,
+            assignment: Arc::new(Mutex::new(assignment)),
+            client: reqwest::Client::new(),
+            log,
+        })
+    }
+
+    /// Return the ID of the producer pushing samples.
+    pub fn producer_id(&self) -> Uuid {
+        self.producer_id
+    }
+
+    /// Push samples to the collector.
+    ///
+    /// Samples are sent in batches of at most [`MAX_PUSHED_SAMPLES`]. If the
+    /// collector is busy, each batch is retried for a while before giving up.
+    pub async fn push(&self, samples: &[Sample]) -> Result<(), Error> {
+        let renew_at = self.assignment.lock().unwrap().renew_at;
+        if Instant::now() >= renew_at {
+            debug!(self.log, "renewing registration with nexus");
+            self.register_again().await?;
+        }
+        for batch in samples.chunks(MAX_PUSHED_SAMPLES) {
+            backoff::retry_notify(
+                retry_policy(),
+                || self.push_batch(batch),
+                |error, delay| {
+                    warn!(
+                        self.log,
+                        "failed to push samples, will retry in {:?}", delay;
+                        "error" => %error,
+                    );
+                },
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Collect samples from all the producers in `registry`, and push them to
+    /// the collector.
+    pub async fn push_registry(
+        &self,
+        registry: &ProducerRegistry,
+    ) -> Result<(), Error> {
+        let mut samples = Vec::new();
+        for result in registry.collect() {
+            match result {
+                ProducerResultsItem::Ok(batch) => samples.extend(batch),
+                ProducerResultsItem::Err(e) => {
+                    warn!(self.log, "failed to produce samples: {}", e);
+                }
+            }
+        }
+        self.push(&samples).await
+    }
+
+    /// Return the address of the collector samples are pushed to.
+    pub fn collector_address(&self) -> SocketAddr {
+        self.assignment.lock().unwrap().collector_address
+    }
+
+    // Register with Nexus again, and push to whichever collector we're
+    // assigned from now on.
+    async fn register_again(&self) -> Result<(), Error> {
+        let assignment = register(&self.nexus, self.producer_id).await?;
+        *self.assignment.lock().unwrap() = assignment;
+        Ok(())
+    }
+
+    async fn push_batch(
+        &self,
+        samples: &[Sample],
+    ) -> Result<(), backoff::BackoffError<Error>> {
+        let url = format!(
+            "http://{}/producers/{}/samples",
+            self.collector_address(),
+            self.producer_id
+        );
+        let response =
+            self.client.post(url).json(samples).send().await.map_err(|e| {
+                backoff::BackoffError::transient(Error::Push(e.to_string()))
+            })?;
+        match response.status() {
+            status if status.is_success() => {
+                debug!(self.log, "pushed {} samples", samples.len());
+                Ok(())
+            }
+            // The collector is falling behind, back off before trying again.
+            StatusCode::SERVICE_UNAVAILABLE => {
+                Err(backoff::BackoffError::transient(Error::Push(
+                    String::from("collector is busy"),
+                )))
+            }
+            // The collector doesn't know about us, probably because it
+            // restarted. Register with Nexus again, and retry the batch with
+            // whichever collector we're assigned.
+            StatusCode::NOT_FOUND => {
+                self.register_again()
+                    .await
+                    .map_err(backoff::BackoffError::transient)?;
+                Err(backoff::BackoffError::transient(Error::Push(
+                    String::from("producer was not registered with collector"),
+                )))
+            }
+            status => Err(backoff::BackoffError::permanent(Error::Push(
+                format!("collector rejected samples with status {}", status),
+            ))),
+        }
+    }
+}
+
+// Register the producer with Nexus, returning the collector it's assigned.
+async fn register(
+    nexus: &nexus_client::Client,
+    producer_id: Uuid,
+) -> Result<Assignment, Error> {
+    let renew_at = Instant::now() + PUSH_PRODUCER_LEASE_DURATION / 2;
+    let assignment = nexus
+        .cpapi_push_producers_post(&nexus_client::types::PushProducerInfo {
+            id: producer_id,
+        })
+        .await
+        .map_err(|e| Error::RegistrationError(e.to_string()))?
+        .into_inner();
+    let collector_address =
+        assignment.collector_address.parse().map_err(|e| {
+            Error::RegistrationError(format!(
+                "invalid collector address {:?}: {}",
+                assignment.collector_address, e
+            ))
+        })?;
+    Ok(Assignment { collector_address, renew_at })
+}
+
+fn retry_policy() -> backoff::ExponentialBackoff {
+    backoff::ExponentialBackoffBuilder::new()
+        .with_initial_interval(Duration::from_millis(100))
+        .with_max_interval(Duration::from_secs(10))
+        .with_max_elapsed_time(Some(MAX_RETRY_DURATION))
+        .build()
+}
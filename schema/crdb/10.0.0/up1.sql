@@ -0,0 +1,8 @@
+ALTER TABLE omicron.public.external_ip
+    ADD COLUMN IF NOT EXISTS project_id UUID,
+
+    -- Only floating IPs for instances belong to a project.
+    ADD CONSTRAINT IF NOT EXISTS null_project_id CHECK (
+        (kind = 'floating' AND is_service = FALSE AND project_id IS NOT NULL) OR
+        ((kind != 'floating' OR is_service = TRUE) AND project_id IS NULL)
+    );
@@ -0,0 +1,5 @@
+CREATE UNIQUE INDEX IF NOT EXISTS lookup_floating_ip_by_name_and_project ON omicron.public.external_ip (
+    project_id,
+    name
+) WHERE
+    project_id IS NOT NULL AND time_deleted IS NULL;
@@ -0,0 +1,4 @@
+CREATE TYPE IF NOT EXISTS omicron.public.affinity_group_kind AS ENUM (
+  'affinity',
+  'anti_affinity'
+);
@@ -0,0 +1,4 @@
+CREATE TYPE IF NOT EXISTS omicron.public.affinity_policy AS ENUM (
+  'fail',
+  'allow'
+);
@@ -0,0 +1 @@
+ALTER TABLE omicron.public.audit_log ALTER COLUMN actor_id DROP NOT NULL;
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS omicron.public.metric_push_producer (
+    id UUID PRIMARY KEY,
+    time_created TIMESTAMPTZ NOT NULL,
+    time_modified TIMESTAMPTZ NOT NULL,
+    oximeter_id UUID NOT NULL,
+    time_expires TIMESTAMPTZ NOT NULL
+);
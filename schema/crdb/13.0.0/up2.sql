@@ -0,0 +1,4 @@
+CREATE UNIQUE INDEX IF NOT EXISTS lookup_push_producer_by_oximeter ON omicron.public.metric_push_producer (
+    oximeter_id,
+    id
+);
@@ -0,0 +1,3 @@
+CREATE INDEX IF NOT EXISTS lookup_push_producer_by_expiration ON omicron.public.metric_push_producer (
+    time_expires
+);
@@ -0,0 +1,20 @@
+CREATE TABLE IF NOT EXISTS omicron.public.virtual_provisioning_quota (
+    -- Should match the UUID of the corresponding collection.
+    id UUID PRIMARY KEY,
+    time_modified TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    -- Identifies the type of the collection.
+    collection_type STRING(63) NOT NULL,
+
+    -- The maximum amount of physical disk space which may be provisioned
+    -- on behalf of the collection. NULL means "no limit".
+    virtual_disk_bytes_limit INT8,
+
+    -- The maximum number of CPUs which may be provisioned by VMs.
+    -- NULL means "no limit".
+    cpus_limit INT8,
+
+    -- The maximum amount of RAM which may be provisioned by VMs.
+    -- NULL means "no limit".
+    ram_limit INT8
+);
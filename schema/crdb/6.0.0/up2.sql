@@ -0,0 +1 @@
+ALTER TABLE omicron.public.device_access_token ADD COLUMN IF NOT EXISTS description STRING(512) NOT NULL DEFAULT '';
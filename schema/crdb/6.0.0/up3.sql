@@ -0,0 +1,3 @@
+CREATE UNIQUE INDEX IF NOT EXISTS lookup_device_access_token_by_id ON omicron.public.device_access_token (
+    id
+);
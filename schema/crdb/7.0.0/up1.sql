@@ -0,0 +1 @@
+ALTER TYPE omicron.public.authentication_mode ADD VALUE IF NOT EXISTS 'oidc';
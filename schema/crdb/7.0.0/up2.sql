@@ -0,0 +1 @@
+ALTER TYPE omicron.public.provider_type ADD VALUE IF NOT EXISTS 'oidc';
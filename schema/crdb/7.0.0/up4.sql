@@ -0,0 +1,5 @@
+CREATE UNIQUE INDEX IF NOT EXISTS lookup_oidc_idp_by_silo_id ON omicron.public.oidc_identity_provider (
+    silo_id,
+    id
+) WHERE
+    time_deleted IS NULL;
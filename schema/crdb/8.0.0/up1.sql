@@ -0,0 +1 @@
+ALTER TABLE omicron.public.silo_user ADD COLUMN IF NOT EXISTS active BOOL NOT NULL DEFAULT TRUE;
@@ -0,0 +1,3 @@
+CREATE UNIQUE INDEX IF NOT EXISTS lookup_scim_client_bearer_token_by_token ON omicron.public.scim_client_bearer_token (
+    bearer_token
+);
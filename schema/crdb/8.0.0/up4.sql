@@ -0,0 +1,3 @@
+CREATE INDEX IF NOT EXISTS lookup_scim_client_bearer_token_by_silo ON omicron.public.scim_client_bearer_token (
+    silo_id
+);
@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS omicron.public.audit_log (
+    id UUID PRIMARY KEY,
+    time_started TIMESTAMPTZ NOT NULL,
+
+    request_id STRING NOT NULL,
+    operation_id STRING NOT NULL,
+    http_method STRING(16) NOT NULL,
+    request_uri STRING NOT NULL,
+
+    actor_id UUID NOT NULL,
+    actor_silo_id UUID,
+
+    time_completed TIMESTAMPTZ,
+    http_status_code INT4
+);
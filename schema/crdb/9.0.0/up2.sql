@@ -0,0 +1,4 @@
+CREATE UNIQUE INDEX IF NOT EXISTS lookup_audit_log_by_time_started ON omicron.public.audit_log (
+    time_started,
+    id
+);
@@ -367,6 +367,33 @@ CREATE TABLE IF NOT EXISTS omicron.public.virtual_provisioning_resource (
     ram_provisioned INT8 NOT NULL
 );
 
+-- Operator-supplied limits on the resources which may be provisioned within
+-- a collection (project or silo).
+--
+-- Collections without a row in this table are unlimited. Limits are checked
+-- in the same CTE which updates 'virtual_provisioning_collection', so that
+-- a provisioning request cannot exceed them concurrently with another.
+CREATE TABLE IF NOT EXISTS omicron.public.virtual_provisioning_quota (
+    -- Should match the UUID of the corresponding collection.
+    id UUID PRIMARY KEY,
+    time_modified TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    -- Identifies the type of the collection.
+    collection_type STRING(63) NOT NULL,
+
+    -- The maximum amount of physical disk space which may be provisioned
+    -- on behalf of the collection. NULL means "no limit".
+    virtual_disk_bytes_limit INT8,
+
+    -- The maximum number of CPUs which may be provisioned by VMs.
+    -- NULL means "no limit".
+    cpus_limit INT8,
+
+    -- The maximum amount of RAM which may be provisioned by VMs.
+    -- NULL means "no limit".
+    ram_limit INT8
+);
+
 /*
  * ZPools of Storage, attached to Sleds.
  * These are backed by a single physical disk.
@@ -552,7 +579,8 @@ CREATE INDEX IF NOT EXISTS lookup_volume_by_deleted on omicron.public.volume (
 
 CREATE TYPE IF NOT EXISTS omicron.public.authentication_mode AS ENUM (
   'local',
-  'saml'
+  'saml',
+  'oidc'
 );
 
 CREATE TYPE IF NOT EXISTS omicron.public.user_provision_type AS ENUM (
@@ -594,7 +622,14 @@ CREATE TABLE IF NOT EXISTS omicron.public.silo_user (
     time_deleted TIMESTAMPTZ,
 
     silo_id UUID NOT NULL,
-    external_id TEXT NOT NULL
+    external_id TEXT NOT NULL,
+
+    /*
+     * Inactive users cannot log in.  Users are deactivated by their identity
+     * provider (e.g., via SCIM) rather than by deleting them, so that they can
+     * later be reactivated without losing their role assignments.
+     */
+    active BOOL NOT NULL DEFAULT TRUE
 );
 
 /* This index lets us quickly find users for a given silo. */
@@ -658,7 +693,8 @@ CREATE INDEX IF NOT EXISTS lookup_silo_group_by_user ON omicron.public.silo_grou
  */
 
 CREATE TYPE IF NOT EXISTS omicron.public.provider_type AS ENUM (
-  'saml'
+  'saml',
+  'oidc'
 );
 
 CREATE TABLE IF NOT EXISTS omicron.public.identity_provider (
@@ -726,6 +762,45 @@ CREATE UNIQUE INDEX IF NOT EXISTS lookup_saml_idp_by_silo_name ON omicron.public
 ) WHERE
     time_deleted IS NULL;
 
+/*
+ * Silo OpenID Connect identity provider
+ */
+CREATE TABLE IF NOT EXISTS omicron.public.oidc_identity_provider (
+    /* Identity metadata */
+    id UUID PRIMARY KEY,
+    name STRING(63) NOT NULL,
+    description STRING(512) NOT NULL,
+    time_created TIMESTAMPTZ NOT NULL,
+    time_modified TIMESTAMPTZ NOT NULL,
+    time_deleted TIMESTAMPTZ,
+
+    silo_id UUID NOT NULL,
+
+    issuer TEXT NOT NULL,
+    authorization_endpoint TEXT NOT NULL,
+    token_endpoint TEXT NOT NULL,
+    jwks_uri TEXT NOT NULL,
+
+    client_id TEXT NOT NULL,
+    client_secret TEXT NOT NULL,
+    redirect_uri TEXT NOT NULL,
+    scopes TEXT NOT NULL,
+
+    group_claim_name TEXT
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS lookup_oidc_idp_by_silo_id ON omicron.public.oidc_identity_provider (
+    silo_id,
+    id
+) WHERE
+    time_deleted IS NULL;
+
+CREATE UNIQUE INDEX IF NOT EXISTS lookup_oidc_idp_by_silo_name ON omicron.public.oidc_identity_provider (
+    silo_id,
+    name
+) WHERE
+    time_deleted IS NULL;
+
 /*
  * Users' public SSH keys, per RFD 44
  */
@@ -907,6 +982,61 @@ WHERE
     instance.time_deleted IS NULL;
 
 
+/*
+ * Affinity groups, which constrain the sleds that their member instances
+ * are placed on.
+ */
+
+CREATE TYPE IF NOT EXISTS omicron.public.affinity_group_kind AS ENUM (
+  'affinity',
+  'anti_affinity'
+);
+
+/* Whether placement fails, or falls back, when a group's rule can't be met. */
+CREATE TYPE IF NOT EXISTS omicron.public.affinity_policy AS ENUM (
+  'fail',
+  'allow'
+);
+
+CREATE TABLE IF NOT EXISTS omicron.public.affinity_group (
+    /* Identity metadata (resource) */
+    id UUID PRIMARY KEY,
+    name STRING(63) NOT NULL,
+    description STRING(512) NOT NULL,
+    time_created TIMESTAMPTZ NOT NULL,
+    time_modified TIMESTAMPTZ NOT NULL,
+    /* Indicates that the object has been deleted */
+    time_deleted TIMESTAMPTZ,
+
+    /* Every affinity group is in exactly one Project at a time. */
+    project_id UUID NOT NULL,
+
+    kind omicron.public.affinity_group_kind NOT NULL,
+    policy omicron.public.affinity_policy NOT NULL
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS lookup_affinity_group_by_project ON omicron.public.affinity_group (
+    project_id,
+    name
+) WHERE
+    time_deleted IS NULL;
+
+/*
+ * The instances in each affinity group. Rows are removed when either the
+ * group or the instance is deleted.
+ */
+CREATE TABLE IF NOT EXISTS omicron.public.affinity_group_instance_membership (
+    group_id UUID NOT NULL,
+    instance_id UUID NOT NULL,
+
+    PRIMARY KEY (group_id, instance_id)
+);
+
+-- Allow looking up the groups that an instance belongs to
+CREATE INDEX IF NOT EXISTS lookup_affinity_group_instance_membership_by_instance ON omicron.public.affinity_group_instance_membership (
+    instance_id
+);
+
 /*
  * Guest-Visible, Virtual Disks
  */
@@ -1153,6 +1283,32 @@ CREATE UNIQUE INDEX IF NOT EXISTS lookup_producer_by_oximeter ON omicron.public.
     id
 );
 
+/*
+ * Information about registered metric producers that push their samples to a
+ * collector, rather than being polled by it.
+ */
+CREATE TABLE IF NOT EXISTS omicron.public.metric_push_producer (
+    id UUID PRIMARY KEY,
+    time_created TIMESTAMPTZ NOT NULL,
+    time_modified TIMESTAMPTZ NOT NULL,
+    /* Oximeter collector instance to which this metric producer is assigned. */
+    oximeter_id UUID NOT NULL,
+    /*
+     * When the producer's registration runs out, unless it registers again.
+     * Expired producers are deleted by a background task in Nexus.
+     */
+    time_expires TIMESTAMPTZ NOT NULL
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS lookup_push_producer_by_oximeter ON omicron.public.metric_push_producer (
+    oximeter_id,
+    id
+);
+
+CREATE INDEX IF NOT EXISTS lookup_push_producer_by_expiration ON omicron.public.metric_push_producer (
+    time_expires
+);
+
 /*
  * VPCs and networking primitives
  */
@@ -1613,6 +1769,9 @@ CREATE TABLE IF NOT EXISTS omicron.public.external_ip (
     /* The last port in the allowed range, also inclusive. */
     last_port INT4 NOT NULL,
 
+    /* FK to the `project` table, for floating IPs owned by a project. */
+    project_id UUID,
+
     /* The name must be non-NULL iff this is a floating IP. */
     CONSTRAINT null_fip_name CHECK (
         (kind != 'floating' AND name IS NULL) OR
@@ -1636,6 +1795,12 @@ CREATE TABLE IF NOT EXISTS omicron.public.external_ip (
     /* Ephemeral IPs are not supported for services. */
     CONSTRAINT ephemeral_kind_service CHECK (
         (kind = 'ephemeral' AND is_service = FALSE) OR (kind != 'ephemeral')
+    ),
+
+    /* Only floating IPs for instances belong to a project. */
+    CONSTRAINT null_project_id CHECK (
+        (kind = 'floating' AND is_service = FALSE AND project_id IS NOT NULL) OR
+        ((kind != 'floating' OR is_service = TRUE) AND project_id IS NULL)
     )
 );
 
@@ -1668,6 +1833,37 @@ CREATE UNIQUE INDEX IF NOT EXISTS lookup_external_ip_by_parent ON omicron.public
 )
     WHERE parent_id IS NOT NULL AND time_deleted IS NULL;
 
+/* Index for looking up floating IPs by name within a project */
+CREATE UNIQUE INDEX IF NOT EXISTS lookup_floating_ip_by_name_and_project ON omicron.public.external_ip (
+    project_id,
+    name
+) WHERE
+    project_id IS NOT NULL AND time_deleted IS NULL;
+
+/*
+ * Floating IPs are the named, project-scoped subset of external IPs, which may
+ * be attached to and detached from instances.
+ */
+CREATE VIEW IF NOT EXISTS omicron.public.floating_ip AS
+SELECT
+    id,
+    name,
+    description,
+    time_created,
+    time_modified,
+    time_deleted,
+    ip_pool_id,
+    ip_pool_range_id,
+    is_service,
+    parent_id,
+    ip,
+    project_id
+FROM
+    omicron.public.external_ip
+WHERE
+    omicron.public.external_ip.kind = 'floating' AND
+    project_id IS NOT NULL;
+
 /*******************************************************************/
 
 /*
@@ -2139,7 +2335,11 @@ CREATE TABLE IF NOT EXISTS omicron.public.device_access_token (
     silo_user_id UUID NOT NULL,
     time_requested TIMESTAMPTZ NOT NULL,
     time_created TIMESTAMPTZ NOT NULL,
-    time_expires TIMESTAMPTZ
+    time_expires TIMESTAMPTZ,
+    -- Identifies the token without revealing it, so that it may be listed and
+    -- revoked.
+    id UUID NOT NULL DEFAULT gen_random_uuid(),
+    description STRING(512) NOT NULL DEFAULT ''
 );
 
 -- This UNIQUE constraint is critical for ensuring that at most
@@ -2153,6 +2353,32 @@ CREATE INDEX IF NOT EXISTS lookup_device_access_token_by_silo_user ON omicron.pu
     silo_user_id
 );
 
+CREATE UNIQUE INDEX IF NOT EXISTS lookup_device_access_token_by_id ON omicron.public.device_access_token (
+    id
+);
+
+/*
+ * Bearer tokens used by an identity provider's SCIM client to provision the
+ * users and groups of a Silo
+ */
+CREATE TABLE IF NOT EXISTS omicron.public.scim_client_bearer_token (
+    id UUID PRIMARY KEY,
+    time_created TIMESTAMPTZ NOT NULL,
+
+    silo_id UUID NOT NULL,
+    bearer_token STRING(40) NOT NULL
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS lookup_scim_client_bearer_token_by_token ON omicron.public.scim_client_bearer_token (
+    bearer_token
+);
+
+-- This index is used to list a Silo's tokens and to remove them when the Silo
+-- is deleted.
+CREATE INDEX IF NOT EXISTS lookup_scim_client_bearer_token_by_silo ON omicron.public.scim_client_bearer_token (
+    silo_id
+);
+
 /*
  * Roles built into the system
  *
@@ -2529,6 +2755,42 @@ CREATE TABLE IF NOT EXISTS omicron.public.switch_port_settings_address_config (
 );
 
 
+/*
+ * Audit log of requests to the external API that may modify state
+ *
+ * An entry is written when the request's actor has been authenticated, before
+ * the operation is carried out, and is completed with the result of the
+ * request.  Entries that are never completed represent requests that were
+ * interrupted, e.g., because Nexus crashed.
+ */
+CREATE TABLE IF NOT EXISTS omicron.public.audit_log (
+    id UUID PRIMARY KEY,
+    time_started TIMESTAMPTZ NOT NULL,
+
+    request_id STRING NOT NULL,
+    operation_id STRING NOT NULL,
+    http_method STRING(16) NOT NULL,
+    request_uri STRING NOT NULL,
+
+    /*
+     * The authenticated user, and their Silo (unless it is a built-in user).
+     * There is no user for requests which failed to authenticate, nor for
+     * requests made by a Silo's SCIM client, which only has a Silo.
+     */
+    actor_id UUID,
+    actor_silo_id UUID,
+
+    /* These are set when the request completes */
+    time_completed TIMESTAMPTZ,
+    http_status_code INT4
+);
+
+/* This index is used to list the audit log by time. */
+CREATE UNIQUE INDEX IF NOT EXISTS lookup_audit_log_by_time_started ON omicron.public.audit_log (
+    time_started,
+    id
+);
+
 /*******************************************************************/
 
 /*
@@ -2562,7 +2824,7 @@ INSERT INTO omicron.public.db_metadata (
     version,
     target_version
 ) VALUES
-    ( TRUE, NOW(), NOW(), '4.0.0', NULL)
+    ( TRUE, NOW(), NOW(), '13.0.0', NULL)
 ON CONFLICT DO NOTHING;
 
 COMMIT;
@@ -131,6 +131,7 @@ fn d2n_zone(
             .iter()
             .map(|(n, r)| (n.clone(), r.iter().map(d2n_record).collect()))
             .collect(),
+        nameservers: zone.nameservers.clone(),
     }
 }
 
@@ -152,5 +153,14 @@ fn d2n_record(
                 weight: srv.weight,
             })
         }
+        dns_service_client::types::DnsRecord::Cname(target) => {
+            nexus_client::types::DnsRecord::Cname(target.clone())
+        }
+        dns_service_client::types::DnsRecord::Txt(strings) => {
+            nexus_client::types::DnsRecord::Txt(strings.clone())
+        }
+        dns_service_client::types::DnsRecord::Ptr(target) => {
+            nexus_client::types::DnsRecord::Ptr(target.clone())
+        }
     }
 }
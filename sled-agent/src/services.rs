@@ -1545,6 +1545,7 @@ impl ServiceManager {
                         "config/dns_address",
                         dns_address.to_string(),
                     )?;
+                    smfh.setprop("config/metrics_id", request.zone.id)?;
 
                     // Refresh the manifest with the new properties we set, so
                     // they become "effective" properties when the service is
@@ -10,22 +10,234 @@ use crate::common::instance::{ObservedPropolisState, PublishedInstanceState};
 use crate::nexus::NexusClient;
 use crate::params::{InstanceMigrationSourceParams, InstanceStateRequested};
 use async_trait::async_trait;
+use dropshot::ConfigDropshot;
+use dropshot::ConfigLogging;
+use dropshot::ConfigLoggingLevel;
 use nexus_client;
 use omicron_common::api::external::Error;
 use omicron_common::api::external::Generation;
 use omicron_common::api::external::InstanceState as ApiInstanceState;
 use omicron_common::api::external::ResourceType;
 use omicron_common::api::internal::nexus::InstanceRuntimeState;
+use omicron_common::api::internal::nexus::ProducerEndpoint;
+use oximeter_producer::LogConfig;
+use oximeter_producer::Server as ProducerServer;
 use propolis_client::api::InstanceMigrateStatusResponse as PropolisMigrateStatus;
 use propolis_client::api::InstanceState as PropolisInstanceState;
 use propolis_client::api::InstanceStateMonitorResponse;
 use std::collections::VecDeque;
+use std::net::{Ipv6Addr, SocketAddr};
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::Duration;
 use uuid::Uuid;
 
 use crate::common::instance::{Action as InstanceAction, InstanceStates};
 
+// These stand in for the instance metrics Propolis is expected to report, and
+// Nexus maps its instance metric names to the timeseries produced here (see
+// `instance_metric_timeseries` in Nexus's metrics module).
+//
+// As with disks, the timeseries names are derived from these struct names, so
+// they live in their own module.
+mod producers {
+    use super::*;
+    use oximeter::{
+        types::{Cumulative, Sample},
+        Metric, Target,
+    };
+
+    #[derive(Debug, Clone, Target)]
+    pub struct VirtualMachine {
+        pub instance_id: Uuid,
+    }
+
+    #[derive(Debug, Default, Copy, Clone, Metric)]
+    struct VcpuUsage {
+        vcpu_id: i64,
+        /// Nanoseconds this vCPU has spent running guest code
+        #[datum]
+        busy_ns: Cumulative<i64>,
+    }
+
+    #[derive(Debug, Default, Copy, Clone, Metric)]
+    struct NicPacketsReceived {
+        nic_id: Uuid,
+        #[datum]
+        count: Cumulative<i64>,
+    }
+    #[derive(Debug, Default, Copy, Clone, Metric)]
+    struct NicPacketsSent {
+        nic_id: Uuid,
+        #[datum]
+        count: Cumulative<i64>,
+    }
+    #[derive(Debug, Default, Copy, Clone, Metric)]
+    struct NicBytesReceived {
+        nic_id: Uuid,
+        #[datum]
+        count: Cumulative<i64>,
+    }
+    #[derive(Debug, Default, Copy, Clone, Metric)]
+    struct NicBytesSent {
+        nic_id: Uuid,
+        #[datum]
+        count: Cumulative<i64>,
+    }
+
+    #[derive(Debug, Default, Copy, Clone, Metric)]
+    struct DiskRead {
+        disk_id: Uuid,
+        #[datum]
+        count: Cumulative<i64>,
+    }
+    #[derive(Debug, Default, Copy, Clone, Metric)]
+    struct DiskReadBytes {
+        disk_id: Uuid,
+        #[datum]
+        count: Cumulative<i64>,
+    }
+    #[derive(Debug, Default, Copy, Clone, Metric)]
+    struct DiskWrite {
+        disk_id: Uuid,
+        #[datum]
+        count: Cumulative<i64>,
+    }
+    #[derive(Debug, Default, Copy, Clone, Metric)]
+    struct DiskWriteBytes {
+        disk_id: Uuid,
+        #[datum]
+        count: Cumulative<i64>,
+    }
+
+    #[derive(Debug, Clone)]
+    struct NicCounters {
+        packets_received: NicPacketsReceived,
+        packets_sent: NicPacketsSent,
+        bytes_received: NicBytesReceived,
+        bytes_sent: NicBytesSent,
+    }
+
+    #[derive(Debug, Clone)]
+    struct DiskCounters {
+        read: DiskRead,
+        read_bytes: DiskReadBytes,
+        write: DiskWrite,
+        write_bytes: DiskWriteBytes,
+    }
+
+    /// Produces synthetic samples for an instance's vCPUs, NICs, and disks.
+    ///
+    /// Each collection makes every counter advance by a fixed amount, so tests
+    /// can rely on the values increasing without depending on their rate.
+    #[derive(Debug, Clone)]
+    pub struct InstanceProducer {
+        target: VirtualMachine,
+        vcpus: Vec<VcpuUsage>,
+        nics: Vec<NicCounters>,
+        disks: Vec<DiskCounters>,
+    }
+
+    impl InstanceProducer {
+        pub fn new(args: &InstanceProducerArgs) -> Self {
+            Self {
+                target: VirtualMachine { instance_id: args.instance_id },
+                vcpus: (0..i64::from(args.ncpus))
+                    .map(|vcpu_id| VcpuUsage {
+                        vcpu_id,
+                        busy_ns: Default::default(),
+                    })
+                    .collect(),
+                nics: args
+                    .nic_ids
+                    .iter()
+                    .map(|&nic_id| NicCounters {
+                        packets_received: NicPacketsReceived {
+                            nic_id,
+                            count: Default::default(),
+                        },
+                        packets_sent: NicPacketsSent {
+                            nic_id,
+                            count: Default::default(),
+                        },
+                        bytes_received: NicBytesReceived {
+                            nic_id,
+                            count: Default::default(),
+                        },
+                        bytes_sent: NicBytesSent {
+                            nic_id,
+                            count: Default::default(),
+                        },
+                    })
+                    .collect(),
+                disks: args
+                    .disk_ids
+                    .iter()
+                    .map(|&disk_id| DiskCounters {
+                        read: DiskRead { disk_id, count: Default::default() },
+                        read_bytes: DiskReadBytes {
+                            disk_id,
+                            count: Default::default(),
+                        },
+                        write: DiskWrite { disk_id, count: Default::default() },
+                        write_bytes: DiskWriteBytes {
+                            disk_id,
+                            count: Default::default(),
+                        },
+                    })
+                    .collect(),
+            }
+        }
+    }
+
+    impl oximeter::Producer for InstanceProducer {
+        fn produce(
+            &mut self,
+        ) -> Result<
+            Box<(dyn Iterator<Item = Sample> + 'static)>,
+            oximeter::MetricsError,
+        > {
+            let mut samples = Vec::new();
+            for vcpu in self.vcpus.iter_mut() {
+                samples.push(Sample::new(&self.target, vcpu)?);
+                *vcpu.datum_mut() += 100_000_000;
+            }
+            for nic in self.nics.iter_mut() {
+                samples.push(Sample::new(&self.target, &nic.packets_received)?);
+                samples.push(Sample::new(&self.target, &nic.packets_sent)?);
+                samples.push(Sample::new(&self.target, &nic.bytes_received)?);
+                samples.push(Sample::new(&self.target, &nic.bytes_sent)?);
+                *nic.packets_received.datum_mut() += 1;
+                *nic.packets_sent.datum_mut() += 1;
+                *nic.bytes_received.datum_mut() += 1500;
+                *nic.bytes_sent.datum_mut() += 1500;
+            }
+            for disk in self.disks.iter_mut() {
+                samples.push(Sample::new(&self.target, &disk.read)?);
+                samples.push(Sample::new(&self.target, &disk.read_bytes)?);
+                samples.push(Sample::new(&self.target, &disk.write)?);
+                samples.push(Sample::new(&self.target, &disk.write_bytes)?);
+                *disk.read.datum_mut() += 1;
+                *disk.read_bytes.datum_mut() += 4096;
+                *disk.write.datum_mut() += 1;
+                *disk.write_bytes.datum_mut() += 4096;
+            }
+            Ok(Box::new(samples.into_iter()))
+        }
+    }
+}
+
+/// The virtual hardware of a simulated instance, used to decide which
+/// timeseries its metric producer reports.
+#[derive(Clone, Debug)]
+pub struct InstanceProducerArgs {
+    pub nexus_address: SocketAddr,
+    pub instance_id: Uuid,
+    pub ncpus: u16,
+    pub nic_ids: Vec<Uuid>,
+    pub disk_ids: Vec<Uuid>,
+}
+
 #[derive(Clone, Debug)]
 enum MonitorChange {
     InstanceState(PropolisInstanceState),
@@ -363,12 +575,62 @@ impl SimInstanceInner {
 /// sled agent proper can ask the instance collection for a clone of a specific
 /// registered instance and get back a reference to the same instance the
 /// `SimCollection` APIs will operate on.
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct SimInstance {
     inner: Arc<Mutex<SimInstanceInner>>,
+    producer: Arc<Mutex<Option<ProducerServer>>>,
+}
+
+// "producer" doesn't implement Debug, so we can't derive it on SimInstance.
+impl std::fmt::Debug for SimInstance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SimInstance").field("inner", &self.inner).finish()
+    }
 }
 
 impl SimInstance {
+    async fn start_producer_server(
+        &self,
+        args: InstanceProducerArgs,
+    ) -> Result<(), String> {
+        // Instances may be registered more than once; keep reporting through
+        // the server we already have.
+        if self.producer.lock().unwrap().is_some() {
+            return Ok(());
+        }
+
+        // Like simulated disks, each instance gets its own producer server
+        // listening on any available port.
+        let producer_address = SocketAddr::new(Ipv6Addr::LOCALHOST.into(), 0);
+        let server_info = ProducerEndpoint {
+            id: args.instance_id,
+            address: producer_address,
+            base_route: "/collect".to_string(),
+            interval: Duration::from_millis(200),
+        };
+        let config = oximeter_producer::Config {
+            server_info,
+            registration_address: args.nexus_address,
+            dropshot: ConfigDropshot {
+                bind_address: producer_address,
+                ..Default::default()
+            },
+            log: LogConfig::Config(ConfigLogging::StderrTerminal {
+                level: ConfigLoggingLevel::Error,
+            }),
+        };
+        let server =
+            ProducerServer::start(&config).await.map_err(|e| e.to_string())?;
+
+        let producer = producers::InstanceProducer::new(&args);
+        server
+            .registry()
+            .register_producer(producer)
+            .map_err(|e| e.to_string())?;
+        self.producer.lock().unwrap().replace(server);
+        Ok(())
+    }
+
     pub fn terminate(&self) -> InstanceRuntimeState {
         self.inner.lock().unwrap().terminate()
     }
@@ -387,7 +649,7 @@ impl SimInstance {
 impl Simulatable for SimInstance {
     type CurrentState = InstanceRuntimeState;
     type RequestedState = InstanceStateRequested;
-    type ProducerArgs = ();
+    type ProducerArgs = InstanceProducerArgs;
     type Action = InstanceAction;
 
     fn new(current: InstanceRuntimeState) -> Self {
@@ -402,14 +664,17 @@ impl Simulatable for SimInstance {
                 queue: VecDeque::new(),
                 destroyed: false,
             })),
+            producer: Arc::new(Mutex::new(None)),
         }
     }
 
     async fn set_producer(
         &mut self,
-        _args: Self::ProducerArgs,
+        args: Self::ProducerArgs,
     ) -> Result<(), Error> {
-        // NOTE: Not implemented, yet.
+        self.start_producer_server(args).await.map_err(|e| {
+            Error::internal_error(&format!("Setting producer server: {e}"))
+        })?;
         Ok(())
     }
 
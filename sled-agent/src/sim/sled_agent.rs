@@ -7,7 +7,7 @@
 use super::collection::{PokeMode, SimCollection};
 use super::config::Config;
 use super::disk::SimDisk;
-use super::instance::SimInstance;
+use super::instance::{InstanceProducerArgs, SimInstance};
 use super::storage::CrucibleData;
 use super::storage::Storage;
 
@@ -211,6 +211,31 @@ impl SledAgent {
         Ok(())
     }
 
+    /// Returns an error unless every region targeted by the sub-volumes of
+    /// `volume_construction_request` exists in simulated storage.
+    pub async fn check_volume_regions_exist(
+        &self,
+        volume_construction_request: &VolumeConstructionRequest,
+    ) -> Result<(), Error> {
+        let mut targets = Vec::new();
+        extract_targets_from_volume_construction_request(
+            &mut targets,
+            volume_construction_request,
+        );
+
+        let storage = self.storage.lock().await;
+        for target in targets {
+            if storage.get_dataset_for_port(target.port()).await.is_none() {
+                return Err(Error::internal_error(&format!(
+                    "no region for port {}",
+                    target.port()
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
     /// Idempotently ensures that the given API Instance (described by
     /// `api_instance`) exists on this server in the given runtime state
     /// (described by `target`).
@@ -228,6 +253,7 @@ impl SledAgent {
             ));
         };
 
+        let mut disk_ids = Vec::with_capacity(initial_hardware.disks.len());
         for disk in &initial_hardware.disks {
             let initial_state = DiskRuntimeState {
                 disk_state: DiskState::Attached(instance_id),
@@ -251,6 +277,7 @@ impl SledAgent {
             self.disks
                 .sim_ensure_producer(&id, (self.nexus_address, id))
                 .await?;
+            disk_ids.push(id);
         }
 
         // if we're making our first instance and a mock propolis-server
@@ -294,10 +321,18 @@ impl SledAgent {
             }
         }
 
+        let producer_args = InstanceProducerArgs {
+            nexus_address: self.nexus_address,
+            instance_id,
+            ncpus: initial_hardware.runtime.ncpus.0,
+            nic_ids: initial_hardware.nics.iter().map(|nic| nic.id).collect(),
+            disk_ids,
+        };
         let instance_run_time_state = self
             .instances
             .sim_ensure(&instance_id, initial_hardware.runtime, None)
             .await?;
+        self.instances.sim_ensure_producer(&instance_id, producer_args).await?;
 
         for disk_request in &initial_hardware.disks {
             let vcr = &disk_request.volume_construction_request;
@@ -850,7 +850,27 @@ impl Pantry {
     }
 
     pub async fn scrub(&self, volume_id: String) -> Result<String, HttpError> {
-        self.entry(volume_id).await?;
+        let vcr = self.entry(volume_id).await?;
+
+        // A scrub copies every block of the volume's read-only parent into
+        // its sub-volumes, so there must be a parent to copy from, and both
+        // the parent's regions and the sub-volumes' regions must exist.
+        let read_only_parent = match &vcr {
+            VolumeConstructionRequest::Volume {
+                read_only_parent: Some(read_only_parent),
+                ..
+            } => read_only_parent,
+
+            _ => {
+                return Err(HttpError::for_bad_request(
+                    None,
+                    "volume has no read-only parent to scrub!".to_string(),
+                ));
+            }
+        };
+
+        self.sled_agent.check_volume_regions_exist(&vcr).await?;
+        self.sled_agent.check_volume_regions_exist(read_only_parent).await?;
 
         // Make up job
         let mut jobs = self.jobs.lock().await;
@@ -6,7 +6,7 @@ use anyhow::{Context, Result};
 use camino::{Utf8Path, Utf8PathBuf};
 use chrono::{DateTime, Utc};
 
-use crate::{AddArtifact, Key, OmicronRepo};
+use crate::{AddArtifact, Key, OmicronRepo, RootConfig};
 
 use super::ArtifactManifest;
 
@@ -16,6 +16,7 @@ pub struct OmicronRepoAssembler {
     log: slog::Logger,
     manifest: ArtifactManifest,
     build_dir: Option<Utf8PathBuf>,
+    root_config: Option<RootConfig>,
     keys: Vec<Key>,
     expiry: DateTime<Utc>,
     output_path: Utf8PathBuf,
@@ -33,6 +34,7 @@ impl OmicronRepoAssembler {
             log: log.new(slog::o!("component" => "OmicronRepoAssembler")),
             manifest,
             build_dir: None,
+            root_config: None,
             keys,
             expiry,
             output_path,
@@ -44,6 +46,14 @@ impl OmicronRepoAssembler {
         self
     }
 
+    /// Sets the keys and thresholds for each role in the repository's root.
+    ///
+    /// By default, every key is trusted for every role.
+    pub fn set_root_config(&mut self, root_config: RootConfig) -> &mut Self {
+        self.root_config = Some(root_config);
+        self
+    }
+
     pub fn build(&self) -> Result<()> {
         let (build_dir, is_temp) = match &self.build_dir {
             Some(dir) => (dir.clone(), false),
@@ -93,10 +103,15 @@ impl OmicronRepoAssembler {
     }
 
     fn build_impl(&self, build_dir: &Utf8Path) -> Result<()> {
+        let root_config = match &self.root_config {
+            Some(root_config) => root_config.clone(),
+            None => RootConfig::from_keys(&self.keys),
+        };
         let mut repository = OmicronRepo::initialize(
             &self.log,
             build_dir,
             self.manifest.system_version.clone(),
+            &root_config,
             self.keys.clone(),
             self.expiry,
         )?
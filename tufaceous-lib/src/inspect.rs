@@ -0,0 +1,460 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Summarizing, comparing and verifying the artifacts in a repository.
+
+use crate::{
+    ControlPlaneZoneImages, HostPhaseImages, OmicronRepo, RotArchives,
+    UpdatePlanError, UpdatePlanRequirements,
+};
+use anyhow::{Context, Result};
+use hubtools::RawHubrisArchive;
+use omicron_common::{
+    api::{external::SemverVersion, internal::nexus::KnownArtifactKind},
+    update::{Artifact, ArtifactHash, ArtifactHashId, ArtifactKind},
+};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    io::{self, Read, Write},
+};
+use tough::TargetName;
+
+/// A summary of the artifacts in a repository.
+#[derive(Clone, Debug)]
+pub struct RepositorySummary {
+    pub system_version: SemverVersion,
+    pub artifacts: Vec<ArtifactSummary>,
+}
+
+impl RepositorySummary {
+    /// Reads every artifact in `repo`, looking inside composite artifacts.
+    ///
+    /// Artifacts that can't be read or unpacked don't cause an error; instead,
+    /// the problem is recorded in [`ArtifactSummary::error`].
+    pub fn from_repo(repo: &OmicronRepo) -> Result<Self> {
+        let artifacts = repo.read_artifacts()?;
+        let summaries = artifacts
+            .artifacts
+            .into_iter()
+            .map(|artifact| ArtifactSummary::read(repo, artifact))
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            system_version: artifacts.system_version,
+            artifacts: summaries,
+        })
+    }
+
+    /// Checks that wicketd could build an update plan from these artifacts,
+    /// returning a description of each problem found.
+    ///
+    /// Every artifact must unpack cleanly, and together they must meet the
+    /// same [`UpdatePlanRequirements`] wicketd checks when a repository is
+    /// uploaded.
+    pub fn verify(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut requirements = UpdatePlanRequirements::new();
+
+        for artifact in &self.artifacts {
+            if let Some(error) = &artifact.error {
+                problems.push(format!("{artifact}: {error}"));
+                continue;
+            }
+
+            let mut check = |result: Result<(), UpdatePlanError>| {
+                if let Err(error) = result {
+                    problems.push(format!("{artifact}: {error}"));
+                }
+            };
+            let known = artifact.kind.to_known();
+            if let Some(kind) = known {
+                check(requirements.add_artifact(kind));
+                if let Some(board) = &artifact.board {
+                    check(requirements.add_board(kind, board));
+                }
+            }
+
+            // wicketd serves RoT and OS artifacts as the images they contain,
+            // and everything else as-is.
+            let served = match known {
+                Some(
+                    KnownArtifactKind::GimletRot
+                    | KnownArtifactKind::PscRot
+                    | KnownArtifactKind::SwitchRot
+                    | KnownArtifactKind::Host
+                    | KnownArtifactKind::Trampoline,
+                ) => artifact
+                    .nested
+                    .iter()
+                    .map(|nested| ArtifactHashId {
+                        kind: ArtifactKind::new(nested.name.clone()),
+                        hash: nested.hash,
+                    })
+                    .collect(),
+                _ => vec![ArtifactHashId {
+                    kind: artifact.kind.clone(),
+                    hash: artifact.hash,
+                }],
+            };
+            for id in served {
+                check(requirements.add_served(id));
+            }
+        }
+
+        problems.extend(requirements.missing_kinds().map(|kind| {
+            UpdatePlanError::MissingArtifactKind(kind).to_string()
+        }));
+        problems
+    }
+
+    /// Compares `self` with a newer repository.
+    pub fn diff<'a>(
+        &'a self,
+        new: &'a RepositorySummary,
+    ) -> RepositoryDiff<'a> {
+        let by_id = |summary: &'a RepositorySummary| {
+            summary
+                .artifacts
+                .iter()
+                .map(|artifact| ((&artifact.kind, &artifact.name), artifact))
+                .collect::<BTreeMap<_, _>>()
+        };
+        let old_artifacts = by_id(self);
+        let new_artifacts = by_id(new);
+
+        let mut changes = Vec::new();
+        for (id, &old) in &old_artifacts {
+            match new_artifacts.get(id) {
+                None => changes.push(ArtifactChange::Removed(old)),
+                Some(&new) => {
+                    if old.version != new.version || old.hash != new.hash {
+                        changes.push(ArtifactChange::Changed { old, new });
+                    }
+                }
+            }
+        }
+        for (id, &new) in &new_artifacts {
+            if !old_artifacts.contains_key(id) {
+                changes.push(ArtifactChange::Added(new));
+            }
+        }
+
+        RepositoryDiff { old: self, new, changes }
+    }
+}
+
+/// A summary of a single artifact in a repository.
+#[derive(Clone, Debug)]
+pub struct ArtifactSummary {
+    pub name: String,
+    pub version: SemverVersion,
+    pub kind: ArtifactKind,
+    pub target: String,
+
+    /// The SHA-256 hash of the artifact, as listed in `targets.json`.
+    pub hash: ArtifactHash,
+
+    /// The length of the artifact, as listed in `targets.json`.
+    pub length: u64,
+
+    /// For SP images, the board named in the image's caboose.
+    pub board: Option<String>,
+
+    /// The artifacts inside a composite artifact, such as the phase 1 and 2
+    /// images inside a host OS artifact.
+    pub nested: Vec<NestedArtifact>,
+
+    /// Why this artifact couldn't be read or unpacked, if it couldn't.
+    pub error: Option<String>,
+}
+
+impl ArtifactSummary {
+    fn read(repo: &OmicronRepo, artifact: Artifact) -> Result<Self> {
+        let target_name = TargetName::try_from(artifact.target.as_str())
+            .with_context(|| {
+                format!("invalid target name `{}`", artifact.target)
+            })?;
+        let target = repo
+            .repo()
+            .targets()
+            .signed
+            .find_target(&target_name)
+            .with_context(|| {
+                format!("target `{}` is not in targets.json", artifact.target)
+            })?;
+        let hash = ArtifactHash(
+            target.hashes.sha256.clone().into_vec().try_into().map_err(
+                |_| {
+                    anyhow::anyhow!(
+                        "target `{}` has an invalid SHA-256 hash",
+                        artifact.target
+                    )
+                },
+            )?,
+        );
+
+        let mut summary = Self {
+            name: artifact.name,
+            version: artifact.version,
+            kind: artifact.kind,
+            target: artifact.target,
+            hash,
+            length: target.length,
+            board: None,
+            nested: Vec::new(),
+            error: None,
+        };
+        if let Err(error) = summary.inspect(repo, &target_name) {
+            summary.error = Some(format!("{error:#}"));
+        }
+        Ok(summary)
+    }
+
+    fn inspect(
+        &mut self,
+        repo: &OmicronRepo,
+        target_name: &TargetName,
+    ) -> Result<()> {
+        let open = || {
+            repo.repo()
+                .read_target(target_name)
+                .context("error reading target")?
+                .context("target is missing")
+        };
+
+        // tough checks the target's length and hash against `targets.json`
+        // once it's read to the end, which the extraction below might not do,
+        // so always read the whole thing first.
+        io::copy(&mut open()?, &mut io::sink())
+            .context("error reading target")?;
+
+        let Some(kind) = self.kind.to_known() else {
+            return Ok(());
+        };
+        match kind {
+            KnownArtifactKind::GimletSp
+            | KnownArtifactKind::PscSp
+            | KnownArtifactKind::SwitchSp => {
+                let mut data = Vec::new();
+                open()?.read_to_end(&mut data)?;
+                self.board = Some(read_board(data)?);
+            }
+            KnownArtifactKind::GimletRot
+            | KnownArtifactKind::PscRot
+            | KnownArtifactKind::SwitchRot => {
+                let (kind_a, kind_b) = match kind {
+                    KnownArtifactKind::GimletRot => (
+                        ArtifactKind::GIMLET_ROT_IMAGE_A,
+                        ArtifactKind::GIMLET_ROT_IMAGE_B,
+                    ),
+                    KnownArtifactKind::PscRot => (
+                        ArtifactKind::PSC_ROT_IMAGE_A,
+                        ArtifactKind::PSC_ROT_IMAGE_B,
+                    ),
+                    _ => (
+                        ArtifactKind::SWITCH_ROT_IMAGE_A,
+                        ArtifactKind::SWITCH_ROT_IMAGE_B,
+                    ),
+                };
+                let mut a = HashingWriter::default();
+                let mut b = HashingWriter::default();
+                RotArchives::extract_into(open()?, &mut a, &mut b)?;
+                self.nested = vec![a.finish(kind_a), b.finish(kind_b)];
+            }
+            KnownArtifactKind::Host | KnownArtifactKind::Trampoline => {
+                let (kind_1, kind_2) = if kind == KnownArtifactKind::Host {
+                    (ArtifactKind::HOST_PHASE_1, ArtifactKind::HOST_PHASE_2)
+                } else {
+                    (
+                        ArtifactKind::TRAMPOLINE_PHASE_1,
+                        ArtifactKind::TRAMPOLINE_PHASE_2,
+                    )
+                };
+                let mut phase_1 = HashingWriter::default();
+                let mut phase_2 = HashingWriter::default();
+                HostPhaseImages::extract_into(
+                    open()?,
+                    &mut phase_1,
+                    &mut phase_2,
+                )?;
+                self.nested =
+                    vec![phase_1.finish(kind_1), phase_2.finish(kind_2)];
+            }
+            KnownArtifactKind::ControlPlane => {
+                let images = ControlPlaneZoneImages::extract(open()?)?;
+                self.nested = images
+                    .zones
+                    .into_iter()
+                    .map(|(name, data)| {
+                        let mut writer = HashingWriter::default();
+                        writer.write_all(&data).expect("hashing can't fail");
+                        writer.finish(name)
+                    })
+                    .collect();
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ArtifactSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.kind, self.name, self.version)
+    }
+}
+
+/// An artifact inside a composite artifact.
+#[derive(Clone, Debug)]
+pub struct NestedArtifact {
+    /// For RoT and OS images, the kind wicketd serves the image as (e.g.
+    /// `host_phase_1`). For control plane zones, the zone's file name.
+    pub name: String,
+    pub hash: ArtifactHash,
+    pub length: u64,
+}
+
+/// The differences between two repositories, created by
+/// [`RepositorySummary::diff`].
+///
+/// Artifacts are matched up by kind and name.
+#[derive(Clone, Debug)]
+pub struct RepositoryDiff<'a> {
+    old: &'a RepositorySummary,
+    new: &'a RepositorySummary,
+    changes: Vec<ArtifactChange<'a>>,
+}
+
+impl<'a> RepositoryDiff<'a> {
+    /// Returns true if the repositories have the same system version and
+    /// artifacts.
+    pub fn is_empty(&self) -> bool {
+        self.old.system_version == self.new.system_version
+            && self.changes.is_empty()
+    }
+
+    /// Returns the artifacts that were added, removed or changed.
+    pub fn changes(&self) -> &[ArtifactChange<'a>] {
+        &self.changes
+    }
+}
+
+impl fmt::Display for RepositoryDiff<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.old.system_version != self.new.system_version {
+            writeln!(
+                f,
+                "system version: {} -> {}",
+                self.old.system_version, self.new.system_version
+            )?;
+        }
+        for change in &self.changes {
+            write!(f, "{change}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A change to a single artifact.
+#[derive(Clone, Debug)]
+pub enum ArtifactChange<'a> {
+    Added(&'a ArtifactSummary),
+    Removed(&'a ArtifactSummary),
+    Changed { old: &'a ArtifactSummary, new: &'a ArtifactSummary },
+}
+
+impl fmt::Display for ArtifactChange<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArtifactChange::Added(artifact) => {
+                writeln!(f, "added {artifact} ({})", artifact.hash)
+            }
+            ArtifactChange::Removed(artifact) => {
+                writeln!(f, "removed {artifact} ({})", artifact.hash)
+            }
+            ArtifactChange::Changed { old, new } => {
+                writeln!(f, "changed {} {}", new.kind, new.name)?;
+                if old.version != new.version {
+                    writeln!(
+                        f,
+                        "  version: {} -> {}",
+                        old.version, new.version
+                    )?;
+                }
+                if old.hash != new.hash {
+                    writeln!(f, "  hash: {} -> {}", old.hash, new.hash)?;
+                }
+
+                let old_nested = old
+                    .nested
+                    .iter()
+                    .map(|nested| (&nested.name, nested))
+                    .collect::<BTreeMap<_, _>>();
+                let new_nested = new
+                    .nested
+                    .iter()
+                    .map(|nested| (&nested.name, nested))
+                    .collect::<BTreeMap<_, _>>();
+                for (name, old) in &old_nested {
+                    match new_nested.get(name) {
+                        None => writeln!(f, "  removed {name} ({})", old.hash)?,
+                        Some(new) if old.hash != new.hash => writeln!(
+                            f,
+                            "  changed {name}: {} -> {}",
+                            old.hash, new.hash
+                        )?,
+                        Some(_) => {}
+                    }
+                }
+                for (name, new) in &new_nested {
+                    if !old_nested.contains_key(name) {
+                        writeln!(f, "  added {name} ({})", new.hash)?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+fn read_board(data: Vec<u8>) -> Result<String> {
+    let archive = RawHubrisArchive::from_vec(data)
+        .context("error parsing hubris archive")?;
+    let caboose =
+        archive.read_caboose().context("error reading hubris caboose")?;
+    let board =
+        caboose.board().context("error reading board from hubris caboose")?;
+    let board = std::str::from_utf8(board)
+        .context("board in hubris caboose is not UTF-8")?;
+    Ok(board.to_owned())
+}
+
+// Hashes the data written to it, for nested artifacts we don't need to keep.
+#[derive(Default)]
+struct HashingWriter {
+    hasher: Sha256,
+    length: u64,
+}
+
+impl HashingWriter {
+    fn finish(self, name: impl fmt::Display) -> NestedArtifact {
+        NestedArtifact {
+            name: name.to_string(),
+            hash: ArtifactHash(self.hasher.finalize().into()),
+            length: self.length,
+        }
+    }
+}
+
+impl Write for HashingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.hasher.update(buf);
+        self.length += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -1,11 +1,17 @@
-use anyhow::{bail, Result};
+use crate::CommandSigner;
+use anyhow::{anyhow, bail, ensure, Result};
 use hex::FromHex;
 use rand::{rngs::OsRng, RngCore};
-use ring::rand::SecureRandom;
-use ring::signature::Ed25519KeyPair;
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::signature::{
+    EcdsaKeyPair, Ed25519KeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fmt::Display;
 use std::str::FromStr;
 use tough::key_source::KeySource;
+use tough::schema::key::{EcdsaKey, EcdsaScheme, Ed25519Key, Ed25519Scheme};
 use tough::sign::{Sign, SignKeyPair};
 
 pub(crate) fn boxed_keys(keys: Vec<Key>) -> Vec<Box<dyn KeySource>> {
@@ -19,6 +25,13 @@ pub enum Key {
         // doesn't impl `Clone`.
         [u8; 32],
     ),
+    EcdsaP256(
+        // A PKCS#8 document, which is the only form `ring` can load ECDSA
+        // keys from.
+        Vec<u8>,
+    ),
+    /// A key held by an external program, such as one fronting an HSM.
+    Command(CommandSigner),
 }
 
 impl Key {
@@ -28,19 +41,66 @@ impl Key {
         Key::Ed25519(key)
     }
 
-    pub(crate) fn as_sign(&self) -> SignKeyPair {
+    pub fn generate_ecdsa_p256() -> Key {
+        let document = EcdsaKeyPair::generate_pkcs8(
+            &ECDSA_P256_SHA256_ASN1_SIGNING,
+            &SystemRandom::new(),
+        )
+        .expect("failed to generate ecdsa key");
+        Key::EcdsaP256(document.as_ref().to_vec())
+    }
+
+    // Returns the key pair for keys we hold ourselves, or `None` for keys held
+    // by an external signer.
+    fn key_pair(&self) -> Option<SignKeyPair> {
         match self {
-            Key::Ed25519(key) => SignKeyPair::ED25519(
+            Key::Ed25519(key) => Some(SignKeyPair::ED25519(
                 Ed25519KeyPair::from_seed_unchecked(key)
                     .expect("ed25519 key length mismatch"),
-            ),
+            )),
+            Key::EcdsaP256(document) => Some(SignKeyPair::ECDSA(
+                ecdsa_key_pair(document).expect("ecdsa key was validated"),
+            )),
+            Key::Command(_) => None,
+        }
+    }
+
+    /// Returns the public half of this key.
+    pub fn public_key(&self) -> PublicKey {
+        match self {
+            Key::Ed25519(key) => {
+                let pair = Ed25519KeyPair::from_seed_unchecked(key)
+                    .expect("ed25519 key length mismatch");
+                let mut public = [0; 32];
+                public.copy_from_slice(pair.public_key().as_ref());
+                PublicKey::Ed25519(public)
+            }
+            Key::EcdsaP256(document) => {
+                let pair =
+                    ecdsa_key_pair(document).expect("ecdsa key was validated");
+                PublicKey::EcdsaP256(pair.public_key().as_ref().to_vec())
+            }
+            Key::Command(signer) => signer.public_key().clone(),
         }
     }
+
+    /// Signs `msg` with this key.
+    pub fn sign_message(&self, msg: &[u8]) -> Result<Vec<u8>> {
+        self.sign(msg, &SystemRandom::new()).map_err(|e| anyhow!(e))
+    }
+}
+
+fn ecdsa_key_pair(document: &[u8]) -> Result<EcdsaKeyPair> {
+    EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, document)
+        .map_err(|e| anyhow!("invalid ecdsa-p256 key: {e}"))
 }
 
 impl Sign for Key {
     fn tuf_key(&self) -> tough::schema::key::Key {
-        self.as_sign().tuf_key()
+        match self.key_pair() {
+            Some(pair) => pair.tuf_key(),
+            None => self.public_key().tuf_key(),
+        }
     }
 
     fn sign(
@@ -49,7 +109,10 @@ impl Sign for Key {
         rng: &dyn SecureRandom,
     ) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync + 'static>>
     {
-        self.as_sign().sign(msg, rng)
+        match self {
+            Key::Command(signer) => Ok(signer.sign(msg)?),
+            _ => self.key_pair().expect("key is held locally").sign(msg, rng),
+        }
     }
 }
 
@@ -76,6 +139,14 @@ impl FromStr for Key {
     fn from_str(s: &str) -> Result<Key> {
         match s.split_once(':') {
             Some(("ed25519", hex)) => Ok(Key::Ed25519(FromHex::from_hex(hex)?)),
+            Some(("ecdsa-p256", hex)) => {
+                let document = Vec::from_hex(hex)?;
+                ecdsa_key_pair(&document)?;
+                Ok(Key::EcdsaP256(document))
+            }
+            Some(("command", program)) => {
+                Ok(Key::Command(CommandSigner::new(program)?))
+            }
             Some((kind, _)) => bail!("Invalid key source kind: {}", kind),
             None => bail!("Invalid key source (format is `kind:data`)"),
         }
@@ -88,15 +159,109 @@ impl Display for Key {
             Key::Ed25519(key) => {
                 write!(f, "ed25519:{}", hex::encode(key))
             }
+            Key::EcdsaP256(document) => {
+                write!(f, "ecdsa-p256:{}", hex::encode(document))
+            }
+            Key::Command(signer) => write!(f, "command:{}", signer.program()),
+        }
+    }
+}
+
+/// The public half of a [`Key`].
+///
+/// Public keys are what a repository's root lists as trusted for each role, so
+/// they can be shared without giving away the ability to sign.
+#[derive(
+    Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize,
+)]
+#[serde(try_from = "String", into = "String")]
+pub enum PublicKey {
+    Ed25519([u8; 32]),
+    /// An uncompressed P-256 point.
+    EcdsaP256(Vec<u8>),
+}
+
+impl PublicKey {
+    pub(crate) fn tuf_key(&self) -> tough::schema::key::Key {
+        match self {
+            PublicKey::Ed25519(key) => tough::schema::key::Key::Ed25519 {
+                keyval: Ed25519Key {
+                    public: key.to_vec().into(),
+                    _extra: HashMap::new(),
+                },
+                scheme: Ed25519Scheme::Ed25519,
+                _extra: HashMap::new(),
+            },
+            PublicKey::EcdsaP256(key) => tough::schema::key::Key::Ecdsa {
+                keyval: EcdsaKey {
+                    public: key.clone().into(),
+                    _extra: HashMap::new(),
+                },
+                scheme: EcdsaScheme::EcdsaSha2Nistp256,
+                _extra: HashMap::new(),
+            },
+        }
+    }
+}
+
+impl FromStr for PublicKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<PublicKey> {
+        match s.split_once(':') {
+            Some(("ed25519", hex)) => {
+                Ok(PublicKey::Ed25519(FromHex::from_hex(hex)?))
+            }
+            Some(("ecdsa-p256", hex)) => {
+                let key = Vec::from_hex(hex)?;
+                ensure!(
+                    key.len() == 65 && key[0] == 0x04,
+                    "Invalid ecdsa-p256 public key (expected an uncompressed \
+                     point)"
+                );
+                Ok(PublicKey::EcdsaP256(key))
+            }
+            Some((kind, _)) => bail!("Invalid public key kind: {}", kind),
+            None => bail!("Invalid public key (format is `kind:data`)"),
+        }
+    }
+}
+
+impl TryFrom<String> for PublicKey {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<PublicKey> {
+        s.parse()
+    }
+}
+
+impl From<PublicKey> for String {
+    fn from(key: PublicKey) -> String {
+        key.to_string()
+    }
+}
+
+impl Display for PublicKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PublicKey::Ed25519(key) => {
+                write!(f, "ed25519:{}", hex::encode(key))
+            }
+            PublicKey::EcdsaP256(key) => {
+                write!(f, "ecdsa-p256:{}", hex::encode(key))
+            }
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Key;
-    use ring::signature::Ed25519KeyPair;
+    use super::{Key, PublicKey};
+    use ring::signature::{
+        Ed25519KeyPair, UnparsedPublicKey, ECDSA_P256_SHA256_ASN1,
+    };
     use std::str::FromStr;
+    use tough::sign::Sign;
 
     #[test]
     fn test_from_str() {
@@ -105,6 +270,41 @@ mod tests {
             Key::Ed25519(key) => {
                 let _ = Ed25519KeyPair::from_seed_unchecked(&key).unwrap();
             }
+            key => panic!("unexpected key: {key:?}"),
         }
     }
+
+    #[test]
+    fn test_public_key() {
+        for key in [Key::generate_ed25519(), Key::generate_ecdsa_p256()] {
+            let public = key.public_key();
+
+            // The public key must be the one tough derives from the private
+            // key, or roots built from public keys won't trust the matching
+            // signatures.
+            assert_eq!(
+                public.tuf_key().key_id().unwrap(),
+                key.tuf_key().key_id().unwrap()
+            );
+
+            let round_trip = PublicKey::from_str(&public.to_string()).unwrap();
+            assert_eq!(round_trip, public);
+        }
+    }
+
+    #[test]
+    fn test_ecdsa_round_trip() {
+        let key = Key::generate_ecdsa_p256();
+        let round_trip = Key::from_str(&key.to_string()).unwrap();
+        assert_eq!(round_trip.public_key(), key.public_key());
+
+        let PublicKey::EcdsaP256(public) = key.public_key() else {
+            panic!("unexpected public key for {key:?}");
+        };
+        let msg = b"hello world";
+        let signature = round_trip.sign_message(msg).unwrap();
+        UnparsedPublicKey::new(&ECDSA_P256_SHA256_ASN1, public)
+            .verify(msg, &signature)
+            .expect("signature should verify");
+    }
 }
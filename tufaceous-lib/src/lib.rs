@@ -5,13 +5,20 @@
 mod archive;
 mod artifact;
 pub mod assemble;
+mod inspect;
 mod key;
 pub mod oxide_metadata;
 mod repository;
+mod requirements;
 mod root;
+mod signer;
 mod target;
 
 pub use archive::*;
 pub use artifact::*;
+pub use inspect::*;
 pub use key::*;
 pub use repository::*;
+pub use requirements::*;
+pub use root::*;
+pub use signer::*;
@@ -2,11 +2,13 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use crate::{key::Key, target::TargetWriter, AddArtifact, ArchiveBuilder};
+use crate::{
+    key::Key, target::TargetWriter, AddArtifact, ArchiveBuilder, RootConfig,
+};
 use anyhow::{anyhow, bail, Context, Result};
 use camino::{Utf8Path, Utf8PathBuf};
 use chrono::{DateTime, Utc};
-use fs_err::{self as fs, File};
+use fs_err as fs;
 use omicron_common::{
     api::external::SemverVersion,
     update::{Artifact, ArtifactsDocument},
@@ -28,14 +30,18 @@ pub struct OmicronRepo {
 
 impl OmicronRepo {
     /// Initializes a new repository at the given path, writing it to disk.
+    ///
+    /// The root trusts the keys listed in `root_config`. `keys` must include
+    /// enough of its root keys to sign the root, and keys for the other roles.
     pub fn initialize(
         log: &slog::Logger,
         repo_path: &Utf8Path,
         system_version: SemverVersion,
+        root_config: &RootConfig,
         keys: Vec<Key>,
         expiry: DateTime<Utc>,
     ) -> Result<Self> {
-        let root = crate::root::new_root(keys.clone(), expiry)?;
+        let root = crate::root::new_root(root_config, &keys, expiry)?;
         let editor = OmicronRepoEditor::initialize(
             repo_path.to_owned(),
             root,
@@ -51,7 +57,50 @@ impl OmicronRepo {
         Self::load_untrusted(log, repo_path)
     }
 
-    /// Loads a repository from the given path.
+    /// Loads a repository from the given path, verifying it against a trusted
+    /// root.
+    ///
+    /// `trusted_root` is the contents of a `root.json` document. The
+    /// repository must chain to it: any newer roots in the repository must be
+    /// signed by the previous root, starting with `trusted_root`, and the rest
+    /// of the repository must be signed by the last of them.
+    ///
+    /// This method enforces expirations. To load without expiration enforcement, use
+    /// [`Self::load_ignore_expiration`].
+    pub fn load(
+        log: &slog::Logger,
+        repo_path: &Utf8Path,
+        trusted_root: &[u8],
+    ) -> Result<Self> {
+        Self::load_impl(
+            log,
+            repo_path,
+            trusted_root,
+            ExpirationEnforcement::Safe,
+        )
+    }
+
+    /// Loads a repository from the given path, verifying it against a trusted
+    /// root but ignoring expiration.
+    ///
+    /// See [`Self::load`] and [`Self::load_untrusted_ignore_expiration`].
+    pub fn load_ignore_expiration(
+        log: &slog::Logger,
+        repo_path: &Utf8Path,
+        trusted_root: &[u8],
+    ) -> Result<Self> {
+        Self::load_impl(
+            log,
+            repo_path,
+            trusted_root,
+            ExpirationEnforcement::Unsafe,
+        )
+    }
+
+    /// Loads a repository from the given path, trusting whichever root it
+    /// starts with.
+    ///
+    /// As with [`Self::load`], any newer roots must chain to `1.root.json`.
     ///
     /// This method enforces expirations. To load without expiration enforcement, use
     /// [`Self::load_untrusted_ignore_expiration`].
@@ -79,12 +128,22 @@ impl OmicronRepo {
         log: &slog::Logger,
         repo_path: &Utf8Path,
         exp: ExpirationEnforcement,
+    ) -> Result<Self> {
+        let root = fs::read(repo_path.join("metadata").join("1.root.json"))?;
+        Self::load_impl(log, repo_path, &root, exp)
+    }
+
+    fn load_impl(
+        log: &slog::Logger,
+        repo_path: &Utf8Path,
+        trusted_root: &[u8],
+        exp: ExpirationEnforcement,
     ) -> Result<Self> {
         let log = log.new(slog::o!("component" => "OmicronRepo"));
         let repo_path = repo_path.canonicalize_utf8()?;
 
         let repo = RepositoryLoader::new(
-            File::open(repo_path.join("metadata").join("1.root.json"))?,
+            trusted_root,
             Url::from_file_path(repo_path.join("metadata"))
                 .expect("the canonical path is not absolute?"),
             Url::from_file_path(repo_path.join("targets"))
@@ -178,7 +237,53 @@ impl OmicronRepo {
     /// Converts `self` into an `OmicronRepoEditor`, which can be used to perform
     /// modifications to the repository.
     pub fn into_editor(self) -> Result<OmicronRepoEditor> {
-        OmicronRepoEditor::new(self)
+        let root_path = self
+            .repo_path
+            .join("metadata")
+            .join(format!("{}.root.json", self.repo.root().signed.version));
+        OmicronRepoEditor::new(self, root_path)
+    }
+
+    /// Replaces the repository's root with a new version trusting the keys in
+    /// `root_config`, and re-signs the rest of the repository under it.
+    ///
+    /// The new root is written alongside the existing ones, and is signed by
+    /// both the current root's keys and its own, so clients that trust any
+    /// earlier root can follow the chain to it. `keys` must therefore include
+    /// enough root keys from both the current and new roots, as well as keys
+    /// for the new root's other roles.
+    pub fn rotate_root(
+        self,
+        root_config: &RootConfig,
+        keys: Vec<Key>,
+        expiry: DateTime<Utc>,
+    ) -> Result<()> {
+        let root = crate::root::rotate_root(
+            &self.repo.root().signed,
+            root_config,
+            &keys,
+            expiry,
+        )?;
+        let root_path = self
+            .repo_path
+            .join("metadata")
+            .join(format!("{}.root.json", root.signed.version));
+        let mut buf = serde_json::to_vec_pretty(&root)?;
+        buf.push(b'\n');
+        fs::write(&root_path, buf)?;
+
+        let log = self.log.clone();
+        let result = OmicronRepoEditor::new(self, root_path.clone())
+            .and_then(|editor| editor.sign_and_finish(keys, expiry));
+        if result.is_err() {
+            // Don't leave behind a root that the rest of the repository isn't
+            // signed for.
+            _ = fs::remove_file(&root_path);
+        }
+        result.context("error signing repository with new root")?;
+
+        slog::info!(log, "rotated root to version {}", root.signed.version);
+        Ok(())
     }
 
     /// Prepends the target digest to the name if using consistent snapshots. Returns both the
@@ -210,7 +315,7 @@ pub struct OmicronRepoEditor {
 }
 
 impl OmicronRepoEditor {
-    fn new(repo: OmicronRepo) -> Result<Self> {
+    fn new(repo: OmicronRepo, root_path: Utf8PathBuf) -> Result<Self> {
         let artifacts = repo.read_artifacts()?;
 
         let existing_target_names = repo
@@ -221,12 +326,7 @@ impl OmicronRepoEditor {
             .map(|(name, _)| name.resolved().to_string())
             .collect::<BTreeSet<_>>();
 
-        let editor = RepositoryEditor::from_repo(
-            repo.repo_path
-                .join("metadata")
-                .join(format!("{}.root.json", repo.repo.root().signed.version)),
-            repo.repo,
-        )?;
+        let editor = RepositoryEditor::from_repo(root_path, repo.repo)?;
 
         Ok(Self {
             editor,
@@ -350,11 +450,13 @@ mod tests {
     fn reject_artifacts_with_the_same_filename() {
         let logctx = test_setup_log("reject_artifacts_with_the_same_filename");
         let tempdir = Utf8TempDir::new().unwrap();
+        let key = Key::generate_ed25519();
         let mut repo = OmicronRepo::initialize(
             &logctx.log,
             tempdir.path(),
             "0.0.0".parse().unwrap(),
-            vec![Key::generate_ed25519()],
+            &RootConfig::from_keys(&[key.clone()]),
+            vec![key],
             Utc::now() + Days::new(1),
         )
         .unwrap()
@@ -393,4 +495,142 @@ mod tests {
 
         logctx.cleanup_successful();
     }
+
+    fn single_key_config(
+        root: &Key,
+        online: &Key,
+        threshold: u64,
+    ) -> RootConfig {
+        let role = |key: &Key| crate::RoleConfig {
+            keys: vec![key.public_key()],
+            threshold: NonZeroU64::new(threshold).unwrap(),
+        };
+        RootConfig {
+            root: role(root),
+            targets: role(online),
+            snapshot: role(online),
+            timestamp: role(online),
+        }
+    }
+
+    #[test]
+    fn rotate_root_with_offline_keys() {
+        let logctx = test_setup_log("rotate_root_with_offline_keys");
+        let tempdir = Utf8TempDir::new().unwrap();
+        let expiry = Utc::now() + Days::new(1);
+        let root_key = Key::generate_ed25519();
+        let online_key = Key::generate_ed25519();
+
+        let repo = OmicronRepo::initialize(
+            &logctx.log,
+            tempdir.path(),
+            "0.0.0".parse().unwrap(),
+            &single_key_config(&root_key, &online_key, 1),
+            vec![root_key.clone(), online_key.clone()],
+            expiry,
+        )
+        .unwrap();
+        let trusted_root =
+            fs::read(tempdir.path().join("metadata/1.root.json")).unwrap();
+
+        // Once the root is signed, the repository can be edited without the
+        // root key.
+        let mut editor = repo.into_editor().unwrap();
+        editor
+            .add_artifact(&AddArtifact::new(
+                "test-kind".parse().unwrap(),
+                "test-artifact-name".to_string(),
+                "1.0.0".parse().unwrap(),
+                ArtifactSource::Memory(BufList::new()),
+            ))
+            .unwrap();
+        editor.sign_and_finish(vec![online_key.clone()], expiry).unwrap();
+
+        // A new root that isn't signed by the current root key is rejected.
+        let new_root_key = Key::generate_ed25519();
+        let new_config = single_key_config(&new_root_key, &online_key, 1);
+        let repo =
+            OmicronRepo::load_untrusted(&logctx.log, tempdir.path()).unwrap();
+        let err = repo
+            .rotate_root(
+                &new_config,
+                vec![new_root_key.clone(), online_key.clone()],
+                expiry,
+            )
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("root version 1 needs 1 signatures"), "{err}");
+        assert!(!tempdir.path().join("metadata/2.root.json").exists());
+
+        let repo =
+            OmicronRepo::load_untrusted(&logctx.log, tempdir.path()).unwrap();
+        repo.rotate_root(
+            &new_config,
+            vec![root_key, new_root_key, online_key],
+            expiry,
+        )
+        .unwrap();
+
+        // Both an untrusted load and one starting from the original root
+        // follow the chain to the new root.
+        let repo =
+            OmicronRepo::load_untrusted(&logctx.log, tempdir.path()).unwrap();
+        assert_eq!(repo.repo().root().signed.version.get(), 2);
+        let repo =
+            OmicronRepo::load(&logctx.log, tempdir.path(), &trusted_root)
+                .unwrap();
+        assert_eq!(repo.repo().root().signed.version.get(), 2);
+        assert_eq!(repo.read_artifacts().unwrap().artifacts.len(), 1);
+
+        logctx.cleanup_successful();
+    }
+
+    #[test]
+    fn reject_root_not_cross_signed() {
+        let logctx = test_setup_log("reject_root_not_cross_signed");
+        let tempdir = Utf8TempDir::new().unwrap();
+        let expiry = Utc::now() + Days::new(1);
+        let key = Key::generate_ed25519();
+
+        let repo = OmicronRepo::initialize(
+            &logctx.log,
+            tempdir.path(),
+            "0.0.0".parse().unwrap(),
+            &RootConfig::from_keys(&[key.clone()]),
+            vec![key.clone()],
+            expiry,
+        )
+        .unwrap();
+
+        // Write out a version 2 root signed only by a new key, the way someone
+        // trying to take over the repository might, and re-sign the rest of
+        // the repository with it.
+        let new_key = Key::generate_ed25519();
+        let root = RootConfig::from_keys(&[new_key.clone()])
+            .to_root(NonZeroU64::new(2).unwrap(), expiry)
+            .unwrap();
+        let signed = SignedRole::new(
+            root.clone(),
+            &tough::schema::KeyHolder::Root(root),
+            &crate::key::boxed_keys(vec![new_key.clone()]),
+            &ring::rand::SystemRandom::new(),
+        )
+        .unwrap();
+        let root_path = tempdir.path().join("metadata/2.root.json");
+        fs::write(&root_path, signed.buffer()).unwrap();
+        OmicronRepoEditor::new(repo, root_path)
+            .unwrap()
+            .sign_and_finish(vec![new_key], expiry)
+            .unwrap();
+
+        let err = OmicronRepo::load_untrusted(&logctx.log, tempdir.path())
+            .err()
+            .expect("a root not signed by its predecessor should be rejected");
+        assert!(
+            format!("{err:#}").contains("root"),
+            "unexpected error: {err:#}"
+        );
+
+        logctx.cleanup_successful();
+    }
 }
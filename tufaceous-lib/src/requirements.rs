@@ -0,0 +1,190 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The artifacts a repository must contain for wicketd to build an update plan
+//! from it.
+//!
+//! wicketd checks these as it ingests an uploaded repository, and `tufaceous
+//! verify` checks them ahead of time, so that release engineers find out about
+//! a repository wicketd would reject before shipping it.
+
+use omicron_common::api::internal::nexus::KnownArtifactKind;
+use omicron_common::update::ArtifactHashId;
+use std::collections::BTreeSet;
+use thiserror::Error;
+
+/// A reason a repository can't be used to build an update plan.
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+pub enum UpdatePlanError {
+    #[error("multiple artifacts found for kind `{0}`")]
+    DuplicateArtifactKind(KnownArtifactKind),
+
+    #[error("duplicate board found for kind `{kind}`: `{board}`")]
+    DuplicateBoardEntry { board: String, kind: KnownArtifactKind },
+
+    #[error(
+        "duplicate hash entries found in artifacts.json for kind `{}`, hash `{}`", .0.kind, .0.hash
+    )]
+    DuplicateHashEntry(ArtifactHashId),
+
+    #[error("missing artifact of kind `{0}`")]
+    MissingArtifactKind(KnownArtifactKind),
+}
+
+/// Tracks the artifacts read from a repository, checking that they're the set
+/// wicketd needs to build an update plan.
+///
+/// There must be exactly one artifact of each known kind, except for SP
+/// images, of which there must be at least one of each kind but only one per
+/// board. No two of the artifacts wicketd serves (which for RoT and OS
+/// artifacts are the images inside them) may share a kind and hash.
+#[derive(Clone, Debug, Default)]
+pub struct UpdatePlanRequirements {
+    kinds: BTreeSet<KnownArtifactKind>,
+    boards: BTreeSet<(KnownArtifactKind, String)>,
+    served: BTreeSet<ArtifactHashId>,
+}
+
+impl UpdatePlanRequirements {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records an artifact of a known kind, failing if there's already one of
+    /// that kind and only one is allowed.
+    pub fn add_artifact(
+        &mut self,
+        kind: KnownArtifactKind,
+    ) -> Result<(), UpdatePlanError> {
+        if !self.kinds.insert(kind) && !Self::multiple_allowed(kind) {
+            return Err(UpdatePlanError::DuplicateArtifactKind(kind));
+        }
+        Ok(())
+    }
+
+    /// Records the board an SP image of `kind` is for, failing if there's
+    /// already an image of that kind for the same board.
+    pub fn add_board(
+        &mut self,
+        kind: KnownArtifactKind,
+        board: &str,
+    ) -> Result<(), UpdatePlanError> {
+        if !self.boards.insert((kind, board.to_owned())) {
+            return Err(UpdatePlanError::DuplicateBoardEntry {
+                board: board.to_owned(),
+                kind,
+            });
+        }
+        Ok(())
+    }
+
+    /// Records an artifact wicketd serves, failing if it already serves one
+    /// with the same kind and hash.
+    pub fn add_served(
+        &mut self,
+        id: ArtifactHashId,
+    ) -> Result<(), UpdatePlanError> {
+        if self.served.contains(&id) {
+            return Err(UpdatePlanError::DuplicateHashEntry(id));
+        }
+        self.served.insert(id);
+        Ok(())
+    }
+
+    /// Returns the known kinds there isn't an artifact for yet.
+    pub fn missing_kinds(
+        &self,
+    ) -> impl Iterator<Item = KnownArtifactKind> + '_ {
+        KnownArtifactKind::iter().filter(|kind| !self.kinds.contains(kind))
+    }
+
+    /// Checks that there's an artifact of every known kind.
+    pub fn check_complete(&self) -> Result<(), UpdatePlanError> {
+        match self.missing_kinds().next() {
+            Some(kind) => Err(UpdatePlanError::MissingArtifactKind(kind)),
+            None => Ok(()),
+        }
+    }
+
+    // SP images are the only kinds of which a repository may have several,
+    // one for each board.
+    fn multiple_allowed(kind: KnownArtifactKind) -> bool {
+        matches!(
+            kind,
+            KnownArtifactKind::GimletSp
+                | KnownArtifactKind::PscSp
+                | KnownArtifactKind::SwitchSp
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use omicron_common::update::ArtifactHash;
+    use omicron_common::update::ArtifactKind;
+
+    #[test]
+    fn test_update_plan_requirements() {
+        let mut requirements = UpdatePlanRequirements::new();
+        for kind in KnownArtifactKind::iter() {
+            requirements.add_artifact(kind).unwrap();
+        }
+        requirements.check_complete().unwrap();
+
+        // Several SP images are fine, as long as they're for different boards.
+        requirements.add_artifact(KnownArtifactKind::GimletSp).unwrap();
+        requirements.add_board(KnownArtifactKind::GimletSp, "a").unwrap();
+        requirements.add_board(KnownArtifactKind::GimletSp, "b").unwrap();
+        requirements.add_board(KnownArtifactKind::PscSp, "a").unwrap();
+        assert_eq!(
+            requirements.add_board(KnownArtifactKind::GimletSp, "a"),
+            Err(UpdatePlanError::DuplicateBoardEntry {
+                board: String::from("a"),
+                kind: KnownArtifactKind::GimletSp,
+            })
+        );
+
+        assert_eq!(
+            requirements.add_artifact(KnownArtifactKind::Host),
+            Err(UpdatePlanError::DuplicateArtifactKind(
+                KnownArtifactKind::Host
+            ))
+        );
+
+        let id = ArtifactHashId {
+            kind: ArtifactKind::HOST_PHASE_1,
+            hash: ArtifactHash([0; 32]),
+        };
+        requirements.add_served(id.clone()).unwrap();
+        requirements
+            .add_served(ArtifactHashId {
+                kind: ArtifactKind::HOST_PHASE_2,
+                hash: id.hash,
+            })
+            .unwrap();
+        assert_eq!(
+            requirements.add_served(id.clone()),
+            Err(UpdatePlanError::DuplicateHashEntry(id))
+        );
+    }
+
+    #[test]
+    fn test_update_plan_requirements_missing() {
+        let mut requirements = UpdatePlanRequirements::new();
+        for kind in KnownArtifactKind::iter()
+            .filter(|kind| *kind != KnownArtifactKind::Trampoline)
+        {
+            requirements.add_artifact(kind).unwrap();
+        }
+        assert_eq!(
+            requirements.missing_kinds().collect::<Vec<_>>(),
+            vec![KnownArtifactKind::Trampoline]
+        );
+        assert_eq!(
+            requirements.check_complete().unwrap_err().to_string(),
+            "missing artifact of kind `trampoline`"
+        );
+    }
+}
@@ -1,50 +1,209 @@
-use crate::key::Key;
-use anyhow::Result;
+use crate::key::{Key, PublicKey};
+use anyhow::{ensure, Context, Result};
+use camino::Utf8Path;
 use chrono::{DateTime, Utc};
 use ring::rand::SystemRandom;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::num::NonZeroU64;
 use tough::editor::signed::SignedRole;
-use tough::schema::{KeyHolder, RoleKeys, RoleType, Root};
+use tough::schema::{KeyHolder, RoleKeys, RoleType, Root, Signed};
 use tough::sign::Sign;
 
+/// The keys trusted for each top-level role of a repository, and how many of
+/// them must sign it.
+///
+/// Keeping the root role's keys apart from the others means they are only
+/// needed to create or rotate the root, so they can be kept offline.
+///
+/// This is read from a TOML file with a table for each role:
+///
+/// ```toml
+/// [root]
+/// keys = ["ed25519:<hex>", "ed25519:<hex>"]
+/// threshold = 2
+///
+/// [targets]
+/// keys = ["ed25519:<hex>"]
+/// threshold = 1
+///
+/// # and likewise for [snapshot] and [timestamp]
+/// ```
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RootConfig {
+    pub root: RoleConfig,
+    pub targets: RoleConfig,
+    pub snapshot: RoleConfig,
+    pub timestamp: RoleConfig,
+}
+
+/// The keys trusted for a single role.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RoleConfig {
+    /// The public keys allowed to sign this role.
+    pub keys: Vec<PublicKey>,
+
+    /// The number of `keys` that must sign this role.
+    pub threshold: NonZeroU64,
+}
+
+impl RootConfig {
+    /// Returns a config trusting all of `keys` for every role, with a
+    /// threshold of 1.
+    pub fn from_keys(keys: &[Key]) -> Self {
+        let role = RoleConfig {
+            keys: keys.iter().map(Key::public_key).collect(),
+            threshold: NonZeroU64::new(1).unwrap(),
+        };
+        Self {
+            root: role.clone(),
+            targets: role.clone(),
+            snapshot: role.clone(),
+            timestamp: role,
+        }
+    }
+
+    /// Reads a config in from a TOML file.
+    pub fn from_path(path: &Utf8Path) -> Result<Self> {
+        let input = fs_err::read_to_string(path)?;
+        let de = toml::Deserializer::new(&input);
+        let config: Self = serde_path_to_error::deserialize(de)
+            .with_context(|| format!("error parsing root config `{path}`"))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn roles(&self) -> [(RoleType, &'static str, &RoleConfig); 4] {
+        [
+            (RoleType::Root, "root", &self.root),
+            (RoleType::Targets, "targets", &self.targets),
+            (RoleType::Snapshot, "snapshot", &self.snapshot),
+            (RoleType::Timestamp, "timestamp", &self.timestamp),
+        ]
+    }
+
+    fn validate(&self) -> Result<()> {
+        for (_, name, role) in self.roles() {
+            let unique = role.keys.iter().collect::<BTreeSet<_>>();
+            ensure!(
+                unique.len() == role.keys.len(),
+                "{name} role lists the same key more than once"
+            );
+            ensure!(
+                role.threshold.get() <= role.keys.len() as u64,
+                "{name} role has a threshold of {}, but only {} keys",
+                role.threshold,
+                role.keys.len()
+            );
+        }
+        Ok(())
+    }
+
+    pub(crate) fn to_root(
+        &self,
+        version: NonZeroU64,
+        expires: DateTime<Utc>,
+    ) -> Result<Root> {
+        self.validate()?;
+
+        let mut root = Root {
+            spec_version: "1.0.0".to_string(),
+            consistent_snapshot: true,
+            version,
+            expires,
+            keys: HashMap::new(),
+            roles: HashMap::new(),
+            _extra: HashMap::new(),
+        };
+        for (kind, _, role) in self.roles() {
+            let mut keyids = Vec::new();
+            for key in &role.keys {
+                let key = key.tuf_key();
+                let key_id = key.key_id()?;
+                root.keys.insert(key_id.clone(), key);
+                keyids.push(key_id);
+            }
+            root.roles.insert(
+                kind,
+                RoleKeys {
+                    keyids,
+                    threshold: role.threshold,
+                    _extra: HashMap::new(),
+                },
+            );
+        }
+        Ok(root)
+    }
+}
+
 pub(crate) fn new_root(
-    keys: Vec<Key>,
+    config: &RootConfig,
+    keys: &[Key],
     expires: DateTime<Utc>,
 ) -> Result<SignedRole<Root>> {
-    let mut root = Root {
-        spec_version: "1.0.0".to_string(),
-        consistent_snapshot: true,
-        version: NonZeroU64::new(1).unwrap(),
-        expires,
-        keys: HashMap::new(),
-        roles: HashMap::new(),
-        _extra: HashMap::new(),
-    };
-    for key in &keys {
-        let key = key.as_sign().tuf_key();
-        root.keys.insert(key.key_id()?, key);
+    let root = config.to_root(NonZeroU64::new(1).unwrap(), expires)?;
+    sign_root(&root, &root, keys)
+}
+
+/// Creates the root following `current`, trusting the keys in `config`.
+///
+/// The new root is signed both by `current`'s root keys, so that clients
+/// trusting `current` accept it, and by its own.
+pub(crate) fn rotate_root(
+    current: &Root,
+    config: &RootConfig,
+    keys: &[Key],
+    expires: DateTime<Utc>,
+) -> Result<Signed<Root>> {
+    let version =
+        current.version.checked_add(1).context("root version overflowed")?;
+    let mut root = config.to_root(version, expires)?;
+    root.consistent_snapshot = current.consistent_snapshot;
+
+    let mut signed = sign_root(&root, &root, keys)?.signed().clone();
+    let cross_signed = sign_root(&root, current, keys)?;
+    for signature in &cross_signed.signed().signatures {
+        if !signed.signatures.iter().any(|s| s.keyid == signature.keyid) {
+            signed.signatures.push(signature.clone());
+        }
     }
-    for kind in [
-        RoleType::Root,
-        RoleType::Snapshot,
-        RoleType::Targets,
-        RoleType::Timestamp,
-    ] {
-        root.roles.insert(
-            kind,
-            RoleKeys {
-                keyids: root.keys.keys().cloned().collect(),
-                threshold: NonZeroU64::new(1).unwrap(),
-                _extra: HashMap::new(),
-            },
-        );
+    Ok(signed)
+}
+
+// Signs `root` with those of `keys` that `signer` trusts for the root role,
+// making sure there are enough of them to meet its threshold. (tough would
+// happily produce a root that clients then refuse to load.)
+fn sign_root(
+    root: &Root,
+    signer: &Root,
+    keys: &[Key],
+) -> Result<SignedRole<Root>> {
+    let role = signer
+        .roles
+        .get(&RoleType::Root)
+        .context("root does not define the root role")?;
+    let mut trusted = HashSet::new();
+    for key in keys {
+        let key_id = key.tuf_key().key_id()?;
+        if role.keyids.contains(&key_id) {
+            trusted.insert(key_id);
+        }
     }
+    ensure!(
+        trusted.len() as u64 >= role.threshold.get(),
+        "root version {} needs {} signatures from its root keys, \
+         but {} of the provided keys are root keys",
+        signer.version,
+        role.threshold,
+        trusted.len()
+    );
 
-    let keys = crate::key::boxed_keys(keys);
+    let keys = crate::key::boxed_keys(keys.to_vec());
     Ok(SignedRole::new(
         root.clone(),
-        &KeyHolder::Root(root),
+        &KeyHolder::Root(signer.clone()),
         &keys,
         &SystemRandom::new(),
     )?)
@@ -0,0 +1,104 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Support for keys held by an external signing program.
+//!
+//! The program is given a single argument describing what to do:
+//!
+//! * `public`: print the key's public key, in the same `kind:hex` form used
+//!   for root configs (e.g. `ecdsa-p256:04...`), to stdout.
+//! * `sign`: read a message from stdin until EOF, and print its signature,
+//!   hex-encoded, to stdout. ECDSA signatures must be ASN.1 DER-encoded, as
+//!   TUF expects.
+//!
+//! In either case, the program must exit with a non-zero status if it fails.
+//! Anything it prints to stderr is included in the error.
+//!
+//! This is meant as a thin shim in front of an HSM or other hardware-backed
+//! key. `tufaceous key public` and `tufaceous key sign` follow the same
+//! protocol, so a script running `tufaceous --key ... key "$@"` acts as a
+//! signer backed by a local key, which is useful for testing.
+
+use crate::PublicKey;
+use anyhow::{bail, Context, Result};
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+/// A key held by an external signing program.
+#[derive(Debug, Clone)]
+pub struct CommandSigner {
+    program: String,
+    public_key: PublicKey,
+}
+
+impl CommandSigner {
+    /// Creates a signer backed by `program`, which is run to fetch the public
+    /// key.
+    pub fn new(program: impl Into<String>) -> Result<Self> {
+        let program = program.into();
+        let output = Command::new(&program)
+            .arg("public")
+            .stdin(Stdio::null())
+            .output()
+            .with_context(|| format!("error running `{program} public`"))?;
+        let stdout = check_output(&program, "public", output)?;
+        let public_key = String::from_utf8(stdout)
+            .map_err(anyhow::Error::from)
+            .and_then(|s| s.trim().parse())
+            .with_context(|| {
+                format!("`{program} public` printed an invalid public key")
+            })?;
+        Ok(Self { program, public_key })
+    }
+
+    /// Returns the signing program.
+    pub fn program(&self) -> &str {
+        &self.program
+    }
+
+    /// Returns the public key reported by the signing program.
+    pub fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+
+    pub(crate) fn sign(&self, msg: &[u8]) -> Result<Vec<u8>> {
+        let program = &self.program;
+        let mut child = Command::new(program)
+            .arg("sign")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("error running `{program} sign`"))?;
+        // Dropping stdin once the message is written closes it, letting the
+        // program know the message is complete.
+        child
+            .stdin
+            .take()
+            .expect("stdin is piped")
+            .write_all(msg)
+            .with_context(|| format!("error writing to `{program} sign`"))?;
+        let output = child
+            .wait_with_output()
+            .with_context(|| format!("error waiting for `{program} sign`"))?;
+        let stdout = check_output(program, "sign", output)?;
+        String::from_utf8(stdout)
+            .map_err(anyhow::Error::from)
+            .and_then(|s| Ok(hex::decode(s.trim())?))
+            .with_context(|| {
+                format!("`{program} sign` printed an invalid signature")
+            })
+    }
+}
+
+fn check_output(program: &str, arg: &str, output: Output) -> Result<Vec<u8>> {
+    if !output.status.success() {
+        bail!(
+            "`{program} {arg}` failed ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(output.stdout)
+}
@@ -3,13 +3,16 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use anyhow::{bail, Context, Result};
-use camino::Utf8PathBuf;
+use camino::{Utf8Path, Utf8PathBuf};
+use camino_tempfile::Utf8TempDir;
 use chrono::{DateTime, Utc};
-use clap::{CommandFactory, Parser};
+use clap::{CommandFactory, Parser, ValueEnum};
 use omicron_common::{api::external::SemverVersion, update::ArtifactKind};
+use std::io::Read;
 use tufaceous_lib::{
     assemble::{ArtifactManifest, OmicronRepoAssembler},
-    AddArtifact, ArchiveExtractor, Key, OmicronRepo,
+    AddArtifact, ArchiveExtractor, Key, OmicronRepo, RepositorySummary,
+    RootConfig,
 };
 
 #[derive(Debug, Parser)]
@@ -43,13 +46,23 @@ impl Args {
         };
 
         match self.command {
-            Command::Init { system_version, no_generate_key } => {
-                let keys = maybe_generate_keys(self.keys, no_generate_key);
+            Command::Init { system_version, no_generate_key, root_config } => {
+                // A generated key won't be in the root config, so there's no
+                // point generating one if a config is provided.
+                let keys = maybe_generate_keys(
+                    self.keys,
+                    no_generate_key || root_config.is_some(),
+                );
+                let root_config = match root_config {
+                    Some(path) => RootConfig::from_path(&path)?,
+                    None => RootConfig::from_keys(&keys),
+                };
 
                 let repo = OmicronRepo::initialize(
                     &log,
                     &repo_path,
                     system_version,
+                    &root_config,
                     keys,
                     self.expiry,
                 )?;
@@ -145,6 +158,7 @@ impl Args {
                 build_dir,
                 no_generate_key,
                 skip_all_present,
+                root_config,
             } => {
                 // The filename must end with "zip".
                 if output_path.extension() != Some("zip") {
@@ -157,7 +171,10 @@ impl Args {
                     manifest.verify_all_present()?;
                 }
 
-                let keys = maybe_generate_keys(self.keys, no_generate_key);
+                let keys = maybe_generate_keys(
+                    self.keys,
+                    no_generate_key || root_config.is_some(),
+                );
                 let mut assembler = OmicronRepoAssembler::new(
                     &log,
                     manifest,
@@ -168,11 +185,99 @@ impl Args {
                 if let Some(dir) = build_dir {
                     assembler.set_build_dir(dir);
                 }
+                if let Some(path) = root_config {
+                    assembler.set_root_config(RootConfig::from_path(&path)?);
+                }
 
                 assembler.build()?;
 
                 Ok(())
             }
+            Command::Diff { old, new } => {
+                // We're only comparing the artifacts, so don't fail on an
+                // expired repository.
+                let load = |dir: &Utf8Path| {
+                    OmicronRepo::load_untrusted_ignore_expiration(&log, dir)
+                };
+                let (_old_dir, old_repo) = extract_archive(&old, load)?;
+                let (_new_dir, new_repo) = extract_archive(&new, load)?;
+                let old_summary = RepositorySummary::from_repo(&old_repo)?;
+                let new_summary = RepositorySummary::from_repo(&new_repo)?;
+
+                let diff = old_summary.diff(&new_summary);
+                if diff.is_empty() {
+                    println!("no differences");
+                } else {
+                    print!("{diff}");
+                }
+                Ok(())
+            }
+            Command::Verify { archive_file, trusted_root } => {
+                // Loading the repository checks its signatures and
+                // expiration.
+                let trusted_root =
+                    trusted_root.map(fs_err::read).transpose()?;
+                let load = |dir: &Utf8Path| match &trusted_root {
+                    Some(root) => OmicronRepo::load(&log, dir, root),
+                    None => OmicronRepo::load_untrusted(&log, dir),
+                };
+                let (_dir, repo) = extract_archive(&archive_file, load)?;
+
+                let summary = RepositorySummary::from_repo(&repo)?;
+                let problems = summary.verify();
+                if !problems.is_empty() {
+                    for problem in &problems {
+                        eprintln!("error: {problem}");
+                    }
+                    bail!(
+                        "repository `{archive_file}` has {} problem(s)",
+                        problems.len()
+                    );
+                }
+
+                println!(
+                    "repository `{archive_file}` is valid: system version {}, \
+                     {} artifacts",
+                    summary.system_version,
+                    summary.artifacts.len()
+                );
+                Ok(())
+            }
+            Command::Root { command: RootCommand::Rotate { root_config } } => {
+                let root_config = RootConfig::from_path(&root_config)?;
+
+                let repo = OmicronRepo::load_untrusted_ignore_expiration(
+                    &log, &repo_path,
+                )?;
+                repo.rotate_root(&root_config, self.keys, self.expiry)?;
+
+                Ok(())
+            }
+            Command::Key { command: KeyCommand::Generate { kind } } => {
+                let key = match kind {
+                    KeyKind::Ed25519 => Key::generate_ed25519(),
+                    KeyKind::EcdsaP256 => Key::generate_ecdsa_p256(),
+                };
+                println!("{key}");
+                Ok(())
+            }
+            Command::Key { command: KeyCommand::Public } => {
+                for key in &self.keys {
+                    println!("{}", key.public_key());
+                }
+                Ok(())
+            }
+            Command::Key { command: KeyCommand::Sign } => {
+                let [key] = &self.keys[..] else {
+                    bail!("exactly one key must be provided to sign with");
+                };
+                let mut msg = Vec::new();
+                std::io::stdin()
+                    .read_to_end(&mut msg)
+                    .context("error reading message from stdin")?;
+                println!("{}", hex::encode(key.sign_message(&msg)?));
+                Ok(())
+            }
         }
     }
 }
@@ -187,6 +292,11 @@ enum Command {
         /// Disable random key generation and exit if no keys are provided
         #[clap(long)]
         no_generate_key: bool,
+
+        /// Path to a config listing the keys and thresholds for each role
+        /// [default: trust all provided keys for every role]
+        #[clap(long)]
+        root_config: Option<Utf8PathBuf>,
     },
     Add {
         /// The kind of artifact this is.
@@ -238,7 +348,94 @@ enum Command {
         /// Skip checking to ensure all expected artifacts are present.
         #[clap(long)]
         skip_all_present: bool,
+
+        /// Path to a config listing the keys and thresholds for each role
+        /// [default: trust all provided keys for every role]
+        #[clap(long)]
+        root_config: Option<Utf8PathBuf>,
+    },
+    /// Compares the artifacts in two archived repositories.
+    Diff {
+        /// The older repository archive.
+        old: Utf8PathBuf,
+
+        /// The newer repository archive.
+        new: Utf8PathBuf,
+    },
+    /// Checks that an archived repository is valid and complete.
+    ///
+    /// This checks the repository's signatures and expiration, the hash of
+    /// every target, and that the artifacts are what wicketd needs to build an
+    /// update plan.
+    Verify {
+        /// The archive to verify.
+        archive_file: Utf8PathBuf,
+
+        /// A root the repository must chain to [default: the repository's own
+        /// 1.root.json]
+        #[clap(long)]
+        trusted_root: Option<Utf8PathBuf>,
+    },
+    /// Manages the root of an existing repository.
+    Root {
+        #[clap(subcommand)]
+        command: RootCommand,
     },
+    /// Works with signing keys.
+    Key {
+        #[clap(subcommand)]
+        command: KeyCommand,
+    },
+}
+
+#[derive(Debug, Parser)]
+enum RootCommand {
+    /// Replaces the root with a new version, cross-signed by the current one.
+    ///
+    /// The keys provided must include enough root keys from both the current
+    /// and new roots, as well as keys for the new root's other roles.
+    Rotate {
+        /// Path to a config listing the keys and thresholds for each role in
+        /// the new root.
+        root_config: Utf8PathBuf,
+    },
+}
+
+#[derive(Debug, Parser)]
+enum KeyCommand {
+    /// Generates a new key and prints it.
+    Generate {
+        /// The kind of key to generate.
+        #[clap(long, value_enum, default_value_t = KeyKind::Ed25519)]
+        kind: KeyKind,
+    },
+    /// Prints the public key for each provided key, for use in a root config.
+    Public,
+    /// Signs a message read from stdin, printing the signature as hex.
+    ///
+    /// Together with `public`, this implements the external signer protocol,
+    /// so tufaceous can stand in for a hardware-backed signer.
+    Sign,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum KeyKind {
+    Ed25519,
+    EcdsaP256,
+}
+
+// Extracts an archived repository into a temporary directory, which must be
+// kept around for as long as the repository is used, and loads it with `load`.
+fn extract_archive(
+    archive_file: &Utf8Path,
+    load: impl FnOnce(&Utf8Path) -> Result<OmicronRepo>,
+) -> Result<(Utf8TempDir, OmicronRepo)> {
+    let dir = camino_tempfile::tempdir()?;
+    ArchiveExtractor::from_path(archive_file)?.extract(dir.path())?;
+    let repo = load(dir.path()).with_context(|| {
+        format!("error loading repository `{archive_file}`")
+    })?;
+    Ok((dir, repo))
 }
 
 fn maybe_generate_keys(keys: Vec<Key>, no_generate_key: bool) -> Vec<Key> {
@@ -2,6 +2,7 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use std::os::unix::fs::PermissionsExt;
 use std::path::Path;
 
 use anyhow::Result;
@@ -136,6 +137,201 @@ fn test_assemble_fake() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_verify_and_diff() -> Result<()> {
+    let logctx = test_setup_log("test_verify_and_diff");
+    let tempdir = tempfile::tempdir().unwrap();
+    let key = Key::generate_ed25519();
+
+    let manifest = fs_err::read_to_string("manifests/fake.toml")?;
+    let assemble = |manifest: &str, name: &str| -> Result<_> {
+        let manifest_path = tempdir.path().join(format!("{name}.toml"));
+        fs_err::write(&manifest_path, manifest)?;
+        let archive_path = tempdir.path().join(format!("{name}.zip"));
+        let mut cmd = make_cmd(&key);
+        cmd.args(["assemble", "--skip-all-present"]);
+        cmd.arg(&manifest_path);
+        cmd.arg(&archive_path);
+        cmd.assert().success();
+        Ok(archive_path)
+    };
+
+    let old_path = assemble(&manifest, "old")?;
+    let mut cmd = make_cmd(&key);
+    cmd.arg("verify");
+    cmd.arg(&old_path);
+    cmd.assert().success().stdout(predicate::str::contains("is valid"));
+
+    // Bump the system version and change the host OS's phase 2 image (the
+    // first `phase_2` in the manifest). Composite artifacts are rebuilt with
+    // new timestamps, so their hashes change regardless, but the images inside
+    // the trampoline artifact don't.
+    let new_manifest = manifest
+        .replacen("system_version = \"1.0.0\"", "system_version = \"2.0.0\"", 1)
+        .replacen(
+            "phase_2 = { kind = \"fake\", size = \"3MiB\" }",
+            "phase_2 = { kind = \"fake\", size = \"4MiB\" }",
+            1,
+        );
+    let new_path = assemble(&new_manifest, "new")?;
+
+    let mut cmd = make_cmd(&key);
+    cmd.arg("diff");
+    cmd.arg(&old_path);
+    cmd.arg(&new_path);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("system version: 1.0.0 -> 2.0.0"))
+        .stdout(predicate::str::contains("changed host fake-host"))
+        .stdout(predicate::str::contains("changed host_phase_2"))
+        .stdout(predicate::str::contains("changed trampoline_phase").not());
+
+    // A repository without a trampoline image is signed correctly, but
+    // wicketd couldn't use it.
+    let start = manifest.find("[[artifact.trampoline]]").unwrap();
+    let end = manifest.find("[[artifact.control_plane]]").unwrap();
+    let incomplete_manifest =
+        format!("{}{}", &manifest[..start], &manifest[end..]);
+    let incomplete_path = assemble(&incomplete_manifest, "incomplete")?;
+
+    let mut cmd = make_cmd(&key);
+    cmd.arg("verify");
+    cmd.arg(&incomplete_path);
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "missing artifact of kind `trampoline`",
+    ));
+
+    // Verifying against a root the repository doesn't chain to fails too.
+    let other_repo = tempdir.path().join("other");
+    let mut cmd = make_cmd(&Key::generate_ed25519());
+    cmd.arg("--repo").arg(&other_repo).args(["init", "0.0.0"]);
+    cmd.assert().success();
+    let mut cmd = make_cmd(&key);
+    cmd.arg("verify");
+    cmd.arg(&old_path);
+    cmd.arg("--trusted-root").arg(other_repo.join("metadata/1.root.json"));
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("error loading repository"));
+
+    logctx.cleanup_successful();
+    Ok(())
+}
+
+#[test]
+fn test_root_rotate() -> Result<()> {
+    let logctx = test_setup_log("test_root_rotate");
+    let tempdir = tempfile::tempdir().unwrap();
+    let old_key = Key::generate_ed25519();
+    let new_key = Key::generate_ed25519();
+
+    let mut cmd = make_cmd_with_repo(tempdir.path(), &old_key);
+    cmd.args(["init", "0.0.0"]);
+    cmd.assert().success();
+
+    let mut cmd = make_cmd(&new_key);
+    cmd.args(["key", "public"]);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let public_key = String::from_utf8(output)?.trim().to_owned();
+    assert_eq!(public_key, new_key.public_key().to_string());
+
+    let role = format!("keys = [\"{public_key}\"]\nthreshold = 1\n");
+    let config_path = tempdir.path().join("root.toml");
+    fs_err::write(
+        &config_path,
+        format!(
+            "[root]\n{role}\n[targets]\n{role}\n\
+             [snapshot]\n{role}\n[timestamp]\n{role}"
+        ),
+    )?;
+
+    // The new root must be signed by the old root key too.
+    let mut cmd = make_cmd_with_repo(tempdir.path(), &new_key);
+    cmd.args(["root", "rotate"]);
+    cmd.arg(&config_path);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("root version 1 needs"));
+
+    let mut cmd = make_cmd_with_repo(tempdir.path(), &new_key);
+    cmd.args(["root", "rotate"]);
+    cmd.arg(&config_path);
+    cmd.arg("-k").arg(old_key.to_string());
+    cmd.arg("-k").arg(new_key.to_string());
+    cmd.assert().success();
+
+    let repo_path: Utf8PathBuf = tempdir.path().join("repo").try_into()?;
+    let repo = OmicronRepo::load_untrusted(&logctx.log, &repo_path)?;
+    assert_eq!(repo.repo().root().signed.version.get(), 2);
+
+    // From now on, the old key is no longer needed.
+    let nexus_path = tempdir.path().join("omicron-nexus.tar.gz");
+    fs_err::write(&nexus_path, "test")?;
+    let mut cmd = make_cmd_with_repo(tempdir.path(), &new_key);
+    cmd.args(["add", "gimlet_sp"]);
+    cmd.arg(&nexus_path);
+    cmd.arg("42.0.0");
+    cmd.assert().success();
+
+    logctx.cleanup_successful();
+    Ok(())
+}
+
+#[test]
+fn test_external_signer() -> Result<()> {
+    let logctx = test_setup_log("test_external_signer");
+    let tempdir = tempfile::tempdir().unwrap();
+
+    let mut cmd = make_cmd(&Key::generate_ed25519());
+    cmd.args(["key", "generate", "--kind", "ecdsa-p256"]);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let ecdsa_key: Key = String::from_utf8(output)?.trim().parse()?;
+
+    // Stand in for an HSM with a script that signs using tufaceous itself.
+    let script_path = tempdir.path().join("signer.sh");
+    fs_err::write(
+        &script_path,
+        format!(
+            "#!/bin/sh\nexec '{}' --key '{ecdsa_key}' key \"$@\"\n",
+            assert_cmd::cargo::cargo_bin("tufaceous").display()
+        ),
+    )?;
+    fs_err::set_permissions(
+        &script_path,
+        std::fs::Permissions::from_mode(0o755),
+    )?;
+    let key: Key = format!("command:{}", script_path.display()).parse()?;
+    assert_eq!(key.public_key(), ecdsa_key.public_key());
+
+    let mut cmd = make_cmd_with_repo(tempdir.path(), &key);
+    cmd.args(["init", "0.0.0"]);
+    cmd.assert().success();
+
+    let nexus_path = tempdir.path().join("omicron-nexus.tar.gz");
+    fs_err::write(&nexus_path, "test")?;
+    let mut cmd = make_cmd_with_repo(tempdir.path(), &key);
+    cmd.args(["add", "gimlet_sp"]);
+    cmd.arg(&nexus_path);
+    cmd.arg("42.0.0");
+    cmd.assert().success();
+
+    let repo_path: Utf8PathBuf = tempdir.path().join("repo").try_into()?;
+    let repo = OmicronRepo::load_untrusted(&logctx.log, &repo_path)?;
+    assert_eq!(repo.read_artifacts()?.artifacts.len(), 1);
+    let root = fs_err::read_to_string(repo_path.join("metadata/1.root.json"))?;
+    assert!(root.contains("ecdsa-sha2-nistp256"), "unexpected root: {root}");
+
+    // The key can also sign directly, without going through the signer.
+    let mut cmd = make_cmd_with_repo(tempdir.path(), &ecdsa_key);
+    cmd.args(["add", "gimlet_sp"]);
+    cmd.arg(&nexus_path);
+    cmd.arg("43.0.0");
+    cmd.assert().success();
+
+    logctx.cleanup_successful();
+    Ok(())
+}
+
 fn make_cmd(key: &Key) -> Command {
     let mut cmd = Command::cargo_bin("tufaceous").unwrap();
     cmd.env("TUFACEOUS_KEY", key.to_string());
@@ -32,6 +32,7 @@ pub enum Event {
     ArtifactsAndEventReports {
         system_version: Option<SemverVersion>,
         artifacts: Vec<ArtifactId>,
+        repository_error: Option<String>,
         event_reports: EventReportMap,
     },
 
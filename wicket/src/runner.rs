@@ -136,6 +136,7 @@ impl RunnerCore {
             Event::ArtifactsAndEventReports {
                 system_version,
                 artifacts,
+                repository_error,
                 event_reports,
             } => {
                 self.state.service_status.reset_wicketd(Duration::ZERO);
@@ -144,6 +145,7 @@ impl RunnerCore {
                     &self.log,
                     system_version,
                     artifacts,
+                    repository_error,
                     event_reports,
                 );
                 self.screen.draw(&self.state, &mut self.terminal)?;
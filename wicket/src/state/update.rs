@@ -23,6 +23,9 @@ pub struct RackUpdateState {
     pub items: BTreeMap<ComponentId, UpdateItem>,
     pub system_version: Option<SemverVersion>,
     pub artifacts: Vec<ArtifactId>,
+    // The reason wicketd rejected the most recently uploaded repository, if
+    // it did.
+    pub repository_error: Option<String>,
     pub artifact_versions: BTreeMap<KnownArtifactKind, SemverVersion>,
     // The update item currently selected is recorded in
     // state.rack_state.selected.
@@ -64,6 +67,7 @@ impl RackUpdateState {
                 })
                 .collect(),
             artifacts: vec![],
+            repository_error: None,
             artifact_versions: BTreeMap::default(),
             status_view_displayed: false,
         }
@@ -91,10 +95,12 @@ impl RackUpdateState {
         logger: &Logger,
         system_version: Option<SemverVersion>,
         artifacts: Vec<ArtifactId>,
+        repository_error: Option<String>,
         reports: EventReportMap,
     ) {
         self.system_version = system_version;
         self.artifacts = artifacts;
+        self.repository_error = repository_error;
         self.artifact_versions.clear();
         for id in &mut self.artifacts {
             if let Ok(known) = id.kind.parse() {
@@ -1446,7 +1446,7 @@ impl UpdatePane {
                 rect.height += 3;
 
                 // Show this command.
-                let text = Text::from(vec![
+                let mut text = Text::from(vec![
                     Line::from(Vec::new()),
                     Line::from(vec![Span::styled(
                         "Use the following command to transfer an update:",
@@ -1464,7 +1464,32 @@ impl UpdatePane {
                         Span::styled(" upload", style::plain_text()),
                     ]),
                 ]);
-                let paragraph = Paragraph::new(text)
+
+                // If the last repository was rejected (for example, because
+                // it isn't signed by a trusted root), say why.
+                if let Some(error) = &state.update_state.repository_error {
+                    text.extend([
+                        Line::from(Vec::new()),
+                        Line::from(vec![Span::styled(
+                            "The last uploaded repository was rejected:",
+                            style::text_failure(),
+                        )]),
+                        Line::from(vec![Span::styled(
+                            error.clone(),
+                            style::plain_text(),
+                        )]),
+                    ]);
+                }
+
+                // Wrap the text to the screen width.
+                let options = crate::ui::wrap::Options {
+                    // Subtract 2 for borders.
+                    width: rect.width.saturating_sub(2) as usize,
+                    initial_indent: Span::raw(""),
+                    subsequent_indent: Span::raw(""),
+                    break_words: true,
+                };
+                let paragraph = Paragraph::new(wrap_text(&text, options))
                     .alignment(Alignment::Center)
                     .block(block.clone().title("AWAITING REPOSITORY"));
                 frame.render_widget(paragraph, rect);
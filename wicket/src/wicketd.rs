@@ -452,10 +452,12 @@ impl WicketdManager {
                             .map(|artifact| artifact.artifact_id)
                             .collect();
                         let system_version = rsp.system_version;
+                        let repository_error = rsp.repository_error;
                         let event_reports: EventReportMap = rsp.event_reports;
                         let _ = tx.send(Event::ArtifactsAndEventReports {
                             system_version,
                             artifacts,
+                            repository_error,
                             event_reports,
                         });
                     }
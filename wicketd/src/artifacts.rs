@@ -10,11 +10,13 @@ mod error;
 mod extracted_artifacts;
 mod server;
 mod store;
+mod trusted_root;
 mod update_plan;
 
 pub(crate) use self::extracted_artifacts::ExtractedArtifactDataHandle;
 pub(crate) use self::server::WicketdArtifactServer;
 pub(crate) use self::store::WicketdArtifactStore;
+pub(crate) use self::trusted_root::TrustedRoot;
 pub use self::update_plan::UpdatePlan;
 
 /// A pair containing both the ID of an artifact and a handle to its data.
@@ -5,13 +5,16 @@
 use super::error::RepositoryError;
 use super::update_plan::UpdatePlanBuilder;
 use super::ExtractedArtifactDataHandle;
+use super::TrustedRoot;
 use super::UpdatePlan;
+use camino::Utf8Path;
 use camino_tempfile::Utf8TempDir;
 use debug_ignore::DebugIgnore;
 use omicron_common::update::ArtifactHash;
 use omicron_common::update::ArtifactHashId;
 use omicron_common::update::ArtifactId;
 use slog::info;
+use slog::warn;
 use slog::Logger;
 use std::collections::BTreeMap;
 use std::collections::HashMap;
@@ -52,6 +55,8 @@ pub(super) struct ArtifactsWithPlan {
 impl ArtifactsWithPlan {
     pub(super) fn from_zip<T>(
         zip_data: T,
+        trusted_roots: &[TrustedRoot],
+        allow_untrusted: bool,
         log: &Logger,
     ) -> Result<Self, RepositoryError>
     where
@@ -63,12 +68,8 @@ impl ArtifactsWithPlan {
         // Time is unavailable during initial setup, so ignore expiration. Even
         // if time were available, we might want to be able to load older
         // versions of artifacts over the technician port in an emergency.
-        //
-        // XXX we aren't checking against a root of trust at this point --
-        // anyone can sign the repositories and this code will accept that.
         let repository =
-            OmicronRepo::load_untrusted_ignore_expiration(log, dir.path())
-                .map_err(RepositoryError::LoadRepository)?;
+            load_trusted(dir.path(), trusted_roots, allow_untrusted, log)?;
 
         let artifacts = repository
             .read_artifacts()
@@ -182,6 +183,49 @@ impl ArtifactsWithPlan {
     }
 }
 
+// Load the repository at `path`, which must chain to one of `trusted_roots`.
+//
+// If no roots are configured, every repository is rejected, unless
+// `allow_untrusted` is set (as it may be on development systems), in which case
+// we trust the root shipped in the repository itself, which anyone can sign.
+fn load_trusted(
+    path: &Utf8Path,
+    trusted_roots: &[TrustedRoot],
+    allow_untrusted: bool,
+    log: &Logger,
+) -> Result<OmicronRepo, RepositoryError> {
+    if trusted_roots.is_empty() {
+        if !allow_untrusted {
+            return Err(RepositoryError::NoTrustedRoots);
+        }
+        warn!(
+            log,
+            "no trusted TUF roots configured, \
+             trusting the root in the uploaded repository"
+        );
+        return OmicronRepo::load_untrusted_ignore_expiration(log, path)
+            .map_err(RepositoryError::LoadRepository);
+    }
+
+    let mut errors = Vec::with_capacity(trusted_roots.len());
+    for root in trusted_roots {
+        match OmicronRepo::load_ignore_expiration(log, path, root.contents()) {
+            Ok(repository) => {
+                info!(
+                    log, "repository chains to trusted root";
+                    "root" => %root.path().display(),
+                );
+                return Ok(repository);
+            }
+            Err(error) => {
+                errors.push(format!("{}: {error:#}", root.path().display()))
+            }
+        }
+    }
+    warn!(log, "repository does not chain to any trusted root");
+    Err(RepositoryError::UntrustedRepository(errors))
+}
+
 fn unzip_into_tempdir<T>(
     zip_data: T,
     log: &Logger,
@@ -215,31 +259,74 @@ mod tests {
     };
     use omicron_test_utils::dev::test_setup_log;
     use std::collections::BTreeSet;
+    use tufaceous_lib::{Key, RootConfig};
 
-    /// Test that `ArtifactsWithPlan` can extract the fake repository generated
-    /// by tufaceous.
-    #[test]
-    fn test_extract_fake() -> Result<()> {
-        let logctx = test_setup_log("test_extract_fake");
-        let temp_dir = Utf8TempDir::new()?;
-        let archive_path = temp_dir.path().join("archive.zip");
-
-        // Create the archive.
+    // Create the fake repository generated by tufaceous, signed with `key`.
+    fn assemble_fake(
+        archive_path: &Utf8Path,
+        key: &Key,
+        log: &Logger,
+    ) -> Result<()> {
         let args = tufaceous::Args::try_parse_from([
             "tufaceous",
+            "--key",
+            &key.to_string(),
             "assemble",
             "../tufaceous/manifests/fake.toml",
             archive_path.as_str(),
         ])
         .context("error parsing args")?;
 
-        args.exec(&logctx.log).context("error executing assemble command")?;
+        args.exec(log).context("error executing assemble command")
+    }
+
+    // Create a root document trusting `key`, in a new repository in `dir`.
+    fn make_trusted_root(
+        dir: &Utf8Path,
+        key: &Key,
+        log: &Logger,
+    ) -> Result<TrustedRoot> {
+        let repo_path = dir.join("root");
+        let args = tufaceous::Args::try_parse_from([
+            "tufaceous",
+            "--key",
+            &key.to_string(),
+            "--repo",
+            repo_path.as_str(),
+            "init",
+            "1.0.0",
+        ])
+        .context("error parsing args")?;
+        args.exec(log).context("error executing init command")?;
+
+        let root_path = repo_path.join("metadata").join("1.root.json");
+        TrustedRoot::read(root_path.as_std_path()).map_err(anyhow::Error::msg)
+    }
+
+    /// Test that `ArtifactsWithPlan` can extract the fake repository generated
+    /// by tufaceous.
+    #[test]
+    fn test_extract_fake() -> Result<()> {
+        let logctx = test_setup_log("test_extract_fake");
+        let temp_dir = Utf8TempDir::new()?;
+        let archive_path = temp_dir.path().join("archive.zip");
+
+        // Create the archive, and a root that it chains to.
+        let key = Key::generate_ed25519();
+        assemble_fake(&archive_path, &key, &logctx.log)?;
+        let trusted_roots =
+            [make_trusted_root(temp_dir.path(), &key, &logctx.log)?];
 
         // Now check that it can be read by the archive extractor.
         let zip_bytes = std::fs::File::open(&archive_path)
             .context("error opening archive.zip")?;
-        let plan = ArtifactsWithPlan::from_zip(zip_bytes, &logctx.log)
-            .context("error reading archive.zip")?;
+        let plan = ArtifactsWithPlan::from_zip(
+            zip_bytes,
+            &trusted_roots,
+            false,
+            &logctx.log,
+        )
+        .context("error reading archive.zip")?;
         // Check that all known artifact kinds are present in the map.
         let by_id_kinds: BTreeSet<_> =
             plan.by_id().keys().map(|id| id.kind.clone()).collect();
@@ -300,4 +387,128 @@ mod tests {
 
         Ok(())
     }
+
+    /// Test that `ArtifactsWithPlan` accepts a repository whose root has been
+    /// rotated since the trusted root.
+    #[test]
+    fn test_extract_rotated() -> Result<()> {
+        let logctx = test_setup_log("test_extract_rotated");
+        let temp_dir = Utf8TempDir::new()?;
+        let build_dir = temp_dir.path().join("build");
+        let archive_path = temp_dir.path().join("archive.zip");
+        let old_key = Key::generate_ed25519();
+        let new_key = Key::generate_ed25519();
+
+        // Assemble a repository, keeping it around so its root can be rotated
+        // to one trusting only `new_key`.
+        let args = tufaceous::Args::try_parse_from([
+            "tufaceous",
+            "--key",
+            &old_key.to_string(),
+            "assemble",
+            "../tufaceous/manifests/fake.toml",
+            temp_dir.path().join("unrotated.zip").as_str(),
+            "--build-dir",
+            build_dir.as_str(),
+        ])
+        .context("error parsing args")?;
+        args.exec(&logctx.log).context("error executing assemble command")?;
+        let trusted_roots = [TrustedRoot::read(
+            build_dir.join("metadata").join("1.root.json").as_std_path(),
+        )
+        .map_err(anyhow::Error::msg)?];
+
+        let config_path = temp_dir.path().join("root.toml");
+        std::fs::write(
+            &config_path,
+            toml::to_string(&RootConfig::from_keys(&[new_key.clone()]))?,
+        )?;
+        for command in [
+            vec!["root", "rotate", config_path.as_str()],
+            vec!["archive", archive_path.as_str()],
+        ] {
+            let args = tufaceous::Args::try_parse_from(
+                [
+                    "tufaceous",
+                    "--key",
+                    &old_key.to_string(),
+                    "--key",
+                    &new_key.to_string(),
+                    "--repo",
+                    build_dir.as_str(),
+                ]
+                .into_iter()
+                .chain(command),
+            )
+            .context("error parsing args")?;
+            args.exec(&logctx.log).context("error executing command")?;
+        }
+
+        let zip_bytes = std::fs::File::open(&archive_path)
+            .context("error opening archive.zip")?;
+        ArtifactsWithPlan::from_zip(
+            zip_bytes,
+            &trusted_roots,
+            false,
+            &logctx.log,
+        )
+        .context("error reading archive.zip")?;
+
+        logctx.cleanup_successful();
+
+        Ok(())
+    }
+
+    /// Test that `ArtifactsWithPlan` rejects repositories that don't chain to
+    /// a trusted root.
+    #[test]
+    fn test_extract_untrusted() -> Result<()> {
+        let logctx = test_setup_log("test_extract_untrusted");
+        let temp_dir = Utf8TempDir::new()?;
+        let archive_path = temp_dir.path().join("archive.zip");
+
+        // Sign the archive with a different key than the trusted root's.
+        assemble_fake(&archive_path, &Key::generate_ed25519(), &logctx.log)?;
+        let trusted_roots = [make_trusted_root(
+            temp_dir.path(),
+            &Key::generate_ed25519(),
+            &logctx.log,
+        )?];
+
+        let zip_bytes = std::fs::File::open(&archive_path)
+            .context("error opening archive.zip")?;
+        let error = ArtifactsWithPlan::from_zip(
+            zip_bytes,
+            &trusted_roots,
+            false,
+            &logctx.log,
+        )
+        .expect_err("untrusted repository should be rejected");
+        assert!(
+            matches!(error, RepositoryError::UntrustedRepository(ref errors) if errors.len() == 1),
+            "unexpected error: {error}"
+        );
+
+        // Without any trusted roots, every repository is rejected...
+        let zip_bytes = std::fs::File::open(&archive_path)
+            .context("error opening archive.zip")?;
+        let error =
+            ArtifactsWithPlan::from_zip(zip_bytes, &[], false, &logctx.log)
+                .expect_err("repository should be rejected");
+        assert!(
+            matches!(error, RepositoryError::NoTrustedRoots),
+            "unexpected error: {error}"
+        );
+
+        // ... unless untrusted repositories are explicitly allowed, in which
+        // case the repository's own root is trusted.
+        let zip_bytes = std::fs::File::open(&archive_path)
+            .context("error opening archive.zip")?;
+        ArtifactsWithPlan::from_zip(zip_bytes, &[], true, &logctx.log)
+            .context("repository should be accepted when allowed")?;
+
+        logctx.cleanup_successful();
+
+        Ok(())
+    }
 }
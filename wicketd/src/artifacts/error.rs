@@ -6,9 +6,10 @@ use camino::Utf8PathBuf;
 use display_error_chain::DisplayErrorChain;
 use dropshot::HttpError;
 use omicron_common::api::internal::nexus::KnownArtifactKind;
-use omicron_common::update::{ArtifactHashId, ArtifactId, ArtifactKind};
+use omicron_common::update::{ArtifactId, ArtifactKind};
 use slog::error;
 use thiserror::Error;
+use tufaceous_lib::UpdatePlanError;
 
 #[derive(Debug, Error)]
 pub(super) enum RepositoryError {
@@ -28,9 +29,17 @@ pub(super) enum RepositoryError {
     #[error("error extracting repository")]
     Extract(#[source] anyhow::Error),
 
+    #[error("wicketd has no trusted TUF roots configured")]
+    NoTrustedRoots,
+
     #[error("error loading repository")]
     LoadRepository(#[source] anyhow::Error),
 
+    #[error(
+        "repository does not chain to any trusted root: {}", .0.join("; ")
+    )]
+    UntrustedRepository(Vec<String>),
+
     #[error("error reading artifacts.json")]
     ReadArtifactsDocument(#[source] anyhow::Error),
 
@@ -70,12 +79,6 @@ pub(super) enum RepositoryError {
         error: anyhow::Error,
     },
 
-    #[error("multiple artifacts found for kind `{0:?}`")]
-    DuplicateArtifactKind(KnownArtifactKind),
-
-    #[error("duplicate board found for kind `{kind:?}`: `{board}`")]
-    DuplicateBoardEntry { board: String, kind: KnownArtifactKind },
-
     #[error("error parsing artifact {id:?} as hubris archive")]
     ParsingHubrisArchive {
         id: ArtifactId,
@@ -102,13 +105,8 @@ pub(super) enum RepositoryError {
     )]
     ReadHubrisCabooseBoardUtf8(ArtifactId),
 
-    #[error("missing artifact of kind `{0:?}`")]
-    MissingArtifactKind(KnownArtifactKind),
-
-    #[error(
-        "duplicate hash entries found in artifacts.json for kind `{}`, hash `{}`", .0.kind, .0.hash
-    )]
-    DuplicateHashEntry(ArtifactHashId),
+    #[error(transparent)]
+    UpdatePlan(#[from] UpdatePlanError),
 }
 
 impl RepositoryError {
@@ -119,18 +117,17 @@ impl RepositoryError {
             // Errors we had that are unrelated to the contents of a repository
             // uploaded by a client.
             RepositoryError::TempDirCreate(_)
-            | RepositoryError::TempFileCreate { .. } => {
+            | RepositoryError::TempFileCreate { .. }
+            | RepositoryError::NoTrustedRoots => {
                 HttpError::for_unavail(None, message)
             }
 
             // Errors that are definitely caused by bad repository contents.
-            RepositoryError::DuplicateArtifactKind(_)
+            RepositoryError::UntrustedRepository(_)
             | RepositoryError::LocateTarget { .. }
             | RepositoryError::TargetHashLength(_)
-            | RepositoryError::MissingArtifactKind(_)
             | RepositoryError::MissingTarget(_)
-            | RepositoryError::DuplicateHashEntry(_)
-            | RepositoryError::DuplicateBoardEntry { .. }
+            | RepositoryError::UpdatePlan(_)
             | RepositoryError::ParsingHubrisArchive { .. }
             | RepositoryError::ReadHubrisCaboose { .. }
             | RepositoryError::ReadHubrisCabooseBoard { .. }
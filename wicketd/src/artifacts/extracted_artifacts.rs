@@ -19,8 +19,11 @@ use std::io;
 use std::io::BufWriter;
 use std::io::Read;
 use std::io::Write;
+use std::ops::Range;
 use std::sync::Arc;
 use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncSeekExt;
 use tokio_util::io::ReaderStream;
 
 /// Handle to the data of an extracted artifact.
@@ -84,6 +87,26 @@ impl ExtractedArtifactDataHandle {
 
         Ok(ReaderStream::new(file))
     }
+
+    /// Async stream to read the bytes of this artifact in `range` on demand.
+    ///
+    /// `range` must lie within the artifact. This can fail for the same
+    /// reasons as `reader_stream()`.
+    pub(crate) async fn range_reader_stream(
+        &self,
+        range: Range<u64>,
+    ) -> anyhow::Result<ReaderStream<impl AsyncRead>> {
+        let path = path_for_artifact(&self.tempdir, &self.hash_id);
+
+        let mut file = tokio::fs::File::open(&path)
+            .await
+            .with_context(|| format!("failed to open {path}"))?;
+        file.seek(io::SeekFrom::Start(range.start))
+            .await
+            .with_context(|| format!("failed to seek in {path}"))?;
+
+        Ok(ReaderStream::new(file.take(range.end - range.start)))
+    }
 }
 
 /// `ExtractedArtifacts` is a temporary wrapper around a `Utf8TempDir` for use
@@ -7,11 +7,13 @@ use crate::installinator_progress::IprArtifactServer;
 use async_trait::async_trait;
 use dropshot::HttpError;
 use hyper::Body;
+use installinator_artifactd::resolve_range;
 use installinator_artifactd::ArtifactGetter;
 use installinator_artifactd::EventReportStatus;
 use omicron_common::update::ArtifactHashId;
 use slog::error;
 use slog::Logger;
+use std::ops::Range;
 use uuid::Uuid;
 
 /// The artifact server interface for wicketd.
@@ -53,6 +55,31 @@ impl ArtifactGetter for WicketdArtifactServer {
         Some((size, Body::wrap_stream(data_stream)))
     }
 
+    async fn get_range_by_hash(
+        &self,
+        id: &ArtifactHashId,
+        range: Range<u64>,
+    ) -> Option<(u64, Body)> {
+        let data_handle = self.store.get_by_hash(id)?;
+        let size = data_handle.file_size() as u64;
+        // Only serve the part of the range within the artifact.
+        let Some(range) = resolve_range(range, size) else {
+            return Some((size, Body::empty()));
+        };
+        let data_stream = match data_handle.range_reader_stream(range).await {
+            Ok(stream) => stream,
+            Err(err) => {
+                error!(
+                    self.log, "failed to open extracted archive on demand";
+                    "error" => #%err,
+                );
+                return None;
+            }
+        };
+
+        Some((size, Body::wrap_stream(data_stream)))
+    }
+
     async fn report_progress(
         &self,
         update_id: Uuid,
@@ -4,8 +4,10 @@
 
 use super::artifacts_with_plan::ArtifactsWithPlan;
 use super::ExtractedArtifactDataHandle;
+use super::TrustedRoot;
 use super::UpdatePlan;
 use crate::http_entrypoints::InstallableArtifacts;
+use display_error_chain::DisplayErrorChain;
 use dropshot::HttpError;
 use omicron_common::api::external::SemverVersion;
 use omicron_common::update::ArtifactHashId;
@@ -24,12 +26,30 @@ pub struct WicketdArtifactStore {
     // NOTE: this is a `std::sync::Mutex` rather than a `tokio::sync::Mutex`
     // because the critical sections are extremely small.
     artifacts_with_plan: Arc<Mutex<Option<ArtifactsWithPlan>>>,
+    // The roots that uploaded repositories must chain to.
+    trusted_roots: Arc<Vec<TrustedRoot>>,
+    // Whether to trust the root in each uploaded repository if there are no
+    // `trusted_roots`.
+    allow_untrusted: bool,
+    // The reason the most recently uploaded repository was rejected, if it
+    // was.
+    repository_error: Arc<Mutex<Option<String>>>,
 }
 
 impl WicketdArtifactStore {
-    pub(crate) fn new(log: &Logger) -> Self {
+    pub(crate) fn new(
+        log: &Logger,
+        trusted_roots: Vec<TrustedRoot>,
+        allow_untrusted: bool,
+    ) -> Self {
         let log = log.new(slog::o!("component" => "wicketd artifact store"));
-        Self { log, artifacts_with_plan: Default::default() }
+        Self {
+            log,
+            artifacts_with_plan: Default::default(),
+            trusted_roots: Arc::new(trusted_roots),
+            allow_untrusted,
+            repository_error: Default::default(),
+        }
     }
 
     pub(crate) async fn put_repository<T>(
@@ -42,15 +62,37 @@ impl WicketdArtifactStore {
         slog::debug!(self.log, "adding repository");
 
         let log = self.log.clone();
-        let new_artifacts = tokio::task::spawn_blocking(move || {
-            ArtifactsWithPlan::from_zip(data, &log)
-                .map_err(|error| error.to_http_error())
+        let trusted_roots = Arc::clone(&self.trusted_roots);
+        let allow_untrusted = self.allow_untrusted;
+        let result = tokio::task::spawn_blocking(move || {
+            ArtifactsWithPlan::from_zip(
+                data,
+                &trusted_roots,
+                allow_untrusted,
+                &log,
+            )
         })
         .await
-        .unwrap()?;
-        self.replace(new_artifacts);
+        .unwrap();
+        match result {
+            Ok(new_artifacts) => {
+                self.replace(new_artifacts);
+                *self.repository_error.lock().unwrap() = None;
+                Ok(())
+            }
+            Err(error) => {
+                let message = DisplayErrorChain::new(&error).to_string();
+                slog::warn!(self.log, "rejected repository: {message}");
+                *self.repository_error.lock().unwrap() = Some(message);
+                Err(error.to_http_error())
+            }
+        }
+    }
 
-        Ok(())
+    /// Returns the reason the most recently uploaded repository was rejected,
+    /// if it was.
+    pub(crate) fn repository_error(&self) -> Option<String> {
+        self.repository_error.lock().unwrap().clone()
     }
 
     pub(crate) fn system_version_and_artifact_ids(
@@ -0,0 +1,37 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use debug_ignore::DebugIgnore;
+use std::path::Path;
+use std::path::PathBuf;
+use tough::schema::Root;
+use tough::schema::Signed;
+
+/// A TUF `root.json` document that uploaded repositories may chain to.
+#[derive(Clone, Debug)]
+pub(crate) struct TrustedRoot {
+    path: PathBuf,
+    contents: DebugIgnore<Vec<u8>>,
+}
+
+impl TrustedRoot {
+    /// Reads a trusted root from `path`, checking that it's a root document.
+    pub(crate) fn read(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read(path).map_err(|error| {
+            format!("error reading trusted root {}: {error}", path.display())
+        })?;
+        serde_json::from_slice::<Signed<Root>>(&contents).map_err(|error| {
+            format!("error parsing trusted root {}: {error}", path.display())
+        })?;
+        Ok(Self { path: path.to_owned(), contents: contents.into() })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) fn contents(&self) -> &[u8] {
+        &self.contents
+    }
+}
@@ -24,13 +24,14 @@ use omicron_common::update::ArtifactId;
 use omicron_common::update::ArtifactKind;
 use slog::info;
 use slog::Logger;
-use std::collections::btree_map;
 use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::io;
 use std::io::Read;
 use tufaceous_lib::HostPhaseImages;
 use tufaceous_lib::RotArchives;
+use tufaceous_lib::UpdatePlanError;
+use tufaceous_lib::UpdatePlanRequirements;
 
 /// The update plan currently in effect.
 ///
@@ -112,6 +113,7 @@ pub(super) struct UpdatePlanBuilder<'a> {
 
     // extra fields we use to build the plan
     extracted_artifacts: ExtractedArtifacts,
+    requirements: UpdatePlanRequirements,
     log: &'a Logger,
 }
 
@@ -139,6 +141,7 @@ impl<'a> UpdatePlanBuilder<'a> {
             control_plane_hash: None,
 
             extracted_artifacts,
+            requirements: UpdatePlanRequirements::new(),
             log,
         })
     }
@@ -163,6 +166,9 @@ impl<'a> UpdatePlanBuilder<'a> {
             );
         };
 
+        // A repository may only have one artifact of most kinds.
+        self.requirements.add_artifact(artifact_kind)?;
+
         // If we do know the artifact kind, we may have additional work to do,
         // so we break each out into its own method. The particulars of that
         // work varies based on the kind of artifact; for example, we have to
@@ -241,30 +247,23 @@ impl<'a> UpdatePlanBuilder<'a> {
         let (artifact_id, board) =
             read_hubris_board_from_archive(artifact_id, data.clone())?;
 
-        let slot = match sp_map.entry(board) {
-            btree_map::Entry::Vacant(slot) => slot,
-            btree_map::Entry::Occupied(slot) => {
-                return Err(RepositoryError::DuplicateBoardEntry {
-                    board: slot.key().0.clone(),
-                    kind: artifact_kind,
-                });
-            }
-        };
+        self.requirements.add_board(artifact_kind, &board.0)?;
 
         let artifact_hash_id =
             ArtifactHashId { kind: artifact_kind.into(), hash: artifact_hash };
         let data = self
             .extracted_artifacts
             .store(artifact_hash_id, io::Cursor::new(&data))?;
-        slot.insert(ArtifactIdData {
-            id: artifact_id.clone(),
-            data: data.clone(),
-        });
+        sp_map.insert(
+            board,
+            ArtifactIdData { id: artifact_id.clone(), data: data.clone() },
+        );
 
         record_extracted_artifact(
             artifact_id,
             by_id,
             by_hash,
+            &mut self.requirements,
             data,
             artifact_kind.into(),
             self.log,
@@ -309,10 +308,6 @@ impl<'a> UpdatePlanBuilder<'a> {
             | KnownArtifactKind::SwitchSp => unreachable!(),
         };
 
-        if rot_a.is_some() || rot_b.is_some() {
-            return Err(RepositoryError::DuplicateArtifactKind(artifact_kind));
-        }
-
         let (rot_a_data, rot_b_data) = Self::extract_nested_artifact_pair(
             &mut self.extracted_artifacts,
             artifact_kind,
@@ -345,6 +340,7 @@ impl<'a> UpdatePlanBuilder<'a> {
             artifact_id.clone(),
             by_id,
             by_hash,
+            &mut self.requirements,
             rot_a_data,
             rot_a_kind,
             self.log,
@@ -353,6 +349,7 @@ impl<'a> UpdatePlanBuilder<'a> {
             artifact_id,
             by_id,
             by_hash,
+            &mut self.requirements,
             rot_b_data,
             rot_b_kind,
             self.log,
@@ -368,12 +365,6 @@ impl<'a> UpdatePlanBuilder<'a> {
         by_id: &mut BTreeMap<ArtifactId, Vec<ArtifactHashId>>,
         by_hash: &mut HashMap<ArtifactHashId, ExtractedArtifactDataHandle>,
     ) -> Result<(), RepositoryError> {
-        if self.host_phase_1.is_some() || self.host_phase_2_hash.is_some() {
-            return Err(RepositoryError::DuplicateArtifactKind(
-                KnownArtifactKind::Host,
-            ));
-        }
-
         let (phase_1_data, phase_2_data) = Self::extract_nested_artifact_pair(
             &mut self.extracted_artifacts,
             KnownArtifactKind::Host,
@@ -396,6 +387,7 @@ impl<'a> UpdatePlanBuilder<'a> {
             artifact_id.clone(),
             by_id,
             by_hash,
+            &mut self.requirements,
             phase_1_data,
             ArtifactKind::HOST_PHASE_1,
             self.log,
@@ -404,6 +396,7 @@ impl<'a> UpdatePlanBuilder<'a> {
             artifact_id,
             by_id,
             by_hash,
+            &mut self.requirements,
             phase_2_data,
             ArtifactKind::HOST_PHASE_2,
             self.log,
@@ -419,14 +412,6 @@ impl<'a> UpdatePlanBuilder<'a> {
         by_id: &mut BTreeMap<ArtifactId, Vec<ArtifactHashId>>,
         by_hash: &mut HashMap<ArtifactHashId, ExtractedArtifactDataHandle>,
     ) -> Result<(), RepositoryError> {
-        if self.trampoline_phase_1.is_some()
-            || self.trampoline_phase_2.is_some()
-        {
-            return Err(RepositoryError::DuplicateArtifactKind(
-                KnownArtifactKind::Trampoline,
-            ));
-        }
-
         let (phase_1_data, phase_2_data) = Self::extract_nested_artifact_pair(
             &mut self.extracted_artifacts,
             KnownArtifactKind::Trampoline,
@@ -456,6 +441,7 @@ impl<'a> UpdatePlanBuilder<'a> {
             artifact_id.clone(),
             by_id,
             by_hash,
+            &mut self.requirements,
             phase_1_data,
             ArtifactKind::TRAMPOLINE_PHASE_1,
             self.log,
@@ -464,6 +450,7 @@ impl<'a> UpdatePlanBuilder<'a> {
             artifact_id,
             by_id,
             by_hash,
+            &mut self.requirements,
             phase_2_data,
             ArtifactKind::TRAMPOLINE_PHASE_2,
             self.log,
@@ -480,12 +467,6 @@ impl<'a> UpdatePlanBuilder<'a> {
         by_id: &mut BTreeMap<ArtifactId, Vec<ArtifactHashId>>,
         by_hash: &mut HashMap<ArtifactHashId, ExtractedArtifactDataHandle>,
     ) -> Result<(), RepositoryError> {
-        if self.control_plane_hash.is_some() {
-            return Err(RepositoryError::DuplicateArtifactKind(
-                KnownArtifactKind::ControlPlane,
-            ));
-        }
-
         // The control plane artifact is the easiest one: we just need to copy
         // it into our tempdir and record it. Nothing to inspect or extract.
         let artifact_hash_id = ArtifactHashId {
@@ -501,6 +482,7 @@ impl<'a> UpdatePlanBuilder<'a> {
             artifact_id,
             by_id,
             by_hash,
+            &mut self.requirements,
             data,
             KnownArtifactKind::ControlPlane.into(),
             self.log,
@@ -527,6 +509,7 @@ impl<'a> UpdatePlanBuilder<'a> {
             artifact_id,
             by_id,
             by_hash,
+            &mut self.requirements,
             data,
             artifact_kind,
             self.log,
@@ -572,76 +555,51 @@ impl<'a> UpdatePlanBuilder<'a> {
     }
 
     pub(super) fn build(self) -> Result<UpdatePlan, RepositoryError> {
-        // Ensure our multi-board-supporting kinds have at least one board
-        // present.
-        if self.gimlet_sp.is_empty() {
-            return Err(RepositoryError::MissingArtifactKind(
-                KnownArtifactKind::GimletSp,
-            ));
-        }
-        if self.psc_sp.is_empty() {
-            return Err(RepositoryError::MissingArtifactKind(
-                KnownArtifactKind::PscSp,
-            ));
-        }
-        if self.sidecar_sp.is_empty() {
-            return Err(RepositoryError::MissingArtifactKind(
-                KnownArtifactKind::SwitchSp,
-            ));
-        }
+        // Ensure we have an artifact of every kind, including at least one
+        // board for our multi-board-supporting kinds.
+        self.requirements.check_complete()?;
+        let missing = |kind| {
+            RepositoryError::from(UpdatePlanError::MissingArtifactKind(kind))
+        };
 
         Ok(UpdatePlan {
             system_version: self.system_version,
             gimlet_sp: self.gimlet_sp, // checked above
-            gimlet_rot_a: self.gimlet_rot_a.ok_or(
-                RepositoryError::MissingArtifactKind(
-                    KnownArtifactKind::GimletRot,
-                ),
-            )?,
-            gimlet_rot_b: self.gimlet_rot_b.ok_or(
-                RepositoryError::MissingArtifactKind(
-                    KnownArtifactKind::GimletRot,
-                ),
-            )?,
+            gimlet_rot_a: self
+                .gimlet_rot_a
+                .ok_or_else(|| missing(KnownArtifactKind::GimletRot))?,
+            gimlet_rot_b: self
+                .gimlet_rot_b
+                .ok_or_else(|| missing(KnownArtifactKind::GimletRot))?,
             psc_sp: self.psc_sp, // checked above
-            psc_rot_a: self.psc_rot_a.ok_or(
-                RepositoryError::MissingArtifactKind(KnownArtifactKind::PscRot),
-            )?,
-            psc_rot_b: self.psc_rot_b.ok_or(
-                RepositoryError::MissingArtifactKind(KnownArtifactKind::PscRot),
-            )?,
+            psc_rot_a: self
+                .psc_rot_a
+                .ok_or_else(|| missing(KnownArtifactKind::PscRot))?,
+            psc_rot_b: self
+                .psc_rot_b
+                .ok_or_else(|| missing(KnownArtifactKind::PscRot))?,
             sidecar_sp: self.sidecar_sp, // checked above
-            sidecar_rot_a: self.sidecar_rot_a.ok_or(
-                RepositoryError::MissingArtifactKind(
-                    KnownArtifactKind::SwitchRot,
-                ),
-            )?,
-            sidecar_rot_b: self.sidecar_rot_b.ok_or(
-                RepositoryError::MissingArtifactKind(
-                    KnownArtifactKind::SwitchRot,
-                ),
-            )?,
-            host_phase_1: self.host_phase_1.ok_or(
-                RepositoryError::MissingArtifactKind(KnownArtifactKind::Host),
-            )?,
-            trampoline_phase_1: self.trampoline_phase_1.ok_or(
-                RepositoryError::MissingArtifactKind(
-                    KnownArtifactKind::Trampoline,
-                ),
-            )?,
-            trampoline_phase_2: self.trampoline_phase_2.ok_or(
-                RepositoryError::MissingArtifactKind(
-                    KnownArtifactKind::Trampoline,
-                ),
-            )?,
-            host_phase_2_hash: self.host_phase_2_hash.ok_or(
-                RepositoryError::MissingArtifactKind(KnownArtifactKind::Host),
-            )?,
-            control_plane_hash: self.control_plane_hash.ok_or(
-                RepositoryError::MissingArtifactKind(
-                    KnownArtifactKind::ControlPlane,
-                ),
-            )?,
+            sidecar_rot_a: self
+                .sidecar_rot_a
+                .ok_or_else(|| missing(KnownArtifactKind::SwitchRot))?,
+            sidecar_rot_b: self
+                .sidecar_rot_b
+                .ok_or_else(|| missing(KnownArtifactKind::SwitchRot))?,
+            host_phase_1: self
+                .host_phase_1
+                .ok_or_else(|| missing(KnownArtifactKind::Host))?,
+            trampoline_phase_1: self
+                .trampoline_phase_1
+                .ok_or_else(|| missing(KnownArtifactKind::Trampoline))?,
+            trampoline_phase_2: self
+                .trampoline_phase_2
+                .ok_or_else(|| missing(KnownArtifactKind::Trampoline))?,
+            host_phase_2_hash: self
+                .host_phase_2_hash
+                .ok_or_else(|| missing(KnownArtifactKind::Host))?,
+            control_plane_hash: self
+                .control_plane_hash
+                .ok_or_else(|| missing(KnownArtifactKind::ControlPlane))?,
         })
     }
 }
@@ -679,44 +637,35 @@ fn read_hubris_board_from_archive(
     Ok((id, Board(board.to_string())))
 }
 
-// Record an artifact in `by_id` and `by_hash`, or fail if either already has an
-// entry for this id/hash.
+// Record an artifact in `by_id` and `by_hash`, or fail if we already serve an
+// artifact with this kind and hash.
 fn record_extracted_artifact(
     tuf_repo_artifact_id: ArtifactId,
     by_id: &mut BTreeMap<ArtifactId, Vec<ArtifactHashId>>,
     by_hash: &mut HashMap<ArtifactHashId, ExtractedArtifactDataHandle>,
+    requirements: &mut UpdatePlanRequirements,
     data: ExtractedArtifactDataHandle,
     data_kind: ArtifactKind,
     log: &Logger,
 ) -> Result<(), RepositoryError> {
-    use std::collections::hash_map::Entry;
-
     let artifact_hash_id =
         ArtifactHashId { kind: data_kind, hash: data.hash() };
-
-    let by_hash_slot = match by_hash.entry(artifact_hash_id) {
-        Entry::Occupied(slot) => {
-            return Err(RepositoryError::DuplicateHashEntry(
-                slot.key().clone(),
-            ));
-        }
-        Entry::Vacant(slot) => slot,
-    };
+    requirements.add_served(artifact_hash_id.clone())?;
 
     info!(
         log, "added artifact";
         "name" => %tuf_repo_artifact_id.name,
-        "kind" => %by_hash_slot.key().kind,
+        "kind" => %artifact_hash_id.kind,
         "version" => %tuf_repo_artifact_id.version,
-        "hash" => %by_hash_slot.key().hash,
+        "hash" => %artifact_hash_id.hash,
         "length" => data.file_size(),
     );
 
     by_id
         .entry(tuf_repo_artifact_id)
         .or_default()
-        .push(by_hash_slot.key().clone());
-    by_hash_slot.insert(data);
+        .push(artifact_hash_id.clone());
+    by_hash.insert(artifact_hash_id, data);
 
     Ok(())
 }
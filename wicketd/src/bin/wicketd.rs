@@ -92,6 +92,9 @@ async fn do_run() -> Result<(), CmdError> {
                 artifact_address,
                 mgs_address,
                 baseboard,
+                trusted_roots: config.trusted_roots,
+                allow_untrusted_repositories: config
+                    .allow_untrusted_repositories,
             };
             let log = config.log.to_logger("wicketd").map_err(|msg| {
                 CmdError::Failure(format!("initializing logger: {}", msg))
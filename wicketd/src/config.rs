@@ -13,6 +13,22 @@ use std::path::PathBuf;
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Config {
     pub log: ConfigLogging,
+
+    /// Paths to the TUF `root.json` documents that uploaded repositories must
+    /// chain to.
+    ///
+    /// Repositories that don't chain to any of these roots are rejected.
+    /// wicketd refuses to start if none are configured, unless
+    /// `allow_untrusted_repositories` is set.
+    #[serde(default)]
+    pub trusted_roots: Vec<PathBuf>,
+
+    /// If no trusted roots are configured, trust the root in each uploaded
+    /// repository instead, so that any signed repository is accepted.
+    ///
+    /// This is only meant for development systems.
+    #[serde(default)]
+    pub allow_untrusted_repositories: bool,
 }
 
 impl Config {
@@ -633,6 +633,12 @@ pub struct GetArtifactsAndEventReportsResponse {
     /// instead.
     pub artifacts: Vec<InstallableArtifacts>,
 
+    /// The reason the most recently uploaded TUF repository was rejected, if
+    /// it was.
+    ///
+    /// This is cleared once a repository is accepted.
+    pub repository_error: Option<String>,
+
     pub event_reports: BTreeMap<SpType, BTreeMap<u32, EventReport>>,
 }
 
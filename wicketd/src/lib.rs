@@ -15,7 +15,7 @@ mod rss_config;
 mod update_tracker;
 
 use anyhow::{anyhow, Result};
-use artifacts::{WicketdArtifactServer, WicketdArtifactStore};
+use artifacts::{TrustedRoot, WicketdArtifactServer, WicketdArtifactStore};
 use bootstrap_addrs::BootstrapPeers;
 pub use config::Config;
 pub(crate) use context::ServerContext;
@@ -27,10 +27,11 @@ pub(crate) use mgs::{MgsHandle, MgsManager};
 use omicron_common::FileKv;
 use preflight_check::PreflightCheckerHandler;
 use sled_hardware::Baseboard;
-use slog::{debug, error, o, Drain};
+use slog::{debug, error, o, warn, Drain};
 use std::sync::OnceLock;
 use std::{
     net::{SocketAddr, SocketAddrV6},
+    path::PathBuf,
     sync::Arc,
 };
 pub use update_tracker::{StartUpdateError, UpdateTracker};
@@ -53,6 +54,12 @@ pub struct Args {
     pub artifact_address: SocketAddrV6,
     pub mgs_address: SocketAddrV6,
     pub baseboard: Option<Baseboard>,
+    /// Paths to the TUF root documents that uploaded repositories must chain
+    /// to
+    pub trusted_roots: Vec<PathBuf>,
+    /// Whether to trust the root in each uploaded repository if there are no
+    /// `trusted_roots` (for development systems only)
+    pub allow_untrusted_repositories: bool,
 }
 
 pub struct Server {
@@ -94,7 +101,29 @@ impl Server {
         let (ipr_artifact, ipr_update_tracker) =
             crate::installinator_progress::new(&log);
 
-        let store = WicketdArtifactStore::new(&log);
+        let trusted_roots = args
+            .trusted_roots
+            .iter()
+            .map(|path| TrustedRoot::read(path))
+            .collect::<Result<Vec<_>, _>>()?;
+        if trusted_roots.is_empty() {
+            if !args.allow_untrusted_repositories {
+                let msg = "no trusted TUF roots configured \
+                           (set `trusted_roots` in the config file)";
+                error!(log, "{}", msg);
+                return Err(msg.to_string());
+            }
+            warn!(
+                log,
+                "no trusted TUF roots configured and untrusted repositories \
+                 allowed: trusting the root in each uploaded repository"
+            );
+        }
+        let store = WicketdArtifactStore::new(
+            &log,
+            trusted_roots,
+            args.allow_untrusted_repositories,
+        );
         let update_tracker = Arc::new(UpdateTracker::new(
             args.mgs_address,
             &log,
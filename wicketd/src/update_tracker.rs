@@ -410,6 +410,7 @@ impl UpdateTracker {
         GetArtifactsAndEventReportsResponse {
             system_version,
             artifacts,
+            repository_error: update_data.artifact_store.repository_error(),
             event_reports,
         }
     }
@@ -6,8 +6,11 @@
 
 use std::net::{Ipv6Addr, SocketAddr, SocketAddrV6};
 
+use camino_tempfile::Utf8TempDir;
+use clap::Parser;
 use dropshot::test_util::ClientTestContext;
 use gateway_test_utils::setup::GatewayTestContext;
+use tufaceous_lib::Key;
 
 pub struct WicketdTestContext {
     pub wicketd_addr: SocketAddrV6,
@@ -19,6 +22,8 @@ pub struct WicketdTestContext {
     pub artifact_client: installinator_artifact_client::Client,
     pub server: wicketd::Server,
     pub gateway: GatewayTestContext,
+    /// The key wicketd trusts to sign uploaded repositories
+    pub tuf_key: Key,
 }
 
 impl WicketdTestContext {
@@ -36,11 +41,34 @@ impl WicketdTestContext {
                 .unwrap()
                 .local_addr(),
         );
+        // Create a root for wicketd to trust, which tests sign their
+        // repositories with.
+        let tuf_key = Key::generate_ed25519();
+        let root_dir = Utf8TempDir::new().expect("temp dir created");
+        tufaceous::Args::try_parse_from([
+            "tufaceous",
+            "--key",
+            &tuf_key.to_string(),
+            "--repo",
+            root_dir.path().as_str(),
+            "init",
+            "1.0.0",
+        ])
+        .expect("args parsed correctly")
+        .exec(log)
+        .expect("init command completed successfully");
+
         let args = wicketd::Args {
             address: localhost_port_0,
             artifact_address: localhost_port_0,
             mgs_address,
             baseboard: None,
+            trusted_roots: vec![root_dir
+                .path()
+                .join("metadata")
+                .join("1.root.json")
+                .into_std_path_buf()],
+            allow_untrusted_repositories: false,
         };
 
         let server = wicketd::Server::start(log.clone(), args)
@@ -86,6 +114,7 @@ impl WicketdTestContext {
             artifact_client,
             server,
             gateway,
+            tuf_key,
         }
     }
 
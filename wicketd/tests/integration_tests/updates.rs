@@ -16,7 +16,9 @@ use omicron_common::{
     api::internal::nexus::KnownArtifactKind,
     update::{ArtifactHashId, ArtifactKind},
 };
+use reqwest::{header, StatusCode};
 use tokio::sync::oneshot;
+use tufaceous_lib::Key;
 use uuid::Uuid;
 use wicket_common::update_events::{StepEventKind, UpdateComponent};
 use wicketd::{RunningUpdateState, StartUpdateError};
@@ -36,6 +38,8 @@ async fn test_updates() {
 
     let args = tufaceous::Args::try_parse_from([
         "tufaceous",
+        "--key",
+        &wicketd_testctx.tuf_key.to_string(),
         "assemble",
         "../tufaceous/manifests/fake.toml",
         archive_path.as_str(),
@@ -182,6 +186,57 @@ async fn test_updates() {
     wicketd_testctx.teardown().await;
 }
 
+#[tokio::test]
+async fn test_untrusted_repository() {
+    let gateway =
+        gateway_setup::test_setup("test_untrusted_repository", SpPort::One)
+            .await;
+    let wicketd_testctx = WicketdTestContext::setup(gateway).await;
+    let log = wicketd_testctx.log();
+
+    let temp_dir = Utf8TempDir::new().expect("temp dir created");
+    let archive_path = temp_dir.path().join("archive.zip");
+
+    // Sign the repository with a key that wicketd doesn't trust.
+    let args = tufaceous::Args::try_parse_from([
+        "tufaceous",
+        "--key",
+        &Key::generate_ed25519().to_string(),
+        "assemble",
+        "../tufaceous/manifests/fake.toml",
+        archive_path.as_str(),
+    ])
+    .expect("args parsed correctly");
+
+    args.exec(log).expect("assemble command completed successfully");
+
+    // Uploading the repository should fail...
+    let zip_bytes =
+        fs_err::read(&archive_path).expect("archive read correctly");
+    wicketd_testctx
+        .wicketd_client
+        .put_repository(zip_bytes)
+        .await
+        .expect_err("untrusted repository rejected");
+
+    // ... and the reason should be reported along with the (absent)
+    // artifacts.
+    let response = wicketd_testctx
+        .wicketd_client
+        .get_artifacts_and_event_reports()
+        .await
+        .expect("get_artifacts_and_event_reports succeeded")
+        .into_inner();
+    assert!(response.artifacts.is_empty(), "no artifacts were accepted");
+    let error = response.repository_error.expect("repository error reported");
+    assert!(
+        error.contains("does not chain to any trusted root"),
+        "unexpected repository error: {error}"
+    );
+
+    wicketd_testctx.teardown().await;
+}
+
 #[tokio::test]
 async fn test_installinator_fetch() {
     let gateway = gateway_setup::test_setup("test_updates", SpPort::One).await;
@@ -193,6 +248,8 @@ async fn test_installinator_fetch() {
 
     let args = tufaceous::Args::try_parse_from([
         "tufaceous",
+        "--key",
+        &wicketd_testctx.tuf_key.to_string(),
         "assemble",
         "../tufaceous/manifests/fake.toml",
         archive_path.as_str(),
@@ -243,6 +300,62 @@ async fn test_installinator_fetch() {
         "control plane ID found by hash"
     );
 
+    // The artifact server can also return ranges of artifacts.
+    let control_plane_url = format!(
+        "http://{}/artifacts/by-hash/{}/{}",
+        wicketd_testctx.artifact_addr,
+        control_plane_id.kind.as_str(),
+        control_plane_hash,
+    );
+    let client = reqwest::Client::new();
+    let response = client
+        .get(&control_plane_url)
+        .send()
+        .await
+        .expect("control plane fetched");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::ACCEPT_RANGES], "bytes");
+    let control_plane =
+        response.bytes().await.expect("control plane body read");
+    let size = control_plane.len();
+    assert!(size > 16, "control plane artifact is large enough to split");
+
+    for (range, expected_range, expected_bytes) in [
+        ("bytes=4-11", format!("bytes 4-11/{size}"), &control_plane[4..12]),
+        // Ranges running past the end of the artifact are cut short.
+        (
+            "bytes=10-",
+            format!("bytes 10-{}/{size}", size - 1),
+            &control_plane[10..],
+        ),
+    ] {
+        let response = client
+            .get(&control_plane_url)
+            .header(header::RANGE, range)
+            .send()
+            .await
+            .expect("control plane range fetched");
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT, "{range}");
+        assert_eq!(
+            response.headers()[header::CONTENT_RANGE],
+            expected_range.as_str(),
+        );
+        let bytes = response.bytes().await.expect("range body read");
+        assert_eq!(bytes, expected_bytes, "{range}");
+    }
+
+    let response = client
+        .get(&control_plane_url)
+        .header(header::RANGE, format!("bytes={size}-"))
+        .send()
+        .await
+        .expect("control plane range fetched");
+    assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(
+        response.headers()[header::CONTENT_RANGE],
+        format!("bytes */{size}").as_str(),
+    );
+
     // Tell the installinator to download artifacts from that location.
     let peers_list = format!(
         "list:[{}]:{}",
@@ -330,6 +443,8 @@ async fn test_update_races() {
 
     let args = tufaceous::Args::try_parse_from([
         "tufaceous",
+        "--key",
+        &wicketd_testctx.tuf_key.to_string(),
         "assemble",
         "../tufaceous/manifests/fake.toml",
         archive_path.as_str(),